@@ -0,0 +1,309 @@
+// Copyright (c) 2017 zzoon <zzoon@igalia.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/* Prepares the ffi module and tells the crate which VA-API version it follows.
+ *
+ * By default src/ffi.rs is used. It was generated from the 0.39 headers and the AV1 decode
+ * structures of 1.8 were added to it by hand, so va_api_0_39 and va_av1_decode are set.
+ * With the generate-bindings feature the bindings are regenerated into OUT_DIR from the va
+ * headers of the libva found by pkg-config, va_generated_bindings is set, and va_api_X_Y is
+ * set for every release up to the installed one, so code using a newer profile or
+ * entrypoint can be written as #[cfg(va_api_1_8)].
+ *
+ * Either way the bindings are parsed and OUT_DIR/ffi_names.rs gives them the names the
+ * crate uses whatever the bindgen version: the anonymous unions and structures bindgen
+ * names _VAParent__bindgen_ty_N get an alias named after their parent and field, e.g.
+ * VAPictureParameterBufferH264_pic_fields, and every enumerator is a module constant.
+ * bindgen's ParseCallbacks can't do this, they tell neither the field an anonymous type is
+ * declared for nor the enum a variant ends up in. */
+
+#[cfg(feature = "generate-bindings")]
+extern crate bindgen;
+#[cfg(feature = "generate-bindings")]
+extern crate pkg_config;
+extern crate syn;
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use syn::visit::{self, Visit};
+
+/* VA-API releases as (major, minor), oldest first */
+const VA_API_VERSIONS: &[(u32, u32)] = &[(0, 39), (0, 40), (1, 0), (1, 1), (1, 2),
+                                         (1, 3), (1, 4), (1, 5), (1, 6), (1, 7),
+                                         (1, 8), (1, 9), (1, 10), (1, 11), (1, 12),
+                                         (1, 13), (1, 14), (1, 15), (1, 16), (1, 17),
+                                         (1, 18), (1, 19), (1, 20), (1, 21), (1, 22)];
+
+/* Version of the headers src/ffi.rs was generated from */
+#[cfg(not(feature = "generate-bindings"))]
+const CHECKED_IN_VERSION: (u32, u32) = (0, 39);
+
+/* First release with va_dec_av1.h */
+#[cfg(feature = "generate-bindings")]
+const AV1_DECODE_VERSION: (u32, u32) = (1, 8);
+
+fn declare_cfg(name: &str) {
+    println!("cargo:rustc-check-cfg=cfg({})", name);
+}
+
+fn emit_version_cfgs(version: (u32, u32)) {
+    for &(major, minor) in VA_API_VERSIONS {
+        if (major, minor) <= version {
+            println!("cargo:rustc-cfg=va_api_{}_{}", major, minor);
+        }
+    }
+}
+
+fn read_file(path: &Path) -> String {
+    let mut content = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut content))
+        .unwrap_or_else(|e| panic!("failed to read {}: {}", path.display(), e));
+    content
+}
+
+/* Anonymous types a field type refers to, also through __BindgenUnionField<T> or arrays */
+#[derive(Default)]
+struct AnonTypes(Vec<String>);
+
+impl<'ast> Visit<'ast> for AnonTypes {
+    fn visit_path_segment(&mut self, segment: &'ast syn::PathSegment) {
+        let name = segment.ident.to_string();
+        if name.contains("__bindgen_ty_") {
+            self.0.push(name);
+        }
+        visit::visit_path_segment(self, segment);
+    }
+}
+
+/* Parent record and field of an anonymous type; members bindgen calls __bindgen_anon_N
+ * have no field of their own, their fields are named as if they were the parent's */
+struct Parent {
+    record: String,
+    field: Option<String>,
+}
+
+fn alias_of(anon: &str, parents: &HashMap<String, Parent>) -> String {
+    let parent = &parents[anon];
+    let parent_alias = if parents.contains_key(&parent.record) {
+        alias_of(&parent.record, parents)
+    } else {
+        parent.record.trim_start_matches('_').to_string()
+    };
+    match parent.field {
+        Some(ref field) => format!("{}_{}", parent_alias, field),
+        None => parent_alias,
+    }
+}
+
+fn record_fields(item: &syn::Item) -> Option<(&syn::Ident, &syn::FieldsNamed)> {
+    match *item {
+        syn::Item::Struct(ref s) => match s.fields {
+            syn::Fields::Named(ref fields) => Some((&s.ident, fields)),
+            _ => None,
+        },
+        syn::Item::Union(ref u) => Some((&u.ident, &u.fields)),
+        _ => None,
+    }
+}
+
+/* Names of the items the bindings define at module level */
+fn module_names(bindings: &syn::File) -> HashSet<String> {
+    bindings.items
+        .iter()
+        .filter_map(|item| match *item {
+            syn::Item::Const(ref c) => Some(c.ident.to_string()),
+            syn::Item::Static(ref s) => Some(s.ident.to_string()),
+            syn::Item::Type(ref t) => Some(t.ident.to_string()),
+            syn::Item::Struct(ref s) => Some(s.ident.to_string()),
+            syn::Item::Union(ref u) => Some(u.ident.to_string()),
+            syn::Item::Enum(ref e) => Some(e.ident.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/* Enumerators of the Rust enums as (enum, enumerator), including those bindgen turns into
+ * associated constants since their value is taken */
+fn enumerators(bindings: &syn::File) -> Vec<(String, String)> {
+    let mut values = Vec::new();
+    for item in &bindings.items {
+        if let syn::Item::Enum(ref e) = *item {
+            for variant in &e.variants {
+                values.push((e.ident.to_string(), variant.ident.to_string()));
+            }
+        }
+    }
+    let enums: HashSet<String> = values.iter().map(|value| value.0.clone()).collect();
+
+    for item in &bindings.items {
+        let imp = match *item {
+            syn::Item::Impl(ref imp) if imp.trait_.is_none() => imp,
+            _ => continue,
+        };
+        let enum_name = match *imp.self_ty {
+            syn::Type::Path(ref path) => match path.path.get_ident() {
+                Some(ident) if enums.contains(&ident.to_string()) => ident.to_string(),
+                _ => continue,
+            },
+            _ => continue,
+        };
+        for impl_item in &imp.items {
+            if let syn::ImplItem::Const(ref c) = *impl_item {
+                values.push((enum_name.clone(), c.ident.to_string()));
+            }
+        }
+    }
+    values
+}
+
+fn write_names(bindings: &str, out: &Path) {
+    let bindings = syn::parse_file(bindings).expect("failed to parse the va bindings");
+
+    let mut records = Vec::new();
+    let mut parents = HashMap::new();
+    for item in &bindings.items {
+        let (name, fields) = match record_fields(item) {
+            Some(record) => record,
+            None => continue,
+        };
+        let name = name.to_string();
+        for field in &fields.named {
+            let field_name = match field.ident {
+                Some(ref ident) => ident.to_string(),
+                None => continue,
+            };
+            let field_name = if field_name.starts_with("__bindgen_anon_") {
+                None
+            } else {
+                Some(field_name)
+            };
+            let mut anon = AnonTypes::default();
+            anon.visit_type(&field.ty);
+            for ty in anon.0.into_iter().filter(|ty| *ty != name) {
+                parents.insert(ty, Parent { record: name.clone(), field: field_name.clone() });
+            }
+        }
+        records.push(name);
+    }
+
+    let mut names = String::new();
+    for name in &records {
+        if parents.get(name).and_then(|parent| parent.field.as_ref()).is_some() {
+            names.push_str(&format!("pub type {} = {};\n", alias_of(name, &parents), name));
+        }
+    }
+    let defined = module_names(&bindings);
+    for (enum_name, value) in enumerators(&bindings) {
+        if !defined.contains(&value) {
+            names.push_str(&format!("pub const {}: {} = {}::{};\n",
+                                    value, enum_name, enum_name, value));
+        }
+    }
+
+    File::create(out)
+        .and_then(|mut f| f.write_all(names.as_bytes()))
+        .expect("failed to write the ffi names");
+}
+
+#[cfg(not(feature = "generate-bindings"))]
+fn configure_bindings(out_dir: &Path) {
+    let path = Path::new("src").join("ffi.rs");
+    println!("cargo:rerun-if-changed={}", path.display());
+    write_names(&read_file(&path), &out_dir.join("ffi_names.rs"));
+
+    emit_version_cfgs(CHECKED_IN_VERSION);
+    println!("cargo:rustc-cfg=va_av1_decode");
+}
+
+#[cfg(feature = "generate-bindings")]
+fn read_header_version(include_paths: &[PathBuf]) -> (u32, u32) {
+    let defaults = [PathBuf::from("/usr/local/include"), PathBuf::from("/usr/include")];
+    let path = include_paths.iter()
+        .chain(defaults.iter())
+        .map(|dir| dir.join("va").join("va_version.h"))
+        .find(|path| path.exists())
+        .expect("va/va_version.h not found, are the libva headers installed?");
+    println!("cargo:rerun-if-changed={}", path.display());
+
+    let header = read_file(&path);
+    let define = |name: &str| -> u32 {
+        header.lines()
+            .filter_map(|l| {
+                let mut words = l.split_whitespace();
+                match (words.next(), words.next(), words.next()) {
+                    (Some("#define"), Some(n), Some(v)) if n == name => v.parse().ok(),
+                    _ => None,
+                }
+            })
+            .next()
+            .expect("va_version.h without version defines")
+    };
+    (define("VA_MAJOR_VERSION"), define("VA_MINOR_VERSION"))
+}
+
+#[cfg(feature = "generate-bindings")]
+fn configure_bindings(out_dir: &Path) {
+    let libva = pkg_config::Config::new()
+        .cargo_metadata(false)
+        .probe("libva")
+        .expect("libva not found by pkg-config");
+    let version = read_header_version(&libva.include_paths);
+
+    let mut wrapper = String::from("#include <va/va.h>\n#include <va/va_vpp.h>\n");
+    if version >= AV1_DECODE_VERSION {
+        wrapper.push_str("#include <va/va_dec_av1.h>\n");
+    }
+
+    /* Unions stay __BindgenUnionField structs and enums stay Rust enums, as in src/ffi.rs */
+    let mut builder = bindgen::Builder::default()
+        .header_contents("va_wrapper.h", &wrapper)
+        .disable_untagged_union()
+        .default_enum_style(bindgen::EnumVariation::Rust { non_exhaustive: false })
+        .derive_default(true)
+        .generate_comments(false)
+        .layout_tests(false)
+        .allowlist_function("va.*")
+        .allowlist_type("_?VA.*")
+        .allowlist_var("VA.*")
+        .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()));
+    for path in &libva.include_paths {
+        builder = builder.clang_arg(format!("-I{}", path.display()));
+    }
+
+    let bindings = builder.generate()
+        .expect("failed to generate the va bindings")
+        .to_string();
+    File::create(out_dir.join("ffi.rs"))
+        .and_then(|mut f| f.write_all(bindings.as_bytes()))
+        .expect("failed to write the va bindings");
+    write_names(&bindings, &out_dir.join("ffi_names.rs"));
+
+    println!("cargo:rerun-if-env-changed=PKG_CONFIG_PATH");
+    println!("cargo:rustc-cfg=va_generated_bindings");
+    emit_version_cfgs(version);
+    if version >= AV1_DECODE_VERSION {
+        println!("cargo:rustc-cfg=va_av1_decode");
+    }
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    declare_cfg("va_generated_bindings");
+    declare_cfg("va_av1_decode");
+    for &(major, minor) in VA_API_VERSIONS {
+        declare_cfg(&format!("va_api_{}_{}", major, minor));
+    }
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    configure_bindings(&out_dir);
+}
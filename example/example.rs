@@ -77,7 +77,9 @@ fn main() {
                        0,
                        WIDTH,
                        HEIGHT);
-    va_surface.sync(&va_disp);
+    if let Err(e) = va_surface.sync(&va_disp) {
+        println!("sync surface fail: {:?}", e);
+    }
     va_image.destroy(&va_disp);
 
     let wait_dur = Duration::new(1, 0);
@@ -85,7 +87,9 @@ fn main() {
     /* FIXME: why doesn't it show up without sleep */
     sleep(wait_dur);
     va_surface.put_surface(&va_disp, win, 0, 0, WIDTH, HEIGHT, 0, 0, WIDTH, HEIGHT);
-    va_surface.sync(&va_disp);
+    if let Err(e) = va_surface.sync(&va_disp) {
+        println!("sync surface fail: {:?}", e);
+    }
     sleep(wait_dur);
 
     va_context.destroy(&va_disp);
@@ -0,0 +1,2545 @@
+// Copyright (c) 2017 zzoon <zzoon@igalia.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/* Parser for the AV1 bitstream units needed to drive VA decoding: the OBU framing, the
+ * sequence header, the uncompressed frame header with the state saved along with the
+ * reference frames (sizes, order hints, loop filter deltas, segmentation features, global
+ * motion and film grain parameters), the tile groups, and their conversion into the VA
+ * buffers. The tiles themselves are decoded by the hardware. */
+
+use std::cmp;
+
+use bitfields::*;
+use bitreader::BitReader;
+use ffi;
+
+pub const OBU_SEQUENCE_HEADER: u8 = 1;
+pub const OBU_TEMPORAL_DELIMITER: u8 = 2;
+pub const OBU_FRAME_HEADER: u8 = 3;
+pub const OBU_TILE_GROUP: u8 = 4;
+pub const OBU_METADATA: u8 = 5;
+pub const OBU_FRAME: u8 = 6;
+pub const OBU_REDUNDANT_FRAME_HEADER: u8 = 7;
+pub const OBU_TILE_LIST: u8 = 8;
+pub const OBU_PADDING: u8 = 15;
+
+pub const KEY_FRAME: u8 = 0;
+pub const INTER_FRAME: u8 = 1;
+pub const INTRA_ONLY_FRAME: u8 = 2;
+pub const SWITCH_FRAME: u8 = 3;
+
+pub const NUM_REF_FRAMES: usize = 8;
+pub const REFS_PER_FRAME: usize = 7;
+pub const TOTAL_REFS_PER_FRAME: usize = 8;
+pub const PRIMARY_REF_NONE: u8 = 7;
+
+pub const INTRA_FRAME: usize = 0;
+pub const LAST_FRAME: usize = 1;
+pub const LAST2_FRAME: usize = 2;
+pub const LAST3_FRAME: usize = 3;
+pub const GOLDEN_FRAME: usize = 4;
+pub const BWDREF_FRAME: usize = 5;
+pub const ALTREF2_FRAME: usize = 6;
+pub const ALTREF_FRAME: usize = 7;
+
+pub const MAX_SEGMENTS: usize = 8;
+pub const SEG_LVL_ALT_Q: usize = 0;
+pub const SEG_LVL_ALT_LF_Y_V: usize = 1;
+pub const SEG_LVL_REF_FRAME: usize = 5;
+pub const SEG_LVL_SKIP: usize = 6;
+pub const SEG_LVL_GLOBALMV: usize = 7;
+pub const SEG_LVL_MAX: usize = 8;
+const SEGMENTATION_FEATURE_BITS: [u32; SEG_LVL_MAX] = [8, 6, 6, 6, 6, 3, 0, 0];
+const SEGMENTATION_FEATURE_SIGNED: [bool; SEG_LVL_MAX] = [true, true, true, true, true, false,
+                                                          false, false];
+const SEGMENTATION_FEATURE_MAX: [i32; SEG_LVL_MAX] = [255, MAX_LOOP_FILTER, MAX_LOOP_FILTER,
+                                                      MAX_LOOP_FILTER, MAX_LOOP_FILTER, 7, 0,
+                                                      0];
+
+/* Same values as the VA interp_filter */
+pub const INTERP_FILTER_EIGHTTAP: u8 = 0;
+pub const INTERP_FILTER_EIGHTTAP_SMOOTH: u8 = 1;
+pub const INTERP_FILTER_EIGHTTAP_SHARP: u8 = 2;
+pub const INTERP_FILTER_BILINEAR: u8 = 3;
+pub const INTERP_FILTER_SWITCHABLE: u8 = 4;
+
+pub const ONLY_4X4: u8 = 0;
+pub const TX_MODE_LARGEST: u8 = 1;
+pub const TX_MODE_SELECT: u8 = 2;
+
+pub const RESTORE_NONE: u8 = 0;
+pub const RESTORE_WIENER: u8 = 1;
+pub const RESTORE_SGRPROJ: u8 = 2;
+pub const RESTORE_SWITCHABLE: u8 = 3;
+const REMAP_LR_TYPE: [u8; 4] = [RESTORE_NONE, RESTORE_SWITCHABLE, RESTORE_WIENER,
+                                RESTORE_SGRPROJ];
+
+/* Global motion models, same values as VAAV1TransformationType */
+pub const IDENTITY: u8 = 0;
+pub const TRANSLATION: u8 = 1;
+pub const ROTZOOM: u8 = 2;
+pub const AFFINE: u8 = 3;
+
+pub const CP_BT_709: u8 = 1;
+pub const CP_UNSPECIFIED: u8 = 2;
+pub const TC_UNSPECIFIED: u8 = 2;
+pub const TC_SRGB: u8 = 13;
+pub const MC_IDENTITY: u8 = 0;
+pub const MC_UNSPECIFIED: u8 = 2;
+
+const SELECT_SCREEN_CONTENT_TOOLS: u8 = 2;
+const SELECT_INTEGER_MV: u8 = 2;
+
+const SUPERRES_NUM: u32 = 8;
+const SUPERRES_DENOM_MIN: u32 = 9;
+const SUPERRES_DENOM_BITS: u32 = 3;
+
+const MAX_TILE_WIDTH: u32 = 4096;
+const MAX_TILE_AREA: u32 = 4096 * 2304;
+const MAX_TILE_ROWS: u32 = 64;
+const MAX_TILE_COLS: u32 = 64;
+
+const MAX_LOOP_FILTER: i32 = 63;
+/* Indexed by reference frame, from INTRA_FRAME to ALTREF_FRAME */
+const DEFAULT_REF_DELTAS: [i8; TOTAL_REFS_PER_FRAME] = [1, 0, 0, 0, -1, 0, -1, -1];
+
+const WARPEDMODEL_PREC_BITS: u32 = 16;
+const GM_ABS_TRANS_BITS: u32 = 12;
+const GM_ABS_TRANS_ONLY_BITS: u32 = 9;
+const GM_ABS_ALPHA_BITS: u32 = 12;
+const GM_ALPHA_PREC_BITS: u32 = 15;
+const GM_TRANS_PREC_BITS: u32 = 6;
+const GM_TRANS_ONLY_PREC_BITS: u32 = 3;
+const WARP_PARAM_REDUCE_BITS: u32 = 6;
+const DIV_LUT_BITS: u32 = 8;
+const DIV_LUT_PREC_BITS: u32 = 14;
+const DEFAULT_GM_PARAMS: [i32; 6] = [0, 0, 1 << WARPEDMODEL_PREC_BITS, 0, 0,
+                                     1 << WARPEDMODEL_PREC_BITS];
+
+fn clamp<T: Ord>(value: T, min: T, max: T) -> T {
+    cmp::min(cmp::max(value, min), max)
+}
+
+fn floor_log2(value: u32) -> u32 {
+    31 - value.leading_zeros()
+}
+
+/* Smallest k such that blk_size << k is at least target */
+fn tile_log2(blk_size: u32, target: u32) -> u32 {
+    let mut k = 0;
+    while (blk_size << k) < target {
+        k += 1;
+    }
+    k
+}
+
+/* su(n) */
+fn read_su(br: &mut BitReader, n: u32) -> Result<i32, ()> {
+    let value = br.read_bits(n)? as i32;
+    let sign_mask = 1 << (n - 1);
+    if value & sign_mask != 0 {
+        Ok(value - 2 * sign_mask)
+    } else {
+        Ok(value)
+    }
+}
+
+/* ns(n), an unsigned value below n */
+fn read_ns(br: &mut BitReader, n: u32) -> Result<u32, ()> {
+    let w = floor_log2(n) + 1;
+    let m = (1 << w) - n;
+    let v = br.read_bits(w - 1)?;
+    if v < m {
+        return Ok(v);
+    }
+    Ok((v << 1) - m + br.read_bits(1)?)
+}
+
+fn read_delta_q(br: &mut BitReader) -> Result<i8, ()> {
+    if br.read_flag()? {
+        Ok(read_su(br, 7)? as i8)
+    } else {
+        Ok(0)
+    }
+}
+
+fn read_leb128(data: &[u8]) -> Result<(usize, usize), ()> {
+    let mut value = 0u64;
+    for (i, &byte) in data.iter().take(8).enumerate() {
+        value |= ((byte & 0x7f) as u64) << (i * 7);
+        if byte & 0x80 == 0 {
+            if value >> 32 != 0 {
+                return Err(());
+            }
+            return Ok((value as usize, i + 1));
+        }
+    }
+    Err(())
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ObuHeader {
+    pub obu_type: u8,
+    pub has_extension: bool,
+    pub temporal_id: u8,
+    pub spatial_id: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct Obu<'a> {
+    pub header: ObuHeader,
+    /* Payload, after the header and the size field */
+    pub data: &'a [u8],
+}
+
+/* Splits a temporal unit into its OBUs, only the last one may have no size field */
+pub fn split_obus<'a>(data: &'a [u8]) -> Result<Vec<Obu<'a>>, ()> {
+    let mut obus = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let byte = data[pos];
+        if byte & 0x80 != 0 {
+            return Err(());
+        }
+        let mut header = ObuHeader {
+            obu_type: (byte >> 3) & 0xf,
+            has_extension: byte & 0x4 != 0,
+            ..ObuHeader::default()
+        };
+        let has_size_field = byte & 0x2 != 0;
+        pos += 1;
+        if header.has_extension {
+            let ext = *data.get(pos).ok_or(())?;
+            header.temporal_id = ext >> 5;
+            header.spatial_id = (ext >> 3) & 0x3;
+            pos += 1;
+        }
+
+        let size = if has_size_field {
+            let (size, len) = read_leb128(&data[pos..])?;
+            pos += len;
+            size
+        } else {
+            data.len() - pos
+        };
+        if size > data.len() - pos {
+            return Err(());
+        }
+        obus.push(Obu {
+            header: header,
+            data: &data[pos..pos + size],
+        });
+        pos += size;
+    }
+    Ok(obus)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ColorConfig {
+    pub bit_depth: u8,
+    pub mono_chrome: bool,
+    pub color_primaries: u8,
+    pub transfer_characteristics: u8,
+    pub matrix_coefficients: u8,
+    pub color_range: bool,
+    pub subsampling_x: u8,
+    pub subsampling_y: u8,
+    pub chroma_sample_position: u8,
+    pub separate_uv_delta_q: bool,
+}
+
+impl ColorConfig {
+    fn parse(br: &mut BitReader, profile: u8) -> Result<ColorConfig, ()> {
+        let mut cc = ColorConfig::default();
+        let high_bitdepth = br.read_flag()?;
+        cc.bit_depth = if profile == 2 && high_bitdepth {
+            if br.read_flag()? { 12 } else { 10 }
+        } else if high_bitdepth {
+            10
+        } else {
+            8
+        };
+        cc.mono_chrome = profile != 1 && br.read_flag()?;
+
+        if br.read_flag()? {
+            cc.color_primaries = br.read_bits(8)? as u8;
+            cc.transfer_characteristics = br.read_bits(8)? as u8;
+            cc.matrix_coefficients = br.read_bits(8)? as u8;
+        } else {
+            cc.color_primaries = CP_UNSPECIFIED;
+            cc.transfer_characteristics = TC_UNSPECIFIED;
+            cc.matrix_coefficients = MC_UNSPECIFIED;
+        }
+
+        if cc.mono_chrome {
+            cc.color_range = br.read_flag()?;
+            cc.subsampling_x = 1;
+            cc.subsampling_y = 1;
+            return Ok(cc);
+        }
+        if cc.color_primaries == CP_BT_709 && cc.transfer_characteristics == TC_SRGB &&
+           cc.matrix_coefficients == MC_IDENTITY {
+            cc.color_range = true;
+        } else {
+            cc.color_range = br.read_flag()?;
+            match profile {
+                0 => {
+                    cc.subsampling_x = 1;
+                    cc.subsampling_y = 1;
+                }
+                1 => {}
+                _ if cc.bit_depth == 12 => {
+                    cc.subsampling_x = br.read_bits(1)? as u8;
+                    if cc.subsampling_x != 0 {
+                        cc.subsampling_y = br.read_bits(1)? as u8;
+                    }
+                }
+                _ => cc.subsampling_x = 1,
+            }
+            if cc.subsampling_x != 0 && cc.subsampling_y != 0 {
+                cc.chroma_sample_position = br.read_bits(2)? as u8;
+            }
+        }
+        cc.separate_uv_delta_q = br.read_flag()?;
+        Ok(cc)
+    }
+
+    pub fn num_planes(&self) -> usize {
+        if self.mono_chrome { 1 } else { 3 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OperatingPoint {
+    pub idc: u16,
+    pub seq_level_idx: u8,
+    pub seq_tier: u8,
+    pub decoder_model_present: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SequenceHeader {
+    pub profile: u8,
+    pub still_picture: bool,
+    pub reduced_still_picture_header: bool,
+    pub timing_info_present: bool,
+    pub equal_picture_interval: bool,
+    pub decoder_model_info_present: bool,
+    pub buffer_delay_length_minus_1: u8,
+    pub buffer_removal_time_length_minus_1: u8,
+    pub frame_presentation_time_length_minus_1: u8,
+    pub operating_points: Vec<OperatingPoint>,
+    pub frame_width_bits: u32,
+    pub frame_height_bits: u32,
+    pub max_frame_width: u32,
+    pub max_frame_height: u32,
+    pub frame_id_numbers_present: bool,
+    pub delta_frame_id_length_minus_2: u8,
+    pub additional_frame_id_length_minus_1: u8,
+    pub use_128x128_superblock: bool,
+    pub enable_filter_intra: bool,
+    pub enable_intra_edge_filter: bool,
+    pub enable_interintra_compound: bool,
+    pub enable_masked_compound: bool,
+    pub enable_warped_motion: bool,
+    pub enable_dual_filter: bool,
+    pub enable_order_hint: bool,
+    pub enable_jnt_comp: bool,
+    pub enable_ref_frame_mvs: bool,
+    /* 0, 1, or 2 when selected in every frame header */
+    pub seq_force_screen_content_tools: u8,
+    pub seq_force_integer_mv: u8,
+    pub order_hint_bits: u8,
+    pub enable_superres: bool,
+    pub enable_cdef: bool,
+    pub enable_restoration: bool,
+    pub color_config: ColorConfig,
+    pub film_grain_params_present: bool,
+}
+
+impl SequenceHeader {
+    pub fn parse(br: &mut BitReader) -> Result<SequenceHeader, ()> {
+        let mut seq = SequenceHeader::default();
+        seq.profile = br.read_bits(3)? as u8;
+        if seq.profile > 2 {
+            return Err(());
+        }
+        seq.still_picture = br.read_flag()?;
+        seq.reduced_still_picture_header = br.read_flag()?;
+
+        if seq.reduced_still_picture_header {
+            seq.operating_points.push(OperatingPoint {
+                seq_level_idx: br.read_bits(5)? as u8,
+                ..OperatingPoint::default()
+            });
+        } else {
+            seq.timing_info_present = br.read_flag()?;
+            if seq.timing_info_present {
+                /* num_units_in_display_tick and time_scale */
+                br.skip_bits(64)?;
+                seq.equal_picture_interval = br.read_flag()?;
+                if seq.equal_picture_interval {
+                    br.read_ue()?;
+                }
+                seq.decoder_model_info_present = br.read_flag()?;
+                if seq.decoder_model_info_present {
+                    seq.buffer_delay_length_minus_1 = br.read_bits(5)? as u8;
+                    br.skip_bits(32)?;
+                    seq.buffer_removal_time_length_minus_1 = br.read_bits(5)? as u8;
+                    seq.frame_presentation_time_length_minus_1 = br.read_bits(5)? as u8;
+                }
+            }
+
+            let initial_display_delay_present = br.read_flag()?;
+            let count = br.read_bits(5)? + 1;
+            for _ in 0..count {
+                let mut op = OperatingPoint::default();
+                op.idc = br.read_bits(12)? as u16;
+                op.seq_level_idx = br.read_bits(5)? as u8;
+                if op.seq_level_idx > 7 {
+                    op.seq_tier = br.read_bits(1)? as u8;
+                }
+                if seq.decoder_model_info_present {
+                    op.decoder_model_present = br.read_flag()?;
+                    if op.decoder_model_present {
+                        /* Decoder and encoder buffer delays, low_delay_mode_flag */
+                        let n = seq.buffer_delay_length_minus_1 as usize + 1;
+                        br.skip_bits(2 * n + 1)?;
+                    }
+                }
+                if initial_display_delay_present && br.read_flag()? {
+                    br.skip_bits(4)?;
+                }
+                seq.operating_points.push(op);
+            }
+        }
+
+        seq.frame_width_bits = br.read_bits(4)? + 1;
+        seq.frame_height_bits = br.read_bits(4)? + 1;
+        seq.max_frame_width = br.read_bits(seq.frame_width_bits)? + 1;
+        seq.max_frame_height = br.read_bits(seq.frame_height_bits)? + 1;
+        if !seq.reduced_still_picture_header {
+            seq.frame_id_numbers_present = br.read_flag()?;
+        }
+        if seq.frame_id_numbers_present {
+            seq.delta_frame_id_length_minus_2 = br.read_bits(4)? as u8;
+            seq.additional_frame_id_length_minus_1 = br.read_bits(3)? as u8;
+        }
+        seq.use_128x128_superblock = br.read_flag()?;
+        seq.enable_filter_intra = br.read_flag()?;
+        seq.enable_intra_edge_filter = br.read_flag()?;
+
+        if seq.reduced_still_picture_header {
+            seq.seq_force_screen_content_tools = SELECT_SCREEN_CONTENT_TOOLS;
+            seq.seq_force_integer_mv = SELECT_INTEGER_MV;
+        } else {
+            seq.enable_interintra_compound = br.read_flag()?;
+            seq.enable_masked_compound = br.read_flag()?;
+            seq.enable_warped_motion = br.read_flag()?;
+            seq.enable_dual_filter = br.read_flag()?;
+            seq.enable_order_hint = br.read_flag()?;
+            if seq.enable_order_hint {
+                seq.enable_jnt_comp = br.read_flag()?;
+                seq.enable_ref_frame_mvs = br.read_flag()?;
+            }
+            seq.seq_force_screen_content_tools = if br.read_flag()? {
+                SELECT_SCREEN_CONTENT_TOOLS
+            } else {
+                br.read_bits(1)? as u8
+            };
+            seq.seq_force_integer_mv = if seq.seq_force_screen_content_tools == 0 ||
+                                          br.read_flag()? {
+                SELECT_INTEGER_MV
+            } else {
+                br.read_bits(1)? as u8
+            };
+            if seq.enable_order_hint {
+                seq.order_hint_bits = br.read_bits(3)? as u8 + 1;
+            }
+        }
+
+        seq.enable_superres = br.read_flag()?;
+        seq.enable_cdef = br.read_flag()?;
+        seq.enable_restoration = br.read_flag()?;
+        seq.color_config = ColorConfig::parse(br, seq.profile)?;
+        seq.film_grain_params_present = br.read_flag()?;
+        Ok(seq)
+    }
+
+    /* Surfaces follow the bit depth and the subsampling, there is no render target for
+     * 12 bits, 4:2:2 or monochrome streams */
+    pub fn get_rt_format(&self) -> Result<u32, ()> {
+        let cc = &self.color_config;
+        match (self.profile, cc.bit_depth, cc.mono_chrome) {
+            (0, 8, false) => Ok(ffi::VA_RT_FORMAT_YUV420),
+            (0, 10, false) => Ok(ffi::VA_RT_FORMAT_YUV420_10BPP),
+            (1, 8, _) => Ok(ffi::VA_RT_FORMAT_YUV444),
+            _ => Err(()),
+        }
+    }
+
+    pub fn get_va_profile(&self) -> Result<ffi::VAProfile, ()> {
+        match (self.get_rt_format()?, self.profile) {
+            (_, 0) => Ok(ffi::VAProfileAV1Profile0),
+            _ => Ok(ffi::VAProfileAV1Profile1),
+        }
+    }
+
+    /* Only the operating point 0 is decoded */
+    pub fn get_operating_point_idc(&self) -> u16 {
+        self.operating_points.first().map_or(0, |op| op.idc)
+    }
+
+    /* Signed distance between two order hints */
+    pub fn get_relative_dist(&self, a: u8, b: u8) -> i32 {
+        if !self.enable_order_hint {
+            return 0;
+        }
+        let diff = a as i32 - b as i32;
+        let m = 1 << (self.order_hint_bits - 1);
+        (diff & (m - 1)) - (diff & m)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TileInfo {
+    pub uniform_spacing: bool,
+    pub cols_log2: u32,
+    pub rows_log2: u32,
+    /* Sizes of the tile columns and rows in superblocks */
+    pub width_in_sbs: Vec<u32>,
+    pub height_in_sbs: Vec<u32>,
+    pub context_update_tile_id: u32,
+    pub tile_size_bytes: u32,
+}
+
+/* Sizes of the tiles of a uniform spacing, the last one taking what is left */
+fn uniform_tile_sizes(count_sb: u32, tile_sb: u32) -> Vec<u32> {
+    let mut sizes = Vec::new();
+    let mut start_sb = 0;
+    while start_sb < count_sb {
+        sizes.push(cmp::min(tile_sb, count_sb - start_sb));
+        start_sb += tile_sb;
+    }
+    sizes
+}
+
+impl TileInfo {
+    fn parse(br: &mut BitReader,
+             seq: &SequenceHeader,
+             mi_cols: u32,
+             mi_rows: u32)
+             -> Result<TileInfo, ()> {
+        let mut info = TileInfo::default();
+        let sb_shift = if seq.use_128x128_superblock { 5 } else { 4 };
+        let sb_cols = (mi_cols + (1 << sb_shift) - 1) >> sb_shift;
+        let sb_rows = (mi_rows + (1 << sb_shift) - 1) >> sb_shift;
+        let sb_size = sb_shift + 2;
+        let max_tile_width_sb = MAX_TILE_WIDTH >> sb_size;
+        let mut max_tile_area_sb = MAX_TILE_AREA >> (2 * sb_size);
+        let min_log2_tile_cols = tile_log2(max_tile_width_sb, sb_cols);
+        let max_log2_tile_cols = tile_log2(1, cmp::min(sb_cols, MAX_TILE_COLS));
+        let max_log2_tile_rows = tile_log2(1, cmp::min(sb_rows, MAX_TILE_ROWS));
+        let min_log2_tiles = cmp::max(min_log2_tile_cols,
+                                      tile_log2(max_tile_area_sb, sb_rows * sb_cols));
+
+        info.uniform_spacing = br.read_flag()?;
+        if info.uniform_spacing {
+            info.cols_log2 = min_log2_tile_cols;
+            while info.cols_log2 < max_log2_tile_cols && br.read_flag()? {
+                info.cols_log2 += 1;
+            }
+            let tile_width_sb = (sb_cols + (1 << info.cols_log2) - 1) >> info.cols_log2;
+            info.width_in_sbs = uniform_tile_sizes(sb_cols, tile_width_sb);
+
+            info.rows_log2 = min_log2_tiles.saturating_sub(info.cols_log2);
+            while info.rows_log2 < max_log2_tile_rows && br.read_flag()? {
+                info.rows_log2 += 1;
+            }
+            let tile_height_sb = (sb_rows + (1 << info.rows_log2) - 1) >> info.rows_log2;
+            info.height_in_sbs = uniform_tile_sizes(sb_rows, tile_height_sb);
+        } else {
+            let mut widest_tile_sb = 0;
+            let mut start_sb = 0;
+            while start_sb < sb_cols {
+                let max_width = cmp::min(sb_cols - start_sb, max_tile_width_sb);
+                let size_sb = read_ns(br, max_width)? + 1;
+                widest_tile_sb = cmp::max(size_sb, widest_tile_sb);
+                info.width_in_sbs.push(size_sb);
+                start_sb += size_sb;
+            }
+            info.cols_log2 = tile_log2(1, info.width_in_sbs.len() as u32);
+
+            if min_log2_tiles > 0 {
+                max_tile_area_sb = (sb_rows * sb_cols) >> (min_log2_tiles + 1);
+            } else {
+                max_tile_area_sb = sb_rows * sb_cols;
+            }
+            let max_tile_height_sb = cmp::max(max_tile_area_sb / widest_tile_sb, 1);
+            start_sb = 0;
+            while start_sb < sb_rows {
+                let max_height = cmp::min(sb_rows - start_sb, max_tile_height_sb);
+                let size_sb = read_ns(br, max_height)? + 1;
+                info.height_in_sbs.push(size_sb);
+                start_sb += size_sb;
+            }
+            info.rows_log2 = tile_log2(1, info.height_in_sbs.len() as u32);
+        }
+        if info.width_in_sbs.len() as u32 > MAX_TILE_COLS ||
+           info.height_in_sbs.len() as u32 > MAX_TILE_ROWS {
+            return Err(());
+        }
+
+        if info.cols_log2 > 0 || info.rows_log2 > 0 {
+            info.context_update_tile_id = br.read_bits(info.rows_log2 + info.cols_log2)?;
+            info.tile_size_bytes = br.read_bits(2)? + 1;
+            if info.context_update_tile_id >= info.num_tiles() {
+                return Err(());
+            }
+        }
+        Ok(info)
+    }
+
+    pub fn num_cols(&self) -> u32 {
+        self.width_in_sbs.len() as u32
+    }
+
+    pub fn num_rows(&self) -> u32 {
+        self.height_in_sbs.len() as u32
+    }
+
+    pub fn num_tiles(&self) -> u32 {
+        self.num_cols() * self.num_rows()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Quantization {
+    pub base_q_idx: u8,
+    pub delta_q_y_dc: i8,
+    pub delta_q_u_dc: i8,
+    pub delta_q_u_ac: i8,
+    pub delta_q_v_dc: i8,
+    pub delta_q_v_ac: i8,
+    pub using_qmatrix: bool,
+    pub qm_y: u8,
+    pub qm_u: u8,
+    pub qm_v: u8,
+}
+
+impl Quantization {
+    fn parse(br: &mut BitReader, cc: &ColorConfig) -> Result<Quantization, ()> {
+        let mut quant = Quantization::default();
+        quant.base_q_idx = br.read_bits(8)? as u8;
+        quant.delta_q_y_dc = read_delta_q(br)?;
+        if cc.num_planes() > 1 {
+            let diff_uv_delta = cc.separate_uv_delta_q && br.read_flag()?;
+            quant.delta_q_u_dc = read_delta_q(br)?;
+            quant.delta_q_u_ac = read_delta_q(br)?;
+            if diff_uv_delta {
+                quant.delta_q_v_dc = read_delta_q(br)?;
+                quant.delta_q_v_ac = read_delta_q(br)?;
+            } else {
+                quant.delta_q_v_dc = quant.delta_q_u_dc;
+                quant.delta_q_v_ac = quant.delta_q_u_ac;
+            }
+        }
+        quant.using_qmatrix = br.read_flag()?;
+        if quant.using_qmatrix {
+            quant.qm_y = br.read_bits(4)? as u8;
+            quant.qm_u = br.read_bits(4)? as u8;
+            quant.qm_v = if cc.separate_uv_delta_q {
+                br.read_bits(4)? as u8
+            } else {
+                quant.qm_u
+            };
+        }
+        Ok(quant)
+    }
+}
+
+/* The features are kept from the primary reference frame when not updated */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Segmentation {
+    pub enabled: bool,
+    pub update_map: bool,
+    pub temporal_update: bool,
+    pub update_data: bool,
+    pub feature_enabled: [[bool; SEG_LVL_MAX]; MAX_SEGMENTS],
+    pub feature_data: [[i16; SEG_LVL_MAX]; MAX_SEGMENTS],
+}
+
+impl Segmentation {
+    fn parse(&mut self, br: &mut BitReader, primary_ref_frame: u8) -> Result<(), ()> {
+        self.enabled = br.read_flag()?;
+        self.update_map = false;
+        self.temporal_update = false;
+        self.update_data = false;
+        if !self.enabled {
+            self.feature_enabled = [[false; SEG_LVL_MAX]; MAX_SEGMENTS];
+            self.feature_data = [[0; SEG_LVL_MAX]; MAX_SEGMENTS];
+            return Ok(());
+        }
+
+        if primary_ref_frame == PRIMARY_REF_NONE {
+            self.update_map = true;
+            self.update_data = true;
+        } else {
+            self.update_map = br.read_flag()?;
+            self.temporal_update = self.update_map && br.read_flag()?;
+            self.update_data = br.read_flag()?;
+        }
+        if !self.update_data {
+            return Ok(());
+        }
+
+        for i in 0..MAX_SEGMENTS {
+            for j in 0..SEG_LVL_MAX {
+                self.feature_enabled[i][j] = br.read_flag()?;
+                let mut value = 0;
+                if self.feature_enabled[i][j] {
+                    let bits = SEGMENTATION_FEATURE_BITS[j];
+                    let limit = SEGMENTATION_FEATURE_MAX[j];
+                    value = if SEGMENTATION_FEATURE_SIGNED[j] {
+                        clamp(read_su(br, 1 + bits)?, -limit, limit)
+                    } else {
+                        clamp(br.read_bits(bits)? as i32, 0, limit)
+                    };
+                }
+                self.feature_data[i][j] = value as i16;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_feature_active(&self, segment: usize, feature: usize) -> bool {
+        self.enabled && self.feature_enabled[segment][feature]
+    }
+}
+
+/* The deltas are kept from the primary reference frame when not updated */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoopFilter {
+    /* Vertical and horizontal luma levels, then the U and V levels */
+    pub level: [u8; 4],
+    pub sharpness: u8,
+    pub delta_enabled: bool,
+    pub delta_update: bool,
+    pub ref_deltas: [i8; TOTAL_REFS_PER_FRAME],
+    pub mode_deltas: [i8; 2],
+}
+
+impl Default for LoopFilter {
+    fn default() -> LoopFilter {
+        LoopFilter {
+            level: [0; 4],
+            sharpness: 0,
+            delta_enabled: false,
+            delta_update: false,
+            ref_deltas: DEFAULT_REF_DELTAS,
+            mode_deltas: [0; 2],
+        }
+    }
+}
+
+impl LoopFilter {
+    fn reset_deltas(&mut self) {
+        self.ref_deltas = DEFAULT_REF_DELTAS;
+        self.mode_deltas = [0; 2];
+    }
+
+    /* Coded lossless frames and those using intra block copy have no loop filter */
+    fn parse(&mut self, br: &mut BitReader, num_planes: usize, disabled: bool) -> Result<(), ()> {
+        self.level = [0; 4];
+        self.sharpness = 0;
+        self.delta_enabled = false;
+        self.delta_update = false;
+        if disabled {
+            self.reset_deltas();
+            return Ok(());
+        }
+
+        self.level[0] = br.read_bits(6)? as u8;
+        self.level[1] = br.read_bits(6)? as u8;
+        if num_planes > 1 && (self.level[0] != 0 || self.level[1] != 0) {
+            self.level[2] = br.read_bits(6)? as u8;
+            self.level[3] = br.read_bits(6)? as u8;
+        }
+        self.sharpness = br.read_bits(3)? as u8;
+        self.delta_enabled = br.read_flag()?;
+        if self.delta_enabled {
+            self.delta_update = br.read_flag()?;
+            if self.delta_update {
+                for delta in self.ref_deltas.iter_mut() {
+                    if br.read_flag()? {
+                        *delta = read_su(br, 7)? as i8;
+                    }
+                }
+                for delta in self.mode_deltas.iter_mut() {
+                    if br.read_flag()? {
+                        *delta = read_su(br, 7)? as i8;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/* The secondary strengths are kept as coded, 3 standing for 4 */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cdef {
+    pub damping_minus_3: u8,
+    pub bits: u8,
+    pub y_pri_strength: [u8; 8],
+    pub y_sec_strength: [u8; 8],
+    pub uv_pri_strength: [u8; 8],
+    pub uv_sec_strength: [u8; 8],
+}
+
+impl Cdef {
+    fn parse(br: &mut BitReader, num_planes: usize) -> Result<Cdef, ()> {
+        let mut cdef = Cdef::default();
+        cdef.damping_minus_3 = br.read_bits(2)? as u8;
+        cdef.bits = br.read_bits(2)? as u8;
+        for i in 0..1 << cdef.bits {
+            cdef.y_pri_strength[i] = br.read_bits(4)? as u8;
+            cdef.y_sec_strength[i] = br.read_bits(2)? as u8;
+            if num_planes > 1 {
+                cdef.uv_pri_strength[i] = br.read_bits(4)? as u8;
+                cdef.uv_sec_strength[i] = br.read_bits(2)? as u8;
+            }
+        }
+        Ok(cdef)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoopRestoration {
+    /* FrameRestorationType of each plane, after remapping */
+    pub frame_restoration_type: [u8; 3],
+    /* Restoration unit size is 64 << lr_unit_shift, halved for chroma by lr_uv_shift */
+    pub lr_unit_shift: u8,
+    pub lr_uv_shift: u8,
+}
+
+impl LoopRestoration {
+    fn parse(br: &mut BitReader, seq: &SequenceHeader) -> Result<LoopRestoration, ()> {
+        let cc = &seq.color_config;
+        let mut lr = LoopRestoration::default();
+        let mut uses_lr = false;
+        let mut uses_chroma_lr = false;
+        for i in 0..cc.num_planes() {
+            lr.frame_restoration_type[i] = REMAP_LR_TYPE[br.read_bits(2)? as usize];
+            if lr.frame_restoration_type[i] != RESTORE_NONE {
+                uses_lr = true;
+                uses_chroma_lr |= i > 0;
+            }
+        }
+        if !uses_lr {
+            return Ok(lr);
+        }
+
+        if seq.use_128x128_superblock {
+            lr.lr_unit_shift = br.read_bits(1)? as u8 + 1;
+        } else {
+            lr.lr_unit_shift = br.read_bits(1)? as u8;
+            if lr.lr_unit_shift != 0 {
+                lr.lr_unit_shift += br.read_bits(1)? as u8;
+            }
+        }
+        if cc.subsampling_x != 0 && cc.subsampling_y != 0 && uses_chroma_lr {
+            lr.lr_uv_shift = br.read_bits(1)? as u8;
+        }
+        Ok(lr)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GlobalMotion {
+    pub wmtype: u8,
+    pub params: [i32; 6],
+    /* The shear of the model is out of range, blocks use translation only */
+    pub invalid: bool,
+}
+
+impl Default for GlobalMotion {
+    fn default() -> GlobalMotion {
+        GlobalMotion {
+            wmtype: IDENTITY,
+            params: DEFAULT_GM_PARAMS,
+            invalid: false,
+        }
+    }
+}
+
+fn inverse_recenter(r: i32, v: i32) -> i32 {
+    if v > 2 * r {
+        v
+    } else if v & 1 != 0 {
+        r - ((v + 1) >> 1)
+    } else {
+        r + (v >> 1)
+    }
+}
+
+fn decode_subexp(br: &mut BitReader, num_syms: i32) -> Result<i32, ()> {
+    let mut i = 0;
+    let mut mk = 0;
+    let k = 3;
+    loop {
+        let b2 = if i != 0 { k + i - 1 } else { k };
+        let a = 1 << b2;
+        if num_syms <= mk + 3 * a {
+            return Ok(read_ns(br, (num_syms - mk) as u32)? as i32 + mk);
+        }
+        if !br.read_flag()? {
+            return Ok(br.read_bits(b2 as u32)? as i32 + mk);
+        }
+        i += 1;
+        mk += a;
+    }
+}
+
+/* A value in [low, high) coded relatively to a reference r */
+fn decode_signed_subexp_with_ref(br: &mut BitReader,
+                                 low: i32,
+                                 high: i32,
+                                 r: i32)
+                                 -> Result<i32, ()> {
+    let mx = high - low;
+    let r = r - low;
+    let v = decode_subexp(br, mx)?;
+    let x = if (r << 1) <= mx {
+        inverse_recenter(r, v)
+    } else {
+        mx - 1 - inverse_recenter(mx - 1 - r, v)
+    };
+    Ok(x + low)
+}
+
+fn read_global_param(br: &mut BitReader,
+                     wmtype: u8,
+                     allow_high_precision_mv: bool,
+                     prev: i32,
+                     idx: usize)
+                     -> Result<i32, ()> {
+    let (abs_bits, prec_bits) = if idx >= 2 {
+        (GM_ABS_ALPHA_BITS, GM_ALPHA_PREC_BITS)
+    } else if wmtype == TRANSLATION {
+        let low_precision = !allow_high_precision_mv as u32;
+        (GM_ABS_TRANS_ONLY_BITS - low_precision, GM_TRANS_ONLY_PREC_BITS - low_precision)
+    } else {
+        (GM_ABS_TRANS_BITS, GM_TRANS_PREC_BITS)
+    };
+    let prec_diff = WARPEDMODEL_PREC_BITS - prec_bits;
+    let (round, sub) = if idx % 3 == 2 {
+        (1 << WARPEDMODEL_PREC_BITS, 1 << prec_bits)
+    } else {
+        (0, 0)
+    };
+    let mx = 1 << abs_bits;
+    let r = (prev >> prec_diff) - sub;
+    Ok((decode_signed_subexp_with_ref(br, -mx, mx + 1, r)? << prec_diff) + round)
+}
+
+fn round2signed(value: i64, n: u32) -> i64 {
+    let round2 = |x: i64| if n == 0 { x } else { (x + (1 << (n - 1))) >> n };
+    if value >= 0 {
+        round2(value)
+    } else {
+        -round2(-value)
+    }
+}
+
+/* Shift and factor approximating a division by d */
+fn resolve_divisor(d: i64) -> (u32, i64) {
+    let n = 63 - d.abs().leading_zeros();
+    let e = d.abs() - (1 << n);
+    let f = if n > DIV_LUT_BITS {
+        round2signed(e, n - DIV_LUT_BITS)
+    } else {
+        e << (DIV_LUT_BITS - n)
+    };
+    /* Div_Lut[f], 1 / (1 + f / 256) with 14 bits of precision */
+    let divisor = 256 + f;
+    let factor = ((1 << 22) + divisor / 2) / divisor;
+    (n + DIV_LUT_PREC_BITS, if d < 0 { -factor } else { factor })
+}
+
+/* setupShear(), whether the affine part of a model can be used to warp blocks */
+fn is_shear_valid(params: &[i32; 6]) -> bool {
+    if params[2] <= 0 {
+        return false;
+    }
+    let clamp16 = |x: i64| clamp(x, -32768, 32767);
+    let reduce = |x: i64| {
+        round2signed(x, WARP_PARAM_REDUCE_BITS) << WARP_PARAM_REDUCE_BITS
+    };
+    let p: Vec<i64> = params.iter().map(|&x| x as i64).collect();
+    let (shift, factor) = resolve_divisor(p[2]);
+
+    let alpha = reduce(clamp16(p[2] - (1 << WARPEDMODEL_PREC_BITS)));
+    let beta = reduce(clamp16(p[3]));
+    let gamma = reduce(clamp16(round2signed((p[4] << WARPEDMODEL_PREC_BITS) * factor, shift)));
+    let delta = reduce(clamp16(p[5] - round2signed(p[3] * p[4] * factor, shift) -
+                               (1 << WARPEDMODEL_PREC_BITS)));
+    4 * alpha.abs() + 7 * beta.abs() < (1 << WARPEDMODEL_PREC_BITS) &&
+    4 * gamma.abs() + 4 * delta.abs() < (1 << WARPEDMODEL_PREC_BITS)
+}
+
+/* Models of the references from LAST_FRAME to ALTREF_FRAME, coded relatively to those of
+ * the primary reference frame */
+fn parse_global_motion(br: &mut BitReader,
+                       hdr: &FrameHeader,
+                       prev_gm_params: &[[i32; 6]; TOTAL_REFS_PER_FRAME])
+                       -> Result<[GlobalMotion; TOTAL_REFS_PER_FRAME], ()> {
+    let mut gm = [GlobalMotion::default(); TOTAL_REFS_PER_FRAME];
+    if hdr.is_intra() {
+        return Ok(gm);
+    }
+
+    for ref_frame in LAST_FRAME..ALTREF_FRAME + 1 {
+        let wmtype = if !br.read_flag()? {
+            IDENTITY
+        } else if br.read_flag()? {
+            ROTZOOM
+        } else if br.read_flag()? {
+            TRANSLATION
+        } else {
+            AFFINE
+        };
+
+        let prev = &prev_gm_params[ref_frame];
+        let hp = hdr.allow_high_precision_mv;
+        let params = &mut gm[ref_frame].params;
+        if wmtype >= ROTZOOM {
+            params[2] = read_global_param(br, wmtype, hp, prev[2], 2)?;
+            params[3] = read_global_param(br, wmtype, hp, prev[3], 3)?;
+            if wmtype == AFFINE {
+                params[4] = read_global_param(br, wmtype, hp, prev[4], 4)?;
+                params[5] = read_global_param(br, wmtype, hp, prev[5], 5)?;
+            } else {
+                params[4] = -params[3];
+                params[5] = params[2];
+            }
+        }
+        if wmtype >= TRANSLATION {
+            params[0] = read_global_param(br, wmtype, hp, prev[0], 0)?;
+            params[1] = read_global_param(br, wmtype, hp, prev[1], 1)?;
+        }
+        gm[ref_frame].wmtype = wmtype;
+        gm[ref_frame].invalid = !is_shear_valid(params);
+    }
+    Ok(gm)
+}
+
+/* Syntax elements as coded, the autoregressive coefficients with their 128 offset */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FilmGrainParams {
+    pub apply_grain: bool,
+    pub grain_seed: u16,
+    pub update_grain: bool,
+    pub film_grain_params_ref_idx: u8,
+    pub num_y_points: u8,
+    pub point_y_value: [u8; 14],
+    pub point_y_scaling: [u8; 14],
+    pub chroma_scaling_from_luma: bool,
+    pub num_cb_points: u8,
+    pub point_cb_value: [u8; 10],
+    pub point_cb_scaling: [u8; 10],
+    pub num_cr_points: u8,
+    pub point_cr_value: [u8; 10],
+    pub point_cr_scaling: [u8; 10],
+    pub grain_scaling_minus_8: u8,
+    pub ar_coeff_lag: u8,
+    pub ar_coeffs_y_plus_128: [u8; 24],
+    pub ar_coeffs_cb_plus_128: [u8; 25],
+    pub ar_coeffs_cr_plus_128: [u8; 25],
+    pub ar_coeff_shift_minus_6: u8,
+    pub grain_scale_shift: u8,
+    pub cb_mult: u8,
+    pub cb_luma_mult: u8,
+    pub cb_offset: u16,
+    pub cr_mult: u8,
+    pub cr_luma_mult: u8,
+    pub cr_offset: u16,
+    pub overlap_flag: bool,
+    pub clip_to_restricted_range: bool,
+}
+
+fn read_scaling_points(br: &mut BitReader,
+                       max_points: usize,
+                       values: &mut [u8],
+                       scalings: &mut [u8])
+                       -> Result<u8, ()> {
+    let num_points = br.read_bits(4)? as usize;
+    if num_points > max_points {
+        return Err(());
+    }
+    for i in 0..num_points {
+        values[i] = br.read_bits(8)? as u8;
+        scalings[i] = br.read_bits(8)? as u8;
+    }
+    Ok(num_points as u8)
+}
+
+fn read_ar_coeffs(br: &mut BitReader, coeffs: &mut [u8], num_pos: usize) -> Result<(), ()> {
+    for coeff in coeffs.iter_mut().take(num_pos) {
+        *coeff = br.read_bits(8)? as u8;
+    }
+    Ok(())
+}
+
+impl FilmGrainParams {
+    fn parse_update(&mut self, br: &mut BitReader, cc: &ColorConfig) -> Result<(), ()> {
+        self.num_y_points = read_scaling_points(br,
+                                                14,
+                                                &mut self.point_y_value,
+                                                &mut self.point_y_scaling)?;
+        self.chroma_scaling_from_luma = !cc.mono_chrome && br.read_flag()?;
+        if !cc.mono_chrome && !self.chroma_scaling_from_luma &&
+           (cc.subsampling_x != 1 || cc.subsampling_y != 1 || self.num_y_points != 0) {
+            self.num_cb_points = read_scaling_points(br,
+                                                     10,
+                                                     &mut self.point_cb_value,
+                                                     &mut self.point_cb_scaling)?;
+            self.num_cr_points = read_scaling_points(br,
+                                                     10,
+                                                     &mut self.point_cr_value,
+                                                     &mut self.point_cr_scaling)?;
+        }
+
+        self.grain_scaling_minus_8 = br.read_bits(2)? as u8;
+        self.ar_coeff_lag = br.read_bits(2)? as u8;
+        let lag = self.ar_coeff_lag as usize;
+        let num_pos_luma = 2 * lag * (lag + 1);
+        let mut num_pos_chroma = num_pos_luma;
+        if self.num_y_points != 0 {
+            read_ar_coeffs(br, &mut self.ar_coeffs_y_plus_128, num_pos_luma)?;
+            num_pos_chroma += 1;
+        }
+        if self.chroma_scaling_from_luma || self.num_cb_points != 0 {
+            read_ar_coeffs(br, &mut self.ar_coeffs_cb_plus_128, num_pos_chroma)?;
+        }
+        if self.chroma_scaling_from_luma || self.num_cr_points != 0 {
+            read_ar_coeffs(br, &mut self.ar_coeffs_cr_plus_128, num_pos_chroma)?;
+        }
+        self.ar_coeff_shift_minus_6 = br.read_bits(2)? as u8;
+        self.grain_scale_shift = br.read_bits(2)? as u8;
+        if self.num_cb_points != 0 {
+            self.cb_mult = br.read_bits(8)? as u8;
+            self.cb_luma_mult = br.read_bits(8)? as u8;
+            self.cb_offset = br.read_bits(9)? as u16;
+        }
+        if self.num_cr_points != 0 {
+            self.cr_mult = br.read_bits(8)? as u8;
+            self.cr_luma_mult = br.read_bits(8)? as u8;
+            self.cr_offset = br.read_bits(9)? as u16;
+        }
+        self.overlap_flag = br.read_flag()?;
+        self.clip_to_restricted_range = br.read_flag()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FrameHeader {
+    pub show_existing_frame: bool,
+    pub frame_to_show_map_idx: u8,
+    pub frame_type: u8,
+    pub show_frame: bool,
+    pub showable_frame: bool,
+    pub error_resilient_mode: bool,
+    pub disable_cdf_update: bool,
+    pub allow_screen_content_tools: bool,
+    pub force_integer_mv: bool,
+    pub current_frame_id: u32,
+    pub frame_size_override_flag: bool,
+    pub order_hint: u8,
+    pub primary_ref_frame: u8,
+    pub refresh_frame_flags: u8,
+    /* Slots of the references, from LAST_FRAME to ALTREF_FRAME */
+    pub ref_frame_idx: [u8; REFS_PER_FRAME],
+    /* Order hints of the references, indexed from INTRA_FRAME to ALTREF_FRAME */
+    pub order_hints: [u8; TOTAL_REFS_PER_FRAME],
+    /* Size of the decoded frame, before the super-resolution upscaling */
+    pub frame_width: u32,
+    pub frame_height: u32,
+    pub upscaled_width: u32,
+    pub render_width: u32,
+    pub render_height: u32,
+    pub use_superres: bool,
+    pub superres_denom: u32,
+    pub allow_intrabc: bool,
+    pub allow_high_precision_mv: bool,
+    pub interp_filter: u8,
+    pub is_motion_mode_switchable: bool,
+    pub use_ref_frame_mvs: bool,
+    pub disable_frame_end_update_cdf: bool,
+    pub tile_info: TileInfo,
+    pub quant: Quantization,
+    pub segmentation: Segmentation,
+    pub delta_q_present: bool,
+    pub delta_q_res: u8,
+    pub delta_lf_present: bool,
+    pub delta_lf_res: u8,
+    pub delta_lf_multi: bool,
+    pub coded_lossless: bool,
+    pub all_lossless: bool,
+    pub loop_filter: LoopFilter,
+    pub cdef: Cdef,
+    pub loop_restoration: LoopRestoration,
+    pub tx_mode: u8,
+    pub reference_select: bool,
+    pub skip_mode_present: bool,
+    pub allow_warped_motion: bool,
+    pub reduced_tx_set: bool,
+    /* Indexed by reference frame, INTRA_FRAME is unused */
+    pub global_motion: [GlobalMotion; TOTAL_REFS_PER_FRAME],
+    pub film_grain: FilmGrainParams,
+    /* Size in bytes of the byte aligned header, where the tile group of an OBU_FRAME
+     * starts */
+    pub header_bytes: usize,
+}
+
+impl FrameHeader {
+    pub fn is_intra(&self) -> bool {
+        self.frame_type == KEY_FRAME || self.frame_type == INTRA_ONLY_FRAME
+    }
+
+    pub fn get_mi_cols(&self) -> u32 {
+        2 * ((self.frame_width + 7) >> 3)
+    }
+
+    pub fn get_mi_rows(&self) -> u32 {
+        2 * ((self.frame_height + 7) >> 3)
+    }
+
+    /* Quantizer index of a segment, before the deltas of the superblocks */
+    pub fn get_qindex(&self, segment: usize) -> u8 {
+        let base = self.quant.base_q_idx as i32;
+        if !self.segmentation.is_feature_active(segment, SEG_LVL_ALT_Q) {
+            return base as u8;
+        }
+        let data = self.segmentation.feature_data[segment][SEG_LVL_ALT_Q] as i32;
+        clamp(base + data, 0, 255) as u8
+    }
+
+    pub fn is_lossless(&self, segment: usize) -> bool {
+        let q = &self.quant;
+        self.get_qindex(segment) == 0 && q.delta_q_y_dc == 0 && q.delta_q_u_dc == 0 &&
+        q.delta_q_u_ac == 0 && q.delta_q_v_dc == 0 && q.delta_q_v_ac == 0
+    }
+}
+
+/* What a frame header needs to know of the frame kept in a reference slot */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RefFrameInfo {
+    pub frame_id: u32,
+    pub frame_type: u8,
+    pub showable_frame: bool,
+    pub order_hint: u8,
+    pub upscaled_width: u32,
+    pub frame_width: u32,
+    pub frame_height: u32,
+    pub render_width: u32,
+    pub render_height: u32,
+}
+
+/* State saved along with a reference frame, loaded back by the frames predicted from it */
+#[derive(Debug, Clone)]
+struct RefFrameState {
+    info: RefFrameInfo,
+    gm_params: [[i32; 6]; TOTAL_REFS_PER_FRAME],
+    loop_filter: LoopFilter,
+    segmentation: Segmentation,
+    film_grain: FilmGrainParams,
+}
+
+/* set_frame_refs(), the slots of the references other than LAST_FRAME and GOLDEN_FRAME
+ * are guessed from their order hints */
+fn set_frame_refs(seq: &SequenceHeader,
+                  ref_order_hint: &[u8; NUM_REF_FRAMES],
+                  order_hint: u8,
+                  last_frame_idx: usize,
+                  gold_frame_idx: usize)
+                  -> Result<[u8; REFS_PER_FRAME], ()> {
+    let mut ref_frame_idx: [Option<usize>; REFS_PER_FRAME] = [None; REFS_PER_FRAME];
+    ref_frame_idx[0] = Some(last_frame_idx);
+    ref_frame_idx[GOLDEN_FRAME - LAST_FRAME] = Some(gold_frame_idx);
+    let mut used_frame = [false; NUM_REF_FRAMES];
+    used_frame[last_frame_idx] = true;
+    used_frame[gold_frame_idx] = true;
+
+    let cur_frame_hint = 1 << (seq.order_hint_bits - 1);
+    let mut shifted_order_hints = [0; NUM_REF_FRAMES];
+    for i in 0..NUM_REF_FRAMES {
+        shifted_order_hints[i] = cur_frame_hint +
+                                 seq.get_relative_dist(ref_order_hint[i], order_hint);
+    }
+    if shifted_order_hints[last_frame_idx] >= cur_frame_hint ||
+       shifted_order_hints[gold_frame_idx] >= cur_frame_hint {
+        return Err(());
+    }
+
+    /* Latest or earliest frame after the current one, or latest one before it */
+    let find = |used_frame: &[bool; NUM_REF_FRAMES], backward: bool, latest: bool| {
+        let mut found: Option<(usize, i32)> = None;
+        for i in 0..NUM_REF_FRAMES {
+            let hint = shifted_order_hints[i];
+            if used_frame[i] || (hint >= cur_frame_hint) != backward {
+                continue;
+            }
+            let better = match found {
+                None => true,
+                Some((_, best)) if latest => hint >= best,
+                Some((_, best)) => hint < best,
+            };
+            if better {
+                found = Some((i, hint));
+            }
+        }
+        found.map(|(i, _)| i)
+    };
+
+    for &(ref_frame, latest) in &[(ALTREF_FRAME, true), (BWDREF_FRAME, false),
+                                  (ALTREF2_FRAME, false)] {
+        if let Some(i) = find(&used_frame, true, latest) {
+            ref_frame_idx[ref_frame - LAST_FRAME] = Some(i);
+            used_frame[i] = true;
+        }
+    }
+    for &ref_frame in &[LAST2_FRAME, LAST3_FRAME, BWDREF_FRAME, ALTREF2_FRAME, ALTREF_FRAME] {
+        if ref_frame_idx[ref_frame - LAST_FRAME].is_none() {
+            if let Some(i) = find(&used_frame, false, true) {
+                ref_frame_idx[ref_frame - LAST_FRAME] = Some(i);
+                used_frame[i] = true;
+            }
+        }
+    }
+
+    /* The remaining references use the earliest frame */
+    let mut earliest = 0;
+    for i in 1..NUM_REF_FRAMES {
+        if shifted_order_hints[i] < shifted_order_hints[earliest] {
+            earliest = i;
+        }
+    }
+    let mut result = [0; REFS_PER_FRAME];
+    for i in 0..REFS_PER_FRAME {
+        result[i] = ref_frame_idx[i].unwrap_or(earliest) as u8;
+    }
+    Ok(result)
+}
+
+/* Keeps the sequence header and the state of the eight reference slots across the frames */
+#[derive(Debug, Clone, Default)]
+pub struct Parser {
+    seq: Option<SequenceHeader>,
+    ref_frames: [Option<RefFrameState>; NUM_REF_FRAMES],
+}
+
+impl Parser {
+    pub fn new() -> Parser {
+        Parser::default()
+    }
+
+    pub fn get_sequence_header(&self) -> Option<&SequenceHeader> {
+        self.seq.as_ref()
+    }
+
+    pub fn get_ref_frame(&self, idx: usize) -> Option<RefFrameInfo> {
+        self.ref_frames[idx].as_ref().map(|state| state.info)
+    }
+
+    pub fn parse_sequence_header(&mut self, data: &[u8]) -> Result<&SequenceHeader, ()> {
+        let seq = SequenceHeader::parse(&mut BitReader::new(data))?;
+        self.seq = Some(seq);
+        Ok(self.seq.as_ref().unwrap())
+    }
+
+    /* OBUs of the layers left out of the operating point 0 are dropped */
+    pub fn is_in_operating_point(&self, obu: &ObuHeader) -> bool {
+        let idc = self.seq.as_ref().map_or(0, |seq| seq.get_operating_point_idc());
+        if idc == 0 || !obu.has_extension || obu.obu_type == OBU_SEQUENCE_HEADER ||
+           obu.obu_type == OBU_TEMPORAL_DELIMITER {
+            return true;
+        }
+        (idc >> obu.temporal_id) & 1 != 0 && (idc >> (obu.spatial_id + 8)) & 1 != 0
+    }
+
+    /* Parses the header of an OBU_FRAME_HEADER or OBU_FRAME. The reference slots are only
+     * updated once the whole header is parsed */
+    pub fn parse_frame_header(&mut self,
+                              data: &[u8],
+                              obu: &ObuHeader)
+                              -> Result<FrameHeader, ()> {
+        let seq = self.seq.clone().ok_or(())?;
+        let mut br = BitReader::new(data);
+        let mut hdr = FrameHeader::default();
+        let id_len = seq.additional_frame_id_length_minus_1 as u32 +
+                     seq.delta_frame_id_length_minus_2 as u32 + 3;
+
+        /* RefValid and RefOrderHint */
+        let mut ref_valid = [false; NUM_REF_FRAMES];
+        let mut ref_order_hint = [0; NUM_REF_FRAMES];
+        for i in 0..NUM_REF_FRAMES {
+            if let Some(ref state) = self.ref_frames[i] {
+                ref_valid[i] = true;
+                ref_order_hint[i] = state.info.order_hint;
+            }
+        }
+
+        if seq.reduced_still_picture_header {
+            hdr.frame_type = KEY_FRAME;
+            hdr.show_frame = true;
+            hdr.error_resilient_mode = true;
+        } else {
+            hdr.show_existing_frame = br.read_flag()?;
+            if hdr.show_existing_frame {
+                hdr.frame_to_show_map_idx = br.read_bits(3)? as u8;
+                if seq.decoder_model_info_present && !seq.equal_picture_interval {
+                    br.skip_bits(seq.frame_presentation_time_length_minus_1 as usize + 1)?;
+                }
+                if seq.frame_id_numbers_present {
+                    br.skip_bits(id_len as usize)?;
+                }
+                hdr.header_bytes = (br.get_pos() + 7) / 8;
+                return self.show_existing_frame(&seq, hdr);
+            }
+
+            hdr.frame_type = br.read_bits(2)? as u8;
+            hdr.show_frame = br.read_flag()?;
+            if hdr.show_frame && seq.decoder_model_info_present && !seq.equal_picture_interval {
+                br.skip_bits(seq.frame_presentation_time_length_minus_1 as usize + 1)?;
+            }
+            hdr.showable_frame = if hdr.show_frame {
+                hdr.frame_type != KEY_FRAME
+            } else {
+                br.read_flag()?
+            };
+            hdr.error_resilient_mode = hdr.frame_type == SWITCH_FRAME ||
+                                       (hdr.frame_type == KEY_FRAME && hdr.show_frame) ||
+                                       br.read_flag()?;
+        }
+
+        if hdr.frame_type == KEY_FRAME && hdr.show_frame {
+            ref_valid = [false; NUM_REF_FRAMES];
+            ref_order_hint = [0; NUM_REF_FRAMES];
+        }
+        hdr.disable_cdf_update = br.read_flag()?;
+        hdr.allow_screen_content_tools = if seq.seq_force_screen_content_tools ==
+                                            SELECT_SCREEN_CONTENT_TOOLS {
+            br.read_flag()?
+        } else {
+            seq.seq_force_screen_content_tools != 0
+        };
+        if hdr.allow_screen_content_tools {
+            hdr.force_integer_mv = if seq.seq_force_integer_mv == SELECT_INTEGER_MV {
+                br.read_flag()?
+            } else {
+                seq.seq_force_integer_mv != 0
+            };
+        }
+        if hdr.is_intra() {
+            hdr.force_integer_mv = true;
+        }
+
+        if seq.frame_id_numbers_present {
+            hdr.current_frame_id = br.read_bits(id_len)?;
+            self.mark_ref_frames(&seq, &hdr, id_len, &mut ref_valid);
+        }
+        hdr.frame_size_override_flag = if hdr.frame_type == SWITCH_FRAME {
+            true
+        } else {
+            !seq.reduced_still_picture_header && br.read_flag()?
+        };
+        hdr.order_hint = br.read_bits(seq.order_hint_bits as u32)? as u8;
+        hdr.primary_ref_frame = if hdr.is_intra() || hdr.error_resilient_mode {
+            PRIMARY_REF_NONE
+        } else {
+            br.read_bits(3)? as u8
+        };
+
+        /* buffer_removal_time of the operating points including this frame */
+        if seq.decoder_model_info_present && br.read_flag()? {
+            for op in &seq.operating_points {
+                if !op.decoder_model_present {
+                    continue;
+                }
+                let in_temporal_layer = (op.idc >> obu.temporal_id) & 1 != 0;
+                let in_spatial_layer = (op.idc >> (obu.spatial_id + 8)) & 1 != 0;
+                if op.idc == 0 || (in_temporal_layer && in_spatial_layer) {
+                    br.skip_bits(seq.buffer_removal_time_length_minus_1 as usize + 1)?;
+                }
+            }
+        }
+
+        hdr.refresh_frame_flags = if hdr.frame_type == SWITCH_FRAME ||
+                                     (hdr.frame_type == KEY_FRAME && hdr.show_frame) {
+            0xff
+        } else {
+            br.read_bits(8)? as u8
+        };
+        if hdr.frame_type == INTRA_ONLY_FRAME && hdr.refresh_frame_flags == 0xff {
+            return Err(());
+        }
+        if (!hdr.is_intra() || hdr.refresh_frame_flags != 0xff) && hdr.error_resilient_mode &&
+           seq.enable_order_hint {
+            for i in 0..NUM_REF_FRAMES {
+                let hint = br.read_bits(seq.order_hint_bits as u32)? as u8;
+                if hint != ref_order_hint[i] || !ref_valid[i] {
+                    ref_order_hint[i] = hint;
+                    ref_valid[i] = false;
+                }
+            }
+        }
+
+        if hdr.is_intra() {
+            self.parse_frame_size(&mut br, &seq, &mut hdr)?;
+            self.parse_render_size(&mut br, &mut hdr)?;
+            if hdr.allow_screen_content_tools && hdr.upscaled_width == hdr.frame_width {
+                hdr.allow_intrabc = br.read_flag()?;
+            }
+        } else {
+            self.parse_inter_frame_refs(&mut br, &seq, &mut hdr, id_len, &ref_valid,
+                                        &ref_order_hint)?;
+        }
+
+        hdr.disable_frame_end_update_cdf = seq.reduced_still_picture_header ||
+                                           hdr.disable_cdf_update ||
+                                           br.read_flag()?;
+
+        /* load_previous() or setup_past_independence() */
+        let (prev_gm_params, mut loop_filter, mut segmentation) =
+            if hdr.primary_ref_frame == PRIMARY_REF_NONE {
+                ([DEFAULT_GM_PARAMS; TOTAL_REFS_PER_FRAME],
+                 LoopFilter::default(),
+                 Segmentation::default())
+            } else {
+                let slot = hdr.ref_frame_idx[hdr.primary_ref_frame as usize] as usize;
+                let state = self.ref_frames[slot].as_ref().ok_or(())?;
+                (state.gm_params, state.loop_filter.clone(), state.segmentation.clone())
+            };
+
+        let cc = &seq.color_config;
+        hdr.tile_info = TileInfo::parse(&mut br, &seq, hdr.get_mi_cols(), hdr.get_mi_rows())?;
+        hdr.quant = Quantization::parse(&mut br, cc)?;
+        segmentation.parse(&mut br, hdr.primary_ref_frame)?;
+        hdr.segmentation = segmentation;
+
+        if hdr.quant.base_q_idx > 0 {
+            hdr.delta_q_present = br.read_flag()?;
+        }
+        if hdr.delta_q_present {
+            hdr.delta_q_res = br.read_bits(2)? as u8;
+            if !hdr.allow_intrabc {
+                hdr.delta_lf_present = br.read_flag()?;
+            }
+            if hdr.delta_lf_present {
+                hdr.delta_lf_res = br.read_bits(2)? as u8;
+                hdr.delta_lf_multi = br.read_flag()?;
+            }
+        }
+
+        hdr.coded_lossless = (0..MAX_SEGMENTS).all(|i| hdr.is_lossless(i));
+        hdr.all_lossless = hdr.coded_lossless && hdr.frame_width == hdr.upscaled_width;
+
+        loop_filter.parse(&mut br, cc.num_planes(), hdr.coded_lossless || hdr.allow_intrabc)?;
+        hdr.loop_filter = loop_filter;
+        if !hdr.coded_lossless && !hdr.allow_intrabc && seq.enable_cdef {
+            hdr.cdef = Cdef::parse(&mut br, cc.num_planes())?;
+        }
+        if !hdr.all_lossless && !hdr.allow_intrabc && seq.enable_restoration {
+            hdr.loop_restoration = LoopRestoration::parse(&mut br, &seq)?;
+        }
+
+        hdr.tx_mode = if hdr.coded_lossless {
+            ONLY_4X4
+        } else if br.read_flag()? {
+            TX_MODE_SELECT
+        } else {
+            TX_MODE_LARGEST
+        };
+        hdr.reference_select = !hdr.is_intra() && br.read_flag()?;
+        if self.is_skip_mode_allowed(&seq, &hdr, &ref_order_hint) {
+            hdr.skip_mode_present = br.read_flag()?;
+        }
+        hdr.allow_warped_motion = !hdr.is_intra() && !hdr.error_resilient_mode &&
+                                  seq.enable_warped_motion &&
+                                  br.read_flag()?;
+        hdr.reduced_tx_set = br.read_flag()?;
+        hdr.global_motion = parse_global_motion(&mut br, &hdr, &prev_gm_params)?;
+        hdr.film_grain = self.parse_film_grain(&mut br, &seq, &hdr)?;
+        br.byte_align();
+        hdr.header_bytes = br.get_pos() / 8;
+
+        /* Reference frame update process */
+        for i in 0..NUM_REF_FRAMES {
+            if !ref_valid[i] {
+                self.ref_frames[i] = None;
+            }
+        }
+        let mut gm_params = [DEFAULT_GM_PARAMS; TOTAL_REFS_PER_FRAME];
+        for (params, gm) in gm_params.iter_mut().zip(hdr.global_motion.iter()) {
+            *params = gm.params;
+        }
+        let state = RefFrameState {
+            info: RefFrameInfo {
+                frame_id: hdr.current_frame_id,
+                frame_type: hdr.frame_type,
+                showable_frame: hdr.showable_frame,
+                order_hint: hdr.order_hint,
+                upscaled_width: hdr.upscaled_width,
+                frame_width: hdr.frame_width,
+                frame_height: hdr.frame_height,
+                render_width: hdr.render_width,
+                render_height: hdr.render_height,
+            },
+            gm_params: gm_params,
+            loop_filter: hdr.loop_filter.clone(),
+            segmentation: hdr.segmentation.clone(),
+            film_grain: hdr.film_grain.clone(),
+        };
+        for i in 0..NUM_REF_FRAMES {
+            if hdr.refresh_frame_flags & (1 << i) != 0 {
+                self.ref_frames[i] = Some(state.clone());
+            }
+        }
+
+        Ok(hdr)
+    }
+
+    /* A key frame shown again refreshes every slot, as if it had just been decoded */
+    fn show_existing_frame(&mut self,
+                           seq: &SequenceHeader,
+                           mut hdr: FrameHeader)
+                           -> Result<FrameHeader, ()> {
+        let idx = hdr.frame_to_show_map_idx as usize;
+        let state = self.ref_frames[idx].clone().ok_or(())?;
+        if !state.info.showable_frame {
+            return Err(());
+        }
+
+        let info = &state.info;
+        hdr.frame_type = info.frame_type;
+        hdr.show_frame = true;
+        hdr.order_hint = info.order_hint;
+        hdr.upscaled_width = info.upscaled_width;
+        hdr.frame_width = info.frame_width;
+        hdr.frame_height = info.frame_height;
+        hdr.render_width = info.render_width;
+        hdr.render_height = info.render_height;
+        if seq.film_grain_params_present {
+            hdr.film_grain = state.film_grain.clone();
+        }
+
+        if hdr.frame_type == KEY_FRAME {
+            hdr.refresh_frame_flags = 0xff;
+            let mut state = state.clone();
+            state.info.showable_frame = false;
+            for slot in self.ref_frames.iter_mut() {
+                *slot = Some(state.clone());
+            }
+        }
+        Ok(hdr)
+    }
+
+    /* Slots whose frame id is too far from the current one are no longer valid */
+    fn mark_ref_frames(&self,
+                       seq: &SequenceHeader,
+                       hdr: &FrameHeader,
+                       id_len: u32,
+                       ref_valid: &mut [bool; NUM_REF_FRAMES]) {
+        let diff_len = seq.delta_frame_id_length_minus_2 as u32 + 2;
+        let cur = hdr.current_frame_id;
+        for i in 0..NUM_REF_FRAMES {
+            let ref_id = match self.ref_frames[i] {
+                Some(ref state) => state.info.frame_id,
+                None => continue,
+            };
+            let stale = if cur > (1 << diff_len) {
+                ref_id > cur || ref_id < cur - (1 << diff_len)
+            } else {
+                ref_id > cur && ref_id < (1 << id_len) + cur - (1 << diff_len)
+            };
+            if stale {
+                ref_valid[i] = false;
+            }
+        }
+    }
+
+    fn parse_inter_frame_refs(&self,
+                              br: &mut BitReader,
+                              seq: &SequenceHeader,
+                              hdr: &mut FrameHeader,
+                              id_len: u32,
+                              ref_valid: &[bool; NUM_REF_FRAMES],
+                              ref_order_hint: &[u8; NUM_REF_FRAMES])
+                              -> Result<(), ()> {
+        let short_signaling = seq.enable_order_hint && br.read_flag()?;
+        if short_signaling {
+            let last_frame_idx = br.read_bits(3)? as usize;
+            let gold_frame_idx = br.read_bits(3)? as usize;
+            hdr.ref_frame_idx = set_frame_refs(seq,
+                                               ref_order_hint,
+                                               hdr.order_hint,
+                                               last_frame_idx,
+                                               gold_frame_idx)?;
+        }
+        for i in 0..REFS_PER_FRAME {
+            if !short_signaling {
+                hdr.ref_frame_idx[i] = br.read_bits(3)? as u8;
+            }
+            let idx = hdr.ref_frame_idx[i] as usize;
+            if !ref_valid[idx] {
+                return Err(());
+            }
+            if seq.frame_id_numbers_present {
+                let delta = br.read_bits(seq.delta_frame_id_length_minus_2 as u32 + 2)? + 1;
+                let expected = (hdr.current_frame_id + (1 << id_len) - delta) % (1 << id_len);
+                if self.get_ref_frame(idx).map(|info| info.frame_id) != Some(expected) {
+                    return Err(());
+                }
+            }
+        }
+
+        if hdr.frame_size_override_flag && !hdr.error_resilient_mode {
+            self.parse_frame_size_with_refs(br, seq, hdr)?;
+        } else {
+            self.parse_frame_size(br, seq, hdr)?;
+            self.parse_render_size(br, hdr)?;
+        }
+        hdr.allow_high_precision_mv = !hdr.force_integer_mv && br.read_flag()?;
+        hdr.interp_filter = if br.read_flag()? {
+            INTERP_FILTER_SWITCHABLE
+        } else {
+            br.read_bits(2)? as u8
+        };
+        hdr.is_motion_mode_switchable = br.read_flag()?;
+        hdr.use_ref_frame_mvs = !hdr.error_resilient_mode && seq.enable_ref_frame_mvs &&
+                                br.read_flag()?;
+        for i in 0..REFS_PER_FRAME {
+            hdr.order_hints[LAST_FRAME + i] = ref_order_hint[hdr.ref_frame_idx[i] as usize];
+        }
+        Ok(())
+    }
+
+    fn parse_frame_size(&self,
+                        br: &mut BitReader,
+                        seq: &SequenceHeader,
+                        hdr: &mut FrameHeader)
+                        -> Result<(), ()> {
+        if hdr.frame_size_override_flag {
+            hdr.frame_width = br.read_bits(seq.frame_width_bits)? + 1;
+            hdr.frame_height = br.read_bits(seq.frame_height_bits)? + 1;
+            if hdr.frame_width > seq.max_frame_width || hdr.frame_height > seq.max_frame_height {
+                return Err(());
+            }
+        } else {
+            hdr.frame_width = seq.max_frame_width;
+            hdr.frame_height = seq.max_frame_height;
+        }
+        self.parse_superres_params(br, seq, hdr)
+    }
+
+    /* The frame is decoded at a reduced width, then upscaled to frame_width */
+    fn parse_superres_params(&self,
+                             br: &mut BitReader,
+                             seq: &SequenceHeader,
+                             hdr: &mut FrameHeader)
+                             -> Result<(), ()> {
+        hdr.use_superres = seq.enable_superres && br.read_flag()?;
+        hdr.superres_denom = if hdr.use_superres {
+            br.read_bits(SUPERRES_DENOM_BITS)? + SUPERRES_DENOM_MIN
+        } else {
+            SUPERRES_NUM
+        };
+        hdr.upscaled_width = hdr.frame_width;
+        hdr.frame_width = (hdr.upscaled_width * SUPERRES_NUM + hdr.superres_denom / 2) /
+                          hdr.superres_denom;
+        Ok(())
+    }
+
+    fn parse_render_size(&self, br: &mut BitReader, hdr: &mut FrameHeader) -> Result<(), ()> {
+        if br.read_flag()? {
+            hdr.render_width = br.read_bits(16)? + 1;
+            hdr.render_height = br.read_bits(16)? + 1;
+        } else {
+            hdr.render_width = hdr.upscaled_width;
+            hdr.render_height = hdr.frame_height;
+        }
+        Ok(())
+    }
+
+    /* The size is either copied from one of the references or sent explicitly */
+    fn parse_frame_size_with_refs(&self,
+                                  br: &mut BitReader,
+                                  seq: &SequenceHeader,
+                                  hdr: &mut FrameHeader)
+                                  -> Result<(), ()> {
+        for i in 0..REFS_PER_FRAME {
+            if br.read_flag()? {
+                let info = self.get_ref_frame(hdr.ref_frame_idx[i] as usize).ok_or(())?;
+                hdr.frame_width = info.upscaled_width;
+                hdr.frame_height = info.frame_height;
+                hdr.render_width = info.render_width;
+                hdr.render_height = info.render_height;
+                return self.parse_superres_params(br, seq, hdr);
+            }
+        }
+        self.parse_frame_size(br, seq, hdr)?;
+        self.parse_render_size(br, hdr)
+    }
+
+    /* Skip mode needs a forward reference, and a backward one or a second forward one */
+    fn is_skip_mode_allowed(&self,
+                            seq: &SequenceHeader,
+                            hdr: &FrameHeader,
+                            ref_order_hint: &[u8; NUM_REF_FRAMES])
+                            -> bool {
+        if hdr.is_intra() || !hdr.reference_select || !seq.enable_order_hint {
+            return false;
+        }
+
+        let mut forward_hint = None;
+        let mut backward_hint = None;
+        for &idx in &hdr.ref_frame_idx {
+            let ref_hint = ref_order_hint[idx as usize];
+            let dist = seq.get_relative_dist(ref_hint, hdr.order_hint);
+            if dist < 0 {
+                if forward_hint.map_or(true, |h| seq.get_relative_dist(ref_hint, h) > 0) {
+                    forward_hint = Some(ref_hint);
+                }
+            } else if dist > 0 &&
+                      backward_hint.map_or(true, |h| seq.get_relative_dist(ref_hint, h) < 0) {
+                backward_hint = Some(ref_hint);
+            }
+        }
+
+        match (forward_hint, backward_hint) {
+            (None, _) => false,
+            (Some(_), Some(_)) => true,
+            (Some(forward_hint), None) => {
+                hdr.ref_frame_idx.iter().any(|&idx| {
+                    seq.get_relative_dist(ref_order_hint[idx as usize], forward_hint) < 0
+                })
+            }
+        }
+    }
+
+    /* Parameters only coded for frames meant to be shown, either updated or loaded from one
+     * of the references */
+    fn parse_film_grain(&self,
+                        br: &mut BitReader,
+                        seq: &SequenceHeader,
+                        hdr: &FrameHeader)
+                        -> Result<FilmGrainParams, ()> {
+        let mut fg = FilmGrainParams::default();
+        if !seq.film_grain_params_present || (!hdr.show_frame && !hdr.showable_frame) {
+            return Ok(fg);
+        }
+        fg.apply_grain = br.read_flag()?;
+        if !fg.apply_grain {
+            return Ok(fg);
+        }
+
+        fg.grain_seed = br.read_bits(16)? as u16;
+        fg.update_grain = hdr.frame_type != INTER_FRAME || br.read_flag()?;
+        if !fg.update_grain {
+            let idx = br.read_bits(3)? as u8;
+            if !hdr.ref_frame_idx.contains(&idx) {
+                return Err(());
+            }
+            let state = self.ref_frames[idx as usize].as_ref().ok_or(())?;
+            return Ok(FilmGrainParams {
+                grain_seed: fg.grain_seed,
+                film_grain_params_ref_idx: idx,
+                ..state.film_grain.clone()
+            });
+        }
+
+        fg.parse_update(br, &seq.color_config)?;
+        Ok(fg)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tile {
+    pub row: u32,
+    pub col: u32,
+    /* Position of the tile in the tile data of its group */
+    pub offset: usize,
+    pub size: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TileGroup {
+    pub tg_start: u32,
+    pub tg_end: u32,
+    /* Offset of the tile data in the payload */
+    pub data_offset: usize,
+    pub tiles: Vec<Tile>,
+}
+
+/* Splits the payload of an OBU_TILE_GROUP, or what follows the header in an OBU_FRAME,
+ * into tiles */
+pub fn parse_tile_group(hdr: &FrameHeader, data: &[u8]) -> Result<TileGroup, ()> {
+    let info = &hdr.tile_info;
+    let num_tiles = info.num_tiles();
+    /* Shown existing frames carry no tile info */
+    if num_tiles == 0 {
+        return Err(());
+    }
+    let mut br = BitReader::new(data);
+    let mut tg = TileGroup::default();
+    tg.tg_end = num_tiles - 1;
+    if num_tiles > 1 && br.read_flag()? {
+        let tile_bits = info.cols_log2 + info.rows_log2;
+        tg.tg_start = br.read_bits(tile_bits)?;
+        tg.tg_end = br.read_bits(tile_bits)?;
+        if tg.tg_end < tg.tg_start || tg.tg_end >= num_tiles {
+            return Err(());
+        }
+    }
+    br.byte_align();
+    tg.data_offset = br.get_pos() / 8;
+
+    let data = &data[tg.data_offset..];
+    let size_bytes = info.tile_size_bytes as usize;
+    let mut pos = 0;
+    for tile_num in tg.tg_start..tg.tg_end + 1 {
+        let size = if tile_num == tg.tg_end {
+            data.len() - pos
+        } else {
+            if data.len() - pos < size_bytes {
+                return Err(());
+            }
+            let mut size = 0;
+            for i in 0..size_bytes {
+                size |= (data[pos + i] as usize) << (8 * i);
+            }
+            pos += size_bytes;
+            size + 1
+        };
+        if size == 0 || size > data.len() - pos {
+            return Err(());
+        }
+        tg.tiles.push(Tile {
+            row: tile_num / info.num_cols(),
+            col: tile_num % info.num_cols(),
+            offset: pos,
+            size: size,
+        });
+        pos += size;
+    }
+    Ok(tg)
+}
+
+/* The reference frame map holds the surfaces of the eight slots, managed by the caller.
+ * Without film grain, the frame is displayed from the surface it is decoded to */
+pub fn build_picture_param(seq: &SequenceHeader,
+                           hdr: &FrameHeader,
+                           current_frame: ffi::VASurfaceID,
+                           current_display_picture: ffi::VASurfaceID,
+                           ref_frame_map: &[ffi::VASurfaceID; NUM_REF_FRAMES])
+                           -> Result<ffi::VADecPictureParameterBufferAV1, ()> {
+    /* The anchor frame list of the large scale tiles is left null */
+    let mut param: ffi::VADecPictureParameterBufferAV1 = zeroed();
+    let cc = &seq.color_config;
+    param.profile = seq.profile;
+    param.order_hint_bits_minus_1 = seq.order_hint_bits.saturating_sub(1);
+    param.bit_depth_idx = match cc.bit_depth {
+        8 => 0,
+        10 => 1,
+        _ => 2,
+    };
+    param.matrix_coefficients = cc.matrix_coefficients;
+    param.seq_info_fields = AV1SeqInfoFields {
+        still_picture: seq.still_picture as u32,
+        use_128x128_superblock: seq.use_128x128_superblock as u32,
+        enable_filter_intra: seq.enable_filter_intra as u32,
+        enable_intra_edge_filter: seq.enable_intra_edge_filter as u32,
+        enable_interintra_compound: seq.enable_interintra_compound as u32,
+        enable_masked_compound: seq.enable_masked_compound as u32,
+        enable_dual_filter: seq.enable_dual_filter as u32,
+        enable_order_hint: seq.enable_order_hint as u32,
+        enable_jnt_comp: seq.enable_jnt_comp as u32,
+        enable_cdef: seq.enable_cdef as u32,
+        mono_chrome: cc.mono_chrome as u32,
+        color_range: cc.color_range as u32,
+        subsampling_x: cc.subsampling_x as u32,
+        subsampling_y: cc.subsampling_y as u32,
+        chroma_sample_position: cc.chroma_sample_position as u32,
+        film_grain_params_present: seq.film_grain_params_present as u32,
+    }.to_va()?;
+
+    param.current_frame = current_frame;
+    param.current_display_picture = current_display_picture;
+    param.frame_width_minus1 = (hdr.upscaled_width - 1) as u16;
+    param.frame_height_minus1 = (hdr.frame_height - 1) as u16;
+    param.ref_frame_map = *ref_frame_map;
+    param.ref_frame_idx = hdr.ref_frame_idx;
+    param.primary_ref_frame = hdr.primary_ref_frame;
+    param.order_hint = hdr.order_hint;
+
+    let seg = &hdr.segmentation;
+    param.seg_info.segment_info_fields = AV1SegmentInfoFields {
+        enabled: seg.enabled as u32,
+        update_map: seg.update_map as u32,
+        temporal_update: seg.temporal_update as u32,
+        update_data: seg.update_data as u32,
+    }.to_va()?;
+    for i in 0..MAX_SEGMENTS {
+        param.seg_info.feature_data[i] = seg.feature_data[i];
+        for j in 0..SEG_LVL_MAX {
+            if seg.feature_enabled[i][j] {
+                param.seg_info.feature_mask[i] |= 1 << j;
+            }
+        }
+    }
+
+    let fg = &hdr.film_grain;
+    let fg_param = &mut param.film_grain_info;
+    fg_param.film_grain_info_fields = AV1FilmGrainInfoFields {
+        apply_grain: fg.apply_grain as u32,
+        chroma_scaling_from_luma: fg.chroma_scaling_from_luma as u32,
+        grain_scaling_minus_8: fg.grain_scaling_minus_8 as u32,
+        ar_coeff_lag: fg.ar_coeff_lag as u32,
+        ar_coeff_shift_minus_6: fg.ar_coeff_shift_minus_6 as u32,
+        grain_scale_shift: fg.grain_scale_shift as u32,
+        overlap_flag: fg.overlap_flag as u32,
+        clip_to_restricted_range: fg.clip_to_restricted_range as u32,
+    }.to_va()?;
+    fg_param.grain_seed = fg.grain_seed;
+    fg_param.num_y_points = fg.num_y_points;
+    fg_param.point_y_value = fg.point_y_value;
+    fg_param.point_y_scaling = fg.point_y_scaling;
+    fg_param.num_cb_points = fg.num_cb_points;
+    fg_param.point_cb_value = fg.point_cb_value;
+    fg_param.point_cb_scaling = fg.point_cb_scaling;
+    fg_param.num_cr_points = fg.num_cr_points;
+    fg_param.point_cr_value = fg.point_cr_value;
+    fg_param.point_cr_scaling = fg.point_cr_scaling;
+    let unbias = |coeffs: &[u8], out: &mut [i8]| {
+        for (out, &coeff) in out.iter_mut().zip(coeffs) {
+            *out = (coeff as i32 - 128) as i8;
+        }
+    };
+    unbias(&fg.ar_coeffs_y_plus_128, &mut fg_param.ar_coeffs_y);
+    unbias(&fg.ar_coeffs_cb_plus_128, &mut fg_param.ar_coeffs_cb);
+    unbias(&fg.ar_coeffs_cr_plus_128, &mut fg_param.ar_coeffs_cr);
+    fg_param.cb_mult = fg.cb_mult;
+    fg_param.cb_luma_mult = fg.cb_luma_mult;
+    fg_param.cb_offset = fg.cb_offset;
+    fg_param.cr_mult = fg.cr_mult;
+    fg_param.cr_luma_mult = fg.cr_luma_mult;
+    fg_param.cr_offset = fg.cr_offset;
+
+    /* The arrays hold 63 sizes, a 64th tile column or row is implied */
+    let tiles = &hdr.tile_info;
+    param.tile_cols = tiles.num_cols() as u8;
+    param.tile_rows = tiles.num_rows() as u8;
+    for (param_size, &size) in param.width_in_sbs_minus_1.iter_mut().zip(&tiles.width_in_sbs) {
+        *param_size = (size - 1) as u16;
+    }
+    for (param_size, &size) in param.height_in_sbs_minus_1.iter_mut().zip(&tiles.height_in_sbs) {
+        *param_size = (size - 1) as u16;
+    }
+    param.tile_count_minus_1 = (tiles.num_tiles() - 1) as u16;
+    param.context_update_tile_id = tiles.context_update_tile_id as u16;
+
+    param.pic_info_fields = AV1PicInfoFields {
+        frame_type: hdr.frame_type as u32,
+        show_frame: hdr.show_frame as u32,
+        showable_frame: hdr.showable_frame as u32,
+        error_resilient_mode: hdr.error_resilient_mode as u32,
+        disable_cdf_update: hdr.disable_cdf_update as u32,
+        allow_screen_content_tools: hdr.allow_screen_content_tools as u32,
+        force_integer_mv: hdr.force_integer_mv as u32,
+        allow_intrabc: hdr.allow_intrabc as u32,
+        use_superres: hdr.use_superres as u32,
+        allow_high_precision_mv: hdr.allow_high_precision_mv as u32,
+        is_motion_mode_switchable: hdr.is_motion_mode_switchable as u32,
+        use_ref_frame_mvs: hdr.use_ref_frame_mvs as u32,
+        disable_frame_end_update_cdf: hdr.disable_frame_end_update_cdf as u32,
+        uniform_tile_spacing_flag: tiles.uniform_spacing as u32,
+        allow_warped_motion: hdr.allow_warped_motion as u32,
+        large_scale_tile: 0,
+    }.to_va()?;
+    param.superres_scale_denominator = hdr.superres_denom as u8;
+    param.interp_filter = hdr.interp_filter;
+
+    let lf = &hdr.loop_filter;
+    param.filter_level = [lf.level[0], lf.level[1]];
+    param.filter_level_u = lf.level[2];
+    param.filter_level_v = lf.level[3];
+    param.loop_filter_info_fields = AV1LoopFilterInfoFields {
+        sharpness_level: lf.sharpness,
+        mode_ref_delta_enabled: lf.delta_enabled as u8,
+        mode_ref_delta_update: lf.delta_update as u8,
+    }.to_va()?;
+    param.ref_deltas = lf.ref_deltas;
+    param.mode_deltas = lf.mode_deltas;
+
+    let q = &hdr.quant;
+    param.base_qindex = q.base_q_idx;
+    param.y_dc_delta_q = q.delta_q_y_dc;
+    param.u_dc_delta_q = q.delta_q_u_dc;
+    param.u_ac_delta_q = q.delta_q_u_ac;
+    param.v_dc_delta_q = q.delta_q_v_dc;
+    param.v_ac_delta_q = q.delta_q_v_ac;
+    param.qmatrix_fields = AV1QMatrixFields {
+        using_qmatrix: q.using_qmatrix as u16,
+        qm_y: q.qm_y as u16,
+        qm_u: q.qm_u as u16,
+        qm_v: q.qm_v as u16,
+    }.to_va()?;
+    param.mode_control_fields = AV1ModeControlFields {
+        delta_q_present_flag: hdr.delta_q_present as u32,
+        log2_delta_q_res: hdr.delta_q_res as u32,
+        delta_lf_present_flag: hdr.delta_lf_present as u32,
+        log2_delta_lf_res: hdr.delta_lf_res as u32,
+        delta_lf_multi: hdr.delta_lf_multi as u32,
+        tx_mode: hdr.tx_mode as u32,
+        reference_select: hdr.reference_select as u32,
+        reduced_tx_set_used: hdr.reduced_tx_set as u32,
+        skip_mode_present: hdr.skip_mode_present as u32,
+    }.to_va()?;
+
+    /* Primary strength in the upper bits, secondary one as coded in the lower two */
+    let cdef = &hdr.cdef;
+    param.cdef_damping_minus_3 = cdef.damping_minus_3;
+    param.cdef_bits = cdef.bits;
+    for i in 0..8 {
+        param.cdef_y_strengths[i] = (cdef.y_pri_strength[i] << 2) | cdef.y_sec_strength[i];
+        param.cdef_uv_strengths[i] = (cdef.uv_pri_strength[i] << 2) | cdef.uv_sec_strength[i];
+    }
+
+    let lr = &hdr.loop_restoration;
+    param.loop_restoration_fields = AV1LoopRestorationFields {
+        yframe_restoration_type: lr.frame_restoration_type[0] as u16,
+        cbframe_restoration_type: lr.frame_restoration_type[1] as u16,
+        crframe_restoration_type: lr.frame_restoration_type[2] as u16,
+        lr_unit_shift: lr.lr_unit_shift as u16,
+        lr_uv_shift: lr.lr_uv_shift as u16,
+    }.to_va()?;
+
+    for i in 0..REFS_PER_FRAME {
+        let gm = &hdr.global_motion[LAST_FRAME + i];
+        let wm = &mut param.wm[i];
+        wm.wmtype = match gm.wmtype {
+            TRANSLATION => ffi::VAAV1TransformationTranslation,
+            ROTZOOM => ffi::VAAV1TransformationRotzoom,
+            AFFINE => ffi::VAAV1TransformationAffine,
+            _ => ffi::VAAV1TransformationIdentity,
+        };
+        wm.wmmat[..6].copy_from_slice(&gm.params);
+        wm.invalid = gm.invalid as u8;
+    }
+    Ok(param)
+}
+
+/* One slice parameter per tile, the offsets are relative to the tile data of the group */
+pub fn build_tile_params(tg: &TileGroup) -> Vec<ffi::VASliceParameterBufferAV1> {
+    tg.tiles
+        .iter()
+        .map(|tile| {
+            let mut param: ffi::VASliceParameterBufferAV1 = zeroed();
+            param.slice_data_size = tile.size as u32;
+            param.slice_data_offset = tile.offset as u32;
+            param.slice_data_flag = ffi::VA_SLICE_DATA_FLAG_ALL;
+            param.tile_row = tile.row as u16;
+            param.tile_column = tile.col as u16;
+            param.tg_start = tg.tg_start as u16;
+            param.tg_end = tg.tg_end as u16;
+            param
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitreader::BitReader;
+    use ffi;
+    use ivf::parse_ivf;
+
+    /* Two tile columns, a key frame followed by a temporal unit made of two hidden frames
+     * and a shown one, the hidden frames being shown later as existing frames */
+    const TILES_128X64: &'static [u8] = include_bytes!("../tests/data/av1/128x64_2tiles.ivf");
+    /* Film grain parameters sent with every frame */
+    const FILM_GRAIN_64X64: &'static [u8] =
+        include_bytes!("../tests/data/av1/64x64_film_grain.ivf");
+
+    fn parse_stream(data: &[u8]) -> (Parser, Vec<(FrameHeader, Option<TileGroup>)>) {
+        let (_, chunks) = parse_ivf(data).unwrap();
+        let mut parser = Parser::new();
+        let mut frames = Vec::new();
+        for chunk in chunks {
+            for obu in split_obus(chunk.data).unwrap() {
+                match obu.header.obu_type {
+                    OBU_SEQUENCE_HEADER => {
+                        parser.parse_sequence_header(obu.data).unwrap();
+                    }
+                    OBU_FRAME_HEADER | OBU_FRAME => {
+                        let hdr = parser.parse_frame_header(obu.data, &obu.header).unwrap();
+                        let tg = if obu.header.obu_type == OBU_FRAME {
+                            Some(parse_tile_group(&hdr, &obu.data[hdr.header_bytes..]).unwrap())
+                        } else {
+                            None
+                        };
+                        frames.push((hdr, tg));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        (parser, frames)
+    }
+
+    #[test]
+    fn obus() {
+        let (_, chunks) = parse_ivf(TILES_128X64).unwrap();
+        assert_eq!(chunks.len(), 5);
+        let obus = split_obus(chunks[0].data).unwrap();
+        let types: Vec<u8> = obus.iter().map(|obu| obu.header.obu_type).collect();
+        assert_eq!(types, vec![OBU_TEMPORAL_DELIMITER, OBU_SEQUENCE_HEADER, OBU_FRAME]);
+        let sizes: Vec<usize> = obus.iter().map(|obu| obu.data.len()).collect();
+        assert_eq!(sizes, vec![0, 10, 1028]);
+        assert_eq!(split_obus(chunks[1].data).unwrap().len(), 4);
+
+        /* Extension header, and a last OBU without size field */
+        let data = [0x36, 0x48, 0x02, 0xaa, 0xbb, 0x30, 0xcc];
+        let obus = split_obus(&data).unwrap();
+        assert_eq!(obus.len(), 2);
+        assert_eq!(obus[0].header,
+                   ObuHeader {
+                       obu_type: OBU_FRAME,
+                       has_extension: true,
+                       temporal_id: 2,
+                       spatial_id: 1,
+                   });
+        assert_eq!(obus[0].data, &[0xaa, 0xbb]);
+        assert_eq!(obus[1].header.obu_type, OBU_FRAME);
+        assert_eq!(obus[1].data, &[0xcc]);
+
+        /* Forbidden bit, sizes past the data and unterminated sizes */
+        assert!(split_obus(&[0x92, 0x00]).is_err());
+        assert!(split_obus(&[0x32, 0x03, 0x00]).is_err());
+        assert!(split_obus(&[0x32, 0x80, 0x80]).is_err());
+        assert!(split_obus(&[0x36]).is_err());
+        assert_eq!(split_obus(&[]).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn syntax_elements() {
+        assert_eq!(read_leb128(&[0x05]), Ok((5, 1)));
+        assert_eq!(read_leb128(&[0xe5, 0x8e, 0x26]), Ok((624485, 3)));
+        assert_eq!(read_leb128(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x01]), Err(()));
+
+        let data = [0xf8, 0x78];
+        let mut br = BitReader::new(&data);
+        assert_eq!(read_su(&mut br, 4), Ok(-1));
+        assert_eq!(read_su(&mut br, 4), Ok(-8));
+        assert_eq!(read_ns(&mut br, 5), Ok(1));
+        assert_eq!(read_ns(&mut br, 5), Ok(4));
+
+        assert_eq!(tile_log2(1, 3), 2);
+        assert_eq!(tile_log2(64, 3), 0);
+        assert_eq!(inverse_recenter(10, 3), 8);
+        assert_eq!(inverse_recenter(10, 4), 12);
+        assert_eq!(inverse_recenter(2, 7), 7);
+        assert!(is_shear_valid(&DEFAULT_GM_PARAMS));
+        assert!(!is_shear_valid(&[0, 0, 0, 0, 0, 1 << 16]));
+        assert!(!is_shear_valid(&[0, 0, 1 << 16, 1 << 15, 0, 1 << 16]));
+    }
+
+    #[test]
+    fn sequence_header() {
+        let (parser, _) = parse_stream(TILES_128X64);
+        let seq = parser.get_sequence_header().unwrap();
+        assert_eq!(seq.profile, 0);
+        assert!(!seq.still_picture && !seq.reduced_still_picture_header);
+        assert!(!seq.timing_info_present);
+        assert_eq!(seq.operating_points.len(), 1);
+        assert_eq!(seq.get_operating_point_idc(), 0);
+        assert_eq!((seq.frame_width_bits, seq.frame_height_bits), (7, 6));
+        assert_eq!((seq.max_frame_width, seq.max_frame_height), (128, 64));
+        assert!(!seq.frame_id_numbers_present && !seq.use_128x128_superblock);
+        assert!(seq.enable_filter_intra && seq.enable_intra_edge_filter);
+        assert!(seq.enable_masked_compound && seq.enable_warped_motion);
+        assert!(!seq.enable_interintra_compound && !seq.enable_dual_filter);
+        assert!(seq.enable_order_hint && seq.enable_ref_frame_mvs && !seq.enable_jnt_comp);
+        assert_eq!(seq.seq_force_screen_content_tools, SELECT_SCREEN_CONTENT_TOOLS);
+        assert_eq!(seq.seq_force_integer_mv, SELECT_INTEGER_MV);
+        assert_eq!(seq.order_hint_bits, 7);
+        assert!(seq.enable_cdef && !seq.enable_restoration && !seq.enable_superres);
+        assert!(!seq.film_grain_params_present);
+
+        let cc = &seq.color_config;
+        assert_eq!((cc.bit_depth, cc.mono_chrome, cc.color_range), (8, false, false));
+        assert_eq!((cc.subsampling_x, cc.subsampling_y), (1, 1));
+        assert_eq!(cc.matrix_coefficients, MC_UNSPECIFIED);
+        assert_eq!(cc.num_planes(), 3);
+        assert_eq!(seq.get_rt_format(), Ok(ffi::VA_RT_FORMAT_YUV420));
+        assert_eq!(seq.get_va_profile(), Ok(ffi::VAProfileAV1Profile0));
+
+        assert_eq!(seq.get_relative_dist(4, 2), 2);
+        assert_eq!(seq.get_relative_dist(1, 127), 2);
+        assert_eq!(seq.get_relative_dist(127, 1), -2);
+
+        /* Monochrome has no VA render target */
+        let mut mono = seq.clone();
+        mono.color_config.mono_chrome = true;
+        assert!(mono.get_rt_format().is_err() && mono.get_va_profile().is_err());
+    }
+
+    #[test]
+    fn key_frame() {
+        let (_, frames) = parse_stream(TILES_128X64);
+        let (ref hdr, ref tg) = frames[0];
+        assert_eq!(hdr.frame_type, KEY_FRAME);
+        assert!(hdr.show_frame && !hdr.showable_frame && hdr.error_resilient_mode);
+        assert!(hdr.is_intra() && hdr.force_integer_mv && !hdr.allow_screen_content_tools);
+        assert_eq!(hdr.order_hint, 0);
+        assert_eq!(hdr.primary_ref_frame, PRIMARY_REF_NONE);
+        assert_eq!(hdr.refresh_frame_flags, 0xff);
+        assert_eq!((hdr.frame_width, hdr.frame_height), (128, 64));
+        assert_eq!((hdr.upscaled_width, hdr.superres_denom), (128, SUPERRES_NUM));
+        assert_eq!((hdr.render_width, hdr.render_height), (128, 64));
+        assert_eq!((hdr.get_mi_cols(), hdr.get_mi_rows()), (32, 16));
+
+        let info = &hdr.tile_info;
+        assert!(info.uniform_spacing);
+        assert_eq!((info.cols_log2, info.rows_log2), (1, 0));
+        assert_eq!(info.width_in_sbs, vec![1, 1]);
+        assert_eq!(info.height_in_sbs, vec![1]);
+        assert_eq!(info.num_tiles(), 2);
+        assert_eq!((info.context_update_tile_id, info.tile_size_bytes), (1, 2));
+
+        assert_eq!(hdr.quant.base_q_idx, 6);
+        assert!(!hdr.quant.using_qmatrix && !hdr.delta_q_present);
+        assert!(!hdr.segmentation.enabled && !hdr.coded_lossless);
+        assert_eq!(hdr.loop_filter.level, [4, 4, 0, 0]);
+        assert!(hdr.loop_filter.delta_enabled && !hdr.loop_filter.delta_update);
+        assert_eq!(hdr.loop_filter.ref_deltas, DEFAULT_REF_DELTAS);
+        assert_eq!((hdr.cdef.bits, hdr.cdef.y_pri_strength[0]), (0, 11));
+        assert_eq!(hdr.loop_restoration.frame_restoration_type, [RESTORE_NONE; 3]);
+        assert_eq!(hdr.tx_mode, TX_MODE_SELECT);
+        assert!(!hdr.reference_select && !hdr.skip_mode_present);
+        assert!(hdr.global_motion.iter().all(|gm| gm.wmtype == IDENTITY));
+        assert_eq!(hdr.header_bytes, 11);
+
+        /* The first tile size is coded on tile_size_bytes */
+        let tg = tg.as_ref().unwrap();
+        assert_eq!((tg.tg_start, tg.tg_end, tg.data_offset), (0, 1, 1));
+        assert_eq!(tg.tiles,
+                   vec![Tile {
+                            row: 0,
+                            col: 0,
+                            offset: 2,
+                            size: 98,
+                        },
+                        Tile {
+                            row: 0,
+                            col: 1,
+                            offset: 100,
+                            size: 916,
+                        }]);
+    }
+
+    #[test]
+    fn inter_frames() {
+        let (parser, frames) = parse_stream(TILES_128X64);
+        assert_eq!(frames.len(), 7);
+
+        /* Hidden alternate reference, then a hidden frame predicted from it */
+        let (ref hdr, _) = frames[1];
+        assert_eq!(hdr.frame_type, INTER_FRAME);
+        assert!(!hdr.show_frame && hdr.showable_frame && !hdr.error_resilient_mode);
+        assert_eq!((hdr.order_hint, hdr.refresh_frame_flags), (4, 0x02));
+        assert_eq!(hdr.ref_frame_idx, [0; REFS_PER_FRAME]);
+        assert!(hdr.is_motion_mode_switchable && hdr.use_ref_frame_mvs);
+        assert!(hdr.allow_warped_motion && !hdr.reference_select);
+        assert_eq!(hdr.interp_filter, INTERP_FILTER_EIGHTTAP);
+
+        let (ref hdr, ref tg) = frames[2];
+        assert_eq!((hdr.order_hint, hdr.refresh_frame_flags), (2, 0x04));
+        assert_eq!(hdr.ref_frame_idx, [0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(hdr.order_hints[ALTREF_FRAME], 4);
+        assert!(hdr.reference_select && hdr.skip_mode_present);
+        assert_eq!(hdr.tx_mode, TX_MODE_LARGEST);
+        assert_eq!(hdr.tile_info.tile_size_bytes, 1);
+        let tg = tg.as_ref().unwrap();
+        assert_eq!((tg.tiles[0].offset, tg.tiles[0].size), (1, 202));
+        assert_eq!((tg.tiles[1].offset, tg.tiles[1].size), (203, 253));
+
+        let (ref hdr, _) = frames[3];
+        assert!(hdr.show_frame);
+        assert_eq!((hdr.order_hint, hdr.refresh_frame_flags), (1, 0x08));
+        assert_eq!(hdr.ref_frame_idx, [0, 0, 0, 0, 2, 0, 1]);
+
+        /* The hidden frame of order hint 2 is shown */
+        let (ref hdr, ref tg) = frames[4];
+        assert!(hdr.show_existing_frame && hdr.show_frame && tg.is_none());
+        assert_eq!(hdr.frame_to_show_map_idx, 2);
+        assert_eq!((hdr.frame_type, hdr.order_hint), (INTER_FRAME, 2));
+        assert_eq!((hdr.upscaled_width, hdr.frame_height), (128, 64));
+        assert_eq!(hdr.refresh_frame_flags, 0);
+        assert!(parse_tile_group(hdr, &[0x00, 0x00]).is_err());
+
+        let (ref hdr, _) = frames[5];
+        assert_eq!(hdr.primary_ref_frame, 1);
+        assert_eq!(hdr.order_hints, [0, 2, 1, 0, 0, 0, 0, 4]);
+
+        let hints: Vec<u8> = (0..NUM_REF_FRAMES)
+            .map(|i| parser.get_ref_frame(i).unwrap().order_hint)
+            .collect();
+        assert_eq!(hints, vec![0, 4, 2, 1, 3, 0, 0, 0]);
+        let info = parser.get_ref_frame(1).unwrap();
+        assert_eq!((info.frame_type, info.showable_frame), (INTER_FRAME, true));
+        assert_eq!((info.upscaled_width, info.frame_height), (128, 64));
+    }
+
+    #[test]
+    fn film_grain() {
+        let (parser, frames) = parse_stream(FILM_GRAIN_64X64);
+        assert!(parser.get_sequence_header().unwrap().film_grain_params_present);
+
+        let (ref hdr, _) = frames[0];
+        let fg = &hdr.film_grain;
+        assert!(fg.apply_grain && fg.update_grain);
+        assert_eq!(fg.grain_seed, 51993);
+        assert_eq!(fg.num_y_points, 14);
+        assert_eq!(fg.point_y_value[..3], [16, 25, 33]);
+        assert_eq!(fg.point_y_scaling[13], 184);
+        assert!(!fg.chroma_scaling_from_luma);
+        assert_eq!((fg.num_cb_points, fg.num_cr_points), (8, 9));
+        assert_eq!(fg.point_cr_value[8], 169);
+        assert_eq!((fg.grain_scaling_minus_8, fg.ar_coeff_lag), (3, 2));
+        assert_eq!(fg.ar_coeffs_y_plus_128[..7], [128, 128, 70, 128, 128, 128, 52]);
+        assert_eq!(fg.ar_coeffs_cb_plus_128[12], 167);
+        assert_eq!(fg.ar_coeffs_cr_plus_128[12], 28);
+        assert_eq!((fg.ar_coeff_shift_minus_6, fg.grain_scale_shift), (2, 0));
+        assert_eq!((fg.cb_mult, fg.cb_luma_mult, fg.cb_offset), (247, 192, 18));
+        assert_eq!((fg.cr_mult, fg.cr_luma_mult, fg.cr_offset), (229, 192, 54));
+        assert!(!fg.overlap_flag && fg.clip_to_restricted_range);
+        assert_eq!(hdr.header_bytes, 120);
+
+        let (ref hdr, _) = frames[1];
+        assert_eq!(hdr.film_grain.grain_seed, 58755);
+        assert_eq!(hdr.film_grain.num_y_points, 14);
+
+        let refs = [0; NUM_REF_FRAMES];
+        let seq = parser.get_sequence_header().unwrap();
+        let param = build_picture_param(seq, hdr, 1, 2, &refs).unwrap();
+        assert_eq!((param.current_frame, param.current_display_picture), (1, 2));
+        let fg_param = &param.film_grain_info;
+        let fields = AV1FilmGrainInfoFields::from_va(&fg_param.film_grain_info_fields);
+        assert_eq!(fields.apply_grain, 1);
+        assert_eq!(fields.grain_scaling_minus_8, 3);
+        assert_eq!(fields.ar_coeff_lag, 2);
+        assert_eq!(fields.clip_to_restricted_range, 1);
+        assert_eq!(fg_param.grain_seed, 58755);
+        assert_eq!(fg_param.ar_coeffs_y[..7], [0, 0, -58, 0, 0, 0, -76]);
+        assert_eq!(fg_param.ar_coeffs_cr[12], -100);
+        assert_eq!(fg_param.point_cb_scaling[7], 208);
+        assert_eq!(fg_param.cr_offset, 54);
+        let fields = AV1SeqInfoFields::from_va(&param.seq_info_fields);
+        assert_eq!(fields.film_grain_params_present, 1);
+    }
+
+    #[test]
+    fn invalid_frames() {
+        let (_, chunks) = parse_ivf(TILES_128X64).unwrap();
+        let key = split_obus(chunks[0].data).unwrap();
+        let hidden = split_obus(chunks[1].data).unwrap();
+        let mut parser = Parser::new();
+
+        /* The sequence header and the references are needed */
+        assert!(parser.parse_frame_header(key[2].data, &key[2].header).is_err());
+        assert!(parser.parse_sequence_header(&key[1].data[..4]).is_err());
+        parser.parse_sequence_header(key[1].data).unwrap();
+        assert!(parser.parse_frame_header(hidden[1].data, &hidden[1].header).is_err());
+        assert!(parser.parse_frame_header(&key[2].data[..3], &key[2].header).is_err());
+        assert!(parser.get_ref_frame(0).is_none());
+
+        let hdr = parser.parse_frame_header(key[2].data, &key[2].header).unwrap();
+        assert!(parser.get_ref_frame(0).is_some());
+        let tiles = &key[2].data[hdr.header_bytes..];
+        assert!(parse_tile_group(&hdr, &tiles[..50]).is_err());
+        assert!(parse_tile_group(&hdr, &tiles[..2]).is_err());
+        /* A tile group ending before it starts */
+        assert!(parse_tile_group(&hdr, &[0xa0, 0x00, 0x00]).is_err());
+        assert!(parser.parse_frame_header(hidden[1].data, &hidden[1].header).is_ok());
+
+        /* Only showable frames can be shown again */
+        assert!(parser.parse_frame_header(&[0x80], &ObuHeader::default()).is_err());
+    }
+
+    #[test]
+    fn va_buffers() {
+        let (parser, frames) = parse_stream(TILES_128X64);
+        let seq = parser.get_sequence_header().unwrap();
+        let refs = [10, 11, 12, 13, 14, 15, 16, 17];
+
+        let (ref hdr, ref tg) = frames[0];
+        let param = build_picture_param(seq, hdr, 20, 20, &refs).unwrap();
+        assert_eq!((param.profile, param.bit_depth_idx), (0, 0));
+        assert_eq!(param.order_hint_bits_minus_1, 6);
+        assert_eq!((param.frame_width_minus1, param.frame_height_minus1), (127, 63));
+        assert_eq!(param.ref_frame_map, refs);
+        assert_eq!(param.primary_ref_frame, PRIMARY_REF_NONE);
+        let fields = AV1SeqInfoFields::from_va(&param.seq_info_fields);
+        assert_eq!(fields.enable_order_hint, 1);
+        assert_eq!(fields.enable_cdef, 1);
+        assert_eq!((fields.subsampling_x, fields.subsampling_y), (1, 1));
+        let fields = AV1PicInfoFields::from_va(&param.pic_info_fields);
+        assert_eq!((fields.frame_type, fields.show_frame), (0, 1));
+        assert_eq!(fields.error_resilient_mode, 1);
+        assert_eq!(fields.force_integer_mv, 1);
+        assert_eq!(fields.uniform_tile_spacing_flag, 1);
+        assert_eq!((param.tile_cols, param.tile_rows), (2, 1));
+        assert_eq!(param.width_in_sbs_minus_1[..2], [0, 0]);
+        assert_eq!((param.tile_count_minus_1, param.context_update_tile_id), (1, 1));
+        assert_eq!(param.superres_scale_denominator, 8);
+        assert_eq!(param.filter_level, [4, 4]);
+        assert_eq!(param.base_qindex, 6);
+        assert_eq!(param.ref_deltas, [1, 0, 0, 0, -1, 0, -1, -1]);
+        let fields = AV1LoopFilterInfoFields::from_va(&param.loop_filter_info_fields);
+        assert_eq!(fields.mode_ref_delta_enabled, 1);
+        let fields = AV1ModeControlFields::from_va(&param.mode_control_fields);
+        assert_eq!(fields.tx_mode, TX_MODE_SELECT as u32);
+        assert_eq!(fields.reference_select, 0);
+        assert_eq!(param.cdef_y_strengths[0], 11 << 2);
+        assert_eq!(param.wm[0].wmtype, ffi::VAAV1TransformationIdentity);
+        assert_eq!(param.wm[0].wmmat[..6], DEFAULT_GM_PARAMS);
+        assert_eq!(param.film_grain_info.grain_seed, 0);
+
+        let tiles = build_tile_params(tg.as_ref().unwrap());
+        assert_eq!(tiles.len(), 2);
+        assert_eq!((tiles[1].slice_data_offset, tiles[1].slice_data_size), (100, 916));
+        assert_eq!((tiles[1].tile_row, tiles[1].tile_column), (0, 1));
+        assert_eq!((tiles[1].tg_start, tiles[1].tg_end), (0, 1));
+        assert_eq!(tiles[0].slice_data_flag, ffi::VA_SLICE_DATA_FLAG_ALL);
+
+        let (ref hdr, _) = frames[2];
+        let param = build_picture_param(seq, hdr, 20, 20, &refs).unwrap();
+        assert_eq!(param.ref_frame_idx, [0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(param.order_hint, 2);
+        let fields = AV1PicInfoFields::from_va(&param.pic_info_fields);
+        assert_eq!((fields.frame_type, fields.show_frame, fields.showable_frame), (1, 0, 1));
+        assert_eq!(fields.allow_warped_motion, 1);
+        assert_eq!(fields.use_ref_frame_mvs, 1);
+        let fields = AV1ModeControlFields::from_va(&param.mode_control_fields);
+        assert_eq!((fields.reference_select, fields.skip_mode_present), (1, 1));
+        assert_eq!(fields.tx_mode, TX_MODE_LARGEST as u32);
+    }
+}
@@ -0,0 +1,882 @@
+// Copyright (c) 2017 zzoon <zzoon@igalia.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/* Named fields for the flags the ffi packs in bitfield unions. to_va() checks every value
+ * fits in its bits, from_va() reads them back */
+
+use std::mem;
+
+use ffi;
+
+/* C structures made of integers, arrays, raw pointers, bitfield unions and enums with a
+ * zero member, for which all bits zero is a valid value. It is only implemented below,
+ * which is what makes zeroed() safe */
+pub(crate) trait PlainStruct {}
+
+/* Parameters start zeroed, VA reads zero from every member not set */
+pub(crate) fn zeroed<T: PlainStruct>() -> T {
+    unsafe { mem::zeroed() }
+}
+
+macro_rules! plain_structs {
+    ($check:ident: $($name:ident,)+) => {
+        $(impl PlainStruct for ffi::$name {})+
+
+        /* mem::zeroed() panics on the types all bits zero is invalid for */
+        #[cfg(test)]
+        fn $check() {
+            $(let _: ffi::$name = zeroed();)+
+        }
+    }
+}
+
+plain_structs! {
+    zero_structs:
+    VABoolCoderContextVPX,
+    VAConfigAttrib,
+    VADecPictureParameterBufferVP9,
+    VAEncMiscParameterBuffer,
+    VAGenericValue_value,
+    VAHuffmanTableBufferJPEGBaseline,
+    VAIQMatrixBufferH264,
+    VAIQMatrixBufferHEVC,
+    VAIQMatrixBufferJPEGBaseline,
+    VAIQMatrixBufferMPEG2,
+    VAIQMatrixBufferMPEG4,
+    VAIQMatrixBufferVP8,
+    VAPictureH264,
+    VAPictureHEVC,
+    VAPictureParameterBufferH264,
+    VAPictureParameterBufferHEVC,
+    VAPictureParameterBufferJPEGBaseline,
+    VAPictureParameterBufferMPEG2,
+    VAPictureParameterBufferMPEG4,
+    VAPictureParameterBufferVC1,
+    VAPictureParameterBufferVP8,
+    VAProbabilityDataBufferVP8,
+    VAProcPipelineParameterBuffer,
+    VARectangle,
+    VASliceParameterBufferH264,
+    VASliceParameterBufferHEVC,
+    VASliceParameterBufferJPEGBaseline,
+    VASliceParameterBufferMPEG2,
+    VASliceParameterBufferMPEG4,
+    VASliceParameterBufferVC1,
+    VASliceParameterBufferVP8,
+    VASliceParameterBufferVP9,
+}
+
+#[cfg(va_av1_decode)]
+plain_structs! {
+    zero_av1_structs:
+    VADecPictureParameterBufferAV1,
+    VASliceParameterBufferAV1,
+}
+
+/* Field whose value does not fit in its bits */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitfieldOverflow(pub &'static str);
+
+/* Parameter builders report the values out of range as invalid streams */
+impl From<BitfieldOverflow> for () {
+    fn from(_: BitfieldOverflow) {}
+}
+
+/* The bits are a member of the union, or sit at its start when bindgen did not make the
+ * members they are nested in reachable the same way with every header */
+macro_rules! bitfields {
+    ($($name:ident: $union:ident.$member:ident as $ty:ty {
+        $($field:ident / $setter:ident,)+
+    })+) => {
+        $(bitfields! { @struct $name: $union (member $member) as $ty { $($field / $setter,)+ } })+
+    };
+    ($($name:ident: $union:ident[$bits:ident] as $ty:ty {
+        $($field:ident / $setter:ident,)+
+    })+) => {
+        $(bitfields! { @struct $name: $union (start $bits) as $ty { $($field / $setter,)+ } })+
+    };
+    (@bits_mut $fields:ident (member $member:ident)) => {
+        unsafe { $fields.$member.as_mut() }
+    };
+    (@bits_mut $fields:ident (start $bits:ident)) => {
+        unsafe { &mut *(&mut $fields as *mut _ as *mut ffi::$bits) }
+    };
+    (@bits $fields:ident (member $member:ident)) => {
+        unsafe { $fields.$member.as_ref() }
+    };
+    (@bits $fields:ident (start $bits:ident)) => {
+        unsafe { &*($fields as *const _ as *const ffi::$bits) }
+    };
+    (@struct $name:ident: $union:ident $access:tt as $ty:ty {
+        $($field:ident / $setter:ident,)+
+    }) => {
+        #[allow(non_snake_case)]
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+        pub struct $name {
+            $(pub $field: $ty,)+
+        }
+
+        impl PlainStruct for ffi::$union {}
+
+        impl $name {
+            pub fn to_va(&self) -> Result<ffi::$union, BitfieldOverflow> {
+                let mut fields: ffi::$union = zeroed();
+                {
+                    let bits = bitfields!(@bits_mut fields $access);
+                    $(
+                        bits.$setter(self.$field);
+                        if bits.$field() != self.$field {
+                            return Err(BitfieldOverflow(stringify!($field)));
+                        }
+                    )+
+                }
+                Ok(fields)
+            }
+
+            pub fn from_va(fields: &ffi::$union) -> $name {
+                let bits = bitfields!(@bits fields $access);
+                $name { $($field: bits.$field(),)+ }
+            }
+        }
+
+        /* Every field round trips alone and at its largest value, and is rejected one past
+         * it unless it fills its type */
+        #[cfg(test)]
+        impl $name {
+            fn check_ranges() {
+                let mut raw: ffi::$union = zeroed();
+                unsafe {
+                    ::std::ptr::write_bytes(&mut raw, 0xff, 1);
+                }
+                let max = $name::from_va(&raw);
+                assert_eq!(max.to_va().map(|fields| $name::from_va(&fields)), Ok(max));
+                $(
+                    assert!(max.$field != 0, stringify!($field));
+                    let one = $name { $field: 1, ..Default::default() };
+                    assert_eq!(one.to_va().map(|fields| $name::from_va(&fields)), Ok(one));
+                    if max.$field < <$ty>::MAX {
+                        let over = $name { $field: max.$field + 1, ..max };
+                        assert_eq!(over.to_va().err(), Some(BitfieldOverflow(stringify!($field))));
+                    }
+                )+
+            }
+        }
+    };
+}
+
+bitfields! {
+    ConfigAttribValEncJPEG: _VAConfigAttribValEncJPEG.bits as u32 {
+        arithmatic_coding_mode / set_arithmatic_coding_mode,
+        progressive_dct_mode / set_progressive_dct_mode,
+        non_interleaved_mode / set_non_interleaved_mode,
+        differential_mode / set_differential_mode,
+        max_num_components / set_max_num_components,
+        max_num_scans / set_max_num_scans,
+        max_num_huffman_tables / set_max_num_huffman_tables,
+        max_num_quantization_tables / set_max_num_quantization_tables,
+    }
+    ConfigAttribValEncROI: _VAConfigAttribValEncROI.bits as u32 {
+        num_roi_regions / set_num_roi_regions,
+        roi_rc_priority_support / set_roi_rc_priority_support,
+    }
+    RateControlFlags: VAEncMiscParameterRateControl_rc_flags.bits as u32 {
+        reset / set_reset,
+        disable_frame_skip / set_disable_frame_skip,
+        disable_bit_stuffing / set_disable_bit_stuffing,
+        mb_rate_control / set_mb_rate_control,
+    }
+    MPEG2PictureCodingExtension:
+        VAPictureParameterBufferMPEG2_picture_coding_extension.bits as u32 {
+        intra_dc_precision / set_intra_dc_precision,
+        picture_structure / set_picture_structure,
+        top_field_first / set_top_field_first,
+        frame_pred_frame_dct / set_frame_pred_frame_dct,
+        concealment_motion_vectors / set_concealment_motion_vectors,
+        q_scale_type / set_q_scale_type,
+        intra_vlc_format / set_intra_vlc_format,
+        alternate_scan / set_alternate_scan,
+        repeat_first_field / set_repeat_first_field,
+        progressive_frame / set_progressive_frame,
+        is_first_field / set_is_first_field,
+    }
+    MPEG2MacroblockModes: VAMacroblockParameterBufferMPEG2_macroblock_modes.bits as u32 {
+        frame_motion_type / set_frame_motion_type,
+        field_motion_type / set_field_motion_type,
+        dct_type / set_dct_type,
+    }
+    MPEG4VolFields: VAPictureParameterBufferMPEG4_vol_fields.bits as u32 {
+        short_video_header / set_short_video_header,
+        chroma_format / set_chroma_format,
+        interlaced / set_interlaced,
+        obmc_disable / set_obmc_disable,
+        sprite_enable / set_sprite_enable,
+        sprite_warping_accuracy / set_sprite_warping_accuracy,
+        quant_type / set_quant_type,
+        quarter_sample / set_quarter_sample,
+        data_partitioned / set_data_partitioned,
+        reversible_vlc / set_reversible_vlc,
+        resync_marker_disable / set_resync_marker_disable,
+    }
+    MPEG4VopFields: VAPictureParameterBufferMPEG4_vop_fields.bits as u32 {
+        vop_coding_type / set_vop_coding_type,
+        backward_reference_vop_coding_type / set_backward_reference_vop_coding_type,
+        vop_rounding_type / set_vop_rounding_type,
+        intra_dc_vlc_thr / set_intra_dc_vlc_thr,
+        top_field_first / set_top_field_first,
+        alternate_vertical_scan_flag / set_alternate_vertical_scan_flag,
+    }
+    VC1SequenceFields: VAPictureParameterBufferVC1_sequence_fields.bits as u32 {
+        pulldown / set_pulldown,
+        interlace / set_interlace,
+        tfcntrflag / set_tfcntrflag,
+        finterpflag / set_finterpflag,
+        psf / set_psf,
+        multires / set_multires,
+        overlap / set_overlap,
+        syncmarker / set_syncmarker,
+        rangered / set_rangered,
+        max_b_frames / set_max_b_frames,
+        profile / set_profile,
+    }
+    VC1EntrypointFields: VAPictureParameterBufferVC1_entrypoint_fields.bits as u32 {
+        broken_link / set_broken_link,
+        closed_entry / set_closed_entry,
+        panscan_flag / set_panscan_flag,
+        loopfilter / set_loopfilter,
+    }
+    VC1RangeMappingFields: VAPictureParameterBufferVC1_range_mapping_fields.bits as u32 {
+        luma_flag / set_luma_flag,
+        luma / set_luma,
+        chroma_flag / set_chroma_flag,
+        chroma / set_chroma,
+    }
+    VC1PictureFields: VAPictureParameterBufferVC1_picture_fields.bits as u32 {
+        picture_type / set_picture_type,
+        frame_coding_mode / set_frame_coding_mode,
+        top_field_first / set_top_field_first,
+        is_first_field / set_is_first_field,
+        intensity_compensation / set_intensity_compensation,
+    }
+    VC1RawCoding: VAPictureParameterBufferVC1_raw_coding.flags as u32 {
+        mv_type_mb / set_mv_type_mb,
+        direct_mb / set_direct_mb,
+        skip_mb / set_skip_mb,
+        field_tx / set_field_tx,
+        forward_mb / set_forward_mb,
+        ac_pred / set_ac_pred,
+        overflags / set_overflags,
+    }
+    VC1BitplanePresent: VAPictureParameterBufferVC1_bitplane_present.flags as u32 {
+        bp_mv_type_mb / set_bp_mv_type_mb,
+        bp_direct_mb / set_bp_direct_mb,
+        bp_skip_mb / set_bp_skip_mb,
+        bp_field_tx / set_bp_field_tx,
+        bp_forward_mb / set_bp_forward_mb,
+        bp_ac_pred / set_bp_ac_pred,
+        bp_overflags / set_bp_overflags,
+    }
+    VC1ReferenceFields: VAPictureParameterBufferVC1_reference_fields.bits as u32 {
+        reference_distance_flag / set_reference_distance_flag,
+        reference_distance / set_reference_distance,
+        num_reference_pictures / set_num_reference_pictures,
+        reference_field_pic_indicator / set_reference_field_pic_indicator,
+    }
+    VC1MvFields: VAPictureParameterBufferVC1_mv_fields.bits as u32 {
+        mv_mode / set_mv_mode,
+        mv_mode2 / set_mv_mode2,
+        mv_table / set_mv_table,
+        two_mv_block_pattern_table / set_two_mv_block_pattern_table,
+        four_mv_switch / set_four_mv_switch,
+        four_mv_block_pattern_table / set_four_mv_block_pattern_table,
+        extended_mv_flag / set_extended_mv_flag,
+        extended_mv_range / set_extended_mv_range,
+        extended_dmv_flag / set_extended_dmv_flag,
+        extended_dmv_range / set_extended_dmv_range,
+    }
+    VC1PicQuantizerFields: VAPictureParameterBufferVC1_pic_quantizer_fields.bits as u32 {
+        dquant / set_dquant,
+        quantizer / set_quantizer,
+        half_qp / set_half_qp,
+        pic_quantizer_scale / set_pic_quantizer_scale,
+        pic_quantizer_type / set_pic_quantizer_type,
+        dq_frame / set_dq_frame,
+        dq_profile / set_dq_profile,
+        dq_sb_edge / set_dq_sb_edge,
+        dq_db_edge / set_dq_db_edge,
+        dq_binary_level / set_dq_binary_level,
+        alt_pic_quantizer / set_alt_pic_quantizer,
+    }
+    VC1TransformFields: VAPictureParameterBufferVC1_transform_fields.bits as u32 {
+        variable_sized_transform_flag / set_variable_sized_transform_flag,
+        mb_level_transform_type_flag / set_mb_level_transform_type_flag,
+        frame_level_transform_type / set_frame_level_transform_type,
+        transform_ac_codingset_idx1 / set_transform_ac_codingset_idx1,
+        transform_ac_codingset_idx2 / set_transform_ac_codingset_idx2,
+        intra_transform_dc_table / set_intra_transform_dc_table,
+    }
+    H264SeqFields: VAPictureParameterBufferH264_seq_fields.bits as u32 {
+        chroma_format_idc / set_chroma_format_idc,
+        residual_colour_transform_flag / set_residual_colour_transform_flag,
+        gaps_in_frame_num_value_allowed_flag / set_gaps_in_frame_num_value_allowed_flag,
+        frame_mbs_only_flag / set_frame_mbs_only_flag,
+        mb_adaptive_frame_field_flag / set_mb_adaptive_frame_field_flag,
+        direct_8x8_inference_flag / set_direct_8x8_inference_flag,
+        MinLumaBiPredSize8x8 / set_MinLumaBiPredSize8x8,
+        log2_max_frame_num_minus4 / set_log2_max_frame_num_minus4,
+        pic_order_cnt_type / set_pic_order_cnt_type,
+        log2_max_pic_order_cnt_lsb_minus4 / set_log2_max_pic_order_cnt_lsb_minus4,
+        delta_pic_order_always_zero_flag / set_delta_pic_order_always_zero_flag,
+    }
+    H264PicFields: VAPictureParameterBufferH264_pic_fields.bits as u32 {
+        entropy_coding_mode_flag / set_entropy_coding_mode_flag,
+        weighted_pred_flag / set_weighted_pred_flag,
+        weighted_bipred_idc / set_weighted_bipred_idc,
+        transform_8x8_mode_flag / set_transform_8x8_mode_flag,
+        field_pic_flag / set_field_pic_flag,
+        constrained_intra_pred_flag / set_constrained_intra_pred_flag,
+        pic_order_present_flag / set_pic_order_present_flag,
+        deblocking_filter_control_present_flag / set_deblocking_filter_control_present_flag,
+        redundant_pic_cnt_present_flag / set_redundant_pic_cnt_present_flag,
+        reference_pic_flag / set_reference_pic_flag,
+    }
+    EncSliceFlags: VAEncSliceParameterBuffer_slice_flags.bits as u32 {
+        is_intra / set_is_intra,
+        disable_deblocking_filter_idc / set_disable_deblocking_filter_idc,
+        uses_long_term_ref / set_uses_long_term_ref,
+        is_long_term_ref / set_is_long_term_ref,
+    }
+    HEVCPicFields: VAPictureParameterBufferHEVC_pic_fields.bits as u32 {
+        chroma_format_idc / set_chroma_format_idc,
+        separate_colour_plane_flag / set_separate_colour_plane_flag,
+        pcm_enabled_flag / set_pcm_enabled_flag,
+        scaling_list_enabled_flag / set_scaling_list_enabled_flag,
+        transform_skip_enabled_flag / set_transform_skip_enabled_flag,
+        amp_enabled_flag / set_amp_enabled_flag,
+        strong_intra_smoothing_enabled_flag / set_strong_intra_smoothing_enabled_flag,
+        sign_data_hiding_enabled_flag / set_sign_data_hiding_enabled_flag,
+        constrained_intra_pred_flag / set_constrained_intra_pred_flag,
+        cu_qp_delta_enabled_flag / set_cu_qp_delta_enabled_flag,
+        weighted_pred_flag / set_weighted_pred_flag,
+        weighted_bipred_flag / set_weighted_bipred_flag,
+        transquant_bypass_enabled_flag / set_transquant_bypass_enabled_flag,
+        tiles_enabled_flag / set_tiles_enabled_flag,
+        entropy_coding_sync_enabled_flag / set_entropy_coding_sync_enabled_flag,
+        pps_loop_filter_across_slices_enabled_flag /
+            set_pps_loop_filter_across_slices_enabled_flag,
+        loop_filter_across_tiles_enabled_flag / set_loop_filter_across_tiles_enabled_flag,
+        pcm_loop_filter_disabled_flag / set_pcm_loop_filter_disabled_flag,
+        NoPicReorderingFlag / set_NoPicReorderingFlag,
+        NoBiPredFlag / set_NoBiPredFlag,
+        ReservedBits / set_ReservedBits,
+    }
+    HEVCSliceParsingFields: VAPictureParameterBufferHEVC_slice_parsing_fields.bits as u32 {
+        lists_modification_present_flag / set_lists_modification_present_flag,
+        long_term_ref_pics_present_flag / set_long_term_ref_pics_present_flag,
+        sps_temporal_mvp_enabled_flag / set_sps_temporal_mvp_enabled_flag,
+        cabac_init_present_flag / set_cabac_init_present_flag,
+        output_flag_present_flag / set_output_flag_present_flag,
+        dependent_slice_segments_enabled_flag / set_dependent_slice_segments_enabled_flag,
+        pps_slice_chroma_qp_offsets_present_flag / set_pps_slice_chroma_qp_offsets_present_flag,
+        sample_adaptive_offset_enabled_flag / set_sample_adaptive_offset_enabled_flag,
+        deblocking_filter_override_enabled_flag / set_deblocking_filter_override_enabled_flag,
+        pps_disable_deblocking_filter_flag / set_pps_disable_deblocking_filter_flag,
+        slice_segment_header_extension_present_flag /
+            set_slice_segment_header_extension_present_flag,
+        RapPicFlag / set_RapPicFlag,
+        IdrPicFlag / set_IdrPicFlag,
+        IntraPicFlag / set_IntraPicFlag,
+        ReservedBits / set_ReservedBits,
+    }
+    HEVCLongSliceFlags: VASliceParameterBufferHEVC_LongSliceFlags.fields as u32 {
+        LastSliceOfPic / set_LastSliceOfPic,
+        dependent_slice_segment_flag / set_dependent_slice_segment_flag,
+        slice_type / set_slice_type,
+        color_plane_id / set_color_plane_id,
+        slice_sao_luma_flag / set_slice_sao_luma_flag,
+        slice_sao_chroma_flag / set_slice_sao_chroma_flag,
+        mvd_l1_zero_flag / set_mvd_l1_zero_flag,
+        cabac_init_flag / set_cabac_init_flag,
+        slice_temporal_mvp_enabled_flag / set_slice_temporal_mvp_enabled_flag,
+        slice_deblocking_filter_disabled_flag / set_slice_deblocking_filter_disabled_flag,
+        collocated_from_l0_flag / set_collocated_from_l0_flag,
+        slice_loop_filter_across_slices_enabled_flag /
+            set_slice_loop_filter_across_slices_enabled_flag,
+    }
+    VP8PicFields: VAPictureParameterBufferVP8_pic_fields.bits as u32 {
+        key_frame / set_key_frame,
+        version / set_version,
+        segmentation_enabled / set_segmentation_enabled,
+        update_mb_segmentation_map / set_update_mb_segmentation_map,
+        update_segment_feature_data / set_update_segment_feature_data,
+        filter_type / set_filter_type,
+        sharpness_level / set_sharpness_level,
+        loop_filter_adj_enable / set_loop_filter_adj_enable,
+        mode_ref_lf_delta_update / set_mode_ref_lf_delta_update,
+        sign_bias_golden / set_sign_bias_golden,
+        sign_bias_alternate / set_sign_bias_alternate,
+        mb_no_coeff_skip / set_mb_no_coeff_skip,
+        loop_filter_disable / set_loop_filter_disable,
+    }
+    VP9PicFields: VADecPictureParameterBufferVP9_pic_fields.bits as u32 {
+        subsampling_x / set_subsampling_x,
+        subsampling_y / set_subsampling_y,
+        frame_type / set_frame_type,
+        show_frame / set_show_frame,
+        error_resilient_mode / set_error_resilient_mode,
+        intra_only / set_intra_only,
+        allow_high_precision_mv / set_allow_high_precision_mv,
+        mcomp_filter_type / set_mcomp_filter_type,
+        frame_parallel_decoding_mode / set_frame_parallel_decoding_mode,
+        reset_frame_context / set_reset_frame_context,
+        refresh_frame_context / set_refresh_frame_context,
+        frame_context_idx / set_frame_context_idx,
+        segmentation_enabled / set_segmentation_enabled,
+        segmentation_temporal_update / set_segmentation_temporal_update,
+        segmentation_update_map / set_segmentation_update_map,
+        last_ref_frame / set_last_ref_frame,
+        last_ref_frame_sign_bias / set_last_ref_frame_sign_bias,
+        golden_ref_frame / set_golden_ref_frame,
+        golden_ref_frame_sign_bias / set_golden_ref_frame_sign_bias,
+        alt_ref_frame / set_alt_ref_frame,
+        alt_ref_frame_sign_bias / set_alt_ref_frame_sign_bias,
+        lossless_flag / set_lossless_flag,
+    }
+    VP9SegmentFlags: VASegmentParameterVP9_segment_flags.fields as u16 {
+        segment_reference_enabled / set_segment_reference_enabled,
+        segment_reference / set_segment_reference,
+        segment_reference_skipped / set_segment_reference_skipped,
+    }
+    EncHEVCSeqFields: VAEncSequenceParameterBufferHEVC_seq_fields.bits as u32 {
+        chroma_format_idc / set_chroma_format_idc,
+        separate_colour_plane_flag / set_separate_colour_plane_flag,
+        bit_depth_luma_minus8 / set_bit_depth_luma_minus8,
+        bit_depth_chroma_minus8 / set_bit_depth_chroma_minus8,
+        scaling_list_enabled_flag / set_scaling_list_enabled_flag,
+        strong_intra_smoothing_enabled_flag / set_strong_intra_smoothing_enabled_flag,
+        amp_enabled_flag / set_amp_enabled_flag,
+        sample_adaptive_offset_enabled_flag / set_sample_adaptive_offset_enabled_flag,
+        pcm_enabled_flag / set_pcm_enabled_flag,
+        pcm_loop_filter_disabled_flag / set_pcm_loop_filter_disabled_flag,
+        sps_temporal_mvp_enabled_flag / set_sps_temporal_mvp_enabled_flag,
+    }
+    EncHEVCVuiFields: VAEncSequenceParameterBufferHEVC_vui_fields.bits as u32 {
+        aspect_ratio_info_present_flag / set_aspect_ratio_info_present_flag,
+        neutral_chroma_indication_flag / set_neutral_chroma_indication_flag,
+        field_seq_flag / set_field_seq_flag,
+        vui_timing_info_present_flag / set_vui_timing_info_present_flag,
+        bitstream_restriction_flag / set_bitstream_restriction_flag,
+        tiles_fixed_structure_flag / set_tiles_fixed_structure_flag,
+        motion_vectors_over_pic_boundaries_flag / set_motion_vectors_over_pic_boundaries_flag,
+        restricted_ref_pic_lists_flag / set_restricted_ref_pic_lists_flag,
+        log2_max_mv_length_horizontal / set_log2_max_mv_length_horizontal,
+        log2_max_mv_length_vertical / set_log2_max_mv_length_vertical,
+    }
+    EncHEVCPicFields: VAEncPictureParameterBufferHEVC_pic_fields.bits as u32 {
+        idr_pic_flag / set_idr_pic_flag,
+        coding_type / set_coding_type,
+        reference_pic_flag / set_reference_pic_flag,
+        dependent_slice_segments_enabled_flag / set_dependent_slice_segments_enabled_flag,
+        sign_data_hiding_enabled_flag / set_sign_data_hiding_enabled_flag,
+        constrained_intra_pred_flag / set_constrained_intra_pred_flag,
+        transform_skip_enabled_flag / set_transform_skip_enabled_flag,
+        cu_qp_delta_enabled_flag / set_cu_qp_delta_enabled_flag,
+        weighted_pred_flag / set_weighted_pred_flag,
+        weighted_bipred_flag / set_weighted_bipred_flag,
+        transquant_bypass_enabled_flag / set_transquant_bypass_enabled_flag,
+        tiles_enabled_flag / set_tiles_enabled_flag,
+        entropy_coding_sync_enabled_flag / set_entropy_coding_sync_enabled_flag,
+        loop_filter_across_tiles_enabled_flag / set_loop_filter_across_tiles_enabled_flag,
+        pps_loop_filter_across_slices_enabled_flag /
+            set_pps_loop_filter_across_slices_enabled_flag,
+        scaling_list_data_present_flag / set_scaling_list_data_present_flag,
+        screen_content_flag / set_screen_content_flag,
+        enable_gpu_weighted_prediction / set_enable_gpu_weighted_prediction,
+        no_output_of_prior_pics_flag / set_no_output_of_prior_pics_flag,
+    }
+    EncHEVCSliceFields: VAEncSliceParameterBufferHEVC_slice_fields.bits as u32 {
+        last_slice_of_pic_flag / set_last_slice_of_pic_flag,
+        dependent_slice_segment_flag / set_dependent_slice_segment_flag,
+        colour_plane_id / set_colour_plane_id,
+        slice_temporal_mvp_enabled_flag / set_slice_temporal_mvp_enabled_flag,
+        slice_sao_luma_flag / set_slice_sao_luma_flag,
+        slice_sao_chroma_flag / set_slice_sao_chroma_flag,
+        num_ref_idx_active_override_flag / set_num_ref_idx_active_override_flag,
+        mvd_l1_zero_flag / set_mvd_l1_zero_flag,
+        cabac_init_flag / set_cabac_init_flag,
+        slice_deblocking_filter_disabled_flag / set_slice_deblocking_filter_disabled_flag,
+        slice_loop_filter_across_slices_enabled_flag /
+            set_slice_loop_filter_across_slices_enabled_flag,
+        collocated_from_l0_flag / set_collocated_from_l0_flag,
+    }
+    EncH264SeqFields: VAEncSequenceParameterBufferH264_seq_fields.bits as u32 {
+        chroma_format_idc / set_chroma_format_idc,
+        frame_mbs_only_flag / set_frame_mbs_only_flag,
+        mb_adaptive_frame_field_flag / set_mb_adaptive_frame_field_flag,
+        seq_scaling_matrix_present_flag / set_seq_scaling_matrix_present_flag,
+        direct_8x8_inference_flag / set_direct_8x8_inference_flag,
+        log2_max_frame_num_minus4 / set_log2_max_frame_num_minus4,
+        pic_order_cnt_type / set_pic_order_cnt_type,
+        log2_max_pic_order_cnt_lsb_minus4 / set_log2_max_pic_order_cnt_lsb_minus4,
+        delta_pic_order_always_zero_flag / set_delta_pic_order_always_zero_flag,
+    }
+    EncH264VuiFields: VAEncSequenceParameterBufferH264_vui_fields.bits as u32 {
+        aspect_ratio_info_present_flag / set_aspect_ratio_info_present_flag,
+        timing_info_present_flag / set_timing_info_present_flag,
+        bitstream_restriction_flag / set_bitstream_restriction_flag,
+        log2_max_mv_length_horizontal / set_log2_max_mv_length_horizontal,
+        log2_max_mv_length_vertical / set_log2_max_mv_length_vertical,
+    }
+    EncH264PicFields: VAEncPictureParameterBufferH264_pic_fields.bits as u32 {
+        idr_pic_flag / set_idr_pic_flag,
+        reference_pic_flag / set_reference_pic_flag,
+        entropy_coding_mode_flag / set_entropy_coding_mode_flag,
+        weighted_pred_flag / set_weighted_pred_flag,
+        weighted_bipred_idc / set_weighted_bipred_idc,
+        constrained_intra_pred_flag / set_constrained_intra_pred_flag,
+        transform_8x8_mode_flag / set_transform_8x8_mode_flag,
+        deblocking_filter_control_present_flag / set_deblocking_filter_control_present_flag,
+        redundant_pic_cnt_present_flag / set_redundant_pic_cnt_present_flag,
+        pic_order_present_flag / set_pic_order_present_flag,
+        pic_scaling_matrix_present_flag / set_pic_scaling_matrix_present_flag,
+    }
+    EncJPEGPicFlags: VAEncPictureParameterBufferJPEG_pic_flags.bits as u32 {
+        profile / set_profile,
+        progressive / set_progressive,
+        huffman / set_huffman,
+        interleaved / set_interleaved,
+        differential / set_differential,
+    }
+    EncMPEG2SequenceExtension: VAEncSequenceParameterBufferMPEG2_sequence_extension.bits as u32 {
+        profile_and_level_indication / set_profile_and_level_indication,
+        progressive_sequence / set_progressive_sequence,
+        chroma_format / set_chroma_format,
+        low_delay / set_low_delay,
+        frame_rate_extension_n / set_frame_rate_extension_n,
+        frame_rate_extension_d / set_frame_rate_extension_d,
+    }
+    EncMPEG2GopHeader: VAEncSequenceParameterBufferMPEG2_gop_header.bits as u32 {
+        time_code / set_time_code,
+        closed_gop / set_closed_gop,
+        broken_link / set_broken_link,
+    }
+    EncMPEG2PictureCodingExtension:
+        VAEncPictureParameterBufferMPEG2_picture_coding_extension.bits as u32 {
+        intra_dc_precision / set_intra_dc_precision,
+        picture_structure / set_picture_structure,
+        top_field_first / set_top_field_first,
+        frame_pred_frame_dct / set_frame_pred_frame_dct,
+        concealment_motion_vectors / set_concealment_motion_vectors,
+        q_scale_type / set_q_scale_type,
+        intra_vlc_format / set_intra_vlc_format,
+        alternate_scan / set_alternate_scan,
+        repeat_first_field / set_repeat_first_field,
+        progressive_frame / set_progressive_frame,
+        composite_display_flag / set_composite_display_flag,
+    }
+    EncMPEG2CompositeDisplay: VAEncPictureParameterBufferMPEG2_composite_display.bits as u32 {
+        v_axis / set_v_axis,
+        field_sequence / set_field_sequence,
+        sub_carrier / set_sub_carrier,
+        burst_amplitude / set_burst_amplitude,
+        sub_carrier_phase / set_sub_carrier_phase,
+    }
+    EncVP8RefFlags: VAEncPictureParameterBufferVP8_ref_flags.bits as u32 {
+        force_kf / set_force_kf,
+        no_ref_last / set_no_ref_last,
+        no_ref_gf / set_no_ref_gf,
+        no_ref_arf / set_no_ref_arf,
+    }
+    EncVP8PicFlags: VAEncPictureParameterBufferVP8_pic_flags.bits as u32 {
+        frame_type / set_frame_type,
+        version / set_version,
+        show_frame / set_show_frame,
+        color_space / set_color_space,
+        recon_filter_type / set_recon_filter_type,
+        loop_filter_type / set_loop_filter_type,
+        auto_partitions / set_auto_partitions,
+        num_token_partitions / set_num_token_partitions,
+        clamping_type / set_clamping_type,
+        segmentation_enabled / set_segmentation_enabled,
+        update_mb_segmentation_map / set_update_mb_segmentation_map,
+        update_segment_feature_data / set_update_segment_feature_data,
+        loop_filter_adj_enable / set_loop_filter_adj_enable,
+        refresh_entropy_probs / set_refresh_entropy_probs,
+        refresh_golden_frame / set_refresh_golden_frame,
+        refresh_alternate_frame / set_refresh_alternate_frame,
+        refresh_last / set_refresh_last,
+        copy_buffer_to_golden / set_copy_buffer_to_golden,
+        copy_buffer_to_alternate / set_copy_buffer_to_alternate,
+        sign_bias_golden / set_sign_bias_golden,
+        sign_bias_alternate / set_sign_bias_alternate,
+        mb_no_coeff_skip / set_mb_no_coeff_skip,
+        forced_lf_adjustment / set_forced_lf_adjustment,
+    }
+    EncVP9RefFlags: VAEncPictureParameterBufferVP9_ref_flags.bits as u32 {
+        force_kf / set_force_kf,
+        ref_frame_ctrl_l0 / set_ref_frame_ctrl_l0,
+        ref_frame_ctrl_l1 / set_ref_frame_ctrl_l1,
+        ref_last_idx / set_ref_last_idx,
+        ref_last_sign_bias / set_ref_last_sign_bias,
+        ref_gf_idx / set_ref_gf_idx,
+        ref_gf_sign_bias / set_ref_gf_sign_bias,
+        ref_arf_idx / set_ref_arf_idx,
+        ref_arf_sign_bias / set_ref_arf_sign_bias,
+        temporal_id / set_temporal_id,
+    }
+    EncVP9PicFlags: VAEncPictureParameterBufferVP9_pic_flags.bits as u32 {
+        frame_type / set_frame_type,
+        show_frame / set_show_frame,
+        error_resilient_mode / set_error_resilient_mode,
+        intra_only / set_intra_only,
+        allow_high_precision_mv / set_allow_high_precision_mv,
+        mcomp_filter_type / set_mcomp_filter_type,
+        frame_parallel_decoding_mode / set_frame_parallel_decoding_mode,
+        reset_frame_context / set_reset_frame_context,
+        refresh_frame_context / set_refresh_frame_context,
+        frame_context_idx / set_frame_context_idx,
+        segmentation_enabled / set_segmentation_enabled,
+        segmentation_temporal_update / set_segmentation_temporal_update,
+        segmentation_update_map / set_segmentation_update_map,
+        lossless_mode / set_lossless_mode,
+        comp_prediction_mode / set_comp_prediction_mode,
+        auto_segmentation / set_auto_segmentation,
+        super_frame_flag / set_super_frame_flag,
+    }
+    EncVP9SegFlags: VAEncSegParamVP9_seg_flags.bits as u8 {
+        segment_reference_enabled / set_segment_reference_enabled,
+        segment_reference / set_segment_reference,
+        segment_reference_skipped / set_segment_reference_skipped,
+    }
+}
+
+/* The intra_fields of the macroblock info start with their bits. Its inter_fields only
+ * have reserved bits */
+bitfields! {
+    EncH264MacroblockIntraFields: VAEncMacroblockParameterBufferH264_info
+        [VAEncMacroblockParameterBufferH264_info_intra_fields_bits] as u32 {
+        pred_avail_override_flag / set_pred_avail_override_flag,
+        pred_avail_flags / set_pred_avail_flags,
+    }
+}
+
+#[cfg(va_av1_decode)]
+bitfields! {
+    AV1SegmentInfoFields: VASegmentationStructAV1_segment_info_fields.bits as u32 {
+        enabled / set_enabled,
+        update_map / set_update_map,
+        temporal_update / set_temporal_update,
+        update_data / set_update_data,
+    }
+    AV1FilmGrainInfoFields: VAFilmGrainStructAV1_film_grain_info_fields.bits as u32 {
+        apply_grain / set_apply_grain,
+        chroma_scaling_from_luma / set_chroma_scaling_from_luma,
+        grain_scaling_minus_8 / set_grain_scaling_minus_8,
+        ar_coeff_lag / set_ar_coeff_lag,
+        ar_coeff_shift_minus_6 / set_ar_coeff_shift_minus_6,
+        grain_scale_shift / set_grain_scale_shift,
+        overlap_flag / set_overlap_flag,
+        clip_to_restricted_range / set_clip_to_restricted_range,
+    }
+    AV1SeqInfoFields: VADecPictureParameterBufferAV1_seq_info_fields.fields as u32 {
+        still_picture / set_still_picture,
+        use_128x128_superblock / set_use_128x128_superblock,
+        enable_filter_intra / set_enable_filter_intra,
+        enable_intra_edge_filter / set_enable_intra_edge_filter,
+        enable_interintra_compound / set_enable_interintra_compound,
+        enable_masked_compound / set_enable_masked_compound,
+        enable_dual_filter / set_enable_dual_filter,
+        enable_order_hint / set_enable_order_hint,
+        enable_jnt_comp / set_enable_jnt_comp,
+        enable_cdef / set_enable_cdef,
+        mono_chrome / set_mono_chrome,
+        color_range / set_color_range,
+        subsampling_x / set_subsampling_x,
+        subsampling_y / set_subsampling_y,
+        chroma_sample_position / set_chroma_sample_position,
+        film_grain_params_present / set_film_grain_params_present,
+    }
+    AV1PicInfoFields: VADecPictureParameterBufferAV1_pic_info_fields.bits as u32 {
+        frame_type / set_frame_type,
+        show_frame / set_show_frame,
+        showable_frame / set_showable_frame,
+        error_resilient_mode / set_error_resilient_mode,
+        disable_cdf_update / set_disable_cdf_update,
+        allow_screen_content_tools / set_allow_screen_content_tools,
+        force_integer_mv / set_force_integer_mv,
+        allow_intrabc / set_allow_intrabc,
+        use_superres / set_use_superres,
+        allow_high_precision_mv / set_allow_high_precision_mv,
+        is_motion_mode_switchable / set_is_motion_mode_switchable,
+        use_ref_frame_mvs / set_use_ref_frame_mvs,
+        disable_frame_end_update_cdf / set_disable_frame_end_update_cdf,
+        uniform_tile_spacing_flag / set_uniform_tile_spacing_flag,
+        allow_warped_motion / set_allow_warped_motion,
+        large_scale_tile / set_large_scale_tile,
+    }
+    AV1LoopFilterInfoFields: VADecPictureParameterBufferAV1_loop_filter_info_fields.bits as u8 {
+        sharpness_level / set_sharpness_level,
+        mode_ref_delta_enabled / set_mode_ref_delta_enabled,
+        mode_ref_delta_update / set_mode_ref_delta_update,
+    }
+    AV1QMatrixFields: VADecPictureParameterBufferAV1_qmatrix_fields.bits as u16 {
+        using_qmatrix / set_using_qmatrix,
+        qm_y / set_qm_y,
+        qm_u / set_qm_u,
+        qm_v / set_qm_v,
+    }
+    AV1ModeControlFields: VADecPictureParameterBufferAV1_mode_control_fields.bits as u32 {
+        delta_q_present_flag / set_delta_q_present_flag,
+        log2_delta_q_res / set_log2_delta_q_res,
+        delta_lf_present_flag / set_delta_lf_present_flag,
+        log2_delta_lf_res / set_log2_delta_lf_res,
+        delta_lf_multi / set_delta_lf_multi,
+        tx_mode / set_tx_mode,
+        reference_select / set_reference_select,
+        reduced_tx_set_used / set_reduced_tx_set_used,
+        skip_mode_present / set_skip_mode_present,
+    }
+    AV1LoopRestorationFields: VADecPictureParameterBufferAV1_loop_restoration_fields.bits as u16 {
+        yframe_restoration_type / set_yframe_restoration_type,
+        cbframe_restoration_type / set_cbframe_restoration_type,
+        crframe_restoration_type / set_crframe_restoration_type,
+        lr_unit_shift / set_lr_unit_shift,
+        lr_uv_shift / set_lr_uv_shift,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zeroed() {
+        zero_structs();
+        #[cfg(va_av1_decode)]
+        zero_av1_structs();
+    }
+
+    #[test]
+    fn test_config_attrib_ranges() {
+        ConfigAttribValEncJPEG::check_ranges();
+        ConfigAttribValEncROI::check_ranges();
+        RateControlFlags::check_ranges();
+    }
+
+    #[test]
+    fn test_decode_ranges() {
+        MPEG2PictureCodingExtension::check_ranges();
+        MPEG2MacroblockModes::check_ranges();
+        MPEG4VolFields::check_ranges();
+        MPEG4VopFields::check_ranges();
+        VC1SequenceFields::check_ranges();
+        VC1EntrypointFields::check_ranges();
+        VC1RangeMappingFields::check_ranges();
+        VC1PictureFields::check_ranges();
+        VC1RawCoding::check_ranges();
+        VC1BitplanePresent::check_ranges();
+        VC1ReferenceFields::check_ranges();
+        VC1MvFields::check_ranges();
+        VC1PicQuantizerFields::check_ranges();
+        VC1TransformFields::check_ranges();
+        H264SeqFields::check_ranges();
+        H264PicFields::check_ranges();
+        HEVCPicFields::check_ranges();
+        HEVCSliceParsingFields::check_ranges();
+        HEVCLongSliceFlags::check_ranges();
+        VP8PicFields::check_ranges();
+        VP9PicFields::check_ranges();
+        VP9SegmentFlags::check_ranges();
+    }
+
+    #[test]
+    fn test_encode_ranges() {
+        EncSliceFlags::check_ranges();
+        EncHEVCSeqFields::check_ranges();
+        EncHEVCVuiFields::check_ranges();
+        EncHEVCPicFields::check_ranges();
+        EncHEVCSliceFields::check_ranges();
+        EncH264SeqFields::check_ranges();
+        EncH264VuiFields::check_ranges();
+        EncH264PicFields::check_ranges();
+        EncJPEGPicFlags::check_ranges();
+        EncMPEG2SequenceExtension::check_ranges();
+        EncMPEG2GopHeader::check_ranges();
+        EncMPEG2PictureCodingExtension::check_ranges();
+        EncMPEG2CompositeDisplay::check_ranges();
+        EncVP8RefFlags::check_ranges();
+        EncVP8PicFlags::check_ranges();
+        EncVP9RefFlags::check_ranges();
+        EncVP9PicFlags::check_ranges();
+        EncVP9SegFlags::check_ranges();
+        EncH264MacroblockIntraFields::check_ranges();
+        assert!(mem::size_of::<ffi::VAEncMacroblockParameterBufferH264_info_intra_fields_bits>() <=
+                mem::size_of::<ffi::VAEncMacroblockParameterBufferH264_info>());
+    }
+
+    #[cfg(va_av1_decode)]
+    #[test]
+    fn test_av1_ranges() {
+        AV1SegmentInfoFields::check_ranges();
+        AV1FilmGrainInfoFields::check_ranges();
+        AV1SeqInfoFields::check_ranges();
+        AV1PicInfoFields::check_ranges();
+        AV1LoopFilterInfoFields::check_ranges();
+        AV1QMatrixFields::check_ranges();
+        AV1ModeControlFields::check_ranges();
+        AV1LoopRestorationFields::check_ranges();
+    }
+
+    #[test]
+    fn test_overflow_names_field() {
+        let flags = RateControlFlags {
+            reset: 1,
+            mb_rate_control: 16,
+            ..Default::default()
+        };
+        assert_eq!(flags.to_va().err(), Some(BitfieldOverflow("mb_rate_control")));
+
+        let fields = H264PicFields {
+            weighted_bipred_idc: 4,
+            ..Default::default()
+        };
+        assert_eq!(fields.to_va().err(), Some(BitfieldOverflow("weighted_bipred_idc")));
+    }
+
+    /* Fields are read at the bit positions of the C declarations */
+    #[test]
+    fn test_from_raw_value() {
+        let raw: ffi::VAPictureParameterBufferH264_pic_fields =
+            unsafe { mem::transmute(0x0000_0429u32) };
+        assert_eq!(H264PicFields::from_va(&raw),
+                   H264PicFields {
+                       entropy_coding_mode_flag: 1,
+                       weighted_bipred_idc: 2,
+                       field_pic_flag: 1,
+                       reference_pic_flag: 1,
+                       ..Default::default()
+                   });
+
+        let raw: ffi::VAEncMiscParameterRateControl_rc_flags =
+            unsafe { mem::transmute(0x0000_0055u32) };
+        assert_eq!(RateControlFlags::from_va(&raw),
+                   RateControlFlags {
+                       reset: 1,
+                       disable_bit_stuffing: 1,
+                       mb_rate_control: 10,
+                       ..Default::default()
+                   });
+
+        let raw: ffi::VAEncMacroblockParameterBufferH264_info =
+            unsafe { mem::transmute([0x0000_01ffu32, 0]) };
+        assert_eq!(EncH264MacroblockIntraFields::from_va(&raw),
+                   EncH264MacroblockIntraFields {
+                       pred_avail_override_flag: 1,
+                       pred_avail_flags: 0xff,
+                   });
+    }
+}
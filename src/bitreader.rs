@@ -0,0 +1,95 @@
+// Copyright (c) 2017 zzoon <zzoon@igalia.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/* MSB-first bit reader shared by the bitstream parsers */
+#[derive(Debug, Clone)]
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader { data: data, pos: 0 }
+    }
+
+    pub fn read_bits(&mut self, n: u32) -> Result<u32, ()> {
+        let value = self.peek_bits(n)?;
+        self.pos += n as usize;
+        Ok(value)
+    }
+
+    pub fn peek_bits(&self, n: u32) -> Result<u32, ()> {
+        if n > 32 || self.bits_left() < n as usize {
+            return Err(());
+        }
+
+        let mut value: u64 = 0;
+        let mut pos = self.pos;
+        for _ in 0..n {
+            let bit = (self.data[pos >> 3] >> (7 - (pos & 7))) & 1;
+            value = (value << 1) | bit as u64;
+            pos += 1;
+        }
+
+        Ok(value as u32)
+    }
+
+    pub fn read_flag(&mut self) -> Result<bool, ()> {
+        Ok(self.read_bits(1)? == 1)
+    }
+
+    /* Unsigned Exp-Golomb code, ue(v) */
+    pub fn read_ue(&mut self) -> Result<u32, ()> {
+        let mut leading_zeros = 0;
+        while !self.read_flag()? {
+            leading_zeros += 1;
+            if leading_zeros > 31 {
+                return Err(());
+            }
+        }
+        if leading_zeros == 0 {
+            return Ok(0);
+        }
+        Ok(((1u64 << leading_zeros) - 1 + self.read_bits(leading_zeros)? as u64) as u32)
+    }
+
+    /* Signed Exp-Golomb code, se(v) */
+    pub fn read_se(&mut self) -> Result<i32, ()> {
+        let value = self.read_ue()? as i64;
+        if value & 1 == 1 {
+            Ok(((value + 1) / 2) as i32)
+        } else {
+            Ok(-(value / 2) as i32)
+        }
+    }
+
+    pub fn skip_bits(&mut self, n: usize) -> Result<(), ()> {
+        if self.bits_left() < n {
+            return Err(());
+        }
+        self.pos += n;
+        Ok(())
+    }
+
+    pub fn byte_align(&mut self) {
+        self.pos = (self.pos + 7) & !7;
+    }
+
+    pub fn is_byte_aligned(&self) -> bool {
+        self.pos & 7 == 0
+    }
+
+    pub fn bits_left(&self) -> usize {
+        (self.data.len() * 8).saturating_sub(self.pos)
+    }
+
+    pub fn get_pos(&self) -> usize {
+        self.pos
+    }
+}
@@ -0,0 +1,901 @@
+// Copyright (c) 2017 zzoon <zzoon@igalia.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::cmp;
+use std::mem;
+use std::rc::Rc;
+use std::slice;
+use std::time::{Duration, Instant};
+use std::os::raw::{c_int, c_uint, c_void};
+
+use ffi;
+use jpeg::JpegError;
+use va::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    InvalidStream,
+    Unsupported,
+    NoSurface,
+    VAFailed,
+    Io,
+    /* JPEG coding processes outside of baseline */
+    Jpeg(JpegError),
+}
+
+/* Bitstream syntax errors */
+impl From<()> for DecodeError {
+    fn from(_: ()) -> DecodeError {
+        DecodeError::InvalidStream
+    }
+}
+
+pub const ZIGZAG: [usize; 64] = [0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19,
+                                 26, 33, 40, 48, 41, 34, 27, 20, 13, 6, 7, 14, 21, 28, 35, 42, 49,
+                                 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59, 52,
+                                 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63];
+
+pub trait VADecoder {
+    /* data holds whole pictures: complete access units of the start code delimited streams,
+     * one RCV frame, one AV1 temporal unit or one JPEG image. The picture being built is
+     * submitted at the end of every call, so feeding one slice or NAL unit per call decodes
+     * each of them as a picture of its own. MPEG-2 is the exception: its elementary stream
+     * may come in chunks of any size, and a picture is submitted once the next picture,
+     * GOP or sequence start code arrives, or on drain() */
+    fn decode(&mut self, va_disp: &VADisplay, data: &[u8], pts: u64) -> Result<(), DecodeError>;
+    /* Outputs every frame still held for reordering at the end of the stream. References
+     * are released, so decoding can go on with a stream starting at a random access point */
+    fn drain(&mut self, va_disp: &VADisplay) -> Result<(), DecodeError>;
+    /* Discards the picture being decoded, references and frames not output yet, e.g. to
+     * seek. Parameter sets, context and surfaces are kept; decoding restarts at the next
+     * random access point */
+    fn flush(&mut self);
+    fn get_frame(&mut self) -> Option<VADecodedFrame>;
+    /* Base and second view of the next stereo access unit */
+    fn get_frame_pair(&mut self) -> Option<(VADecodedFrame, VADecodedFrame)> {
+        None
+    }
+    /* Counters of the frames decoded, dropped and concealed so far, with timings */
+    fn get_stats(&self) -> VADecodeStats;
+    /* Pictures left out by the mode are never submitted to VA */
+    fn set_decode_mode(&mut self, mode: DecodeMode);
+    fn destroy(&mut self, va_disp: &VADisplay);
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VADecodeStats {
+    pub decoded_frames: u64,
+    /* Frames lost to stream errors or skipped until the stream could be decoded again */
+    pub dropped_frames: u64,
+    /* Decoded frames missing slices or predicted from corrupted references */
+    pub concealed_frames: u64,
+    pub skipped_slices: u64,
+    /* Pictures left out by the decode mode */
+    pub skipped_frames: u64,
+    /* Time since the first decoded frame */
+    pub elapsed: Duration,
+    /* Frames synced so far and the sum of their submit to ready latencies, for the
+     * current stream configuration */
+    pub synced_frames: u64,
+    pub total_latency: Duration,
+    /* Surfaces held by the decoder or by frames not released yet */
+    pub surfaces_in_use: usize,
+}
+
+impl VADecodeStats {
+    pub fn get_fps(&self) -> f64 {
+        let secs = self.elapsed.as_secs() as f64 + self.elapsed.subsec_nanos() as f64 * 1e-9;
+        if secs > 0.0 {
+            self.decoded_frames as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    pub fn get_average_latency(&self) -> Option<Duration> {
+        if self.synced_frames == 0 {
+            return None;
+        }
+        /* Duration only divides by a u32, which the frame count may not fit */
+        let average = self.total_latency.as_nanos() / self.synced_frames as u128;
+        Some(Duration::new((average / 1_000_000_000) as u64, (average % 1_000_000_000) as u32))
+    }
+
+    /* Adds the latencies and surface usage of the current configuration */
+    pub fn with_context(mut self, context: Option<&VADecodeContext>) -> VADecodeStats {
+        if let Some(context) = context {
+            let (count, total) = context.pool.get_latencies();
+            self.synced_frames = count;
+            self.total_latency = total;
+            self.surfaces_in_use = context.pool.get_num_used();
+        }
+        self
+    }
+}
+
+/* Pictures decoded out of a stream, e.g. only keyframes to make thumbnails */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeMode {
+    All,
+    ReferencesOnly,
+    KeyframesOnly,
+}
+
+impl Default for DecodeMode {
+    fn default() -> DecodeMode {
+        DecodeMode::All
+    }
+}
+
+impl DecodeMode {
+    /* Whether a picture is left out. Keyframes decode on their own */
+    pub fn skips(&self, is_key: bool, is_reference: bool) -> bool {
+        match *self {
+            DecodeMode::All => false,
+            DecodeMode::ReferencesOnly => !is_reference,
+            DecodeMode::KeyframesOnly => !is_key,
+        }
+    }
+
+    /* Keyframes are output as soon as decoded, since no other pictures are reordered */
+    pub fn is_keyframes_only(&self) -> bool {
+        *self == DecodeMode::KeyframesOnly
+    }
+}
+
+/* Error recovery state of a stream. Once a reference picture is lost, the pictures
+ * predicted from it are dropped until one that decodes on its own: an IDR, an intra
+ * picture or a recovery point */
+#[derive(Debug, Default)]
+pub struct VAErrorRecovery {
+    stats: VADecodeStats,
+    waiting: bool,
+    started: Option<Instant>,
+}
+
+impl VAErrorRecovery {
+    pub fn new() -> VAErrorRecovery {
+        VAErrorRecovery::default()
+    }
+
+    pub fn get_stats(&self) -> VADecodeStats {
+        VADecodeStats {
+            elapsed: self.started.map_or(Duration::from_secs(0), |t| t.elapsed()),
+            ..self.stats
+        }
+    }
+
+    /* Whether pictures are dropped until the next recovery point */
+    pub fn is_waiting(&self) -> bool {
+        self.waiting
+    }
+
+    pub fn lost_reference(&mut self) {
+        self.waiting = true;
+    }
+
+    pub fn recover(&mut self) {
+        self.waiting = false;
+    }
+
+    /* Decoding starts over after a flush or at the end of the stream */
+    pub fn restart(&mut self) {
+        self.waiting = true;
+    }
+
+    pub fn drop_frame(&mut self) {
+        self.stats.dropped_frames += 1;
+    }
+
+    pub fn skip_frame(&mut self) {
+        self.stats.skipped_frames += 1;
+    }
+
+    pub fn skip_slice(&mut self) {
+        self.stats.skipped_slices += 1;
+    }
+
+    pub fn frame_decoded(&mut self, frame: &VADecodedFrame) {
+        if self.started.is_none() {
+            self.started = Some(Instant::now());
+        }
+        self.stats.decoded_frames += 1;
+        if frame.corrupted {
+            self.stats.concealed_frames += 1;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PictureType {
+    I,
+    P,
+    B,
+}
+
+impl PictureType {
+    /* Type of a picture made of slices or fields of the given types */
+    pub fn merge(self, other: PictureType) -> PictureType {
+        match (self, other) {
+            (PictureType::B, _) | (_, PictureType::B) => PictureType::B,
+            (PictureType::P, _) | (_, PictureType::P) => PictureType::P,
+            _ => PictureType::I,
+        }
+    }
+}
+
+/* What is known of the coded picture a frame comes from */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VAFrameInfo {
+    pub picture_type: Option<PictureType>,
+    /* Lowest and highest quantizer of the slices */
+    pub qp_range: Option<(i32, i32)>,
+    /* Coded size of the picture */
+    pub bytes: usize,
+    /* Picture order count, or temporal reference */
+    pub poc: Option<i32>,
+    pub frame_num: Option<u32>,
+    /* References the picture was predicted from */
+    pub num_references: usize,
+    /* Submit to ready time, known once the frame is synced */
+    pub latency: Option<Duration>,
+}
+
+impl VAFrameInfo {
+    pub fn add_qp(&mut self, qp: i32) {
+        self.qp_range = match self.qp_range {
+            Some((min, max)) => Some((cmp::min(min, qp), cmp::max(max, qp))),
+            None => Some((qp, qp)),
+        };
+    }
+
+    /* Info of a frame coded as two field pictures, the type is the one of the first */
+    pub fn merge_field(mut self, first: &VAFrameInfo) -> VAFrameInfo {
+        self.picture_type = first.picture_type.or(self.picture_type);
+        if let Some((min, max)) = first.qp_range {
+            self.add_qp(min);
+            self.add_qp(max);
+        }
+        self.bytes += first.bytes;
+        self.poc = match (first.poc, self.poc) {
+            (Some(a), Some(b)) => Some(cmp::min(a, b)),
+            (a, b) => a.or(b),
+        };
+        self.frame_num = first.frame_num.or(self.frame_num);
+        self.num_references = cmp::max(self.num_references, first.num_references);
+        self
+    }
+}
+
+/* Order the two fields of an interlaced frame are meant to be displayed in */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldOrder {
+    Progressive,
+    TopFieldFirst,
+    BottomFieldFirst,
+}
+
+#[derive(Debug, Clone)]
+pub struct VADecodedFrame {
+    surface: Rc<VASurface>,
+    width: u32,
+    height: u32,
+    pts: u64,
+    view_id: u16,
+    field_order: FieldOrder,
+    info: VAFrameInfo,
+    corrupted: bool,
+    decode_errors: Option<VADecodeErrorReport>,
+}
+
+impl VADecodedFrame {
+    pub fn new(surface: Rc<VASurface>, width: u32, height: u32, pts: u64) -> VADecodedFrame {
+        VADecodedFrame {
+            surface: surface,
+            width: width,
+            height: height,
+            pts: pts,
+            view_id: 0,
+            field_order: FieldOrder::Progressive,
+            info: VAFrameInfo::default(),
+            corrupted: false,
+            decode_errors: None,
+        }
+    }
+
+    /* View of a multiview stream the frame belongs to, 0 for the base view */
+    pub fn with_view_id(mut self, view_id: u16) -> VADecodedFrame {
+        self.view_id = view_id;
+        self
+    }
+
+    /* Fields of the frame, for deinterlacing of interlaced content */
+    pub fn with_field_order(mut self, field_order: FieldOrder) -> VADecodedFrame {
+        self.field_order = field_order;
+        self
+    }
+
+    pub fn with_info(mut self, info: VAFrameInfo) -> VADecodedFrame {
+        self.info = info;
+        self
+    }
+
+    pub fn get_surface(&self) -> &VASurface {
+        &self.surface
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn get_pts(&self) -> u64 {
+        self.pts
+    }
+
+    pub fn get_view_id(&self) -> u16 {
+        self.view_id
+    }
+
+    pub fn get_field_order(&self) -> FieldOrder {
+        self.field_order
+    }
+
+    pub fn get_info(&self) -> &VAFrameInfo {
+        &self.info
+    }
+
+    /* Same picture shown again at another time, e.g. for skipped frames */
+    pub fn with_pts(&self, pts: u64) -> VADecodedFrame {
+        VADecodedFrame { pts: pts, ..self.clone() }
+    }
+
+    /* Waits for the decoding of the frame. A frame decoded with errors is still usable,
+     * its damaged macroblocks are then given by get_decode_errors() */
+    pub fn sync(&mut self, va_disp: &VADisplay) -> Result<(), DecodeError> {
+        let ret = self.surface.sync(va_disp);
+        if self.info.latency.is_none() {
+            self.info.latency = self.surface.get_latency();
+        }
+        match ret {
+            Ok(()) => Ok(()),
+            Err(VASyncError::Decoding(report)) => {
+                self.decode_errors = Some(report);
+                Ok(())
+            }
+            Err(VASyncError::Failed(_)) => Err(DecodeError::VAFailed),
+        }
+    }
+
+    /* Non-blocking sync(), tells whether the frame is ready */
+    pub fn try_sync(&mut self, va_disp: &VADisplay) -> Result<bool, DecodeError> {
+        match self.surface.status(va_disp) {
+            Ok(SurfaceStatus::Rendering) => Ok(false),
+            Ok(_) => self.sync(va_disp).map(|_| true),
+            Err(_) => Err(DecodeError::VAFailed),
+        }
+    }
+
+    pub fn get_decode_errors(&self) -> Option<&VADecodeErrorReport> {
+        self.decode_errors.as_ref()
+    }
+
+    /* Flags a frame decoded from an incomplete picture or from corrupted references */
+    pub fn mark_corrupted(&mut self) {
+        self.corrupted = true;
+    }
+
+    /* Whether the stream or the hardware reported damage in the frame */
+    pub fn is_corrupted(&self) -> bool {
+        self.corrupted || self.decode_errors.is_some()
+    }
+
+    /* Copies a synced frame to memory, as NV12 or, for high bit depth surfaces, P010 or
+     * P016 as the driver lays them out; RGB surfaces give RGBA */
+    pub fn download(&self, va_disp: &VADisplay) -> Result<VAFrameImage, DecodeError> {
+        let image = match self.surface.derive_image(va_disp) {
+            Ok(image) => image,
+            Err(_) => self.surface.get_image(va_disp).map_err(|_| DecodeError::VAFailed)?,
+        };
+        let buffer = image.get_buffer();
+        let ptr = buffer.map::<u8>(va_disp);
+        let ret = if ptr.is_null() {
+            Err(DecodeError::VAFailed)
+        } else {
+            let mapped = unsafe { slice::from_raw_parts(ptr, buffer.get_size() as usize) };
+            let ret = VAFrameImage::from_image(&image, mapped, self.width, self.height);
+            buffer.unmap(va_disp);
+            ret
+        };
+        image.destroy(va_disp);
+
+        ret
+    }
+}
+
+/* 4:2:0 frame in memory: the luma plane then the interleaved chroma one, without
+ * padding; 16-bit samples are little endian. Frames of RGB surfaces are RGBA pixels */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VAFrameImage {
+    pub fourcc: u32,
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+impl VAFrameImage {
+    fn from_image(image: &VAImage,
+                  mapped: &[u8],
+                  width: u32,
+                  height: u32)
+                  -> Result<VAFrameImage, DecodeError> {
+        let planes = [(image.get_offset(0), image.get_stride(0)),
+                      (image.get_offset(1), image.get_stride(1))];
+        VAFrameImage::from_planes(image.get_fourcc(), &planes, mapped, width, height)
+    }
+
+    /* Copies the rows of the planes, given as (offset, stride), out of the mapped image */
+    fn from_planes(fourcc: u32,
+                   layout: &[(u32, u32)],
+                   mapped: &[u8],
+                   width: u32,
+                   height: u32)
+                   -> Result<VAFrameImage, DecodeError> {
+        let bytes = match fourcc {
+            ffi::VA_FOURCC_NV12 => 1,
+            ffi::VA_FOURCC_P010 | ffi::VA_FOURCC_P016 => 2,
+            ffi::VA_FOURCC_RGBA | ffi::VA_FOURCC_RGBX | ffi::VA_FOURCC_BGRA |
+            ffi::VA_FOURCC_BGRX => 4,
+            _ => return Err(DecodeError::Unsupported),
+        };
+        let planes = if bytes == 4 {
+            vec![(width as usize * bytes, height as usize)]
+        } else {
+            vec![(width as usize * bytes, height as usize),
+                 ((width as usize + 1) / 2 * 2 * bytes, (height as usize + 1) / 2)]
+        };
+
+        let mut data = Vec::with_capacity(planes.iter().map(|&(row, rows)| row * rows).sum());
+        for (i, &(row, rows)) in planes.iter().enumerate() {
+            let (offset, stride) = (layout[i].0 as usize, layout[i].1 as usize);
+            for y in 0..rows {
+                let start = offset + y * stride;
+                data.extend_from_slice(mapped.get(start..start + row)
+                    .ok_or(DecodeError::VAFailed)?);
+            }
+        }
+
+        /* Pixels of the other RGB layouts are reordered, padding bytes become opaque */
+        if bytes == 4 && fourcc != ffi::VA_FOURCC_RGBA {
+            for pixel in data.chunks_mut(4) {
+                if fourcc == ffi::VA_FOURCC_BGRA || fourcc == ffi::VA_FOURCC_BGRX {
+                    pixel.swap(0, 2);
+                }
+                if fourcc != ffi::VA_FOURCC_BGRA {
+                    pixel[3] = 0xff;
+                }
+            }
+        }
+        let fourcc = if bytes == 4 { ffi::VA_FOURCC_RGBA } else { fourcc };
+
+        Ok(VAFrameImage {
+            fourcc: fourcc,
+            width: width,
+            height: height,
+            data: data,
+        })
+    }
+
+    /* Significant bits of the samples; P010 ones are in the 10 high bits of each word */
+    pub fn get_bit_depth(&self) -> u8 {
+        match self.fourcc {
+            ffi::VA_FOURCC_P010 => 10,
+            ffi::VA_FOURCC_P016 => 16,
+            _ => 8,
+        }
+    }
+
+    /* Samples as stored, in the high bits of 16-bit words */
+    fn get_sample(&self, idx: usize) -> u16 {
+        if self.fourcc == ffi::VA_FOURCC_NV12 {
+            (self.data[idx] as u16) << 8
+        } else {
+            self.data[idx * 2] as u16 | (self.data[idx * 2 + 1] as u16) << 8
+        }
+    }
+
+    /* Indices of the Y, U and V samples in planar order */
+    fn get_planar_indices(&self) -> Vec<usize> {
+        let luma_size = (self.width * self.height) as usize;
+        let chroma_size = ((self.width as usize + 1) / 2) * ((self.height as usize + 1) / 2);
+        let mut indices: Vec<usize> = (0..luma_size).collect();
+        indices.extend((0..chroma_size).map(|i| luma_size + i * 2));
+        indices.extend((0..chroma_size).map(|i| luma_size + i * 2 + 1));
+        indices
+    }
+
+    /* 8-bit planar 4:2:0 (I420), high bit depth samples lose their low bits */
+    pub fn to_planar_8bit(&self) -> Vec<u8> {
+        self.get_planar_indices().into_iter().map(|i| (self.get_sample(i) >> 8) as u8).collect()
+    }
+
+    /* 16-bit planar 4:2:0 with the samples in the low bits, as for yuv420p10 */
+    pub fn to_planar_16bit(&self) -> Vec<u16> {
+        let shift = 16 - self.get_bit_depth();
+        self.get_planar_indices().into_iter().map(|i| self.get_sample(i) >> shift).collect()
+    }
+}
+
+/* Buffers of one picture, created as they are added and rendered in a single
+ * vaRenderPicture call. They are destroyed after vaEndPicture, or with the builder when
+ * it is not submitted */
+pub struct PictureSubmission<'a> {
+    va_disp: &'a VADisplay,
+    context: &'a VADecodeContext,
+    buffers: Vec<VABuffer>,
+}
+
+impl<'a> PictureSubmission<'a> {
+    pub fn new(va_disp: &'a VADisplay, context: &'a VADecodeContext) -> PictureSubmission<'a> {
+        PictureSubmission {
+            va_disp: va_disp,
+            context: context,
+            buffers: Vec::new(),
+        }
+    }
+
+    pub fn add_param<T: ParamBuffer>(mut self, data: &T) -> Result<Self, ()> {
+        let buffer = self.context.create_buffer(self.va_disp, data)?;
+        self.buffers.push(buffer);
+        Ok(self)
+    }
+
+    /* Elements of an array in one buffer, e.g. the parameters of several slices */
+    pub fn add_params<T: ParamBuffer>(mut self, data: &[T]) -> Result<Self, ()> {
+        let buffer = self.context.create_array_buffer(self.va_disp, data)?;
+        self.buffers.push(buffer);
+        Ok(self)
+    }
+
+    pub fn add_data(mut self, buffer_type: VABufferType, data: &[u8]) -> Result<Self, ()> {
+        let buffer = self.context.create_data_buffer(self.va_disp, buffer_type, data)?;
+        self.buffers.push(buffer);
+        Ok(self)
+    }
+
+    /* Slice parameters followed by the slice data they describe */
+    pub fn add_slice<T: ParamBuffer>(self, param: &T, data: &[u8]) -> Result<Self, ()> {
+        self.add_param(param)
+            .and_then(|s| s.add_data(ffi::VASliceDataBufferType, data))
+    }
+
+    /* Restricts the last buffer added to its first elements, for arrays allocated larger
+     * than the slices actually found */
+    pub fn set_num_elements(mut self, num_elem: u32) -> Result<Self, ()> {
+        match self.buffers.last_mut() {
+            Some(buffer) => buffer.set_num_elements(self.va_disp, num_elem)?,
+            None => return Err(()),
+        }
+        Ok(self)
+    }
+
+    pub fn submit(mut self, surface: &VASurface) -> Result<(), ()> {
+        let buffers = mem::replace(&mut self.buffers, Vec::new());
+        self.context.submit(self.va_disp, surface, buffers)
+    }
+}
+
+impl<'a> Drop for PictureSubmission<'a> {
+    fn drop(&mut self) {
+        for buffer in &self.buffers {
+            buffer.destroy(self.va_disp);
+        }
+    }
+}
+
+/* Offsets of every 00 00 01 prefix in the buffer */
+pub fn find_start_codes(data: &[u8]) -> Vec<usize> {
+    let mut offsets = Vec::new();
+    let mut i = 0;
+    while i + 3 < data.len() {
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+            offsets.push(i);
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+    offsets
+}
+
+/* A surface is free again once nobody but the pool holds a reference to it */
+#[derive(Debug)]
+pub struct VASurfacePool {
+    surfaces: Vec<Rc<VASurface>>,
+}
+
+impl VASurfacePool {
+    pub fn new(va_disp: &VADisplay,
+               width: c_uint,
+               height: c_uint,
+               format: c_uint,
+               count: usize)
+               -> Result<VASurfacePool, ()> {
+        let mut pool = VASurfacePool { surfaces: Vec::with_capacity(count) };
+
+        for _ in 0..count {
+            match VASurface::new(va_disp, width, height, format, 1) {
+                Ok(surface) => pool.surfaces.push(Rc::new(surface)),
+                Err(_) => {
+                    pool.destroy(va_disp);
+                    return Err(());
+                }
+            }
+        }
+
+        Ok(pool)
+    }
+
+    pub fn get_free(&self) -> Option<Rc<VASurface>> {
+        self.surfaces.iter().find(|s| Rc::strong_count(s) == 1).cloned()
+    }
+
+    pub fn get_ids(&self) -> Vec<ffi::VASurfaceID> {
+        self.surfaces.iter().map(|s| s.get_id()).collect()
+    }
+
+    pub fn get_num_used(&self) -> usize {
+        self.surfaces.iter().filter(|s| Rc::strong_count(s) > 1).count()
+    }
+
+    pub fn get_latencies(&self) -> (u64, Duration) {
+        self.surfaces.iter().fold((0, Duration::from_secs(0)), |(count, total), s| {
+            let (n, latency) = s.get_latencies();
+            (count + n, total + latency)
+        })
+    }
+
+    pub fn destroy(&self, va_disp: &VADisplay) {
+        for surface in &self.surfaces {
+            surface.destroy_surfaces(va_disp);
+        }
+    }
+}
+
+/* Config, context and surfaces for one stream configuration */
+#[derive(Debug)]
+pub struct VADecodeContext {
+    config: VAConfig,
+    context: VAContext,
+    pool: VASurfacePool,
+    profile: VAProfile,
+    rt_format: c_uint,
+    width: u32,
+    height: u32,
+}
+
+impl VADecodeContext {
+    pub fn new(va_disp: &VADisplay,
+               profile: VAProfile,
+               rt_format: c_uint,
+               width: u32,
+               height: u32,
+               num_surfaces: usize)
+               -> Result<VADecodeContext, ()> {
+        let config = VAConfig::new(va_disp, profile, ffi::VAEntrypointVLD, 1)?;
+        let pool = match VASurfacePool::new(va_disp, width, height, rt_format, num_surfaces) {
+            Ok(pool) => pool,
+            Err(_) => {
+                config.destroy(va_disp);
+                return Err(());
+            }
+        };
+        let context = match VAContext::new_with_surfaces(va_disp,
+                                                         &config,
+                                                         &pool.get_ids(),
+                                                         width as c_int,
+                                                         height as c_int,
+                                                         ffi::VA_PROGRESSIVE as c_int) {
+            Ok(context) => context,
+            Err(_) => {
+                pool.destroy(va_disp);
+                config.destroy(va_disp);
+                return Err(());
+            }
+        };
+
+        Ok(VADecodeContext {
+            config: config,
+            context: context,
+            pool: pool,
+            profile: profile,
+            rt_format: rt_format,
+            width: width,
+            height: height,
+        })
+    }
+
+    pub fn is_compatible(&self,
+                         profile: VAProfile,
+                         rt_format: c_uint,
+                         width: u32,
+                         height: u32)
+                         -> bool {
+        self.profile == profile && self.rt_format == rt_format && self.width == width &&
+        self.height == height
+    }
+
+    pub fn get_surface(&self) -> Option<Rc<VASurface>> {
+        self.pool.get_free()
+    }
+
+    pub fn create_buffer<T: ParamBuffer>(&self,
+                                         va_disp: &VADisplay,
+                                         data: &T)
+                                         -> Result<VABuffer, ()> {
+        VABuffer::from_param(va_disp, &self.context, data)
+    }
+
+    /* One buffer holding every element, e.g. the slice parameters of a picture */
+    pub fn create_array_buffer<T: ParamBuffer>(&self,
+                                               va_disp: &VADisplay,
+                                               data: &[T])
+                                               -> Result<VABuffer, ()> {
+        VABuffer::from_params(va_disp, &self.context, data)
+    }
+
+    pub fn create_data_buffer(&self,
+                              va_disp: &VADisplay,
+                              buffer_type: VABufferType,
+                              data: &[u8])
+                              -> Result<VABuffer, ()> {
+        VABuffer::new(va_disp,
+                      &self.context,
+                      buffer_type,
+                      data.len() as c_uint,
+                      1,
+                      data.as_ptr() as *mut c_void)
+    }
+
+    /* Renders one picture and releases its buffers whatever the outcome */
+    pub fn submit(&self,
+                  va_disp: &VADisplay,
+                  surface: &VASurface,
+                  buffers: Vec<VABuffer>)
+                  -> Result<(), ()> {
+        /* A begun picture is always ended, even when rendering it failed */
+        let ret = self.context.begin_picture(va_disp, surface).and_then(|_| {
+            let rendered = self.context.render_picture(va_disp, &buffers);
+            let ended = self.context.end_picture(va_disp);
+            rendered.and(ended)
+        });
+        if ret.is_ok() {
+            surface.mark_submitted();
+        }
+
+        for buffer in &buffers {
+            buffer.destroy(va_disp);
+        }
+
+        ret
+    }
+
+    pub fn destroy(&self, va_disp: &VADisplay) {
+        self.context.destroy(va_disp);
+        self.pool.destroy(va_disp);
+        self.config.destroy(va_disp);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* Maps a 4:2:0 frame as a driver would, with padding bytes after every row. Chroma
+     * samples are given as U, V pairs */
+    fn map_pitched(fourcc: u32,
+                   width: u32,
+                   height: u32,
+                   luma: &[u16],
+                   chroma: &[u16],
+                   stride: u32)
+                   -> Result<VAFrameImage, DecodeError> {
+        let bytes = if fourcc == ffi::VA_FOURCC_NV12 { 1 } else { 2 };
+        let rows = [(luma, width as usize, height as usize),
+                    (chroma, (width as usize + 1) / 2 * 2, (height as usize + 1) / 2)];
+        let mut mapped = Vec::new();
+        let mut layout = Vec::new();
+        for &(samples, row, num_rows) in &rows {
+            layout.push((mapped.len() as u32, stride));
+            for line in samples.chunks(row).take(num_rows) {
+                for &sample in line {
+                    if bytes == 1 {
+                        mapped.push(sample as u8);
+                    } else {
+                        mapped.extend_from_slice(&[sample as u8, (sample >> 8) as u8]);
+                    }
+                }
+                mapped.resize(mapped.len() + stride as usize - row * bytes, 0xee);
+            }
+        }
+        VAFrameImage::from_planes(fourcc, &layout, &mapped, width, height)
+    }
+
+    #[test]
+    fn test_nv12_odd_size_to_planar() {
+        /* 3x3 luma, 2x2 chroma */
+        let luma: Vec<u16> = (1..10).collect();
+        let chroma = [10, 20, 11, 21, 12, 22, 13, 23];
+        let image = map_pitched(ffi::VA_FOURCC_NV12, 3, 3, &luma, &chroma, 8).unwrap();
+        assert_eq!(image.data.len(), 9 + 8);
+        assert_eq!(image.get_bit_depth(), 8);
+
+        let planar = image.to_planar_8bit();
+        assert_eq!(planar, [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 20, 21, 22, 23]);
+        let wide = image.to_planar_16bit();
+        assert_eq!(wide, planar.iter().map(|&s| s as u16).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_p010_odd_size_to_planar() {
+        /* 5x3 luma, 3x2 chroma, samples in the 10 high bits of each word */
+        let values: Vec<u16> = (0..27).map(|i| i * 37 + 3).collect();
+        let luma: Vec<u16> = values[..15].iter().map(|v| v << 6).collect();
+        let chroma: Vec<u16> = values[15..].iter().map(|v| v << 6).collect();
+        let image = map_pitched(ffi::VA_FOURCC_P010, 5, 3, &luma, &chroma, 16).unwrap();
+        assert_eq!(image.data.len(), (15 + 12) * 2);
+        assert_eq!(image.get_bit_depth(), 10);
+
+        let mut expected = values[..15].to_vec();
+        expected.extend(values[15..].iter().step_by(2));
+        expected.extend(values[16..].iter().step_by(2));
+        assert_eq!(image.to_planar_16bit(), expected);
+        assert_eq!(image.to_planar_8bit(),
+                   expected.iter().map(|&v| (v >> 2) as u8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_p016_to_planar() {
+        /* 2x2 luma, one chroma pair */
+        let luma = [0x0102, 0x8384, 0xfffe, 0x0000];
+        let chroma = [0x1234, 0xabcd];
+        let image = map_pitched(ffi::VA_FOURCC_P016, 2, 2, &luma, &chroma, 6).unwrap();
+        assert_eq!(image.data.len(), (4 + 2) * 2);
+        assert_eq!(image.get_bit_depth(), 16);
+
+        assert_eq!(image.to_planar_16bit(), [0x0102, 0x8384, 0xfffe, 0x0000, 0x1234, 0xabcd]);
+        assert_eq!(image.to_planar_8bit(), [0x01, 0x83, 0xff, 0x00, 0x12, 0xab]);
+    }
+
+    #[test]
+    fn test_truncated_mapping() {
+        /* The chroma row of a 2x2 NV12 frame would end past the mapping */
+        let mapped = [0; 7];
+        let layout = [(0, 3), (6, 3)];
+        assert_eq!(VAFrameImage::from_planes(ffi::VA_FOURCC_NV12, &layout, &mapped, 2, 2),
+                   Err(DecodeError::VAFailed));
+    }
+
+    #[test]
+    fn test_average_latency() {
+        let mut stats = VADecodeStats::default();
+        assert_eq!(stats.get_average_latency(), None);
+
+        stats.synced_frames = 4;
+        stats.total_latency = Duration::from_millis(10);
+        assert_eq!(stats.get_average_latency(), Some(Duration::from_micros(2500)));
+
+        /* More frames than a u32 holds */
+        stats.synced_frames = 1 << 33;
+        stats.total_latency = Duration::from_secs(1 << 33);
+        assert_eq!(stats.get_average_latency(), Some(Duration::from_secs(1)));
+    }
+}
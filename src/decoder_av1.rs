@@ -0,0 +1,462 @@
+// Copyright (c) 2017 zzoon <zzoon@igalia.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::VecDeque;
+
+use av1::*;
+use decoder::*;
+use ffi;
+use va::*;
+
+/* Shown frames not fetched by get_frame() yet. A temporal unit shows one frame, so this
+ * only fills up when frames are not fetched after every call; the oldest are dropped then */
+const MAX_DISPLAY_QUEUE: usize = 4;
+
+/* Every reference slot and the frame being decoded can hold a surface for the decoded
+ * frame and another for its film grain output, and every queued frame one more */
+const NUM_SURFACES: usize = (NUM_REF_FRAMES + 1) * 2 + MAX_DISPLAY_QUEUE;
+
+/* VA-API version with the AV1 decode buffers, first shipped with libva 2.8 */
+const MIN_VA_VERSION: (i32, i32) = (1, 8);
+
+/* A decoded frame and the one displayed for it, which differ once film grain is applied */
+#[derive(Debug, Clone)]
+struct RefSlot {
+    frame: VADecodedFrame,
+    display: VADecodedFrame,
+}
+
+#[derive(Debug)]
+struct PendingPicture {
+    header: FrameHeader,
+    frame: VADecodedFrame,
+    display: Option<VADecodedFrame>,
+    tile_groups: Vec<(Vec<ffi::VASliceParameterBufferAV1>, Vec<u8>)>,
+    corrupted: bool,
+}
+
+#[derive(Debug)]
+pub struct VADecoderAV1 {
+    context: Option<VADecodeContext>,
+    parser: Parser,
+    picture: Option<PendingPicture>,
+    /* Header of a frame that is not decoded, its tile groups are ignored */
+    skipped: Option<FrameHeader>,
+    ref_slots: [Option<RefSlot>; NUM_REF_FRAMES],
+    output: VecDeque<VADecodedFrame>,
+    recovery: VAErrorRecovery,
+    mode: DecodeMode,
+}
+
+impl VADecoderAV1 {
+    pub fn new() -> Result<Box<VADecoder>, ()> {
+        Ok(Box::new(VADecoderAV1 {
+            context: None,
+            parser: Parser::new(),
+            picture: None,
+            skipped: None,
+            ref_slots: Default::default(),
+            output: VecDeque::new(),
+            recovery: VAErrorRecovery::new(),
+            mode: DecodeMode::All,
+        }))
+    }
+
+    /* Surfaces are allocated at the largest size of the sequence, the frames use part of
+     * them */
+    fn ensure_context(&mut self, va_disp: &VADisplay) -> Result<(), DecodeError> {
+        if va_disp.get_va_version() < MIN_VA_VERSION {
+            return Err(DecodeError::Unsupported);
+        }
+        let (profile, rt_format, width, height) = match self.parser.get_sequence_header() {
+            Some(seq) => {
+                let rt_format = seq.get_rt_format().map_err(|_| DecodeError::Unsupported)?;
+                let profile = seq.get_va_profile().map_err(|_| DecodeError::Unsupported)?;
+                (profile, rt_format, seq.max_frame_width, seq.max_frame_height)
+            }
+            None => return Err(DecodeError::InvalidStream),
+        };
+
+        if let Some(ref context) = self.context {
+            if context.is_compatible(profile, rt_format, width, height) {
+                return Ok(());
+            }
+        }
+
+        self.ref_slots = Default::default();
+        if let Some(context) = self.context.take() {
+            context.destroy(va_disp);
+        }
+
+        let context = VADecodeContext::new(va_disp,
+                                           profile,
+                                           rt_format,
+                                           width,
+                                           height,
+                                           NUM_SURFACES)
+            .map_err(|_| DecodeError::VAFailed)?;
+        self.context = Some(context);
+
+        Ok(())
+    }
+
+    /* The slots a frame would have refreshed hold nothing usable anymore */
+    fn clear_refreshed_slots(&mut self, hdr: &FrameHeader) {
+        for (i, slot) in self.ref_slots.iter_mut().enumerate() {
+            if hdr.refresh_frame_flags & (1 << i) != 0 {
+                *slot = None;
+            }
+        }
+    }
+
+    /* A frame that cannot be decoded, frames predicted from it are dropped until the next
+     * key frame */
+    fn lose_frame(&mut self, hdr: Option<&FrameHeader>) {
+        self.picture = None;
+        self.skipped = None;
+        self.recovery.drop_frame();
+        match hdr {
+            Some(hdr) if hdr.refresh_frame_flags == 0 => {}
+            Some(hdr) => {
+                self.clear_refreshed_slots(hdr);
+                self.recovery.lost_reference();
+            }
+            None => self.recovery.lost_reference(),
+        }
+    }
+
+    /* A frame decoded earlier is shown again, a key frame also becomes the only
+     * reference */
+    fn show_existing_frame(&mut self, hdr: &FrameHeader, pts: u64) {
+        let slot = match self.ref_slots[hdr.frame_to_show_map_idx as usize] {
+            Some(ref slot) => slot.clone(),
+            None => {
+                self.lose_frame(Some(hdr));
+                return;
+            }
+        };
+        if hdr.frame_type == KEY_FRAME {
+            self.recovery.recover();
+            for s in self.ref_slots.iter_mut() {
+                *s = Some(slot.clone());
+            }
+        }
+        self.queue_frame(slot.display.with_pts(pts));
+    }
+
+    fn queue_frame(&mut self, frame: VADecodedFrame) {
+        if self.output.len() == MAX_DISPLAY_QUEUE {
+            self.output.pop_front();
+            self.recovery.drop_frame();
+        }
+        self.output.push_back(frame);
+    }
+
+    fn start_picture(&mut self,
+                     va_disp: &VADisplay,
+                     hdr: FrameHeader,
+                     pts: u64)
+                     -> Result<(), DecodeError> {
+        let is_key = hdr.frame_type == KEY_FRAME;
+        if self.mode.skips(is_key, hdr.refresh_frame_flags != 0) {
+            self.clear_refreshed_slots(&hdr);
+            self.recovery.skip_frame();
+            self.skipped = Some(hdr);
+            return Ok(());
+        }
+        if is_key && hdr.show_frame {
+            self.recovery.recover();
+        }
+        let missing_ref = !hdr.is_intra() &&
+                          hdr.ref_frame_idx.iter().any(|&i| self.ref_slots[i as usize].is_none());
+        if self.recovery.is_waiting() || missing_ref {
+            self.lose_frame(Some(&hdr));
+            self.skipped = Some(hdr);
+            return Ok(());
+        }
+        self.ensure_context(va_disp)?;
+
+        let (frame, display) = {
+            let context = self.context.as_ref().ok_or(DecodeError::InvalidStream)?;
+            let width = hdr.upscaled_width;
+            let height = hdr.frame_height;
+            let surface = context.get_surface().ok_or(DecodeError::NoSurface)?;
+            let frame = VADecodedFrame::new(surface, width, height, pts);
+            let display = if hdr.film_grain.apply_grain {
+                let surface = context.get_surface().ok_or(DecodeError::NoSurface)?;
+                Some(VADecodedFrame::new(surface, width, height, pts))
+            } else {
+                None
+            };
+            (frame, display)
+        };
+
+        self.picture = Some(PendingPicture {
+            header: hdr,
+            frame: frame,
+            display: display,
+            tile_groups: Vec::new(),
+            corrupted: false,
+        });
+        Ok(())
+    }
+
+    /* A frame still missing tiles when the next one starts is lost */
+    fn abandon_picture(&mut self) {
+        if let Some(pic) = self.picture.take() {
+            self.lose_frame(Some(&pic.header));
+        }
+    }
+
+    fn decode_tile_group(&mut self, va_disp: &VADisplay, data: &[u8]) -> Result<(), DecodeError> {
+        /* The tiles of a skipped frame are only followed up to the last one */
+        if let Some(hdr) = self.skipped.take() {
+            match parse_tile_group(&hdr, data) {
+                Ok(ref tg) if tg.tg_end == hdr.tile_info.num_tiles() - 1 => {}
+                _ => self.skipped = Some(hdr),
+            }
+            return Ok(());
+        }
+        let complete = {
+            let pic = match self.picture {
+                Some(ref mut pic) => pic,
+                None => return Err(DecodeError::InvalidStream),
+            };
+            let tg = match parse_tile_group(&pic.header, data) {
+                Ok(tg) => tg,
+                Err(_) => {
+                    pic.corrupted = true;
+                    self.recovery.skip_slice();
+                    return Ok(());
+                }
+            };
+            let complete = tg.tg_end == pic.header.tile_info.num_tiles() - 1;
+            pic.tile_groups.push((build_tile_params(&tg), data[tg.data_offset..].to_vec()));
+            complete
+        };
+
+        if complete {
+            self.end_picture(va_disp)?;
+        }
+        Ok(())
+    }
+
+    fn end_picture(&mut self, va_disp: &VADisplay) -> Result<(), DecodeError> {
+        let pic = match self.picture.take() {
+            Some(pic) => pic,
+            None => return Ok(()),
+        };
+
+        let hdr = pic.header;
+        let mut frame = pic.frame;
+        let mut ref_frame_map = [ffi::VA_INVALID_SURFACE; NUM_REF_FRAMES];
+        for (id, slot) in ref_frame_map.iter_mut().zip(self.ref_slots.iter()) {
+            if let Some(ref slot) = *slot {
+                *id = slot.frame.get_surface().get_id();
+            }
+        }
+
+        let mut refs: Vec<usize> = Vec::new();
+        if !hdr.is_intra() {
+            refs = hdr.ref_frame_idx.iter().map(|&i| i as usize).collect();
+            refs.sort();
+            refs.dedup();
+        }
+        let corrupted = refs.iter().any(|&i| {
+            self.ref_slots[i].as_ref().map_or(false, |slot| slot.frame.is_corrupted())
+        });
+        if pic.corrupted || corrupted {
+            frame.mark_corrupted();
+        }
+
+        let mut info = VAFrameInfo {
+            picture_type: Some(if hdr.is_intra() {
+                PictureType::I
+            } else if hdr.reference_select {
+                PictureType::B
+            } else {
+                PictureType::P
+            }),
+            bytes: pic.tile_groups.iter().map(|&(_, ref data)| data.len()).sum(),
+            poc: Some(hdr.order_hint as i32),
+            num_references: refs.len(),
+            ..VAFrameInfo::default()
+        };
+        info.add_qp(hdr.quant.base_q_idx as i32);
+        frame = frame.with_info(info.clone());
+
+        let pic_param = match self.parser.get_sequence_header() {
+            Some(seq) => {
+                let display_id = match pic.display {
+                    Some(ref display) => display.get_surface().get_id(),
+                    None => frame.get_surface().get_id(),
+                };
+                build_picture_param(seq,
+                                    &hdr,
+                                    frame.get_surface().get_id(),
+                                    display_id,
+                                    &ref_frame_map)?
+            }
+            None => return Err(DecodeError::InvalidStream),
+        };
+
+        {
+            let context = self.context.as_ref().ok_or(DecodeError::InvalidStream)?;
+            let mut submission = PictureSubmission::new(va_disp, context).add_param(&pic_param);
+            for &(ref tile_params, ref data) in &pic.tile_groups {
+                submission = submission.and_then(|s| s.add_params(tile_params))
+                    .and_then(|s| s.add_data(ffi::VASliceDataBufferType, data));
+            }
+            submission.and_then(|s| s.submit(frame.get_surface()))
+                .map_err(|_| DecodeError::VAFailed)?;
+        }
+
+        let mut display = match pic.display {
+            Some(display) => display.with_info(info),
+            None => frame.clone(),
+        };
+        if frame.is_corrupted() {
+            display.mark_corrupted();
+        }
+        self.recovery.frame_decoded(&display);
+        for (i, slot) in self.ref_slots.iter_mut().enumerate() {
+            if hdr.refresh_frame_flags & (1 << i) != 0 {
+                *slot = Some(RefSlot {
+                    frame: frame.clone(),
+                    display: display.clone(),
+                });
+            }
+        }
+        if hdr.show_frame {
+            self.queue_frame(display);
+        }
+
+        Ok(())
+    }
+
+    fn decode_frame_header(&mut self,
+                           va_disp: &VADisplay,
+                           obu: &Obu,
+                           pts: u64)
+                           -> Result<(), DecodeError> {
+        /* Copies of the header of the frame being decoded */
+        if obu.header.obu_type == OBU_FRAME_HEADER &&
+           (self.picture.is_some() || self.skipped.is_some()) {
+            return Ok(());
+        }
+        self.abandon_picture();
+        self.skipped = None;
+
+        let hdr = match self.parser.parse_frame_header(obu.data, &obu.header) {
+            Ok(hdr) => hdr,
+            Err(_) => {
+                self.lose_frame(None);
+                return Ok(());
+            }
+        };
+        if hdr.show_existing_frame {
+            self.show_existing_frame(&hdr, pts);
+            return Ok(());
+        }
+
+        let header_bytes = hdr.header_bytes;
+        self.start_picture(va_disp, hdr, pts)?;
+        if obu.header.obu_type == OBU_FRAME {
+            self.decode_tile_group(va_disp, &obu.data[header_bytes..])?;
+        }
+        Ok(())
+    }
+
+    fn decode_obu(&mut self, va_disp: &VADisplay, obu: &Obu, pts: u64) -> Result<(), DecodeError> {
+        if !self.parser.is_in_operating_point(&obu.header) {
+            return Ok(());
+        }
+
+        match obu.header.obu_type {
+            OBU_SEQUENCE_HEADER => {
+                self.abandon_picture();
+                self.parser
+                    .parse_sequence_header(obu.data)
+                    .map_err(|_| DecodeError::InvalidStream)?;
+            }
+            OBU_TEMPORAL_DELIMITER => self.abandon_picture(),
+            OBU_FRAME_HEADER | OBU_FRAME => self.decode_frame_header(va_disp, obu, pts)?,
+            OBU_TILE_GROUP => self.decode_tile_group(va_disp, obu.data)?,
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+impl VADecoder for VADecoderAV1 {
+    fn decode(&mut self, va_disp: &VADisplay, data: &[u8], pts: u64) -> Result<(), DecodeError> {
+        let obus = match split_obus(data) {
+            Ok(obus) => obus,
+            Err(_) => {
+                self.lose_frame(None);
+                return Ok(());
+            }
+        };
+        for obu in &obus {
+            match self.decode_obu(va_disp, obu, pts) {
+                /* Broken headers are ignored, the frames needing them are dropped */
+                Ok(_) | Err(DecodeError::InvalidStream) => {}
+                Err(e) => {
+                    self.picture = None;
+                    return Err(e);
+                }
+            }
+        }
+
+        /* Every call carries a whole temporal unit */
+        self.abandon_picture();
+        self.skipped = None;
+        Ok(())
+    }
+
+    /* Frames are output as soon as they are decoded, nothing is held back */
+    fn drain(&mut self, _va_disp: &VADisplay) -> Result<(), DecodeError> {
+        self.abandon_picture();
+        self.skipped = None;
+        self.ref_slots = Default::default();
+        self.recovery.restart();
+        Ok(())
+    }
+
+    /* Without references, frames up to the next key frame are dropped */
+    fn flush(&mut self) {
+        self.picture = None;
+        self.skipped = None;
+        self.ref_slots = Default::default();
+        self.output.clear();
+        self.recovery.restart();
+    }
+
+    fn get_frame(&mut self) -> Option<VADecodedFrame> {
+        self.output.pop_front()
+    }
+
+    fn get_stats(&self) -> VADecodeStats {
+        self.recovery.get_stats().with_context(self.context.as_ref())
+    }
+
+    fn set_decode_mode(&mut self, mode: DecodeMode) {
+        self.mode = mode;
+    }
+
+    fn destroy(&mut self, va_disp: &VADisplay) {
+        self.picture = None;
+        self.skipped = None;
+        self.ref_slots = Default::default();
+        self.output.clear();
+        if let Some(context) = self.context.take() {
+            context.destroy(va_disp);
+        }
+    }
+}
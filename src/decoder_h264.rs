@@ -0,0 +1,1789 @@
+// Copyright (c) 2017 zzoon <zzoon@igalia.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::os::raw::c_char;
+
+use bitfields::*;
+use decoder::*;
+use ffi;
+use h264::*;
+use va::*;
+
+/* Surfaces on top of the DPB: the picture being decoded and frames waiting in the output
+ * queue */
+const NUM_EXTRA_SURFACES: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reference {
+    None,
+    ShortTerm,
+    LongTerm,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PicStructure {
+    Frame,
+    TopField,
+    BottomField,
+}
+
+impl PicStructure {
+    fn from_header(hdr: &SliceHeader) -> PicStructure {
+        match (hdr.field_pic_flag, hdr.bottom_field_flag) {
+            (false, _) => PicStructure::Frame,
+            (true, false) => PicStructure::TopField,
+            (true, true) => PicStructure::BottomField,
+        }
+    }
+
+    fn from_parity(parity: usize) -> PicStructure {
+        if parity == 0 {
+            PicStructure::TopField
+        } else {
+            PicStructure::BottomField
+        }
+    }
+
+    /* Index of the field in the arrays holding both fields, 0 for frames */
+    fn get_parity(&self) -> usize {
+        (*self == PicStructure::BottomField) as usize
+    }
+}
+
+/* Entry of a reference picture list: a frame, or one field of it */
+type RefPic = (usize, PicStructure);
+
+/* A frame, a complementary field pair or a single field */
+#[derive(Debug, Clone)]
+struct DpbPicture {
+    frame: VADecodedFrame,
+    view_id: u16,
+    voc: usize,
+    /* Access unit the view component belongs to, the one of the first field for pairs */
+    au: u64,
+    structure: PicStructure,
+    frame_num: u32,
+    top_poc: i32,
+    bottom_poc: i32,
+    /* Marking of the top and bottom fields */
+    reference: [Reference; 2],
+    long_term_frame_idx: u32,
+    inter_view: bool,
+    needed_for_output: bool,
+    /* First field held back from output until its second field is decoded */
+    pending_second_field: bool,
+    /* Whether pictures predicted from this one are corrupted as well */
+    corrupted: bool,
+}
+
+impl DpbPicture {
+    fn get_poc(&self) -> i32 {
+        match self.structure {
+            PicStructure::Frame => cmp::min(self.top_poc, self.bottom_poc),
+            PicStructure::TopField => self.top_poc,
+            PicStructure::BottomField => self.bottom_poc,
+        }
+    }
+
+    /* Order count of the fields with the given marking, clause 8.2.4.2.4 */
+    fn get_ref_poc(&self, reference: Reference) -> i32 {
+        match (self.reference[0] == reference, self.reference[1] == reference) {
+            (true, false) => self.top_poc,
+            (false, true) => self.bottom_poc,
+            _ => self.get_poc(),
+        }
+    }
+
+    fn is_reference(&self) -> bool {
+        self.reference[0] != Reference::None || self.reference[1] != Reference::None
+    }
+
+    fn has_reference(&self, reference: Reference) -> bool {
+        self.reference[0] == reference || self.reference[1] == reference
+    }
+
+    /* Marking of the frame as a whole, both of its fields share it */
+    fn get_frame_reference(&self) -> Reference {
+        if self.structure == PicStructure::Frame && self.reference[0] == self.reference[1] {
+            self.reference[0]
+        } else {
+            Reference::None
+        }
+    }
+
+    fn set_reference(&mut self, structure: PicStructure, reference: Reference) {
+        match structure {
+            PicStructure::Frame => self.reference = [reference; 2],
+            field => self.reference[field.get_parity()] = reference,
+        }
+    }
+
+    fn unmark(&mut self, reference: Reference) {
+        for r in &mut self.reference {
+            if *r == reference {
+                *r = Reference::None;
+            }
+        }
+    }
+
+    /* Fields the frame is a reference with */
+    fn get_ref_structure(&self) -> PicStructure {
+        match (self.reference[0] != Reference::None, self.reference[1] != Reference::None) {
+            (true, false) => PicStructure::TopField,
+            (false, true) => PicStructure::BottomField,
+            _ => PicStructure::Frame,
+        }
+    }
+
+    fn to_va(&self, structure: PicStructure) -> ffi::VAPictureH264 {
+        let reference = match structure {
+            PicStructure::Frame if self.reference[0] == Reference::None => self.reference[1],
+            PicStructure::Frame => self.reference[0],
+            field => self.reference[field.get_parity()],
+        };
+        let (frame_idx, mut flags) = match reference {
+            Reference::ShortTerm => (self.frame_num, ffi::VA_PICTURE_H264_SHORT_TERM_REFERENCE),
+            Reference::LongTerm => {
+                (self.long_term_frame_idx, ffi::VA_PICTURE_H264_LONG_TERM_REFERENCE)
+            }
+            Reference::None => (self.frame_num, 0),
+        };
+        let (top_poc, bottom_poc) = match structure {
+            PicStructure::Frame => (self.top_poc, self.bottom_poc),
+            PicStructure::TopField => {
+                flags |= ffi::VA_PICTURE_H264_TOP_FIELD;
+                (self.top_poc, 0)
+            }
+            PicStructure::BottomField => {
+                flags |= ffi::VA_PICTURE_H264_BOTTOM_FIELD;
+                (0, self.bottom_poc)
+            }
+        };
+        build_va_picture(self.frame.get_surface().get_id(), frame_idx, flags, top_poc, bottom_poc)
+    }
+}
+
+/* Order of the fields of a frame from their order counts; frames of streams without
+ * field coding are progressive */
+fn get_field_order(sps: &Sps, top_poc: i32, bottom_poc: i32) -> FieldOrder {
+    if sps.frame_mbs_only_flag {
+        FieldOrder::Progressive
+    } else if top_poc < bottom_poc {
+        FieldOrder::TopFieldFirst
+    } else if bottom_poc < top_poc {
+        FieldOrder::BottomFieldFirst
+    } else {
+        FieldOrder::Progressive
+    }
+}
+
+fn build_iq_matrix(lists: &ScalingLists) -> ffi::VAIQMatrixBufferH264 {
+    let mut iq_matrix: ffi::VAIQMatrixBufferH264 = zeroed();
+    for i in 0..6 {
+        iq_matrix.ScalingList4x4[i] = lists.get_raster_4x4(i);
+    }
+    for i in 0..2 {
+        iq_matrix.ScalingList8x8[i] = lists.get_raster_8x8(i);
+    }
+    iq_matrix
+}
+
+fn build_va_picture(picture_id: ffi::VASurfaceID,
+                    frame_idx: u32,
+                    flags: u32,
+                    top_poc: i32,
+                    bottom_poc: i32)
+                    -> ffi::VAPictureH264 {
+    let mut va_pic: ffi::VAPictureH264 = zeroed();
+    va_pic.picture_id = picture_id;
+    va_pic.frame_idx = frame_idx;
+    va_pic.flags = flags;
+    va_pic.TopFieldOrderCnt = top_poc;
+    va_pic.BottomFieldOrderCnt = bottom_poc;
+    va_pic
+}
+
+fn invalid_va_picture() -> ffi::VAPictureH264 {
+    build_va_picture(ffi::VA_INVALID_SURFACE, 0, ffi::VA_PICTURE_H264_INVALID, 0, 0)
+}
+
+/* Picture order count and frame_num state of one view */
+#[derive(Debug, Clone, Default)]
+struct ViewState {
+    prev_poc_msb: i32,
+    prev_poc_lsb: i32,
+    prev_frame_num_offset: i32,
+    prev_frame_num: u32,
+    prev_ref_frame_num: u32,
+    max_long_term_frame_idx: Option<u32>,
+}
+
+struct PendingPicture {
+    frame: VADecodedFrame,
+    sps: Sps,
+    pps: Pps,
+    hdr: SliceHeader,
+    nal: NalHeader,
+    voc: usize,
+    top_poc: i32,
+    bottom_poc: i32,
+    poc_msb: i32,
+    frame_num_offset: i32,
+    structure: PicStructure,
+    /* Access unit of the first field when this is the second one */
+    first_field: Option<u64>,
+    slices: Vec<(ffi::VASliceParameterBufferH264, Vec<u8>)>,
+    corrupted: bool,
+    /* Picture waiting for a recovery point, its slices are not decoded */
+    dropped: bool,
+    /* Picture left out by the decode mode, dropped as well */
+    skipped: bool,
+}
+
+impl PendingPicture {
+    fn get_poc(&self) -> i32 {
+        cmp::min(self.top_poc, self.bottom_poc)
+    }
+
+    fn get_view(&self) -> Option<&MvcView> {
+        self.sps.mvc_views.get(self.voc)
+    }
+}
+
+pub struct VADecoderH264 {
+    context: Option<VADecodeContext>,
+    num_surfaces: usize,
+    sps: Vec<Option<Sps>>,
+    subset_sps: Vec<Option<Sps>>,
+    pps: Vec<Option<Pps>>,
+    /* MVC header of the base view, from the prefix NAL unit */
+    prefix: Option<MvcExtension>,
+    views: Vec<ViewState>,
+    num_views: usize,
+    dpb: Vec<DpbPicture>,
+    dpb_size: usize,
+    num_reorder_frames: usize,
+    au: u64,
+    au_started: bool,
+    picture: Option<PendingPicture>,
+    /* View components of each access unit, in view order */
+    output: VecDeque<Vec<VADecodedFrame>>,
+    recovery: VAErrorRecovery,
+    mode: DecodeMode,
+    /* recovery_frame_cnt of the recovery point SEI preceding the next picture */
+    recovery_point: Option<u32>,
+    /* Pictures left until the output is exact again after recovering at a recovery point */
+    recovery_frames: Option<u32>,
+    /* Highest nal_ref_idc of the broken slices met while no picture was started */
+    lost_slices: Option<u8>,
+}
+
+impl VADecoderH264 {
+    pub fn new() -> Result<Box<VADecoder>, ()> {
+        Ok(Box::new(VADecoderH264 {
+            context: None,
+            num_surfaces: 0,
+            sps: vec![None; MAX_SPS_COUNT],
+            subset_sps: vec![None; MAX_SPS_COUNT],
+            pps: vec![None; MAX_PPS_COUNT],
+            prefix: None,
+            views: Vec::new(),
+            num_views: 1,
+            dpb: Vec::new(),
+            dpb_size: 1,
+            num_reorder_frames: 0,
+            au: 0,
+            au_started: false,
+            picture: None,
+            output: VecDeque::new(),
+            recovery: VAErrorRecovery::new(),
+            mode: DecodeMode::All,
+            recovery_point: None,
+            recovery_frames: None,
+            lost_slices: None,
+        }))
+    }
+
+    /* Subset SPS describing the views, preferably the one sharing the base SPS id */
+    fn get_mvc_sps(&self, id: u32) -> Option<&Sps> {
+        match self.subset_sps[id as usize] {
+            Some(ref sps) if !sps.mvc_views.is_empty() => Some(sps),
+            _ => {
+                self.subset_sps
+                    .iter()
+                    .filter_map(|sps| sps.as_ref())
+                    .find(|sps| !sps.mvc_views.is_empty())
+            }
+        }
+    }
+
+    fn get_profile(sps: &Sps) -> Result<VAProfile, DecodeError> {
+        match sps.profile_idc {
+            /* FMO and ASO are rejected with the PPS, leaving Constrained Baseline */
+            66 => Ok(ffi::VAProfileH264ConstrainedBaseline),
+            77 | 88 => Ok(ffi::VAProfileH264Main),
+            100 => Ok(ffi::VAProfileH264High),
+            118 => Ok(ffi::VAProfileH264MultiviewHigh),
+            128 => Ok(ffi::VAProfileH264StereoHigh),
+            _ => Err(DecodeError::Unsupported),
+        }
+    }
+
+    fn ensure_context(&mut self, va_disp: &VADisplay, sps: &Sps) -> Result<(), DecodeError> {
+        if sps.chroma_format_idc != 1 || sps.bit_depth_luma_minus8 != 0 ||
+           sps.bit_depth_chroma_minus8 != 0 {
+            return Err(DecodeError::Unsupported);
+        }
+
+        let (profile, num_views) = match self.get_mvc_sps(sps.seq_parameter_set_id) {
+            Some(mvc) if mvc.mvc_views.len() > 1 => {
+                (VADecoderH264::get_profile(mvc)?, mvc.mvc_views.len())
+            }
+            _ => (VADecoderH264::get_profile(sps)?, 1),
+        };
+        let width = (sps.pic_width_in_mbs_minus1 + 1) * 16;
+        let height = sps.get_frame_height_in_mbs() * 16;
+        let dpb_size = sps.get_dpb_size();
+        let num_surfaces = (dpb_size + 1) * num_views + NUM_EXTRA_SURFACES;
+
+        self.num_reorder_frames = sps.get_num_reorder_frames();
+        if let Some(ref context) = self.context {
+            if context.is_compatible(profile, ffi::VA_RT_FORMAT_YUV420, width, height) &&
+               self.num_surfaces == num_surfaces {
+                return Ok(());
+            }
+        }
+
+        self.flush_dpb();
+        if let Some(context) = self.context.take() {
+            context.destroy(va_disp);
+        }
+
+        let context = VADecodeContext::new(va_disp,
+                                           profile,
+                                           ffi::VA_RT_FORMAT_YUV420,
+                                           width,
+                                           height,
+                                           num_surfaces)
+            .map_err(|_| DecodeError::VAFailed)?;
+        self.context = Some(context);
+        self.num_surfaces = num_surfaces;
+        self.dpb_size = dpb_size;
+        self.num_views = num_views;
+        self.views = vec![ViewState::default(); num_views];
+
+        Ok(())
+    }
+
+    /* Outputs the access unit with the smallest picture order count; the one being decoded
+     * is left alone unless `include_current`, as are first fields waiting for the second */
+    fn bump(&mut self, include_current: bool) -> bool {
+        let current = if self.au_started { Some(self.au) } else { None };
+        let au = self.dpb
+            .iter()
+            .filter(|pic| pic.needed_for_output && !pic.pending_second_field)
+            .filter(|pic| include_current || Some(pic.au) != current)
+            .min_by_key(|pic| (pic.get_poc(), pic.au))
+            .map(|pic| pic.au);
+        let au = match au {
+            Some(au) => au,
+            None => return false,
+        };
+
+        let mut frames: Vec<(usize, VADecodedFrame)> = Vec::new();
+        for pic in self.dpb.iter_mut().filter(|pic| pic.au == au && pic.needed_for_output) {
+            pic.needed_for_output = false;
+            frames.push((pic.voc, pic.frame.clone()));
+        }
+        frames.sort_by_key(|&(voc, _)| voc);
+        self.output.push_back(frames.into_iter().map(|(_, frame)| frame).collect());
+        self.remove_unused();
+
+        true
+    }
+
+    /* Inter-view references stay until their access unit is complete */
+    fn remove_unused(&mut self) {
+        let current = if self.au_started { Some(self.au) } else { None };
+        self.dpb.retain(|pic| {
+            pic.needed_for_output || pic.is_reference() || Some(pic.au) == current
+        });
+    }
+
+    /* First fields of the view left without their second field are output on their own */
+    fn end_unpaired_fields(&mut self, view_id: Option<u16>, keep: Option<u64>) {
+        for p in &mut self.dpb {
+            if !p.pending_second_field || view_id.map_or(false, |v| v != p.view_id) ||
+               Some(p.au) == keep {
+                continue;
+            }
+            p.pending_second_field = false;
+            let order = if p.structure == PicStructure::TopField {
+                FieldOrder::TopFieldFirst
+            } else {
+                FieldOrder::BottomFieldFirst
+            };
+            p.frame = p.frame.clone().with_field_order(order);
+            self.recovery.frame_decoded(&p.frame);
+        }
+    }
+
+    /* Frame of the view holding the first field decoded in access unit `au` */
+    fn find_first_field(&self, au: u64, view_id: u16) -> Option<usize> {
+        self.dpb.iter().position(|p| p.au == au && p.view_id == view_id && p.pending_second_field)
+    }
+
+    fn flush_dpb(&mut self) {
+        self.end_unpaired_fields(None, None);
+        while self.bump(true) {}
+        self.dpb.clear();
+    }
+
+    /* Ends the stream, the next one has to start at an IDR or a recovery point */
+    fn end_stream(&mut self, va_disp: &VADisplay) -> Result<(), DecodeError> {
+        let ret = self.end_picture(va_disp);
+        self.end_access_unit();
+        self.flush_dpb();
+        for view in &mut self.views {
+            *view = ViewState::default();
+        }
+        self.recovery_point = None;
+        self.recovery_frames = None;
+        self.recovery.restart();
+        ret
+    }
+
+    fn end_access_unit(&mut self) {
+        if !self.au_started {
+            return;
+        }
+        self.au_started = false;
+        self.au += 1;
+        self.prefix = None;
+        self.remove_unused();
+
+        loop {
+            let mut waiting: Vec<u64> = self.dpb
+                .iter()
+                .filter(|pic| pic.needed_for_output)
+                .map(|pic| pic.au)
+                .collect();
+            waiting.sort();
+            waiting.dedup();
+            /* Keyframes are output as soon as decoded, nothing is reordered among them */
+            let num_reorder_frames = if self.mode.is_keyframes_only() {
+                0
+            } else {
+                self.num_reorder_frames
+            };
+            if waiting.len() <= num_reorder_frames || !self.bump(true) {
+                break;
+            }
+        }
+    }
+
+    fn get_frame_num_wrap(&self, pic: &DpbPicture, frame_num: u32, max_frame_num: u32) -> i32 {
+        if pic.frame_num > frame_num {
+            pic.frame_num as i32 - max_frame_num as i32
+        } else {
+            pic.frame_num as i32
+        }
+    }
+
+    /* Decoding process for picture order count, clause 8.2.1 */
+    fn compute_poc(&self, sps: &Sps, hdr: &SliceHeader, nal: &NalHeader, voc: usize)
+                   -> (i32, i32, i32, i32) {
+        let view = &self.views[voc];
+        let idr = nal.is_idr();
+        let frame_num = hdr.frame_num as i32;
+
+        if sps.pic_order_cnt_type == 0 {
+            let (prev_msb, prev_lsb) = if idr {
+                (0, 0)
+            } else {
+                (view.prev_poc_msb, view.prev_poc_lsb)
+            };
+            let max_lsb = 1 << (sps.log2_max_pic_order_cnt_lsb_minus4 + 4);
+            let lsb = hdr.pic_order_cnt_lsb as i32;
+            let msb = if lsb < prev_lsb && prev_lsb - lsb >= max_lsb / 2 {
+                prev_msb + max_lsb
+            } else if lsb > prev_lsb && lsb - prev_lsb > max_lsb / 2 {
+                prev_msb - max_lsb
+            } else {
+                prev_msb
+            };
+            let top = msb + lsb;
+            return match PicStructure::from_header(hdr) {
+                PicStructure::Frame => (top, top + hdr.delta_pic_order_cnt_bottom, msb, 0),
+                _ => (top, top, msb, 0),
+            };
+        }
+
+        let frame_num_offset = if idr {
+            0
+        } else if view.prev_frame_num > hdr.frame_num {
+            view.prev_frame_num_offset + sps.get_max_frame_num() as i32
+        } else {
+            view.prev_frame_num_offset
+        };
+
+        if sps.pic_order_cnt_type == 1 {
+            let cycle_len = sps.offset_for_ref_frame.len() as i32;
+            let mut abs_frame_num = if cycle_len != 0 {
+                frame_num_offset + frame_num
+            } else {
+                0
+            };
+            if nal.nal_ref_idc == 0 && abs_frame_num > 0 {
+                abs_frame_num -= 1;
+            }
+            let mut expected_poc = 0;
+            if abs_frame_num > 0 {
+                let cycle_cnt = (abs_frame_num - 1) / cycle_len;
+                let frame_num_in_cycle = (abs_frame_num - 1) % cycle_len;
+                let expected_delta_per_cycle: i32 = sps.offset_for_ref_frame.iter().sum();
+                expected_poc = cycle_cnt * expected_delta_per_cycle +
+                               sps.offset_for_ref_frame[..frame_num_in_cycle as usize + 1]
+                    .iter()
+                    .sum::<i32>();
+            }
+            if nal.nal_ref_idc == 0 {
+                expected_poc += sps.offset_for_non_ref_pic;
+            }
+            let top = expected_poc + hdr.delta_pic_order_cnt[0];
+            let bottom = match PicStructure::from_header(hdr) {
+                PicStructure::Frame => {
+                    top + sps.offset_for_top_to_bottom_field + hdr.delta_pic_order_cnt[1]
+                }
+                PicStructure::TopField => top,
+                PicStructure::BottomField => {
+                    let bottom = expected_poc + sps.offset_for_top_to_bottom_field +
+                                 hdr.delta_pic_order_cnt[0];
+                    return (bottom, bottom, 0, frame_num_offset);
+                }
+            };
+            return (top, bottom, 0, frame_num_offset);
+        }
+
+        let poc = if idr {
+            0
+        } else if nal.nal_ref_idc == 0 {
+            2 * (frame_num_offset + frame_num) - 1
+        } else {
+            2 * (frame_num_offset + frame_num)
+        };
+        (poc, poc, 0, frame_num_offset)
+    }
+
+    /* Fills frame_num gaps with "non-existing" frames, clause 8.2.5.2; they reuse the
+     * surface of the last reference as they must never be displayed */
+    /* Returns false when the gap comes from reference pictures lost in the stream, unless
+     * they are to be concealed with the last reference */
+    fn fill_frame_num_gap(&mut self,
+                          sps: &Sps,
+                          frame_num: u32,
+                          view_id: u16,
+                          voc: usize,
+                          conceal: bool)
+                          -> bool {
+        let max_frame_num = sps.get_max_frame_num();
+        let prev_ref_frame_num = self.views[voc].prev_ref_frame_num;
+        if frame_num == prev_ref_frame_num || frame_num == (prev_ref_frame_num + 1) % max_frame_num {
+            return true;
+        }
+        if !sps.gaps_in_frame_num_value_allowed_flag && !conceal {
+            return false;
+        }
+
+        let last = self.dpb
+            .iter()
+            .filter(|pic| pic.view_id == view_id && pic.is_reference())
+            .max_by_key(|pic| pic.au)
+            .cloned();
+        let last = match last {
+            Some(last) => last,
+            None => return true,
+        };
+
+        let mut unused_frame_num = (prev_ref_frame_num + 1) % max_frame_num;
+        while unused_frame_num != frame_num {
+            self.sliding_window(sps, unused_frame_num, view_id);
+            self.dpb.push(DpbPicture {
+                structure: PicStructure::Frame,
+                frame_num: unused_frame_num,
+                reference: [Reference::ShortTerm; 2],
+                inter_view: false,
+                needed_for_output: false,
+                pending_second_field: false,
+                corrupted: last.corrupted || conceal,
+                ..last.clone()
+            });
+            self.views[voc].prev_ref_frame_num = unused_frame_num;
+            self.views[voc].prev_frame_num = unused_frame_num;
+            unused_frame_num = (unused_frame_num + 1) % max_frame_num;
+        }
+        true
+    }
+
+    fn sliding_window(&mut self, sps: &Sps, frame_num: u32, view_id: u16) {
+        let max_frame_num = sps.get_max_frame_num();
+        let max_num_ref_frames = cmp::max(sps.max_num_ref_frames, 1) as usize;
+        loop {
+            let num_refs = self.dpb
+                .iter()
+                .filter(|pic| pic.view_id == view_id && pic.is_reference())
+                .count();
+            if num_refs < max_num_ref_frames {
+                break;
+            }
+            let oldest = self.dpb
+                .iter()
+                .enumerate()
+                .filter(|&(_, pic)| {
+                    pic.view_id == view_id && pic.has_reference(Reference::ShortTerm)
+                })
+                .min_by_key(|&(_, pic)| self.get_frame_num_wrap(pic, frame_num, max_frame_num))
+                .map(|(i, _)| i);
+            match oldest {
+                Some(i) => self.dpb[i].unmark(Reference::ShortTerm),
+                None => break,
+            }
+        }
+    }
+
+    /* Short term reference with the given picture number, clause 8.2.4.1; fields of the
+     * parity of the current one have odd numbers */
+    fn find_short_term(&self, pic: &PendingPicture, pic_num: i32) -> Option<RefPic> {
+        let view_id = pic.nal.mvc.view_id;
+        let max_frame_num = pic.sps.get_max_frame_num();
+        let frame_num = pic.hdr.frame_num;
+        for (i, p) in self.dpb.iter().enumerate().filter(|&(_, p)| p.view_id == view_id) {
+            let wrap = self.get_frame_num_wrap(p, frame_num, max_frame_num);
+            if pic.structure == PicStructure::Frame {
+                if p.get_frame_reference() == Reference::ShortTerm && wrap == pic_num {
+                    return Some((i, PicStructure::Frame));
+                }
+                continue;
+            }
+            for parity in 0..2 {
+                let same = (parity == pic.structure.get_parity()) as i32;
+                if p.reference[parity] == Reference::ShortTerm && 2 * wrap + same == pic_num {
+                    return Some((i, PicStructure::from_parity(parity)));
+                }
+            }
+        }
+        None
+    }
+
+    /* Long term reference with the given long term picture number */
+    fn find_long_term(&self, pic: &PendingPicture, long_term_pic_num: u32) -> Option<RefPic> {
+        let view_id = pic.nal.mvc.view_id;
+        for (i, p) in self.dpb.iter().enumerate().filter(|&(_, p)| p.view_id == view_id) {
+            let idx = p.long_term_frame_idx;
+            if pic.structure == PicStructure::Frame {
+                if p.get_frame_reference() == Reference::LongTerm && idx == long_term_pic_num {
+                    return Some((i, PicStructure::Frame));
+                }
+                continue;
+            }
+            for parity in 0..2 {
+                let same = (parity == pic.structure.get_parity()) as u32;
+                if p.reference[parity] == Reference::LongTerm &&
+                   2 * idx + same == long_term_pic_num {
+                    return Some((i, PicStructure::from_parity(parity)));
+                }
+            }
+        }
+        None
+    }
+
+    /* Marks the references of the view after decoding `pic`, clause 8.2.5; returns the
+     * marking of the current picture along with its long term index */
+    fn mark_references(&mut self, pic: &PendingPicture) -> (Reference, u32) {
+        let view_id = pic.nal.mvc.view_id;
+        let voc = pic.voc;
+        let marking = &pic.hdr.dec_ref_pic_marking;
+        let frame_num = pic.hdr.frame_num;
+        /* Frame holding the first field when `pic` is the second one */
+        let first_field = pic.first_field.and_then(|au| self.find_first_field(au, view_id));
+
+        if pic.nal.nal_ref_idc == 0 {
+            return (Reference::None, 0);
+        }
+        if pic.nal.is_idr() {
+            if marking.long_term_reference_flag {
+                self.views[voc].max_long_term_frame_idx = Some(0);
+                return (Reference::LongTerm, 0);
+            }
+            self.views[voc].max_long_term_frame_idx = None;
+            return (Reference::ShortTerm, 0);
+        }
+
+        let operations = match marking.operations {
+            Some(ref operations) => operations,
+            None => {
+                /* The second field of a reference frame does not take another slot */
+                if !first_field.map_or(false, |i| self.dpb[i].is_reference()) {
+                    self.sliding_window(&pic.sps, frame_num, view_id);
+                }
+                return (Reference::ShortTerm, 0);
+            }
+        };
+
+        let curr_pic_num = match pic.structure {
+            PicStructure::Frame => frame_num as i32,
+            _ => 2 * frame_num as i32 + 1,
+        };
+        let mut current = (Reference::ShortTerm, 0);
+        for op in operations {
+            let pic_num_x = curr_pic_num - (op.difference_of_pic_nums_minus1 as i32 + 1);
+            match op.memory_management_control_operation {
+                1 | 3 => {
+                    let (i, structure) = match self.find_short_term(pic, pic_num_x) {
+                        Some(found) => found,
+                        None => continue,
+                    };
+                    if op.memory_management_control_operation == 1 {
+                        self.dpb[i].set_reference(structure, Reference::None);
+                    } else {
+                        for (j, p) in self.dpb.iter_mut().enumerate() {
+                            if j != i && p.view_id == view_id &&
+                               p.has_reference(Reference::LongTerm) &&
+                               p.long_term_frame_idx == op.long_term_frame_idx {
+                                p.unmark(Reference::LongTerm);
+                            }
+                        }
+                        self.dpb[i].set_reference(structure, Reference::LongTerm);
+                        self.dpb[i].long_term_frame_idx = op.long_term_frame_idx;
+                    }
+                }
+                2 => {
+                    if let Some((i, structure)) = self.find_long_term(pic, op.long_term_pic_num) {
+                        self.dpb[i].set_reference(structure, Reference::None);
+                    }
+                }
+                4 => {
+                    let max_idx = op.max_long_term_frame_idx_plus1.checked_sub(1);
+                    for p in self.dpb.iter_mut() {
+                        if p.view_id == view_id && p.has_reference(Reference::LongTerm) &&
+                           max_idx.map_or(true, |max| p.long_term_frame_idx > max) {
+                            p.unmark(Reference::LongTerm);
+                        }
+                    }
+                    self.views[voc].max_long_term_frame_idx = max_idx;
+                }
+                5 => {
+                    for p in self.dpb.iter_mut() {
+                        if p.view_id == view_id {
+                            p.reference = [Reference::None; 2];
+                        }
+                    }
+                    self.views[voc].max_long_term_frame_idx = None;
+                }
+                6 => {
+                    for (j, p) in self.dpb.iter_mut().enumerate() {
+                        if Some(j) != first_field && p.view_id == view_id &&
+                           p.has_reference(Reference::LongTerm) &&
+                           p.long_term_frame_idx == op.long_term_frame_idx {
+                            p.unmark(Reference::LongTerm);
+                        }
+                    }
+                    current = (Reference::LongTerm, op.long_term_frame_idx);
+                }
+                _ => {}
+            }
+        }
+
+        current
+    }
+
+    /* Inter-view references of the current access unit for list 0 or 1, clause H.8.2.1 */
+    fn get_inter_view_refs(&self, pic: &PendingPicture, list: usize) -> Vec<usize> {
+        let view = match pic.get_view() {
+            Some(view) if pic.voc > 0 => view,
+            _ => return Vec::new(),
+        };
+        let view_ids = if pic.nal.mvc.anchor_pic_flag {
+            &view.anchor_refs[list]
+        } else {
+            &view.non_anchor_refs[list]
+        };
+        view_ids.iter()
+            .filter_map(|&view_id| {
+                self.dpb.iter().position(|p| {
+                    p.au == self.au && p.view_id == view_id && p.inter_view
+                })
+            })
+            .collect()
+    }
+
+    /* Fields of `frames` alternating in parity from the one of the current field,
+     * clause 8.2.4.2.5 */
+    fn alternate_fields(&self,
+                        frames: &[usize],
+                        reference: Reference,
+                        parity: usize)
+                        -> Vec<RefPic> {
+        let fields_of = |parity: usize| -> Vec<RefPic> {
+            frames.iter()
+                .filter(|&&i| self.dpb[i].reference[parity] == reference)
+                .map(|&i| (i, PicStructure::from_parity(parity)))
+                .collect()
+        };
+        let same = fields_of(parity);
+        let opposite = fields_of(1 - parity);
+
+        let mut fields = Vec::with_capacity(same.len() + opposite.len());
+        for i in 0..cmp::max(same.len(), opposite.len()) {
+            fields.extend(same.get(i));
+            fields.extend(opposite.get(i));
+        }
+        fields
+    }
+
+    /* Initial reference picture lists, clauses 8.2.4.2.1 to 8.2.4.2.4 */
+    fn init_ref_pic_lists(&self, pic: &PendingPicture) -> [Vec<RefPic>; 2] {
+        let view_id = pic.nal.mvc.view_id;
+        let max_frame_num = pic.sps.get_max_frame_num();
+        let frame_num = pic.hdr.frame_num;
+        let field = pic.structure != PicStructure::Frame;
+        /* Fields are taken from every frame with at least one of them marked */
+        let refs_of = |reference: Reference| -> Vec<usize> {
+            self.dpb
+                .iter()
+                .enumerate()
+                .filter(|&(_, p)| {
+                    p.view_id == view_id &&
+                    if field {
+                        p.has_reference(reference)
+                    } else {
+                        p.get_frame_reference() == reference
+                    }
+                })
+                .map(|(i, _)| i)
+                .collect()
+        };
+        let to_list = |frames: &[usize], reference: Reference| -> Vec<RefPic> {
+            if field {
+                self.alternate_fields(frames, reference, pic.structure.get_parity())
+            } else {
+                frames.iter().map(|&i| (i, PicStructure::Frame)).collect()
+            }
+        };
+        let mut short_term = refs_of(Reference::ShortTerm);
+        let mut long_term = refs_of(Reference::LongTerm);
+        long_term.sort_by_key(|&i| self.dpb[i].long_term_frame_idx);
+        let long_term = to_list(&long_term, Reference::LongTerm);
+
+        let mut lists = [Vec::new(), Vec::new()];
+        if pic.hdr.slice_type != SLICE_TYPE_B {
+            short_term.sort_by_key(|&i| {
+                -self.get_frame_num_wrap(&self.dpb[i], frame_num, max_frame_num)
+            });
+            lists[0] = to_list(&short_term, Reference::ShortTerm);
+            lists[0].extend_from_slice(&long_term);
+        } else {
+            /* A field pair counts as a frame while decoding fields, before the current one
+             * when one of its fields is */
+            let poc = pic.get_poc();
+            let ref_poc = |i: usize| self.dpb[i].get_ref_poc(Reference::ShortTerm);
+            let mut before: Vec<usize> = short_term.iter()
+                .cloned()
+                .filter(|&i| ref_poc(i) < poc || (field && ref_poc(i) == poc))
+                .collect();
+            let mut after: Vec<usize> = short_term.iter()
+                .cloned()
+                .filter(|&i| !before.contains(&i))
+                .collect();
+            before.sort_by_key(|&i| -ref_poc(i));
+            after.sort_by_key(|&i| ref_poc(i));
+
+            let mut frames = before.clone();
+            frames.extend_from_slice(&after);
+            lists[0] = to_list(&frames, Reference::ShortTerm);
+            lists[0].extend_from_slice(&long_term);
+            let mut frames = after;
+            frames.extend_from_slice(&before);
+            lists[1] = to_list(&frames, Reference::ShortTerm);
+            lists[1].extend_from_slice(&long_term);
+            if lists[1].len() > 1 && lists[0] == lists[1] {
+                lists[1].swap(0, 1);
+            }
+        }
+
+        lists
+    }
+
+    /* Modification process for reference picture lists, clauses 8.2.4.3 and H.8.2.2 */
+    fn modify_ref_pic_list(&self,
+                           pic: &PendingPicture,
+                           init: Vec<RefPic>,
+                           list: usize,
+                           num_ref_idx_active: usize)
+                           -> Vec<Option<RefPic>> {
+        let (max_pic_num, curr_pic_num) = match pic.structure {
+            PicStructure::Frame => (pic.sps.get_max_frame_num() as i32, pic.hdr.frame_num as i32),
+            _ => (2 * pic.sps.get_max_frame_num() as i32, 2 * pic.hdr.frame_num as i32 + 1),
+        };
+        let inter_view: Vec<RefPic> = self.get_inter_view_refs(pic, list)
+            .into_iter()
+            .map(|i| (i, pic.structure))
+            .collect();
+
+        let mut refs: Vec<Option<RefPic>> = init.into_iter().map(Some).collect();
+        refs.extend(inter_view.iter().map(|&r| Some(r)));
+        refs.resize(num_ref_idx_active + 1, None);
+
+        let mut pic_num_pred = curr_pic_num;
+        let mut pic_view_idx_pred = -1;
+        let mut ref_idx = 0;
+        for m in &pic.hdr.ref_pic_list_modification[list] {
+            let target = match m.modification_of_pic_nums_idc {
+                0 | 1 => {
+                    let abs_diff_pic_num = m.value as i32 + 1;
+                    let mut pic_num_no_wrap = if m.modification_of_pic_nums_idc == 0 {
+                        pic_num_pred - abs_diff_pic_num
+                    } else {
+                        pic_num_pred + abs_diff_pic_num
+                    };
+                    if pic_num_no_wrap < 0 {
+                        pic_num_no_wrap += max_pic_num;
+                    } else if pic_num_no_wrap >= max_pic_num {
+                        pic_num_no_wrap -= max_pic_num;
+                    }
+                    pic_num_pred = pic_num_no_wrap;
+                    let pic_num = if pic_num_no_wrap > curr_pic_num {
+                        pic_num_no_wrap - max_pic_num
+                    } else {
+                        pic_num_no_wrap
+                    };
+                    self.find_short_term(pic, pic_num)
+                }
+                2 => self.find_long_term(pic, m.value),
+                _ => {
+                    let num_refs = inter_view.len() as i32;
+                    if num_refs == 0 {
+                        continue;
+                    }
+                    let abs_diff_view_idx = m.value as i32 + 1;
+                    let mut pic_view_idx = if m.modification_of_pic_nums_idc == 4 {
+                        pic_view_idx_pred - abs_diff_view_idx
+                    } else {
+                        pic_view_idx_pred + abs_diff_view_idx
+                    };
+                    if pic_view_idx < 0 {
+                        pic_view_idx += num_refs;
+                    } else if pic_view_idx >= num_refs {
+                        pic_view_idx -= num_refs;
+                    }
+                    pic_view_idx_pred = pic_view_idx;
+                    inter_view.get(pic_view_idx as usize).cloned()
+                }
+            };
+
+            /* Missing references are left to the error concealment of the driver */
+            let target = match target {
+                Some(target) => target,
+                None => continue,
+            };
+            refs.insert(ref_idx, Some(target));
+            ref_idx += 1;
+            if let Some(pos) = refs[ref_idx..].iter().position(|&r| r == Some(target)) {
+                refs.remove(ref_idx + pos);
+            }
+            refs.truncate(num_ref_idx_active + 1);
+        }
+
+        refs.truncate(num_ref_idx_active);
+        refs
+    }
+
+    fn build_slice_param(&self,
+                         pic: &PendingPicture,
+                         hdr: &SliceHeader,
+                         size: usize)
+                         -> ffi::VASliceParameterBufferH264 {
+        let mut param: ffi::VASliceParameterBufferH264 = zeroed();
+        param.slice_data_size = size as u32;
+        param.slice_data_offset = 0;
+        param.slice_data_flag = ffi::VA_SLICE_DATA_FLAG_ALL;
+        param.slice_data_bit_offset = (pic.nal.header_bytes * 8 + hdr.header_size) as u16;
+        param.first_mb_in_slice = hdr.first_mb_in_slice as u16;
+        param.slice_type = hdr.slice_type;
+        param.direct_spatial_mv_pred_flag = hdr.direct_spatial_mv_pred_flag as u8;
+        param.cabac_init_idc = hdr.cabac_init_idc;
+        param.slice_qp_delta = hdr.slice_qp_delta as c_char;
+        param.disable_deblocking_filter_idc = hdr.disable_deblocking_filter_idc;
+        param.slice_alpha_c0_offset_div2 = hdr.slice_alpha_c0_offset_div2 as c_char;
+        param.slice_beta_offset_div2 = hdr.slice_beta_offset_div2 as c_char;
+
+        for i in 0..32 {
+            param.RefPicList0[i] = invalid_va_picture();
+            param.RefPicList1[i] = invalid_va_picture();
+        }
+
+        let num_lists = match hdr.slice_type {
+            SLICE_TYPE_B => 2,
+            SLICE_TYPE_P | SLICE_TYPE_SP => 1,
+            _ => 0,
+        };
+        let slice_pic = PendingPicture { hdr: hdr.clone(), ..pic.shallow_clone() };
+        let init = self.init_ref_pic_lists(&slice_pic);
+        let num_ref_idx_active = [hdr.num_ref_idx_l0_active_minus1 as usize + 1,
+                                  hdr.num_ref_idx_l1_active_minus1 as usize + 1];
+        for (list, init) in init.iter().enumerate().take(num_lists) {
+            let refs = self.modify_ref_pic_list(&slice_pic,
+                                                init.clone(),
+                                                list,
+                                                num_ref_idx_active[list]);
+            let va_list = if list == 0 {
+                &mut param.RefPicList0
+            } else {
+                &mut param.RefPicList1
+            };
+            for (i, r) in refs.iter().enumerate() {
+                if let Some((idx, structure)) = *r {
+                    va_list[i] = self.dpb[idx].to_va(structure);
+                    /* Inter-view references are not used for temporal prediction */
+                    if self.dpb[idx].view_id != pic.nal.mvc.view_id {
+                        va_list[i].flags &= ffi::VA_PICTURE_H264_TOP_FIELD |
+                                            ffi::VA_PICTURE_H264_BOTTOM_FIELD;
+                    }
+                }
+            }
+        }
+        if num_lists > 0 {
+            param.num_ref_idx_l0_active_minus1 = hdr.num_ref_idx_l0_active_minus1 as u8;
+        }
+        if num_lists > 1 {
+            param.num_ref_idx_l1_active_minus1 = hdr.num_ref_idx_l1_active_minus1 as u8;
+        }
+
+        if let Some(ref table) = hdr.pred_weight_table {
+            param.luma_log2_weight_denom = table.luma_log2_weight_denom;
+            param.chroma_log2_weight_denom = table.chroma_log2_weight_denom;
+            param.luma_weight_l0_flag = table.luma_weight_flag[0] as u8;
+            param.luma_weight_l0 = table.luma_weight[0];
+            param.luma_offset_l0 = table.luma_offset[0];
+            param.chroma_weight_l0_flag = table.chroma_weight_flag[0] as u8;
+            param.chroma_weight_l0 = table.chroma_weight[0];
+            param.chroma_offset_l0 = table.chroma_offset[0];
+            param.luma_weight_l1_flag = table.luma_weight_flag[1] as u8;
+            param.luma_weight_l1 = table.luma_weight[1];
+            param.luma_offset_l1 = table.luma_offset[1];
+            param.chroma_weight_l1_flag = table.chroma_weight_flag[1] as u8;
+            param.chroma_weight_l1 = table.chroma_weight[1];
+            param.chroma_offset_l1 = table.chroma_offset[1];
+        }
+
+        param
+    }
+
+    fn build_picture_param(&self, pic: &PendingPicture)
+                           -> Result<ffi::VAPictureParameterBufferH264, ()> {
+        let sps = &pic.sps;
+        let pps = &pic.pps;
+        let view_id = pic.nal.mvc.view_id;
+
+        let mut param: ffi::VAPictureParameterBufferH264 = zeroed();
+        let marking = &pic.hdr.dec_ref_pic_marking;
+        let long_term = (pic.nal.is_idr() && marking.long_term_reference_flag) ||
+                        marking.has_operation(6);
+        let flags = match pic.nal.nal_ref_idc {
+            0 => 0,
+            _ if long_term => ffi::VA_PICTURE_H264_LONG_TERM_REFERENCE,
+            _ => ffi::VA_PICTURE_H264_SHORT_TERM_REFERENCE,
+        };
+        let (flags, top_poc, bottom_poc) = match pic.structure {
+            PicStructure::Frame => (flags, pic.top_poc, pic.bottom_poc),
+            PicStructure::TopField => (flags | ffi::VA_PICTURE_H264_TOP_FIELD, pic.top_poc, 0),
+            PicStructure::BottomField => {
+                (flags | ffi::VA_PICTURE_H264_BOTTOM_FIELD, 0, pic.bottom_poc)
+            }
+        };
+        param.CurrPic = build_va_picture(pic.frame.get_surface().get_id(),
+                                         pic.hdr.frame_num,
+                                         flags,
+                                         top_poc,
+                                         bottom_poc);
+
+        /* The first field of the current frame is among them when decoding the second */
+        let mut refs: Vec<usize> = self.dpb
+            .iter()
+            .enumerate()
+            .filter(|&(_, p)| p.view_id == view_id && p.is_reference())
+            .map(|(i, _)| i)
+            .collect();
+        for list in 0..2 {
+            for i in self.get_inter_view_refs(pic, list) {
+                if !refs.contains(&i) {
+                    refs.push(i);
+                }
+            }
+        }
+        for i in 0..16 {
+            param.ReferenceFrames[i] = match refs.get(i) {
+                Some(&idx) => self.dpb[idx].to_va(self.dpb[idx].get_ref_structure()),
+                None => invalid_va_picture(),
+            };
+        }
+
+        param.picture_width_in_mbs_minus1 = sps.pic_width_in_mbs_minus1 as u16;
+        param.picture_height_in_mbs_minus1 = (sps.get_frame_height_in_mbs() - 1) as u16;
+        param.bit_depth_luma_minus8 = sps.bit_depth_luma_minus8;
+        param.bit_depth_chroma_minus8 = sps.bit_depth_chroma_minus8;
+        param.num_ref_frames = sps.max_num_ref_frames as u8;
+        param.num_slice_groups_minus1 = pps.num_slice_groups_minus1 as u8;
+        param.slice_group_map_type = pps.slice_group_map_type as u8;
+        param.slice_group_change_rate_minus1 = pps.slice_group_change_rate_minus1 as u16;
+        param.pic_init_qp_minus26 = pps.pic_init_qp_minus26 as c_char;
+        param.pic_init_qs_minus26 = pps.pic_init_qs_minus26 as c_char;
+        param.chroma_qp_index_offset = pps.chroma_qp_index_offset as c_char;
+        param.second_chroma_qp_index_offset = pps.second_chroma_qp_index_offset as c_char;
+        param.frame_num = pic.hdr.frame_num as u16;
+
+        param.seq_fields = H264SeqFields {
+            chroma_format_idc: sps.chroma_format_idc as u32,
+            residual_colour_transform_flag: sps.separate_colour_plane_flag as u32,
+            gaps_in_frame_num_value_allowed_flag: sps.gaps_in_frame_num_value_allowed_flag as u32,
+            frame_mbs_only_flag: sps.frame_mbs_only_flag as u32,
+            mb_adaptive_frame_field_flag: sps.mb_adaptive_frame_field_flag as u32,
+            direct_8x8_inference_flag: sps.direct_8x8_inference_flag as u32,
+            /* Table A-1, bi-prediction of blocks smaller than 8x8 ends at level 3.1 */
+            MinLumaBiPredSize8x8: (sps.level_idc >= 31) as u32,
+            log2_max_frame_num_minus4: sps.log2_max_frame_num_minus4,
+            pic_order_cnt_type: sps.pic_order_cnt_type,
+            log2_max_pic_order_cnt_lsb_minus4: sps.log2_max_pic_order_cnt_lsb_minus4,
+            delta_pic_order_always_zero_flag: sps.delta_pic_order_always_zero_flag as u32,
+        }.to_va()?;
+
+        param.pic_fields = H264PicFields {
+            entropy_coding_mode_flag: pps.entropy_coding_mode_flag as u32,
+            weighted_pred_flag: pps.weighted_pred_flag as u32,
+            weighted_bipred_idc: pps.weighted_bipred_idc as u32,
+            transform_8x8_mode_flag: pps.transform_8x8_mode_flag as u32,
+            field_pic_flag: pic.hdr.field_pic_flag as u32,
+            constrained_intra_pred_flag: pps.constrained_intra_pred_flag as u32,
+            pic_order_present_flag: pps.bottom_field_pic_order_in_frame_present_flag as u32,
+            deblocking_filter_control_present_flag:
+                pps.deblocking_filter_control_present_flag as u32,
+            redundant_pic_cnt_present_flag: pps.redundant_pic_cnt_present_flag as u32,
+            reference_pic_flag: (pic.nal.nal_ref_idc != 0) as u32,
+        }.to_va()?;
+
+        Ok(param)
+    }
+
+    fn start_picture(&mut self,
+                     va_disp: &VADisplay,
+                     hdr: SliceHeader,
+                     nal: NalHeader,
+                     sps: Sps,
+                     pps: Pps,
+                     pts: u64)
+                     -> Result<(), DecodeError> {
+        let voc = sps.mvc_views
+            .iter()
+            .position(|view| view.view_id == nal.mvc.view_id)
+            .unwrap_or(0);
+        if pps.num_slice_groups_minus1 > 0 {
+            return Err(DecodeError::Unsupported);
+        }
+
+        if voc == 0 {
+            self.ensure_context(va_disp, &sps)?;
+        } else if self.context.is_none() || voc >= self.num_views {
+            return Ok(());
+        }
+        self.au_started = true;
+
+        let recovery_point = if voc == 0 { self.recovery_point.take() } else { None };
+        let mut corrupted = self.lost_slices.take().is_some();
+        let mut dropped = false;
+        let view_id = nal.mvc.view_id;
+
+        /* A field following the one of opposite parity with the same frame_num completes
+         * its frame, clause 7.4.1.2.4 */
+        let structure = PicStructure::from_header(&hdr);
+        let first_field = match structure {
+            PicStructure::Frame => None,
+            _ if nal.is_idr() => None,
+            _ => {
+                self.dpb
+                    .iter()
+                    .find(|p| {
+                        p.view_id == view_id && p.pending_second_field &&
+                        p.frame_num == hdr.frame_num && p.structure != structure
+                    })
+                    .map(|p| p.au)
+            }
+        };
+        self.end_unpaired_fields(Some(view_id), first_field);
+
+        /* The second field of a decoded frame is never left out */
+        let intra = hdr.slice_type == SLICE_TYPE_I || hdr.slice_type == SLICE_TYPE_SI;
+        let skipped = first_field.is_none() &&
+                      self.mode.skips(nal.is_idr() || intra, nal.nal_ref_idc != 0);
+
+        if nal.is_idr() {
+            if voc == 0 {
+                if hdr.dec_ref_pic_marking.no_output_of_prior_pics_flag {
+                    let au = self.au;
+                    self.dpb.retain(|p| p.au == au);
+                } else {
+                    while self.bump(false) {}
+                }
+            }
+            for p in self.dpb.iter_mut().filter(|p| p.view_id == view_id) {
+                p.reference = [Reference::None; 2];
+            }
+            self.remove_unused();
+            self.views[voc] = ViewState::default();
+            if voc == 0 {
+                self.recovery.recover();
+                self.recovery_frames = None;
+            }
+        } else if skipped {
+            dropped = true;
+        } else {
+            /* Decoding restarts at a recovery point, references lost before it are replaced
+             * by the last one left */
+            if recovery_point.is_some() && self.recovery.is_waiting() {
+                self.recovery.recover();
+                self.recovery_frames = recovery_point;
+            }
+            /* Only intra pictures are left when decoding keyframes, the frame_num gaps of
+             * the pictures left out are not filled either */
+            let keyframes_only = self.mode.is_keyframes_only();
+            if keyframes_only {
+                self.recovery.recover();
+            }
+            let conceal = recovery_point.is_some() || (voc > 0 && self.recovery_frames.is_some());
+            if self.recovery.is_waiting() {
+                dropped = true;
+            } else if !keyframes_only &&
+                      !self.fill_frame_num_gap(&sps, hdr.frame_num, view_id, voc, conceal) {
+                self.recovery.lost_reference();
+                dropped = true;
+            }
+        }
+
+        if !dropped && voc == 0 {
+            match self.recovery_frames {
+                Some(0) => {
+                    for p in &mut self.dpb {
+                        p.corrupted = false;
+                    }
+                    self.recovery_frames = None;
+                }
+                Some(n) => {
+                    corrupted = true;
+                    self.recovery_frames = Some(n - 1);
+                }
+                None => {}
+            }
+        }
+        if !dropped && !nal.is_idr() && !intra {
+            corrupted |= self.dpb
+                .iter()
+                .any(|p| p.is_reference() && p.corrupted);
+        }
+
+        let (top_poc, bottom_poc, poc_msb, frame_num_offset) =
+            self.compute_poc(&sps, &hdr, &nal, voc);
+
+        /* Both fields of a frame are decoded into the same surface */
+        let frame = match first_field.and_then(|au| self.find_first_field(au, view_id)) {
+            Some(i) => self.dpb[i].frame.clone(),
+            None => {
+                let surface = match self.context {
+                    Some(ref context) => context.get_surface(),
+                    None => None,
+                };
+                let surface = surface.ok_or(DecodeError::NoSurface)?;
+                let (width, height) = sps.get_size();
+                VADecodedFrame::new(surface, width, height, pts).with_view_id(view_id)
+            }
+        };
+
+        self.picture = Some(PendingPicture {
+            frame: frame,
+            sps: sps,
+            pps: pps,
+            hdr: hdr,
+            nal: nal,
+            voc: voc,
+            top_poc: top_poc,
+            bottom_poc: bottom_poc,
+            poc_msb: poc_msb,
+            frame_num_offset: frame_num_offset,
+            structure: structure,
+            first_field: first_field,
+            slices: Vec::new(),
+            corrupted: corrupted,
+            dropped: dropped,
+            skipped: skipped,
+        });
+
+        Ok(())
+    }
+
+    fn end_picture(&mut self, va_disp: &VADisplay) -> Result<(), DecodeError> {
+        let mut pic = match self.picture.take() {
+            Some(pic) => pic,
+            None => return Ok(()),
+        };
+        if pic.skipped {
+            self.recovery.skip_frame();
+            return Ok(());
+        }
+        if pic.dropped {
+            self.recovery.drop_frame();
+            return Ok(());
+        }
+        if pic.slices.is_empty() {
+            /* Every slice of the picture was broken */
+            if pic.corrupted {
+                self.lose_picture(pic.nal.nal_ref_idc);
+            }
+            return Ok(());
+        }
+
+        let pic_param = self.build_picture_param(&pic)?;
+        let iq_matrix = build_iq_matrix(&pic.pps.scaling_lists);
+        {
+            let context = self.context.as_ref().ok_or(DecodeError::InvalidStream)?;
+            let mut submission = PictureSubmission::new(va_disp, context)
+                .add_param(&pic_param)
+                .and_then(|s| s.add_param(&iq_matrix));
+            for &(ref slice_param, ref data) in &pic.slices {
+                submission = submission.and_then(|s| s.add_slice(slice_param, data));
+            }
+            submission.and_then(|s| s.submit(pic.frame.get_surface()))
+                .map_err(|_| DecodeError::VAFailed)?;
+        }
+
+        if pic.corrupted {
+            pic.frame.mark_corrupted();
+        }
+
+        let picture_type = pic.slices
+            .iter()
+            .map(|&(ref slice_param, _)| match slice_param.slice_type {
+                SLICE_TYPE_I | SLICE_TYPE_SI => PictureType::I,
+                SLICE_TYPE_B => PictureType::B,
+                _ => PictureType::P,
+            })
+            .fold(PictureType::I, PictureType::merge);
+        let mut info = VAFrameInfo {
+            picture_type: Some(picture_type),
+            bytes: pic.slices.iter().map(|&(_, ref data)| data.len()).sum(),
+            poc: Some(pic.get_poc()),
+            frame_num: Some(pic.hdr.frame_num),
+            num_references: match picture_type {
+                PictureType::I => 0,
+                _ => {
+                    pic_param.ReferenceFrames
+                        .iter()
+                        .filter(|r| r.picture_id != ffi::VA_INVALID_SURFACE)
+                        .count()
+                }
+            },
+            ..VAFrameInfo::default()
+        };
+        for &(ref slice_param, _) in &pic.slices {
+            info.add_qp(26 + pic.pps.pic_init_qp_minus26 + slice_param.slice_qp_delta as i32);
+        }
+        if pic.first_field.is_some() {
+            info = info.merge_field(pic.frame.get_info());
+        }
+        pic.frame = pic.frame.with_info(info);
+
+        let (reference, long_term_frame_idx) = self.mark_references(&pic);
+        let mmco5 = pic.hdr.dec_ref_pic_marking.has_operation(5);
+        let mut frame_num = pic.hdr.frame_num;
+        let (mut top_poc, mut bottom_poc) = (pic.top_poc, pic.bottom_poc);
+        if mmco5 {
+            let temp_poc = cmp::min(top_poc, bottom_poc);
+            top_poc -= temp_poc;
+            bottom_poc -= temp_poc;
+            frame_num = 0;
+            if pic.voc == 0 {
+                while self.bump(false) {}
+            }
+        }
+
+        {
+            let view = &mut self.views[pic.voc];
+            view.prev_frame_num = frame_num;
+            view.prev_frame_num_offset = if mmco5 { 0 } else { pic.frame_num_offset };
+            if pic.nal.nal_ref_idc != 0 {
+                view.prev_ref_frame_num = frame_num;
+                view.prev_poc_msb = if mmco5 { 0 } else { pic.poc_msb };
+                view.prev_poc_lsb = if mmco5 && pic.structure == PicStructure::BottomField {
+                    0
+                } else if mmco5 {
+                    top_poc
+                } else {
+                    pic.hdr.pic_order_cnt_lsb as i32
+                };
+            }
+        }
+
+        let view_id = pic.nal.mvc.view_id;
+        if let Some(i) = pic.first_field.and_then(|au| self.find_first_field(au, view_id)) {
+            let first = &mut self.dpb[i];
+            let parity = pic.structure.get_parity();
+            first.reference[parity] = reference;
+            if reference == Reference::LongTerm {
+                first.long_term_frame_idx = long_term_frame_idx;
+            }
+            if parity == 0 {
+                first.top_poc = top_poc;
+            } else {
+                first.bottom_poc = bottom_poc;
+            }
+            /* Fields with the same order count are displayed in decoding order */
+            let order = match get_field_order(&pic.sps, first.top_poc, first.bottom_poc) {
+                FieldOrder::Progressive if first.structure == PicStructure::TopField => {
+                    FieldOrder::TopFieldFirst
+                }
+                FieldOrder::Progressive => FieldOrder::BottomFieldFirst,
+                order => order,
+            };
+            first.structure = PicStructure::Frame;
+            first.pending_second_field = false;
+            first.corrupted |= pic.corrupted;
+            first.frame = pic.frame.with_field_order(order);
+            self.recovery.frame_decoded(&first.frame);
+            return Ok(());
+        }
+
+        let field = pic.structure != PicStructure::Frame;
+        let mut frame = pic.frame;
+        if !field {
+            frame = frame.with_field_order(get_field_order(&pic.sps, top_poc, bottom_poc));
+            self.recovery.frame_decoded(&frame);
+        }
+        let mut references = [Reference::None; 2];
+        match pic.structure {
+            PicStructure::Frame => references = [reference; 2],
+            field => references[field.get_parity()] = reference,
+        }
+
+        /* Makes room for the new picture, clause C.4.5.3 */
+        self.remove_unused();
+        while self.dpb.len() >= self.dpb_size * self.num_views {
+            if !self.bump(false) {
+                break;
+            }
+        }
+        self.dpb.push(DpbPicture {
+            frame: frame,
+            view_id: view_id,
+            voc: pic.voc,
+            au: self.au,
+            structure: pic.structure,
+            frame_num: frame_num,
+            top_poc: top_poc,
+            bottom_poc: bottom_poc,
+            reference: references,
+            long_term_frame_idx: long_term_frame_idx,
+            inter_view: pic.nal.mvc.inter_view_flag,
+            needed_for_output: true,
+            pending_second_field: field,
+            corrupted: pic.corrupted,
+        });
+
+        Ok(())
+    }
+
+    fn decode_slice(&mut self,
+                    va_disp: &VADisplay,
+                    data: &[u8],
+                    nal: NalHeader,
+                    pts: u64)
+                    -> Result<(), DecodeError> {
+        let rbsp = remove_emulation_prevention(&data[nal.header_bytes..]);
+        let hdr = {
+            let sps_list = if nal.nal_unit_type == NAL_SLICE_EXTENSION {
+                &self.subset_sps
+            } else {
+                &self.sps
+            };
+            SliceHeader::parse(&rbsp, &nal, sps_list, &self.pps)?
+        };
+
+        let pps = self.pps[hdr.pic_parameter_set_id as usize].clone().ok_or(DecodeError::InvalidStream)?;
+        let (sps, voc) = if nal.nal_unit_type == NAL_SLICE_EXTENSION {
+            let sps = self.subset_sps[pps.seq_parameter_set_id as usize]
+                .clone()
+                .ok_or(DecodeError::InvalidStream)?;
+            match sps.mvc_views.iter().position(|view| view.view_id == nal.mvc.view_id) {
+                Some(voc) if voc > 0 => (sps, voc),
+                /* View outside of the ones the subset SPS describes */
+                _ => return Ok(()),
+            }
+        } else {
+            let sps = self.sps[pps.seq_parameter_set_id as usize]
+                .clone()
+                .ok_or(DecodeError::InvalidStream)?;
+            /* The base view takes the view dependencies from the subset SPS */
+            let mvc_views = self.get_mvc_sps(sps.seq_parameter_set_id)
+                .map(|mvc| mvc.mvc_views.clone())
+                .unwrap_or_default();
+            (Sps { mvc_views: mvc_views, ..sps }, 0)
+        };
+
+        let new_picture = match self.picture {
+            Some(ref pic) => pic.is_first_slice_of_next(&hdr, &nal),
+            None => true,
+        };
+        if new_picture {
+            self.end_picture(va_disp)?;
+            if voc == 0 {
+                self.end_access_unit();
+            }
+            self.start_picture(va_disp, hdr.clone(), nal, sps, pps, pts)?;
+        }
+
+        /* Redundant slices only help decoders that lost the primary ones */
+        if hdr.redundant_pic_cnt > 0 {
+            return Ok(());
+        }
+
+        let slice_param = match self.picture {
+            Some(ref pic) if !pic.dropped => self.build_slice_param(pic, &hdr, data.len()),
+            _ => return Ok(()),
+        };
+        if let Some(ref mut pic) = self.picture {
+            pic.slices.push((slice_param, data.to_vec()));
+        }
+
+        Ok(())
+    }
+
+    /* A picture that cannot be decoded at all, a reference one breaks the pictures
+     * predicted from it until the next recovery point */
+    fn lose_picture(&mut self, nal_ref_idc: u8) {
+        self.recovery.drop_frame();
+        if nal_ref_idc != 0 {
+            self.recovery.lost_reference();
+        }
+    }
+
+    /* A broken slice damages the picture being decoded, or the next one when it was the
+     * first slice of its picture */
+    fn skip_slice(&mut self, nal: &NalHeader) {
+        self.recovery.skip_slice();
+        match self.picture {
+            Some(ref mut pic) => pic.corrupted = true,
+            None => {
+                let nal_ref_idc = cmp::max(self.lost_slices.unwrap_or(0), nal.nal_ref_idc);
+                self.lost_slices = Some(nal_ref_idc);
+            }
+        }
+    }
+
+    fn decode_nal(&mut self, va_disp: &VADisplay, data: &[u8], pts: u64) -> Result<(), DecodeError> {
+        let mut nal = NalHeader::parse(data)?;
+
+        match nal.nal_unit_type {
+            NAL_SLICE | NAL_SLICE_IDR => {
+                if let Some(prefix) = self.prefix {
+                    nal.mvc = prefix;
+                    nal.mvc.non_idr_flag = nal.nal_unit_type != NAL_SLICE_IDR;
+                }
+                match self.decode_slice(va_disp, data, nal, pts) {
+                    Err(DecodeError::InvalidStream) => self.skip_slice(&nal),
+                    ret => ret?,
+                }
+            }
+            /* SVC is not supported */
+            NAL_SLICE_EXTENSION if !nal.svc_extension_flag => {
+                match self.decode_slice(va_disp, data, nal, pts) {
+                    Err(DecodeError::InvalidStream) => self.skip_slice(&nal),
+                    ret => ret?,
+                }
+            }
+            NAL_PREFIX if !nal.svc_extension_flag => self.prefix = Some(nal.mvc),
+            NAL_SEI => {
+                let rbsp = remove_emulation_prevention(&data[1..]);
+                /* Only the recovery point is used, which does not depend on the SPS */
+                for message in parse_sei(&rbsp, None)? {
+                    if let SeiMessage::RecoveryPoint(rp) = message {
+                        self.recovery_point = Some(rp.recovery_frame_cnt);
+                    }
+                }
+            }
+            NAL_SPS | NAL_SUBSET_SPS => {
+                let rbsp = remove_emulation_prevention(&data[1..]);
+                if nal.nal_unit_type == NAL_SPS {
+                    let sps = Sps::parse(&rbsp)?;
+                    let id = sps.seq_parameter_set_id as usize;
+                    self.sps[id] = Some(sps);
+                } else {
+                    let sps = Sps::parse_subset(&rbsp)?;
+                    let id = sps.seq_parameter_set_id as usize;
+                    self.subset_sps[id] = Some(sps);
+                }
+            }
+            NAL_PPS => {
+                let rbsp = remove_emulation_prevention(&data[1..]);
+                /* The PPS of non-base views refers to a subset SPS */
+                let pps = Pps::parse(&rbsp, &self.sps)
+                    .or_else(|_| Pps::parse(&rbsp, &self.subset_sps))?;
+                let id = pps.pic_parameter_set_id as usize;
+                self.pps[id] = Some(pps);
+            }
+            NAL_AU_DELIMITER => {
+                self.end_picture(va_disp)?;
+                self.end_access_unit();
+            }
+            /* The next picture is an IDR, which releases the references */
+            NAL_END_OF_SEQUENCE => {
+                self.end_picture(va_disp)?;
+                self.end_access_unit();
+                while self.bump(true) {}
+            }
+            NAL_END_OF_STREAM => self.end_stream(va_disp)?,
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+impl PendingPicture {
+    /* Copy of the picture state without its slices, to build lists for other slices */
+    fn shallow_clone(&self) -> PendingPicture {
+        PendingPicture {
+            frame: self.frame.clone(),
+            sps: self.sps.clone(),
+            pps: self.pps.clone(),
+            hdr: self.hdr.clone(),
+            nal: self.nal,
+            voc: self.voc,
+            top_poc: self.top_poc,
+            bottom_poc: self.bottom_poc,
+            poc_msb: self.poc_msb,
+            frame_num_offset: self.frame_num_offset,
+            structure: self.structure,
+            first_field: self.first_field,
+            slices: Vec::new(),
+            corrupted: self.corrupted,
+            dropped: self.dropped,
+            skipped: self.skipped,
+        }
+    }
+
+    /* First VCL NAL unit detection of clause 7.4.1.2.4, so that a picture whose first
+     * slice was lost is not merged into the previous one, e.g. the second field of a frame.
+     * Arbitrary slice order is not supported, so the first macroblock starts a picture too */
+    fn is_first_slice_of_next(&self, hdr: &SliceHeader, nal: &NalHeader) -> bool {
+        let first = &self.hdr;
+        hdr.first_mb_in_slice == 0 || self.nal.mvc.view_id != nal.mvc.view_id ||
+        first.frame_num != hdr.frame_num ||
+        first.pic_parameter_set_id != hdr.pic_parameter_set_id ||
+        first.field_pic_flag != hdr.field_pic_flag ||
+        first.bottom_field_flag != hdr.bottom_field_flag ||
+        (self.nal.nal_ref_idc == 0) != (nal.nal_ref_idc == 0) ||
+        first.pic_order_cnt_lsb != hdr.pic_order_cnt_lsb ||
+        first.delta_pic_order_cnt_bottom != hdr.delta_pic_order_cnt_bottom ||
+        first.delta_pic_order_cnt != hdr.delta_pic_order_cnt ||
+        self.nal.is_idr() != nal.is_idr() ||
+        (nal.is_idr() && first.idr_pic_id != hdr.idr_pic_id)
+    }
+}
+
+impl VADecoder for VADecoderH264 {
+    fn decode(&mut self, va_disp: &VADisplay, data: &[u8], pts: u64) -> Result<(), DecodeError> {
+        for nal in split_nal_units(data) {
+            match self.decode_nal(va_disp, nal, pts) {
+                /* Broken slices are skipped already, other broken units are ignored */
+                Ok(_) | Err(DecodeError::InvalidStream) => {}
+                Err(e) => {
+                    self.picture = None;
+                    return Err(e);
+                }
+            }
+        }
+
+        /* Every call carries whole access units */
+        let ret = self.end_picture(va_disp);
+        self.end_access_unit();
+        if let Some(nal_ref_idc) = self.lost_slices.take() {
+            self.lose_picture(nal_ref_idc);
+        }
+        ret
+    }
+
+    fn drain(&mut self, va_disp: &VADisplay) -> Result<(), DecodeError> {
+        self.end_stream(va_disp)
+    }
+
+    fn flush(&mut self) {
+        self.picture = None;
+        self.au_started = false;
+        self.prefix = None;
+        self.dpb.clear();
+        self.output.clear();
+        for view in &mut self.views {
+            *view = ViewState::default();
+        }
+        self.recovery_point = None;
+        self.recovery_frames = None;
+        self.lost_slices = None;
+        self.recovery.restart();
+    }
+
+    fn get_frame(&mut self) -> Option<VADecodedFrame> {
+        while let Some(mut frames) = self.output.pop_front() {
+            if frames.is_empty() {
+                continue;
+            }
+            let frame = frames.remove(0);
+            if !frames.is_empty() {
+                self.output.push_front(frames);
+            }
+            return Some(frame);
+        }
+        None
+    }
+
+    /* Access units missing their second view cannot be shown in stereo and are dropped */
+    fn get_frame_pair(&mut self) -> Option<(VADecodedFrame, VADecodedFrame)> {
+        while let Some(mut frames) = self.output.pop_front() {
+            if frames.len() >= 2 {
+                let right = frames.remove(1);
+                let left = frames.remove(0);
+                return Some((left, right));
+            }
+        }
+        None
+    }
+
+    fn get_stats(&self) -> VADecodeStats {
+        self.recovery.get_stats().with_context(self.context.as_ref())
+    }
+
+    fn set_decode_mode(&mut self, mode: DecodeMode) {
+        self.mode = mode;
+    }
+
+    fn destroy(&mut self, va_disp: &VADisplay) {
+        self.picture = None;
+        self.dpb.clear();
+        self.output.clear();
+        if let Some(context) = self.context.take() {
+            context.destroy(va_disp);
+        }
+    }
+}
@@ -0,0 +1,291 @@
+// Copyright (c) 2017 zzoon <zzoon@igalia.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::slice;
+
+use decoder::*;
+use ffi;
+use jpeg::*;
+use va::*;
+
+const NUM_SURFACES: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JpegOutputFormat {
+    RGBA,
+    NV12,
+}
+
+#[derive(Debug)]
+pub struct VADecoderJPEG {
+    context: Option<VADecodeContext>,
+    output: VecDeque<VADecodedFrame>,
+    recovery: VAErrorRecovery,
+}
+
+impl VADecoderJPEG {
+    pub fn new() -> Result<Box<VADecoder>, ()> {
+        Ok(Box::new(VADecoderJPEG {
+            context: None,
+            output: VecDeque::new(),
+            recovery: VAErrorRecovery::new(),
+        }))
+    }
+
+    fn ensure_context(&mut self,
+                      va_disp: &VADisplay,
+                      rt_format: u32,
+                      width: u32,
+                      height: u32)
+                      -> Result<(), DecodeError> {
+        let width = (width + 15) & !15;
+        let height = (height + 15) & !15;
+
+        if let Some(ref context) = self.context {
+            if context.is_compatible(ffi::VAProfileJPEGBaseline, rt_format, width, height) {
+                return Ok(());
+            }
+        }
+        if let Some(context) = self.context.take() {
+            context.destroy(va_disp);
+        }
+
+        let context = VADecodeContext::new(va_disp,
+                                           ffi::VAProfileJPEGBaseline,
+                                           rt_format,
+                                           width,
+                                           height,
+                                           NUM_SURFACES)
+            .map_err(|_| DecodeError::VAFailed)?;
+        self.context = Some(context);
+
+        Ok(())
+    }
+}
+
+impl VADecoder for VADecoderJPEG {
+    fn decode(&mut self, va_disp: &VADisplay, data: &[u8], pts: u64) -> Result<(), DecodeError> {
+        let image = match JpegImage::parse(data) {
+            Ok(image) => image,
+            /* Images are independent, a broken one is just dropped */
+            Err(JpegError::InvalidStream) => {
+                self.recovery.drop_frame();
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let (width, height) = (image.frame.width, image.frame.height);
+        let rt_format = image.frame.get_rt_format()?;
+
+        self.ensure_context(va_disp, rt_format, width, height)?;
+        let context = self.context.as_ref().unwrap();
+        let surface = context.get_surface().ok_or(DecodeError::NoSurface)?;
+
+        let pic_param = build_picture_param(&image);
+        let iq_matrix = build_iq_matrix(&image);
+        let huffman_table = build_huffman_table(&image);
+
+        let mut submission = PictureSubmission::new(va_disp, context)
+            .add_param(&pic_param)
+            .and_then(|s| s.add_param(&iq_matrix))
+            .and_then(|s| s.add_param(&huffman_table));
+        for scan in &image.scans {
+            let slice_param = build_slice_param(&image, scan);
+            submission = submission.and_then(|s| {
+                s.add_slice(&slice_param, &data[scan.offset..scan.offset + scan.size])
+            });
+        }
+        submission.and_then(|s| s.submit(&surface)).map_err(|_| DecodeError::VAFailed)?;
+
+        let info = VAFrameInfo {
+            picture_type: Some(PictureType::I),
+            bytes: data.len(),
+            ..VAFrameInfo::default()
+        };
+        let mut frame = VADecodedFrame::new(surface, width, height, pts).with_info(info);
+        if image.truncated {
+            frame.mark_corrupted();
+        }
+        self.recovery.frame_decoded(&frame);
+        self.output.push_back(frame);
+
+        Ok(())
+    }
+
+    fn drain(&mut self, _va_disp: &VADisplay) -> Result<(), DecodeError> {
+        /* Frames are output as soon as they are decoded */
+        Ok(())
+    }
+
+    fn flush(&mut self) {
+        self.output.clear();
+    }
+
+    fn get_frame(&mut self) -> Option<VADecodedFrame> {
+        self.output.pop_front()
+    }
+
+    fn get_stats(&self) -> VADecodeStats {
+        self.recovery.get_stats().with_context(self.context.as_ref())
+    }
+
+    /* Every JPEG picture is a keyframe */
+    fn set_decode_mode(&mut self, _mode: DecodeMode) {}
+
+    fn destroy(&mut self, va_disp: &VADisplay) {
+        self.output.clear();
+        if let Some(context) = self.context.take() {
+            context.destroy(va_disp);
+        }
+    }
+}
+
+/* Chroma layout of the image formats a JPEG surface can be derived to:
+ * (horizontal subsampling, vertical subsampling, interleaved, V plane first) */
+fn get_chroma_layout(fourcc: u32) -> Result<Option<(u32, u32, bool, bool)>, DecodeError> {
+    match fourcc {
+        ffi::VA_FOURCC_NV12 => Ok(Some((2, 2, true, false))),
+        ffi::VA_FOURCC_IYUV | ffi::VA_FOURCC_IMC3 => Ok(Some((2, 2, false, false))),
+        ffi::VA_FOURCC_YV12 => Ok(Some((2, 2, false, true))),
+        ffi::VA_FOURCC_422H => Ok(Some((2, 1, false, false))),
+        ffi::VA_FOURCC_422V => Ok(Some((1, 2, false, false))),
+        ffi::VA_FOURCC_444P => Ok(Some((1, 1, false, false))),
+        ffi::VA_FOURCC_411P => Ok(Some((4, 1, false, false))),
+        ffi::VA_FOURCC_Y800 => Ok(None),
+        _ => Err(DecodeError::Unsupported),
+    }
+}
+
+fn clamp(value: i32) -> u8 {
+    if value < 0 {
+        0
+    } else if value > 255 {
+        255
+    } else {
+        value as u8
+    }
+}
+
+/* Converts a mapped image to packed RGBA (full range BT.601, as in JFIF) or NV12 */
+fn convert_image(image: &VAImage,
+                 data: &[u8],
+                 width: u32,
+                 height: u32,
+                 format: JpegOutputFormat)
+                 -> Result<Vec<u8>, DecodeError> {
+    let layout = get_chroma_layout(image.get_fourcc())?;
+    let y_offset = image.get_offset(0) as usize;
+    let y_stride = image.get_stride(0) as usize;
+
+    let sample = |x: u32, y: u32| -> (i32, i32, i32) {
+        let luma = data[y_offset + y as usize * y_stride + x as usize] as i32;
+        match layout {
+            None => (luma, 128, 128),
+            Some((sx, sy, interleaved, swapped)) => {
+                let cx = (x / sx) as usize;
+                let cy = (y / sy) as usize;
+                if interleaved {
+                    let pos = image.get_offset(1) as usize + cy * image.get_stride(1) as usize +
+                              cx * 2;
+                    (luma, data[pos] as i32, data[pos + 1] as i32)
+                } else {
+                    let (u, v) = if swapped { (2, 1) } else { (1, 2) };
+                    let u_pos = image.get_offset(u) as usize +
+                                cy * image.get_stride(u) as usize + cx;
+                    let v_pos = image.get_offset(v) as usize +
+                                cy * image.get_stride(v) as usize + cx;
+                    (luma, data[u_pos] as i32, data[v_pos] as i32)
+                }
+            }
+        }
+    };
+
+    match format {
+        JpegOutputFormat::RGBA => {
+            let mut out = Vec::with_capacity((width * height * 4) as usize);
+            for y in 0..height {
+                for x in 0..width {
+                    let (luma, cb, cr) = sample(x, y);
+                    let (cb, cr) = (cb - 128, cr - 128);
+                    let luma = luma << 16;
+                    out.push(clamp((luma + 91881 * cr + 32768) >> 16));
+                    out.push(clamp((luma - 22554 * cb - 46802 * cr + 32768) >> 16));
+                    out.push(clamp((luma + 116130 * cb + 32768) >> 16));
+                    out.push(255);
+                }
+            }
+            Ok(out)
+        }
+        JpegOutputFormat::NV12 => {
+            let chroma_width = (width + 1) / 2;
+            let chroma_height = (height + 1) / 2;
+            let mut out = Vec::with_capacity((width * height + chroma_width * chroma_height * 2) as
+                                             usize);
+            for y in 0..height {
+                for x in 0..width {
+                    out.push(sample(x, y).0 as u8);
+                }
+            }
+            for y in 0..chroma_height {
+                for x in 0..chroma_width {
+                    let (_, cb, cr) = sample(x * 2, y * 2);
+                    out.push(cb as u8);
+                    out.push(cr as u8);
+                }
+            }
+            Ok(out)
+        }
+    }
+}
+
+/* Decodes one JPEG image and downloads it as (data, width, height) */
+pub fn decode_jpeg(va_disp: &VADisplay,
+                   data: &[u8],
+                   format: JpegOutputFormat)
+                   -> Result<(Vec<u8>, u32, u32), DecodeError> {
+    let mut decoder = VADecoderJPEG::new().map_err(|_| DecodeError::VAFailed)?;
+    let ret = decoder.decode(va_disp, data, 0).and_then(|_| {
+        let mut frame = decoder.get_frame().ok_or(DecodeError::InvalidStream)?;
+        let (width, height) = (frame.get_width(), frame.get_height());
+        frame.sync(va_disp)?;
+
+        let image = frame.get_surface().derive_image(va_disp).map_err(|_| DecodeError::VAFailed)?;
+        let buffer = image.get_buffer();
+        let ptr = buffer.map::<u8>(va_disp);
+        let ret = if ptr.is_null() {
+            Err(DecodeError::VAFailed)
+        } else {
+            let mapped = unsafe { slice::from_raw_parts(ptr, buffer.get_size() as usize) };
+            let ret = convert_image(&image, mapped, width, height, format);
+            buffer.unmap(va_disp);
+            ret
+        };
+        image.destroy(va_disp);
+
+        ret.map(|out| (out, width, height))
+    });
+    decoder.destroy(va_disp);
+
+    ret
+}
+
+pub fn decode_jpeg_file<P: AsRef<Path>>(va_disp: &VADisplay,
+                                        path: P,
+                                        format: JpegOutputFormat)
+                                        -> Result<(Vec<u8>, u32, u32), DecodeError> {
+    let mut data = Vec::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut data))
+        .map_err(|_| DecodeError::Io)?;
+
+    decode_jpeg(va_disp, &data, format)
+}
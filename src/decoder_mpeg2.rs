@@ -0,0 +1,564 @@
+// Copyright (c) 2017 zzoon <zzoon@igalia.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::VecDeque;
+use std::mem;
+
+use bitreader::BitReader;
+use decoder::*;
+use ffi;
+use mpeg2::*;
+use va::*;
+
+const NUM_SURFACES: usize = 8;
+
+/* A field waiting for its complementary field */
+#[derive(Debug)]
+struct FirstField {
+    frame: VADecodedFrame,
+    picture_coding_type: u8,
+    picture_structure: u8,
+}
+
+#[derive(Debug)]
+struct PendingPicture {
+    header: PictureHeader,
+    ext: Option<PictureCodingExtension>,
+    frame: Option<VADecodedFrame>,
+    is_first_field: bool,
+    skip: bool,
+    slices: Vec<(ffi::VASliceParameterBufferMPEG2, Vec<u8>)>,
+    corrupted: bool,
+    pts: u64,
+}
+
+#[derive(Debug)]
+pub struct VADecoderMPEG2 {
+    context: Option<VADecodeContext>,
+    seq_hdr: Option<SequenceHeader>,
+    seq_ext: Option<SequenceExtension>,
+    gop: Option<GopHeader>,
+    leading_b: Option<GopHeader>,
+    quant: QuantMatrices,
+    picture: Option<PendingPicture>,
+    first_field: Option<FirstField>,
+    prev_ref: Option<VADecodedFrame>,
+    next_ref: Option<VADecodedFrame>,
+    output: VecDeque<VADecodedFrame>,
+    recovery: VAErrorRecovery,
+    mode: DecodeMode,
+    /* Input from the last start code on, which may continue in the next call */
+    pending: Vec<u8>,
+    pending_pts: u64,
+}
+
+impl VADecoderMPEG2 {
+    pub fn new() -> Result<Box<VADecoder>, ()> {
+        Ok(Box::new(VADecoderMPEG2 {
+            context: None,
+            seq_hdr: None,
+            seq_ext: None,
+            gop: None,
+            leading_b: None,
+            quant: QuantMatrices::new(),
+            picture: None,
+            first_field: None,
+            prev_ref: None,
+            next_ref: None,
+            output: VecDeque::new(),
+            recovery: VAErrorRecovery::new(),
+            mode: DecodeMode::All,
+            pending: Vec::new(),
+            pending_pts: 0,
+        }))
+    }
+
+    fn get_size(&self) -> (u32, u32) {
+        match (&self.seq_hdr, &self.seq_ext) {
+            (&Some(ref hdr), &Some(ref ext)) => hdr.get_size(Some(ext)),
+            _ => (0, 0),
+        }
+    }
+
+    fn ensure_context(&mut self, va_disp: &VADisplay) -> Result<(), DecodeError> {
+        let (profile, progressive) = match self.seq_ext {
+            Some(ref ext) => {
+                /* 4:2:0 only, the VA MPEG-2 profiles do not cover 4:2:2 */
+                if ext.chroma_format != 1 {
+                    return Err(DecodeError::Unsupported);
+                }
+                match (ext.profile_and_level_indication >> 4) & 0x7 {
+                    5 => (ffi::VAProfileMPEG2Simple, ext.progressive_sequence),
+                    _ => (ffi::VAProfileMPEG2Main, ext.progressive_sequence),
+                }
+            }
+            /* MPEG-1 streams carry no sequence extension */
+            None => return Err(DecodeError::Unsupported),
+        };
+
+        let (width, height) = self.get_size();
+        let width = (width + 15) & !15;
+        let height = if progressive {
+            (height + 15) & !15
+        } else {
+            (height + 31) & !31
+        };
+
+        if let Some(ref context) = self.context {
+            if context.is_compatible(profile, ffi::VA_RT_FORMAT_YUV420, width, height) {
+                return Ok(());
+            }
+        }
+
+        self.output_references();
+        if let Some(context) = self.context.take() {
+            context.destroy(va_disp);
+        }
+
+        let context = VADecodeContext::new(va_disp,
+                                           profile,
+                                           ffi::VA_RT_FORMAT_YUV420,
+                                           width,
+                                           height,
+                                           NUM_SURFACES)
+            .map_err(|_| DecodeError::VAFailed)?;
+        self.context = Some(context);
+
+        Ok(())
+    }
+
+    /* Pushes the frames held for reordering to the output queue */
+    fn output_references(&mut self) {
+        if let Some(field) = self.first_field.take() {
+            self.recovery.frame_decoded(&field.frame);
+            self.output.push_back(field.frame);
+        }
+        if let Some(frame) = self.next_ref.take() {
+            self.output.push_back(frame);
+        }
+        self.prev_ref = None;
+    }
+
+    /* A picture that cannot be decoded, pictures predicted from a lost reference are
+     * skipped until the next I picture as there is nothing left to predict them from */
+    fn lose_picture(&mut self, is_reference: bool) {
+        self.picture = None;
+        self.recovery.drop_frame();
+        if is_reference {
+            self.recovery.lost_reference();
+            self.output_references();
+        }
+    }
+
+    fn start_picture(&mut self, va_disp: &VADisplay) -> Result<(), DecodeError> {
+        if self.seq_hdr.is_none() {
+            return Err(DecodeError::InvalidStream);
+        }
+        self.ensure_context(va_disp)?;
+
+        let (coding_type, structure) = match self.picture {
+            Some(ref pic) => {
+                match pic.ext {
+                    Some(ref ext) => (pic.header.picture_coding_type, ext.picture_structure),
+                    None => return Err(DecodeError::InvalidStream),
+                }
+            }
+            None => return Err(DecodeError::InvalidStream),
+        };
+
+        if coding_type < PICTURE_TYPE_I || coding_type > PICTURE_TYPE_B {
+            return Err(DecodeError::Unsupported);
+        }
+        if coding_type == PICTURE_TYPE_I {
+            self.recovery.recover();
+        }
+
+        /* The second field of a frame goes to the surface of the first one */
+        if structure != PICTURE_STRUCTURE_FRAME {
+            if let Some(field) = self.first_field.take() {
+                if field.picture_structure != structure {
+                    let pic = self.picture.as_mut().unwrap();
+                    pic.frame = Some(field.frame);
+                    pic.is_first_field = false;
+                    return Ok(());
+                }
+                /* Unpaired field, output it as it is */
+                self.complete_frame(field.frame, field.picture_coding_type);
+            }
+        } else if let Some(field) = self.first_field.take() {
+            self.complete_frame(field.frame, field.picture_coding_type);
+        }
+
+        if self.mode.skips(coding_type == PICTURE_TYPE_I, coding_type != PICTURE_TYPE_B) {
+            self.picture.as_mut().unwrap().skip = true;
+            self.recovery.skip_frame();
+            return Ok(());
+        }
+
+        /* B pictures right after the I picture of a closed GOP only predict backwards,
+         * those of a broken link cannot be decoded at all */
+        let skip = match coding_type {
+            PICTURE_TYPE_P => self.next_ref.is_none(),
+            PICTURE_TYPE_B => {
+                match self.leading_b {
+                    Some(ref gop) if gop.broken_link => true,
+                    Some(ref gop) if gop.closed_gop => self.next_ref.is_none(),
+                    _ => self.prev_ref.is_none() || self.next_ref.is_none(),
+                }
+            }
+            _ => false,
+        };
+        if coding_type == PICTURE_TYPE_P {
+            self.leading_b = None;
+        }
+        if skip {
+            self.picture.as_mut().unwrap().skip = true;
+            self.recovery.drop_frame();
+            return Ok(());
+        }
+
+        if coding_type != PICTURE_TYPE_B {
+            self.prev_ref = self.next_ref.take();
+            if let Some(ref frame) = self.prev_ref {
+                self.output.push_back(frame.clone());
+            }
+        }
+
+        let surface = match self.context {
+            Some(ref context) => context.get_surface(),
+            None => None,
+        };
+        let surface = surface.ok_or(DecodeError::NoSurface)?;
+        let (width, height) = self.get_size();
+        let pic = self.picture.as_mut().unwrap();
+        /* Field pictures are displayed in the order they are coded in */
+        let field_order = match pic.ext {
+            Some(ref ext) if ext.progressive_frame => FieldOrder::Progressive,
+            Some(ref ext) if ext.picture_structure == PICTURE_STRUCTURE_BOTTOM_FIELD => {
+                FieldOrder::BottomFieldFirst
+            }
+            Some(ref ext) if ext.picture_structure == PICTURE_STRUCTURE_FRAME &&
+                             !ext.top_field_first => FieldOrder::BottomFieldFirst,
+            _ => FieldOrder::TopFieldFirst,
+        };
+        pic.frame = Some(VADecodedFrame::new(surface, width, height, pic.pts)
+            .with_field_order(field_order));
+        pic.is_first_field = true;
+
+        Ok(())
+    }
+
+    fn complete_frame(&mut self, frame: VADecodedFrame, coding_type: u8) {
+        self.recovery.frame_decoded(&frame);
+        if coding_type == PICTURE_TYPE_B || self.mode.is_keyframes_only() {
+            self.output.push_back(frame);
+        } else {
+            self.next_ref = Some(frame);
+        }
+    }
+
+    fn end_picture(&mut self, va_disp: &VADisplay) -> Result<(), DecodeError> {
+        let pic = match self.picture.take() {
+            Some(pic) => pic,
+            None => return Ok(()),
+        };
+        if pic.skip {
+            return Ok(());
+        }
+        if pic.slices.is_empty() {
+            /* Every slice of the picture was broken */
+            if pic.corrupted {
+                self.lose_picture(pic.header.picture_coding_type != PICTURE_TYPE_B);
+            }
+            return Ok(());
+        }
+        let (mut frame, ext) = match (pic.frame, pic.ext) {
+            (Some(frame), Some(ext)) => (frame, ext),
+            _ => return Ok(()),
+        };
+
+        let coding_type = pic.header.picture_coding_type;
+        let surface_id = |f: &Option<VADecodedFrame>| match *f {
+            Some(ref frame) => frame.get_surface().get_id(),
+            None => ffi::VA_INVALID_SURFACE,
+        };
+        let forward = match coding_type {
+            PICTURE_TYPE_P | PICTURE_TYPE_B => surface_id(&self.prev_ref),
+            _ => ffi::VA_INVALID_SURFACE,
+        };
+        let backward = match coding_type {
+            PICTURE_TYPE_B => surface_id(&self.next_ref),
+            _ => ffi::VA_INVALID_SURFACE,
+        };
+        let is_corrupted = |f: &Option<VADecodedFrame>| {
+            f.as_ref().map_or(false, |frame| frame.is_corrupted())
+        };
+        let corrupted = match coding_type {
+            PICTURE_TYPE_P => is_corrupted(&self.prev_ref),
+            PICTURE_TYPE_B => is_corrupted(&self.prev_ref) || is_corrupted(&self.next_ref),
+            _ => false,
+        };
+        if pic.corrupted || corrupted {
+            frame.mark_corrupted();
+        }
+
+        let mut info = VAFrameInfo {
+            picture_type: match coding_type {
+                PICTURE_TYPE_I => Some(PictureType::I),
+                PICTURE_TYPE_P => Some(PictureType::P),
+                _ => Some(PictureType::B),
+            },
+            bytes: pic.slices.iter().map(|&(_, ref data)| data.len()).sum(),
+            poc: Some(pic.header.temporal_reference as i32),
+            num_references: [forward, backward]
+                .iter()
+                .filter(|&&id| id != ffi::VA_INVALID_SURFACE)
+                .count(),
+            ..VAFrameInfo::default()
+        };
+        for &(ref slice_param, _) in &pic.slices {
+            info.add_qp(slice_param.quantiser_scale_code);
+        }
+        if !pic.is_first_field {
+            info = info.merge_field(frame.get_info());
+        }
+        frame = frame.with_info(info);
+
+        let pic_param = match self.seq_hdr {
+            Some(ref seq_hdr) => {
+                build_picture_param(seq_hdr,
+                                    self.seq_ext.as_ref(),
+                                    &pic.header,
+                                    &ext,
+                                    forward,
+                                    backward,
+                                    pic.is_first_field)?
+            }
+            None => return Err(DecodeError::InvalidStream),
+        };
+        let iq_matrix = build_iq_matrix(&self.quant);
+
+        {
+            let context = self.context.as_ref().ok_or(DecodeError::InvalidStream)?;
+            let mut submission = PictureSubmission::new(va_disp, context)
+                .add_param(&pic_param)
+                .and_then(|s| s.add_param(&iq_matrix));
+            for &(ref slice_param, ref data) in &pic.slices {
+                submission = submission.and_then(|s| s.add_slice(slice_param, data));
+            }
+            submission.and_then(|s| s.submit(frame.get_surface()))
+                .map_err(|_| DecodeError::VAFailed)?;
+        }
+
+        if ext.picture_structure != PICTURE_STRUCTURE_FRAME && pic.is_first_field {
+            self.first_field = Some(FirstField {
+                frame: frame,
+                picture_coding_type: coding_type,
+                picture_structure: ext.picture_structure,
+            });
+        } else {
+            self.complete_frame(frame, coding_type);
+        }
+
+        Ok(())
+    }
+
+    fn parse_extension(&mut self, br: &mut BitReader) -> Result<(), DecodeError> {
+        match Extension::parse(br).map_err(|_| DecodeError::InvalidStream)? {
+            Extension::Sequence(ext) => self.seq_ext = Some(ext),
+            Extension::QuantMatrix(ext) => self.quant.update_from_extension(&ext),
+            Extension::PictureCoding(ext) => {
+                if let Some(ref mut pic) = self.picture {
+                    pic.ext = Some(ext);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn decode_slice(&mut self, va_disp: &VADisplay, data: &[u8]) -> Result<(), DecodeError> {
+        let (started, coding_type) = match self.picture {
+            Some(ref pic) => (pic.frame.is_some() || pic.skip, pic.header.picture_coding_type),
+            None => return Ok(()),
+        };
+        if !started {
+            match self.start_picture(va_disp) {
+                /* Picture coding extension or sequence header missing */
+                Err(DecodeError::InvalidStream) => {
+                    self.lose_picture(coding_type != PICTURE_TYPE_B);
+                    return Ok(());
+                }
+                ret => ret?,
+            }
+        }
+
+        let (_, vertical_size) = self.get_size();
+        let pic = self.picture.as_mut().unwrap();
+        if pic.skip {
+            return Ok(());
+        }
+        let hdr = match SliceHeader::parse(data, vertical_size) {
+            Ok(hdr) => hdr,
+            Err(_) => {
+                pic.corrupted = true;
+                self.recovery.skip_slice();
+                return Ok(());
+            }
+        };
+        pic.slices.push((build_slice_param(&hdr, 0, data.len() as u32), data.to_vec()));
+
+        Ok(())
+    }
+
+    fn decode_unit(&mut self,
+                   va_disp: &VADisplay,
+                   data: &[u8],
+                   pts: u64)
+                   -> Result<(), DecodeError> {
+        let code = data[3];
+        let mut br = BitReader::new(&data[4..]);
+
+        match code {
+            PICTURE_START_CODE => {
+                self.end_picture(va_disp)?;
+                let header = match PictureHeader::parse(&mut br) {
+                    Ok(header) => header,
+                    Err(_) => {
+                        self.lose_picture(true);
+                        return Ok(());
+                    }
+                };
+                if header.picture_coding_type == PICTURE_TYPE_I {
+                    self.leading_b = self.gop.take();
+                }
+                self.picture = Some(PendingPicture {
+                    header: header,
+                    ext: None,
+                    frame: None,
+                    is_first_field: true,
+                    skip: false,
+                    slices: Vec::new(),
+                    corrupted: false,
+                    pts: pts,
+                });
+            }
+            SLICE_MIN_START_CODE..=SLICE_MAX_START_CODE => self.decode_slice(va_disp, data)?,
+            SEQUENCE_HEADER_CODE => {
+                self.end_picture(va_disp)?;
+                let seq_hdr = SequenceHeader::parse(&mut br).map_err(|_| DecodeError::InvalidStream)?;
+                self.quant.update_from_sequence(&seq_hdr);
+                self.seq_hdr = Some(seq_hdr);
+                self.seq_ext = None;
+            }
+            EXTENSION_START_CODE => self.parse_extension(&mut br)?,
+            GROUP_START_CODE => {
+                self.end_picture(va_disp)?;
+                self.gop = Some(GopHeader::parse(&mut br).map_err(|_| DecodeError::InvalidStream)?);
+            }
+            SEQUENCE_END_CODE => {
+                self.end_picture(va_disp)?;
+                self.output_references();
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn decode_units<F>(&mut self,
+                       va_disp: &VADisplay,
+                       units: &[StartCodeUnit],
+                       pts_of: F)
+                       -> Result<(), DecodeError>
+        where F: Fn(&StartCodeUnit) -> u64
+    {
+        for unit in units {
+            match self.decode_unit(va_disp, unit.data, pts_of(unit)) {
+                /* Broken headers are ignored, the pictures needing them are dropped */
+                Ok(_) | Err(DecodeError::InvalidStream) => {}
+                Err(e) => {
+                    self.picture = None;
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl VADecoder for VADecoderMPEG2 {
+    /* Pictures may be split across calls, as PES payloads split them: a picture is ended
+     * once the start code of the next one, of a sequence header or of a GOP arrives, or
+     * on drain() */
+    fn decode(&mut self, va_disp: &VADisplay, data: &[u8], pts: u64) -> Result<(), DecodeError> {
+        let mut input = mem::take(&mut self.pending);
+        if input.is_empty() {
+            self.pending_pts = pts;
+        }
+        let continued = input.len();
+        input.extend_from_slice(data);
+
+        let (units, rest) = split_complete_units(&input);
+        /* A unit begun in an earlier call belongs to the pts of that call */
+        let rest_pts = if rest < continued { self.pending_pts } else { pts };
+        let pending_pts = self.pending_pts;
+        let ret = self.decode_units(va_disp, &units, |unit| {
+            if unit.offset < continued { pending_pts } else { pts }
+        });
+
+        self.pending = input[rest..].to_vec();
+        self.pending_pts = rest_pts;
+        ret
+    }
+
+    fn drain(&mut self, va_disp: &VADisplay) -> Result<(), DecodeError> {
+        let input = mem::take(&mut self.pending);
+        let pts = self.pending_pts;
+        let ret = self.decode_units(va_disp, &split_start_code_units(&input), |_| pts)
+            .and_then(|_| self.end_picture(va_disp));
+        self.output_references();
+        ret
+    }
+
+    /* Without references, pictures up to the next I picture are skipped */
+    fn flush(&mut self) {
+        self.pending.clear();
+        self.picture = None;
+        self.first_field = None;
+        self.prev_ref = None;
+        self.next_ref = None;
+        self.gop = None;
+        self.leading_b = None;
+        self.output.clear();
+    }
+
+    fn get_frame(&mut self) -> Option<VADecodedFrame> {
+        self.output.pop_front()
+    }
+
+    fn get_stats(&self) -> VADecodeStats {
+        self.recovery.get_stats().with_context(self.context.as_ref())
+    }
+
+    fn set_decode_mode(&mut self, mode: DecodeMode) {
+        self.mode = mode;
+    }
+
+    fn destroy(&mut self, va_disp: &VADisplay) {
+        self.pending.clear();
+        self.picture = None;
+        self.first_field = None;
+        self.prev_ref = None;
+        self.next_ref = None;
+        self.output.clear();
+        if let Some(context) = self.context.take() {
+            context.destroy(va_disp);
+        }
+    }
+}
@@ -0,0 +1,1079 @@
+// Copyright (c) 2017 zzoon <zzoon@igalia.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::cmp;
+use std::collections::VecDeque;
+
+use bitfields::*;
+use bitreader::BitReader;
+use decoder::*;
+use ffi;
+use va::*;
+
+const NUM_SURFACES: usize = 8;
+
+const VIDEO_OBJECT_LAYER_MIN_START_CODE: u8 = 0x20;
+const VIDEO_OBJECT_LAYER_MAX_START_CODE: u8 = 0x2f;
+const VISUAL_OBJECT_SEQUENCE_START_CODE: u8 = 0xb0;
+const VISUAL_OBJECT_SEQUENCE_END_CODE: u8 = 0xb1;
+const GROUP_OF_VOP_START_CODE: u8 = 0xb3;
+const VOP_START_CODE: u8 = 0xb6;
+
+const SHAPE_RECTANGULAR: u32 = 0;
+const ASPECT_RATIO_EXTENDED_PAR: u32 = 15;
+
+const SPRITE_STATIC: u8 = 1;
+const SPRITE_GMC: u8 = 2;
+
+const VOP_TYPE_I: u8 = 0;
+const VOP_TYPE_P: u8 = 1;
+const VOP_TYPE_B: u8 = 2;
+const VOP_TYPE_S: u8 = 3;
+
+/* Time base of H.263 temporal references, 29.97 Hz */
+const H263_TIME_RESOLUTION: u16 = 30000;
+
+/* Default weighting matrices of ISO/IEC 14496-2, in raster order */
+const DEFAULT_INTRA_MATRIX: [u8; 64] = [8, 17, 18, 19, 21, 23, 25, 27, 17, 18, 19, 21, 23, 25,
+                                        27, 28, 20, 21, 22, 23, 24, 26, 28, 30, 21, 22, 23, 24,
+                                        26, 28, 30, 32, 22, 23, 24, 26, 28, 30, 32, 35, 23, 24,
+                                        26, 28, 30, 32, 35, 38, 25, 26, 28, 30, 32, 35, 38, 41,
+                                        27, 28, 30, 32, 35, 38, 41, 45];
+const DEFAULT_NON_INTRA_MATRIX: [u8; 64] = [16, 17, 18, 19, 20, 21, 22, 23, 17, 18, 19, 20, 21,
+                                            22, 23, 24, 18, 19, 20, 21, 22, 23, 24, 25, 19, 20,
+                                            21, 22, 23, 24, 26, 27, 20, 21, 22, 23, 25, 26, 27,
+                                            28, 21, 22, 23, 24, 26, 27, 28, 30, 22, 23, 24, 26,
+                                            27, 28, 30, 31, 23, 24, 25, 27, 28, 30, 31, 33];
+
+/* dmv_length of the sprite trajectory as (code, length) */
+const SPRITE_TRAJECTORY_CODES: [(u32, u32); 15] =
+    [(0x00, 2), (0x02, 3), (0x03, 3), (0x04, 3), (0x05, 3), (0x06, 3), (0x0e, 4), (0x1e, 5),
+     (0x3e, 6), (0x7e, 7), (0xfe, 8), (0x1fe, 9), (0x3fe, 10), (0x7fe, 11), (0xffe, 12)];
+
+fn default_matrix(raster: &[u8; 64]) -> [u8; 64] {
+    let mut matrix = [0; 64];
+    for i in 0..64 {
+        matrix[i] = raster[ZIGZAG[i]];
+    }
+    matrix
+}
+
+/* Loaded matrices are coded in zig-zag order, a zero repeats the last value to the end */
+fn read_quant_matrix(br: &mut BitReader) -> Result<[u8; 64], ()> {
+    let mut matrix = [0; 64];
+    let mut last = 0;
+    for i in 0..64 {
+        let value = br.read_bits(8)? as u8;
+        if value == 0 {
+            if i == 0 {
+                return Err(());
+            }
+            for entry in matrix[i..].iter_mut() {
+                *entry = last;
+            }
+            break;
+        }
+        matrix[i] = value;
+        last = value;
+    }
+    Ok(matrix)
+}
+
+/* Number of bits needed to code values up to `count - 1` */
+fn get_bit_length(count: u32) -> u32 {
+    cmp::max(1, 32 - count.saturating_sub(1).leading_zeros())
+}
+
+fn read_marker(br: &mut BitReader) -> Result<(), ()> {
+    if br.read_flag()? { Ok(()) } else { Err(()) }
+}
+
+fn read_sprite_trajectory_value(br: &mut BitReader) -> Result<i16, ()> {
+    let mut length = None;
+    for (value, &(code, len)) in SPRITE_TRAJECTORY_CODES.iter().enumerate() {
+        if br.peek_bits(len) == Ok(code) {
+            br.skip_bits(len as usize)?;
+            length = Some(value as u32);
+            break;
+        }
+    }
+    let length = length.ok_or(())?;
+    if length == 0 {
+        return Ok(0);
+    }
+
+    let code = br.read_bits(length)? as i32;
+    if code >> (length - 1) == 1 {
+        Ok(code as i16)
+    } else {
+        Ok((code - ((1 << length) - 1)) as i16)
+    }
+}
+
+/* Offsets of the byte aligned 22 bit picture start codes of H.263 */
+fn find_short_header_start_codes(data: &[u8]) -> Vec<usize> {
+    let mut offsets = Vec::new();
+    let mut i = 0;
+    while i + 3 < data.len() {
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] & 0xfc == 0x80 {
+            offsets.push(i);
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+    offsets
+}
+
+#[derive(Debug, Clone)]
+struct VolHeader {
+    short_video_header: bool,
+    width: u32,
+    height: u32,
+    chroma_format: u8,
+    vop_time_increment_resolution: u32,
+    vop_time_increment_bits: u32,
+    interlaced: bool,
+    obmc_disable: bool,
+    sprite_enable: u8,
+    no_of_sprite_warping_points: u8,
+    sprite_warping_accuracy: u8,
+    sprite_brightness_change: bool,
+    quant_precision: u8,
+    quant_type: bool,
+    intra_quant_mat: [u8; 64],
+    non_intra_quant_mat: [u8; 64],
+    quarter_sample: bool,
+    resync_marker_disable: bool,
+    data_partitioned: bool,
+    reversible_vlc: bool,
+    reduced_resolution_vop_enable: bool,
+}
+
+impl VolHeader {
+    fn parse(br: &mut BitReader) -> Result<VolHeader, DecodeError> {
+        /* random_accessible_vol, video_object_type_indication */
+        br.skip_bits(1 + 8)?;
+        let mut verid = 1;
+        if br.read_flag()? {
+            verid = br.read_bits(4)?;
+            /* video_object_layer_priority */
+            br.skip_bits(3)?;
+        }
+        if br.read_bits(4)? == ASPECT_RATIO_EXTENDED_PAR {
+            /* par_width, par_height */
+            br.skip_bits(8 + 8)?;
+        }
+        let mut chroma_format = 1;
+        if br.read_flag()? {
+            chroma_format = br.read_bits(2)? as u8;
+            /* low_delay */
+            br.skip_bits(1)?;
+            if br.read_flag()? {
+                /* bit rate, vbv buffer size and vbv occupancy, with their marker bits */
+                br.skip_bits(15 + 1 + 15 + 1 + 15 + 1 + 3 + 11 + 1 + 15 + 1)?;
+            }
+        }
+        if br.read_bits(2)? != SHAPE_RECTANGULAR {
+            return Err(DecodeError::Unsupported);
+        }
+
+        read_marker(br)?;
+        let vop_time_increment_resolution = br.read_bits(16)?;
+        if vop_time_increment_resolution == 0 {
+            return Err(DecodeError::InvalidStream);
+        }
+        let vop_time_increment_bits = get_bit_length(vop_time_increment_resolution);
+        read_marker(br)?;
+        if br.read_flag()? {
+            /* fixed_vop_time_increment */
+            br.skip_bits(vop_time_increment_bits as usize)?;
+        }
+
+        read_marker(br)?;
+        let width = br.read_bits(13)?;
+        read_marker(br)?;
+        let height = br.read_bits(13)?;
+        read_marker(br)?;
+        let interlaced = br.read_flag()?;
+        let obmc_disable = br.read_flag()?;
+
+        let sprite_enable = br.read_bits(if verid == 1 { 1 } else { 2 })? as u8;
+        let mut no_of_sprite_warping_points = 0;
+        let mut sprite_warping_accuracy = 0;
+        let mut sprite_brightness_change = false;
+        if sprite_enable == SPRITE_STATIC || sprite_enable == SPRITE_GMC {
+            if sprite_enable != SPRITE_GMC {
+                /* sprite_width, sprite_height, sprite_left and sprite_top, with markers */
+                br.skip_bits(4 * (13 + 1))?;
+            }
+            no_of_sprite_warping_points = br.read_bits(6)? as u8;
+            sprite_warping_accuracy = br.read_bits(2)? as u8;
+            sprite_brightness_change = br.read_flag()?;
+            if sprite_enable != SPRITE_GMC {
+                /* low_latency_sprite_enable */
+                br.skip_bits(1)?;
+            }
+        }
+
+        let mut quant_precision = 5;
+        if br.read_flag()? {
+            quant_precision = br.read_bits(4)? as u8;
+            /* bits_per_pixel */
+            br.skip_bits(4)?;
+        }
+
+        let quant_type = br.read_flag()?;
+        let mut intra_quant_mat = default_matrix(&DEFAULT_INTRA_MATRIX);
+        let mut non_intra_quant_mat = default_matrix(&DEFAULT_NON_INTRA_MATRIX);
+        if quant_type {
+            if br.read_flag()? {
+                intra_quant_mat = read_quant_matrix(br)?;
+            }
+            if br.read_flag()? {
+                non_intra_quant_mat = read_quant_matrix(br)?;
+            }
+        }
+
+        let mut quarter_sample = false;
+        if verid != 1 {
+            quarter_sample = br.read_flag()?;
+        }
+        /* complexity_estimation_disable */
+        if !br.read_flag()? {
+            return Err(DecodeError::Unsupported);
+        }
+        let resync_marker_disable = br.read_flag()?;
+        let data_partitioned = br.read_flag()?;
+        let mut reversible_vlc = false;
+        if data_partitioned {
+            reversible_vlc = br.read_flag()?;
+        }
+        let mut reduced_resolution_vop_enable = false;
+        if verid != 1 {
+            /* newpred_enable */
+            if br.read_flag()? {
+                return Err(DecodeError::Unsupported);
+            }
+            reduced_resolution_vop_enable = br.read_flag()?;
+        }
+        /* scalability */
+        if br.read_flag()? {
+            return Err(DecodeError::Unsupported);
+        }
+
+        Ok(VolHeader {
+            short_video_header: false,
+            width: width,
+            height: height,
+            chroma_format: chroma_format,
+            vop_time_increment_resolution: vop_time_increment_resolution,
+            vop_time_increment_bits: vop_time_increment_bits,
+            interlaced: interlaced,
+            obmc_disable: obmc_disable,
+            sprite_enable: sprite_enable,
+            no_of_sprite_warping_points: no_of_sprite_warping_points,
+            sprite_warping_accuracy: sprite_warping_accuracy,
+            sprite_brightness_change: sprite_brightness_change,
+            quant_precision: quant_precision,
+            quant_type: quant_type,
+            intra_quant_mat: intra_quant_mat,
+            non_intra_quant_mat: non_intra_quant_mat,
+            quarter_sample: quarter_sample,
+            resync_marker_disable: resync_marker_disable,
+            data_partitioned: data_partitioned,
+            reversible_vlc: reversible_vlc,
+            reduced_resolution_vop_enable: reduced_resolution_vop_enable,
+        })
+    }
+
+    /* The fixed layer parameters of H.263 baseline */
+    fn new_short_header(width: u32, height: u32) -> VolHeader {
+        VolHeader {
+            short_video_header: true,
+            width: width,
+            height: height,
+            chroma_format: 1,
+            vop_time_increment_resolution: H263_TIME_RESOLUTION as u32,
+            vop_time_increment_bits: 8,
+            interlaced: false,
+            obmc_disable: true,
+            sprite_enable: 0,
+            no_of_sprite_warping_points: 0,
+            sprite_warping_accuracy: 0,
+            sprite_brightness_change: false,
+            quant_precision: 5,
+            quant_type: false,
+            intra_quant_mat: default_matrix(&DEFAULT_INTRA_MATRIX),
+            non_intra_quant_mat: default_matrix(&DEFAULT_NON_INTRA_MATRIX),
+            quarter_sample: false,
+            resync_marker_disable: true,
+            data_partitioned: false,
+            reversible_vlc: false,
+            reduced_resolution_vop_enable: false,
+        }
+    }
+
+    fn get_mb_size(&self) -> (u32, u32) {
+        ((self.width + 15) / 16, (self.height + 15) / 16)
+    }
+
+    /* GOBs are one macroblock row high up to 400 lines, then two, then four */
+    fn get_gob_layout(&self) -> (u32, u32) {
+        let (mb_width, mb_height) = self.get_mb_size();
+        let gob_height = if self.height <= 400 {
+            1
+        } else if self.height <= 800 {
+            2
+        } else {
+            4
+        };
+        let num_macroblocks_in_gob = mb_width * gob_height;
+        (mb_width * mb_height / num_macroblocks_in_gob, num_macroblocks_in_gob)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct VopHeader {
+    coding_type: u8,
+    modulo_time_base: u32,
+    time_increment: u32,
+    coded: bool,
+    rounding_type: bool,
+    intra_dc_vlc_thr: u8,
+    top_field_first: bool,
+    alternate_vertical_scan: bool,
+    sprite_trajectory: [(i16, i16); 3],
+    quant: u8,
+    fcode_forward: u8,
+    fcode_backward: u8,
+}
+
+impl VopHeader {
+    fn parse(br: &mut BitReader, vol: &VolHeader) -> Result<VopHeader, DecodeError> {
+        let mut vop = VopHeader::default();
+
+        vop.coding_type = br.read_bits(2)? as u8;
+        while br.read_flag()? {
+            vop.modulo_time_base += 1;
+        }
+        read_marker(br)?;
+        vop.time_increment = br.read_bits(vol.vop_time_increment_bits)?;
+        read_marker(br)?;
+        vop.coded = br.read_flag()?;
+        if !vop.coded {
+            return Ok(vop);
+        }
+
+        if vop.coding_type == VOP_TYPE_P ||
+           (vop.coding_type == VOP_TYPE_S && vol.sprite_enable == SPRITE_GMC) {
+            vop.rounding_type = br.read_flag()?;
+        }
+        if vol.reduced_resolution_vop_enable &&
+           (vop.coding_type == VOP_TYPE_I || vop.coding_type == VOP_TYPE_P) &&
+           br.read_flag()? {
+            return Err(DecodeError::Unsupported);
+        }
+        vop.intra_dc_vlc_thr = br.read_bits(3)? as u8;
+        if vol.interlaced {
+            vop.top_field_first = br.read_flag()?;
+            vop.alternate_vertical_scan = br.read_flag()?;
+        }
+
+        if vop.coding_type == VOP_TYPE_S {
+            /* Only global motion compensation, static sprites have no VA support */
+            if vol.sprite_enable != SPRITE_GMC || vol.no_of_sprite_warping_points > 3 ||
+               vol.sprite_brightness_change {
+                return Err(DecodeError::Unsupported);
+            }
+            for i in 0..vol.no_of_sprite_warping_points as usize {
+                let du = read_sprite_trajectory_value(br)?;
+                read_marker(br)?;
+                let dv = read_sprite_trajectory_value(br)?;
+                read_marker(br)?;
+                vop.sprite_trajectory[i] = (du, dv);
+            }
+        }
+
+        vop.quant = br.read_bits(vol.quant_precision as u32)? as u8;
+        if vop.quant == 0 {
+            return Err(DecodeError::InvalidStream);
+        }
+        if vop.coding_type != VOP_TYPE_I {
+            vop.fcode_forward = br.read_bits(3)? as u8;
+            if vop.fcode_forward == 0 {
+                return Err(DecodeError::InvalidStream);
+            }
+        }
+        if vop.coding_type == VOP_TYPE_B {
+            vop.fcode_backward = br.read_bits(3)? as u8;
+            if vop.fcode_backward == 0 {
+                return Err(DecodeError::InvalidStream);
+            }
+        }
+
+        Ok(vop)
+    }
+
+    /* Picture header of H.263 baseline; returns the frame size along with it */
+    fn parse_short_header(br: &mut BitReader) -> Result<(VopHeader, u32, u32), DecodeError> {
+        let mut vop = VopHeader::default();
+
+        /* short_video_start_marker */
+        br.skip_bits(22)?;
+        vop.time_increment = br.read_bits(8)?;
+        read_marker(br)?;
+        /* zero_bit, split_screen_indicator, document_camera_indicator,
+         * full_picture_freeze_release */
+        br.skip_bits(4)?;
+        let (width, height) = match br.read_bits(3)? {
+            1 => (128, 96),
+            2 => (176, 144),
+            3 => (352, 288),
+            4 => (704, 576),
+            /* 16CIF does not fit the GOB fields, the rest is extended PTYPE */
+            _ => return Err(DecodeError::Unsupported),
+        };
+        vop.coding_type = br.read_bits(1)? as u8;
+        /* Unrestricted motion vectors, SAC, advanced prediction and PB-frames */
+        if br.read_bits(4)? != 0 {
+            return Err(DecodeError::Unsupported);
+        }
+        vop.quant = br.read_bits(5)? as u8;
+        if vop.quant == 0 {
+            return Err(DecodeError::InvalidStream);
+        }
+        /* CPM */
+        br.skip_bits(1)?;
+        while br.read_flag()? {
+            /* PSUPP */
+            br.skip_bits(8)?;
+        }
+        vop.coded = true;
+        vop.fcode_forward = 1;
+
+        Ok((vop, width, height))
+    }
+
+    /* Resync markers are 17 bits long for I-VOPs, longer with larger motion vector ranges */
+    fn get_resync_marker_len(&self) -> u32 {
+        match self.coding_type {
+            VOP_TYPE_I => 17,
+            VOP_TYPE_B => cmp::max(17, 16 + cmp::max(self.fcode_forward, self.fcode_backward) as u32),
+            _ => 16 + self.fcode_forward as u32,
+        }
+    }
+}
+
+/* Returns macroblock_number and quant_scale of a video packet */
+fn parse_video_packet_header(br: &mut BitReader,
+                             vol: &VolHeader,
+                             mb_num: u32)
+                             -> Result<(u32, u8), ()> {
+    let macroblock_number = br.read_bits(get_bit_length(mb_num))?;
+    let quant_scale = br.read_bits(vol.quant_precision as u32)? as u8;
+    if macroblock_number >= mb_num || quant_scale == 0 {
+        return Err(());
+    }
+    /* header_extension_code */
+    if br.read_flag()? {
+        while br.read_flag()? {}
+        read_marker(br)?;
+        br.skip_bits(vol.vop_time_increment_bits as usize)?;
+        read_marker(br)?;
+        let coding_type = br.read_bits(2)? as u8;
+        /* intra_dc_vlc_thr */
+        br.skip_bits(3)?;
+        if coding_type != VOP_TYPE_I {
+            br.skip_bits(3)?;
+        }
+        if coding_type == VOP_TYPE_B {
+            br.skip_bits(3)?;
+        }
+    }
+    Ok((macroblock_number, quant_scale))
+}
+
+fn build_slice_param(size: usize,
+                     bit_offset: usize,
+                     macroblock_number: u32,
+                     quant_scale: u8)
+                     -> ffi::VASliceParameterBufferMPEG4 {
+    let mut param: ffi::VASliceParameterBufferMPEG4 = zeroed();
+    param.slice_data_size = size as u32;
+    param.slice_data_flag = ffi::VA_SLICE_DATA_FLAG_ALL;
+    param.macroblock_offset = (bit_offset % 8) as u32;
+    param.macroblock_number = macroblock_number;
+    param.quant_scale = quant_scale as i32;
+    param
+}
+
+/* Splits the VOP data into video packets; each slice starts at the byte holding its
+ * first macroblock */
+fn get_slices(data: &[u8],
+              vol: &VolHeader,
+              vop: &VopHeader,
+              header_size: usize)
+              -> Vec<(ffi::VASliceParameterBufferMPEG4, Vec<u8>)> {
+    let (mb_width, mb_height) = vol.get_mb_size();
+    let mb_num = mb_width * mb_height;
+    /* (start of the marker, start of the macroblocks, macroblock_number, quant_scale) */
+    let mut packets = vec![(data.len(), header_size, 0, vop.quant)];
+
+    if !vol.resync_marker_disable {
+        let marker_len = vop.get_resync_marker_len();
+        let mut i = header_size / 8 + 1;
+        while i + 3 < data.len() {
+            /* The marker is byte aligned by the stuffing ending the previous packet */
+            if data[i] != 0 || data[i + 1] != 0 || data[i + 2] >> (24 - marker_len) != 1 {
+                i += 1;
+                continue;
+            }
+            let mut br = BitReader::new(&data[i..]);
+            let header = br.skip_bits(marker_len as usize)
+                .and_then(|_| parse_video_packet_header(&mut br, vol, mb_num));
+            match header {
+                Ok((macroblock_number, quant_scale))
+                    if macroblock_number > packets[packets.len() - 1].2 => {
+                    packets.push((i, i * 8 + br.get_pos(), macroblock_number, quant_scale));
+                    i += 3;
+                }
+                _ => i += 1,
+            }
+        }
+    }
+
+    let mut slices = Vec::with_capacity(packets.len());
+    for (n, &(_, start, macroblock_number, quant_scale)) in packets.iter().enumerate() {
+        let end = match packets.get(n + 1) {
+            Some(&(marker, _, _, _)) => marker,
+            None => data.len(),
+        };
+        let slice_data = &data[start / 8..end];
+        slices.push((build_slice_param(slice_data.len(), start, macroblock_number, quant_scale),
+                     slice_data.to_vec()));
+    }
+    slices
+}
+
+#[derive(Debug, Clone)]
+struct GovHeader {
+    closed_gov: bool,
+    broken_link: bool,
+}
+
+#[derive(Debug)]
+pub struct VADecoderMPEG4 {
+    context: Option<VADecodeContext>,
+    short_video_header: Option<bool>,
+    profile_and_level: Option<u8>,
+    vol: Option<VolHeader>,
+    leading_b: Option<GovHeader>,
+    /* Whole seconds of the current and the previous reference VOP */
+    time_base: u32,
+    last_time_base: u32,
+    last_non_b_time: i64,
+    pp_time: i64,
+    prev_ref: Option<VADecodedFrame>,
+    next_ref: Option<VADecodedFrame>,
+    next_ref_type: u8,
+    output: VecDeque<VADecodedFrame>,
+    recovery: VAErrorRecovery,
+    mode: DecodeMode,
+}
+
+impl VADecoderMPEG4 {
+    pub fn new() -> Result<Box<VADecoder>, ()> {
+        Ok(Box::new(VADecoderMPEG4 {
+            context: None,
+            short_video_header: None,
+            profile_and_level: None,
+            vol: None,
+            leading_b: None,
+            time_base: 0,
+            last_time_base: 0,
+            last_non_b_time: 0,
+            pp_time: 0,
+            prev_ref: None,
+            next_ref: None,
+            next_ref_type: VOP_TYPE_I,
+            output: VecDeque::new(),
+            recovery: VAErrorRecovery::new(),
+            mode: DecodeMode::All,
+        }))
+    }
+
+    fn get_profile(&self) -> VAProfile {
+        if self.short_video_header == Some(true) {
+            return ffi::VAProfileH263Baseline;
+        }
+        match self.profile_and_level {
+            Some(0x01..=0x08) => ffi::VAProfileMPEG4Simple,
+            Some(0x32..=0x34) => ffi::VAProfileMPEG4Main,
+            _ => ffi::VAProfileMPEG4AdvancedSimple,
+        }
+    }
+
+    fn ensure_context(&mut self, va_disp: &VADisplay) -> Result<(), DecodeError> {
+        let profile = self.get_profile();
+        let (width, height) = match self.vol {
+            Some(ref vol) => {
+                if vol.chroma_format != 1 {
+                    return Err(DecodeError::Unsupported);
+                }
+                let height = if vol.interlaced {
+                    (vol.height + 31) & !31
+                } else {
+                    (vol.height + 15) & !15
+                };
+                ((vol.width + 15) & !15, height)
+            }
+            None => return Err(DecodeError::InvalidStream),
+        };
+
+        if let Some(ref context) = self.context {
+            if context.is_compatible(profile, ffi::VA_RT_FORMAT_YUV420, width, height) {
+                return Ok(());
+            }
+        }
+
+        self.output_references();
+        if let Some(context) = self.context.take() {
+            context.destroy(va_disp);
+        }
+
+        let context = VADecodeContext::new(va_disp,
+                                           profile,
+                                           ffi::VA_RT_FORMAT_YUV420,
+                                           width,
+                                           height,
+                                           NUM_SURFACES)
+            .map_err(|_| DecodeError::VAFailed)?;
+        self.context = Some(context);
+
+        Ok(())
+    }
+
+    /* Pushes the frames held for reordering to the output queue */
+    fn output_references(&mut self) {
+        if let Some(frame) = self.next_ref.take() {
+            self.output.push_back(frame);
+        }
+        self.prev_ref = None;
+    }
+
+    /* A VOP that cannot be decoded, VOPs predicted from a lost reference are skipped
+     * until the next I-VOP as there is nothing left to predict them from */
+    fn lose_picture(&mut self, is_reference: bool) {
+        self.recovery.drop_frame();
+        if is_reference {
+            self.recovery.lost_reference();
+            self.output_references();
+        }
+    }
+
+    /* A VOP that is not coded repeats the last reference */
+    fn repeat_reference(&mut self, pts: u64) {
+        if self.mode.is_keyframes_only() {
+            self.recovery.skip_frame();
+            return;
+        }
+        if let Some(frame) = self.next_ref.take() {
+            self.output.push_back(frame.clone());
+            self.next_ref = Some(frame.with_pts(pts));
+            self.prev_ref = Some(frame);
+        }
+    }
+
+    /* Returns TRB and TRD, the distances used by direct mode, or None when the
+     * B-VOP does not fall between its references */
+    fn update_time(&mut self, vop: &VopHeader) -> Option<(i64, i64)> {
+        let resolution = match self.vol {
+            Some(ref vol) => vol.vop_time_increment_resolution as i64,
+            None => return None,
+        };
+
+        if vop.coding_type != VOP_TYPE_B {
+            self.last_time_base = self.time_base;
+            self.time_base += vop.modulo_time_base;
+            let time = self.time_base as i64 * resolution + vop.time_increment as i64;
+            self.pp_time = time - self.last_non_b_time;
+            self.last_non_b_time = time;
+            Some((0, 0))
+        } else {
+            let time = (self.last_time_base + vop.modulo_time_base) as i64 * resolution +
+                       vop.time_increment as i64;
+            let pb_time = self.pp_time - (self.last_non_b_time - time);
+            if self.pp_time <= 0 || pb_time <= 0 || pb_time >= self.pp_time {
+                return None;
+            }
+            Some((pb_time, self.pp_time))
+        }
+    }
+
+    fn build_picture_param(&self,
+                           vol: &VolHeader,
+                           vop: &VopHeader,
+                           trb: i64,
+                           trd: i64)
+                           -> Result<ffi::VAPictureParameterBufferMPEG4, ()> {
+        let surface_id = |f: &Option<VADecodedFrame>| match *f {
+            Some(ref frame) => frame.get_surface().get_id(),
+            None => ffi::VA_INVALID_SURFACE,
+        };
+        let (num_gobs_in_vop, num_macroblocks_in_gob) = vol.get_gob_layout();
+
+        let mut param: ffi::VAPictureParameterBufferMPEG4 = zeroed();
+        param.vop_width = vol.width as u16;
+        param.vop_height = vol.height as u16;
+        param.forward_reference_picture = match vop.coding_type {
+            VOP_TYPE_I => ffi::VA_INVALID_SURFACE,
+            _ => surface_id(&self.prev_ref),
+        };
+        param.backward_reference_picture = match vop.coding_type {
+            VOP_TYPE_B => surface_id(&self.next_ref),
+            _ => ffi::VA_INVALID_SURFACE,
+        };
+        param.no_of_sprite_warping_points = vol.no_of_sprite_warping_points;
+        for (i, &(du, dv)) in vop.sprite_trajectory.iter().enumerate() {
+            param.sprite_trajectory_du[i] = du;
+            param.sprite_trajectory_dv[i] = dv;
+        }
+        param.quant_precision = vol.quant_precision;
+        param.vop_fcode_forward = vop.fcode_forward;
+        param.vop_fcode_backward = vop.fcode_backward;
+        param.vop_time_increment_resolution = vol.vop_time_increment_resolution as u16;
+        param.num_gobs_in_vop = num_gobs_in_vop as u8;
+        param.num_macroblocks_in_gob = num_macroblocks_in_gob as u8;
+        param.TRB = trb as i16;
+        param.TRD = trd as i16;
+
+        param.vol_fields = MPEG4VolFields {
+            short_video_header: vol.short_video_header as u32,
+            chroma_format: vol.chroma_format as u32,
+            interlaced: vol.interlaced as u32,
+            obmc_disable: vol.obmc_disable as u32,
+            sprite_enable: vol.sprite_enable as u32,
+            sprite_warping_accuracy: vol.sprite_warping_accuracy as u32,
+            quant_type: vol.quant_type as u32,
+            quarter_sample: vol.quarter_sample as u32,
+            data_partitioned: vol.data_partitioned as u32,
+            reversible_vlc: vol.reversible_vlc as u32,
+            resync_marker_disable: vol.resync_marker_disable as u32,
+        }.to_va()?;
+
+        param.vop_fields = MPEG4VopFields {
+            vop_coding_type: vop.coding_type as u32,
+            backward_reference_vop_coding_type: match vop.coding_type {
+                VOP_TYPE_B => self.next_ref_type as u32,
+                _ => 0,
+            },
+            vop_rounding_type: vop.rounding_type as u32,
+            intra_dc_vlc_thr: vop.intra_dc_vlc_thr as u32,
+            top_field_first: vop.top_field_first as u32,
+            alternate_vertical_scan_flag: vop.alternate_vertical_scan as u32,
+        }.to_va()?;
+
+        Ok(param)
+    }
+
+    fn decode_picture(&mut self,
+                      va_disp: &VADisplay,
+                      data: &[u8],
+                      vop: VopHeader,
+                      header_size: usize,
+                      pts: u64)
+                      -> Result<(), DecodeError> {
+        if !vop.coded {
+            if vop.coding_type != VOP_TYPE_B {
+                self.repeat_reference(pts);
+            }
+            return Ok(());
+        }
+
+        let (trb, trd) = match self.update_time(&vop) {
+            Some(distances) => distances,
+            None => {
+                self.recovery.drop_frame();
+                return Ok(());
+            }
+        };
+        self.ensure_context(va_disp)?;
+        if vop.coding_type == VOP_TYPE_I {
+            self.recovery.recover();
+        }
+
+        if self.mode.skips(vop.coding_type == VOP_TYPE_I, vop.coding_type != VOP_TYPE_B) {
+            self.recovery.skip_frame();
+            return Ok(());
+        }
+
+        /* B-VOPs right after the I-VOP of a closed GOV only predict backwards,
+         * those of a broken link cannot be decoded at all */
+        let skip = match vop.coding_type {
+            VOP_TYPE_P | VOP_TYPE_S => self.next_ref.is_none(),
+            VOP_TYPE_B => {
+                match self.leading_b {
+                    Some(ref gov) if gov.broken_link => true,
+                    Some(ref gov) if gov.closed_gov => self.next_ref.is_none(),
+                    _ => self.prev_ref.is_none() || self.next_ref.is_none(),
+                }
+            }
+            _ => false,
+        };
+        if vop.coding_type == VOP_TYPE_P || vop.coding_type == VOP_TYPE_S {
+            self.leading_b = None;
+        }
+        if skip {
+            self.recovery.drop_frame();
+            return Ok(());
+        }
+
+        if vop.coding_type != VOP_TYPE_B {
+            self.prev_ref = self.next_ref.take();
+            if let Some(ref frame) = self.prev_ref {
+                self.output.push_back(frame.clone());
+            }
+        }
+
+        let vol = match self.vol {
+            Some(ref vol) => vol.clone(),
+            None => return Err(DecodeError::InvalidStream),
+        };
+        let surface = match self.context {
+            Some(ref context) => context.get_surface(),
+            None => None,
+        };
+        let surface = surface.ok_or(DecodeError::NoSurface)?;
+        let field_order = match (vol.interlaced, vop.top_field_first) {
+            (false, _) => FieldOrder::Progressive,
+            (true, true) => FieldOrder::TopFieldFirst,
+            (true, false) => FieldOrder::BottomFieldFirst,
+        };
+        let mut frame = VADecodedFrame::new(surface, vol.width, vol.height, pts)
+            .with_field_order(field_order);
+        let is_corrupted = |f: &Option<VADecodedFrame>| {
+            f.as_ref().map_or(false, |frame| frame.is_corrupted())
+        };
+        let corrupted = match vop.coding_type {
+            VOP_TYPE_P | VOP_TYPE_S => is_corrupted(&self.prev_ref),
+            VOP_TYPE_B => is_corrupted(&self.prev_ref) || is_corrupted(&self.next_ref),
+            _ => false,
+        };
+        if corrupted {
+            frame.mark_corrupted();
+        }
+
+        let pic_param = self.build_picture_param(&vol, &vop, trb, trd)?;
+        let mut iq_matrix: ffi::VAIQMatrixBufferMPEG4 = zeroed();
+        iq_matrix.load_intra_quant_mat = 1;
+        iq_matrix.load_non_intra_quant_mat = 1;
+        iq_matrix.intra_quant_mat = vol.intra_quant_mat;
+        iq_matrix.non_intra_quant_mat = vol.non_intra_quant_mat;
+        let slices = get_slices(data, &vol, &vop, header_size);
+
+        let mut info = VAFrameInfo {
+            picture_type: match vop.coding_type {
+                VOP_TYPE_I => Some(PictureType::I),
+                VOP_TYPE_B => Some(PictureType::B),
+                _ => Some(PictureType::P),
+            },
+            bytes: data.len(),
+            num_references: match vop.coding_type {
+                VOP_TYPE_I => 0,
+                VOP_TYPE_B => 2,
+                _ => 1,
+            },
+            ..VAFrameInfo::default()
+        };
+        for &(ref slice_param, _) in &slices {
+            info.add_qp(slice_param.quant_scale);
+        }
+        frame = frame.with_info(info);
+
+        {
+            let context = self.context.as_ref().ok_or(DecodeError::InvalidStream)?;
+            let mut submission = PictureSubmission::new(va_disp, context)
+                .add_param(&pic_param);
+            /* The weighting matrices only apply to the MPEG quantization method */
+            if vol.quant_type {
+                submission = submission.and_then(|s| s.add_param(&iq_matrix));
+            }
+            for &(ref slice_param, ref data) in &slices {
+                submission = submission.and_then(|s| s.add_slice(slice_param, data));
+            }
+            submission.and_then(|s| s.submit(frame.get_surface()))
+                .map_err(|_| DecodeError::VAFailed)?;
+        }
+
+        self.recovery.frame_decoded(&frame);
+        if vop.coding_type == VOP_TYPE_B || self.mode.is_keyframes_only() {
+            self.output.push_back(frame);
+        } else {
+            self.next_ref = Some(frame);
+            self.next_ref_type = vop.coding_type;
+        }
+
+        Ok(())
+    }
+
+    fn decode_unit(&mut self,
+                   va_disp: &VADisplay,
+                   data: &[u8],
+                   pts: u64)
+                   -> Result<(), DecodeError> {
+        let code = data[3];
+        let payload = &data[4..];
+        let mut br = BitReader::new(payload);
+
+        match code {
+            VOP_START_CODE => {
+                let vop = match self.vol {
+                    Some(ref vol) => VopHeader::parse(&mut br, vol),
+                    None => return Ok(()),
+                };
+                let vop = match vop {
+                    Err(DecodeError::InvalidStream) => {
+                        self.lose_picture(true);
+                        return Ok(());
+                    }
+                    ret => ret?,
+                };
+                let header_size = br.get_pos();
+                self.decode_picture(va_disp, payload, vop, header_size, pts)?;
+            }
+            VIDEO_OBJECT_LAYER_MIN_START_CODE..=VIDEO_OBJECT_LAYER_MAX_START_CODE => {
+                self.vol = Some(VolHeader::parse(&mut br)?);
+            }
+            VISUAL_OBJECT_SEQUENCE_START_CODE => {
+                self.profile_and_level = Some(br.read_bits(8)? as u8);
+            }
+            GROUP_OF_VOP_START_CODE => {
+                let hours = br.read_bits(5)?;
+                let minutes = br.read_bits(6)?;
+                read_marker(&mut br)?;
+                let seconds = br.read_bits(6)?;
+                self.time_base = (hours * 60 + minutes) * 60 + seconds;
+                self.leading_b = Some(GovHeader {
+                    closed_gov: br.read_flag()?,
+                    broken_link: br.read_flag()?,
+                });
+            }
+            VISUAL_OBJECT_SEQUENCE_END_CODE => self.output_references(),
+            /* Video objects and user data */
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn decode_short_header(&mut self,
+                           va_disp: &VADisplay,
+                           data: &[u8],
+                           pts: u64)
+                           -> Result<(), DecodeError> {
+        let mut br = BitReader::new(data);
+        let (vop, width, height) = match VopHeader::parse_short_header(&mut br) {
+            Err(DecodeError::InvalidStream) => {
+                self.lose_picture(true);
+                return Ok(());
+            }
+            ret => ret?,
+        };
+        let header_size = br.get_pos();
+
+        let size_changed = match self.vol {
+            Some(ref vol) => vol.width != width || vol.height != height,
+            None => true,
+        };
+        if size_changed {
+            self.vol = Some(VolHeader::new_short_header(width, height));
+        }
+
+        self.decode_picture(va_disp, data, vop, header_size, pts)
+    }
+}
+
+impl VADecoder for VADecoderMPEG4 {
+    fn decode(&mut self, va_disp: &VADisplay, data: &[u8], pts: u64) -> Result<(), DecodeError> {
+        if self.short_video_header.is_none() {
+            if data.len() < 4 || data[0] != 0 || data[1] != 0 {
+                return Err(DecodeError::InvalidStream);
+            }
+            if data[2] == 1 {
+                self.short_video_header = Some(false);
+            } else if data[2] & 0xfc == 0x80 {
+                self.short_video_header = Some(true);
+            } else {
+                return Err(DecodeError::InvalidStream);
+            }
+        }
+
+        if self.short_video_header == Some(true) {
+            let offsets = find_short_header_start_codes(data);
+            for (i, &start) in offsets.iter().enumerate() {
+                let end = offsets.get(i + 1).cloned().unwrap_or(data.len());
+                self.decode_short_header(va_disp, &data[start..end], pts)?;
+            }
+        } else {
+            let offsets = find_start_codes(data);
+            for (i, &start) in offsets.iter().enumerate() {
+                let end = offsets.get(i + 1).cloned().unwrap_or(data.len());
+                if end - start < 4 {
+                    continue;
+                }
+                match self.decode_unit(va_disp, &data[start..end], pts) {
+                    /* Broken headers are ignored, the VOPs needing them are dropped */
+                    Ok(_) | Err(DecodeError::InvalidStream) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn drain(&mut self, _: &VADisplay) -> Result<(), DecodeError> {
+        self.output_references();
+        Ok(())
+    }
+
+    /* Without references, VOPs up to the next I-VOP are skipped */
+    fn flush(&mut self) {
+        self.prev_ref = None;
+        self.next_ref = None;
+        self.next_ref_type = VOP_TYPE_I;
+        self.leading_b = None;
+        self.time_base = 0;
+        self.last_time_base = 0;
+        self.last_non_b_time = 0;
+        self.pp_time = 0;
+        self.output.clear();
+    }
+
+    fn get_frame(&mut self) -> Option<VADecodedFrame> {
+        self.output.pop_front()
+    }
+
+    fn get_stats(&self) -> VADecodeStats {
+        self.recovery.get_stats().with_context(self.context.as_ref())
+    }
+
+    fn set_decode_mode(&mut self, mode: DecodeMode) {
+        self.mode = mode;
+    }
+
+    fn destroy(&mut self, va_disp: &VADisplay) {
+        self.prev_ref = None;
+        self.next_ref = None;
+        self.output.clear();
+        if let Some(context) = self.context.take() {
+            context.destroy(va_disp);
+        }
+    }
+}
@@ -0,0 +1,1856 @@
+// Copyright (c) 2017 zzoon <zzoon@igalia.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::VecDeque;
+use std::mem;
+
+use bitfields::*;
+use bitreader::BitReader;
+use decoder::*;
+use ffi;
+use va::*;
+
+const NUM_SURFACES: usize = 8;
+
+const END_OF_SEQUENCE: u8 = 0x0a;
+const SLICE_START_CODE: u8 = 0x0b;
+const FIELD_START_CODE: u8 = 0x0c;
+const FRAME_START_CODE: u8 = 0x0d;
+const ENTRY_POINT_START_CODE: u8 = 0x0e;
+const SEQUENCE_START_CODE: u8 = 0x0f;
+
+const RCV_HEADER_SIZE: usize = 36;
+const RCV_FRAME_HEADER_SIZE: usize = 8;
+
+const PROFILE_SIMPLE: u8 = 0;
+const PROFILE_MAIN: u8 = 1;
+const PROFILE_ADVANCED: u8 = 3;
+
+/* Picture types, numbered as VAPictureParameterBufferVC1 expects them */
+const PICTURE_TYPE_I: u8 = 0;
+const PICTURE_TYPE_P: u8 = 1;
+const PICTURE_TYPE_B: u8 = 2;
+const PICTURE_TYPE_BI: u8 = 3;
+const PICTURE_TYPE_SKIPPED: u8 = 4;
+
+const ADVANCED_PICTURE_TYPES: [u8; 5] = [PICTURE_TYPE_P,
+                                         PICTURE_TYPE_B,
+                                         PICTURE_TYPE_I,
+                                         PICTURE_TYPE_BI,
+                                         PICTURE_TYPE_SKIPPED];
+
+const FCM_PROGRESSIVE: u8 = 0;
+const FCM_FRAME_INTERLACE: u8 = 1;
+const FCM_FIELD_INTERLACE: u8 = 2;
+
+const QUANT_FRAME_IMPLICIT: u8 = 0;
+const QUANT_FRAME_EXPLICIT: u8 = 1;
+const QUANT_NON_UNIFORM: u8 = 2;
+
+const DQPROFILE_DOUBLE_EDGES: u8 = 1;
+const DQPROFILE_SINGLE_EDGE: u8 = 2;
+const DQPROFILE_ALL_MBS: u8 = 3;
+
+const BFRACTION_RESERVED: u8 = 21;
+const BFRACTION_BI: u8 = 22;
+
+const MV_MODE_1MV: u8 = ffi::VAMvMode1Mv as u8;
+const MV_MODE_1MV_HPEL: u8 = ffi::VAMvMode1MvHalfPel as u8;
+const MV_MODE_1MV_HPEL_BILINEAR: u8 = ffi::VAMvMode1MvHalfPelBilinear as u8;
+const MV_MODE_MIXED: u8 = ffi::VAMvModeMixedMv as u8;
+const MV_MODE_IC: u8 = ffi::VAMvModeIntensityCompensation as u8;
+
+/* MVMODE and MVMODE2 tables, the first row is used when PQUANT > 12 */
+const MV_MODE_TABLE: [[u8; 5]; 2] =
+    [[MV_MODE_1MV_HPEL_BILINEAR, MV_MODE_1MV, MV_MODE_1MV_HPEL, MV_MODE_IC, MV_MODE_MIXED],
+     [MV_MODE_1MV, MV_MODE_MIXED, MV_MODE_1MV_HPEL, MV_MODE_IC, MV_MODE_1MV_HPEL_BILINEAR]];
+const MV_MODE2_TABLE: [[u8; 4]; 2] =
+    [[MV_MODE_1MV_HPEL_BILINEAR, MV_MODE_1MV, MV_MODE_1MV_HPEL, MV_MODE_MIXED],
+     [MV_MODE_1MV, MV_MODE_MIXED, MV_MODE_1MV_HPEL, MV_MODE_1MV_HPEL_BILINEAR]];
+
+/* PQINDEX to PQUANT with the implicit quantizer */
+const PQUANT_IMPLICIT: [u8; 32] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 6, 7, 8, 9, 10, 11, 12, 13, 14,
+                                   15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 27, 29, 31];
+
+const IMODE_RAW: u8 = 0;
+const IMODE_NORM2: u8 = 1;
+const IMODE_DIFF2: u8 = 2;
+const IMODE_NORM6: u8 = 3;
+const IMODE_DIFF6: u8 = 4;
+const IMODE_ROWSKIP: u8 = 5;
+const IMODE_COLSKIP: u8 = 6;
+
+/* Norm-6 tile codes as (code, length), indexed by the six bits of the tile */
+const NORM6_CODES: [(u32, u32); 64] =
+    [(1, 1), (2, 4), (3, 4), (0, 8), (4, 4), (1, 8), (2, 8), (71, 10), (5, 4), (3, 8), (4, 8),
+     (75, 10), (5, 8), (77, 10), (78, 10), (782, 13), (6, 4), (6, 8), (7, 8), (83, 10), (8, 8),
+     (85, 10), (86, 10), (781, 13), (9, 8), (89, 10), (90, 10), (780, 13), (92, 10), (779, 13),
+     (778, 13), (55, 9), (7, 4), (10, 8), (11, 8), (67, 10), (12, 8), (69, 10), (70, 10),
+     (777, 13), (13, 8), (73, 10), (74, 10), (776, 13), (76, 10), (775, 13), (774, 13), (54, 9),
+     (14, 8), (81, 10), (82, 10), (773, 13), (84, 10), (772, 13), (771, 13), (53, 9), (88, 10),
+     (770, 13), (769, 13), (52, 9), (768, 13), (51, 9), (50, 9), (7, 6)];
+
+fn read_le32(data: &[u8]) -> u32 {
+    (data[0] as u32) | ((data[1] as u32) << 8) | ((data[2] as u32) << 16) |
+    ((data[3] as u32) << 24)
+}
+
+/* Counts the bits read before `stop`, reading at most `len` of them */
+fn read_unary(br: &mut BitReader, stop: u32, len: u32) -> Result<u32, ()> {
+    let mut count = 0;
+    while count < len && br.read_bits(1)? != stop {
+        count += 1;
+    }
+    Ok(count)
+}
+
+/* 0 -> 0, 10 -> 1, 11 -> 2 */
+fn read_012(br: &mut BitReader) -> Result<u8, ()> {
+    if !br.read_flag()? {
+        return Ok(0);
+    }
+    Ok(br.read_bits(1)? as u8 + 1)
+}
+
+/* Index into the BFRACTION table, 22 marks a BI picture */
+fn read_bfraction(br: &mut BitReader) -> Result<u8, ()> {
+    let index = br.read_bits(3)? as u8;
+    if index < 7 {
+        return Ok(index);
+    }
+    let index = 7 + br.read_bits(4)? as u8;
+    if index == BFRACTION_RESERVED {
+        return Err(());
+    }
+    Ok(index)
+}
+
+/* Strips the emulation prevention bytes of an advanced profile unit */
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut zeros = 0;
+    for (i, &byte) in data.iter().enumerate() {
+        if zeros >= 2 && byte == 3 && data.get(i + 1).map_or(false, |&next| next < 4) {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        out.push(byte);
+    }
+    out
+}
+
+#[derive(Debug, Clone)]
+struct Bitplane {
+    /* Coded at macroblock level rather than in the picture header */
+    raw: bool,
+    bits: Vec<u8>,
+}
+
+fn read_imode(br: &mut BitReader) -> Result<u8, ()> {
+    Ok(match br.read_bits(2)? {
+        0b10 => IMODE_NORM2,
+        0b11 => IMODE_NORM6,
+        0b01 => if br.read_flag()? { IMODE_COLSKIP } else { IMODE_ROWSKIP },
+        _ => {
+            if br.read_flag()? {
+                IMODE_DIFF2
+            } else if br.read_flag()? {
+                IMODE_DIFF6
+            } else {
+                IMODE_RAW
+            }
+        }
+    })
+}
+
+/* 0 -> (0, 0), 100 -> (1, 0), 101 -> (0, 1), 11 -> (1, 1) */
+fn read_norm2(br: &mut BitReader) -> Result<u8, ()> {
+    if !br.read_flag()? {
+        Ok(0)
+    } else if !br.read_flag()? {
+        Ok(1 + br.read_bits(1)? as u8)
+    } else {
+        Ok(3)
+    }
+}
+
+fn read_norm6(br: &mut BitReader) -> Result<u32, ()> {
+    for (value, &(code, len)) in NORM6_CODES.iter().enumerate() {
+        if br.peek_bits(len) == Ok(code) {
+            br.skip_bits(len as usize)?;
+            return Ok(value as u32);
+        }
+    }
+    Err(())
+}
+
+fn decode_rowskip(br: &mut BitReader,
+                  plane: &mut [u8],
+                  offset: usize,
+                  width: usize,
+                  height: usize,
+                  stride: usize)
+                  -> Result<(), ()> {
+    for y in 0..height {
+        if br.read_flag()? {
+            for x in 0..width {
+                plane[offset + y * stride + x] = br.read_bits(1)? as u8;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn decode_colskip(br: &mut BitReader,
+                  plane: &mut [u8],
+                  width: usize,
+                  height: usize,
+                  stride: usize)
+                  -> Result<(), ()> {
+    for x in 0..width {
+        if br.read_flag()? {
+            for y in 0..height {
+                plane[y * stride + x] = br.read_bits(1)? as u8;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn decode_norm6(br: &mut BitReader,
+                plane: &mut [u8],
+                width: usize,
+                height: usize)
+                -> Result<(), ()> {
+    if height % 3 == 0 && width % 3 != 0 {
+        /* 2x3 tiles, an odd first column is coded with column skip */
+        let x0 = width & 1;
+        for y in (0..height).step_by(3) {
+            for x in (x0..width).step_by(2) {
+                let code = read_norm6(br)?;
+                for k in 0..6 {
+                    plane[(y + k / 2) * width + x + k % 2] = ((code >> k) & 1) as u8;
+                }
+            }
+        }
+        if x0 != 0 {
+            decode_colskip(br, plane, 1, height, width)?;
+        }
+    } else {
+        /* 3x2 tiles, the leftover columns and top row use column and row skip */
+        let x0 = width % 3;
+        let y0 = height & 1;
+        for y in (y0..height).step_by(2) {
+            for x in (x0..width).step_by(3) {
+                let code = read_norm6(br)?;
+                for k in 0..6 {
+                    plane[(y + k / 3) * width + x + k % 3] = ((code >> k) & 1) as u8;
+                }
+            }
+        }
+        if x0 != 0 {
+            decode_colskip(br, plane, x0, height, width)?;
+        }
+        if y0 != 0 {
+            decode_rowskip(br, plane, x0, width - x0, 1, width)?;
+        }
+    }
+    Ok(())
+}
+
+/* Bitplane coding of SMPTE 421M 8.7, one byte per macroblock */
+fn decode_bitplane(br: &mut BitReader, width: usize, height: usize) -> Result<Bitplane, ()> {
+    let invert = br.read_bits(1)? as u8;
+    let imode = read_imode(br)?;
+    let mut plane = vec![0; width * height];
+
+    match imode {
+        IMODE_RAW => {
+            return Ok(Bitplane {
+                raw: true,
+                bits: Vec::new(),
+            })
+        }
+        IMODE_NORM2 | IMODE_DIFF2 => {
+            let mut i = 0;
+            if plane.len() & 1 == 1 {
+                plane[0] = br.read_bits(1)? as u8;
+                i = 1;
+            }
+            while i < plane.len() {
+                let code = read_norm2(br)?;
+                plane[i] = code & 1;
+                plane[i + 1] = code >> 1;
+                i += 2;
+            }
+        }
+        IMODE_NORM6 | IMODE_DIFF6 => decode_norm6(br, &mut plane, width, height)?,
+        IMODE_ROWSKIP => decode_rowskip(br, &mut plane, 0, width, height, width)?,
+        _ => decode_colskip(br, &mut plane, width, height, width)?,
+    }
+
+    if imode == IMODE_DIFF2 || imode == IMODE_DIFF6 {
+        for y in 0..height {
+            for x in 0..width {
+                let i = y * width + x;
+                let pred = if x == 0 && y == 0 {
+                    invert
+                } else if y == 0 {
+                    plane[i - 1]
+                } else if x == 0 {
+                    plane[i - width]
+                } else if plane[i - 1] != plane[i - width] {
+                    invert
+                } else {
+                    plane[i - 1]
+                };
+                plane[i] ^= pred;
+            }
+        }
+    } else if invert == 1 {
+        for bit in plane.iter_mut() {
+            *bit ^= 1;
+        }
+    }
+
+    Ok(Bitplane {
+        raw: false,
+        bits: plane,
+    })
+}
+
+fn is_raw(plane: &Option<Bitplane>) -> bool {
+    match *plane {
+        Some(ref plane) => plane.raw,
+        None => false,
+    }
+}
+
+fn get_plane_bits(plane: &Option<Bitplane>) -> Option<&[u8]> {
+    match *plane {
+        Some(ref plane) if !plane.raw => Some(&plane.bits),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct SequenceHeader {
+    profile: u8,
+    coded_width: u32,
+    coded_height: u32,
+    /* Simple and main profiles */
+    res_y411: bool,
+    res_x8: bool,
+    multires: bool,
+    syncmarker: bool,
+    rangered: bool,
+    max_b_frames: u8,
+    finterpflag: bool,
+    /* Advanced profile */
+    chroma_format: u8,
+    postprocflag: bool,
+    pulldown: bool,
+    interlace: bool,
+    tfcntrflag: bool,
+    psf: bool,
+    hrd_num_leaky_buckets: u32,
+}
+
+/* Entry point header; simple and main profiles carry the same fields in STRUCT_C */
+#[derive(Debug, Clone, Default)]
+struct EntryPoint {
+    broken_link: bool,
+    closed_entry: bool,
+    panscan: bool,
+    refdist_flag: bool,
+    loopfilter: bool,
+    fastuvmc: bool,
+    extended_mv: bool,
+    dquant: u8,
+    vstransform: bool,
+    overlap: bool,
+    quantizer: u8,
+    extended_dmv: bool,
+    range_mapy: Option<u8>,
+    range_mapuv: Option<u8>,
+}
+
+impl SequenceHeader {
+    /* STRUCT_C of the RCV header, the frame size comes from STRUCT_A */
+    fn parse_struct_c(br: &mut BitReader,
+                      width: u32,
+                      height: u32)
+                      -> Result<(SequenceHeader, EntryPoint), ()> {
+        let mut seq = SequenceHeader::default();
+        let mut entry = EntryPoint::default();
+
+        seq.profile = br.read_bits(2)? as u8;
+        seq.res_y411 = br.read_flag()?;
+        /* RES_SPRITE, FRMRTQ_POSTPROC, BITRTQ_POSTPROC */
+        br.skip_bits(1 + 3 + 5)?;
+        entry.loopfilter = br.read_flag()?;
+        seq.res_x8 = br.read_flag()?;
+        seq.multires = br.read_flag()?;
+        /* RES_FASTTX */
+        br.skip_bits(1)?;
+        entry.fastuvmc = br.read_flag()?;
+        entry.extended_mv = br.read_flag()?;
+        entry.dquant = br.read_bits(2)? as u8;
+        entry.vstransform = br.read_flag()?;
+        /* RES_TRANSTAB */
+        br.skip_bits(1)?;
+        entry.overlap = br.read_flag()?;
+        seq.syncmarker = br.read_flag()?;
+        seq.rangered = br.read_flag()?;
+        seq.max_b_frames = br.read_bits(3)? as u8;
+        entry.quantizer = br.read_bits(2)? as u8;
+        seq.finterpflag = br.read_flag()?;
+
+        seq.chroma_format = 1;
+        seq.coded_width = width;
+        seq.coded_height = height;
+
+        Ok((seq, entry))
+    }
+
+    fn parse_advanced(br: &mut BitReader) -> Result<SequenceHeader, ()> {
+        let mut seq = SequenceHeader::default();
+
+        seq.profile = br.read_bits(2)? as u8;
+        if seq.profile != PROFILE_ADVANCED {
+            return Err(());
+        }
+        /* LEVEL */
+        br.skip_bits(3)?;
+        seq.chroma_format = br.read_bits(2)? as u8;
+        /* FRMRTQ_POSTPROC, BITRTQ_POSTPROC */
+        br.skip_bits(3 + 5)?;
+        seq.postprocflag = br.read_flag()?;
+        seq.coded_width = (br.read_bits(12)? + 1) * 2;
+        seq.coded_height = (br.read_bits(12)? + 1) * 2;
+        seq.pulldown = br.read_flag()?;
+        seq.interlace = br.read_flag()?;
+        seq.tfcntrflag = br.read_flag()?;
+        seq.finterpflag = br.read_flag()?;
+        /* reserved */
+        br.skip_bits(1)?;
+        seq.psf = br.read_flag()?;
+        seq.max_b_frames = 7;
+
+        if br.read_flag()? {
+            /* DISP_HORIZ_SIZE, DISP_VERT_SIZE */
+            br.skip_bits(14 + 14)?;
+            if br.read_flag()? && br.read_bits(4)? == 15 {
+                /* ASPECT_HORIZ_SIZE, ASPECT_VERT_SIZE */
+                br.skip_bits(8 + 8)?;
+            }
+            if br.read_flag()? {
+                if br.read_flag()? {
+                    /* FRAMERATEEXP */
+                    br.skip_bits(16)?;
+                } else {
+                    /* FRAMERATENR, FRAMERATEDR */
+                    br.skip_bits(8 + 4)?;
+                }
+            }
+            if br.read_flag()? {
+                /* COLOR_PRIM, TRANSFER_CHAR, MATRIX_COEF */
+                br.skip_bits(8 + 8 + 8)?;
+            }
+        }
+
+        if br.read_flag()? {
+            seq.hrd_num_leaky_buckets = br.read_bits(5)?;
+            /* BIT_RATE_EXPONENT, BUFFER_SIZE_EXPONENT, HRD_RATE and HRD_BUFFER per bucket */
+            br.skip_bits(4 + 4 + seq.hrd_num_leaky_buckets as usize * 32)?;
+        }
+
+        Ok(seq)
+    }
+
+    fn get_mb_size(&self, fcm: u8) -> (usize, usize) {
+        let mb_width = (self.coded_width as usize + 15) / 16;
+        let mb_height = (self.coded_height as usize + 15) / 16;
+        if fcm == FCM_FIELD_INTERLACE {
+            (mb_width, mb_height >> 1)
+        } else {
+            (mb_width, mb_height)
+        }
+    }
+}
+
+impl EntryPoint {
+    /* Returns the entry point and the coded size it overrides, if any */
+    fn parse(br: &mut BitReader, seq: &SequenceHeader) -> Result<(EntryPoint, Option<(u32, u32)>), ()> {
+        let mut entry = EntryPoint::default();
+
+        entry.broken_link = br.read_flag()?;
+        entry.closed_entry = br.read_flag()?;
+        entry.panscan = br.read_flag()?;
+        entry.refdist_flag = br.read_flag()?;
+        entry.loopfilter = br.read_flag()?;
+        entry.fastuvmc = br.read_flag()?;
+        entry.extended_mv = br.read_flag()?;
+        entry.dquant = br.read_bits(2)? as u8;
+        entry.vstransform = br.read_flag()?;
+        entry.overlap = br.read_flag()?;
+        entry.quantizer = br.read_bits(2)? as u8;
+        /* HRD_FULL */
+        br.skip_bits(seq.hrd_num_leaky_buckets as usize * 8)?;
+
+        let mut coded_size = None;
+        if br.read_flag()? {
+            let width = (br.read_bits(12)? + 1) * 2;
+            let height = (br.read_bits(12)? + 1) * 2;
+            coded_size = Some((width, height));
+        }
+        if entry.extended_mv {
+            entry.extended_dmv = br.read_flag()?;
+        }
+        if br.read_flag()? {
+            entry.range_mapy = Some(br.read_bits(3)? as u8);
+        }
+        if br.read_flag()? {
+            entry.range_mapuv = Some(br.read_bits(3)? as u8);
+        }
+
+        Ok((entry, coded_size))
+    }
+}
+
+/* Picture type of either field given FPTYPE */
+fn get_field_picture_type(fptype: u8, first_field: bool) -> u8 {
+    let bit = if first_field { fptype & 2 } else { fptype & 1 };
+    match (fptype & 4 != 0, bit != 0) {
+        (false, false) => PICTURE_TYPE_I,
+        (false, true) => PICTURE_TYPE_P,
+        (true, false) => PICTURE_TYPE_B,
+        (true, true) => PICTURE_TYPE_BI,
+    }
+}
+
+fn is_reference(picture_type: u8) -> bool {
+    picture_type == PICTURE_TYPE_I || picture_type == PICTURE_TYPE_P
+}
+
+fn is_intra(picture_type: u8) -> bool {
+    picture_type == PICTURE_TYPE_I || picture_type == PICTURE_TYPE_BI
+}
+
+#[derive(Debug, Clone, Default)]
+struct PictureHeader {
+    picture_type: u8,
+    fcm: u8,
+    fptype: u8,
+    tff: bool,
+    rnd: bool,
+    rangeredfrm: bool,
+    respic: u8,
+    x8_type: bool,
+    bfraction: u8,
+    refdist: u8,
+    postproc: u8,
+    pq: u8,
+    halfqp: bool,
+    pquantizer: bool,
+    mvrange: u8,
+    dmvrange: u8,
+    mv_mode: u8,
+    mv_mode2: u8,
+    intcomp: bool,
+    lumscale: u8,
+    lumshift: u8,
+    numref: bool,
+    reffield: bool,
+    mvswitch4: bool,
+    mvtab: u8,
+    cbptab: u8,
+    mbmodetab: u8,
+    twomvbptab: u8,
+    fourmvbptab: u8,
+    condover: u8,
+    dquantfrm: bool,
+    dqprofile: u8,
+    dqsbedge: u8,
+    dqbilevel: bool,
+    altpq: u8,
+    ttmbf: bool,
+    ttfrm: u8,
+    transacfrm: u8,
+    transacfrm2: u8,
+    transdctab: bool,
+    mv_type_mb: Option<Bitplane>,
+    direct_mb: Option<Bitplane>,
+    skip_mb: Option<Bitplane>,
+    field_tx: Option<Bitplane>,
+    forward_mb: Option<Bitplane>,
+    ac_pred: Option<Bitplane>,
+    overflags: Option<Bitplane>,
+}
+
+impl PictureHeader {
+    fn parse_simple(br: &mut BitReader,
+                    seq: &SequenceHeader,
+                    entry: &EntryPoint)
+                    -> Result<PictureHeader, ()> {
+        let mut hdr = PictureHeader::default();
+        let (mb_width, mb_height) = seq.get_mb_size(FCM_PROGRESSIVE);
+
+        if seq.finterpflag {
+            /* INTERPFRM */
+            br.skip_bits(1)?;
+        }
+        /* FRMCNT */
+        br.skip_bits(2)?;
+        if seq.rangered {
+            hdr.rangeredfrm = br.read_flag()?;
+        }
+        hdr.picture_type = if br.read_flag()? {
+            PICTURE_TYPE_P
+        } else if seq.max_b_frames == 0 || br.read_flag()? {
+            PICTURE_TYPE_I
+        } else {
+            PICTURE_TYPE_B
+        };
+        if hdr.picture_type == PICTURE_TYPE_B {
+            hdr.bfraction = read_bfraction(br)?;
+            if hdr.bfraction == BFRACTION_BI {
+                hdr.picture_type = PICTURE_TYPE_BI;
+            }
+        }
+        if is_intra(hdr.picture_type) {
+            /* BF */
+            br.skip_bits(7)?;
+        }
+
+        hdr.parse_quantizer(br, entry)?;
+        if entry.extended_mv {
+            hdr.mvrange = read_unary(br, 0, 3)? as u8;
+        }
+        if seq.multires && hdr.picture_type != PICTURE_TYPE_B {
+            hdr.respic = br.read_bits(2)? as u8;
+        }
+        if seq.res_x8 && is_intra(hdr.picture_type) {
+            hdr.x8_type = br.read_flag()?;
+        }
+
+        match hdr.picture_type {
+            PICTURE_TYPE_P => {
+                hdr.parse_mv_mode(br)?;
+                if hdr.mv_mode == MV_MODE_IC {
+                    hdr.lumscale = br.read_bits(6)? as u8;
+                    hdr.lumshift = br.read_bits(6)? as u8;
+                }
+                if hdr.is_mixed_mv() {
+                    hdr.mv_type_mb = Some(decode_bitplane(br, mb_width, mb_height)?);
+                }
+                hdr.skip_mb = Some(decode_bitplane(br, mb_width, mb_height)?);
+                hdr.mvtab = br.read_bits(2)? as u8;
+                hdr.cbptab = br.read_bits(2)? as u8;
+                hdr.parse_vopdquant(br, entry)?;
+                hdr.parse_transform_type(br, entry)?;
+            }
+            PICTURE_TYPE_B => {
+                hdr.mv_mode = if br.read_flag()? {
+                    MV_MODE_1MV
+                } else {
+                    MV_MODE_1MV_HPEL_BILINEAR
+                };
+                hdr.direct_mb = Some(decode_bitplane(br, mb_width, mb_height)?);
+                hdr.skip_mb = Some(decode_bitplane(br, mb_width, mb_height)?);
+                hdr.mvtab = br.read_bits(2)? as u8;
+                hdr.cbptab = br.read_bits(2)? as u8;
+                hdr.parse_vopdquant(br, entry)?;
+                hdr.parse_transform_type(br, entry)?;
+            }
+            _ => {}
+        }
+
+        if !hdr.x8_type {
+            hdr.parse_transform_tables(br)?;
+        }
+
+        Ok(hdr)
+    }
+
+    /* Frame header of the advanced profile, up to the first field layer */
+    fn parse_advanced(br: &mut BitReader,
+                      seq: &SequenceHeader,
+                      entry: &EntryPoint)
+                      -> Result<PictureHeader, ()> {
+        let mut hdr = PictureHeader::default();
+
+        if seq.interlace {
+            hdr.fcm = read_012(br)?;
+        }
+        if hdr.fcm == FCM_FIELD_INTERLACE {
+            hdr.fptype = br.read_bits(3)? as u8;
+            hdr.picture_type = get_field_picture_type(hdr.fptype, true);
+        } else {
+            hdr.picture_type = ADVANCED_PICTURE_TYPES[read_unary(br, 0, 4)? as usize];
+        }
+        if seq.tfcntrflag {
+            /* TFCNTR */
+            br.skip_bits(8)?;
+        }
+
+        hdr.tff = true;
+        let mut rptfrm = 0;
+        let mut rff = false;
+        if seq.pulldown {
+            if !seq.interlace || seq.psf {
+                rptfrm = br.read_bits(2)?;
+            } else {
+                hdr.tff = br.read_flag()?;
+                rff = br.read_flag()?;
+            }
+        }
+        if entry.panscan && br.read_flag()? {
+            let windows = if !seq.interlace || seq.psf {
+                rptfrm + 1
+            } else {
+                2 + rff as u32
+            };
+            /* PS_HOFFSET, PS_VOFFSET, PS_WIDTH, PS_HEIGHT per window */
+            br.skip_bits(windows as usize * (18 + 18 + 14 + 14))?;
+        }
+        if hdr.picture_type == PICTURE_TYPE_SKIPPED {
+            return Ok(hdr);
+        }
+
+        hdr.rnd = br.read_flag()?;
+        if seq.interlace {
+            /* UVSAMP */
+            br.skip_bits(1)?;
+        }
+        if hdr.fcm == FCM_FIELD_INTERLACE {
+            let b_field = hdr.fptype & 4 != 0;
+            if entry.refdist_flag && !b_field {
+                hdr.refdist = br.read_bits(2)? as u8;
+                if hdr.refdist == 3 {
+                    hdr.refdist += read_unary(br, 0, 14)? as u8;
+                }
+            }
+            if b_field {
+                hdr.bfraction = read_bfraction(br)?;
+            }
+        } else if hdr.fcm == FCM_PROGRESSIVE {
+            if seq.finterpflag {
+                /* INTERPFRM */
+                br.skip_bits(1)?;
+            }
+            if hdr.picture_type == PICTURE_TYPE_B {
+                hdr.bfraction = read_bfraction(br)?;
+                if hdr.bfraction == BFRACTION_BI {
+                    hdr.picture_type = PICTURE_TYPE_BI;
+                }
+            }
+        }
+
+        hdr.parse_field_layer(br, seq, entry)?;
+
+        Ok(hdr)
+    }
+
+    /* Header of the second field, sharing the frame level syntax of the first */
+    fn parse_second_field(&self,
+                          br: &mut BitReader,
+                          seq: &SequenceHeader,
+                          entry: &EntryPoint)
+                          -> Result<PictureHeader, ()> {
+        let mut hdr = PictureHeader {
+            picture_type: get_field_picture_type(self.fptype, false),
+            fcm: self.fcm,
+            fptype: self.fptype,
+            tff: self.tff,
+            rnd: self.rnd,
+            bfraction: self.bfraction,
+            refdist: self.refdist,
+            ..Default::default()
+        };
+        hdr.parse_field_layer(br, seq, entry)?;
+        Ok(hdr)
+    }
+
+    /* Everything from PQINDEX on, shared by frames and both fields */
+    fn parse_field_layer(&mut self,
+                         br: &mut BitReader,
+                         seq: &SequenceHeader,
+                         entry: &EntryPoint)
+                         -> Result<(), ()> {
+        let (mb_width, mb_height) = seq.get_mb_size(self.fcm);
+
+        self.parse_quantizer(br, entry)?;
+        if seq.postprocflag {
+            self.postproc = br.read_bits(2)? as u8;
+        }
+
+        match self.picture_type {
+            PICTURE_TYPE_I | PICTURE_TYPE_BI => {
+                if self.fcm == FCM_FRAME_INTERLACE {
+                    self.field_tx = Some(decode_bitplane(br, mb_width, mb_height)?);
+                }
+                self.ac_pred = Some(decode_bitplane(br, mb_width, mb_height)?);
+                if entry.overlap && self.pq <= 8 {
+                    self.condover = read_012(br)?;
+                    if self.condover == 2 {
+                        self.overflags = Some(decode_bitplane(br, mb_width, mb_height)?);
+                    }
+                }
+            }
+            PICTURE_TYPE_P => {
+                if self.fcm == FCM_FIELD_INTERLACE {
+                    self.numref = br.read_flag()?;
+                    if !self.numref {
+                        self.reffield = br.read_flag()?;
+                    }
+                }
+                if entry.extended_mv {
+                    self.mvrange = read_unary(br, 0, 3)? as u8;
+                }
+                if seq.interlace && entry.extended_dmv {
+                    self.dmvrange = read_unary(br, 0, 3)? as u8;
+                }
+
+                if self.fcm == FCM_FRAME_INTERLACE {
+                    self.mv_mode = MV_MODE_1MV;
+                    self.mvswitch4 = br.read_flag()?;
+                    self.intcomp = br.read_flag()?;
+                    if self.intcomp {
+                        self.lumscale = br.read_bits(6)? as u8;
+                        self.lumshift = br.read_bits(6)? as u8;
+                    }
+                    self.skip_mb = Some(decode_bitplane(br, mb_width, mb_height)?);
+                    self.mbmodetab = br.read_bits(2)? as u8;
+                    self.mvtab = br.read_bits(2)? as u8;
+                    self.cbptab = br.read_bits(3)? as u8;
+                    self.twomvbptab = br.read_bits(2)? as u8;
+                    if self.mvswitch4 {
+                        self.fourmvbptab = br.read_bits(2)? as u8;
+                    }
+                } else {
+                    self.parse_mv_mode(br)?;
+                    if self.mv_mode == MV_MODE_IC {
+                        /* INTCOMPFIELD: 1 both fields, 01 bottom, 00 top */
+                        let fields = if self.fcm != FCM_FIELD_INTERLACE || br.read_flag()? {
+                            3
+                        } else if br.read_flag()? {
+                            2
+                        } else {
+                            1
+                        };
+                        /* VA takes a single scale and shift, that of the first field coded */
+                        if fields & 1 != 0 {
+                            self.lumscale = br.read_bits(6)? as u8;
+                            self.lumshift = br.read_bits(6)? as u8;
+                        }
+                        if fields & 2 != 0 && self.fcm == FCM_FIELD_INTERLACE {
+                            let lumscale = br.read_bits(6)? as u8;
+                            let lumshift = br.read_bits(6)? as u8;
+                            if fields == 2 {
+                                self.lumscale = lumscale;
+                                self.lumshift = lumshift;
+                            }
+                        }
+                    }
+
+                    if self.fcm == FCM_PROGRESSIVE {
+                        if self.is_mixed_mv() {
+                            self.mv_type_mb = Some(decode_bitplane(br, mb_width, mb_height)?);
+                        }
+                        self.skip_mb = Some(decode_bitplane(br, mb_width, mb_height)?);
+                        self.mvtab = br.read_bits(2)? as u8;
+                        self.cbptab = br.read_bits(2)? as u8;
+                    } else {
+                        self.mbmodetab = br.read_bits(3)? as u8;
+                        self.mvtab = br.read_bits(2 + self.numref as u32)? as u8;
+                        self.cbptab = br.read_bits(3)? as u8;
+                        if self.is_mixed_mv() {
+                            self.fourmvbptab = br.read_bits(2)? as u8;
+                        }
+                    }
+                }
+
+                self.parse_vopdquant(br, entry)?;
+                self.parse_transform_type(br, entry)?;
+            }
+            PICTURE_TYPE_B => {
+                if self.fcm == FCM_FRAME_INTERLACE {
+                    self.bfraction = read_bfraction(br)?;
+                    if self.bfraction == BFRACTION_BI {
+                        return Err(());
+                    }
+                }
+                if entry.extended_mv {
+                    self.mvrange = read_unary(br, 0, 3)? as u8;
+                }
+
+                if self.fcm == FCM_FIELD_INTERLACE {
+                    if entry.extended_dmv {
+                        self.dmvrange = read_unary(br, 0, 3)? as u8;
+                    }
+                    let lowquant = if self.pq > 12 { 0 } else { 1 };
+                    self.mv_mode = MV_MODE2_TABLE[lowquant][read_unary(br, 1, 3)? as usize];
+                    self.forward_mb = Some(decode_bitplane(br, mb_width, mb_height)?);
+                    self.mbmodetab = br.read_bits(3)? as u8;
+                    self.mvtab = br.read_bits(3)? as u8;
+                    self.cbptab = br.read_bits(3)? as u8;
+                    if self.mv_mode == MV_MODE_MIXED {
+                        self.fourmvbptab = br.read_bits(2)? as u8;
+                    }
+                    self.numref = true;
+                } else if self.fcm == FCM_FRAME_INTERLACE {
+                    if entry.extended_dmv {
+                        self.dmvrange = read_unary(br, 0, 3)? as u8;
+                    }
+                    /* INTCOMP, always 0 in B pictures */
+                    br.skip_bits(1)?;
+                    self.mv_mode = MV_MODE_1MV;
+                    self.direct_mb = Some(decode_bitplane(br, mb_width, mb_height)?);
+                    self.skip_mb = Some(decode_bitplane(br, mb_width, mb_height)?);
+                    self.mbmodetab = br.read_bits(2)? as u8;
+                    self.mvtab = br.read_bits(2)? as u8;
+                    self.cbptab = br.read_bits(3)? as u8;
+                    self.twomvbptab = br.read_bits(2)? as u8;
+                    self.fourmvbptab = br.read_bits(2)? as u8;
+                } else {
+                    self.mv_mode = if br.read_flag()? {
+                        MV_MODE_1MV
+                    } else {
+                        MV_MODE_1MV_HPEL_BILINEAR
+                    };
+                    self.direct_mb = Some(decode_bitplane(br, mb_width, mb_height)?);
+                    self.skip_mb = Some(decode_bitplane(br, mb_width, mb_height)?);
+                    self.mvtab = br.read_bits(2)? as u8;
+                    self.cbptab = br.read_bits(2)? as u8;
+                }
+
+                self.parse_vopdquant(br, entry)?;
+                self.parse_transform_type(br, entry)?;
+            }
+            _ => return Err(()),
+        }
+
+        self.parse_transform_tables(br)?;
+        if is_intra(self.picture_type) {
+            self.parse_vopdquant(br, entry)?;
+        }
+
+        Ok(())
+    }
+
+    fn parse_quantizer(&mut self, br: &mut BitReader, entry: &EntryPoint) -> Result<(), ()> {
+        let pqindex = br.read_bits(5)? as u8;
+        if pqindex == 0 {
+            return Err(());
+        }
+        self.pq = if entry.quantizer == QUANT_FRAME_IMPLICIT {
+            PQUANT_IMPLICIT[pqindex as usize]
+        } else {
+            pqindex
+        };
+        if pqindex <= 8 {
+            self.halfqp = br.read_flag()?;
+        }
+        self.pquantizer = match entry.quantizer {
+            QUANT_FRAME_IMPLICIT => pqindex <= 8,
+            QUANT_FRAME_EXPLICIT => br.read_flag()?,
+            QUANT_NON_UNIFORM => false,
+            _ => true,
+        };
+        Ok(())
+    }
+
+    fn parse_mv_mode(&mut self, br: &mut BitReader) -> Result<(), ()> {
+        let lowquant = if self.pq > 12 { 0 } else { 1 };
+        self.mv_mode = MV_MODE_TABLE[lowquant][read_unary(br, 1, 4)? as usize];
+        if self.mv_mode == MV_MODE_IC {
+            self.mv_mode2 = MV_MODE2_TABLE[lowquant][read_unary(br, 1, 3)? as usize];
+        }
+        Ok(())
+    }
+
+    fn is_mixed_mv(&self) -> bool {
+        self.mv_mode == MV_MODE_MIXED ||
+        (self.mv_mode == MV_MODE_IC && self.mv_mode2 == MV_MODE_MIXED)
+    }
+
+    fn parse_vopdquant(&mut self, br: &mut BitReader, entry: &EntryPoint) -> Result<(), ()> {
+        if entry.dquant == 0 {
+            return Ok(());
+        }
+        if entry.dquant != 2 {
+            self.dquantfrm = br.read_flag()?;
+            if !self.dquantfrm {
+                return Ok(());
+            }
+            self.dqprofile = br.read_bits(2)? as u8;
+            match self.dqprofile {
+                DQPROFILE_SINGLE_EDGE | DQPROFILE_DOUBLE_EDGES => {
+                    self.dqsbedge = br.read_bits(2)? as u8;
+                }
+                DQPROFILE_ALL_MBS => {
+                    self.dqbilevel = br.read_flag()?;
+                    if !self.dqbilevel {
+                        self.halfqp = false;
+                        return Ok(());
+                    }
+                }
+                _ => {}
+            }
+        }
+        let pqdiff = br.read_bits(3)? as u8;
+        self.altpq = if pqdiff == 7 {
+            br.read_bits(5)? as u8
+        } else {
+            self.pq + pqdiff + 1
+        };
+        Ok(())
+    }
+
+    fn parse_transform_type(&mut self, br: &mut BitReader, entry: &EntryPoint) -> Result<(), ()> {
+        if entry.vstransform {
+            self.ttmbf = br.read_flag()?;
+            if self.ttmbf {
+                self.ttfrm = br.read_bits(2)? as u8;
+            }
+        } else {
+            self.ttmbf = true;
+        }
+        Ok(())
+    }
+
+    fn parse_transform_tables(&mut self, br: &mut BitReader) -> Result<(), ()> {
+        self.transacfrm = read_012(br)?;
+        if is_intra(self.picture_type) {
+            self.transacfrm2 = read_012(br)?;
+        }
+        self.transdctab = br.read_flag()?;
+        Ok(())
+    }
+
+    /* Packs the coded bitplanes into the nibble per macroblock layout of VA */
+    fn pack_bitplanes(&self, mb_width: usize, mb_height: usize) -> Option<Vec<u8>> {
+        let planes = match self.picture_type {
+            PICTURE_TYPE_P => {
+                [get_plane_bits(&self.direct_mb),
+                 get_plane_bits(&self.skip_mb),
+                 get_plane_bits(&self.mv_type_mb)]
+            }
+            PICTURE_TYPE_B => {
+                [get_plane_bits(&self.direct_mb),
+                 get_plane_bits(&self.skip_mb),
+                 get_plane_bits(&self.forward_mb)]
+            }
+            _ => {
+                [get_plane_bits(&self.field_tx),
+                 get_plane_bits(&self.ac_pred),
+                 get_plane_bits(&self.overflags)]
+            }
+        };
+        if planes.iter().all(|p| p.is_none()) {
+            return None;
+        }
+
+        let count = mb_width * mb_height;
+        let mut packed = vec![0u8; (count + 1) / 2];
+        for i in 0..count {
+            let mut value = 0;
+            for (bit, plane) in planes.iter().enumerate() {
+                if let Some(plane) = *plane {
+                    value |= plane[i] << bit;
+                }
+            }
+            packed[i / 2] = (packed[i / 2] << 4) | value;
+        }
+        if count & 1 == 1 {
+            packed[count / 2] <<= 4;
+        }
+
+        Some(packed)
+    }
+}
+
+fn build_picture_param(seq: &SequenceHeader,
+                       entry: &EntryPoint,
+                       hdr: &PictureHeader,
+                       is_first_field: bool,
+                       forward: ffi::VASurfaceID,
+                       backward: ffi::VASurfaceID)
+                       -> Result<ffi::VAPictureParameterBufferVC1, ()> {
+    let mut param: ffi::VAPictureParameterBufferVC1 = zeroed();
+
+    param.forward_reference_picture = forward;
+    param.backward_reference_picture = backward;
+    param.inloop_decoded_picture = ffi::VA_INVALID_SURFACE;
+    param.coded_width = seq.coded_width as u16;
+    param.coded_height = seq.coded_height as u16;
+    param.conditional_overlap_flag = hdr.condover;
+    param.fast_uvmc_flag = entry.fastuvmc as u8;
+    param.b_picture_fraction = hdr.bfraction;
+    param.cbp_table = hdr.cbptab;
+    param.mb_mode_table = hdr.mbmodetab;
+    /* The driver expands range reduced pictures and scales their references */
+    param.range_reduction_frame = hdr.rangeredfrm as u8;
+    param.rounding_control = hdr.rnd as u8;
+    param.post_processing = hdr.postproc;
+    param.picture_resolution_index = hdr.respic;
+    param.luma_scale = hdr.lumscale;
+    param.luma_shift = hdr.lumshift;
+
+    let motion = hdr.picture_type == PICTURE_TYPE_P || hdr.picture_type == PICTURE_TYPE_B;
+
+    param.sequence_fields = VC1SequenceFields {
+        pulldown: seq.pulldown as u32,
+        interlace: seq.interlace as u32,
+        tfcntrflag: seq.tfcntrflag as u32,
+        finterpflag: seq.finterpflag as u32,
+        psf: seq.psf as u32,
+        multires: seq.multires as u32,
+        overlap: entry.overlap as u32,
+        syncmarker: seq.syncmarker as u32,
+        rangered: seq.rangered as u32,
+        max_b_frames: seq.max_b_frames as u32,
+        profile: seq.profile as u32,
+    }.to_va()?;
+
+    param.entrypoint_fields = VC1EntrypointFields {
+        broken_link: entry.broken_link as u32,
+        closed_entry: entry.closed_entry as u32,
+        panscan_flag: entry.panscan as u32,
+        loopfilter: entry.loopfilter as u32,
+    }.to_va()?;
+
+    param.range_mapping_fields = VC1RangeMappingFields {
+        luma_flag: entry.range_mapy.is_some() as u32,
+        luma: entry.range_mapy.unwrap_or(0) as u32,
+        chroma_flag: entry.range_mapuv.is_some() as u32,
+        chroma: entry.range_mapuv.unwrap_or(0) as u32,
+    }.to_va()?;
+
+    param.picture_fields = VC1PictureFields {
+        picture_type: hdr.picture_type as u32,
+        frame_coding_mode: hdr.fcm as u32,
+        top_field_first: hdr.tff as u32,
+        is_first_field: is_first_field as u32,
+        intensity_compensation: (hdr.intcomp || hdr.mv_mode == MV_MODE_IC) as u32,
+    }.to_va()?;
+
+    param.raw_coding = VC1RawCoding {
+        mv_type_mb: is_raw(&hdr.mv_type_mb) as u32,
+        direct_mb: is_raw(&hdr.direct_mb) as u32,
+        skip_mb: is_raw(&hdr.skip_mb) as u32,
+        field_tx: is_raw(&hdr.field_tx) as u32,
+        forward_mb: is_raw(&hdr.forward_mb) as u32,
+        ac_pred: is_raw(&hdr.ac_pred) as u32,
+        overflags: is_raw(&hdr.overflags) as u32,
+    }.to_va()?;
+
+    param.bitplane_present = VC1BitplanePresent {
+        bp_mv_type_mb: get_plane_bits(&hdr.mv_type_mb).is_some() as u32,
+        bp_direct_mb: get_plane_bits(&hdr.direct_mb).is_some() as u32,
+        bp_skip_mb: get_plane_bits(&hdr.skip_mb).is_some() as u32,
+        bp_field_tx: get_plane_bits(&hdr.field_tx).is_some() as u32,
+        bp_forward_mb: get_plane_bits(&hdr.forward_mb).is_some() as u32,
+        bp_ac_pred: get_plane_bits(&hdr.ac_pred).is_some() as u32,
+        bp_overflags: get_plane_bits(&hdr.overflags).is_some() as u32,
+    }.to_va()?;
+
+    param.reference_fields = VC1ReferenceFields {
+        reference_distance_flag: entry.refdist_flag as u32,
+        reference_distance: hdr.refdist as u32,
+        num_reference_pictures: hdr.numref as u32,
+        reference_field_pic_indicator: hdr.reffield as u32,
+    }.to_va()?;
+
+    param.mv_fields = VC1MvFields {
+        mv_mode: if motion { hdr.mv_mode as u32 } else { 0 },
+        mv_mode2: if motion && hdr.mv_mode == MV_MODE_IC { hdr.mv_mode2 as u32 } else { 0 },
+        mv_table: hdr.mvtab as u32,
+        two_mv_block_pattern_table: hdr.twomvbptab as u32,
+        four_mv_switch: hdr.mvswitch4 as u32,
+        four_mv_block_pattern_table: hdr.fourmvbptab as u32,
+        extended_mv_flag: entry.extended_mv as u32,
+        extended_mv_range: hdr.mvrange as u32,
+        extended_dmv_flag: entry.extended_dmv as u32,
+        extended_dmv_range: hdr.dmvrange as u32,
+    }.to_va()?;
+
+    param.pic_quantizer_fields = VC1PicQuantizerFields {
+        dquant: entry.dquant as u32,
+        quantizer: entry.quantizer as u32,
+        half_qp: hdr.halfqp as u32,
+        pic_quantizer_scale: hdr.pq as u32,
+        pic_quantizer_type: hdr.pquantizer as u32,
+        dq_frame: hdr.dquantfrm as u32,
+        dq_profile: hdr.dqprofile as u32,
+        dq_sb_edge: match hdr.dqprofile {
+            DQPROFILE_SINGLE_EDGE => hdr.dqsbedge as u32,
+            _ => 0,
+        },
+        dq_db_edge: match hdr.dqprofile {
+            DQPROFILE_DOUBLE_EDGES => hdr.dqsbedge as u32,
+            _ => 0,
+        },
+        dq_binary_level: hdr.dqbilevel as u32,
+        alt_pic_quantizer: hdr.altpq as u32,
+    }.to_va()?;
+
+    param.transform_fields = VC1TransformFields {
+        variable_sized_transform_flag: entry.vstransform as u32,
+        mb_level_transform_type_flag: hdr.ttmbf as u32,
+        frame_level_transform_type: hdr.ttfrm as u32,
+        transform_ac_codingset_idx1: hdr.transacfrm as u32,
+        transform_ac_codingset_idx2: hdr.transacfrm2 as u32,
+        intra_transform_dc_table: hdr.transdctab as u32,
+    }.to_va()?;
+
+    Ok(param)
+}
+
+fn build_slice_param(size: usize,
+                     macroblock_offset: usize,
+                     vertical_position: u32)
+                     -> ffi::VASliceParameterBufferVC1 {
+    let mut param: ffi::VASliceParameterBufferVC1 = zeroed();
+    param.slice_data_size = size as u32;
+    param.slice_data_flag = ffi::VA_SLICE_DATA_FLAG_ALL;
+    param.macroblock_offset = macroblock_offset as u32;
+    param.slice_vertical_position = vertical_position;
+    param
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StreamFormat {
+    /* Simple and main profile frames wrapped in the RCV file format */
+    Rcv,
+    /* Advanced profile elementary stream with start codes */
+    Advanced,
+}
+
+/* A field waiting for its complementary field */
+#[derive(Debug)]
+struct FirstField {
+    frame: VADecodedFrame,
+    header: PictureHeader,
+}
+
+#[derive(Debug)]
+struct PendingPicture {
+    header: PictureHeader,
+    frame: VADecodedFrame,
+    is_first_field: bool,
+    slices: Vec<(ffi::VASliceParameterBufferVC1, Vec<u8>)>,
+    corrupted: bool,
+}
+
+#[derive(Debug)]
+pub struct VADecoderVC1 {
+    context: Option<VADecodeContext>,
+    format: Option<StreamFormat>,
+    rcv_data: Vec<u8>,
+    seq: Option<SequenceHeader>,
+    entry: Option<EntryPoint>,
+    leading_b: Option<EntryPoint>,
+    rnd: bool,
+    picture: Option<PendingPicture>,
+    first_field: Option<FirstField>,
+    prev_ref: Option<VADecodedFrame>,
+    next_ref: Option<VADecodedFrame>,
+    output: VecDeque<VADecodedFrame>,
+    recovery: VAErrorRecovery,
+    mode: DecodeMode,
+}
+
+impl VADecoderVC1 {
+    pub fn new() -> Result<Box<VADecoder>, ()> {
+        Ok(Box::new(VADecoderVC1 {
+            context: None,
+            format: None,
+            rcv_data: Vec::new(),
+            seq: None,
+            entry: None,
+            leading_b: None,
+            rnd: false,
+            picture: None,
+            first_field: None,
+            prev_ref: None,
+            next_ref: None,
+            output: VecDeque::new(),
+            recovery: VAErrorRecovery::new(),
+            mode: DecodeMode::All,
+        }))
+    }
+
+    fn ensure_context(&mut self, va_disp: &VADisplay) -> Result<(), DecodeError> {
+        let (profile, width, height) = match self.seq {
+            Some(ref seq) => {
+                let profile = match seq.profile {
+                    PROFILE_SIMPLE => ffi::VAProfileVC1Simple,
+                    PROFILE_MAIN => ffi::VAProfileVC1Main,
+                    _ => ffi::VAProfileVC1Advanced,
+                };
+                let width = (seq.coded_width + 15) & !15;
+                let height = if seq.interlace {
+                    (seq.coded_height + 31) & !31
+                } else {
+                    (seq.coded_height + 15) & !15
+                };
+                (profile, width, height)
+            }
+            None => return Err(DecodeError::InvalidStream),
+        };
+
+        if let Some(ref context) = self.context {
+            if context.is_compatible(profile, ffi::VA_RT_FORMAT_YUV420, width, height) {
+                return Ok(());
+            }
+        }
+
+        self.output_references();
+        if let Some(context) = self.context.take() {
+            context.destroy(va_disp);
+        }
+
+        let context = VADecodeContext::new(va_disp,
+                                           profile,
+                                           ffi::VA_RT_FORMAT_YUV420,
+                                           width,
+                                           height,
+                                           NUM_SURFACES)
+            .map_err(|_| DecodeError::VAFailed)?;
+        self.context = Some(context);
+
+        Ok(())
+    }
+
+    /* Pushes the frames held for reordering to the output queue */
+    fn output_references(&mut self) {
+        if let Some(field) = self.first_field.take() {
+            self.recovery.frame_decoded(&field.frame);
+            self.output.push_back(field.frame);
+        }
+        if let Some(frame) = self.next_ref.take() {
+            self.output.push_back(frame);
+        }
+        self.prev_ref = None;
+    }
+
+    fn complete_frame(&mut self, frame: VADecodedFrame, picture_type: u8) {
+        self.recovery.frame_decoded(&frame);
+        if is_reference(picture_type) && !self.mode.is_keyframes_only() {
+            self.next_ref = Some(frame);
+        } else {
+            self.output.push_back(frame);
+        }
+    }
+
+    /* A picture that cannot be decoded, pictures predicted from a lost reference are
+     * skipped until the next I picture as there is nothing left to predict them from */
+    fn lose_picture(&mut self, is_reference: bool) {
+        self.picture = None;
+        self.recovery.drop_frame();
+        if is_reference {
+            self.recovery.lost_reference();
+            self.output_references();
+        }
+    }
+
+    /* A skipped P picture is a copy of the last reference */
+    fn repeat_reference(&mut self, pts: u64) {
+        if self.mode.is_keyframes_only() {
+            self.recovery.skip_frame();
+            return;
+        }
+        if let Some(frame) = self.next_ref.take() {
+            self.output.push_back(frame.clone());
+            self.next_ref = Some(frame.with_pts(pts));
+            self.prev_ref = Some(frame);
+        }
+    }
+
+    /* Starts a frame, or its first field, whose header begins the data */
+    fn start_frame(&mut self, va_disp: &VADisplay, data: &[u8], pts: u64) -> Result<(), DecodeError> {
+        if let Some(field) = self.first_field.take() {
+            /* Unpaired field, output it as it is */
+            self.complete_frame(field.frame, field.header.picture_type);
+        }
+
+        let (seq, entry) = match (&self.seq, &self.entry) {
+            (&Some(ref seq), &Some(ref entry)) => (seq.clone(), entry.clone()),
+            _ => return Ok(()),
+        };
+
+        let (header, macroblock_offset) = if seq.profile == PROFILE_ADVANCED {
+            let unescaped = unescape(data);
+            let mut br = BitReader::new(&unescaped);
+            match PictureHeader::parse_advanced(&mut br, &seq, &entry) {
+                Ok(header) => (header, br.get_pos()),
+                Err(_) => {
+                    self.lose_picture(true);
+                    return Ok(());
+                }
+            }
+        } else {
+            /* Simple and main profiles code skipped frames as empty ones */
+            if data.len() <= 1 {
+                self.repeat_reference(pts);
+                return Ok(());
+            }
+            let mut br = BitReader::new(data);
+            let mut header = match PictureHeader::parse_simple(&mut br, &seq, &entry) {
+                Ok(header) => header,
+                Err(_) => {
+                    self.lose_picture(true);
+                    return Ok(());
+                }
+            };
+            if header.x8_type {
+                return Err(DecodeError::Unsupported);
+            }
+            match header.picture_type {
+                PICTURE_TYPE_I | PICTURE_TYPE_BI => self.rnd = true,
+                PICTURE_TYPE_P => self.rnd = !self.rnd,
+                _ => {}
+            }
+            header.rnd = self.rnd;
+            (header, br.get_pos())
+        };
+
+        if header.picture_type == PICTURE_TYPE_SKIPPED {
+            self.repeat_reference(pts);
+            return Ok(());
+        }
+
+        self.ensure_context(va_disp)?;
+        if header.picture_type == PICTURE_TYPE_I {
+            self.recovery.recover();
+        }
+
+        if self.mode.skips(header.picture_type == PICTURE_TYPE_I,
+                           is_reference(header.picture_type)) {
+            self.recovery.skip_frame();
+            return Ok(());
+        }
+
+        /* B pictures right after a closed entry point only predict backwards,
+         * those after a broken link cannot be decoded at all */
+        let skip = match header.picture_type {
+            PICTURE_TYPE_P => self.next_ref.is_none(),
+            PICTURE_TYPE_B => {
+                match self.leading_b {
+                    Some(ref entry) if entry.broken_link => true,
+                    Some(ref entry) if entry.closed_entry => self.next_ref.is_none(),
+                    _ => self.prev_ref.is_none() || self.next_ref.is_none(),
+                }
+            }
+            _ => false,
+        };
+        if header.picture_type == PICTURE_TYPE_P {
+            self.leading_b = None;
+        }
+        if skip {
+            self.recovery.drop_frame();
+            return Ok(());
+        }
+
+        if is_reference(header.picture_type) {
+            self.prev_ref = self.next_ref.take();
+            if let Some(ref frame) = self.prev_ref {
+                self.output.push_back(frame.clone());
+            }
+        }
+
+        let surface = match self.context {
+            Some(ref context) => context.get_surface(),
+            None => None,
+        };
+        let surface = surface.ok_or(DecodeError::NoSurface)?;
+        let field_order = match (header.fcm, header.tff) {
+            (FCM_PROGRESSIVE, _) => FieldOrder::Progressive,
+            (_, true) => FieldOrder::TopFieldFirst,
+            (_, false) => FieldOrder::BottomFieldFirst,
+        };
+        let frame = VADecodedFrame::new(surface, seq.coded_width, seq.coded_height, pts)
+            .with_field_order(field_order);
+
+        self.picture = Some(PendingPicture {
+            header: header,
+            frame: frame,
+            is_first_field: true,
+            slices: vec![(build_slice_param(data.len(), macroblock_offset, 0), data.to_vec())],
+            corrupted: false,
+        });
+
+        Ok(())
+    }
+
+    /* The second field goes to the surface of the first one */
+    fn start_second_field(&mut self, data: &[u8]) -> Result<(), DecodeError> {
+        let field = match self.first_field.take() {
+            Some(field) => field,
+            None => return Ok(()),
+        };
+        let (seq, entry) = match (&self.seq, &self.entry) {
+            (&Some(ref seq), &Some(ref entry)) => (seq, entry),
+            _ => return Ok(()),
+        };
+
+        let unescaped = unescape(data);
+        let mut br = BitReader::new(&unescaped);
+        let header = match field.header.parse_second_field(&mut br, seq, entry) {
+            Ok(header) => header,
+            Err(_) => {
+                /* The frame is left with its first field only */
+                let mut frame = field.frame;
+                frame.mark_corrupted();
+                self.recovery.skip_slice();
+                self.complete_frame(frame, field.header.picture_type);
+                return Ok(());
+            }
+        };
+
+        self.picture = Some(PendingPicture {
+            header: header,
+            frame: field.frame,
+            is_first_field: false,
+            slices: vec![(build_slice_param(data.len(), br.get_pos(), 0), data.to_vec())],
+            corrupted: false,
+        });
+
+        Ok(())
+    }
+
+    fn decode_slice(&mut self, data: &[u8]) -> Result<(), DecodeError> {
+        let (seq, entry) = match (&self.seq, &self.entry) {
+            (&Some(ref seq), &Some(ref entry)) => (seq, entry),
+            _ => return Ok(()),
+        };
+        let pic = match self.picture {
+            Some(ref mut pic) => pic,
+            None => return Ok(()),
+        };
+
+        let unescaped = unescape(data);
+        let mut br = BitReader::new(&unescaped);
+        let ret = br.read_bits(9).and_then(|slice_addr| {
+            /* PIC_HEADER_FLAG, the repeated header only moves the macroblock data */
+            if br.read_flag()? {
+                if pic.is_first_field {
+                    PictureHeader::parse_advanced(&mut br, seq, entry)?;
+                } else {
+                    pic.header.parse_second_field(&mut br, seq, entry)?;
+                }
+            }
+            Ok(slice_addr)
+        });
+        let slice_addr = match ret {
+            Ok(slice_addr) => slice_addr,
+            Err(_) => {
+                pic.corrupted = true;
+                self.recovery.skip_slice();
+                return Ok(());
+            }
+        };
+
+        let mb_height = if pic.header.fcm == FCM_FIELD_INTERLACE {
+            (seq.coded_height + 31) >> 5
+        } else {
+            (seq.coded_height + 15) >> 4
+        };
+        let slice_param = build_slice_param(data.len(), br.get_pos(), slice_addr % mb_height);
+        pic.slices.push((slice_param, data.to_vec()));
+
+        Ok(())
+    }
+
+    fn end_picture(&mut self, va_disp: &VADisplay) -> Result<(), DecodeError> {
+        let mut pic = match self.picture.take() {
+            Some(pic) => pic,
+            None => return Ok(()),
+        };
+        let (seq, entry) = match (&self.seq, &self.entry) {
+            (&Some(ref seq), &Some(ref entry)) => (seq, entry),
+            _ => return Ok(()),
+        };
+
+        let picture_type = pic.header.picture_type;
+        let surface_id = |f: &Option<VADecodedFrame>| match *f {
+            Some(ref frame) => frame.get_surface().get_id(),
+            None => ffi::VA_INVALID_SURFACE,
+        };
+        let forward = match picture_type {
+            PICTURE_TYPE_P | PICTURE_TYPE_B | PICTURE_TYPE_BI => surface_id(&self.prev_ref),
+            _ => ffi::VA_INVALID_SURFACE,
+        };
+        let backward = match picture_type {
+            PICTURE_TYPE_B | PICTURE_TYPE_BI => surface_id(&self.next_ref),
+            _ => ffi::VA_INVALID_SURFACE,
+        };
+        let is_corrupted = |f: &Option<VADecodedFrame>| {
+            f.as_ref().map_or(false, |frame| frame.is_corrupted())
+        };
+        let corrupted = match picture_type {
+            PICTURE_TYPE_P => is_corrupted(&self.prev_ref),
+            PICTURE_TYPE_B => is_corrupted(&self.prev_ref) || is_corrupted(&self.next_ref),
+            _ => false,
+        };
+        if pic.corrupted || corrupted {
+            pic.frame.mark_corrupted();
+        }
+
+        let mut info = VAFrameInfo {
+            picture_type: match picture_type {
+                PICTURE_TYPE_P => Some(PictureType::P),
+                PICTURE_TYPE_B => Some(PictureType::B),
+                _ => Some(PictureType::I),
+            },
+            bytes: pic.slices.iter().map(|&(_, ref data)| data.len()).sum(),
+            num_references: match picture_type {
+                _ if is_intra(picture_type) => 0,
+                _ => {
+                    [forward, backward]
+                        .iter()
+                        .filter(|&&id| id != ffi::VA_INVALID_SURFACE)
+                        .count()
+                }
+            },
+            ..VAFrameInfo::default()
+        };
+        info.add_qp(pic.header.pq as i32);
+        if !pic.is_first_field {
+            info = info.merge_field(pic.frame.get_info());
+        }
+        pic.frame = pic.frame.with_info(info);
+
+        let pic_param = build_picture_param(seq,
+                                            entry,
+                                            &pic.header,
+                                            pic.is_first_field,
+                                            forward,
+                                            backward)?;
+        let (mb_width, mb_height) = seq.get_mb_size(pic.header.fcm);
+        let bitplane = pic.header.pack_bitplanes(mb_width, mb_height);
+
+        {
+            let context = self.context.as_ref().ok_or(DecodeError::InvalidStream)?;
+            let mut submission = PictureSubmission::new(va_disp, context)
+                .add_param(&pic_param);
+            if let Some(ref bitplane) = bitplane {
+                submission =
+                    submission.and_then(|s| s.add_data(ffi::VABitPlaneBufferType, bitplane));
+            }
+            for &(ref slice_param, ref data) in &pic.slices {
+                submission = submission.and_then(|s| s.add_slice(slice_param, data));
+            }
+            submission.and_then(|s| s.submit(pic.frame.get_surface()))
+                .map_err(|_| DecodeError::VAFailed)?;
+        }
+
+        if pic.header.fcm == FCM_FIELD_INTERLACE && pic.is_first_field {
+            self.first_field = Some(FirstField {
+                frame: pic.frame,
+                header: pic.header,
+            });
+        } else {
+            self.complete_frame(pic.frame, picture_type);
+        }
+
+        Ok(())
+    }
+
+    fn decode_unit(&mut self,
+                   va_disp: &VADisplay,
+                   data: &[u8],
+                   pts: u64)
+                   -> Result<(), DecodeError> {
+        let code = data[3];
+        let payload = &data[4..];
+
+        match code {
+            FRAME_START_CODE => {
+                self.end_picture(va_disp)?;
+                self.start_frame(va_disp, payload, pts)?;
+            }
+            FIELD_START_CODE => {
+                self.end_picture(va_disp)?;
+                self.start_second_field(payload)?;
+            }
+            SLICE_START_CODE => self.decode_slice(payload)?,
+            SEQUENCE_START_CODE => {
+                self.end_picture(va_disp)?;
+                let unescaped = unescape(payload);
+                let seq = SequenceHeader::parse_advanced(&mut BitReader::new(&unescaped))
+                    .map_err(|_| DecodeError::InvalidStream)?;
+                if seq.chroma_format != 1 {
+                    return Err(DecodeError::Unsupported);
+                }
+                self.seq = Some(seq);
+                self.entry = None;
+            }
+            ENTRY_POINT_START_CODE => {
+                self.end_picture(va_disp)?;
+                let unescaped = unescape(payload);
+                let mut br = BitReader::new(&unescaped);
+                if let Some(ref mut seq) = self.seq {
+                    let (entry, coded_size) = EntryPoint::parse(&mut br, seq)
+                        .map_err(|_| DecodeError::InvalidStream)?;
+                    if let Some((width, height)) = coded_size {
+                        seq.coded_width = width;
+                        seq.coded_height = height;
+                    }
+                    self.leading_b = Some(entry.clone());
+                    self.entry = Some(entry);
+                }
+            }
+            END_OF_SEQUENCE => {
+                self.end_picture(va_disp)?;
+                self.output_references();
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn decode_advanced(&mut self,
+                       va_disp: &VADisplay,
+                       data: &[u8],
+                       pts: u64)
+                       -> Result<(), DecodeError> {
+        let offsets = find_start_codes(data);
+
+        for (i, &start) in offsets.iter().enumerate() {
+            let end = offsets.get(i + 1).cloned().unwrap_or(data.len());
+            if end - start < 4 {
+                continue;
+            }
+            match self.decode_unit(va_disp, &data[start..end], pts) {
+                /* Broken headers are ignored, the pictures needing them are dropped */
+                Ok(_) | Err(DecodeError::InvalidStream) => {}
+                Err(e) => {
+                    self.picture = None;
+                    return Err(e);
+                }
+            }
+        }
+
+        /* Every call carries whole pictures */
+        self.end_picture(va_disp)
+    }
+
+    /* RCV frames carry their own timestamps, in milliseconds */
+    fn decode_rcv(&mut self, va_disp: &VADisplay, data: &[u8]) -> Result<(), DecodeError> {
+        self.rcv_data.extend_from_slice(data);
+        let data = mem::replace(&mut self.rcv_data, Vec::new());
+        let mut offset = 0;
+
+        if self.seq.is_none() {
+            if data.len() < RCV_HEADER_SIZE {
+                self.rcv_data = data;
+                return Ok(());
+            }
+            /* STRUCT_C, then STRUCT_A with the height first */
+            let height = read_le32(&data[12..16]);
+            let width = read_le32(&data[16..20]);
+            let (seq, entry) = SequenceHeader::parse_struct_c(&mut BitReader::new(&data[8..12]),
+                                                              width,
+                                                              height)
+                .map_err(|_| DecodeError::InvalidStream)?;
+            if (seq.profile != PROFILE_SIMPLE && seq.profile != PROFILE_MAIN) || seq.res_y411 {
+                return Err(DecodeError::Unsupported);
+            }
+            self.seq = Some(seq);
+            self.entry = Some(entry);
+            offset = RCV_HEADER_SIZE;
+        }
+
+        let mut ret = Ok(());
+        while data.len() - offset >= RCV_FRAME_HEADER_SIZE {
+            let size = (read_le32(&data[offset..]) & 0x00ff_ffff) as usize;
+            let timestamp = read_le32(&data[offset + 4..]);
+            let start = offset + RCV_FRAME_HEADER_SIZE;
+            if data.len() - start < size {
+                break;
+            }
+            offset = start + size;
+
+            ret = self.start_frame(va_disp, &data[start..offset], timestamp as u64)
+                .and_then(|_| self.end_picture(va_disp));
+            if ret.is_err() {
+                self.picture = None;
+                break;
+            }
+        }
+
+        self.rcv_data = data[offset..].to_vec();
+        ret
+    }
+}
+
+impl VADecoder for VADecoderVC1 {
+    fn decode(&mut self, va_disp: &VADisplay, data: &[u8], pts: u64) -> Result<(), DecodeError> {
+        if self.format.is_none() {
+            if data.len() >= 8 && data[3] == 0xc5 && read_le32(&data[4..8]) == 4 {
+                self.format = Some(StreamFormat::Rcv);
+            } else if data.len() >= 4 && data[0] == 0 && data[1] == 0 && data[2] == 1 {
+                self.format = Some(StreamFormat::Advanced);
+            } else {
+                return Err(DecodeError::InvalidStream);
+            }
+        }
+
+        match self.format {
+            Some(StreamFormat::Rcv) => self.decode_rcv(va_disp, data),
+            _ => self.decode_advanced(va_disp, data, pts),
+        }
+    }
+
+    fn drain(&mut self, va_disp: &VADisplay) -> Result<(), DecodeError> {
+        let ret = self.end_picture(va_disp);
+        self.output_references();
+        ret
+    }
+
+    /* Without references, pictures up to the next I picture are skipped */
+    fn flush(&mut self) {
+        self.picture = None;
+        self.first_field = None;
+        self.prev_ref = None;
+        self.next_ref = None;
+        self.leading_b = None;
+        self.rnd = false;
+        self.output.clear();
+        self.rcv_data.clear();
+    }
+
+    fn get_frame(&mut self) -> Option<VADecodedFrame> {
+        self.output.pop_front()
+    }
+
+    fn get_stats(&self) -> VADecodeStats {
+        self.recovery.get_stats().with_context(self.context.as_ref())
+    }
+
+    fn set_decode_mode(&mut self, mode: DecodeMode) {
+        self.mode = mode;
+    }
+
+    fn destroy(&mut self, va_disp: &VADisplay) {
+        self.picture = None;
+        self.first_field = None;
+        self.prev_ref = None;
+        self.next_ref = None;
+        self.output.clear();
+        self.rcv_data.clear();
+        if let Some(context) = self.context.take() {
+            context.destroy(va_disp);
+        }
+    }
+}
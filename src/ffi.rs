@@ -495,6 +495,10 @@ pub const VAProfileVP9Profile2: _bindgen_ty_2 =
     _bindgen_ty_2::VAProfileVP9Profile2;
 pub const VAProfileVP9Profile3: _bindgen_ty_2 =
     _bindgen_ty_2::VAProfileVP9Profile3;
+pub const VAProfileAV1Profile0: _bindgen_ty_2 =
+    _bindgen_ty_2::VAProfileAV1Profile0;
+pub const VAProfileAV1Profile1: _bindgen_ty_2 =
+    _bindgen_ty_2::VAProfileAV1Profile1;
 #[repr(i32)]
 /** Currently defined profiles */
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -523,6 +527,8 @@ pub enum _bindgen_ty_2 {
     VAProfileVP9Profile1 = 20,
     VAProfileVP9Profile2 = 21,
     VAProfileVP9Profile3 = 22,
+    VAProfileAV1Profile0 = 32,
+    VAProfileAV1Profile1 = 33,
 }
 pub use self::_bindgen_ty_2 as VAProfile;
 pub const VAEntrypointVLD: _bindgen_ty_3 = _bindgen_ty_3::VAEntrypointVLD;
@@ -6930,6 +6936,1340 @@ impl Clone for _VASliceParameterBufferVP9 {
     fn clone(&self) -> Self { *self }
 }
 pub type VASliceParameterBufferVP9 = _VASliceParameterBufferVP9;
+#[repr(C)]
+#[derive(Debug, Copy)]
+pub struct _VASegmentationStructAV1 {
+    pub segment_info_fields: _VASegmentationStructAV1__bindgen_ty_1,
+    pub feature_data: [[i16; 8usize]; 8usize],
+    pub feature_mask: [u8; 8usize],
+    pub va_reserved: [u32; 4usize],
+}
+#[repr(C)]
+#[derive(Debug, Copy)]
+pub struct _VASegmentationStructAV1__bindgen_ty_1 {
+    pub bits: __BindgenUnionField<_VASegmentationStructAV1__bindgen_ty_1__bindgen_ty_1>,
+    pub value: __BindgenUnionField<u32>,
+    pub bindgen_union_field: u32,
+}
+#[repr(C)]
+#[derive(Debug, Copy)]
+pub struct _VASegmentationStructAV1__bindgen_ty_1__bindgen_ty_1 {
+    pub _bitfield_1: u32,
+}
+#[test]
+fn bindgen_test_layout__VASegmentationStructAV1__bindgen_ty_1__bindgen_ty_1() {
+    assert_eq!(::std::mem::size_of::<_VASegmentationStructAV1__bindgen_ty_1__bindgen_ty_1>()
+               , 4usize);
+    assert_eq!(::std::mem::align_of::<_VASegmentationStructAV1__bindgen_ty_1__bindgen_ty_1>()
+               , 4usize);
+}
+impl Clone for _VASegmentationStructAV1__bindgen_ty_1__bindgen_ty_1 {
+    fn clone(&self) -> Self { *self }
+}
+impl _VASegmentationStructAV1__bindgen_ty_1__bindgen_ty_1 {
+    #[inline]
+    pub fn enabled(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (1usize as u32)) >>
+                                       0u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_enabled(&mut self, val: u32) {
+        self._bitfield_1 &= !(1usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 0u32) & (1usize as u32);
+    }
+    #[inline]
+    pub fn update_map(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (2usize as u32)) >>
+                                       1u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_update_map(&mut self, val: u32) {
+        self._bitfield_1 &= !(2usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 1u32) & (2usize as u32);
+    }
+    #[inline]
+    pub fn temporal_update(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (4usize as u32)) >>
+                                       2u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_temporal_update(&mut self, val: u32) {
+        self._bitfield_1 &= !(4usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 2u32) & (4usize as u32);
+    }
+    #[inline]
+    pub fn update_data(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (8usize as u32)) >>
+                                       3u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_update_data(&mut self, val: u32) {
+        self._bitfield_1 &= !(8usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 3u32) & (8usize as u32);
+    }
+    #[inline]
+    pub fn reserved(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (4294967280usize as u32)) >>
+                                       4u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_reserved(&mut self, val: u32) {
+        self._bitfield_1 &= !(4294967280usize as u32);
+        self._bitfield_1 |=
+            ((val as u32 as u32) << 4u32) & (4294967280usize as u32);
+    }
+}
+#[test]
+fn bindgen_test_layout__VASegmentationStructAV1__bindgen_ty_1() {
+    assert_eq!(::std::mem::size_of::<_VASegmentationStructAV1__bindgen_ty_1>() ,
+               4usize);
+    assert_eq!(::std::mem::align_of::<_VASegmentationStructAV1__bindgen_ty_1>()
+               , 4usize);
+}
+impl Clone for _VASegmentationStructAV1__bindgen_ty_1 {
+    fn clone(&self) -> Self { *self }
+}
+#[test]
+fn bindgen_test_layout__VASegmentationStructAV1() {
+    assert_eq!(::std::mem::size_of::<_VASegmentationStructAV1>() , 156usize);
+    assert_eq!(::std::mem::align_of::<_VASegmentationStructAV1>() , 4usize);
+}
+impl Clone for _VASegmentationStructAV1 {
+    fn clone(&self) -> Self { *self }
+}
+pub type VASegmentationStructAV1 = _VASegmentationStructAV1;
+#[repr(C)]
+#[derive(Debug, Copy)]
+pub struct _VAFilmGrainStructAV1 {
+    pub film_grain_info_fields: _VAFilmGrainStructAV1__bindgen_ty_1,
+    pub grain_seed: u16,
+    pub num_y_points: u8,
+    pub point_y_value: [u8; 14usize],
+    pub point_y_scaling: [u8; 14usize],
+    pub num_cb_points: u8,
+    pub point_cb_value: [u8; 10usize],
+    pub point_cb_scaling: [u8; 10usize],
+    pub num_cr_points: u8,
+    pub point_cr_value: [u8; 10usize],
+    pub point_cr_scaling: [u8; 10usize],
+    pub ar_coeffs_y: [i8; 24usize],
+    pub ar_coeffs_cb: [i8; 25usize],
+    pub ar_coeffs_cr: [i8; 25usize],
+    pub cb_mult: u8,
+    pub cb_luma_mult: u8,
+    pub cb_offset: u16,
+    pub cr_mult: u8,
+    pub cr_luma_mult: u8,
+    pub cr_offset: u16,
+    pub va_reserved: [u32; 4usize],
+}
+#[repr(C)]
+#[derive(Debug, Copy)]
+pub struct _VAFilmGrainStructAV1__bindgen_ty_1 {
+    pub bits: __BindgenUnionField<_VAFilmGrainStructAV1__bindgen_ty_1__bindgen_ty_1>,
+    pub value: __BindgenUnionField<u32>,
+    pub bindgen_union_field: u32,
+}
+#[repr(C)]
+#[derive(Debug, Copy)]
+pub struct _VAFilmGrainStructAV1__bindgen_ty_1__bindgen_ty_1 {
+    pub _bitfield_1: u32,
+}
+#[test]
+fn bindgen_test_layout__VAFilmGrainStructAV1__bindgen_ty_1__bindgen_ty_1() {
+    assert_eq!(::std::mem::size_of::<_VAFilmGrainStructAV1__bindgen_ty_1__bindgen_ty_1>()
+               , 4usize);
+    assert_eq!(::std::mem::align_of::<_VAFilmGrainStructAV1__bindgen_ty_1__bindgen_ty_1>()
+               , 4usize);
+}
+impl Clone for _VAFilmGrainStructAV1__bindgen_ty_1__bindgen_ty_1 {
+    fn clone(&self) -> Self { *self }
+}
+impl _VAFilmGrainStructAV1__bindgen_ty_1__bindgen_ty_1 {
+    #[inline]
+    pub fn apply_grain(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (1usize as u32)) >>
+                                       0u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_apply_grain(&mut self, val: u32) {
+        self._bitfield_1 &= !(1usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 0u32) & (1usize as u32);
+    }
+    #[inline]
+    pub fn chroma_scaling_from_luma(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (2usize as u32)) >>
+                                       1u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_chroma_scaling_from_luma(&mut self, val: u32) {
+        self._bitfield_1 &= !(2usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 1u32) & (2usize as u32);
+    }
+    #[inline]
+    pub fn grain_scaling_minus_8(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (12usize as u32)) >>
+                                       2u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_grain_scaling_minus_8(&mut self, val: u32) {
+        self._bitfield_1 &= !(12usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 2u32) & (12usize as u32);
+    }
+    #[inline]
+    pub fn ar_coeff_lag(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (48usize as u32)) >>
+                                       4u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_ar_coeff_lag(&mut self, val: u32) {
+        self._bitfield_1 &= !(48usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 4u32) & (48usize as u32);
+    }
+    #[inline]
+    pub fn ar_coeff_shift_minus_6(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (192usize as u32)) >>
+                                       6u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_ar_coeff_shift_minus_6(&mut self, val: u32) {
+        self._bitfield_1 &= !(192usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 6u32) & (192usize as u32);
+    }
+    #[inline]
+    pub fn grain_scale_shift(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (768usize as u32)) >>
+                                       8u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_grain_scale_shift(&mut self, val: u32) {
+        self._bitfield_1 &= !(768usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 8u32) & (768usize as u32);
+    }
+    #[inline]
+    pub fn overlap_flag(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (1024usize as u32)) >>
+                                       10u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_overlap_flag(&mut self, val: u32) {
+        self._bitfield_1 &= !(1024usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 10u32) & (1024usize as u32);
+    }
+    #[inline]
+    pub fn clip_to_restricted_range(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (2048usize as u32)) >>
+                                       11u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_clip_to_restricted_range(&mut self, val: u32) {
+        self._bitfield_1 &= !(2048usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 11u32) & (2048usize as u32);
+    }
+    #[inline]
+    pub fn reserved(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (4294963200usize as u32)) >>
+                                       12u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_reserved(&mut self, val: u32) {
+        self._bitfield_1 &= !(4294963200usize as u32);
+        self._bitfield_1 |=
+            ((val as u32 as u32) << 12u32) & (4294963200usize as u32);
+    }
+}
+#[test]
+fn bindgen_test_layout__VAFilmGrainStructAV1__bindgen_ty_1() {
+    assert_eq!(::std::mem::size_of::<_VAFilmGrainStructAV1__bindgen_ty_1>() ,
+               4usize);
+    assert_eq!(::std::mem::align_of::<_VAFilmGrainStructAV1__bindgen_ty_1>() ,
+               4usize);
+}
+impl Clone for _VAFilmGrainStructAV1__bindgen_ty_1 {
+    fn clone(&self) -> Self { *self }
+}
+#[test]
+fn bindgen_test_layout__VAFilmGrainStructAV1() {
+    assert_eq!(::std::mem::size_of::<_VAFilmGrainStructAV1>() , 176usize);
+    assert_eq!(::std::mem::align_of::<_VAFilmGrainStructAV1>() , 4usize);
+}
+impl Clone for _VAFilmGrainStructAV1 {
+    fn clone(&self) -> Self { *self }
+}
+pub type VAFilmGrainStructAV1 = _VAFilmGrainStructAV1;
+pub const VAAV1TransformationIdentity: _bindgen_ty_20 =
+    _bindgen_ty_20::VAAV1TransformationIdentity;
+pub const VAAV1TransformationTranslation: _bindgen_ty_20 =
+    _bindgen_ty_20::VAAV1TransformationTranslation;
+pub const VAAV1TransformationRotzoom: _bindgen_ty_20 =
+    _bindgen_ty_20::VAAV1TransformationRotzoom;
+pub const VAAV1TransformationAffine: _bindgen_ty_20 =
+    _bindgen_ty_20::VAAV1TransformationAffine;
+pub const VAAV1TransformationCount: _bindgen_ty_20 =
+    _bindgen_ty_20::VAAV1TransformationCount;
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum _bindgen_ty_20 {
+    VAAV1TransformationIdentity = 0,
+    VAAV1TransformationTranslation = 1,
+    VAAV1TransformationRotzoom = 2,
+    VAAV1TransformationAffine = 3,
+    VAAV1TransformationCount = 4,
+}
+pub use self::_bindgen_ty_20 as VAAV1TransformationType;
+#[repr(C)]
+#[derive(Debug, Copy)]
+pub struct _VAWarpedMotionParamsAV1 {
+    pub wmtype: VAAV1TransformationType,
+    pub wmmat: [i32; 8usize],
+    pub invalid: u8,
+    pub va_reserved: [u32; 4usize],
+}
+#[test]
+fn bindgen_test_layout__VAWarpedMotionParamsAV1() {
+    assert_eq!(::std::mem::size_of::<_VAWarpedMotionParamsAV1>() , 56usize);
+    assert_eq!(::std::mem::align_of::<_VAWarpedMotionParamsAV1>() , 4usize);
+}
+impl Clone for _VAWarpedMotionParamsAV1 {
+    fn clone(&self) -> Self { *self }
+}
+pub type VAWarpedMotionParamsAV1 = _VAWarpedMotionParamsAV1;
+#[repr(C)]
+#[derive(Copy)]
+pub struct _VADecPictureParameterBufferAV1 {
+    pub profile: u8,
+    pub order_hint_bits_minus_1: u8,
+    pub bit_depth_idx: u8,
+    pub matrix_coefficients: u8,
+    pub seq_info_fields: _VADecPictureParameterBufferAV1__bindgen_ty_1,
+    pub current_frame: VASurfaceID,
+    pub current_display_picture: VASurfaceID,
+    pub anchor_frames_num: u8,
+    pub anchor_frames_list: *mut VASurfaceID,
+    pub frame_width_minus1: u16,
+    pub frame_height_minus1: u16,
+    pub output_frame_width_in_tiles_minus_1: u16,
+    pub output_frame_height_in_tiles_minus_1: u16,
+    pub ref_frame_map: [VASurfaceID; 8usize],
+    pub ref_frame_idx: [u8; 7usize],
+    pub primary_ref_frame: u8,
+    pub order_hint: u8,
+    pub seg_info: VASegmentationStructAV1,
+    pub film_grain_info: VAFilmGrainStructAV1,
+    pub tile_cols: u8,
+    pub tile_rows: u8,
+    pub width_in_sbs_minus_1: [u16; 63usize],
+    pub height_in_sbs_minus_1: [u16; 63usize],
+    pub tile_count_minus_1: u16,
+    pub context_update_tile_id: u16,
+    pub pic_info_fields: _VADecPictureParameterBufferAV1__bindgen_ty_2,
+    pub superres_scale_denominator: u8,
+    pub interp_filter: u8,
+    pub filter_level: [u8; 2usize],
+    pub filter_level_u: u8,
+    pub filter_level_v: u8,
+    pub loop_filter_info_fields: _VADecPictureParameterBufferAV1__bindgen_ty_3,
+    pub ref_deltas: [i8; 8usize],
+    pub mode_deltas: [i8; 2usize],
+    pub base_qindex: u8,
+    pub y_dc_delta_q: i8,
+    pub u_dc_delta_q: i8,
+    pub u_ac_delta_q: i8,
+    pub v_dc_delta_q: i8,
+    pub v_ac_delta_q: i8,
+    pub qmatrix_fields: _VADecPictureParameterBufferAV1__bindgen_ty_4,
+    pub mode_control_fields: _VADecPictureParameterBufferAV1__bindgen_ty_5,
+    pub cdef_damping_minus_3: u8,
+    pub cdef_bits: u8,
+    pub cdef_y_strengths: [u8; 8usize],
+    pub cdef_uv_strengths: [u8; 8usize],
+    pub loop_restoration_fields: _VADecPictureParameterBufferAV1__bindgen_ty_6,
+    pub wm: [VAWarpedMotionParamsAV1; 7usize],
+    pub va_reserved: [u32; 8usize],
+}
+#[repr(C)]
+#[derive(Debug, Copy)]
+pub struct _VADecPictureParameterBufferAV1__bindgen_ty_1 {
+    pub fields: __BindgenUnionField<_VADecPictureParameterBufferAV1__bindgen_ty_1__bindgen_ty_1>,
+    pub value: __BindgenUnionField<u32>,
+    pub bindgen_union_field: u32,
+}
+#[repr(C)]
+#[derive(Debug, Copy)]
+pub struct _VADecPictureParameterBufferAV1__bindgen_ty_1__bindgen_ty_1 {
+    pub _bitfield_1: u32,
+}
+#[test]
+fn bindgen_test_layout__VADecPictureParameterBufferAV1__bindgen_ty_1__bindgen_ty_1() {
+    assert_eq!(::std::mem::size_of::<_VADecPictureParameterBufferAV1__bindgen_ty_1__bindgen_ty_1>()
+               , 4usize);
+    assert_eq!(::std::mem::align_of::<_VADecPictureParameterBufferAV1__bindgen_ty_1__bindgen_ty_1>()
+               , 4usize);
+}
+impl Clone for _VADecPictureParameterBufferAV1__bindgen_ty_1__bindgen_ty_1 {
+    fn clone(&self) -> Self { *self }
+}
+impl _VADecPictureParameterBufferAV1__bindgen_ty_1__bindgen_ty_1 {
+    #[inline]
+    pub fn still_picture(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (1usize as u32)) >>
+                                       0u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_still_picture(&mut self, val: u32) {
+        self._bitfield_1 &= !(1usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 0u32) & (1usize as u32);
+    }
+    #[inline]
+    pub fn use_128x128_superblock(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (2usize as u32)) >>
+                                       1u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_use_128x128_superblock(&mut self, val: u32) {
+        self._bitfield_1 &= !(2usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 1u32) & (2usize as u32);
+    }
+    #[inline]
+    pub fn enable_filter_intra(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (4usize as u32)) >>
+                                       2u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_enable_filter_intra(&mut self, val: u32) {
+        self._bitfield_1 &= !(4usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 2u32) & (4usize as u32);
+    }
+    #[inline]
+    pub fn enable_intra_edge_filter(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (8usize as u32)) >>
+                                       3u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_enable_intra_edge_filter(&mut self, val: u32) {
+        self._bitfield_1 &= !(8usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 3u32) & (8usize as u32);
+    }
+    #[inline]
+    pub fn enable_interintra_compound(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (16usize as u32)) >>
+                                       4u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_enable_interintra_compound(&mut self, val: u32) {
+        self._bitfield_1 &= !(16usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 4u32) & (16usize as u32);
+    }
+    #[inline]
+    pub fn enable_masked_compound(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (32usize as u32)) >>
+                                       5u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_enable_masked_compound(&mut self, val: u32) {
+        self._bitfield_1 &= !(32usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 5u32) & (32usize as u32);
+    }
+    #[inline]
+    pub fn enable_dual_filter(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (64usize as u32)) >>
+                                       6u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_enable_dual_filter(&mut self, val: u32) {
+        self._bitfield_1 &= !(64usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 6u32) & (64usize as u32);
+    }
+    #[inline]
+    pub fn enable_order_hint(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (128usize as u32)) >>
+                                       7u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_enable_order_hint(&mut self, val: u32) {
+        self._bitfield_1 &= !(128usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 7u32) & (128usize as u32);
+    }
+    #[inline]
+    pub fn enable_jnt_comp(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (256usize as u32)) >>
+                                       8u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_enable_jnt_comp(&mut self, val: u32) {
+        self._bitfield_1 &= !(256usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 8u32) & (256usize as u32);
+    }
+    #[inline]
+    pub fn enable_cdef(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (512usize as u32)) >>
+                                       9u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_enable_cdef(&mut self, val: u32) {
+        self._bitfield_1 &= !(512usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 9u32) & (512usize as u32);
+    }
+    #[inline]
+    pub fn mono_chrome(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (1024usize as u32)) >>
+                                       10u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_mono_chrome(&mut self, val: u32) {
+        self._bitfield_1 &= !(1024usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 10u32) & (1024usize as u32);
+    }
+    #[inline]
+    pub fn color_range(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (2048usize as u32)) >>
+                                       11u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_color_range(&mut self, val: u32) {
+        self._bitfield_1 &= !(2048usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 11u32) & (2048usize as u32);
+    }
+    #[inline]
+    pub fn subsampling_x(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (4096usize as u32)) >>
+                                       12u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_subsampling_x(&mut self, val: u32) {
+        self._bitfield_1 &= !(4096usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 12u32) & (4096usize as u32);
+    }
+    #[inline]
+    pub fn subsampling_y(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (8192usize as u32)) >>
+                                       13u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_subsampling_y(&mut self, val: u32) {
+        self._bitfield_1 &= !(8192usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 13u32) & (8192usize as u32);
+    }
+    #[inline]
+    pub fn chroma_sample_position(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (16384usize as u32)) >>
+                                       14u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_chroma_sample_position(&mut self, val: u32) {
+        self._bitfield_1 &= !(16384usize as u32);
+        self._bitfield_1 |=
+            ((val as u32 as u32) << 14u32) & (16384usize as u32);
+    }
+    #[inline]
+    pub fn film_grain_params_present(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (32768usize as u32)) >>
+                                       15u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_film_grain_params_present(&mut self, val: u32) {
+        self._bitfield_1 &= !(32768usize as u32);
+        self._bitfield_1 |=
+            ((val as u32 as u32) << 15u32) & (32768usize as u32);
+    }
+    #[inline]
+    pub fn reserved(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (4294901760usize as u32)) >>
+                                       16u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_reserved(&mut self, val: u32) {
+        self._bitfield_1 &= !(4294901760usize as u32);
+        self._bitfield_1 |=
+            ((val as u32 as u32) << 16u32) & (4294901760usize as u32);
+    }
+}
+#[test]
+fn bindgen_test_layout__VADecPictureParameterBufferAV1__bindgen_ty_1() {
+    assert_eq!(::std::mem::size_of::<_VADecPictureParameterBufferAV1__bindgen_ty_1>()
+               , 4usize);
+    assert_eq!(::std::mem::align_of::<_VADecPictureParameterBufferAV1__bindgen_ty_1>()
+               , 4usize);
+}
+impl Clone for _VADecPictureParameterBufferAV1__bindgen_ty_1 {
+    fn clone(&self) -> Self { *self }
+}
+#[repr(C)]
+#[derive(Debug, Copy)]
+pub struct _VADecPictureParameterBufferAV1__bindgen_ty_2 {
+    pub bits: __BindgenUnionField<_VADecPictureParameterBufferAV1__bindgen_ty_2__bindgen_ty_1>,
+    pub value: __BindgenUnionField<u32>,
+    pub bindgen_union_field: u32,
+}
+#[repr(C)]
+#[derive(Debug, Copy)]
+pub struct _VADecPictureParameterBufferAV1__bindgen_ty_2__bindgen_ty_1 {
+    pub _bitfield_1: u32,
+}
+#[test]
+fn bindgen_test_layout__VADecPictureParameterBufferAV1__bindgen_ty_2__bindgen_ty_1() {
+    assert_eq!(::std::mem::size_of::<_VADecPictureParameterBufferAV1__bindgen_ty_2__bindgen_ty_1>()
+               , 4usize);
+    assert_eq!(::std::mem::align_of::<_VADecPictureParameterBufferAV1__bindgen_ty_2__bindgen_ty_1>()
+               , 4usize);
+}
+impl Clone for _VADecPictureParameterBufferAV1__bindgen_ty_2__bindgen_ty_1 {
+    fn clone(&self) -> Self { *self }
+}
+impl _VADecPictureParameterBufferAV1__bindgen_ty_2__bindgen_ty_1 {
+    #[inline]
+    pub fn frame_type(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (3usize as u32)) >>
+                                       0u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_frame_type(&mut self, val: u32) {
+        self._bitfield_1 &= !(3usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 0u32) & (3usize as u32);
+    }
+    #[inline]
+    pub fn show_frame(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (4usize as u32)) >>
+                                       2u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_show_frame(&mut self, val: u32) {
+        self._bitfield_1 &= !(4usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 2u32) & (4usize as u32);
+    }
+    #[inline]
+    pub fn showable_frame(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (8usize as u32)) >>
+                                       3u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_showable_frame(&mut self, val: u32) {
+        self._bitfield_1 &= !(8usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 3u32) & (8usize as u32);
+    }
+    #[inline]
+    pub fn error_resilient_mode(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (16usize as u32)) >>
+                                       4u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_error_resilient_mode(&mut self, val: u32) {
+        self._bitfield_1 &= !(16usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 4u32) & (16usize as u32);
+    }
+    #[inline]
+    pub fn disable_cdf_update(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (32usize as u32)) >>
+                                       5u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_disable_cdf_update(&mut self, val: u32) {
+        self._bitfield_1 &= !(32usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 5u32) & (32usize as u32);
+    }
+    #[inline]
+    pub fn allow_screen_content_tools(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (64usize as u32)) >>
+                                       6u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_allow_screen_content_tools(&mut self, val: u32) {
+        self._bitfield_1 &= !(64usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 6u32) & (64usize as u32);
+    }
+    #[inline]
+    pub fn force_integer_mv(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (128usize as u32)) >>
+                                       7u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_force_integer_mv(&mut self, val: u32) {
+        self._bitfield_1 &= !(128usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 7u32) & (128usize as u32);
+    }
+    #[inline]
+    pub fn allow_intrabc(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (256usize as u32)) >>
+                                       8u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_allow_intrabc(&mut self, val: u32) {
+        self._bitfield_1 &= !(256usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 8u32) & (256usize as u32);
+    }
+    #[inline]
+    pub fn use_superres(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (512usize as u32)) >>
+                                       9u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_use_superres(&mut self, val: u32) {
+        self._bitfield_1 &= !(512usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 9u32) & (512usize as u32);
+    }
+    #[inline]
+    pub fn allow_high_precision_mv(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (1024usize as u32)) >>
+                                       10u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_allow_high_precision_mv(&mut self, val: u32) {
+        self._bitfield_1 &= !(1024usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 10u32) & (1024usize as u32);
+    }
+    #[inline]
+    pub fn is_motion_mode_switchable(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (2048usize as u32)) >>
+                                       11u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_is_motion_mode_switchable(&mut self, val: u32) {
+        self._bitfield_1 &= !(2048usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 11u32) & (2048usize as u32);
+    }
+    #[inline]
+    pub fn use_ref_frame_mvs(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (4096usize as u32)) >>
+                                       12u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_use_ref_frame_mvs(&mut self, val: u32) {
+        self._bitfield_1 &= !(4096usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 12u32) & (4096usize as u32);
+    }
+    #[inline]
+    pub fn disable_frame_end_update_cdf(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (8192usize as u32)) >>
+                                       13u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_disable_frame_end_update_cdf(&mut self, val: u32) {
+        self._bitfield_1 &= !(8192usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 13u32) & (8192usize as u32);
+    }
+    #[inline]
+    pub fn uniform_tile_spacing_flag(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (16384usize as u32)) >>
+                                       14u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_uniform_tile_spacing_flag(&mut self, val: u32) {
+        self._bitfield_1 &= !(16384usize as u32);
+        self._bitfield_1 |=
+            ((val as u32 as u32) << 14u32) & (16384usize as u32);
+    }
+    #[inline]
+    pub fn allow_warped_motion(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (32768usize as u32)) >>
+                                       15u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_allow_warped_motion(&mut self, val: u32) {
+        self._bitfield_1 &= !(32768usize as u32);
+        self._bitfield_1 |=
+            ((val as u32 as u32) << 15u32) & (32768usize as u32);
+    }
+    #[inline]
+    pub fn large_scale_tile(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (65536usize as u32)) >>
+                                       16u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_large_scale_tile(&mut self, val: u32) {
+        self._bitfield_1 &= !(65536usize as u32);
+        self._bitfield_1 |=
+            ((val as u32 as u32) << 16u32) & (65536usize as u32);
+    }
+    #[inline]
+    pub fn reserved(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (4294836224usize as u32)) >>
+                                       17u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_reserved(&mut self, val: u32) {
+        self._bitfield_1 &= !(4294836224usize as u32);
+        self._bitfield_1 |=
+            ((val as u32 as u32) << 17u32) & (4294836224usize as u32);
+    }
+}
+#[test]
+fn bindgen_test_layout__VADecPictureParameterBufferAV1__bindgen_ty_2() {
+    assert_eq!(::std::mem::size_of::<_VADecPictureParameterBufferAV1__bindgen_ty_2>()
+               , 4usize);
+    assert_eq!(::std::mem::align_of::<_VADecPictureParameterBufferAV1__bindgen_ty_2>()
+               , 4usize);
+}
+impl Clone for _VADecPictureParameterBufferAV1__bindgen_ty_2 {
+    fn clone(&self) -> Self { *self }
+}
+#[repr(C)]
+#[derive(Debug, Copy)]
+pub struct _VADecPictureParameterBufferAV1__bindgen_ty_3 {
+    pub bits: __BindgenUnionField<_VADecPictureParameterBufferAV1__bindgen_ty_3__bindgen_ty_1>,
+    pub value: __BindgenUnionField<u8>,
+    pub bindgen_union_field: u8,
+}
+#[repr(C)]
+#[derive(Debug, Copy)]
+pub struct _VADecPictureParameterBufferAV1__bindgen_ty_3__bindgen_ty_1 {
+    pub _bitfield_1: u8,
+}
+#[test]
+fn bindgen_test_layout__VADecPictureParameterBufferAV1__bindgen_ty_3__bindgen_ty_1() {
+    assert_eq!(::std::mem::size_of::<_VADecPictureParameterBufferAV1__bindgen_ty_3__bindgen_ty_1>()
+               , 1usize);
+    assert_eq!(::std::mem::align_of::<_VADecPictureParameterBufferAV1__bindgen_ty_3__bindgen_ty_1>()
+               , 1usize);
+}
+impl Clone for _VADecPictureParameterBufferAV1__bindgen_ty_3__bindgen_ty_1 {
+    fn clone(&self) -> Self { *self }
+}
+impl _VADecPictureParameterBufferAV1__bindgen_ty_3__bindgen_ty_1 {
+    #[inline]
+    pub fn sharpness_level(&self) -> u8 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (7usize as u8)) >>
+                                       0u32) as u8)
+        }
+    }
+    #[inline]
+    pub fn set_sharpness_level(&mut self, val: u8) {
+        self._bitfield_1 &= !(7usize as u8);
+        self._bitfield_1 |= ((val as u8 as u8) << 0u32) & (7usize as u8);
+    }
+    #[inline]
+    pub fn mode_ref_delta_enabled(&self) -> u8 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (8usize as u8)) >>
+                                       3u32) as u8)
+        }
+    }
+    #[inline]
+    pub fn set_mode_ref_delta_enabled(&mut self, val: u8) {
+        self._bitfield_1 &= !(8usize as u8);
+        self._bitfield_1 |= ((val as u8 as u8) << 3u32) & (8usize as u8);
+    }
+    #[inline]
+    pub fn mode_ref_delta_update(&self) -> u8 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (16usize as u8)) >>
+                                       4u32) as u8)
+        }
+    }
+    #[inline]
+    pub fn set_mode_ref_delta_update(&mut self, val: u8) {
+        self._bitfield_1 &= !(16usize as u8);
+        self._bitfield_1 |= ((val as u8 as u8) << 4u32) & (16usize as u8);
+    }
+    #[inline]
+    pub fn reserved(&self) -> u8 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (224usize as u8)) >>
+                                       5u32) as u8)
+        }
+    }
+    #[inline]
+    pub fn set_reserved(&mut self, val: u8) {
+        self._bitfield_1 &= !(224usize as u8);
+        self._bitfield_1 |= ((val as u8 as u8) << 5u32) & (224usize as u8);
+    }
+}
+#[test]
+fn bindgen_test_layout__VADecPictureParameterBufferAV1__bindgen_ty_3() {
+    assert_eq!(::std::mem::size_of::<_VADecPictureParameterBufferAV1__bindgen_ty_3>()
+               , 1usize);
+    assert_eq!(::std::mem::align_of::<_VADecPictureParameterBufferAV1__bindgen_ty_3>()
+               , 1usize);
+}
+impl Clone for _VADecPictureParameterBufferAV1__bindgen_ty_3 {
+    fn clone(&self) -> Self { *self }
+}
+#[repr(C)]
+#[derive(Debug, Copy)]
+pub struct _VADecPictureParameterBufferAV1__bindgen_ty_4 {
+    pub bits: __BindgenUnionField<_VADecPictureParameterBufferAV1__bindgen_ty_4__bindgen_ty_1>,
+    pub value: __BindgenUnionField<u16>,
+    pub bindgen_union_field: u16,
+}
+#[repr(C)]
+#[derive(Debug, Copy)]
+pub struct _VADecPictureParameterBufferAV1__bindgen_ty_4__bindgen_ty_1 {
+    pub _bitfield_1: u16,
+}
+#[test]
+fn bindgen_test_layout__VADecPictureParameterBufferAV1__bindgen_ty_4__bindgen_ty_1() {
+    assert_eq!(::std::mem::size_of::<_VADecPictureParameterBufferAV1__bindgen_ty_4__bindgen_ty_1>()
+               , 2usize);
+    assert_eq!(::std::mem::align_of::<_VADecPictureParameterBufferAV1__bindgen_ty_4__bindgen_ty_1>()
+               , 2usize);
+}
+impl Clone for _VADecPictureParameterBufferAV1__bindgen_ty_4__bindgen_ty_1 {
+    fn clone(&self) -> Self { *self }
+}
+impl _VADecPictureParameterBufferAV1__bindgen_ty_4__bindgen_ty_1 {
+    #[inline]
+    pub fn using_qmatrix(&self) -> u16 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (1usize as u16)) >>
+                                       0u32) as u16)
+        }
+    }
+    #[inline]
+    pub fn set_using_qmatrix(&mut self, val: u16) {
+        self._bitfield_1 &= !(1usize as u16);
+        self._bitfield_1 |= ((val as u16 as u16) << 0u32) & (1usize as u16);
+    }
+    #[inline]
+    pub fn qm_y(&self) -> u16 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (30usize as u16)) >>
+                                       1u32) as u16)
+        }
+    }
+    #[inline]
+    pub fn set_qm_y(&mut self, val: u16) {
+        self._bitfield_1 &= !(30usize as u16);
+        self._bitfield_1 |= ((val as u16 as u16) << 1u32) & (30usize as u16);
+    }
+    #[inline]
+    pub fn qm_u(&self) -> u16 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (480usize as u16)) >>
+                                       5u32) as u16)
+        }
+    }
+    #[inline]
+    pub fn set_qm_u(&mut self, val: u16) {
+        self._bitfield_1 &= !(480usize as u16);
+        self._bitfield_1 |= ((val as u16 as u16) << 5u32) & (480usize as u16);
+    }
+    #[inline]
+    pub fn qm_v(&self) -> u16 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (7680usize as u16)) >>
+                                       9u32) as u16)
+        }
+    }
+    #[inline]
+    pub fn set_qm_v(&mut self, val: u16) {
+        self._bitfield_1 &= !(7680usize as u16);
+        self._bitfield_1 |= ((val as u16 as u16) << 9u32) & (7680usize as u16);
+    }
+    #[inline]
+    pub fn reserved(&self) -> u16 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (57344usize as u16)) >>
+                                       13u32) as u16)
+        }
+    }
+    #[inline]
+    pub fn set_reserved(&mut self, val: u16) {
+        self._bitfield_1 &= !(57344usize as u16);
+        self._bitfield_1 |=
+            ((val as u16 as u16) << 13u32) & (57344usize as u16);
+    }
+}
+#[test]
+fn bindgen_test_layout__VADecPictureParameterBufferAV1__bindgen_ty_4() {
+    assert_eq!(::std::mem::size_of::<_VADecPictureParameterBufferAV1__bindgen_ty_4>()
+               , 2usize);
+    assert_eq!(::std::mem::align_of::<_VADecPictureParameterBufferAV1__bindgen_ty_4>()
+               , 2usize);
+}
+impl Clone for _VADecPictureParameterBufferAV1__bindgen_ty_4 {
+    fn clone(&self) -> Self { *self }
+}
+#[repr(C)]
+#[derive(Debug, Copy)]
+pub struct _VADecPictureParameterBufferAV1__bindgen_ty_5 {
+    pub bits: __BindgenUnionField<_VADecPictureParameterBufferAV1__bindgen_ty_5__bindgen_ty_1>,
+    pub value: __BindgenUnionField<u32>,
+    pub bindgen_union_field: u32,
+}
+#[repr(C)]
+#[derive(Debug, Copy)]
+pub struct _VADecPictureParameterBufferAV1__bindgen_ty_5__bindgen_ty_1 {
+    pub _bitfield_1: u32,
+}
+#[test]
+fn bindgen_test_layout__VADecPictureParameterBufferAV1__bindgen_ty_5__bindgen_ty_1() {
+    assert_eq!(::std::mem::size_of::<_VADecPictureParameterBufferAV1__bindgen_ty_5__bindgen_ty_1>()
+               , 4usize);
+    assert_eq!(::std::mem::align_of::<_VADecPictureParameterBufferAV1__bindgen_ty_5__bindgen_ty_1>()
+               , 4usize);
+}
+impl Clone for _VADecPictureParameterBufferAV1__bindgen_ty_5__bindgen_ty_1 {
+    fn clone(&self) -> Self { *self }
+}
+impl _VADecPictureParameterBufferAV1__bindgen_ty_5__bindgen_ty_1 {
+    #[inline]
+    pub fn delta_q_present_flag(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (1usize as u32)) >>
+                                       0u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_delta_q_present_flag(&mut self, val: u32) {
+        self._bitfield_1 &= !(1usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 0u32) & (1usize as u32);
+    }
+    #[inline]
+    pub fn log2_delta_q_res(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (6usize as u32)) >>
+                                       1u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_log2_delta_q_res(&mut self, val: u32) {
+        self._bitfield_1 &= !(6usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 1u32) & (6usize as u32);
+    }
+    #[inline]
+    pub fn delta_lf_present_flag(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (8usize as u32)) >>
+                                       3u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_delta_lf_present_flag(&mut self, val: u32) {
+        self._bitfield_1 &= !(8usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 3u32) & (8usize as u32);
+    }
+    #[inline]
+    pub fn log2_delta_lf_res(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (48usize as u32)) >>
+                                       4u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_log2_delta_lf_res(&mut self, val: u32) {
+        self._bitfield_1 &= !(48usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 4u32) & (48usize as u32);
+    }
+    #[inline]
+    pub fn delta_lf_multi(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (64usize as u32)) >>
+                                       6u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_delta_lf_multi(&mut self, val: u32) {
+        self._bitfield_1 &= !(64usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 6u32) & (64usize as u32);
+    }
+    #[inline]
+    pub fn tx_mode(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (384usize as u32)) >>
+                                       7u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_tx_mode(&mut self, val: u32) {
+        self._bitfield_1 &= !(384usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 7u32) & (384usize as u32);
+    }
+    #[inline]
+    pub fn reference_select(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (512usize as u32)) >>
+                                       9u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_reference_select(&mut self, val: u32) {
+        self._bitfield_1 &= !(512usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 9u32) & (512usize as u32);
+    }
+    #[inline]
+    pub fn reduced_tx_set_used(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (1024usize as u32)) >>
+                                       10u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_reduced_tx_set_used(&mut self, val: u32) {
+        self._bitfield_1 &= !(1024usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 10u32) & (1024usize as u32);
+    }
+    #[inline]
+    pub fn skip_mode_present(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (2048usize as u32)) >>
+                                       11u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_skip_mode_present(&mut self, val: u32) {
+        self._bitfield_1 &= !(2048usize as u32);
+        self._bitfield_1 |= ((val as u32 as u32) << 11u32) & (2048usize as u32);
+    }
+    #[inline]
+    pub fn reserved(&self) -> u32 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (4294963200usize as u32)) >>
+                                       12u32) as u32)
+        }
+    }
+    #[inline]
+    pub fn set_reserved(&mut self, val: u32) {
+        self._bitfield_1 &= !(4294963200usize as u32);
+        self._bitfield_1 |=
+            ((val as u32 as u32) << 12u32) & (4294963200usize as u32);
+    }
+}
+#[test]
+fn bindgen_test_layout__VADecPictureParameterBufferAV1__bindgen_ty_5() {
+    assert_eq!(::std::mem::size_of::<_VADecPictureParameterBufferAV1__bindgen_ty_5>()
+               , 4usize);
+    assert_eq!(::std::mem::align_of::<_VADecPictureParameterBufferAV1__bindgen_ty_5>()
+               , 4usize);
+}
+impl Clone for _VADecPictureParameterBufferAV1__bindgen_ty_5 {
+    fn clone(&self) -> Self { *self }
+}
+#[repr(C)]
+#[derive(Debug, Copy)]
+pub struct _VADecPictureParameterBufferAV1__bindgen_ty_6 {
+    pub bits: __BindgenUnionField<_VADecPictureParameterBufferAV1__bindgen_ty_6__bindgen_ty_1>,
+    pub value: __BindgenUnionField<u16>,
+    pub bindgen_union_field: u16,
+}
+#[repr(C)]
+#[derive(Debug, Copy)]
+pub struct _VADecPictureParameterBufferAV1__bindgen_ty_6__bindgen_ty_1 {
+    pub _bitfield_1: u16,
+}
+#[test]
+fn bindgen_test_layout__VADecPictureParameterBufferAV1__bindgen_ty_6__bindgen_ty_1() {
+    assert_eq!(::std::mem::size_of::<_VADecPictureParameterBufferAV1__bindgen_ty_6__bindgen_ty_1>()
+               , 2usize);
+    assert_eq!(::std::mem::align_of::<_VADecPictureParameterBufferAV1__bindgen_ty_6__bindgen_ty_1>()
+               , 2usize);
+}
+impl Clone for _VADecPictureParameterBufferAV1__bindgen_ty_6__bindgen_ty_1 {
+    fn clone(&self) -> Self { *self }
+}
+impl _VADecPictureParameterBufferAV1__bindgen_ty_6__bindgen_ty_1 {
+    #[inline]
+    pub fn yframe_restoration_type(&self) -> u16 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (3usize as u16)) >>
+                                       0u32) as u16)
+        }
+    }
+    #[inline]
+    pub fn set_yframe_restoration_type(&mut self, val: u16) {
+        self._bitfield_1 &= !(3usize as u16);
+        self._bitfield_1 |= ((val as u16 as u16) << 0u32) & (3usize as u16);
+    }
+    #[inline]
+    pub fn cbframe_restoration_type(&self) -> u16 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (12usize as u16)) >>
+                                       2u32) as u16)
+        }
+    }
+    #[inline]
+    pub fn set_cbframe_restoration_type(&mut self, val: u16) {
+        self._bitfield_1 &= !(12usize as u16);
+        self._bitfield_1 |= ((val as u16 as u16) << 2u32) & (12usize as u16);
+    }
+    #[inline]
+    pub fn crframe_restoration_type(&self) -> u16 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (48usize as u16)) >>
+                                       4u32) as u16)
+        }
+    }
+    #[inline]
+    pub fn set_crframe_restoration_type(&mut self, val: u16) {
+        self._bitfield_1 &= !(48usize as u16);
+        self._bitfield_1 |= ((val as u16 as u16) << 4u32) & (48usize as u16);
+    }
+    #[inline]
+    pub fn lr_unit_shift(&self) -> u16 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (192usize as u16)) >>
+                                       6u32) as u16)
+        }
+    }
+    #[inline]
+    pub fn set_lr_unit_shift(&mut self, val: u16) {
+        self._bitfield_1 &= !(192usize as u16);
+        self._bitfield_1 |= ((val as u16 as u16) << 6u32) & (192usize as u16);
+    }
+    #[inline]
+    pub fn lr_uv_shift(&self) -> u16 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (256usize as u16)) >>
+                                       8u32) as u16)
+        }
+    }
+    #[inline]
+    pub fn set_lr_uv_shift(&mut self, val: u16) {
+        self._bitfield_1 &= !(256usize as u16);
+        self._bitfield_1 |= ((val as u16 as u16) << 8u32) & (256usize as u16);
+    }
+    #[inline]
+    pub fn reserved(&self) -> u16 {
+        unsafe {
+            ::std::mem::transmute(((self._bitfield_1 & (65024usize as u16)) >>
+                                       9u32) as u16)
+        }
+    }
+    #[inline]
+    pub fn set_reserved(&mut self, val: u16) {
+        self._bitfield_1 &= !(65024usize as u16);
+        self._bitfield_1 |= ((val as u16 as u16) << 9u32) & (65024usize as u16);
+    }
+}
+#[test]
+fn bindgen_test_layout__VADecPictureParameterBufferAV1__bindgen_ty_6() {
+    assert_eq!(::std::mem::size_of::<_VADecPictureParameterBufferAV1__bindgen_ty_6>()
+               , 2usize);
+    assert_eq!(::std::mem::align_of::<_VADecPictureParameterBufferAV1__bindgen_ty_6>()
+               , 2usize);
+}
+impl Clone for _VADecPictureParameterBufferAV1__bindgen_ty_6 {
+    fn clone(&self) -> Self { *self }
+}
+#[test]
+fn bindgen_test_layout__VADecPictureParameterBufferAV1() {
+    assert_eq!(::std::mem::size_of::<_VADecPictureParameterBufferAV1>() ,
+               1160usize);
+    assert_eq!(::std::mem::align_of::<_VADecPictureParameterBufferAV1>() ,
+               8usize);
+}
+impl Clone for _VADecPictureParameterBufferAV1 {
+    fn clone(&self) -> Self { *self }
+}
+pub type VADecPictureParameterBufferAV1 = _VADecPictureParameterBufferAV1;
+#[repr(C)]
+#[derive(Debug, Copy)]
+pub struct _VASliceParameterBufferAV1 {
+    pub slice_data_size: u32,
+    pub slice_data_offset: u32,
+    pub slice_data_flag: u32,
+    pub tile_row: u16,
+    pub tile_column: u16,
+    pub tg_start: u16,
+    pub tg_end: u16,
+    pub anchor_frame_idx: u8,
+    pub tile_idx_in_tile_list: u16,
+    pub va_reserved: [u32; 4usize],
+}
+#[test]
+fn bindgen_test_layout__VASliceParameterBufferAV1() {
+    assert_eq!(::std::mem::size_of::<_VASliceParameterBufferAV1>() , 40usize);
+    assert_eq!(::std::mem::align_of::<_VASliceParameterBufferAV1>() , 4usize);
+}
+impl Clone for _VASliceParameterBufferAV1 {
+    fn clone(&self) -> Self { *self }
+}
+pub type VASliceParameterBufferAV1 = _VASliceParameterBufferAV1;
 pub const VAEncPackedHeaderHEVC_VPS: _bindgen_ty_17 =
     _bindgen_ty_17::VAEncPackedHeaderHEVC_VPS;
 pub const VAEncPackedHeaderHEVC_SPS: _bindgen_ty_17 =
@@ -0,0 +1,206 @@
+// Copyright (c) 2017 zzoon <zzoon@igalia.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/* Futures resolving once the hardware is done with a surface, so that results can be
+ * awaited without blocking an executor thread. Polling only queries the surface status;
+ * pending tasks are woken again by a single waiter thread after a short interval.
+ * Dropping a future before it resolves is harmless, its task just gets a stale wake-up.
+ * The futures borrow the display, which is not Send, so they run on local executors. */
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::{self, Sender};
+use std::sync::Mutex;
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::Duration;
+
+use decoder::*;
+use va::*;
+
+const WAITER_INTERVAL_MS: u64 = 1;
+
+static WAITER: Mutex<Option<Sender<Waker>>> = Mutex::new(None);
+
+fn run_waiter(wakers: mpsc::Receiver<Waker>) {
+    /* Sleeps until a task waits, then wakes every task queued during the interval. Tasks
+     * queued again while those are woken wait for the next interval, so that a task polling
+     * right away does not spin */
+    while let Ok(waker) = wakers.recv() {
+        thread::sleep(Duration::from_millis(WAITER_INTERVAL_MS));
+        let mut queued = wakers.try_iter().collect::<Vec<_>>();
+        queued.push(waker);
+        for waker in queued {
+            waker.wake();
+        }
+    }
+}
+
+/* Has the task polled again after the waiter interval */
+fn wake_later(waker: &Waker) {
+    let mut waiter = WAITER.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(ref sender) = *waiter {
+        if sender.send(waker.clone()).is_ok() {
+            return;
+        }
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    let _ = sender.send(waker.clone());
+    thread::spawn(move || run_waiter(receiver));
+    *waiter = Some(sender);
+}
+
+/* Resolves to the result of syncing the surface, once its rendering is over */
+#[derive(Debug)]
+pub struct VASurfaceFuture<'a> {
+    va_disp: &'a VADisplay,
+    surface: &'a VASurface,
+}
+
+impl<'a> VASurfaceFuture<'a> {
+    pub fn new(va_disp: &'a VADisplay, surface: &'a VASurface) -> VASurfaceFuture<'a> {
+        VASurfaceFuture {
+            va_disp: va_disp,
+            surface: surface,
+        }
+    }
+}
+
+impl<'a> Future for VASurfaceFuture<'a> {
+    type Output = Result<(), VASyncError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        match self.surface.try_sync(self.va_disp) {
+            Ok(true) => Poll::Ready(Ok(())),
+            Ok(false) => {
+                wake_later(cx.waker());
+                Poll::Pending
+            }
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+/* Resolves once the frame is decoded, recording its decoding errors as sync() does */
+#[derive(Debug)]
+pub struct VADecodedFrameFuture<'a> {
+    va_disp: &'a VADisplay,
+    frame: &'a mut VADecodedFrame,
+}
+
+impl<'a> VADecodedFrameFuture<'a> {
+    pub fn new(va_disp: &'a VADisplay, frame: &'a mut VADecodedFrame) -> VADecodedFrameFuture<'a> {
+        VADecodedFrameFuture {
+            va_disp: va_disp,
+            frame: frame,
+        }
+    }
+}
+
+impl<'a> Future for VADecodedFrameFuture<'a> {
+    type Output = Result<(), DecodeError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let va_disp = self.va_disp;
+        match self.frame.try_sync(va_disp) {
+            Ok(true) => Poll::Ready(Ok(())),
+            Ok(false) => {
+                wake_later(cx.waker());
+                Poll::Pending
+            }
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+/* Resolves to the coded buffer of an encoding once its source surface is done, mapping
+ * the buffer then no longer blocks */
+#[derive(Debug)]
+pub struct VACodedBufferFuture<'a> {
+    surface: VASurfaceFuture<'a>,
+    buffer: &'a VABuffer,
+}
+
+impl<'a> VACodedBufferFuture<'a> {
+    pub fn new(va_disp: &'a VADisplay,
+               surface: &'a VASurface,
+               buffer: &'a VABuffer)
+               -> VACodedBufferFuture<'a> {
+        VACodedBufferFuture {
+            surface: VASurfaceFuture::new(va_disp, surface),
+            buffer: buffer,
+        }
+    }
+}
+
+impl<'a> Future for VACodedBufferFuture<'a> {
+    type Output = Result<&'a VABuffer, VASyncError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let buffer = self.buffer;
+        Pin::new(&mut self.surface).poll(cx).map(|ret| ret.map(|_| buffer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::Wake;
+
+    /* Never ready, asks to be polled again on every poll */
+    struct StillPending;
+
+    impl Future for StillPending {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+            wake_later(cx.waker());
+            Poll::Pending
+        }
+    }
+
+    /* Polls the future again as soon as it is woken */
+    struct Repoll {
+        wakeups: AtomicUsize,
+        stopped: AtomicBool,
+    }
+
+    impl Wake for Repoll {
+        fn wake(self: Arc<Self>) {
+            if self.stopped.load(Ordering::SeqCst) {
+                return;
+            }
+            self.wakeups.fetch_add(1, Ordering::SeqCst);
+            let waker = Waker::from(self.clone());
+            let mut cx = Context::from_waker(&waker);
+            let _ = Pin::new(&mut StillPending).poll(&mut cx);
+        }
+    }
+
+    #[test]
+    fn test_pending_future_woken_once_per_interval() {
+        let repoll = Arc::new(Repoll {
+            wakeups: AtomicUsize::new(0),
+            stopped: AtomicBool::new(false),
+        });
+        let waker = Waker::from(repoll.clone());
+        let mut cx = Context::from_waker(&waker);
+        assert!(Pin::new(&mut StillPending).poll(&mut cx).is_pending());
+
+        let period_ms = 50;
+        thread::sleep(Duration::from_millis(period_ms));
+        repoll.stopped.store(true, Ordering::SeqCst);
+
+        let wakeups = repoll.wakeups.load(Ordering::SeqCst) as u64;
+        assert!(wakeups > 0);
+        assert!(wakeups <= period_ms / WAITER_INTERVAL_MS + 1);
+    }
+}
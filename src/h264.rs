@@ -0,0 +1,1754 @@
+// Copyright (c) 2017 zzoon <zzoon@igalia.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/* Parser for the H.264 syntax needed to drive VA decoding: parameter sets, slice headers
+ * and SEI messages of Annex B byte streams. Scaling lists are kept in zig-zag order. */
+
+use std::cmp;
+
+use bitreader::BitReader;
+use decoder::{find_start_codes, ZIGZAG};
+
+pub const NAL_SLICE: u8 = 1;
+pub const NAL_SLICE_IDR: u8 = 5;
+pub const NAL_SEI: u8 = 6;
+pub const NAL_SPS: u8 = 7;
+pub const NAL_PPS: u8 = 8;
+pub const NAL_AU_DELIMITER: u8 = 9;
+pub const NAL_END_OF_SEQUENCE: u8 = 10;
+pub const NAL_END_OF_STREAM: u8 = 11;
+pub const NAL_PREFIX: u8 = 14;
+pub const NAL_SUBSET_SPS: u8 = 15;
+pub const NAL_SLICE_EXTENSION: u8 = 20;
+
+pub const SLICE_TYPE_P: u8 = 0;
+pub const SLICE_TYPE_B: u8 = 1;
+pub const SLICE_TYPE_I: u8 = 2;
+pub const SLICE_TYPE_SP: u8 = 3;
+pub const SLICE_TYPE_SI: u8 = 4;
+
+pub const MAX_SPS_COUNT: usize = 32;
+pub const MAX_PPS_COUNT: usize = 256;
+
+/* Default scaling lists of Table 7-3 and 7-4, in zig-zag order */
+pub const DEFAULT_4X4_INTRA: [u8; 16] = [6, 13, 13, 20, 20, 20, 28, 28, 28, 28, 32, 32, 32, 37, 37,
+                                         42];
+pub const DEFAULT_4X4_INTER: [u8; 16] = [10, 14, 14, 20, 20, 20, 24, 24, 24, 24, 27, 27, 27, 30,
+                                         30, 34];
+pub const DEFAULT_8X8_INTRA: [u8; 64] = [6, 10, 10, 13, 11, 13, 16, 16, 16, 16, 18, 18, 18, 18, 18,
+                                         23, 23, 23, 23, 23, 23, 25, 25, 25, 25, 25, 25, 25, 27,
+                                         27, 27, 27, 27, 27, 27, 27, 29, 29, 29, 29, 29, 29, 29,
+                                         31, 31, 31, 31, 31, 31, 33, 33, 33, 33, 33, 36, 36, 36,
+                                         36, 38, 38, 38, 40, 40, 42];
+pub const DEFAULT_8X8_INTER: [u8; 64] = [9, 13, 13, 15, 13, 15, 17, 17, 17, 17, 19, 19, 19, 19, 19,
+                                         21, 21, 21, 21, 21, 21, 22, 22, 22, 22, 22, 22, 22, 24,
+                                         24, 24, 24, 24, 24, 24, 24, 25, 25, 25, 25, 25, 25, 25,
+                                         27, 27, 27, 27, 27, 27, 28, 28, 28, 28, 28, 30, 30, 30,
+                                         30, 32, 32, 32, 33, 33, 35];
+
+pub const ZIGZAG_4X4: [usize; 16] = [0, 1, 4, 8, 5, 2, 3, 6, 9, 12, 13, 10, 7, 11, 14, 15];
+
+/* Drops the emulation prevention byte of every 00 00 03 sequence */
+pub fn remove_emulation_prevention(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut zeros = 0;
+    for &byte in data {
+        if zeros >= 2 && byte == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        out.push(byte);
+    }
+    out
+}
+
+/* True while syntax elements remain before the rbsp_stop_one_bit */
+pub fn more_rbsp_data(br: &BitReader, data: &[u8]) -> bool {
+    match data.iter().rposition(|&byte| byte != 0) {
+        Some(last) => {
+            let stop_bit = last * 8 + 7 - data[last].trailing_zeros() as usize;
+            br.get_pos() < stop_bit
+        }
+        None => false,
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct ScalingLists {
+    pub list_4x4: [[u8; 16]; 6],
+    pub list_8x8: [[u8; 64]; 6],
+}
+
+impl ScalingLists {
+    pub fn new_flat() -> ScalingLists {
+        ScalingLists {
+            list_4x4: [[16; 16]; 6],
+            list_8x8: [[16; 64]; 6],
+        }
+    }
+
+    pub fn new_default() -> ScalingLists {
+        let mut lists = ScalingLists::new_flat();
+        for i in 0..6 {
+            lists.list_4x4[i] = if i < 3 { DEFAULT_4X4_INTRA } else { DEFAULT_4X4_INTER };
+            lists.list_8x8[i] = if i % 2 == 0 { DEFAULT_8X8_INTRA } else { DEFAULT_8X8_INTER };
+        }
+        lists
+    }
+
+    /* Lists that are not sent fall back to the previous list of the same kind, the first
+     * ones of each kind to `fallback` (rule A for sequences, B for pictures) */
+    fn parse(br: &mut BitReader,
+             count: usize,
+             fallback: &ScalingLists)
+             -> Result<ScalingLists, ()> {
+        let mut lists = ScalingLists::new_flat();
+
+        for i in 0..12 {
+            let present = i < count && br.read_flag()?;
+            if i < 6 {
+                if !present {
+                    lists.list_4x4[i] = if i == 0 || i == 3 {
+                        fallback.list_4x4[i]
+                    } else {
+                        lists.list_4x4[i - 1]
+                    };
+                } else if parse_scaling_list(br, &mut lists.list_4x4[i])? {
+                    lists.list_4x4[i] = if i < 3 { DEFAULT_4X4_INTRA } else { DEFAULT_4X4_INTER };
+                }
+            } else {
+                let k = i - 6;
+                if !present {
+                    lists.list_8x8[k] = if k < 2 {
+                        fallback.list_8x8[k]
+                    } else {
+                        lists.list_8x8[k - 2]
+                    };
+                } else if parse_scaling_list(br, &mut lists.list_8x8[k])? {
+                    lists.list_8x8[k] = if k % 2 == 0 {
+                        DEFAULT_8X8_INTRA
+                    } else {
+                        DEFAULT_8X8_INTER
+                    };
+                }
+            }
+        }
+
+        Ok(lists)
+    }
+
+    /* List `i` in raster scan order */
+    pub fn get_raster_4x4(&self, i: usize) -> [u8; 16] {
+        let mut list = [0; 16];
+        for (j, &pos) in ZIGZAG_4X4.iter().enumerate() {
+            list[pos] = self.list_4x4[i][j];
+        }
+        list
+    }
+
+    pub fn get_raster_8x8(&self, i: usize) -> [u8; 64] {
+        let mut list = [0; 64];
+        for (j, &pos) in ZIGZAG.iter().enumerate() {
+            list[pos] = self.list_8x8[i][j];
+        }
+        list
+    }
+}
+
+/* Returns true when the list signals the default one */
+fn parse_scaling_list(br: &mut BitReader, list: &mut [u8]) -> Result<bool, ()> {
+    let mut last_scale = 8;
+    let mut next_scale = 8;
+    for j in 0..list.len() {
+        if next_scale != 0 {
+            let delta_scale = br.read_se()?;
+            if delta_scale < -128 || delta_scale > 127 {
+                return Err(());
+            }
+            next_scale = (last_scale + delta_scale + 256) % 256;
+            if j == 0 && next_scale == 0 {
+                return Ok(true);
+            }
+        }
+        list[j] = if next_scale == 0 { last_scale } else { next_scale } as u8;
+        last_scale = list[j] as i32;
+    }
+    Ok(false)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Hrd {
+    pub cpb_cnt_minus1: u32,
+    pub bit_rate_value_minus1: Vec<u32>,
+    pub cpb_size_value_minus1: Vec<u32>,
+    pub initial_cpb_removal_delay_length_minus1: u8,
+    pub cpb_removal_delay_length_minus1: u8,
+    pub dpb_output_delay_length_minus1: u8,
+    pub time_offset_length: u8,
+}
+
+impl Hrd {
+    fn parse(br: &mut BitReader) -> Result<Hrd, ()> {
+        let mut hrd = Hrd::default();
+        hrd.cpb_cnt_minus1 = br.read_ue()?;
+        if hrd.cpb_cnt_minus1 > 31 {
+            return Err(());
+        }
+        /* bit_rate_scale, cpb_size_scale */
+        br.skip_bits(8)?;
+        for _ in 0..hrd.cpb_cnt_minus1 + 1 {
+            hrd.bit_rate_value_minus1.push(br.read_ue()?);
+            hrd.cpb_size_value_minus1.push(br.read_ue()?);
+            /* cbr_flag */
+            br.skip_bits(1)?;
+        }
+        hrd.initial_cpb_removal_delay_length_minus1 = br.read_bits(5)? as u8;
+        hrd.cpb_removal_delay_length_minus1 = br.read_bits(5)? as u8;
+        hrd.dpb_output_delay_length_minus1 = br.read_bits(5)? as u8;
+        hrd.time_offset_length = br.read_bits(5)? as u8;
+        Ok(hrd)
+    }
+}
+
+pub const ASPECT_RATIO_EXTENDED_SAR: u32 = 255;
+
+#[derive(Debug, Clone, Default)]
+pub struct Vui {
+    pub aspect_ratio_idc: u8,
+    pub sar_width: u16,
+    pub sar_height: u16,
+    pub video_full_range_flag: bool,
+    pub colour_primaries: u8,
+    pub transfer_characteristics: u8,
+    pub matrix_coefficients: u8,
+    pub num_units_in_tick: u32,
+    pub time_scale: u32,
+    pub fixed_frame_rate_flag: bool,
+    pub nal_hrd: Option<Hrd>,
+    pub vcl_hrd: Option<Hrd>,
+    pub low_delay_hrd_flag: bool,
+    pub pic_struct_present_flag: bool,
+    pub max_num_reorder_frames: Option<u32>,
+    pub max_dec_frame_buffering: Option<u32>,
+}
+
+impl Vui {
+    fn parse(br: &mut BitReader) -> Result<Vui, ()> {
+        let mut vui = Vui::default();
+        vui.colour_primaries = 2;
+        vui.transfer_characteristics = 2;
+        vui.matrix_coefficients = 2;
+
+        if br.read_flag()? {
+            let aspect_ratio_idc = br.read_bits(8)?;
+            if aspect_ratio_idc == ASPECT_RATIO_EXTENDED_SAR {
+                vui.sar_width = br.read_bits(16)? as u16;
+                vui.sar_height = br.read_bits(16)? as u16;
+            }
+            vui.aspect_ratio_idc = aspect_ratio_idc as u8;
+        }
+        /* overscan_info_present_flag, overscan_appropriate_flag */
+        if br.read_flag()? {
+            br.skip_bits(1)?;
+        }
+        if br.read_flag()? {
+            /* video_format */
+            br.skip_bits(3)?;
+            vui.video_full_range_flag = br.read_flag()?;
+            if br.read_flag()? {
+                vui.colour_primaries = br.read_bits(8)? as u8;
+                vui.transfer_characteristics = br.read_bits(8)? as u8;
+                vui.matrix_coefficients = br.read_bits(8)? as u8;
+            }
+        }
+        /* chroma_sample_loc_type_top_field, chroma_sample_loc_type_bottom_field */
+        if br.read_flag()? {
+            br.read_ue()?;
+            br.read_ue()?;
+        }
+        if br.read_flag()? {
+            vui.num_units_in_tick = br.read_bits(32)?;
+            vui.time_scale = br.read_bits(32)?;
+            vui.fixed_frame_rate_flag = br.read_flag()?;
+        }
+        if br.read_flag()? {
+            vui.nal_hrd = Some(Hrd::parse(br)?);
+        }
+        if br.read_flag()? {
+            vui.vcl_hrd = Some(Hrd::parse(br)?);
+        }
+        if vui.nal_hrd.is_some() || vui.vcl_hrd.is_some() {
+            vui.low_delay_hrd_flag = br.read_flag()?;
+        }
+        vui.pic_struct_present_flag = br.read_flag()?;
+        if br.read_flag()? {
+            /* motion_vectors_over_pic_boundaries_flag, max_bytes_per_pic_denom,
+             * max_bits_per_mb_denom, log2_max_mv_length_horizontal and vertical */
+            br.skip_bits(1)?;
+            for _ in 0..4 {
+                br.read_ue()?;
+            }
+            vui.max_num_reorder_frames = Some(br.read_ue()?);
+            vui.max_dec_frame_buffering = Some(br.read_ue()?);
+        }
+
+        Ok(vui)
+    }
+}
+
+/* Dependencies of one view, in view order */
+#[derive(Debug, Clone, Default)]
+pub struct MvcView {
+    pub view_id: u16,
+    pub anchor_refs: [Vec<u16>; 2],
+    pub non_anchor_refs: [Vec<u16>; 2],
+}
+
+fn parse_view_refs(br: &mut BitReader) -> Result<Vec<u16>, ()> {
+    let num_refs = br.read_ue()?;
+    if num_refs > 15 {
+        return Err(());
+    }
+    let mut refs = Vec::with_capacity(num_refs as usize);
+    for _ in 0..num_refs {
+        refs.push(br.read_ue()? as u16);
+    }
+    Ok(refs)
+}
+
+fn parse_mvc_extension(br: &mut BitReader) -> Result<Vec<MvcView>, ()> {
+    let num_views_minus1 = br.read_ue()?;
+    if num_views_minus1 > 1023 {
+        return Err(());
+    }
+    let mut views = Vec::with_capacity(num_views_minus1 as usize + 1);
+    for _ in 0..num_views_minus1 + 1 {
+        views.push(MvcView { view_id: br.read_ue()? as u16, ..Default::default() });
+    }
+    for view in views.iter_mut().skip(1) {
+        view.anchor_refs[0] = parse_view_refs(br)?;
+        view.anchor_refs[1] = parse_view_refs(br)?;
+    }
+    for view in views.iter_mut().skip(1) {
+        view.non_anchor_refs[0] = parse_view_refs(br)?;
+        view.non_anchor_refs[1] = parse_view_refs(br)?;
+    }
+    Ok(views)
+}
+
+#[derive(Clone)]
+pub struct Sps {
+    pub profile_idc: u8,
+    pub constraint_set_flags: u8,
+    pub level_idc: u8,
+    pub seq_parameter_set_id: u32,
+    pub chroma_format_idc: u8,
+    pub separate_colour_plane_flag: bool,
+    pub bit_depth_luma_minus8: u8,
+    pub bit_depth_chroma_minus8: u8,
+    pub seq_scaling_matrix_present_flag: bool,
+    pub scaling_lists: ScalingLists,
+    pub log2_max_frame_num_minus4: u32,
+    pub pic_order_cnt_type: u32,
+    pub log2_max_pic_order_cnt_lsb_minus4: u32,
+    pub delta_pic_order_always_zero_flag: bool,
+    pub offset_for_non_ref_pic: i32,
+    pub offset_for_top_to_bottom_field: i32,
+    pub offset_for_ref_frame: Vec<i32>,
+    pub max_num_ref_frames: u32,
+    pub gaps_in_frame_num_value_allowed_flag: bool,
+    pub pic_width_in_mbs_minus1: u32,
+    pub pic_height_in_map_units_minus1: u32,
+    pub frame_mbs_only_flag: bool,
+    pub mb_adaptive_frame_field_flag: bool,
+    pub direct_8x8_inference_flag: bool,
+    pub frame_crop_offsets: Option<[u32; 4]>,
+    pub vui: Option<Vui>,
+    /* Views of the subset SPS of MVC streams */
+    pub mvc_views: Vec<MvcView>,
+}
+
+impl Sps {
+    /* `data` is the RBSP following the NAL unit header */
+    pub fn parse(data: &[u8]) -> Result<Sps, ()> {
+        Sps::parse_data(&mut BitReader::new(data))
+    }
+
+    fn parse_data(br: &mut BitReader) -> Result<Sps, ()> {
+        let profile_idc = br.read_bits(8)? as u8;
+        let constraint_set_flags = br.read_bits(8)? as u8;
+        let level_idc = br.read_bits(8)? as u8;
+        let seq_parameter_set_id = br.read_ue()?;
+        if seq_parameter_set_id as usize >= MAX_SPS_COUNT {
+            return Err(());
+        }
+
+        let mut chroma_format_idc = 1;
+        let mut separate_colour_plane_flag = false;
+        let mut bit_depth_luma_minus8 = 0;
+        let mut bit_depth_chroma_minus8 = 0;
+        let mut seq_scaling_matrix_present_flag = false;
+        let mut scaling_lists = ScalingLists::new_flat();
+        match profile_idc {
+            100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135 => {
+                chroma_format_idc = br.read_ue()?;
+                if chroma_format_idc > 3 {
+                    return Err(());
+                }
+                if chroma_format_idc == 3 {
+                    separate_colour_plane_flag = br.read_flag()?;
+                }
+                bit_depth_luma_minus8 = br.read_ue()?;
+                bit_depth_chroma_minus8 = br.read_ue()?;
+                if bit_depth_luma_minus8 > 6 || bit_depth_chroma_minus8 > 6 {
+                    return Err(());
+                }
+                /* qpprime_y_zero_transform_bypass_flag */
+                br.skip_bits(1)?;
+                seq_scaling_matrix_present_flag = br.read_flag()?;
+                if seq_scaling_matrix_present_flag {
+                    let count = if chroma_format_idc == 3 { 12 } else { 8 };
+                    scaling_lists = ScalingLists::parse(br, count, &ScalingLists::new_default())?;
+                }
+            }
+            _ => {}
+        }
+
+        let log2_max_frame_num_minus4 = br.read_ue()?;
+        if log2_max_frame_num_minus4 > 12 {
+            return Err(());
+        }
+        let pic_order_cnt_type = br.read_ue()?;
+        let mut log2_max_pic_order_cnt_lsb_minus4 = 0;
+        let mut delta_pic_order_always_zero_flag = false;
+        let mut offset_for_non_ref_pic = 0;
+        let mut offset_for_top_to_bottom_field = 0;
+        let mut offset_for_ref_frame = Vec::new();
+        match pic_order_cnt_type {
+            0 => {
+                log2_max_pic_order_cnt_lsb_minus4 = br.read_ue()?;
+                if log2_max_pic_order_cnt_lsb_minus4 > 12 {
+                    return Err(());
+                }
+            }
+            1 => {
+                delta_pic_order_always_zero_flag = br.read_flag()?;
+                offset_for_non_ref_pic = br.read_se()?;
+                offset_for_top_to_bottom_field = br.read_se()?;
+                let num_ref_frames_in_pic_order_cnt_cycle = br.read_ue()?;
+                if num_ref_frames_in_pic_order_cnt_cycle > 255 {
+                    return Err(());
+                }
+                for _ in 0..num_ref_frames_in_pic_order_cnt_cycle {
+                    offset_for_ref_frame.push(br.read_se()?);
+                }
+            }
+            2 => {}
+            _ => return Err(()),
+        }
+
+        let max_num_ref_frames = br.read_ue()?;
+        if max_num_ref_frames > 16 {
+            return Err(());
+        }
+        let gaps_in_frame_num_value_allowed_flag = br.read_flag()?;
+        let pic_width_in_mbs_minus1 = br.read_ue()?;
+        let pic_height_in_map_units_minus1 = br.read_ue()?;
+        let frame_mbs_only_flag = br.read_flag()?;
+        let mut mb_adaptive_frame_field_flag = false;
+        if !frame_mbs_only_flag {
+            mb_adaptive_frame_field_flag = br.read_flag()?;
+        }
+        let direct_8x8_inference_flag = br.read_flag()?;
+        let mut frame_crop_offsets = None;
+        if br.read_flag()? {
+            frame_crop_offsets = Some([br.read_ue()?, br.read_ue()?, br.read_ue()?, br.read_ue()?]);
+        }
+        let mut vui = None;
+        if br.read_flag()? {
+            vui = Some(Vui::parse(br)?);
+        }
+
+        Ok(Sps {
+            profile_idc: profile_idc,
+            constraint_set_flags: constraint_set_flags,
+            level_idc: level_idc,
+            seq_parameter_set_id: seq_parameter_set_id,
+            chroma_format_idc: chroma_format_idc as u8,
+            separate_colour_plane_flag: separate_colour_plane_flag,
+            bit_depth_luma_minus8: bit_depth_luma_minus8 as u8,
+            bit_depth_chroma_minus8: bit_depth_chroma_minus8 as u8,
+            seq_scaling_matrix_present_flag: seq_scaling_matrix_present_flag,
+            scaling_lists: scaling_lists,
+            log2_max_frame_num_minus4: log2_max_frame_num_minus4,
+            pic_order_cnt_type: pic_order_cnt_type,
+            log2_max_pic_order_cnt_lsb_minus4: log2_max_pic_order_cnt_lsb_minus4,
+            delta_pic_order_always_zero_flag: delta_pic_order_always_zero_flag,
+            offset_for_non_ref_pic: offset_for_non_ref_pic,
+            offset_for_top_to_bottom_field: offset_for_top_to_bottom_field,
+            offset_for_ref_frame: offset_for_ref_frame,
+            max_num_ref_frames: max_num_ref_frames,
+            gaps_in_frame_num_value_allowed_flag: gaps_in_frame_num_value_allowed_flag,
+            pic_width_in_mbs_minus1: pic_width_in_mbs_minus1,
+            pic_height_in_map_units_minus1: pic_height_in_map_units_minus1,
+            frame_mbs_only_flag: frame_mbs_only_flag,
+            mb_adaptive_frame_field_flag: mb_adaptive_frame_field_flag,
+            direct_8x8_inference_flag: direct_8x8_inference_flag,
+            frame_crop_offsets: frame_crop_offsets,
+            vui: vui,
+            mvc_views: Vec::new(),
+        })
+    }
+
+    /* Subset SPS; only the MVC extension is of interest, SVC ones are kept without views */
+    pub fn parse_subset(data: &[u8]) -> Result<Sps, ()> {
+        let mut br = BitReader::new(data);
+        let br = &mut br;
+        let mut sps = Sps::parse_data(br)?;
+        match sps.profile_idc {
+            118 | 128 | 134 => {
+                /* bit_equal_to_one */
+                if !br.read_flag()? {
+                    return Err(());
+                }
+                sps.mvc_views = parse_mvc_extension(br)?;
+            }
+            _ => {}
+        }
+        Ok(sps)
+    }
+
+    pub fn get_max_frame_num(&self) -> u32 {
+        1 << (self.log2_max_frame_num_minus4 + 4)
+    }
+
+    pub fn get_frame_height_in_mbs(&self) -> u32 {
+        (2 - self.frame_mbs_only_flag as u32) * (self.pic_height_in_map_units_minus1 + 1)
+    }
+
+    /* Frame size after cropping */
+    pub fn get_size(&self) -> (u32, u32) {
+        let width = (self.pic_width_in_mbs_minus1 + 1) * 16;
+        let height = self.get_frame_height_in_mbs() * 16;
+        let offsets = match self.frame_crop_offsets {
+            Some(offsets) => offsets,
+            None => return (width, height),
+        };
+
+        let (crop_unit_x, crop_unit_y) = match self.chroma_format_idc {
+            _ if self.separate_colour_plane_flag => (1, 1),
+            0 | 3 => (1, 1),
+            1 => (2, 2),
+            _ => (2, 1),
+        };
+        let crop_unit_y = crop_unit_y * (2 - self.frame_mbs_only_flag as u32);
+        (width.saturating_sub(crop_unit_x * (offsets[0] + offsets[1])),
+         height.saturating_sub(crop_unit_y * (offsets[2] + offsets[3])))
+    }
+
+    /* Frames the DPB holds, per view */
+    pub fn get_dpb_size(&self) -> usize {
+        let max_dpb_mbs = match self.level_idc {
+            9 | 10 => 396,
+            /* Level 1b of the Baseline, Main and Extended profiles */
+            11 if self.constraint_set_flags & 0x10 != 0 &&
+                  (self.profile_idc == 66 || self.profile_idc == 77 ||
+                   self.profile_idc == 88) => 396,
+            11 => 900,
+            12 | 13 | 20 => 2376,
+            21 => 4752,
+            22 | 30 => 8100,
+            31 => 18000,
+            32 => 20480,
+            40 | 41 => 32768,
+            42 => 34816,
+            50 => 110400,
+            51 | 52 => 184320,
+            _ => 696320,
+        };
+        let frame_mbs = (self.pic_width_in_mbs_minus1 + 1) * self.get_frame_height_in_mbs();
+        let mut size = cmp::min(max_dpb_mbs / frame_mbs, 16);
+        if let Some(ref vui) = self.vui {
+            if let Some(max_dec_frame_buffering) = vui.max_dec_frame_buffering {
+                size = max_dec_frame_buffering;
+            }
+        }
+        cmp::max(size, self.max_num_ref_frames).clamp(1, 16) as usize
+    }
+
+    /* Frames that may precede another one in decoding order and follow it in output order */
+    pub fn get_num_reorder_frames(&self) -> usize {
+        if let Some(max_num_reorder_frames) =
+            self.vui.as_ref().and_then(|vui| vui.max_num_reorder_frames) {
+            return cmp::min(max_num_reorder_frames as usize, self.get_dpb_size());
+        }
+        match self.profile_idc {
+            /* Intra profiles */
+            44 | 86 | 100 | 110 | 122 | 244 if self.constraint_set_flags & 0x10 != 0 => 0,
+            _ => self.get_dpb_size(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Pps {
+    pub pic_parameter_set_id: u32,
+    pub seq_parameter_set_id: u32,
+    pub entropy_coding_mode_flag: bool,
+    pub bottom_field_pic_order_in_frame_present_flag: bool,
+    pub num_slice_groups_minus1: u32,
+    pub slice_group_map_type: u32,
+    pub slice_group_change_rate_minus1: u32,
+    pub num_ref_idx_l0_default_active_minus1: u32,
+    pub num_ref_idx_l1_default_active_minus1: u32,
+    pub weighted_pred_flag: bool,
+    pub weighted_bipred_idc: u8,
+    pub pic_init_qp_minus26: i32,
+    pub pic_init_qs_minus26: i32,
+    pub chroma_qp_index_offset: i32,
+    pub deblocking_filter_control_present_flag: bool,
+    pub constrained_intra_pred_flag: bool,
+    pub redundant_pic_cnt_present_flag: bool,
+    pub transform_8x8_mode_flag: bool,
+    pub scaling_lists: ScalingLists,
+    pub second_chroma_qp_index_offset: i32,
+}
+
+impl Pps {
+    pub fn parse(data: &[u8], sps_list: &[Option<Sps>]) -> Result<Pps, ()> {
+        let mut br = BitReader::new(data);
+        let br = &mut br;
+        let pic_parameter_set_id = br.read_ue()?;
+        let seq_parameter_set_id = br.read_ue()?;
+        if pic_parameter_set_id as usize >= MAX_PPS_COUNT {
+            return Err(());
+        }
+        let sps = match sps_list.get(seq_parameter_set_id as usize) {
+            Some(&Some(ref sps)) => sps,
+            _ => return Err(()),
+        };
+
+        let entropy_coding_mode_flag = br.read_flag()?;
+        let bottom_field_pic_order_in_frame_present_flag = br.read_flag()?;
+        let num_slice_groups_minus1 = br.read_ue()?;
+        let mut slice_group_map_type = 0;
+        let mut slice_group_change_rate_minus1 = 0;
+        if num_slice_groups_minus1 > 7 {
+            return Err(());
+        }
+        if num_slice_groups_minus1 > 0 {
+            slice_group_map_type = br.read_ue()?;
+            match slice_group_map_type {
+                0 => {
+                    for _ in 0..num_slice_groups_minus1 + 1 {
+                        /* run_length_minus1 */
+                        br.read_ue()?;
+                    }
+                }
+                2 => {
+                    for _ in 0..num_slice_groups_minus1 {
+                        /* top_left, bottom_right */
+                        br.read_ue()?;
+                        br.read_ue()?;
+                    }
+                }
+                3..=5 => {
+                    /* slice_group_change_direction_flag */
+                    br.skip_bits(1)?;
+                    slice_group_change_rate_minus1 = br.read_ue()?;
+                }
+                6 => {
+                    let pic_size_in_map_units_minus1 = br.read_ue()?;
+                    let bits = 32 - num_slice_groups_minus1.leading_zeros();
+                    br.skip_bits((pic_size_in_map_units_minus1 as usize + 1) * bits as usize)?;
+                }
+                1 => {}
+                _ => return Err(()),
+            }
+        }
+
+        let num_ref_idx_l0_default_active_minus1 = br.read_ue()?;
+        let num_ref_idx_l1_default_active_minus1 = br.read_ue()?;
+        if num_ref_idx_l0_default_active_minus1 > 31 || num_ref_idx_l1_default_active_minus1 > 31 {
+            return Err(());
+        }
+        let weighted_pred_flag = br.read_flag()?;
+        let weighted_bipred_idc = br.read_bits(2)? as u8;
+        let pic_init_qp_minus26 = br.read_se()?;
+        let pic_init_qs_minus26 = br.read_se()?;
+        let chroma_qp_index_offset = br.read_se()?;
+        let deblocking_filter_control_present_flag = br.read_flag()?;
+        let constrained_intra_pred_flag = br.read_flag()?;
+        let redundant_pic_cnt_present_flag = br.read_flag()?;
+
+        let mut transform_8x8_mode_flag = false;
+        let mut scaling_lists = sps.scaling_lists;
+        let mut second_chroma_qp_index_offset = chroma_qp_index_offset;
+        if more_rbsp_data(br, data) {
+            transform_8x8_mode_flag = br.read_flag()?;
+            if br.read_flag()? {
+                let num_8x8 = if sps.chroma_format_idc == 3 { 6 } else { 2 };
+                let count = 6 + num_8x8 * transform_8x8_mode_flag as usize;
+                let fallback = if sps.seq_scaling_matrix_present_flag {
+                    sps.scaling_lists
+                } else {
+                    ScalingLists::new_default()
+                };
+                scaling_lists = ScalingLists::parse(br, count, &fallback)?;
+            }
+            second_chroma_qp_index_offset = br.read_se()?;
+        }
+
+        Ok(Pps {
+            pic_parameter_set_id: pic_parameter_set_id,
+            seq_parameter_set_id: seq_parameter_set_id,
+            entropy_coding_mode_flag: entropy_coding_mode_flag,
+            bottom_field_pic_order_in_frame_present_flag:
+                bottom_field_pic_order_in_frame_present_flag,
+            num_slice_groups_minus1: num_slice_groups_minus1,
+            slice_group_map_type: slice_group_map_type,
+            slice_group_change_rate_minus1: slice_group_change_rate_minus1,
+            num_ref_idx_l0_default_active_minus1: num_ref_idx_l0_default_active_minus1,
+            num_ref_idx_l1_default_active_minus1: num_ref_idx_l1_default_active_minus1,
+            weighted_pred_flag: weighted_pred_flag,
+            weighted_bipred_idc: weighted_bipred_idc,
+            pic_init_qp_minus26: pic_init_qp_minus26,
+            pic_init_qs_minus26: pic_init_qs_minus26,
+            chroma_qp_index_offset: chroma_qp_index_offset,
+            deblocking_filter_control_present_flag: deblocking_filter_control_present_flag,
+            constrained_intra_pred_flag: constrained_intra_pred_flag,
+            redundant_pic_cnt_present_flag: redundant_pic_cnt_present_flag,
+            transform_8x8_mode_flag: transform_8x8_mode_flag,
+            scaling_lists: scaling_lists,
+            second_chroma_qp_index_offset: second_chroma_qp_index_offset,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MvcExtension {
+    pub non_idr_flag: bool,
+    pub priority_id: u8,
+    pub view_id: u16,
+    pub temporal_id: u8,
+    pub anchor_pic_flag: bool,
+    pub inter_view_flag: bool,
+}
+
+impl MvcExtension {
+    /* nal_unit_header_mvc_extension, following the svc_extension_flag */
+    fn parse(br: &mut BitReader) -> Result<MvcExtension, ()> {
+        let ext = MvcExtension {
+            non_idr_flag: br.read_flag()?,
+            priority_id: br.read_bits(6)? as u8,
+            view_id: br.read_bits(10)? as u16,
+            temporal_id: br.read_bits(3)? as u8,
+            anchor_pic_flag: br.read_flag()?,
+            inter_view_flag: br.read_flag()?,
+        };
+        /* reserved_one_bit */
+        br.skip_bits(1)?;
+        Ok(ext)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct NalHeader {
+    pub nal_ref_idc: u8,
+    pub nal_unit_type: u8,
+    pub svc_extension_flag: bool,
+    /* Base view values unless a prefix or slice extension NAL unit carries them */
+    pub mvc: MvcExtension,
+    /* Size of the NAL unit header, 4 bytes with the MVC extension */
+    pub header_bytes: usize,
+}
+
+impl NalHeader {
+    pub fn parse(data: &[u8]) -> Result<NalHeader, ()> {
+        let mut br = BitReader::new(data);
+        /* forbidden_zero_bit */
+        if br.read_flag()? {
+            return Err(());
+        }
+        let mut nal = NalHeader {
+            nal_ref_idc: br.read_bits(2)? as u8,
+            nal_unit_type: br.read_bits(5)? as u8,
+            svc_extension_flag: false,
+            mvc: MvcExtension { inter_view_flag: true, ..Default::default() },
+            header_bytes: 1,
+        };
+        nal.mvc.non_idr_flag = nal.nal_unit_type != NAL_SLICE_IDR;
+
+        if nal.nal_unit_type == NAL_PREFIX || nal.nal_unit_type == NAL_SLICE_EXTENSION {
+            nal.svc_extension_flag = br.read_flag()?;
+            /* The SVC extension is not parsed, its slices are left to the caller to skip */
+            if !nal.svc_extension_flag {
+                nal.mvc = MvcExtension::parse(&mut br)?;
+            }
+            nal.header_bytes = 4;
+        }
+
+        Ok(nal)
+    }
+
+    pub fn is_idr(&self) -> bool {
+        match self.nal_unit_type {
+            NAL_SLICE_EXTENSION => !self.mvc.non_idr_flag,
+            t => t == NAL_SLICE_IDR,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RefPicListModification {
+    pub modification_of_pic_nums_idc: u32,
+    /* abs_diff_pic_num_minus1, long_term_pic_num or abs_diff_view_idx_minus1 */
+    pub value: u32,
+}
+
+fn parse_ref_pic_list_modification(br: &mut BitReader,
+                                   mvc: bool)
+                                   -> Result<Vec<RefPicListModification>, ()> {
+    let mut modifications = Vec::new();
+    if !br.read_flag()? {
+        return Ok(modifications);
+    }
+    loop {
+        let idc = br.read_ue()?;
+        match idc {
+            0..=2 => {}
+            3 => break,
+            4 | 5 if mvc => {}
+            _ => return Err(()),
+        }
+        if modifications.len() > 32 {
+            return Err(());
+        }
+        modifications.push(RefPicListModification {
+            modification_of_pic_nums_idc: idc,
+            value: br.read_ue()?,
+        });
+    }
+    Ok(modifications)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PredWeightTable {
+    pub luma_log2_weight_denom: u8,
+    pub chroma_log2_weight_denom: u8,
+    pub luma_weight_flag: [bool; 2],
+    pub chroma_weight_flag: [bool; 2],
+    pub luma_weight: [[i16; 32]; 2],
+    pub luma_offset: [[i16; 32]; 2],
+    pub chroma_weight: [[[i16; 2]; 32]; 2],
+    pub chroma_offset: [[[i16; 2]; 32]; 2],
+}
+
+impl PredWeightTable {
+    fn parse(br: &mut BitReader,
+             chroma_array_type: u8,
+             num_ref_idx_active: [u32; 2],
+             num_lists: usize)
+             -> Result<PredWeightTable, ()> {
+        let mut table = PredWeightTable::default();
+        table.luma_log2_weight_denom = br.read_ue()? as u8;
+        if table.luma_log2_weight_denom > 7 {
+            return Err(());
+        }
+        if chroma_array_type != 0 {
+            table.chroma_log2_weight_denom = br.read_ue()? as u8;
+            if table.chroma_log2_weight_denom > 7 {
+                return Err(());
+            }
+        }
+
+        for list in 0..num_lists {
+            for i in 0..num_ref_idx_active[list] as usize {
+                table.luma_weight[list][i] = 1 << table.luma_log2_weight_denom;
+                if br.read_flag()? {
+                    table.luma_weight_flag[list] = true;
+                    table.luma_weight[list][i] = br.read_se()? as i16;
+                    table.luma_offset[list][i] = br.read_se()? as i16;
+                }
+                if chroma_array_type == 0 {
+                    continue;
+                }
+                table.chroma_weight[list][i] = [1 << table.chroma_log2_weight_denom; 2];
+                if br.read_flag()? {
+                    table.chroma_weight_flag[list] = true;
+                    for j in 0..2 {
+                        table.chroma_weight[list][i][j] = br.read_se()? as i16;
+                        table.chroma_offset[list][i][j] = br.read_se()? as i16;
+                    }
+                }
+            }
+        }
+
+        Ok(table)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RefPicMarkingOp {
+    pub memory_management_control_operation: u32,
+    pub difference_of_pic_nums_minus1: u32,
+    pub long_term_pic_num: u32,
+    pub long_term_frame_idx: u32,
+    pub max_long_term_frame_idx_plus1: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DecRefPicMarking {
+    pub no_output_of_prior_pics_flag: bool,
+    pub long_term_reference_flag: bool,
+    /* Set when adaptive_ref_pic_marking_mode_flag is */
+    pub operations: Option<Vec<RefPicMarkingOp>>,
+}
+
+impl DecRefPicMarking {
+    fn parse(br: &mut BitReader, idr: bool) -> Result<DecRefPicMarking, ()> {
+        let mut marking = DecRefPicMarking::default();
+        if idr {
+            marking.no_output_of_prior_pics_flag = br.read_flag()?;
+            marking.long_term_reference_flag = br.read_flag()?;
+            return Ok(marking);
+        }
+        if !br.read_flag()? {
+            return Ok(marking);
+        }
+
+        let mut operations = Vec::new();
+        loop {
+            let mut op = RefPicMarkingOp::default();
+            op.memory_management_control_operation = br.read_ue()?;
+            match op.memory_management_control_operation {
+                0 => break,
+                1 => op.difference_of_pic_nums_minus1 = br.read_ue()?,
+                2 => op.long_term_pic_num = br.read_ue()?,
+                3 => {
+                    op.difference_of_pic_nums_minus1 = br.read_ue()?;
+                    op.long_term_frame_idx = br.read_ue()?;
+                }
+                4 => op.max_long_term_frame_idx_plus1 = br.read_ue()?,
+                5 => {}
+                6 => op.long_term_frame_idx = br.read_ue()?,
+                _ => return Err(()),
+            }
+            if operations.len() > 66 {
+                return Err(());
+            }
+            operations.push(op);
+        }
+        marking.operations = Some(operations);
+
+        Ok(marking)
+    }
+
+    pub fn has_operation(&self, operation: u32) -> bool {
+        match self.operations {
+            Some(ref ops) => ops.iter().any(|op| op.memory_management_control_operation == operation),
+            None => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SliceHeader {
+    pub first_mb_in_slice: u32,
+    pub slice_type: u8,
+    pub pic_parameter_set_id: u32,
+    pub frame_num: u32,
+    pub field_pic_flag: bool,
+    pub bottom_field_flag: bool,
+    pub idr_pic_id: u32,
+    pub pic_order_cnt_lsb: u32,
+    pub delta_pic_order_cnt_bottom: i32,
+    pub delta_pic_order_cnt: [i32; 2],
+    pub redundant_pic_cnt: u32,
+    pub direct_spatial_mv_pred_flag: bool,
+    pub num_ref_idx_l0_active_minus1: u32,
+    pub num_ref_idx_l1_active_minus1: u32,
+    pub ref_pic_list_modification: [Vec<RefPicListModification>; 2],
+    pub pred_weight_table: Option<PredWeightTable>,
+    pub dec_ref_pic_marking: DecRefPicMarking,
+    pub cabac_init_idc: u8,
+    pub slice_qp_delta: i32,
+    pub disable_deblocking_filter_idc: u8,
+    pub slice_alpha_c0_offset_div2: i32,
+    pub slice_beta_offset_div2: i32,
+    /* Size of the header in bits, after the NAL unit header */
+    pub header_size: usize,
+}
+
+impl SliceHeader {
+    /* `data` is the RBSP following the NAL unit header, the SPS list being the subset ones
+     * for slice extensions */
+    pub fn parse(data: &[u8],
+                 nal: &NalHeader,
+                 sps_list: &[Option<Sps>],
+                 pps_list: &[Option<Pps>])
+                 -> Result<SliceHeader, ()> {
+        let mut br = BitReader::new(data);
+        let br = &mut br;
+        let mut hdr = SliceHeader::default();
+        hdr.first_mb_in_slice = br.read_ue()?;
+        let slice_type = br.read_ue()?;
+        if slice_type > 9 {
+            return Err(());
+        }
+        hdr.slice_type = (slice_type % 5) as u8;
+        hdr.pic_parameter_set_id = br.read_ue()?;
+
+        let pps = match pps_list.get(hdr.pic_parameter_set_id as usize) {
+            Some(&Some(ref pps)) => pps,
+            _ => return Err(()),
+        };
+        let sps = match sps_list.get(pps.seq_parameter_set_id as usize) {
+            Some(&Some(ref sps)) => sps,
+            _ => return Err(()),
+        };
+        let idr = nal.is_idr();
+
+        if sps.separate_colour_plane_flag {
+            /* colour_plane_id */
+            br.skip_bits(2)?;
+        }
+        hdr.frame_num = br.read_bits(sps.log2_max_frame_num_minus4 + 4)?;
+        if !sps.frame_mbs_only_flag {
+            hdr.field_pic_flag = br.read_flag()?;
+            if hdr.field_pic_flag {
+                hdr.bottom_field_flag = br.read_flag()?;
+            }
+        }
+        if idr {
+            hdr.idr_pic_id = br.read_ue()?;
+        }
+        if sps.pic_order_cnt_type == 0 {
+            hdr.pic_order_cnt_lsb = br.read_bits(sps.log2_max_pic_order_cnt_lsb_minus4 + 4)?;
+            if pps.bottom_field_pic_order_in_frame_present_flag && !hdr.field_pic_flag {
+                hdr.delta_pic_order_cnt_bottom = br.read_se()?;
+            }
+        }
+        if sps.pic_order_cnt_type == 1 && !sps.delta_pic_order_always_zero_flag {
+            hdr.delta_pic_order_cnt[0] = br.read_se()?;
+            if pps.bottom_field_pic_order_in_frame_present_flag && !hdr.field_pic_flag {
+                hdr.delta_pic_order_cnt[1] = br.read_se()?;
+            }
+        }
+        if pps.redundant_pic_cnt_present_flag {
+            hdr.redundant_pic_cnt = br.read_ue()?;
+        }
+        if hdr.slice_type == SLICE_TYPE_B {
+            hdr.direct_spatial_mv_pred_flag = br.read_flag()?;
+        }
+
+        hdr.num_ref_idx_l0_active_minus1 = pps.num_ref_idx_l0_default_active_minus1;
+        hdr.num_ref_idx_l1_active_minus1 = pps.num_ref_idx_l1_default_active_minus1;
+        let inter = hdr.slice_type != SLICE_TYPE_I && hdr.slice_type != SLICE_TYPE_SI;
+        if inter && br.read_flag()? {
+            hdr.num_ref_idx_l0_active_minus1 = br.read_ue()?;
+            if hdr.slice_type == SLICE_TYPE_B {
+                hdr.num_ref_idx_l1_active_minus1 = br.read_ue()?;
+            }
+        }
+        if hdr.num_ref_idx_l0_active_minus1 > 31 || hdr.num_ref_idx_l1_active_minus1 > 31 {
+            return Err(());
+        }
+
+        let mvc = nal.nal_unit_type == NAL_SLICE_EXTENSION;
+        if hdr.slice_type != SLICE_TYPE_I && hdr.slice_type != SLICE_TYPE_SI {
+            hdr.ref_pic_list_modification[0] = parse_ref_pic_list_modification(br, mvc)?;
+        }
+        if hdr.slice_type == SLICE_TYPE_B {
+            hdr.ref_pic_list_modification[1] = parse_ref_pic_list_modification(br, mvc)?;
+        }
+
+        if (pps.weighted_pred_flag &&
+            (hdr.slice_type == SLICE_TYPE_P || hdr.slice_type == SLICE_TYPE_SP)) ||
+           (pps.weighted_bipred_idc == 1 && hdr.slice_type == SLICE_TYPE_B) {
+            let chroma_array_type = if sps.separate_colour_plane_flag {
+                0
+            } else {
+                sps.chroma_format_idc
+            };
+            let num_lists = if hdr.slice_type == SLICE_TYPE_B { 2 } else { 1 };
+            hdr.pred_weight_table = Some(PredWeightTable::parse(br,
+                                                                    chroma_array_type,
+                                                                    [hdr.num_ref_idx_l0_active_minus1 + 1,
+                                                                     hdr.num_ref_idx_l1_active_minus1 + 1],
+                                                                    num_lists)?);
+        }
+        if nal.nal_ref_idc != 0 {
+            hdr.dec_ref_pic_marking = DecRefPicMarking::parse(br, idr)?;
+        }
+        if pps.entropy_coding_mode_flag && hdr.slice_type != SLICE_TYPE_I &&
+           hdr.slice_type != SLICE_TYPE_SI {
+            hdr.cabac_init_idc = br.read_ue()? as u8;
+            if hdr.cabac_init_idc > 2 {
+                return Err(());
+            }
+        }
+        hdr.slice_qp_delta = br.read_se()?;
+        if hdr.slice_type == SLICE_TYPE_SP || hdr.slice_type == SLICE_TYPE_SI {
+            if hdr.slice_type == SLICE_TYPE_SP {
+                /* sp_for_switch_flag */
+                br.skip_bits(1)?;
+            }
+            /* slice_qs_delta */
+            br.read_se()?;
+        }
+        if pps.deblocking_filter_control_present_flag {
+            hdr.disable_deblocking_filter_idc = br.read_ue()? as u8;
+            if hdr.disable_deblocking_filter_idc > 2 {
+                return Err(());
+            }
+            if hdr.disable_deblocking_filter_idc != 1 {
+                hdr.slice_alpha_c0_offset_div2 = br.read_se()?;
+                hdr.slice_beta_offset_div2 = br.read_se()?;
+            }
+        }
+        if pps.num_slice_groups_minus1 > 0 && pps.slice_group_map_type >= 3 &&
+           pps.slice_group_map_type <= 5 {
+            let pic_size_in_map_units = (sps.pic_width_in_mbs_minus1 + 1) *
+                                            (sps.pic_height_in_map_units_minus1 + 1);
+            let slice_group_change_rate = pps.slice_group_change_rate_minus1 + 1;
+            let count = pic_size_in_map_units / slice_group_change_rate +
+                        (pic_size_in_map_units % slice_group_change_rate != 0) as u32;
+            /* slice_group_change_cycle, Ceil(Log2(count + 1)) bits */
+            br.skip_bits(32 - count.leading_zeros() as usize)?;
+        }
+        hdr.header_size = br.get_pos();
+
+        Ok(hdr)
+    }
+}
+
+pub const SEI_BUFFERING_PERIOD: u32 = 0;
+pub const SEI_PIC_TIMING: u32 = 1;
+pub const SEI_USER_DATA_UNREGISTERED: u32 = 5;
+pub const SEI_RECOVERY_POINT: u32 = 6;
+pub const SEI_FRAME_PACKING_ARRANGEMENT: u32 = 45;
+
+#[derive(Debug, Clone, Default)]
+pub struct BufferingPeriod {
+    pub seq_parameter_set_id: u32,
+    /* initial_cpb_removal_delay and initial_cpb_removal_delay_offset of each CPB */
+    pub nal_initial_cpb_removal: Vec<(u32, u32)>,
+    pub vcl_initial_cpb_removal: Vec<(u32, u32)>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ClockTimestamp {
+    pub ct_type: u8,
+    pub nuit_field_based_flag: bool,
+    pub counting_type: u8,
+    pub discontinuity_flag: bool,
+    pub cnt_dropped_flag: bool,
+    pub n_frames: u8,
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
+    pub time_offset: i32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PicTiming {
+    pub cpb_removal_delay: Option<u32>,
+    pub dpb_output_delay: Option<u32>,
+    pub pic_struct: Option<u8>,
+    pub clock_timestamps: Vec<Option<ClockTimestamp>>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RecoveryPoint {
+    pub recovery_frame_cnt: u32,
+    pub exact_match_flag: bool,
+    pub broken_link_flag: bool,
+    pub changing_slice_group_idc: u8,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FramePacking {
+    pub frame_packing_arrangement_id: u32,
+    pub cancel_flag: bool,
+    pub arrangement_type: u8,
+    pub quincunx_sampling_flag: bool,
+    pub content_interpretation_type: u8,
+    pub spatial_flipping_flag: bool,
+    pub frame0_flipped_flag: bool,
+    pub field_views_flag: bool,
+    pub current_frame_is_frame0_flag: bool,
+    pub repetition_period: u32,
+}
+
+#[derive(Debug, Clone)]
+pub enum SeiMessage {
+    BufferingPeriod(BufferingPeriod),
+    PicTiming(PicTiming),
+    /* uuid_iso_iec_11578 and the payload following it */
+    UserDataUnregistered([u8; 16], Vec<u8>),
+    RecoveryPoint(RecoveryPoint),
+    FramePacking(FramePacking),
+    /* Payload type and data of the messages that are not parsed */
+    Other(u32, Vec<u8>),
+}
+
+fn read_sei_value(data: &[u8], pos: &mut usize) -> Result<usize, ()> {
+    let mut value = 0;
+    loop {
+        let byte = *data.get(*pos).ok_or(())?;
+        *pos += 1;
+        value += byte as usize;
+        if byte != 0xff {
+            return Ok(value);
+        }
+    }
+}
+
+fn parse_buffering_period(br: &mut BitReader, sps: &Sps) -> Result<BufferingPeriod, ()> {
+    let mut bp = BufferingPeriod::default();
+    bp.seq_parameter_set_id = br.read_ue()?;
+    let vui = match sps.vui {
+        Some(ref vui) => vui,
+        None => return Ok(bp),
+    };
+    for (i, hrd) in [&vui.nal_hrd, &vui.vcl_hrd].iter().enumerate() {
+        let hrd = match **hrd {
+            Some(ref hrd) => hrd,
+            None => continue,
+        };
+        let length = hrd.initial_cpb_removal_delay_length_minus1 as u32 + 1;
+        for _ in 0..hrd.cpb_cnt_minus1 + 1 {
+            let removal = (br.read_bits(length)?, br.read_bits(length)?);
+            if i == 0 {
+                bp.nal_initial_cpb_removal.push(removal);
+            } else {
+                bp.vcl_initial_cpb_removal.push(removal);
+            }
+        }
+    }
+    Ok(bp)
+}
+
+fn parse_clock_timestamp(br: &mut BitReader,
+                         time_offset_length: u32)
+                         -> Result<ClockTimestamp, ()> {
+    let mut ts = ClockTimestamp::default();
+    ts.ct_type = br.read_bits(2)? as u8;
+    ts.nuit_field_based_flag = br.read_flag()?;
+    ts.counting_type = br.read_bits(5)? as u8;
+    let full_timestamp_flag = br.read_flag()?;
+    ts.discontinuity_flag = br.read_flag()?;
+    ts.cnt_dropped_flag = br.read_flag()?;
+    ts.n_frames = br.read_bits(8)? as u8;
+    if full_timestamp_flag {
+        ts.seconds = br.read_bits(6)? as u8;
+        ts.minutes = br.read_bits(6)? as u8;
+        ts.hours = br.read_bits(5)? as u8;
+    } else if br.read_flag()? {
+        ts.seconds = br.read_bits(6)? as u8;
+        if br.read_flag()? {
+            ts.minutes = br.read_bits(6)? as u8;
+            if br.read_flag()? {
+                ts.hours = br.read_bits(5)? as u8;
+            }
+        }
+    }
+    if time_offset_length > 0 {
+        let value = br.read_bits(time_offset_length)?;
+        let shift = 32 - time_offset_length;
+        ts.time_offset = ((value << shift) as i32) >> shift;
+    }
+    Ok(ts)
+}
+
+fn parse_pic_timing(br: &mut BitReader, sps: &Sps) -> Result<PicTiming, ()> {
+    let mut pt = PicTiming::default();
+    let vui = match sps.vui {
+        Some(ref vui) => vui,
+        None => return Ok(pt),
+    };
+    let hrd = vui.nal_hrd.as_ref().or(vui.vcl_hrd.as_ref());
+    if let Some(hrd) = hrd {
+        pt.cpb_removal_delay = Some(br.read_bits(hrd.cpb_removal_delay_length_minus1 as u32 + 1)?);
+        pt.dpb_output_delay = Some(br.read_bits(hrd.dpb_output_delay_length_minus1 as u32 + 1)?);
+    }
+    if vui.pic_struct_present_flag {
+        let pic_struct = br.read_bits(4)? as u8;
+        /* Table D-1 */
+        let num_clock_ts = match pic_struct {
+            0..=2 => 1,
+            3 | 4 | 7 => 2,
+            5 | 6 | 8 => 3,
+            _ => return Err(()),
+        };
+        let time_offset_length = hrd.map_or(24, |hrd| hrd.time_offset_length as u32);
+        for _ in 0..num_clock_ts {
+            pt.clock_timestamps.push(if br.read_flag()? {
+                Some(parse_clock_timestamp(br, time_offset_length)?)
+            } else {
+                None
+            });
+        }
+        pt.pic_struct = Some(pic_struct);
+    }
+    Ok(pt)
+}
+
+fn parse_frame_packing(br: &mut BitReader) -> Result<FramePacking, ()> {
+    let mut fp = FramePacking::default();
+    fp.frame_packing_arrangement_id = br.read_ue()?;
+    fp.cancel_flag = br.read_flag()?;
+    if fp.cancel_flag {
+        return Ok(fp);
+    }
+    fp.arrangement_type = br.read_bits(7)? as u8;
+    fp.quincunx_sampling_flag = br.read_flag()?;
+    fp.content_interpretation_type = br.read_bits(6)? as u8;
+    fp.spatial_flipping_flag = br.read_flag()?;
+    fp.frame0_flipped_flag = br.read_flag()?;
+    fp.field_views_flag = br.read_flag()?;
+    fp.current_frame_is_frame0_flag = br.read_flag()?;
+    /* frame0_self_contained_flag, frame1_self_contained_flag */
+    br.skip_bits(2)?;
+    if !fp.quincunx_sampling_flag && fp.arrangement_type != 5 {
+        /* frame0 and frame1 grid positions */
+        br.skip_bits(16)?;
+    }
+    /* frame_packing_arrangement_reserved_byte */
+    br.skip_bits(8)?;
+    fp.repetition_period = br.read_ue()?;
+    Ok(fp)
+}
+
+/* Messages of a SEI RBSP; the timing ones are only parsed when `sps` gives their layout */
+pub fn parse_sei(data: &[u8], sps: Option<&Sps>) -> Result<Vec<SeiMessage>, ()> {
+    let mut messages = Vec::new();
+    let mut pos = 0;
+
+    /* Stops at the rbsp_trailing_bits */
+    while pos < data.len() && data[pos] != 0x80 {
+        let payload_type = read_sei_value(data, &mut pos)? as u32;
+        let payload_size = read_sei_value(data, &mut pos)?;
+        if data.len() - pos < payload_size {
+            return Err(());
+        }
+        let payload = &data[pos..pos + payload_size];
+        pos += payload_size;
+
+        let mut br = BitReader::new(payload);
+        let message = match (payload_type, sps) {
+            (SEI_BUFFERING_PERIOD, Some(sps)) => {
+                SeiMessage::BufferingPeriod(parse_buffering_period(&mut br, sps)?)
+            }
+            (SEI_PIC_TIMING, Some(sps)) => SeiMessage::PicTiming(parse_pic_timing(&mut br, sps)?),
+            (SEI_USER_DATA_UNREGISTERED, _) if payload_size >= 16 => {
+                let mut uuid = [0; 16];
+                uuid.copy_from_slice(&payload[..16]);
+                SeiMessage::UserDataUnregistered(uuid, payload[16..].to_vec())
+            }
+            (SEI_RECOVERY_POINT, _) => {
+                SeiMessage::RecoveryPoint(RecoveryPoint {
+                    recovery_frame_cnt: br.read_ue()?,
+                    exact_match_flag: br.read_flag()?,
+                    broken_link_flag: br.read_flag()?,
+                    changing_slice_group_idc: br.read_bits(2)? as u8,
+                })
+            }
+            (SEI_FRAME_PACKING_ARRANGEMENT, _) => {
+                SeiMessage::FramePacking(parse_frame_packing(&mut br)?)
+            }
+            _ => SeiMessage::Other(payload_type, payload.to_vec()),
+        };
+        messages.push(message);
+    }
+
+    Ok(messages)
+}
+
+/* NAL units of an Annex B byte stream, without start codes and trailing zero bytes */
+pub fn split_nal_units(data: &[u8]) -> Vec<&[u8]> {
+    let offsets = find_start_codes(data);
+    let mut units = Vec::with_capacity(offsets.len());
+
+    for (i, &start) in offsets.iter().enumerate() {
+        let end = offsets.get(i + 1).cloned().unwrap_or(data.len());
+        let mut nal = &data[start + 3..end];
+        while !nal.is_empty() && nal[nal.len() - 1] == 0 {
+            nal = &nal[..nal.len() - 1];
+        }
+        if !nal.is_empty() {
+            units.push(nal);
+        }
+    }
+
+    units
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitreader::BitReader;
+
+    /* SPS, PPS, IDR and P slice of a 352x288 Constrained Baseline stream */
+    const BASELINE_CIF: &'static [u8] = include_bytes!("../tests/data/h264/baseline_cif.h264");
+    /* AUD, SPS with VUI and scaling lists, PPS, SEI, then I, P and B slices of a 1080p High
+     * stream */
+    const HIGH_1080P: &'static [u8] = include_bytes!("../tests/data/h264/high_1080p.h264");
+
+    struct Stream {
+        sps: Vec<Option<Sps>>,
+        pps: Vec<Option<Pps>>,
+        sei: Vec<SeiMessage>,
+        slices: Vec<(NalHeader, SliceHeader)>,
+    }
+
+    fn parse_stream(data: &[u8]) -> Stream {
+        let mut stream = Stream {
+            sps: vec![None; MAX_SPS_COUNT],
+            pps: vec![None; MAX_PPS_COUNT],
+            sei: Vec::new(),
+            slices: Vec::new(),
+        };
+
+        for unit in split_nal_units(data) {
+            let nal = NalHeader::parse(unit).unwrap();
+            let rbsp = remove_emulation_prevention(&unit[nal.header_bytes..]);
+            match nal.nal_unit_type {
+                NAL_SPS => {
+                    let sps = Sps::parse(&rbsp).unwrap();
+                    let id = sps.seq_parameter_set_id as usize;
+                    stream.sps[id] = Some(sps);
+                }
+                NAL_PPS => {
+                    let pps = Pps::parse(&rbsp, &stream.sps).unwrap();
+                    let id = pps.pic_parameter_set_id as usize;
+                    stream.pps[id] = Some(pps);
+                }
+                NAL_SEI => {
+                    let sps = stream.sps[0].as_ref();
+                    stream.sei.extend(parse_sei(&rbsp, sps).unwrap());
+                }
+                NAL_SLICE | NAL_SLICE_IDR => {
+                    let hdr = SliceHeader::parse(&rbsp, &nal, &stream.sps, &stream.pps).unwrap();
+                    stream.slices.push((nal, hdr));
+                }
+                _ => {}
+            }
+        }
+
+        stream
+    }
+
+    #[test]
+    fn exp_golomb() {
+        /* 1 010 011 00100 */
+        let data = [0xa6, 0x40];
+        let mut br = BitReader::new(&data);
+        assert_eq!(br.read_ue(), Ok(0));
+        assert_eq!(br.read_ue(), Ok(1));
+        assert_eq!(br.read_ue(), Ok(2));
+        assert_eq!(br.read_ue(), Ok(3));
+
+        /* 010 011 00100 00101 */
+        let data = [0x4c, 0x85];
+        let mut br = BitReader::new(&data);
+        assert_eq!(br.read_se(), Ok(1));
+        assert_eq!(br.read_se(), Ok(-1));
+        assert_eq!(br.read_se(), Ok(2));
+        assert_eq!(br.read_se(), Ok(-2));
+
+        /* Runs of more than 31 leading zeros do not fit */
+        let data = [0, 0, 0, 0, 0x80];
+        assert_eq!(BitReader::new(&data).read_ue(), Err(()));
+    }
+
+    #[test]
+    fn emulation_prevention() {
+        assert_eq!(remove_emulation_prevention(&[0, 0, 3, 1, 0, 0, 3, 0, 0, 3]),
+                   vec![0, 0, 1, 0, 0, 0, 0]);
+        assert_eq!(remove_emulation_prevention(&[0, 0, 3, 3]), vec![0, 0, 3]);
+        assert_eq!(remove_emulation_prevention(&[0, 3, 0, 0, 2]), vec![0, 3, 0, 0, 2]);
+    }
+
+    #[test]
+    fn nal_units() {
+        let types: Vec<u8> = split_nal_units(BASELINE_CIF)
+            .iter()
+            .map(|unit| NalHeader::parse(unit).unwrap().nal_unit_type)
+            .collect();
+        assert_eq!(types, vec![NAL_SPS, NAL_PPS, NAL_SLICE_IDR, NAL_SLICE]);
+
+        let nal = NalHeader::parse(&[0x74, 0x00, 0x00, 0x47]).unwrap();
+        assert_eq!(nal.nal_unit_type, NAL_SLICE_EXTENSION);
+        assert_eq!(nal.header_bytes, 4);
+        assert!(!nal.svc_extension_flag);
+        assert!(nal.is_idr());
+        assert_eq!(nal.mvc.view_id, 1);
+        assert!(nal.mvc.anchor_pic_flag);
+        assert!(nal.mvc.inter_view_flag);
+
+        assert!(NalHeader::parse(&[0x80]).is_err());
+    }
+
+    #[test]
+    fn baseline_parameter_sets() {
+        let stream = parse_stream(BASELINE_CIF);
+        let sps = stream.sps[0].as_ref().unwrap();
+        assert_eq!(sps.profile_idc, 66);
+        assert_eq!(sps.constraint_set_flags, 0xc0);
+        assert_eq!(sps.level_idc, 20);
+        assert_eq!(sps.chroma_format_idc, 1);
+        assert_eq!(sps.pic_order_cnt_type, 2);
+        assert_eq!(sps.max_num_ref_frames, 1);
+        assert_eq!(sps.get_max_frame_num(), 16);
+        assert_eq!(sps.get_size(), (352, 288));
+        assert_eq!(sps.get_dpb_size(), 6);
+        assert_eq!(sps.get_num_reorder_frames(), 6);
+        assert!(sps.vui.is_none());
+        assert!(!sps.seq_scaling_matrix_present_flag);
+        assert_eq!(sps.scaling_lists.list_4x4[0], [16; 16]);
+
+        let pps = stream.pps[0].as_ref().unwrap();
+        assert!(!pps.entropy_coding_mode_flag);
+        assert!(pps.deblocking_filter_control_present_flag);
+        assert!(!pps.transform_8x8_mode_flag);
+        assert_eq!(pps.num_ref_idx_l0_default_active_minus1, 0);
+        assert_eq!(pps.second_chroma_qp_index_offset, pps.chroma_qp_index_offset);
+    }
+
+    #[test]
+    fn baseline_slices() {
+        let stream = parse_stream(BASELINE_CIF);
+        assert_eq!(stream.slices.len(), 2);
+
+        let (ref nal, ref idr) = stream.slices[0];
+        assert!(nal.is_idr());
+        assert_eq!(nal.nal_ref_idc, 3);
+        assert_eq!(idr.slice_type, SLICE_TYPE_I);
+        assert_eq!(idr.frame_num, 0);
+        assert_eq!(idr.idr_pic_id, 0);
+        assert_eq!(idr.slice_qp_delta, -2);
+        assert_eq!(idr.disable_deblocking_filter_idc, 0);
+        assert_eq!(idr.slice_alpha_c0_offset_div2, 1);
+        assert_eq!(idr.slice_beta_offset_div2, -1);
+        assert!(!idr.dec_ref_pic_marking.long_term_reference_flag);
+        assert_eq!(idr.header_size, 28);
+
+        let (ref nal, ref p) = stream.slices[1];
+        assert!(!nal.is_idr());
+        assert_eq!(p.slice_type, SLICE_TYPE_P);
+        assert_eq!(p.frame_num, 1);
+        assert_eq!(p.num_ref_idx_l0_active_minus1, 0);
+        assert!(p.ref_pic_list_modification[0].is_empty());
+        assert!(p.dec_ref_pic_marking.operations.is_none());
+        assert_eq!(p.disable_deblocking_filter_idc, 1);
+        assert_eq!(p.header_size, 18);
+    }
+
+    #[test]
+    fn high_sps_vui() {
+        let stream = parse_stream(HIGH_1080P);
+        let sps = stream.sps[0].as_ref().unwrap();
+        assert_eq!(sps.profile_idc, 100);
+        assert_eq!(sps.level_idc, 40);
+        assert_eq!(sps.log2_max_frame_num_minus4, 4);
+        assert_eq!(sps.pic_order_cnt_type, 0);
+        assert_eq!(sps.log2_max_pic_order_cnt_lsb_minus4, 2);
+        assert_eq!(sps.get_frame_height_in_mbs(), 68);
+        assert_eq!(sps.frame_crop_offsets, Some([0, 0, 0, 4]));
+        assert_eq!(sps.get_size(), (1920, 1080));
+
+        let vui = sps.vui.as_ref().unwrap();
+        assert_eq!(vui.aspect_ratio_idc, 255);
+        assert_eq!((vui.sar_width, vui.sar_height), (4, 3));
+        assert!(!vui.video_full_range_flag);
+        assert_eq!(vui.colour_primaries, 1);
+        assert_eq!(vui.matrix_coefficients, 1);
+        assert_eq!((vui.num_units_in_tick, vui.time_scale), (1001, 60000));
+        assert!(vui.fixed_frame_rate_flag);
+        assert!(vui.pic_struct_present_flag);
+        assert!(vui.vcl_hrd.is_none());
+        let hrd = vui.nal_hrd.as_ref().unwrap();
+        assert_eq!(hrd.cpb_cnt_minus1, 0);
+        assert_eq!(hrd.bit_rate_value_minus1, vec![9999]);
+        assert_eq!(hrd.cpb_size_value_minus1, vec![19999]);
+        assert_eq!(hrd.cpb_removal_delay_length_minus1, 23);
+        assert_eq!(hrd.time_offset_length, 24);
+        assert_eq!(vui.max_num_reorder_frames, Some(2));
+        assert_eq!(vui.max_dec_frame_buffering, Some(4));
+        assert_eq!(sps.get_dpb_size(), 4);
+        assert_eq!(sps.get_num_reorder_frames(), 2);
+    }
+
+    #[test]
+    fn scaling_lists() {
+        let stream = parse_stream(HIGH_1080P);
+
+        /* Fall-back rule A: lists 1 and 2 repeat list 0, 4 and 5 the default list 3 */
+        let sps = stream.sps[0].as_ref().unwrap();
+        assert!(sps.seq_scaling_matrix_present_flag);
+        assert_eq!(sps.scaling_lists.list_4x4[0], [20; 16]);
+        assert_eq!(sps.scaling_lists.list_4x4[2], [20; 16]);
+        assert_eq!(sps.scaling_lists.list_4x4[3], DEFAULT_4X4_INTER);
+        assert_eq!(sps.scaling_lists.list_4x4[5], DEFAULT_4X4_INTER);
+        assert_eq!(sps.scaling_lists.list_8x8[0][..], DEFAULT_8X8_INTRA[..]);
+        assert_eq!(sps.scaling_lists.list_8x8[1][..], [16; 64][..]);
+
+        /* Fall-back rule B: lists 0 and 3 come from the SPS */
+        let pps = stream.pps[1].as_ref().unwrap();
+        assert_eq!(pps.scaling_lists.list_4x4[0], [20; 16]);
+        assert_eq!(pps.scaling_lists.list_4x4[1], [6; 16]);
+        assert_eq!(pps.scaling_lists.list_4x4[2], [6; 16]);
+        assert_eq!(pps.scaling_lists.list_4x4[3], DEFAULT_4X4_INTER);
+        assert_eq!(pps.scaling_lists.list_8x8[0][..], DEFAULT_8X8_INTRA[..]);
+        assert_eq!(pps.scaling_lists.list_8x8[1][..], [16; 64][..]);
+
+        let raster = ScalingLists::new_default().get_raster_4x4(0);
+        assert_eq!(&raster[..4], &[6, 13, 20, 28]);
+        assert_eq!(raster[4], 13);
+        assert_eq!(raster[15], 42);
+        let raster = ScalingLists::new_default().get_raster_8x8(1);
+        assert_eq!(raster[1], 13);
+        assert_eq!(raster[8], 13);
+        assert_eq!(raster[63], 35);
+    }
+
+    #[test]
+    fn high_pps() {
+        let stream = parse_stream(HIGH_1080P);
+        let pps = stream.pps[1].as_ref().unwrap();
+        assert_eq!(pps.seq_parameter_set_id, 0);
+        assert!(pps.entropy_coding_mode_flag);
+        assert_eq!(pps.num_ref_idx_l0_default_active_minus1, 2);
+        assert_eq!(pps.weighted_bipred_idc, 1);
+        assert_eq!(pps.pic_init_qp_minus26, -3);
+        assert_eq!(pps.chroma_qp_index_offset, -2);
+        assert_eq!(pps.second_chroma_qp_index_offset, 3);
+        assert!(pps.transform_8x8_mode_flag);
+    }
+
+    #[test]
+    fn sei_messages() {
+        let stream = parse_stream(HIGH_1080P);
+        assert_eq!(stream.sei.len(), 5);
+
+        match stream.sei[0] {
+            SeiMessage::BufferingPeriod(ref bp) => {
+                assert_eq!(bp.seq_parameter_set_id, 0);
+                assert_eq!(bp.nal_initial_cpb_removal, vec![(90000, 0)]);
+                assert!(bp.vcl_initial_cpb_removal.is_empty());
+            }
+            ref message => panic!("unexpected {:?}", message),
+        }
+        match stream.sei[1] {
+            SeiMessage::PicTiming(ref pt) => {
+                assert_eq!(pt.cpb_removal_delay, Some(0));
+                assert_eq!(pt.dpb_output_delay, Some(4));
+                assert_eq!(pt.pic_struct, Some(0));
+                let ts = pt.clock_timestamps[0].unwrap();
+                assert_eq!((ts.hours, ts.minutes, ts.seconds, ts.n_frames), (1, 15, 30, 12));
+                assert_eq!(ts.time_offset, -5);
+            }
+            ref message => panic!("unexpected {:?}", message),
+        }
+        match stream.sei[2] {
+            SeiMessage::RecoveryPoint(rp) => {
+                assert_eq!(rp.recovery_frame_cnt, 0);
+                assert!(rp.exact_match_flag);
+                assert!(!rp.broken_link_flag);
+            }
+            ref message => panic!("unexpected {:?}", message),
+        }
+        match stream.sei[3] {
+            SeiMessage::UserDataUnregistered(uuid, ref data) => {
+                assert_eq!(uuid[0], 0xd0);
+                assert_eq!(uuid[15], 0xdf);
+                assert_eq!(&data[..], b"libva");
+            }
+            ref message => panic!("unexpected {:?}", message),
+        }
+        match stream.sei[4] {
+            SeiMessage::FramePacking(fp) => {
+                assert!(!fp.cancel_flag);
+                /* Side by side */
+                assert_eq!(fp.arrangement_type, 3);
+                assert_eq!(fp.content_interpretation_type, 1);
+                assert!(fp.current_frame_is_frame0_flag);
+                assert_eq!(fp.repetition_period, 1);
+            }
+            ref message => panic!("unexpected {:?}", message),
+        }
+
+        /* Timing messages need the SPS to be parsed */
+        let sei = [SEI_PIC_TIMING as u8, 1, 0x55, 0x80];
+        match parse_sei(&sei, None).unwrap()[0] {
+            SeiMessage::Other(SEI_PIC_TIMING, ref data) => assert_eq!(&data[..], &[0x55]),
+            ref message => panic!("unexpected {:?}", message),
+        }
+        assert!(parse_sei(&[SEI_RECOVERY_POINT as u8, 4, 0x80], None).is_err());
+    }
+
+    #[test]
+    fn high_slices() {
+        let stream = parse_stream(HIGH_1080P);
+        assert_eq!(stream.slices.len(), 3);
+
+        let (ref nal, ref i) = stream.slices[0];
+        assert!(nal.is_idr());
+        assert_eq!(i.slice_type, SLICE_TYPE_I);
+        assert_eq!(i.pic_parameter_set_id, 1);
+        assert_eq!(i.idr_pic_id, 1);
+        assert_eq!(i.slice_qp_delta, 4);
+        assert_eq!(i.header_size, 40);
+
+        let (_, ref p) = stream.slices[1];
+        assert_eq!(p.slice_type, SLICE_TYPE_P);
+        assert_eq!(p.frame_num, 1);
+        assert_eq!(p.pic_order_cnt_lsb, 8);
+        assert_eq!(p.num_ref_idx_l0_active_minus1, 0);
+        assert_eq!(p.ref_pic_list_modification[0].len(), 1);
+        assert_eq!(p.ref_pic_list_modification[0][0].modification_of_pic_nums_idc, 0);
+        assert_eq!(p.ref_pic_list_modification[0][0].value, 0);
+        let ops = p.dec_ref_pic_marking.operations.as_ref().unwrap();
+        assert_eq!(ops.len(), 1);
+        assert_eq!(ops[0].memory_management_control_operation, 1);
+        assert_eq!(ops[0].difference_of_pic_nums_minus1, 0);
+        assert!(p.dec_ref_pic_marking.has_operation(1));
+        assert!(!p.dec_ref_pic_marking.has_operation(5));
+        assert_eq!(p.cabac_init_idc, 1);
+        assert_eq!(p.slice_qp_delta, -1);
+        assert_eq!(p.header_size, 48);
+
+        let (ref nal, ref b) = stream.slices[2];
+        assert_eq!(nal.nal_ref_idc, 0);
+        assert_eq!(b.slice_type, SLICE_TYPE_B);
+        assert_eq!(b.pic_order_cnt_lsb, 4);
+        assert!(b.direct_spatial_mv_pred_flag);
+        assert_eq!(b.num_ref_idx_l0_active_minus1, 2);
+        assert_eq!(b.num_ref_idx_l1_active_minus1, 0);
+        assert_eq!(b.cabac_init_idc, 2);
+        assert_eq!(b.disable_deblocking_filter_idc, 2);
+        assert_eq!(b.slice_alpha_c0_offset_div2, -1);
+        assert_eq!(b.slice_beta_offset_div2, 2);
+        assert_eq!(b.header_size, 110);
+
+        let table = b.pred_weight_table.as_ref().unwrap();
+        assert_eq!(table.luma_log2_weight_denom, 6);
+        assert_eq!(table.chroma_log2_weight_denom, 5);
+        assert_eq!(table.luma_weight_flag, [true, false]);
+        assert_eq!(table.chroma_weight_flag, [true, false]);
+        assert_eq!((table.luma_weight[0][0], table.luma_offset[0][0]), (70, -3));
+        assert_eq!(table.chroma_weight[0][0], [32, 32]);
+        assert_eq!(table.luma_weight[0][1], 64);
+        assert_eq!(table.chroma_weight[0][1], [30, 34]);
+        assert_eq!(table.chroma_offset[0][1], [1, -1]);
+        assert_eq!(table.luma_weight[1][0], 64);
+    }
+}
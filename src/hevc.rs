@@ -0,0 +1,2644 @@
+// Copyright (c) 2017 zzoon <zzoon@igalia.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/* Parser for the HEVC syntax needed to drive VA decoding: parameter sets, slice segment
+ * headers and SEI messages of Annex B byte streams, and their conversion into the VA
+ * buffers. Scaling lists are kept in up-right diagonal order. */
+
+use std::cmp;
+
+use bitfields::*;
+use bitreader::BitReader;
+use ffi;
+
+pub const NAL_TRAIL_N: u8 = 0;
+pub const NAL_TRAIL_R: u8 = 1;
+pub const NAL_RASL_R: u8 = 9;
+pub const NAL_BLA_W_LP: u8 = 16;
+pub const NAL_IDR_W_RADL: u8 = 19;
+pub const NAL_IDR_N_LP: u8 = 20;
+pub const NAL_CRA: u8 = 21;
+pub const NAL_VPS: u8 = 32;
+pub const NAL_SPS: u8 = 33;
+pub const NAL_PPS: u8 = 34;
+pub const NAL_AU_DELIMITER: u8 = 35;
+pub const NAL_END_OF_SEQUENCE: u8 = 36;
+pub const NAL_END_OF_BITSTREAM: u8 = 37;
+pub const NAL_PREFIX_SEI: u8 = 39;
+pub const NAL_SUFFIX_SEI: u8 = 40;
+
+pub const NAL_HEADER_BYTES: usize = 2;
+
+pub const SLICE_TYPE_B: u8 = 0;
+pub const SLICE_TYPE_P: u8 = 1;
+pub const SLICE_TYPE_I: u8 = 2;
+
+pub const MAX_VPS_COUNT: usize = 16;
+pub const MAX_SPS_COUNT: usize = 16;
+pub const MAX_PPS_COUNT: usize = 64;
+pub const MAX_SUB_LAYERS: usize = 7;
+
+/* Default 8x8 scaling lists of Table 7-6, in up-right diagonal order */
+pub const DEFAULT_8X8_INTRA: [u8; 64] = [16, 16, 16, 16, 16, 16, 16, 16, 16, 16, 17, 16, 17, 16,
+                                         17, 18, 17, 18, 18, 17, 18, 21, 19, 20, 21, 20, 19, 21,
+                                         24, 22, 22, 24, 24, 22, 22, 24, 25, 25, 27, 30, 27, 25,
+                                         25, 29, 31, 35, 35, 31, 29, 36, 41, 44, 41, 36, 47, 54,
+                                         54, 47, 65, 70, 65, 88, 88, 115];
+pub const DEFAULT_8X8_INTER: [u8; 64] = [16, 16, 16, 16, 16, 16, 16, 16, 16, 16, 17, 17, 17, 17,
+                                         17, 18, 18, 18, 18, 18, 18, 20, 20, 20, 20, 20, 20, 20,
+                                         24, 24, 24, 24, 24, 24, 24, 24, 25, 25, 25, 25, 25, 25,
+                                         25, 28, 28, 28, 28, 28, 28, 33, 33, 33, 33, 33, 41, 41,
+                                         41, 41, 54, 54, 54, 71, 71, 91];
+
+/* Raster positions of the up-right diagonal scans */
+pub const DIAGONAL_4X4: [usize; 16] = [0, 4, 1, 8, 5, 2, 12, 9, 6, 3, 13, 10, 7, 14, 11, 15];
+pub const DIAGONAL_8X8: [usize; 64] = [0, 8, 1, 16, 9, 2, 24, 17, 10, 3, 32, 25, 18, 11, 4, 40,
+                                       33, 26, 19, 12, 5, 48, 41, 34, 27, 20, 13, 6, 56, 49, 42,
+                                       35, 28, 21, 14, 7, 57, 50, 43, 36, 29, 22, 15, 58, 51, 44,
+                                       37, 30, 23, 59, 52, 45, 38, 31, 60, 53, 46, 39, 61, 54,
+                                       47, 62, 55, 63];
+
+/* Ceil(Log2(value)) */
+fn ceil_log2(value: u32) -> u32 {
+    if value <= 1 {
+        0
+    } else {
+        32 - (value - 1).leading_zeros()
+    }
+}
+
+/* Offset in `data` of the RBSP byte at `offset`, emulation prevention bytes included */
+fn get_escaped_offset(data: &[u8], offset: usize) -> usize {
+    let mut zeros = 0;
+    let mut count = 0;
+    let mut pos = 0;
+    while pos < data.len() {
+        if zeros >= 2 && data[pos] == 3 {
+            zeros = 0;
+            pos += 1;
+            continue;
+        }
+        if count == offset {
+            break;
+        }
+        zeros = if data[pos] == 0 { zeros + 1 } else { 0 };
+        count += 1;
+        pos += 1;
+    }
+    pos
+}
+
+/* Lists of sizes 16x16 and 32x32 are coded as 8x8 ones plus a DC value. Only matrices 0
+ * and 3 of the 32x32 size are sent, the chroma ones being copied from the 16x16 size. */
+#[derive(Clone, Copy)]
+pub struct ScalingLists {
+    pub list_4x4: [[u8; 16]; 6],
+    pub list_8x8: [[u8; 64]; 6],
+    pub list_16x16: [[u8; 64]; 6],
+    pub list_32x32: [[u8; 64]; 6],
+    pub dc_16x16: [u8; 6],
+    pub dc_32x32: [u8; 6],
+}
+
+impl ScalingLists {
+    pub fn new_flat() -> ScalingLists {
+        ScalingLists {
+            list_4x4: [[16; 16]; 6],
+            list_8x8: [[16; 64]; 6],
+            list_16x16: [[16; 64]; 6],
+            list_32x32: [[16; 64]; 6],
+            dc_16x16: [16; 6],
+            dc_32x32: [16; 6],
+        }
+    }
+
+    pub fn new_default() -> ScalingLists {
+        let mut lists = ScalingLists::new_flat();
+        for matrix_id in 0..6 {
+            for size_id in 1..4 {
+                lists.set_default(size_id, matrix_id);
+            }
+        }
+        lists
+    }
+
+    fn set_default(&mut self, size_id: usize, matrix_id: usize) {
+        let list = if matrix_id < 3 { DEFAULT_8X8_INTRA } else { DEFAULT_8X8_INTER };
+        match size_id {
+            0 => self.list_4x4[matrix_id] = [16; 16],
+            1 => self.list_8x8[matrix_id] = list,
+            2 => {
+                self.list_16x16[matrix_id] = list;
+                self.dc_16x16[matrix_id] = 16;
+            }
+            _ => {
+                self.list_32x32[matrix_id] = list;
+                self.dc_32x32[matrix_id] = 16;
+            }
+        }
+    }
+
+    fn get_list_mut(&mut self, size_id: usize, matrix_id: usize) -> &mut [u8] {
+        match size_id {
+            0 => &mut self.list_4x4[matrix_id],
+            1 => &mut self.list_8x8[matrix_id],
+            2 => &mut self.list_16x16[matrix_id],
+            _ => &mut self.list_32x32[matrix_id],
+        }
+    }
+
+    fn get_dc_mut(&mut self, size_id: usize, matrix_id: usize) -> Option<&mut u8> {
+        match size_id {
+            2 => Some(&mut self.dc_16x16[matrix_id]),
+            3 => Some(&mut self.dc_32x32[matrix_id]),
+            _ => None,
+        }
+    }
+
+    /* scaling_list_data() */
+    fn parse(br: &mut BitReader) -> Result<ScalingLists, ()> {
+        let mut lists = ScalingLists::new_default();
+
+        for size_id in 0..4 {
+            let step = if size_id == 3 { 3 } else { 1 };
+            let coef_num = if size_id == 0 { 16 } else { 64 };
+            for matrix_id in (0..6).step_by(step) {
+                if !br.read_flag()? {
+                    /* scaling_list_pred_matrix_id_delta */
+                    let delta = br.read_ue()? as usize * step;
+                    if delta > matrix_id {
+                        return Err(());
+                    }
+                    if delta == 0 {
+                        lists.set_default(size_id, matrix_id);
+                    } else {
+                        let ref_id = matrix_id - delta;
+                        let mut list = [0; 64];
+                        list[..coef_num].copy_from_slice(lists.get_list_mut(size_id, ref_id));
+                        lists.get_list_mut(size_id, matrix_id)
+                            .copy_from_slice(&list[..coef_num]);
+                        let dc = lists.get_dc_mut(size_id, ref_id).map(|dc| *dc);
+                        if let (Some(dc), Some(value)) = (lists.get_dc_mut(size_id, matrix_id),
+                                                          dc) {
+                            *dc = value;
+                        }
+                    }
+                    continue;
+                }
+
+                let mut next_coef = 8;
+                if size_id > 1 {
+                    let dc_coef_minus8 = br.read_se()?;
+                    if dc_coef_minus8 < -7 || dc_coef_minus8 > 247 {
+                        return Err(());
+                    }
+                    next_coef = dc_coef_minus8 + 8;
+                    if let Some(dc) = lists.get_dc_mut(size_id, matrix_id) {
+                        *dc = next_coef as u8;
+                    }
+                }
+                let list = lists.get_list_mut(size_id, matrix_id);
+                for coef in list.iter_mut() {
+                    let delta_coef = br.read_se()?;
+                    if delta_coef < -128 || delta_coef > 127 {
+                        return Err(());
+                    }
+                    next_coef = (next_coef + delta_coef + 256) % 256;
+                    *coef = next_coef as u8;
+                }
+            }
+        }
+
+        for &matrix_id in &[1, 2, 4, 5] {
+            lists.list_32x32[matrix_id] = lists.list_16x16[matrix_id];
+            lists.dc_32x32[matrix_id] = lists.dc_16x16[matrix_id];
+        }
+
+        Ok(lists)
+    }
+
+    /* List `i` in raster scan order */
+    pub fn get_raster_4x4(&self, i: usize) -> [u8; 16] {
+        let mut list = [0; 16];
+        for (j, &pos) in DIAGONAL_4X4.iter().enumerate() {
+            list[pos] = self.list_4x4[i][j];
+        }
+        list
+    }
+
+    /* Coded 8x8 list `i` of the size `size_id` (1 to 3) in raster scan order */
+    pub fn get_raster_8x8(&self, size_id: usize, i: usize) -> [u8; 64] {
+        let coded = match size_id {
+            1 => &self.list_8x8[i],
+            2 => &self.list_16x16[i],
+            _ => &self.list_32x32[i],
+        };
+        let mut list = [0; 64];
+        for (j, &pos) in DIAGONAL_8X8.iter().enumerate() {
+            list[pos] = coded[j];
+        }
+        list
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ProfileTierLevel {
+    pub general_profile_space: u8,
+    pub general_tier_flag: bool,
+    pub general_profile_idc: u8,
+    pub general_profile_compatibility_flags: u32,
+    pub general_progressive_source_flag: bool,
+    pub general_interlaced_source_flag: bool,
+    pub general_non_packed_constraint_flag: bool,
+    pub general_frame_only_constraint_flag: bool,
+    pub general_level_idc: u8,
+    /* Level of each sub-layer below the highest one, when present */
+    pub sub_layer_level_idc: Vec<Option<u8>>,
+}
+
+impl ProfileTierLevel {
+    /* profile_tier_level(1, max_sub_layers_minus1) */
+    fn parse(br: &mut BitReader, max_sub_layers_minus1: u8) -> Result<ProfileTierLevel, ()> {
+        let mut ptl = ProfileTierLevel::default();
+        ptl.general_profile_space = br.read_bits(2)? as u8;
+        ptl.general_tier_flag = br.read_flag()?;
+        ptl.general_profile_idc = br.read_bits(5)? as u8;
+        ptl.general_profile_compatibility_flags = br.read_bits(32)?;
+        ptl.general_progressive_source_flag = br.read_flag()?;
+        ptl.general_interlaced_source_flag = br.read_flag()?;
+        ptl.general_non_packed_constraint_flag = br.read_flag()?;
+        ptl.general_frame_only_constraint_flag = br.read_flag()?;
+        /* Constraint flags of the range extension profiles and general_inbld_flag */
+        br.skip_bits(44)?;
+        ptl.general_level_idc = br.read_bits(8)? as u8;
+
+        let mut present = Vec::with_capacity(max_sub_layers_minus1 as usize);
+        for _ in 0..max_sub_layers_minus1 {
+            /* sub_layer_profile_present_flag, sub_layer_level_present_flag */
+            present.push((br.read_flag()?, br.read_flag()?));
+        }
+        if max_sub_layers_minus1 > 0 {
+            /* reserved_zero_2bits */
+            br.skip_bits(2 * (8 - max_sub_layers_minus1 as usize))?;
+        }
+        for &(profile_present, level_present) in &present {
+            if profile_present {
+                br.skip_bits(88)?;
+            }
+            ptl.sub_layer_level_idc.push(if level_present {
+                Some(br.read_bits(8)? as u8)
+            } else {
+                None
+            });
+        }
+
+        Ok(ptl)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SubLayerOrdering {
+    pub max_dec_pic_buffering_minus1: u32,
+    pub max_num_reorder_pics: u32,
+    pub max_latency_increase_plus1: u32,
+}
+
+/* One entry per sub-layer, the lower ones copying the highest when not sent */
+fn parse_sub_layer_ordering(br: &mut BitReader,
+                            max_sub_layers_minus1: u8)
+                            -> Result<Vec<SubLayerOrdering>, ()> {
+    let count = max_sub_layers_minus1 as usize + 1;
+    let info_present = br.read_flag()?;
+    let first = if info_present { 0 } else { count - 1 };
+    let mut ordering = vec![SubLayerOrdering::default(); count];
+    for entry in ordering.iter_mut().skip(first) {
+        entry.max_dec_pic_buffering_minus1 = br.read_ue()?;
+        entry.max_num_reorder_pics = br.read_ue()?;
+        entry.max_latency_increase_plus1 = br.read_ue()?;
+        if entry.max_dec_pic_buffering_minus1 > 15 ||
+           entry.max_num_reorder_pics > entry.max_dec_pic_buffering_minus1 {
+            return Err(());
+        }
+    }
+    for i in 0..first {
+        ordering[i] = ordering[first];
+    }
+    Ok(ordering)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Hrd {
+    pub nal_hrd_parameters_present_flag: bool,
+    pub vcl_hrd_parameters_present_flag: bool,
+    pub sub_pic_hrd_params_present_flag: bool,
+    pub du_cpb_removal_delay_increment_length_minus1: u8,
+    pub sub_pic_cpb_params_in_pic_timing_sei_flag: bool,
+    pub dpb_output_delay_du_length_minus1: u8,
+    pub initial_cpb_removal_delay_length_minus1: u8,
+    pub au_cpb_removal_delay_length_minus1: u8,
+    pub dpb_output_delay_length_minus1: u8,
+    /* Per sub-layer */
+    pub cpb_cnt_minus1: Vec<u32>,
+}
+
+impl Hrd {
+    /* hrd_parameters(common_inf_present_flag, max_sub_layers_minus1) */
+    fn parse(br: &mut BitReader,
+             common_inf_present_flag: bool,
+             max_sub_layers_minus1: u8)
+             -> Result<Hrd, ()> {
+        let mut hrd = Hrd::default();
+        hrd.initial_cpb_removal_delay_length_minus1 = 23;
+        hrd.au_cpb_removal_delay_length_minus1 = 23;
+        hrd.dpb_output_delay_length_minus1 = 23;
+
+        if common_inf_present_flag {
+            hrd.nal_hrd_parameters_present_flag = br.read_flag()?;
+            hrd.vcl_hrd_parameters_present_flag = br.read_flag()?;
+            if hrd.nal_hrd_parameters_present_flag || hrd.vcl_hrd_parameters_present_flag {
+                hrd.sub_pic_hrd_params_present_flag = br.read_flag()?;
+                if hrd.sub_pic_hrd_params_present_flag {
+                    /* tick_divisor_minus2 */
+                    br.skip_bits(8)?;
+                    hrd.du_cpb_removal_delay_increment_length_minus1 = br.read_bits(5)? as u8;
+                    hrd.sub_pic_cpb_params_in_pic_timing_sei_flag = br.read_flag()?;
+                    hrd.dpb_output_delay_du_length_minus1 = br.read_bits(5)? as u8;
+                }
+                /* bit_rate_scale, cpb_size_scale and cpb_size_du_scale */
+                br.skip_bits(if hrd.sub_pic_hrd_params_present_flag { 12 } else { 8 })?;
+                hrd.initial_cpb_removal_delay_length_minus1 = br.read_bits(5)? as u8;
+                hrd.au_cpb_removal_delay_length_minus1 = br.read_bits(5)? as u8;
+                hrd.dpb_output_delay_length_minus1 = br.read_bits(5)? as u8;
+            }
+        }
+
+        for _ in 0..max_sub_layers_minus1 as usize + 1 {
+            let fixed_pic_rate_general_flag = br.read_flag()?;
+            let fixed_pic_rate_within_cvs_flag = fixed_pic_rate_general_flag || br.read_flag()?;
+            let mut low_delay_hrd_flag = false;
+            if fixed_pic_rate_within_cvs_flag {
+                /* elemental_duration_in_tc_minus1 */
+                br.read_ue()?;
+            } else {
+                low_delay_hrd_flag = br.read_flag()?;
+            }
+            let mut cpb_cnt_minus1 = 0;
+            if !low_delay_hrd_flag {
+                cpb_cnt_minus1 = br.read_ue()?;
+                if cpb_cnt_minus1 > 31 {
+                    return Err(());
+                }
+            }
+            let count = hrd.nal_hrd_parameters_present_flag as usize +
+                        hrd.vcl_hrd_parameters_present_flag as usize;
+            for _ in 0..count * (cpb_cnt_minus1 as usize + 1) {
+                /* bit_rate_value_minus1, cpb_size_value_minus1, cpb_size_du_value_minus1,
+                 * bit_rate_du_value_minus1 and cbr_flag */
+                let values = if hrd.sub_pic_hrd_params_present_flag { 4 } else { 2 };
+                for _ in 0..values {
+                    br.read_ue()?;
+                }
+                br.skip_bits(1)?;
+            }
+            hrd.cpb_cnt_minus1.push(cpb_cnt_minus1);
+        }
+
+        Ok(hrd)
+    }
+
+    /* CpbDpbDelaysPresentFlag */
+    pub fn has_cpb_dpb_delays(&self) -> bool {
+        self.nal_hrd_parameters_present_flag || self.vcl_hrd_parameters_present_flag
+    }
+}
+
+pub const ASPECT_RATIO_EXTENDED_SAR: u32 = 255;
+
+#[derive(Debug, Clone, Default)]
+pub struct Vui {
+    pub aspect_ratio_idc: u8,
+    pub sar_width: u16,
+    pub sar_height: u16,
+    pub video_format: u8,
+    pub video_full_range_flag: bool,
+    pub colour_primaries: u8,
+    pub transfer_characteristics: u8,
+    pub matrix_coefficients: u8,
+    pub field_seq_flag: bool,
+    pub frame_field_info_present_flag: bool,
+    /* Left, right, top and bottom offsets */
+    pub default_display_window: Option<[u32; 4]>,
+    pub num_units_in_tick: u32,
+    pub time_scale: u32,
+    pub hrd: Option<Hrd>,
+    pub restricted_ref_pic_lists_flag: bool,
+}
+
+impl Vui {
+    fn parse(br: &mut BitReader, max_sub_layers_minus1: u8) -> Result<Vui, ()> {
+        let mut vui = Vui::default();
+        vui.video_format = 5;
+        vui.colour_primaries = 2;
+        vui.transfer_characteristics = 2;
+        vui.matrix_coefficients = 2;
+
+        if br.read_flag()? {
+            let aspect_ratio_idc = br.read_bits(8)?;
+            if aspect_ratio_idc == ASPECT_RATIO_EXTENDED_SAR {
+                vui.sar_width = br.read_bits(16)? as u16;
+                vui.sar_height = br.read_bits(16)? as u16;
+            }
+            vui.aspect_ratio_idc = aspect_ratio_idc as u8;
+        }
+        /* overscan_info_present_flag, overscan_appropriate_flag */
+        if br.read_flag()? {
+            br.skip_bits(1)?;
+        }
+        if br.read_flag()? {
+            vui.video_format = br.read_bits(3)? as u8;
+            vui.video_full_range_flag = br.read_flag()?;
+            if br.read_flag()? {
+                vui.colour_primaries = br.read_bits(8)? as u8;
+                vui.transfer_characteristics = br.read_bits(8)? as u8;
+                vui.matrix_coefficients = br.read_bits(8)? as u8;
+            }
+        }
+        /* chroma_sample_loc_type_top_field, chroma_sample_loc_type_bottom_field */
+        if br.read_flag()? {
+            br.read_ue()?;
+            br.read_ue()?;
+        }
+        /* neutral_chroma_indication_flag */
+        br.skip_bits(1)?;
+        vui.field_seq_flag = br.read_flag()?;
+        vui.frame_field_info_present_flag = br.read_flag()?;
+        if br.read_flag()? {
+            vui.default_display_window = Some([br.read_ue()?,
+                                               br.read_ue()?,
+                                               br.read_ue()?,
+                                               br.read_ue()?]);
+        }
+        if br.read_flag()? {
+            vui.num_units_in_tick = br.read_bits(32)?;
+            vui.time_scale = br.read_bits(32)?;
+            /* vui_poc_proportional_to_timing_flag, vui_num_ticks_poc_diff_one_minus1 */
+            if br.read_flag()? {
+                br.read_ue()?;
+            }
+            if br.read_flag()? {
+                vui.hrd = Some(Hrd::parse(br, true, max_sub_layers_minus1)?);
+            }
+        }
+        if br.read_flag()? {
+            /* tiles_fixed_structure_flag, motion_vectors_over_pic_boundaries_flag */
+            br.skip_bits(2)?;
+            vui.restricted_ref_pic_lists_flag = br.read_flag()?;
+            /* min_spatial_segmentation_idc, max_bytes_per_pic_denom,
+             * max_bits_per_min_cu_denom, log2_max_mv_length_horizontal and vertical */
+            for _ in 0..5 {
+                br.read_ue()?;
+            }
+        }
+
+        Ok(vui)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Vps {
+    pub video_parameter_set_id: u8,
+    pub max_layers_minus1: u8,
+    pub max_sub_layers_minus1: u8,
+    pub temporal_id_nesting_flag: bool,
+    pub profile_tier_level: ProfileTierLevel,
+    pub sub_layer_ordering: Vec<SubLayerOrdering>,
+    pub max_layer_id: u8,
+    pub num_layer_sets_minus1: u32,
+    pub num_units_in_tick: u32,
+    pub time_scale: u32,
+    pub hrd: Vec<Hrd>,
+}
+
+impl Vps {
+    /* `data` is the RBSP following the NAL unit header */
+    pub fn parse(data: &[u8]) -> Result<Vps, ()> {
+        let mut br = BitReader::new(data);
+        let br = &mut br;
+        let video_parameter_set_id = br.read_bits(4)? as u8;
+        /* vps_base_layer_internal_flag, vps_base_layer_available_flag */
+        br.skip_bits(2)?;
+        let max_layers_minus1 = br.read_bits(6)? as u8;
+        let max_sub_layers_minus1 = br.read_bits(3)? as u8;
+        if max_sub_layers_minus1 as usize >= MAX_SUB_LAYERS {
+            return Err(());
+        }
+        let temporal_id_nesting_flag = br.read_flag()?;
+        /* vps_reserved_0xffff_16bits */
+        br.skip_bits(16)?;
+        let profile_tier_level = ProfileTierLevel::parse(br, max_sub_layers_minus1)?;
+        let sub_layer_ordering = parse_sub_layer_ordering(br, max_sub_layers_minus1)?;
+
+        let max_layer_id = br.read_bits(6)? as u8;
+        let num_layer_sets_minus1 = br.read_ue()?;
+        if num_layer_sets_minus1 > 1023 {
+            return Err(());
+        }
+        /* layer_id_included_flag */
+        br.skip_bits(num_layer_sets_minus1 as usize * (max_layer_id as usize + 1))?;
+
+        let mut num_units_in_tick = 0;
+        let mut time_scale = 0;
+        let mut hrd = Vec::new();
+        if br.read_flag()? {
+            num_units_in_tick = br.read_bits(32)?;
+            time_scale = br.read_bits(32)?;
+            /* vps_poc_proportional_to_timing_flag, vps_num_ticks_poc_diff_one_minus1 */
+            if br.read_flag()? {
+                br.read_ue()?;
+            }
+            let num_hrd_parameters = br.read_ue()?;
+            if num_hrd_parameters > num_layer_sets_minus1 + 1 {
+                return Err(());
+            }
+            for i in 0..num_hrd_parameters {
+                /* hrd_layer_set_idx, cprms_present_flag */
+                br.read_ue()?;
+                let common_inf_present_flag = i == 0 || br.read_flag()?;
+                hrd.push(Hrd::parse(br, common_inf_present_flag, max_sub_layers_minus1)?);
+            }
+        }
+
+        Ok(Vps {
+            video_parameter_set_id: video_parameter_set_id,
+            max_layers_minus1: max_layers_minus1,
+            max_sub_layers_minus1: max_sub_layers_minus1,
+            temporal_id_nesting_flag: temporal_id_nesting_flag,
+            profile_tier_level: profile_tier_level,
+            sub_layer_ordering: sub_layer_ordering,
+            max_layer_id: max_layer_id,
+            num_layer_sets_minus1: num_layer_sets_minus1,
+            num_units_in_tick: num_units_in_tick,
+            time_scale: time_scale,
+            hrd: hrd,
+        })
+    }
+}
+
+/* Derived DeltaPocS0/S1 and UsedByCurrPicS0/S1 of a short-term reference picture set */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ShortTermRefPicSet {
+    pub delta_poc_s0: Vec<i32>,
+    pub used_by_curr_pic_s0: Vec<bool>,
+    pub delta_poc_s1: Vec<i32>,
+    pub used_by_curr_pic_s1: Vec<bool>,
+}
+
+impl ShortTermRefPicSet {
+    /* st_ref_pic_set(stRpsIdx) with stRpsIdx the number of `sets` parsed before, the one
+     * of a slice header coming after all the sets of the SPS */
+    fn parse(br: &mut BitReader,
+             sets: &[ShortTermRefPicSet],
+             in_slice_header: bool)
+             -> Result<ShortTermRefPicSet, ()> {
+        let mut rps = ShortTermRefPicSet::default();
+
+        if !sets.is_empty() && br.read_flag()? {
+            let delta_idx = if in_slice_header { br.read_ue()? as usize + 1 } else { 1 };
+            if delta_idx > sets.len() {
+                return Err(());
+            }
+            let delta_rps_sign = br.read_flag()?;
+            let abs_delta_rps_minus1 = br.read_ue()?;
+            if abs_delta_rps_minus1 > 0x7fff {
+                return Err(());
+            }
+            let delta_rps = if delta_rps_sign { -1 } else { 1 } *
+                            (abs_delta_rps_minus1 as i32 + 1);
+            let ref_rps = &sets[sets.len() - delta_idx];
+            let num_negative = ref_rps.delta_poc_s0.len();
+            let num_delta_pocs = ref_rps.get_num_delta_pocs();
+
+            let mut used_by_curr_pic_flag = Vec::with_capacity(num_delta_pocs + 1);
+            let mut use_delta_flag = Vec::with_capacity(num_delta_pocs + 1);
+            for _ in 0..num_delta_pocs + 1 {
+                let used = br.read_flag()?;
+                used_by_curr_pic_flag.push(used);
+                use_delta_flag.push(used || br.read_flag()?);
+            }
+
+            /* Equations 7-61 and 7-62 */
+            let mut push = |delta_poc: i32, j: usize| {
+                if !use_delta_flag[j] {
+                    return;
+                }
+                if delta_poc < 0 {
+                    rps.delta_poc_s0.push(delta_poc);
+                    rps.used_by_curr_pic_s0.push(used_by_curr_pic_flag[j]);
+                } else if delta_poc > 0 {
+                    rps.delta_poc_s1.push(delta_poc);
+                    rps.used_by_curr_pic_s1.push(used_by_curr_pic_flag[j]);
+                }
+            };
+            for (j, &delta_poc) in ref_rps.delta_poc_s1.iter().enumerate().rev() {
+                if delta_poc + delta_rps < 0 {
+                    push(delta_poc + delta_rps, num_negative + j);
+                }
+            }
+            if delta_rps < 0 {
+                push(delta_rps, num_delta_pocs);
+            }
+            for (j, &delta_poc) in ref_rps.delta_poc_s0.iter().enumerate() {
+                if delta_poc + delta_rps < 0 {
+                    push(delta_poc + delta_rps, j);
+                }
+            }
+            for (j, &delta_poc) in ref_rps.delta_poc_s0.iter().enumerate().rev() {
+                if delta_poc + delta_rps > 0 {
+                    push(delta_poc + delta_rps, j);
+                }
+            }
+            if delta_rps > 0 {
+                push(delta_rps, num_delta_pocs);
+            }
+            for (j, &delta_poc) in ref_rps.delta_poc_s1.iter().enumerate() {
+                if delta_poc + delta_rps > 0 {
+                    push(delta_poc + delta_rps, num_negative + j);
+                }
+            }
+        } else {
+            let num_negative_pics = br.read_ue()?;
+            let num_positive_pics = br.read_ue()?;
+            if num_negative_pics > 16 || num_positive_pics > 16 - num_negative_pics {
+                return Err(());
+            }
+            let mut poc = 0;
+            for _ in 0..num_negative_pics {
+                poc -= br.read_ue()? as i32 + 1;
+                rps.delta_poc_s0.push(poc);
+                rps.used_by_curr_pic_s0.push(br.read_flag()?);
+            }
+            poc = 0;
+            for _ in 0..num_positive_pics {
+                poc += br.read_ue()? as i32 + 1;
+                rps.delta_poc_s1.push(poc);
+                rps.used_by_curr_pic_s1.push(br.read_flag()?);
+            }
+        }
+
+        if rps.get_num_delta_pocs() > 16 {
+            return Err(());
+        }
+        Ok(rps)
+    }
+
+    pub fn get_num_delta_pocs(&self) -> usize {
+        self.delta_poc_s0.len() + self.delta_poc_s1.len()
+    }
+
+    /* Pictures of the set that the current one may reference */
+    pub fn get_num_used_by_curr_pic(&self) -> usize {
+        self.used_by_curr_pic_s0.iter().chain(self.used_by_curr_pic_s1.iter()).filter(|&&used| used).count()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpsRangeExtension {
+    pub transform_skip_rotation_enabled_flag: bool,
+    pub transform_skip_context_enabled_flag: bool,
+    pub implicit_rdpcm_enabled_flag: bool,
+    pub explicit_rdpcm_enabled_flag: bool,
+    pub extended_precision_processing_flag: bool,
+    pub intra_smoothing_disabled_flag: bool,
+    pub high_precision_offsets_enabled_flag: bool,
+    pub persistent_rice_adaptation_enabled_flag: bool,
+    pub cabac_bypass_alignment_enabled_flag: bool,
+}
+
+impl SpsRangeExtension {
+    fn parse(br: &mut BitReader) -> Result<SpsRangeExtension, ()> {
+        Ok(SpsRangeExtension {
+            transform_skip_rotation_enabled_flag: br.read_flag()?,
+            transform_skip_context_enabled_flag: br.read_flag()?,
+            implicit_rdpcm_enabled_flag: br.read_flag()?,
+            explicit_rdpcm_enabled_flag: br.read_flag()?,
+            extended_precision_processing_flag: br.read_flag()?,
+            intra_smoothing_disabled_flag: br.read_flag()?,
+            high_precision_offsets_enabled_flag: br.read_flag()?,
+            persistent_rice_adaptation_enabled_flag: br.read_flag()?,
+            cabac_bypass_alignment_enabled_flag: br.read_flag()?,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct Sps {
+    pub video_parameter_set_id: u8,
+    pub max_sub_layers_minus1: u8,
+    pub temporal_id_nesting_flag: bool,
+    pub profile_tier_level: ProfileTierLevel,
+    pub seq_parameter_set_id: u32,
+    pub chroma_format_idc: u8,
+    pub separate_colour_plane_flag: bool,
+    pub pic_width_in_luma_samples: u32,
+    pub pic_height_in_luma_samples: u32,
+    /* Left, right, top and bottom offsets, in chroma samples */
+    pub conformance_window: Option<[u32; 4]>,
+    pub bit_depth_luma_minus8: u8,
+    pub bit_depth_chroma_minus8: u8,
+    pub log2_max_pic_order_cnt_lsb_minus4: u32,
+    pub sub_layer_ordering: Vec<SubLayerOrdering>,
+    pub log2_min_luma_coding_block_size_minus3: u32,
+    pub log2_diff_max_min_luma_coding_block_size: u32,
+    pub log2_min_luma_transform_block_size_minus2: u32,
+    pub log2_diff_max_min_luma_transform_block_size: u32,
+    pub max_transform_hierarchy_depth_inter: u32,
+    pub max_transform_hierarchy_depth_intra: u32,
+    pub scaling_list_enabled_flag: bool,
+    /* Flat unless scaling_list_enabled_flag is set */
+    pub scaling_lists: ScalingLists,
+    pub amp_enabled_flag: bool,
+    pub sample_adaptive_offset_enabled_flag: bool,
+    pub pcm_enabled_flag: bool,
+    pub pcm_sample_bit_depth_luma_minus1: u8,
+    pub pcm_sample_bit_depth_chroma_minus1: u8,
+    pub log2_min_pcm_luma_coding_block_size_minus3: u32,
+    pub log2_diff_max_min_pcm_luma_coding_block_size: u32,
+    pub pcm_loop_filter_disabled_flag: bool,
+    pub short_term_ref_pic_sets: Vec<ShortTermRefPicSet>,
+    pub long_term_ref_pics_present_flag: bool,
+    pub lt_ref_pic_poc_lsb_sps: Vec<u32>,
+    pub used_by_curr_pic_lt_sps_flag: Vec<bool>,
+    pub sps_temporal_mvp_enabled_flag: bool,
+    pub strong_intra_smoothing_enabled_flag: bool,
+    pub vui: Option<Vui>,
+    pub range_extension: Option<SpsRangeExtension>,
+}
+
+impl Sps {
+    /* `data` is the RBSP following the NAL unit header */
+    pub fn parse(data: &[u8]) -> Result<Sps, ()> {
+        let mut br = BitReader::new(data);
+        let br = &mut br;
+        let video_parameter_set_id = br.read_bits(4)? as u8;
+        let max_sub_layers_minus1 = br.read_bits(3)? as u8;
+        if max_sub_layers_minus1 as usize >= MAX_SUB_LAYERS {
+            return Err(());
+        }
+        let temporal_id_nesting_flag = br.read_flag()?;
+        let profile_tier_level = ProfileTierLevel::parse(br, max_sub_layers_minus1)?;
+        let seq_parameter_set_id = br.read_ue()?;
+        if seq_parameter_set_id as usize >= MAX_SPS_COUNT {
+            return Err(());
+        }
+
+        let chroma_format_idc = br.read_ue()?;
+        if chroma_format_idc > 3 {
+            return Err(());
+        }
+        let separate_colour_plane_flag = chroma_format_idc == 3 && br.read_flag()?;
+        let pic_width_in_luma_samples = br.read_ue()?;
+        let pic_height_in_luma_samples = br.read_ue()?;
+        let conformance_window = if br.read_flag()? {
+            Some([br.read_ue()?, br.read_ue()?, br.read_ue()?, br.read_ue()?])
+        } else {
+            None
+        };
+        let bit_depth_luma_minus8 = br.read_ue()?;
+        let bit_depth_chroma_minus8 = br.read_ue()?;
+        if bit_depth_luma_minus8 > 8 || bit_depth_chroma_minus8 > 8 {
+            return Err(());
+        }
+        let log2_max_pic_order_cnt_lsb_minus4 = br.read_ue()?;
+        if log2_max_pic_order_cnt_lsb_minus4 > 12 {
+            return Err(());
+        }
+        let sub_layer_ordering = parse_sub_layer_ordering(br, max_sub_layers_minus1)?;
+
+        let log2_min_luma_coding_block_size_minus3 = br.read_ue()?;
+        let log2_diff_max_min_luma_coding_block_size = br.read_ue()?;
+        let log2_min_luma_transform_block_size_minus2 = br.read_ue()?;
+        let log2_diff_max_min_luma_transform_block_size = br.read_ue()?;
+        let max_transform_hierarchy_depth_inter = br.read_ue()?;
+        let max_transform_hierarchy_depth_intra = br.read_ue()?;
+        let min_cb_log2_size = log2_min_luma_coding_block_size_minus3 + 3;
+        let ctb_log2_size = min_cb_log2_size + log2_diff_max_min_luma_coding_block_size;
+        if ctb_log2_size > 6 || log2_min_luma_transform_block_size_minus2 > 3 ||
+           log2_diff_max_min_luma_transform_block_size > 3 ||
+           max_transform_hierarchy_depth_inter > 4 || max_transform_hierarchy_depth_intra > 4 {
+            return Err(());
+        }
+        let min_cb_size = 1 << min_cb_log2_size;
+        if pic_width_in_luma_samples == 0 || pic_height_in_luma_samples == 0 ||
+           pic_width_in_luma_samples % min_cb_size != 0 ||
+           pic_height_in_luma_samples % min_cb_size != 0 {
+            return Err(());
+        }
+
+        let scaling_list_enabled_flag = br.read_flag()?;
+        let scaling_lists = if !scaling_list_enabled_flag {
+            ScalingLists::new_flat()
+        } else if br.read_flag()? {
+            ScalingLists::parse(br)?
+        } else {
+            ScalingLists::new_default()
+        };
+        let amp_enabled_flag = br.read_flag()?;
+        let sample_adaptive_offset_enabled_flag = br.read_flag()?;
+
+        let pcm_enabled_flag = br.read_flag()?;
+        let mut pcm_sample_bit_depth_luma_minus1 = 0;
+        let mut pcm_sample_bit_depth_chroma_minus1 = 0;
+        let mut log2_min_pcm_luma_coding_block_size_minus3 = 0;
+        let mut log2_diff_max_min_pcm_luma_coding_block_size = 0;
+        let mut pcm_loop_filter_disabled_flag = false;
+        if pcm_enabled_flag {
+            pcm_sample_bit_depth_luma_minus1 = br.read_bits(4)? as u8;
+            pcm_sample_bit_depth_chroma_minus1 = br.read_bits(4)? as u8;
+            log2_min_pcm_luma_coding_block_size_minus3 = br.read_ue()?;
+            log2_diff_max_min_pcm_luma_coding_block_size = br.read_ue()?;
+            pcm_loop_filter_disabled_flag = br.read_flag()?;
+            if log2_min_pcm_luma_coding_block_size_minus3 +
+               log2_diff_max_min_pcm_luma_coding_block_size > 2 {
+                return Err(());
+            }
+        }
+
+        let num_short_term_ref_pic_sets = br.read_ue()?;
+        if num_short_term_ref_pic_sets > 64 {
+            return Err(());
+        }
+        let mut short_term_ref_pic_sets = Vec::with_capacity(num_short_term_ref_pic_sets as usize);
+        for _ in 0..num_short_term_ref_pic_sets {
+            let rps = ShortTermRefPicSet::parse(br, &short_term_ref_pic_sets, false)?;
+            short_term_ref_pic_sets.push(rps);
+        }
+
+        let long_term_ref_pics_present_flag = br.read_flag()?;
+        let mut lt_ref_pic_poc_lsb_sps = Vec::new();
+        let mut used_by_curr_pic_lt_sps_flag = Vec::new();
+        if long_term_ref_pics_present_flag {
+            let num_long_term_ref_pics_sps = br.read_ue()?;
+            if num_long_term_ref_pics_sps > 32 {
+                return Err(());
+            }
+            for _ in 0..num_long_term_ref_pics_sps {
+                lt_ref_pic_poc_lsb_sps.push(br.read_bits(log2_max_pic_order_cnt_lsb_minus4 + 4)?);
+                used_by_curr_pic_lt_sps_flag.push(br.read_flag()?);
+            }
+        }
+        let sps_temporal_mvp_enabled_flag = br.read_flag()?;
+        let strong_intra_smoothing_enabled_flag = br.read_flag()?;
+        let vui = if br.read_flag()? {
+            Some(Vui::parse(br, max_sub_layers_minus1)?)
+        } else {
+            None
+        };
+
+        let mut range_extension = None;
+        if br.read_flag()? {
+            let sps_range_extension_flag = br.read_flag()?;
+            /* sps_multilayer_extension_flag, sps_3d_extension_flag, sps_scc_extension_flag,
+             * sps_extension_4bits; these extensions are not parsed */
+            br.skip_bits(7)?;
+            if sps_range_extension_flag {
+                range_extension = Some(SpsRangeExtension::parse(br)?);
+            }
+        }
+
+        Ok(Sps {
+            video_parameter_set_id: video_parameter_set_id,
+            max_sub_layers_minus1: max_sub_layers_minus1,
+            temporal_id_nesting_flag: temporal_id_nesting_flag,
+            profile_tier_level: profile_tier_level,
+            seq_parameter_set_id: seq_parameter_set_id,
+            chroma_format_idc: chroma_format_idc as u8,
+            separate_colour_plane_flag: separate_colour_plane_flag,
+            pic_width_in_luma_samples: pic_width_in_luma_samples,
+            pic_height_in_luma_samples: pic_height_in_luma_samples,
+            conformance_window: conformance_window,
+            bit_depth_luma_minus8: bit_depth_luma_minus8 as u8,
+            bit_depth_chroma_minus8: bit_depth_chroma_minus8 as u8,
+            log2_max_pic_order_cnt_lsb_minus4: log2_max_pic_order_cnt_lsb_minus4,
+            sub_layer_ordering: sub_layer_ordering,
+            log2_min_luma_coding_block_size_minus3: log2_min_luma_coding_block_size_minus3,
+            log2_diff_max_min_luma_coding_block_size: log2_diff_max_min_luma_coding_block_size,
+            log2_min_luma_transform_block_size_minus2: log2_min_luma_transform_block_size_minus2,
+            log2_diff_max_min_luma_transform_block_size:
+                log2_diff_max_min_luma_transform_block_size,
+            max_transform_hierarchy_depth_inter: max_transform_hierarchy_depth_inter,
+            max_transform_hierarchy_depth_intra: max_transform_hierarchy_depth_intra,
+            scaling_list_enabled_flag: scaling_list_enabled_flag,
+            scaling_lists: scaling_lists,
+            amp_enabled_flag: amp_enabled_flag,
+            sample_adaptive_offset_enabled_flag: sample_adaptive_offset_enabled_flag,
+            pcm_enabled_flag: pcm_enabled_flag,
+            pcm_sample_bit_depth_luma_minus1: pcm_sample_bit_depth_luma_minus1,
+            pcm_sample_bit_depth_chroma_minus1: pcm_sample_bit_depth_chroma_minus1,
+            log2_min_pcm_luma_coding_block_size_minus3: log2_min_pcm_luma_coding_block_size_minus3,
+            log2_diff_max_min_pcm_luma_coding_block_size:
+                log2_diff_max_min_pcm_luma_coding_block_size,
+            pcm_loop_filter_disabled_flag: pcm_loop_filter_disabled_flag,
+            short_term_ref_pic_sets: short_term_ref_pic_sets,
+            long_term_ref_pics_present_flag: long_term_ref_pics_present_flag,
+            lt_ref_pic_poc_lsb_sps: lt_ref_pic_poc_lsb_sps,
+            used_by_curr_pic_lt_sps_flag: used_by_curr_pic_lt_sps_flag,
+            sps_temporal_mvp_enabled_flag: sps_temporal_mvp_enabled_flag,
+            strong_intra_smoothing_enabled_flag: strong_intra_smoothing_enabled_flag,
+            vui: vui,
+            range_extension: range_extension,
+        })
+    }
+
+    pub fn get_chroma_array_type(&self) -> u8 {
+        if self.separate_colour_plane_flag {
+            0
+        } else {
+            self.chroma_format_idc
+        }
+    }
+
+    pub fn get_ctb_log2_size(&self) -> u32 {
+        self.log2_min_luma_coding_block_size_minus3 + 3 +
+        self.log2_diff_max_min_luma_coding_block_size
+    }
+
+    pub fn get_pic_width_in_ctbs(&self) -> u32 {
+        let ctb_log2_size = self.get_ctb_log2_size();
+        (self.pic_width_in_luma_samples + (1 << ctb_log2_size) - 1) >> ctb_log2_size
+    }
+
+    pub fn get_pic_height_in_ctbs(&self) -> u32 {
+        let ctb_log2_size = self.get_ctb_log2_size();
+        (self.pic_height_in_luma_samples + (1 << ctb_log2_size) - 1) >> ctb_log2_size
+    }
+
+    pub fn get_max_pic_order_cnt_lsb(&self) -> u32 {
+        1 << (self.log2_max_pic_order_cnt_lsb_minus4 + 4)
+    }
+
+    /* Displayed size, after the conformance window cropping */
+    pub fn get_size(&self) -> (u32, u32) {
+        let (width, height) = (self.pic_width_in_luma_samples, self.pic_height_in_luma_samples);
+        match self.conformance_window {
+            Some(offsets) => {
+                let (sub_width, sub_height) = match self.get_chroma_array_type() {
+                    1 => (2, 2),
+                    2 => (2, 1),
+                    _ => (1, 1),
+                };
+                (width.saturating_sub(sub_width * (offsets[0] + offsets[1])),
+                 height.saturating_sub(sub_height * (offsets[2] + offsets[3])))
+            }
+            None => (width, height),
+        }
+    }
+
+    /* Surfaces follow the bit depth, Main 10 at most and 4:2:0 only */
+    pub fn get_rt_format(&self) -> Result<u32, ()> {
+        if self.get_chroma_array_type() != 1 {
+            return Err(());
+        }
+        match cmp::max(self.bit_depth_luma_minus8, self.bit_depth_chroma_minus8) {
+            0 => Ok(ffi::VA_RT_FORMAT_YUV420),
+            1 | 2 => Ok(ffi::VA_RT_FORMAT_YUV420_10BPP),
+            _ => Err(()),
+        }
+    }
+
+    pub fn get_va_profile(&self) -> Result<ffi::VAProfile, ()> {
+        match self.get_rt_format()? {
+            ffi::VA_RT_FORMAT_YUV420 => Ok(ffi::VAProfileHEVCMain),
+            _ => Ok(ffi::VAProfileHEVCMain10),
+        }
+    }
+
+    /* Values of the highest sub-layer */
+    pub fn get_dpb_size(&self) -> usize {
+        self.sub_layer_ordering[self.max_sub_layers_minus1 as usize]
+            .max_dec_pic_buffering_minus1 as usize + 1
+    }
+
+    pub fn get_num_reorder_pics(&self) -> usize {
+        self.sub_layer_ordering[self.max_sub_layers_minus1 as usize].max_num_reorder_pics as usize
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PpsRangeExtension {
+    pub log2_max_transform_skip_block_size_minus2: u32,
+    pub cross_component_prediction_enabled_flag: bool,
+    pub chroma_qp_offset_list_enabled_flag: bool,
+    pub diff_cu_chroma_qp_offset_depth: u32,
+    pub cb_qp_offset_list: Vec<i32>,
+    pub cr_qp_offset_list: Vec<i32>,
+    pub log2_sao_offset_scale_luma: u32,
+    pub log2_sao_offset_scale_chroma: u32,
+}
+
+impl PpsRangeExtension {
+    fn parse(br: &mut BitReader, transform_skip_enabled_flag: bool) -> Result<PpsRangeExtension, ()> {
+        let mut ext = PpsRangeExtension::default();
+        if transform_skip_enabled_flag {
+            ext.log2_max_transform_skip_block_size_minus2 = br.read_ue()?;
+        }
+        ext.cross_component_prediction_enabled_flag = br.read_flag()?;
+        ext.chroma_qp_offset_list_enabled_flag = br.read_flag()?;
+        if ext.chroma_qp_offset_list_enabled_flag {
+            ext.diff_cu_chroma_qp_offset_depth = br.read_ue()?;
+            let chroma_qp_offset_list_len_minus1 = br.read_ue()?;
+            if chroma_qp_offset_list_len_minus1 > 5 {
+                return Err(());
+            }
+            for _ in 0..chroma_qp_offset_list_len_minus1 + 1 {
+                ext.cb_qp_offset_list.push(br.read_se()?);
+                ext.cr_qp_offset_list.push(br.read_se()?);
+            }
+        }
+        ext.log2_sao_offset_scale_luma = br.read_ue()?;
+        ext.log2_sao_offset_scale_chroma = br.read_ue()?;
+        Ok(ext)
+    }
+}
+
+#[derive(Clone)]
+pub struct Pps {
+    pub pic_parameter_set_id: u32,
+    pub seq_parameter_set_id: u32,
+    pub dependent_slice_segments_enabled_flag: bool,
+    pub output_flag_present_flag: bool,
+    pub num_extra_slice_header_bits: u8,
+    pub sign_data_hiding_enabled_flag: bool,
+    pub cabac_init_present_flag: bool,
+    pub num_ref_idx_l0_default_active_minus1: u32,
+    pub num_ref_idx_l1_default_active_minus1: u32,
+    pub init_qp_minus26: i32,
+    pub constrained_intra_pred_flag: bool,
+    pub transform_skip_enabled_flag: bool,
+    pub cu_qp_delta_enabled_flag: bool,
+    pub diff_cu_qp_delta_depth: u32,
+    pub pps_cb_qp_offset: i32,
+    pub pps_cr_qp_offset: i32,
+    pub pps_slice_chroma_qp_offsets_present_flag: bool,
+    pub weighted_pred_flag: bool,
+    pub weighted_bipred_flag: bool,
+    pub transquant_bypass_enabled_flag: bool,
+    pub tiles_enabled_flag: bool,
+    pub entropy_coding_sync_enabled_flag: bool,
+    pub uniform_spacing_flag: bool,
+    /* Sizes in CTBs of every tile column and row, derived when the spacing is uniform */
+    pub column_width_minus1: Vec<u32>,
+    pub row_height_minus1: Vec<u32>,
+    pub loop_filter_across_tiles_enabled_flag: bool,
+    pub pps_loop_filter_across_slices_enabled_flag: bool,
+    pub deblocking_filter_control_present_flag: bool,
+    pub deblocking_filter_override_enabled_flag: bool,
+    pub pps_deblocking_filter_disabled_flag: bool,
+    pub pps_beta_offset_div2: i32,
+    pub pps_tc_offset_div2: i32,
+    /* The SPS ones unless pps_scaling_list_data_present_flag is set */
+    pub scaling_lists: ScalingLists,
+    pub lists_modification_present_flag: bool,
+    pub log2_parallel_merge_level_minus2: u32,
+    pub slice_segment_header_extension_present_flag: bool,
+    pub range_extension: Option<PpsRangeExtension>,
+}
+
+/* Sizes of `count` tiles spread evenly over `size` CTBs, equations 6-3 and 6-4 */
+fn get_uniform_tile_sizes(count: u32, size: u32) -> Vec<u32> {
+    (0..count)
+        .map(|i| ((i + 1) * size) / count - (i * size) / count - 1)
+        .collect()
+}
+
+/* Explicit sizes of all the tiles but the last one, which takes the remaining CTBs */
+fn parse_tile_sizes(br: &mut BitReader, count_minus1: u32, size: u32) -> Result<Vec<u32>, ()> {
+    let mut sizes = Vec::with_capacity(count_minus1 as usize + 1);
+    let mut remaining = size;
+    for _ in 0..count_minus1 {
+        let size_minus1 = br.read_ue()?;
+        if size_minus1 >= remaining {
+            return Err(());
+        }
+        remaining -= size_minus1 + 1;
+        sizes.push(size_minus1);
+    }
+    if remaining == 0 {
+        return Err(());
+    }
+    sizes.push(remaining - 1);
+    Ok(sizes)
+}
+
+impl Pps {
+    pub fn parse(data: &[u8], sps_list: &[Option<Sps>]) -> Result<Pps, ()> {
+        let mut br = BitReader::new(data);
+        let br = &mut br;
+        let pic_parameter_set_id = br.read_ue()?;
+        let seq_parameter_set_id = br.read_ue()?;
+        if pic_parameter_set_id as usize >= MAX_PPS_COUNT {
+            return Err(());
+        }
+        let sps = match sps_list.get(seq_parameter_set_id as usize) {
+            Some(&Some(ref sps)) => sps,
+            _ => return Err(()),
+        };
+
+        let dependent_slice_segments_enabled_flag = br.read_flag()?;
+        let output_flag_present_flag = br.read_flag()?;
+        let num_extra_slice_header_bits = br.read_bits(3)? as u8;
+        let sign_data_hiding_enabled_flag = br.read_flag()?;
+        let cabac_init_present_flag = br.read_flag()?;
+        let num_ref_idx_l0_default_active_minus1 = br.read_ue()?;
+        let num_ref_idx_l1_default_active_minus1 = br.read_ue()?;
+        if num_ref_idx_l0_default_active_minus1 > 14 || num_ref_idx_l1_default_active_minus1 > 14 {
+            return Err(());
+        }
+        let init_qp_minus26 = br.read_se()?;
+        let constrained_intra_pred_flag = br.read_flag()?;
+        let transform_skip_enabled_flag = br.read_flag()?;
+        let cu_qp_delta_enabled_flag = br.read_flag()?;
+        let diff_cu_qp_delta_depth = if cu_qp_delta_enabled_flag { br.read_ue()? } else { 0 };
+        let pps_cb_qp_offset = br.read_se()?;
+        let pps_cr_qp_offset = br.read_se()?;
+        if pps_cb_qp_offset < -12 || pps_cb_qp_offset > 12 || pps_cr_qp_offset < -12 ||
+           pps_cr_qp_offset > 12 {
+            return Err(());
+        }
+        let pps_slice_chroma_qp_offsets_present_flag = br.read_flag()?;
+        let weighted_pred_flag = br.read_flag()?;
+        let weighted_bipred_flag = br.read_flag()?;
+        let transquant_bypass_enabled_flag = br.read_flag()?;
+        let tiles_enabled_flag = br.read_flag()?;
+        let entropy_coding_sync_enabled_flag = br.read_flag()?;
+
+        let mut uniform_spacing_flag = true;
+        let mut column_width_minus1 = vec![sps.get_pic_width_in_ctbs() - 1];
+        let mut row_height_minus1 = vec![sps.get_pic_height_in_ctbs() - 1];
+        let mut loop_filter_across_tiles_enabled_flag = false;
+        if tiles_enabled_flag {
+            let num_tile_columns_minus1 = br.read_ue()?;
+            let num_tile_rows_minus1 = br.read_ue()?;
+            if num_tile_columns_minus1 >= sps.get_pic_width_in_ctbs() ||
+               num_tile_rows_minus1 >= sps.get_pic_height_in_ctbs() {
+                return Err(());
+            }
+            uniform_spacing_flag = br.read_flag()?;
+            if uniform_spacing_flag {
+                column_width_minus1 = get_uniform_tile_sizes(num_tile_columns_minus1 + 1,
+                                                             sps.get_pic_width_in_ctbs());
+                row_height_minus1 = get_uniform_tile_sizes(num_tile_rows_minus1 + 1,
+                                                           sps.get_pic_height_in_ctbs());
+            } else {
+                column_width_minus1 = parse_tile_sizes(br,
+                                                       num_tile_columns_minus1,
+                                                       sps.get_pic_width_in_ctbs())?;
+                row_height_minus1 = parse_tile_sizes(br,
+                                                     num_tile_rows_minus1,
+                                                     sps.get_pic_height_in_ctbs())?;
+            }
+            loop_filter_across_tiles_enabled_flag = br.read_flag()?;
+        }
+        let pps_loop_filter_across_slices_enabled_flag = br.read_flag()?;
+
+        let deblocking_filter_control_present_flag = br.read_flag()?;
+        let mut deblocking_filter_override_enabled_flag = false;
+        let mut pps_deblocking_filter_disabled_flag = false;
+        let mut pps_beta_offset_div2 = 0;
+        let mut pps_tc_offset_div2 = 0;
+        if deblocking_filter_control_present_flag {
+            deblocking_filter_override_enabled_flag = br.read_flag()?;
+            pps_deblocking_filter_disabled_flag = br.read_flag()?;
+            if !pps_deblocking_filter_disabled_flag {
+                pps_beta_offset_div2 = br.read_se()?;
+                pps_tc_offset_div2 = br.read_se()?;
+                if pps_beta_offset_div2 < -6 || pps_beta_offset_div2 > 6 ||
+                   pps_tc_offset_div2 < -6 || pps_tc_offset_div2 > 6 {
+                    return Err(());
+                }
+            }
+        }
+
+        let scaling_lists = if br.read_flag()? {
+            ScalingLists::parse(br)?
+        } else {
+            sps.scaling_lists
+        };
+        let lists_modification_present_flag = br.read_flag()?;
+        let log2_parallel_merge_level_minus2 = br.read_ue()?;
+        if log2_parallel_merge_level_minus2 + 2 > sps.get_ctb_log2_size() {
+            return Err(());
+        }
+        let slice_segment_header_extension_present_flag = br.read_flag()?;
+
+        let mut range_extension = None;
+        if br.read_flag()? {
+            let pps_range_extension_flag = br.read_flag()?;
+            /* pps_multilayer_extension_flag, pps_3d_extension_flag, pps_scc_extension_flag,
+             * pps_extension_4bits; these extensions are not parsed */
+            br.skip_bits(7)?;
+            if pps_range_extension_flag {
+                range_extension = Some(PpsRangeExtension::parse(br, transform_skip_enabled_flag)?);
+            }
+        }
+
+        Ok(Pps {
+            pic_parameter_set_id: pic_parameter_set_id,
+            seq_parameter_set_id: seq_parameter_set_id,
+            dependent_slice_segments_enabled_flag: dependent_slice_segments_enabled_flag,
+            output_flag_present_flag: output_flag_present_flag,
+            num_extra_slice_header_bits: num_extra_slice_header_bits,
+            sign_data_hiding_enabled_flag: sign_data_hiding_enabled_flag,
+            cabac_init_present_flag: cabac_init_present_flag,
+            num_ref_idx_l0_default_active_minus1: num_ref_idx_l0_default_active_minus1,
+            num_ref_idx_l1_default_active_minus1: num_ref_idx_l1_default_active_minus1,
+            init_qp_minus26: init_qp_minus26,
+            constrained_intra_pred_flag: constrained_intra_pred_flag,
+            transform_skip_enabled_flag: transform_skip_enabled_flag,
+            cu_qp_delta_enabled_flag: cu_qp_delta_enabled_flag,
+            diff_cu_qp_delta_depth: diff_cu_qp_delta_depth,
+            pps_cb_qp_offset: pps_cb_qp_offset,
+            pps_cr_qp_offset: pps_cr_qp_offset,
+            pps_slice_chroma_qp_offsets_present_flag: pps_slice_chroma_qp_offsets_present_flag,
+            weighted_pred_flag: weighted_pred_flag,
+            weighted_bipred_flag: weighted_bipred_flag,
+            transquant_bypass_enabled_flag: transquant_bypass_enabled_flag,
+            tiles_enabled_flag: tiles_enabled_flag,
+            entropy_coding_sync_enabled_flag: entropy_coding_sync_enabled_flag,
+            uniform_spacing_flag: uniform_spacing_flag,
+            column_width_minus1: column_width_minus1,
+            row_height_minus1: row_height_minus1,
+            loop_filter_across_tiles_enabled_flag: loop_filter_across_tiles_enabled_flag,
+            pps_loop_filter_across_slices_enabled_flag: pps_loop_filter_across_slices_enabled_flag,
+            deblocking_filter_control_present_flag: deblocking_filter_control_present_flag,
+            deblocking_filter_override_enabled_flag: deblocking_filter_override_enabled_flag,
+            pps_deblocking_filter_disabled_flag: pps_deblocking_filter_disabled_flag,
+            pps_beta_offset_div2: pps_beta_offset_div2,
+            pps_tc_offset_div2: pps_tc_offset_div2,
+            scaling_lists: scaling_lists,
+            lists_modification_present_flag: lists_modification_present_flag,
+            log2_parallel_merge_level_minus2: log2_parallel_merge_level_minus2,
+            slice_segment_header_extension_present_flag:
+                slice_segment_header_extension_present_flag,
+            range_extension: range_extension,
+        })
+    }
+}
+#[derive(Debug, Clone, Copy)]
+pub struct NalHeader {
+    pub nal_unit_type: u8,
+    pub nuh_layer_id: u8,
+    pub nuh_temporal_id_plus1: u8,
+}
+
+impl NalHeader {
+    pub fn parse(data: &[u8]) -> Result<NalHeader, ()> {
+        let mut br = BitReader::new(data);
+        /* forbidden_zero_bit */
+        if br.read_flag()? {
+            return Err(());
+        }
+        let nal = NalHeader {
+            nal_unit_type: br.read_bits(6)? as u8,
+            nuh_layer_id: br.read_bits(6)? as u8,
+            nuh_temporal_id_plus1: br.read_bits(3)? as u8,
+        };
+        if nal.nuh_temporal_id_plus1 == 0 {
+            return Err(());
+        }
+        Ok(nal)
+    }
+
+    pub fn is_slice(&self) -> bool {
+        self.nal_unit_type <= NAL_RASL_R ||
+        (self.nal_unit_type >= NAL_BLA_W_LP && self.nal_unit_type <= NAL_CRA)
+    }
+
+    pub fn is_irap(&self) -> bool {
+        self.nal_unit_type >= NAL_BLA_W_LP && self.nal_unit_type <= 23
+    }
+
+    pub fn is_idr(&self) -> bool {
+        self.nal_unit_type == NAL_IDR_W_RADL || self.nal_unit_type == NAL_IDR_N_LP
+    }
+}
+
+/* Syntax element values of pred_weight_table(), ChromaOffsetL0/L1 being derived */
+#[derive(Debug, Clone, Default)]
+pub struct PredWeightTable {
+    pub luma_log2_weight_denom: u8,
+    pub delta_chroma_log2_weight_denom: i8,
+    pub delta_luma_weight: [[i8; 15]; 2],
+    pub luma_offset: [[i16; 15]; 2],
+    pub delta_chroma_weight: [[[i8; 2]; 15]; 2],
+    pub chroma_offset: [[[i16; 2]; 15]; 2],
+}
+
+impl PredWeightTable {
+    fn parse(br: &mut BitReader,
+             sps: &Sps,
+             num_ref_idx_active: [u32; 2],
+             num_lists: usize)
+             -> Result<PredWeightTable, ()> {
+        let mut table = PredWeightTable::default();
+        let chroma = sps.get_chroma_array_type() != 0;
+        table.luma_log2_weight_denom = br.read_ue()? as u8;
+        if table.luma_log2_weight_denom > 7 {
+            return Err(());
+        }
+        let mut chroma_log2_weight_denom = table.luma_log2_weight_denom as i32;
+        if chroma {
+            let delta = br.read_se()?;
+            chroma_log2_weight_denom += delta;
+            if chroma_log2_weight_denom < 0 || chroma_log2_weight_denom > 7 {
+                return Err(());
+            }
+            table.delta_chroma_log2_weight_denom = delta as i8;
+        }
+
+        let high_precision = sps.range_extension
+            .map_or(false, |ext| ext.high_precision_offsets_enabled_flag);
+        let (offset_range_luma, offset_range_chroma) = if high_precision {
+            (1 << (sps.bit_depth_luma_minus8 + 7), 1 << (sps.bit_depth_chroma_minus8 + 7))
+        } else {
+            (128, 128)
+        };
+
+        for list in 0..num_lists {
+            let count = num_ref_idx_active[list] as usize;
+            let mut luma_weight_flags = [false; 15];
+            let mut chroma_weight_flags = [false; 15];
+            for flag in luma_weight_flags.iter_mut().take(count) {
+                *flag = br.read_flag()?;
+            }
+            if chroma {
+                for flag in chroma_weight_flags.iter_mut().take(count) {
+                    *flag = br.read_flag()?;
+                }
+            }
+            for i in 0..count {
+                if luma_weight_flags[i] {
+                    let delta_weight = br.read_se()?;
+                    let offset = br.read_se()?;
+                    if delta_weight < -128 || delta_weight > 127 || offset < -offset_range_luma ||
+                       offset >= offset_range_luma {
+                        return Err(());
+                    }
+                    table.delta_luma_weight[list][i] = delta_weight as i8;
+                    table.luma_offset[list][i] = offset as i16;
+                }
+                if !chroma_weight_flags[i] {
+                    continue;
+                }
+                for j in 0..2 {
+                    let delta_weight = br.read_se()?;
+                    let delta_offset = br.read_se()?;
+                    if delta_weight < -128 || delta_weight > 127 ||
+                       delta_offset < -4 * offset_range_chroma ||
+                       delta_offset >= 4 * offset_range_chroma {
+                        return Err(());
+                    }
+                    /* Equation 7-56 */
+                    let weight = (1 << chroma_log2_weight_denom) + delta_weight;
+                    let offset = offset_range_chroma + delta_offset -
+                                 ((offset_range_chroma * weight) >> chroma_log2_weight_denom);
+                    table.delta_chroma_weight[list][i][j] = delta_weight as i8;
+                    table.chroma_offset[list][i][j] =
+                        cmp::min(cmp::max(offset, -offset_range_chroma), offset_range_chroma - 1) as
+                        i16;
+                }
+            }
+        }
+
+        Ok(table)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SliceHeader {
+    pub first_slice_segment_in_pic_flag: bool,
+    pub no_output_of_prior_pics_flag: bool,
+    pub pic_parameter_set_id: u32,
+    pub dependent_slice_segment_flag: bool,
+    pub slice_segment_address: u32,
+    pub slice_type: u8,
+    pub pic_output_flag: bool,
+    pub colour_plane_id: u8,
+    pub slice_pic_order_cnt_lsb: u32,
+    pub short_term_ref_pic_set_sps_flag: bool,
+    pub short_term_ref_pic_set_idx: u32,
+    /* The set in use, either taken from the SPS or sent in the header */
+    pub short_term_ref_pic_set: ShortTermRefPicSet,
+    /* Size of the st_ref_pic_set() of the header in bits */
+    pub st_rps_bits: u32,
+    pub num_long_term_sps: u32,
+    pub num_long_term_pics: u32,
+    /* PocLsbLt, UsedByCurrPicLt and DeltaPocMsbCycleLt of each long-term picture */
+    pub poc_lsb_lt: Vec<u32>,
+    pub used_by_curr_pic_lt: Vec<bool>,
+    pub delta_poc_msb_present_flag: Vec<bool>,
+    pub delta_poc_msb_cycle_lt: Vec<u32>,
+    pub slice_temporal_mvp_enabled_flag: bool,
+    pub slice_sao_luma_flag: bool,
+    pub slice_sao_chroma_flag: bool,
+    pub num_ref_idx_l0_active_minus1: u32,
+    pub num_ref_idx_l1_active_minus1: u32,
+    /* list_entry_l0 and list_entry_l1, when ref_pic_list_modification_flag is set */
+    pub list_entry: [Option<Vec<u32>>; 2],
+    pub mvd_l1_zero_flag: bool,
+    pub cabac_init_flag: bool,
+    pub collocated_from_l0_flag: bool,
+    pub collocated_ref_idx: u32,
+    pub pred_weight_table: Option<PredWeightTable>,
+    pub five_minus_max_num_merge_cand: u32,
+    pub slice_qp_delta: i32,
+    pub slice_cb_qp_offset: i32,
+    pub slice_cr_qp_offset: i32,
+    pub cu_chroma_qp_offset_enabled_flag: bool,
+    pub slice_deblocking_filter_disabled_flag: bool,
+    pub slice_beta_offset_div2: i32,
+    pub slice_tc_offset_div2: i32,
+    pub slice_loop_filter_across_slices_enabled_flag: bool,
+    pub entry_point_offset_minus1: Vec<u32>,
+    /* Size of the header in bits, after the NAL unit header and up to the slice data */
+    pub header_size: usize,
+}
+
+impl SliceHeader {
+    /* `data` is the RBSP following the NAL unit header. Dependent slice segments take the
+     * values of `prev`, the header of the preceding independent segment. */
+    pub fn parse(data: &[u8],
+                 nal: &NalHeader,
+                 sps_list: &[Option<Sps>],
+                 pps_list: &[Option<Pps>],
+                 prev: Option<&SliceHeader>)
+                 -> Result<SliceHeader, ()> {
+        let mut br = BitReader::new(data);
+        let br = &mut br;
+        let first_slice_segment_in_pic_flag = br.read_flag()?;
+        let no_output_of_prior_pics_flag = nal.is_irap() && br.read_flag()?;
+        let pic_parameter_set_id = br.read_ue()?;
+
+        let pps = match pps_list.get(pic_parameter_set_id as usize) {
+            Some(&Some(ref pps)) => pps,
+            _ => return Err(()),
+        };
+        let sps = match sps_list.get(pps.seq_parameter_set_id as usize) {
+            Some(&Some(ref sps)) => sps,
+            _ => return Err(()),
+        };
+
+        let mut dependent_slice_segment_flag = false;
+        let mut slice_segment_address = 0;
+        if !first_slice_segment_in_pic_flag {
+            if pps.dependent_slice_segments_enabled_flag {
+                dependent_slice_segment_flag = br.read_flag()?;
+            }
+            let pic_size_in_ctbs = sps.get_pic_width_in_ctbs() * sps.get_pic_height_in_ctbs();
+            slice_segment_address = br.read_bits(ceil_log2(pic_size_in_ctbs))?;
+            if slice_segment_address >= pic_size_in_ctbs {
+                return Err(());
+            }
+        }
+
+        let mut hdr = if dependent_slice_segment_flag {
+            match prev {
+                Some(prev) if prev.pic_parameter_set_id == pic_parameter_set_id => prev.clone(),
+                _ => return Err(()),
+            }
+        } else {
+            SliceHeader::parse_independent(br, nal, sps, pps)?
+        };
+        hdr.first_slice_segment_in_pic_flag = first_slice_segment_in_pic_flag;
+        hdr.no_output_of_prior_pics_flag = no_output_of_prior_pics_flag;
+        hdr.pic_parameter_set_id = pic_parameter_set_id;
+        hdr.dependent_slice_segment_flag = dependent_slice_segment_flag;
+        hdr.slice_segment_address = slice_segment_address;
+
+        hdr.entry_point_offset_minus1.clear();
+        if pps.tiles_enabled_flag || pps.entropy_coding_sync_enabled_flag {
+            let num_entry_point_offsets = br.read_ue()?;
+            if num_entry_point_offsets > sps.get_pic_width_in_ctbs() * sps.get_pic_height_in_ctbs() {
+                return Err(());
+            }
+            if num_entry_point_offsets > 0 {
+                let offset_len_minus1 = br.read_ue()?;
+                if offset_len_minus1 > 31 {
+                    return Err(());
+                }
+                for _ in 0..num_entry_point_offsets {
+                    hdr.entry_point_offset_minus1.push(br.read_bits(offset_len_minus1 + 1)?);
+                }
+            }
+        }
+        if pps.slice_segment_header_extension_present_flag {
+            let slice_segment_header_extension_length = br.read_ue()?;
+            if slice_segment_header_extension_length > 256 {
+                return Err(());
+            }
+            br.skip_bits(slice_segment_header_extension_length as usize * 8)?;
+        }
+
+        /* byte_alignment() */
+        if !br.read_flag()? {
+            return Err(());
+        }
+        br.byte_align();
+        hdr.header_size = br.get_pos();
+
+        Ok(hdr)
+    }
+
+    /* Syntax elements of independent slice segments only */
+    fn parse_independent(br: &mut BitReader,
+                         nal: &NalHeader,
+                         sps: &Sps,
+                         pps: &Pps)
+                         -> Result<SliceHeader, ()> {
+        let mut hdr = SliceHeader::default();
+        /* slice_reserved_flag */
+        br.skip_bits(pps.num_extra_slice_header_bits as usize)?;
+        let slice_type = br.read_ue()?;
+        if slice_type > 2 || (nal.is_irap() && slice_type != SLICE_TYPE_I as u32) {
+            return Err(());
+        }
+        hdr.slice_type = slice_type as u8;
+        hdr.pic_output_flag = !pps.output_flag_present_flag || br.read_flag()?;
+        if sps.separate_colour_plane_flag {
+            hdr.colour_plane_id = br.read_bits(2)? as u8;
+        }
+
+        if !nal.is_idr() {
+            hdr.slice_pic_order_cnt_lsb = br.read_bits(sps.log2_max_pic_order_cnt_lsb_minus4 + 4)?;
+            hdr.short_term_ref_pic_set_sps_flag = br.read_flag()?;
+            let num_sets = sps.short_term_ref_pic_sets.len();
+            if !hdr.short_term_ref_pic_set_sps_flag {
+                let start = br.get_pos();
+                hdr.short_term_ref_pic_set = ShortTermRefPicSet::parse(br,
+                                                                       &sps.short_term_ref_pic_sets,
+                                                                       true)?;
+                hdr.st_rps_bits = (br.get_pos() - start) as u32;
+            } else {
+                if num_sets == 0 {
+                    return Err(());
+                }
+                if num_sets > 1 {
+                    hdr.short_term_ref_pic_set_idx = br.read_bits(ceil_log2(num_sets as u32))?;
+                }
+                hdr.short_term_ref_pic_set = match sps.short_term_ref_pic_sets
+                    .get(hdr.short_term_ref_pic_set_idx as usize) {
+                    Some(rps) => rps.clone(),
+                    None => return Err(()),
+                };
+            }
+
+            if sps.long_term_ref_pics_present_flag {
+                hdr.parse_long_term_refs(br, sps)?;
+            }
+            if sps.sps_temporal_mvp_enabled_flag {
+                hdr.slice_temporal_mvp_enabled_flag = br.read_flag()?;
+            }
+        }
+
+        if sps.sample_adaptive_offset_enabled_flag {
+            hdr.slice_sao_luma_flag = br.read_flag()?;
+            if sps.get_chroma_array_type() != 0 {
+                hdr.slice_sao_chroma_flag = br.read_flag()?;
+            }
+        }
+
+        hdr.collocated_from_l0_flag = true;
+        if hdr.slice_type != SLICE_TYPE_I {
+            hdr.num_ref_idx_l0_active_minus1 = pps.num_ref_idx_l0_default_active_minus1;
+            if hdr.slice_type == SLICE_TYPE_B {
+                hdr.num_ref_idx_l1_active_minus1 = pps.num_ref_idx_l1_default_active_minus1;
+            }
+            if br.read_flag()? {
+                hdr.num_ref_idx_l0_active_minus1 = br.read_ue()?;
+                if hdr.slice_type == SLICE_TYPE_B {
+                    hdr.num_ref_idx_l1_active_minus1 = br.read_ue()?;
+                }
+            }
+            if hdr.num_ref_idx_l0_active_minus1 > 14 || hdr.num_ref_idx_l1_active_minus1 > 14 {
+                return Err(());
+            }
+
+            let num_pic_total_curr = hdr.get_num_pic_total_curr();
+            if pps.lists_modification_present_flag && num_pic_total_curr > 1 {
+                let bits = ceil_log2(num_pic_total_curr);
+                let num_lists = if hdr.slice_type == SLICE_TYPE_B { 2 } else { 1 };
+                for list in 0..num_lists {
+                    if !br.read_flag()? {
+                        continue;
+                    }
+                    let count = if list == 0 {
+                        hdr.num_ref_idx_l0_active_minus1
+                    } else {
+                        hdr.num_ref_idx_l1_active_minus1
+                    } + 1;
+                    let mut entries = Vec::with_capacity(count as usize);
+                    for _ in 0..count {
+                        entries.push(br.read_bits(bits)?);
+                    }
+                    hdr.list_entry[list] = Some(entries);
+                }
+            }
+
+            if hdr.slice_type == SLICE_TYPE_B {
+                hdr.mvd_l1_zero_flag = br.read_flag()?;
+            }
+            if pps.cabac_init_present_flag {
+                hdr.cabac_init_flag = br.read_flag()?;
+            }
+            if hdr.slice_temporal_mvp_enabled_flag {
+                if hdr.slice_type == SLICE_TYPE_B {
+                    hdr.collocated_from_l0_flag = br.read_flag()?;
+                }
+                let num_ref_idx_active_minus1 = if hdr.collocated_from_l0_flag {
+                    hdr.num_ref_idx_l0_active_minus1
+                } else {
+                    hdr.num_ref_idx_l1_active_minus1
+                };
+                if num_ref_idx_active_minus1 > 0 {
+                    hdr.collocated_ref_idx = br.read_ue()?;
+                    if hdr.collocated_ref_idx > num_ref_idx_active_minus1 {
+                        return Err(());
+                    }
+                }
+            }
+            if (pps.weighted_pred_flag && hdr.slice_type == SLICE_TYPE_P) ||
+               (pps.weighted_bipred_flag && hdr.slice_type == SLICE_TYPE_B) {
+                let num_lists = if hdr.slice_type == SLICE_TYPE_B { 2 } else { 1 };
+                hdr.pred_weight_table = Some(PredWeightTable::parse(br,
+                                                                    sps,
+                                                                    [hdr.num_ref_idx_l0_active_minus1 + 1,
+                                                                     hdr.num_ref_idx_l1_active_minus1 + 1],
+                                                                    num_lists)?);
+            }
+            hdr.five_minus_max_num_merge_cand = br.read_ue()?;
+            if hdr.five_minus_max_num_merge_cand > 4 {
+                return Err(());
+            }
+        }
+
+        hdr.slice_qp_delta = br.read_se()?;
+        if pps.pps_slice_chroma_qp_offsets_present_flag {
+            hdr.slice_cb_qp_offset = br.read_se()?;
+            hdr.slice_cr_qp_offset = br.read_se()?;
+            if hdr.slice_cb_qp_offset < -12 || hdr.slice_cb_qp_offset > 12 ||
+               hdr.slice_cr_qp_offset < -12 || hdr.slice_cr_qp_offset > 12 {
+                return Err(());
+            }
+        }
+        if pps.range_extension.as_ref().map_or(false, |ext| ext.chroma_qp_offset_list_enabled_flag) {
+            hdr.cu_chroma_qp_offset_enabled_flag = br.read_flag()?;
+        }
+
+        hdr.slice_deblocking_filter_disabled_flag = pps.pps_deblocking_filter_disabled_flag;
+        hdr.slice_beta_offset_div2 = pps.pps_beta_offset_div2;
+        hdr.slice_tc_offset_div2 = pps.pps_tc_offset_div2;
+        if pps.deblocking_filter_override_enabled_flag && br.read_flag()? {
+            hdr.slice_deblocking_filter_disabled_flag = br.read_flag()?;
+            if !hdr.slice_deblocking_filter_disabled_flag {
+                hdr.slice_beta_offset_div2 = br.read_se()?;
+                hdr.slice_tc_offset_div2 = br.read_se()?;
+                if hdr.slice_beta_offset_div2 < -6 || hdr.slice_beta_offset_div2 > 6 ||
+                   hdr.slice_tc_offset_div2 < -6 || hdr.slice_tc_offset_div2 > 6 {
+                    return Err(());
+                }
+            }
+        }
+        hdr.slice_loop_filter_across_slices_enabled_flag =
+            pps.pps_loop_filter_across_slices_enabled_flag;
+        if pps.pps_loop_filter_across_slices_enabled_flag &&
+           (hdr.slice_sao_luma_flag || hdr.slice_sao_chroma_flag ||
+            !hdr.slice_deblocking_filter_disabled_flag) {
+            hdr.slice_loop_filter_across_slices_enabled_flag = br.read_flag()?;
+        }
+
+        Ok(hdr)
+    }
+
+    fn parse_long_term_refs(&mut self, br: &mut BitReader, sps: &Sps) -> Result<(), ()> {
+        let num_lt_sps = sps.lt_ref_pic_poc_lsb_sps.len() as u32;
+        if num_lt_sps > 0 {
+            self.num_long_term_sps = br.read_ue()?;
+            if self.num_long_term_sps > num_lt_sps {
+                return Err(());
+            }
+        }
+        self.num_long_term_pics = br.read_ue()?;
+        let count = self.num_long_term_sps + self.num_long_term_pics;
+        if count as usize + self.short_term_ref_pic_set.get_num_delta_pocs() > 16 {
+            return Err(());
+        }
+
+        for i in 0..count {
+            if i < self.num_long_term_sps {
+                let lt_idx_sps = if num_lt_sps > 1 {
+                    br.read_bits(ceil_log2(num_lt_sps))?
+                } else {
+                    0
+                } as usize;
+                if lt_idx_sps >= num_lt_sps as usize {
+                    return Err(());
+                }
+                self.poc_lsb_lt.push(sps.lt_ref_pic_poc_lsb_sps[lt_idx_sps]);
+                self.used_by_curr_pic_lt.push(sps.used_by_curr_pic_lt_sps_flag[lt_idx_sps]);
+            } else {
+                self.poc_lsb_lt.push(br.read_bits(sps.log2_max_pic_order_cnt_lsb_minus4 + 4)?);
+                self.used_by_curr_pic_lt.push(br.read_flag()?);
+            }
+            let delta_poc_msb_present_flag = br.read_flag()?;
+            let mut delta_poc_msb_cycle_lt = if delta_poc_msb_present_flag {
+                br.read_ue()?
+            } else {
+                0
+            };
+            /* Equation 7-52 */
+            if i != 0 && i != self.num_long_term_sps {
+                delta_poc_msb_cycle_lt += self.delta_poc_msb_cycle_lt[i as usize - 1];
+            }
+            self.delta_poc_msb_present_flag.push(delta_poc_msb_present_flag);
+            self.delta_poc_msb_cycle_lt.push(delta_poc_msb_cycle_lt);
+        }
+
+        Ok(())
+    }
+
+    /* NumPicTotalCurr */
+    pub fn get_num_pic_total_curr(&self) -> u32 {
+        (self.short_term_ref_pic_set.get_num_used_by_curr_pic() +
+         self.used_by_curr_pic_lt.iter().filter(|&&used| used).count()) as u32
+    }
+}
+
+pub const SEI_BUFFERING_PERIOD: u32 = 0;
+pub const SEI_PIC_TIMING: u32 = 1;
+pub const SEI_USER_DATA_UNREGISTERED: u32 = 5;
+pub const SEI_RECOVERY_POINT: u32 = 6;
+pub const SEI_MASTERING_DISPLAY_COLOUR_VOLUME: u32 = 137;
+pub const SEI_CONTENT_LIGHT_LEVEL_INFO: u32 = 144;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PicTiming {
+    /* Set when the VUI has frame_field_info_present_flag */
+    pub pic_struct: Option<u8>,
+    pub source_scan_type: u8,
+    pub duplicate_flag: bool,
+    /* Set when the VUI has HRD parameters */
+    pub au_cpb_removal_delay_minus1: Option<u32>,
+    pub pic_dpb_output_delay: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RecoveryPoint {
+    pub recovery_poc_cnt: i32,
+    pub exact_match_flag: bool,
+    pub broken_link_flag: bool,
+}
+
+/* Chromaticities in units of 0.00002, luminances in units of 0.0001 cd/m2 */
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MasteringDisplayColourVolume {
+    /* x and y of the green, blue and red primaries */
+    pub display_primaries: [(u16, u16); 3],
+    pub white_point: (u16, u16),
+    pub max_display_mastering_luminance: u32,
+    pub min_display_mastering_luminance: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ContentLightLevel {
+    pub max_content_light_level: u16,
+    pub max_pic_average_light_level: u16,
+}
+
+#[derive(Debug, Clone)]
+pub enum SeiMessage {
+    PicTiming(PicTiming),
+    /* uuid_iso_iec_11578 and the payload following it */
+    UserDataUnregistered([u8; 16], Vec<u8>),
+    RecoveryPoint(RecoveryPoint),
+    MasteringDisplayColourVolume(MasteringDisplayColourVolume),
+    ContentLightLevel(ContentLightLevel),
+    /* Payload type and data of the messages that are not parsed */
+    Other(u32, Vec<u8>),
+}
+
+fn read_sei_value(data: &[u8], pos: &mut usize) -> Result<usize, ()> {
+    let mut value = 0;
+    loop {
+        let byte = *data.get(*pos).ok_or(())?;
+        *pos += 1;
+        value += byte as usize;
+        if byte != 0xff {
+            return Ok(value);
+        }
+    }
+}
+
+fn parse_pic_timing(br: &mut BitReader, sps: &Sps) -> Result<PicTiming, ()> {
+    let mut pt = PicTiming::default();
+    let vui = match sps.vui {
+        Some(ref vui) => vui,
+        None => return Ok(pt),
+    };
+    if vui.frame_field_info_present_flag {
+        pt.pic_struct = Some(br.read_bits(4)? as u8);
+        pt.source_scan_type = br.read_bits(2)? as u8;
+        pt.duplicate_flag = br.read_flag()?;
+    }
+    if let Some(ref hrd) = vui.hrd {
+        if hrd.has_cpb_dpb_delays() {
+            pt.au_cpb_removal_delay_minus1 =
+                Some(br.read_bits(hrd.au_cpb_removal_delay_length_minus1 as u32 + 1)?);
+            pt.pic_dpb_output_delay =
+                Some(br.read_bits(hrd.dpb_output_delay_length_minus1 as u32 + 1)?);
+        }
+    }
+    Ok(pt)
+}
+
+fn parse_mastering_display(br: &mut BitReader) -> Result<MasteringDisplayColourVolume, ()> {
+    let mut mdcv = MasteringDisplayColourVolume::default();
+    for primary in mdcv.display_primaries.iter_mut() {
+        *primary = (br.read_bits(16)? as u16, br.read_bits(16)? as u16);
+    }
+    mdcv.white_point = (br.read_bits(16)? as u16, br.read_bits(16)? as u16);
+    mdcv.max_display_mastering_luminance = br.read_bits(32)?;
+    mdcv.min_display_mastering_luminance = br.read_bits(32)?;
+    Ok(mdcv)
+}
+
+/* Messages of a prefix or suffix SEI RBSP; the picture timing ones are only parsed when
+ * `sps` gives their layout */
+pub fn parse_sei(data: &[u8], sps: Option<&Sps>) -> Result<Vec<SeiMessage>, ()> {
+    let mut messages = Vec::new();
+    let mut pos = 0;
+
+    /* Stops at the rbsp_trailing_bits */
+    while pos < data.len() && data[pos] != 0x80 {
+        let payload_type = read_sei_value(data, &mut pos)? as u32;
+        let payload_size = read_sei_value(data, &mut pos)?;
+        if data.len() - pos < payload_size {
+            return Err(());
+        }
+        let payload = &data[pos..pos + payload_size];
+        pos += payload_size;
+
+        let mut br = BitReader::new(payload);
+        let message = match (payload_type, sps) {
+            (SEI_PIC_TIMING, Some(sps)) => SeiMessage::PicTiming(parse_pic_timing(&mut br, sps)?),
+            (SEI_USER_DATA_UNREGISTERED, _) if payload_size >= 16 => {
+                let mut uuid = [0; 16];
+                uuid.copy_from_slice(&payload[..16]);
+                SeiMessage::UserDataUnregistered(uuid, payload[16..].to_vec())
+            }
+            (SEI_RECOVERY_POINT, _) => {
+                SeiMessage::RecoveryPoint(RecoveryPoint {
+                    recovery_poc_cnt: br.read_se()?,
+                    exact_match_flag: br.read_flag()?,
+                    broken_link_flag: br.read_flag()?,
+                })
+            }
+            (SEI_MASTERING_DISPLAY_COLOUR_VOLUME, _) => {
+                SeiMessage::MasteringDisplayColourVolume(parse_mastering_display(&mut br)?)
+            }
+            (SEI_CONTENT_LIGHT_LEVEL_INFO, _) => {
+                SeiMessage::ContentLightLevel(ContentLightLevel {
+                    max_content_light_level: br.read_bits(16)? as u16,
+                    max_pic_average_light_level: br.read_bits(16)? as u16,
+                })
+            }
+            _ => SeiMessage::Other(payload_type, payload.to_vec()),
+        };
+        messages.push(message);
+    }
+
+    Ok(messages)
+}
+
+pub fn build_va_picture(picture_id: ffi::VASurfaceID,
+                        pic_order_cnt: i32,
+                        flags: u32)
+                        -> ffi::VAPictureHEVC {
+    let mut va_pic: ffi::VAPictureHEVC = zeroed();
+    va_pic.picture_id = picture_id;
+    va_pic.pic_order_cnt = pic_order_cnt;
+    va_pic.flags = flags;
+    va_pic
+}
+
+pub fn invalid_va_picture() -> ffi::VAPictureHEVC {
+    build_va_picture(ffi::VA_INVALID_SURFACE, 0, ffi::VA_PICTURE_HEVC_INVALID)
+}
+
+/* The DPB is managed by the caller, which gives the VA descriptions of the current
+ * picture and of the pictures of its reference picture set */
+pub fn build_picture_param(sps: &Sps,
+                           pps: &Pps,
+                           nal: &NalHeader,
+                           hdr: &SliceHeader,
+                           curr_pic: ffi::VAPictureHEVC,
+                           ref_frames: &[ffi::VAPictureHEVC])
+                           -> Result<ffi::VAPictureParameterBufferHEVC, ()> {
+    let mut param: ffi::VAPictureParameterBufferHEVC = zeroed();
+    param.CurrPic = curr_pic;
+    for i in 0..param.ReferenceFrames.len() {
+        param.ReferenceFrames[i] = ref_frames.get(i).cloned().unwrap_or_else(invalid_va_picture);
+    }
+
+    param.pic_width_in_luma_samples = sps.pic_width_in_luma_samples as u16;
+    param.pic_height_in_luma_samples = sps.pic_height_in_luma_samples as u16;
+    param.sps_max_dec_pic_buffering_minus1 = (sps.get_dpb_size() - 1) as u8;
+    param.bit_depth_luma_minus8 = sps.bit_depth_luma_minus8;
+    param.bit_depth_chroma_minus8 = sps.bit_depth_chroma_minus8;
+    param.pcm_sample_bit_depth_luma_minus1 = sps.pcm_sample_bit_depth_luma_minus1;
+    param.pcm_sample_bit_depth_chroma_minus1 = sps.pcm_sample_bit_depth_chroma_minus1;
+    param.log2_min_luma_coding_block_size_minus3 =
+        sps.log2_min_luma_coding_block_size_minus3 as u8;
+    param.log2_diff_max_min_luma_coding_block_size =
+        sps.log2_diff_max_min_luma_coding_block_size as u8;
+    param.log2_min_transform_block_size_minus2 =
+        sps.log2_min_luma_transform_block_size_minus2 as u8;
+    param.log2_diff_max_min_transform_block_size =
+        sps.log2_diff_max_min_luma_transform_block_size as u8;
+    param.log2_min_pcm_luma_coding_block_size_minus3 =
+        sps.log2_min_pcm_luma_coding_block_size_minus3 as u8;
+    param.log2_diff_max_min_pcm_luma_coding_block_size =
+        sps.log2_diff_max_min_pcm_luma_coding_block_size as u8;
+    param.max_transform_hierarchy_depth_intra = sps.max_transform_hierarchy_depth_intra as u8;
+    param.max_transform_hierarchy_depth_inter = sps.max_transform_hierarchy_depth_inter as u8;
+    param.init_qp_minus26 = pps.init_qp_minus26 as i8;
+    param.diff_cu_qp_delta_depth = pps.diff_cu_qp_delta_depth as u8;
+    param.pps_cb_qp_offset = pps.pps_cb_qp_offset as i8;
+    param.pps_cr_qp_offset = pps.pps_cr_qp_offset as i8;
+    param.log2_parallel_merge_level_minus2 = pps.log2_parallel_merge_level_minus2 as u8;
+    param.num_tile_columns_minus1 = (pps.column_width_minus1.len() - 1) as u8;
+    param.num_tile_rows_minus1 = (pps.row_height_minus1.len() - 1) as u8;
+    for (dst, &width) in param.column_width_minus1.iter_mut().zip(&pps.column_width_minus1) {
+        *dst = width as u16;
+    }
+    for (dst, &height) in param.row_height_minus1.iter_mut().zip(&pps.row_height_minus1) {
+        *dst = height as u16;
+    }
+    param.log2_max_pic_order_cnt_lsb_minus4 = sps.log2_max_pic_order_cnt_lsb_minus4 as u8;
+    param.num_short_term_ref_pic_sets = sps.short_term_ref_pic_sets.len() as u8;
+    param.num_long_term_ref_pic_sps = sps.lt_ref_pic_poc_lsb_sps.len() as u8;
+    param.num_ref_idx_l0_default_active_minus1 = pps.num_ref_idx_l0_default_active_minus1 as u8;
+    param.num_ref_idx_l1_default_active_minus1 = pps.num_ref_idx_l1_default_active_minus1 as u8;
+    param.pps_beta_offset_div2 = pps.pps_beta_offset_div2 as i8;
+    param.pps_tc_offset_div2 = pps.pps_tc_offset_div2 as i8;
+    param.num_extra_slice_header_bits = pps.num_extra_slice_header_bits;
+    param.st_rps_bits = hdr.st_rps_bits;
+
+    param.pic_fields = HEVCPicFields {
+        chroma_format_idc: sps.chroma_format_idc as u32,
+        separate_colour_plane_flag: sps.separate_colour_plane_flag as u32,
+        pcm_enabled_flag: sps.pcm_enabled_flag as u32,
+        scaling_list_enabled_flag: sps.scaling_list_enabled_flag as u32,
+        transform_skip_enabled_flag: pps.transform_skip_enabled_flag as u32,
+        amp_enabled_flag: sps.amp_enabled_flag as u32,
+        strong_intra_smoothing_enabled_flag: sps.strong_intra_smoothing_enabled_flag as u32,
+        sign_data_hiding_enabled_flag: pps.sign_data_hiding_enabled_flag as u32,
+        constrained_intra_pred_flag: pps.constrained_intra_pred_flag as u32,
+        cu_qp_delta_enabled_flag: pps.cu_qp_delta_enabled_flag as u32,
+        weighted_pred_flag: pps.weighted_pred_flag as u32,
+        weighted_bipred_flag: pps.weighted_bipred_flag as u32,
+        transquant_bypass_enabled_flag: pps.transquant_bypass_enabled_flag as u32,
+        tiles_enabled_flag: pps.tiles_enabled_flag as u32,
+        entropy_coding_sync_enabled_flag: pps.entropy_coding_sync_enabled_flag as u32,
+        pps_loop_filter_across_slices_enabled_flag:
+            pps.pps_loop_filter_across_slices_enabled_flag as u32,
+        loop_filter_across_tiles_enabled_flag: pps.loop_filter_across_tiles_enabled_flag as u32,
+        pcm_loop_filter_disabled_flag: sps.pcm_loop_filter_disabled_flag as u32,
+        NoPicReorderingFlag: (sps.get_num_reorder_pics() == 0) as u32,
+        ..HEVCPicFields::default()
+    }.to_va()?;
+
+    param.slice_parsing_fields = HEVCSliceParsingFields {
+        lists_modification_present_flag: pps.lists_modification_present_flag as u32,
+        long_term_ref_pics_present_flag: sps.long_term_ref_pics_present_flag as u32,
+        sps_temporal_mvp_enabled_flag: sps.sps_temporal_mvp_enabled_flag as u32,
+        cabac_init_present_flag: pps.cabac_init_present_flag as u32,
+        output_flag_present_flag: pps.output_flag_present_flag as u32,
+        dependent_slice_segments_enabled_flag: pps.dependent_slice_segments_enabled_flag as u32,
+        pps_slice_chroma_qp_offsets_present_flag:
+            pps.pps_slice_chroma_qp_offsets_present_flag as u32,
+        sample_adaptive_offset_enabled_flag: sps.sample_adaptive_offset_enabled_flag as u32,
+        deblocking_filter_override_enabled_flag:
+            pps.deblocking_filter_override_enabled_flag as u32,
+        pps_disable_deblocking_filter_flag: pps.pps_deblocking_filter_disabled_flag as u32,
+        slice_segment_header_extension_present_flag:
+            pps.slice_segment_header_extension_present_flag as u32,
+        RapPicFlag: nal.is_irap() as u32,
+        IdrPicFlag: nal.is_idr() as u32,
+        IntraPicFlag: (hdr.slice_type == SLICE_TYPE_I) as u32,
+        ..HEVCSliceParsingFields::default()
+    }.to_va()?;
+
+    Ok(param)
+}
+
+/* `data` is the whole NAL unit of the slice segment and `ref_pic_list` gives the index in
+ * ReferenceFrames of each entry of RefPicList0 and RefPicList1, 0xff for none */
+pub fn build_slice_param(sps: &Sps,
+                         data: &[u8],
+                         hdr: &SliceHeader,
+                         ref_pic_list: &[[u8; 15]; 2],
+                         last_slice_of_pic: bool)
+                         -> Result<ffi::VASliceParameterBufferHEVC, ()> {
+    let mut param: ffi::VASliceParameterBufferHEVC = zeroed();
+    param.slice_data_size = data.len() as u32;
+    param.slice_data_offset = 0;
+    param.slice_data_flag = ffi::VA_SLICE_DATA_FLAG_ALL as u16;
+    param.slice_data_byte_offset = (NAL_HEADER_BYTES +
+                                    get_escaped_offset(&data[NAL_HEADER_BYTES..],
+                                                       hdr.header_size / 8)) as u32;
+    param.slice_segment_address = hdr.slice_segment_address;
+    param.RefPicList = *ref_pic_list;
+    param.collocated_ref_idx = if hdr.slice_temporal_mvp_enabled_flag {
+        hdr.collocated_ref_idx as u8
+    } else {
+        0xff
+    };
+    param.num_ref_idx_l0_active_minus1 = hdr.num_ref_idx_l0_active_minus1 as u8;
+    param.num_ref_idx_l1_active_minus1 = hdr.num_ref_idx_l1_active_minus1 as u8;
+    param.slice_qp_delta = hdr.slice_qp_delta as i8;
+    param.slice_cb_qp_offset = hdr.slice_cb_qp_offset as i8;
+    param.slice_cr_qp_offset = hdr.slice_cr_qp_offset as i8;
+    param.slice_beta_offset_div2 = hdr.slice_beta_offset_div2 as i8;
+    param.slice_tc_offset_div2 = hdr.slice_tc_offset_div2 as i8;
+    param.five_minus_max_num_merge_cand = hdr.five_minus_max_num_merge_cand as u8;
+
+    if let Some(ref table) = hdr.pred_weight_table {
+        param.luma_log2_weight_denom = table.luma_log2_weight_denom;
+        if sps.get_chroma_array_type() != 0 {
+            param.delta_chroma_log2_weight_denom = table.delta_chroma_log2_weight_denom;
+        }
+        for i in 0..15 {
+            param.delta_luma_weight_l0[i] = table.delta_luma_weight[0][i];
+            param.luma_offset_l0[i] = table.luma_offset[0][i] as i8;
+            param.delta_luma_weight_l1[i] = table.delta_luma_weight[1][i];
+            param.luma_offset_l1[i] = table.luma_offset[1][i] as i8;
+            for j in 0..2 {
+                param.delta_chroma_weight_l0[i][j] = table.delta_chroma_weight[0][i][j];
+                param.ChromaOffsetL0[i][j] = table.chroma_offset[0][i][j] as i8;
+                param.delta_chroma_weight_l1[i][j] = table.delta_chroma_weight[1][i][j];
+                param.ChromaOffsetL1[i][j] = table.chroma_offset[1][i][j] as i8;
+            }
+        }
+    }
+
+    param.LongSliceFlags = HEVCLongSliceFlags {
+        LastSliceOfPic: last_slice_of_pic as u32,
+        dependent_slice_segment_flag: hdr.dependent_slice_segment_flag as u32,
+        slice_type: hdr.slice_type as u32,
+        color_plane_id: hdr.colour_plane_id as u32,
+        slice_sao_luma_flag: hdr.slice_sao_luma_flag as u32,
+        slice_sao_chroma_flag: hdr.slice_sao_chroma_flag as u32,
+        mvd_l1_zero_flag: hdr.mvd_l1_zero_flag as u32,
+        cabac_init_flag: hdr.cabac_init_flag as u32,
+        slice_temporal_mvp_enabled_flag: hdr.slice_temporal_mvp_enabled_flag as u32,
+        slice_deblocking_filter_disabled_flag: hdr.slice_deblocking_filter_disabled_flag as u32,
+        collocated_from_l0_flag: hdr.collocated_from_l0_flag as u32,
+        slice_loop_filter_across_slices_enabled_flag:
+            hdr.slice_loop_filter_across_slices_enabled_flag as u32,
+    }.to_va()?;
+
+    Ok(param)
+}
+
+pub fn build_iq_matrix(lists: &ScalingLists) -> ffi::VAIQMatrixBufferHEVC {
+    let mut iq_matrix: ffi::VAIQMatrixBufferHEVC = zeroed();
+    iq_matrix.ScalingListDC16x16 = lists.dc_16x16;
+    iq_matrix.ScalingListDC32x32 = [lists.dc_32x32[0], lists.dc_32x32[3]];
+    for i in 0..6 {
+        iq_matrix.ScalingList4x4[i] = lists.get_raster_4x4(i);
+        iq_matrix.ScalingList8x8[i] = lists.get_raster_8x8(1, i);
+        iq_matrix.ScalingList16x16[i] = lists.get_raster_8x8(2, i);
+    }
+    for i in 0..2 {
+        iq_matrix.ScalingList32x32[i] = lists.get_raster_8x8(3, i * 3);
+    }
+    iq_matrix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi;
+    use h264::{remove_emulation_prevention, split_nal_units};
+
+    /* AUD, VPS, SPS with scaling lists, reference picture sets and VUI, PPS with tiles,
+     * SEI, then IDR, P and B slices and a dependent slice segment of a 1080p Main stream */
+    const MAIN_1080P: &'static [u8] = include_bytes!("../tests/data/hevc/main_1080p.hevc");
+    /* VPS, SPS and PPS with range extensions and an IDR slice of a 4:4:4 10-bit stream
+     * with two sub-layers */
+    const REXT_444: &'static [u8] = include_bytes!("../tests/data/hevc/rext_444.hevc");
+
+    struct Stream {
+        vps: Vec<Vps>,
+        sps: Vec<Option<Sps>>,
+        pps: Vec<Option<Pps>>,
+        sei: Vec<SeiMessage>,
+        slices: Vec<(NalHeader, SliceHeader, Vec<u8>)>,
+    }
+
+    fn parse_stream(data: &[u8]) -> Stream {
+        let mut stream = Stream {
+            vps: Vec::new(),
+            sps: vec![None; MAX_SPS_COUNT],
+            pps: vec![None; MAX_PPS_COUNT],
+            sei: Vec::new(),
+            slices: Vec::new(),
+        };
+
+        for unit in split_nal_units(data) {
+            let nal = NalHeader::parse(unit).unwrap();
+            let rbsp = remove_emulation_prevention(&unit[NAL_HEADER_BYTES..]);
+            match nal.nal_unit_type {
+                NAL_VPS => stream.vps.push(Vps::parse(&rbsp).unwrap()),
+                NAL_SPS => {
+                    let sps = Sps::parse(&rbsp).unwrap();
+                    let id = sps.seq_parameter_set_id as usize;
+                    stream.sps[id] = Some(sps);
+                }
+                NAL_PPS => {
+                    let pps = Pps::parse(&rbsp, &stream.sps).unwrap();
+                    let id = pps.pic_parameter_set_id as usize;
+                    stream.pps[id] = Some(pps);
+                }
+                NAL_PREFIX_SEI | NAL_SUFFIX_SEI => {
+                    let sps = stream.sps[0].as_ref();
+                    stream.sei.extend(parse_sei(&rbsp, sps).unwrap());
+                }
+                _ if nal.is_slice() => {
+                    let hdr = {
+                        let prev = stream.slices.last().map(|slice| &slice.1);
+                        SliceHeader::parse(&rbsp, &nal, &stream.sps, &stream.pps, prev).unwrap()
+                    };
+                    stream.slices.push((nal, hdr, unit.to_vec()));
+                }
+                _ => {}
+            }
+        }
+
+        stream
+    }
+
+    #[test]
+    fn nal_units() {
+        let types: Vec<u8> = split_nal_units(MAIN_1080P)
+            .iter()
+            .map(|unit| NalHeader::parse(unit).unwrap().nal_unit_type)
+            .collect();
+        assert_eq!(types,
+                   vec![NAL_AU_DELIMITER, NAL_VPS, NAL_SPS, NAL_PPS, NAL_PREFIX_SEI,
+                        NAL_IDR_W_RADL, NAL_TRAIL_R, NAL_TRAIL_N, NAL_TRAIL_N]);
+
+        let nal = NalHeader::parse(&[0x2a, 0x01]).unwrap();
+        assert_eq!(nal.nal_unit_type, NAL_CRA);
+        assert!(nal.is_slice() && nal.is_irap() && !nal.is_idr());
+        let nal = NalHeader::parse(&[0x48, 0x0b]).unwrap();
+        assert_eq!(nal.nal_unit_type, NAL_END_OF_SEQUENCE);
+        assert_eq!(nal.nuh_layer_id, 1);
+        assert_eq!(nal.nuh_temporal_id_plus1, 3);
+        assert!(!nal.is_slice());
+
+        /* forbidden_zero_bit and nuh_temporal_id_plus1 of 0 */
+        assert!(NalHeader::parse(&[0x80, 0x01]).is_err());
+        assert!(NalHeader::parse(&[0x02, 0x00]).is_err());
+    }
+
+    #[test]
+    fn main_sps() {
+        let stream = parse_stream(MAIN_1080P);
+        let vps = &stream.vps[0];
+        assert_eq!(vps.max_sub_layers_minus1, 0);
+        assert_eq!(vps.profile_tier_level.general_profile_idc, 1);
+        assert_eq!(vps.sub_layer_ordering[0].max_dec_pic_buffering_minus1, 4);
+        assert_eq!((vps.num_units_in_tick, vps.time_scale), (1001, 60000));
+
+        let sps = stream.sps[0].as_ref().unwrap();
+        let ptl = &sps.profile_tier_level;
+        assert_eq!(ptl.general_profile_idc, 1);
+        assert_eq!(ptl.general_profile_compatibility_flags, 0x60000000);
+        assert!(ptl.general_progressive_source_flag && ptl.general_frame_only_constraint_flag);
+        assert_eq!(ptl.general_level_idc, 120);
+        assert_eq!(sps.chroma_format_idc, 1);
+        assert_eq!((sps.pic_width_in_luma_samples, sps.pic_height_in_luma_samples),
+                   (1920, 1088));
+        assert_eq!(sps.get_size(), (1920, 1080));
+        assert_eq!(sps.get_ctb_log2_size(), 6);
+        assert_eq!((sps.get_pic_width_in_ctbs(), sps.get_pic_height_in_ctbs()), (30, 17));
+        assert_eq!(sps.get_max_pic_order_cnt_lsb(), 256);
+        assert_eq!(sps.get_rt_format(), Ok(ffi::VA_RT_FORMAT_YUV420));
+        assert_eq!(sps.get_va_profile(), Ok(ffi::VAProfileHEVCMain));
+        assert_eq!(sps.get_dpb_size(), 5);
+        assert_eq!(sps.get_num_reorder_pics(), 2);
+        assert!(sps.amp_enabled_flag && sps.sample_adaptive_offset_enabled_flag);
+        assert!(sps.pcm_enabled_flag && sps.pcm_loop_filter_disabled_flag);
+        assert_eq!(sps.pcm_sample_bit_depth_luma_minus1, 7);
+        assert_eq!(sps.log2_diff_max_min_pcm_luma_coding_block_size, 2);
+        assert!(sps.sps_temporal_mvp_enabled_flag && sps.strong_intra_smoothing_enabled_flag);
+        assert!(sps.range_extension.is_none());
+
+        /* The last set is predicted from the second one */
+        let sets = &sps.short_term_ref_pic_sets;
+        assert_eq!(sets.len(), 3);
+        assert_eq!(sets[0].delta_poc_s0, vec![-1, -3]);
+        assert!(sets[0].delta_poc_s1.is_empty());
+        assert_eq!((&sets[1].delta_poc_s0, &sets[1].delta_poc_s1), (&vec![-1], &vec![2]));
+        assert_eq!(sets[1].used_by_curr_pic_s1, vec![false]);
+        assert_eq!(sets[2].delta_poc_s0, vec![-1, -2]);
+        assert_eq!(sets[2].used_by_curr_pic_s0, vec![true, true]);
+        assert_eq!(sets[2].delta_poc_s1, vec![1]);
+        assert_eq!(sets[2].used_by_curr_pic_s1, vec![false]);
+        assert_eq!(sets[2].get_num_used_by_curr_pic(), 2);
+
+        assert!(sps.long_term_ref_pics_present_flag);
+        assert_eq!(sps.lt_ref_pic_poc_lsb_sps, vec![100, 200]);
+        assert_eq!(sps.used_by_curr_pic_lt_sps_flag, vec![true, false]);
+
+        let vui = sps.vui.as_ref().unwrap();
+        assert_eq!(vui.aspect_ratio_idc, 1);
+        assert_eq!(vui.video_format, 5);
+        assert_eq!((vui.colour_primaries, vui.transfer_characteristics, vui.matrix_coefficients),
+                   (9, 16, 9));
+        assert!(vui.frame_field_info_present_flag);
+        assert_eq!(vui.default_display_window, Some([8, 8, 0, 0]));
+        assert_eq!((vui.num_units_in_tick, vui.time_scale), (1001, 60000));
+        let hrd = vui.hrd.as_ref().unwrap();
+        assert!(hrd.nal_hrd_parameters_present_flag && !hrd.vcl_hrd_parameters_present_flag);
+        assert!(hrd.has_cpb_dpb_delays());
+        assert_eq!(hrd.initial_cpb_removal_delay_length_minus1, 23);
+        assert_eq!(hrd.au_cpb_removal_delay_length_minus1, 15);
+        assert_eq!(hrd.dpb_output_delay_length_minus1, 4);
+        assert_eq!(hrd.cpb_cnt_minus1, vec![0]);
+    }
+
+    #[test]
+    fn scaling_lists() {
+        let stream = parse_stream(MAIN_1080P);
+        let lists = &stream.sps[0].as_ref().unwrap().scaling_lists;
+        let mut ramp = [0; 16];
+        for (i, value) in ramp.iter_mut().enumerate() {
+            *value = 16 + i as u8;
+        }
+        /* Sent, copied from the previous matrix, then the default ones */
+        assert_eq!(lists.list_4x4[0], ramp);
+        assert_eq!(lists.list_4x4[1], ramp);
+        assert_eq!(lists.list_4x4[2], [16; 16]);
+        assert_eq!(&lists.list_8x8[0][..], &DEFAULT_8X8_INTRA[..]);
+        assert_eq!(&lists.list_8x8[5][..], &DEFAULT_8X8_INTER[..]);
+        assert_eq!(&lists.list_16x16[0][..], &[24; 64][..]);
+        assert_eq!((lists.dc_16x16[0], lists.dc_16x16[1], lists.dc_16x16[2]), (20, 20, 16));
+        assert_eq!(&lists.list_16x16[1][..], &[24; 64][..]);
+        /* The second 32x32 matrix refers to the first one, three matrices before */
+        assert_eq!(&lists.list_32x32[3][..], &DEFAULT_8X8_INTRA[..]);
+        assert_eq!(&lists.list_32x32[1][..], &[24; 64][..]);
+        assert_eq!(lists.dc_32x32[1], 20);
+
+        let raster = lists.get_raster_4x4(0);
+        assert_eq!(&raster[..5], &[16, 18, 21, 25, 17]);
+        let raster = lists.get_raster_8x8(3, 3);
+        assert_eq!((raster[0], raster[1], raster[8], raster[63]), (16, 16, 16, 115));
+
+        let flat = ScalingLists::new_flat();
+        assert_eq!(&flat.list_32x32[0][..], &[16; 64][..]);
+    }
+
+    #[test]
+    fn main_pps() {
+        let stream = parse_stream(MAIN_1080P);
+        let pps = stream.pps[0].as_ref().unwrap();
+        assert!(pps.dependent_slice_segments_enabled_flag && pps.output_flag_present_flag);
+        assert_eq!(pps.num_extra_slice_header_bits, 1);
+        assert!(pps.sign_data_hiding_enabled_flag && pps.cabac_init_present_flag);
+        assert_eq!(pps.num_ref_idx_l0_default_active_minus1, 2);
+        assert_eq!(pps.init_qp_minus26, -4);
+        assert!(pps.transform_skip_enabled_flag && pps.cu_qp_delta_enabled_flag);
+        assert_eq!(pps.diff_cu_qp_delta_depth, 1);
+        assert_eq!((pps.pps_cb_qp_offset, pps.pps_cr_qp_offset), (-2, 3));
+        assert!(pps.weighted_pred_flag && !pps.weighted_bipred_flag);
+        assert!(pps.tiles_enabled_flag && !pps.uniform_spacing_flag);
+        /* The last column and row take the remaining CTBs */
+        assert_eq!(pps.column_width_minus1, vec![7, 11, 9]);
+        assert_eq!(pps.row_height_minus1, vec![4, 11]);
+        assert!(pps.loop_filter_across_tiles_enabled_flag);
+        assert!(pps.deblocking_filter_override_enabled_flag);
+        assert!(!pps.pps_deblocking_filter_disabled_flag);
+        assert_eq!((pps.pps_beta_offset_div2, pps.pps_tc_offset_div2), (2, -1));
+        assert!(pps.lists_modification_present_flag);
+        assert_eq!(pps.log2_parallel_merge_level_minus2, 1);
+        assert!(pps.slice_segment_header_extension_present_flag);
+        assert!(pps.range_extension.is_none());
+        assert_eq!(pps.scaling_lists.list_4x4[0][15], 31);
+    }
+
+    #[test]
+    fn sei_messages() {
+        let stream = parse_stream(MAIN_1080P);
+        assert_eq!(stream.sei.len(), 5);
+
+        match stream.sei[0] {
+            SeiMessage::PicTiming(ref pt) => {
+                assert_eq!(pt.pic_struct, Some(1));
+                assert_eq!(pt.source_scan_type, 1);
+                assert!(!pt.duplicate_flag);
+                assert_eq!(pt.au_cpb_removal_delay_minus1, Some(3));
+                assert_eq!(pt.pic_dpb_output_delay, Some(2));
+            }
+            ref msg => panic!("unexpected {:?}", msg),
+        }
+        match stream.sei[1] {
+            SeiMessage::RecoveryPoint(ref rp) => {
+                assert_eq!(rp.recovery_poc_cnt, 4);
+                assert!(rp.exact_match_flag && !rp.broken_link_flag);
+            }
+            ref msg => panic!("unexpected {:?}", msg),
+        }
+        match stream.sei[2] {
+            SeiMessage::MasteringDisplayColourVolume(ref mdcv) => {
+                assert_eq!(mdcv.display_primaries,
+                           [(13250, 34500), (7500, 3000), (34000, 16000)]);
+                assert_eq!(mdcv.white_point, (15635, 16450));
+                assert_eq!(mdcv.max_display_mastering_luminance, 10000000);
+                assert_eq!(mdcv.min_display_mastering_luminance, 50);
+            }
+            ref msg => panic!("unexpected {:?}", msg),
+        }
+        match stream.sei[3] {
+            SeiMessage::ContentLightLevel(cll) => {
+                assert_eq!(cll,
+                           ContentLightLevel {
+                               max_content_light_level: 1000,
+                               max_pic_average_light_level: 400,
+                           });
+            }
+            ref msg => panic!("unexpected {:?}", msg),
+        }
+        match stream.sei[4] {
+            SeiMessage::UserDataUnregistered(uuid, ref data) => {
+                assert_eq!(uuid[0], 0x10);
+                assert_eq!(uuid[15], 0x1f);
+                assert_eq!(data, b"x265");
+            }
+            ref msg => panic!("unexpected {:?}", msg),
+        }
+
+        /* Without the SPS the picture timing layout is unknown */
+        let msgs = parse_sei(&[1, 1, 0x50, 0x80], None).unwrap();
+        match msgs[0] {
+            SeiMessage::Other(SEI_PIC_TIMING, ref data) => assert_eq!(data, &vec![0x50]),
+            ref msg => panic!("unexpected {:?}", msg),
+        }
+        assert!(parse_sei(&[6, 4, 0x80], None).is_err());
+    }
+
+    #[test]
+    fn main_slices() {
+        let stream = parse_stream(MAIN_1080P);
+        assert_eq!(stream.slices.len(), 4);
+
+        let (ref nal, ref idr, _) = stream.slices[0];
+        assert!(nal.is_idr());
+        assert!(idr.first_slice_segment_in_pic_flag && !idr.no_output_of_prior_pics_flag);
+        assert_eq!(idr.slice_type, SLICE_TYPE_I);
+        assert!(idr.pic_output_flag);
+        assert!(idr.slice_sao_luma_flag && idr.slice_sao_chroma_flag);
+        assert_eq!(idr.slice_qp_delta, 3);
+        assert_eq!((idr.slice_cb_qp_offset, idr.slice_cr_qp_offset), (1, -1));
+        assert!(!idr.slice_deblocking_filter_disabled_flag);
+        assert_eq!((idr.slice_beta_offset_div2, idr.slice_tc_offset_div2), (-2, 3));
+        assert!(!idr.slice_loop_filter_across_slices_enabled_flag);
+        assert_eq!(idr.entry_point_offset_minus1, vec![0, 0, 0, 1, 0]);
+        assert_eq!(idr.get_num_pic_total_curr(), 0);
+        assert_eq!(idr.header_size, 120);
+
+        let (ref nal, ref p, _) = stream.slices[1];
+        assert_eq!(nal.nal_unit_type, NAL_TRAIL_R);
+        assert_eq!(p.slice_type, SLICE_TYPE_P);
+        assert!(!p.pic_output_flag);
+        assert_eq!(p.slice_pic_order_cnt_lsb, 8);
+        assert!(p.short_term_ref_pic_set_sps_flag);
+        assert_eq!(p.short_term_ref_pic_set_idx, 2);
+        assert_eq!(p.short_term_ref_pic_set,
+                   stream.sps[0].as_ref().unwrap().short_term_ref_pic_sets[2]);
+        assert_eq!(p.st_rps_bits, 0);
+        assert_eq!((p.num_long_term_sps, p.num_long_term_pics), (1, 2));
+        assert_eq!(p.poc_lsb_lt, vec![200, 50, 60]);
+        assert_eq!(p.used_by_curr_pic_lt, vec![false, true, false]);
+        /* DeltaPocMsbCycleLt accumulates from the first picture not taken from the SPS */
+        assert_eq!(p.delta_poc_msb_cycle_lt, vec![1, 2, 3]);
+        assert_eq!(p.get_num_pic_total_curr(), 3);
+        assert!(p.slice_temporal_mvp_enabled_flag);
+        assert!(!p.slice_sao_luma_flag && p.slice_sao_chroma_flag);
+        assert_eq!(p.num_ref_idx_l0_active_minus1, 1);
+        assert_eq!(p.list_entry[0], Some(vec![2, 0]));
+        assert!(p.list_entry[1].is_none());
+        assert!(p.cabac_init_flag && p.collocated_from_l0_flag);
+        assert_eq!(p.collocated_ref_idx, 1);
+        let table = p.pred_weight_table.as_ref().unwrap();
+        assert_eq!(table.luma_log2_weight_denom, 6);
+        assert_eq!(table.delta_chroma_log2_weight_denom, -1);
+        assert_eq!((table.delta_luma_weight[0][0], table.luma_offset[0][0]), (-3, 5));
+        assert_eq!((table.delta_luma_weight[0][1], table.luma_offset[0][1]), (0, 0));
+        assert_eq!(table.delta_chroma_weight[0][0], [0, 0]);
+        assert_eq!(table.delta_chroma_weight[0][1], [2, -1]);
+        assert_eq!(table.chroma_offset[0][1], [-18, 24]);
+        assert_eq!(p.five_minus_max_num_merge_cand, 2);
+        assert_eq!(p.slice_qp_delta, -1);
+        /* Deblocking parameters of the PPS */
+        assert!(!p.slice_deblocking_filter_disabled_flag);
+        assert_eq!((p.slice_beta_offset_div2, p.slice_tc_offset_div2), (2, -1));
+        assert!(p.slice_loop_filter_across_slices_enabled_flag);
+        assert!(p.entry_point_offset_minus1.is_empty());
+        assert_eq!(p.header_size, 136);
+
+        let (_, ref b, _) = stream.slices[2];
+        assert_eq!(b.slice_type, SLICE_TYPE_B);
+        assert_eq!(b.slice_pic_order_cnt_lsb, 6);
+        assert!(!b.short_term_ref_pic_set_sps_flag);
+        /* Predicted from the first set of the SPS with a deltaRps of 2 */
+        assert_eq!(b.short_term_ref_pic_set.delta_poc_s0, vec![-1]);
+        assert_eq!(b.short_term_ref_pic_set.delta_poc_s1, vec![1, 2]);
+        assert_eq!(b.short_term_ref_pic_set.used_by_curr_pic_s1, vec![true, false]);
+        assert_eq!(b.st_rps_bits, 12);
+        assert!(b.poc_lsb_lt.is_empty());
+        assert_eq!((b.num_ref_idx_l0_active_minus1, b.num_ref_idx_l1_active_minus1), (0, 1));
+        assert_eq!(b.list_entry[0], None);
+        assert_eq!(b.list_entry[1], Some(vec![1, 0]));
+        assert!(b.mvd_l1_zero_flag && !b.cabac_init_flag && !b.collocated_from_l0_flag);
+        assert_eq!(b.collocated_ref_idx, 1);
+        assert!(b.pred_weight_table.is_none());
+        assert_eq!((b.slice_cb_qp_offset, b.slice_cr_qp_offset), (-3, 2));
+        assert!(b.slice_deblocking_filter_disabled_flag);
+        assert!(!b.slice_loop_filter_across_slices_enabled_flag);
+        assert_eq!(b.header_size, 72);
+
+        /* The dependent segment keeps the values of the B slice */
+        let (_, ref dep, _) = stream.slices[3];
+        assert!(!dep.first_slice_segment_in_pic_flag && dep.dependent_slice_segment_flag);
+        assert_eq!(dep.slice_segment_address, 255);
+        assert_eq!(dep.slice_type, SLICE_TYPE_B);
+        assert_eq!(dep.slice_pic_order_cnt_lsb, 6);
+        assert_eq!(dep.list_entry[1], Some(vec![1, 0]));
+        assert_eq!(dep.entry_point_offset_minus1, vec![7]);
+        assert_eq!(dep.header_size, 32);
+
+        let (ref nal, _, ref unit) = stream.slices[3];
+        let rbsp = remove_emulation_prevention(&unit[NAL_HEADER_BYTES..]);
+        assert!(SliceHeader::parse(&rbsp, nal, &stream.sps, &stream.pps, None).is_err());
+    }
+
+    #[test]
+    fn rext_parameter_sets() {
+        let stream = parse_stream(REXT_444);
+        assert_eq!(stream.vps[0].profile_tier_level.general_profile_idc, 4);
+
+        let sps = stream.sps[0].as_ref().unwrap();
+        assert_eq!(sps.max_sub_layers_minus1, 1);
+        assert_eq!(sps.profile_tier_level.general_level_idc, 93);
+        assert_eq!(sps.profile_tier_level.sub_layer_level_idc, vec![Some(90)]);
+        assert_eq!(sps.chroma_format_idc, 3);
+        assert!(!sps.separate_colour_plane_flag);
+        assert_eq!(sps.get_chroma_array_type(), 3);
+        assert_eq!((sps.bit_depth_luma_minus8, sps.bit_depth_chroma_minus8), (2, 2));
+        assert_eq!(sps.get_size(), (416, 240));
+        assert_eq!((sps.get_pic_width_in_ctbs(), sps.get_pic_height_in_ctbs()), (26, 15));
+        /* Only the highest sub-layer is sent */
+        assert_eq!(sps.sub_layer_ordering.len(), 2);
+        assert_eq!(sps.sub_layer_ordering[0].max_dec_pic_buffering_minus1, 2);
+        assert_eq!(sps.get_dpb_size(), 3);
+        assert!(sps.scaling_list_enabled_flag);
+        assert_eq!(&sps.scaling_lists.list_8x8[0][..], &DEFAULT_8X8_INTRA[..]);
+        assert!(sps.short_term_ref_pic_sets.is_empty());
+        assert!(sps.vui.is_none());
+        let ext = sps.range_extension.unwrap();
+        assert!(ext.transform_skip_rotation_enabled_flag && !ext.transform_skip_context_enabled_flag);
+        assert!(ext.implicit_rdpcm_enabled_flag && !ext.explicit_rdpcm_enabled_flag);
+        assert!(!ext.extended_precision_processing_flag && ext.intra_smoothing_disabled_flag);
+        assert!(ext.high_precision_offsets_enabled_flag);
+        assert!(!ext.persistent_rice_adaptation_enabled_flag);
+        assert!(ext.cabac_bypass_alignment_enabled_flag);
+
+        let pps = stream.pps[1].as_ref().unwrap();
+        assert!(pps.tiles_enabled_flag && pps.entropy_coding_sync_enabled_flag);
+        assert!(pps.uniform_spacing_flag);
+        assert_eq!(pps.column_width_minus1, vec![5, 6, 5, 6]);
+        assert_eq!(pps.row_height_minus1, vec![6, 7]);
+        assert!(!pps.deblocking_filter_control_present_flag);
+        let mut list = [0; 16];
+        for (i, value) in list.iter_mut().enumerate() {
+            *value = 12 + 2 * i as u8;
+        }
+        assert_eq!(pps.scaling_lists.list_4x4[3], list);
+        assert_eq!(pps.scaling_lists.list_4x4[0], [16; 16]);
+        let ext = pps.range_extension.as_ref().unwrap();
+        assert_eq!(ext.log2_max_transform_skip_block_size_minus2, 1);
+        assert!(ext.cross_component_prediction_enabled_flag);
+        assert!(ext.chroma_qp_offset_list_enabled_flag);
+        assert_eq!(ext.diff_cu_chroma_qp_offset_depth, 1);
+        assert_eq!(ext.cb_qp_offset_list, vec![-2, 4]);
+        assert_eq!(ext.cr_qp_offset_list, vec![3, -5]);
+        assert_eq!((ext.log2_sao_offset_scale_luma, ext.log2_sao_offset_scale_chroma), (1, 2));
+
+        let (ref nal, ref idr, _) = stream.slices[0];
+        assert_eq!(nal.nal_unit_type, NAL_IDR_N_LP);
+        assert!(idr.no_output_of_prior_pics_flag);
+        assert_eq!(idr.pic_parameter_set_id, 1);
+        assert_eq!(idr.slice_type, SLICE_TYPE_I);
+        assert!(idr.cu_chroma_qp_offset_enabled_flag);
+        assert!(!idr.slice_loop_filter_across_slices_enabled_flag);
+        assert_eq!(idr.entry_point_offset_minus1, vec![10, 20]);
+        assert_eq!(idr.header_size, 40);
+    }
+
+    #[test]
+    fn va_buffers() {
+        let stream = parse_stream(MAIN_1080P);
+        let sps = stream.sps[0].as_ref().unwrap();
+        let pps = stream.pps[0].as_ref().unwrap();
+
+        let (ref nal, ref p, ref unit) = stream.slices[1];
+        let curr_pic = build_va_picture(5, 8, 0);
+        let refs = [build_va_picture(3, 7, ffi::VA_PICTURE_HEVC_RPS_ST_CURR_BEFORE),
+                    build_va_picture(4, 6, ffi::VA_PICTURE_HEVC_RPS_ST_CURR_BEFORE)];
+        let param = build_picture_param(sps, pps, nal, p, curr_pic, &refs).unwrap();
+        assert_eq!(param.CurrPic.picture_id, 5);
+        assert_eq!(param.ReferenceFrames[1].picture_id, 4);
+        assert_eq!(param.ReferenceFrames[2].picture_id, ffi::VA_INVALID_SURFACE);
+        assert_eq!(param.ReferenceFrames[2].flags, ffi::VA_PICTURE_HEVC_INVALID);
+        assert_eq!((param.pic_width_in_luma_samples, param.pic_height_in_luma_samples),
+                   (1920, 1088));
+        assert_eq!(param.sps_max_dec_pic_buffering_minus1, 4);
+        assert_eq!(param.log2_diff_max_min_luma_coding_block_size, 3);
+        assert_eq!(param.init_qp_minus26, -4);
+        assert_eq!((param.num_tile_columns_minus1, param.num_tile_rows_minus1), (2, 1));
+        assert_eq!(&param.column_width_minus1[..4], &[7, 11, 9, 0]);
+        assert_eq!(&param.row_height_minus1[..2], &[4, 11]);
+        assert_eq!(param.num_short_term_ref_pic_sets, 3);
+        assert_eq!(param.num_long_term_ref_pic_sps, 2);
+        assert_eq!(param.num_extra_slice_header_bits, 1);
+        let bits = HEVCPicFields::from_va(&param.pic_fields);
+        assert_eq!(bits.chroma_format_idc, 1);
+        assert_eq!(bits.tiles_enabled_flag, 1);
+        assert_eq!(bits.pcm_enabled_flag, 1);
+        assert_eq!(bits.NoPicReorderingFlag, 0);
+        let bits = HEVCSliceParsingFields::from_va(&param.slice_parsing_fields);
+        assert_eq!(bits.lists_modification_present_flag, 1);
+        assert_eq!(bits.IdrPicFlag, 0);
+        assert_eq!(bits.RapPicFlag, 0);
+        assert_eq!(bits.IntraPicFlag, 0);
+
+        let (ref nal, ref b, _) = stream.slices[2];
+        let param = build_picture_param(sps, pps, nal, b, curr_pic, &refs).unwrap();
+        assert_eq!(param.st_rps_bits, 12);
+
+        let ref_pic_list = [[0xff; 15]; 2];
+        let slice = build_slice_param(sps, unit, p, &ref_pic_list, false).unwrap();
+        assert_eq!(slice.slice_data_size as usize, unit.len());
+        assert_eq!(slice.slice_data_byte_offset, 19);
+        assert_eq!(slice.collocated_ref_idx, 1);
+        assert_eq!(slice.num_ref_idx_l0_active_minus1, 1);
+        assert_eq!(slice.luma_log2_weight_denom, 6);
+        assert_eq!(slice.delta_chroma_log2_weight_denom, -1);
+        assert_eq!((slice.delta_luma_weight_l0[0], slice.luma_offset_l0[0]), (-3, 5));
+        assert_eq!(slice.delta_chroma_weight_l0[1], [2, -1]);
+        assert_eq!(slice.ChromaOffsetL0[1], [-18, 24]);
+        assert_eq!(slice.five_minus_max_num_merge_cand, 2);
+        let fields = HEVCLongSliceFlags::from_va(&slice.LongSliceFlags);
+        assert_eq!(fields.slice_type, SLICE_TYPE_P as u32);
+        assert_eq!(fields.LastSliceOfPic, 0);
+        assert_eq!(fields.slice_sao_chroma_flag, 1);
+        assert_eq!(fields.slice_temporal_mvp_enabled_flag, 1);
+
+        /* The IDR slice header holds an emulation prevention byte */
+        let (ref nal, ref idr, ref unit) = stream.slices[0];
+        let slice = build_slice_param(sps, unit, idr, &ref_pic_list, true).unwrap();
+        assert_eq!(slice.slice_data_byte_offset, 18);
+        assert_eq!(slice.collocated_ref_idx, 0xff);
+        assert_eq!(HEVCLongSliceFlags::from_va(&slice.LongSliceFlags).LastSliceOfPic, 1);
+        let param = build_picture_param(sps, pps, nal, idr, curr_pic, &[]).unwrap();
+        let bits = HEVCSliceParsingFields::from_va(&param.slice_parsing_fields);
+        assert_eq!((bits.IdrPicFlag, bits.RapPicFlag, bits.IntraPicFlag), (1, 1, 1));
+
+        let iq_matrix = build_iq_matrix(&pps.scaling_lists);
+        assert_eq!(&iq_matrix.ScalingList4x4[0][..5], &[16, 18, 21, 25, 17]);
+        assert_eq!(iq_matrix.ScalingListDC16x16, [20, 20, 16, 16, 16, 16]);
+        assert_eq!(iq_matrix.ScalingListDC32x32, [16, 16]);
+        assert_eq!(iq_matrix.ScalingList32x32[1][63], 115);
+
+        let stream = parse_stream(REXT_444);
+        let iq_matrix = build_iq_matrix(&stream.pps[1].as_ref().unwrap().scaling_lists);
+        assert_eq!(&iq_matrix.ScalingList4x4[3][..5], &[12, 16, 22, 30, 14]);
+        assert_eq!(iq_matrix.ScalingList8x8[3][63], 91);
+    }
+}
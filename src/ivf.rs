@@ -0,0 +1,86 @@
+// Copyright (c) 2017 zzoon <zzoon@igalia.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/* Reader of the IVF container used to carry VP8, VP9 and AV1 elementary streams: a 32 bytes
+ * file header followed by frames, each preceded by a 12 bytes frame header */
+
+pub const FILE_HEADER_BYTES: usize = 32;
+pub const FRAME_HEADER_BYTES: usize = 12;
+
+fn read_u16(data: &[u8]) -> u16 {
+    data[0] as u16 | (data[1] as u16) << 8
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    read_u16(data) as u32 | (read_u16(&data[2..]) as u32) << 16
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct IvfHeader {
+    pub version: u16,
+    pub fourcc: [u8; 4],
+    pub width: u16,
+    pub height: u16,
+    /* Time base of the presentation timestamps is time_scale / frame_rate */
+    pub frame_rate: u32,
+    pub time_scale: u32,
+    pub num_frames: u32,
+}
+
+impl IvfHeader {
+    pub fn parse(data: &[u8]) -> Result<IvfHeader, ()> {
+        if data.len() < FILE_HEADER_BYTES || &data[0..4] != b"DKIF" {
+            return Err(());
+        }
+        let header_size = read_u16(&data[6..]) as usize;
+        if header_size < FILE_HEADER_BYTES {
+            return Err(());
+        }
+
+        let mut fourcc = [0; 4];
+        fourcc.copy_from_slice(&data[8..12]);
+        Ok(IvfHeader {
+            version: read_u16(&data[4..]),
+            fourcc: fourcc,
+            width: read_u16(&data[12..]),
+            height: read_u16(&data[14..]),
+            frame_rate: read_u32(&data[16..]),
+            time_scale: read_u32(&data[20..]),
+            num_frames: read_u32(&data[24..]),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IvfFrame<'a> {
+    pub pts: u64,
+    pub data: &'a [u8],
+}
+
+/* Splits a whole IVF file into its frames, stopping at the first truncated one */
+pub fn parse_ivf<'a>(data: &'a [u8]) -> Result<(IvfHeader, Vec<IvfFrame<'a>>), ()> {
+    let header = IvfHeader::parse(data)?;
+    let mut frames = Vec::new();
+    let mut pos = read_u16(&data[6..]) as usize;
+
+    while pos + FRAME_HEADER_BYTES <= data.len() {
+        let size = read_u32(&data[pos..]) as usize;
+        let pts = read_u32(&data[pos + 4..]) as u64 | (read_u32(&data[pos + 8..]) as u64) << 32;
+        pos += FRAME_HEADER_BYTES;
+        if size > data.len() - pos {
+            break;
+        }
+        frames.push(IvfFrame {
+            pts: pts,
+            data: &data[pos..pos + size],
+        });
+        pos += size;
+    }
+
+    Ok((header, frames))
+}
@@ -0,0 +1,732 @@
+// Copyright (c) 2017 zzoon <zzoon@igalia.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/* Parser for the JPEG markers needed to drive VA baseline decoding: frame and scan
+ * headers, quantisation and Huffman tables, restart intervals and the JFIF and EXIF
+ * application segments, and their conversion into the VA buffers. */
+
+use bitfields::zeroed;
+use decoder::DecodeError;
+use ffi;
+
+pub const MARKER_SOF0: u8 = 0xc0;
+pub const MARKER_SOF1: u8 = 0xc1;
+pub const MARKER_SOF2: u8 = 0xc2;
+pub const MARKER_SOF3: u8 = 0xc3;
+pub const MARKER_DHT: u8 = 0xc4;
+pub const MARKER_SOF5: u8 = 0xc5;
+pub const MARKER_SOF6: u8 = 0xc6;
+pub const MARKER_SOF7: u8 = 0xc7;
+pub const MARKER_SOF9: u8 = 0xc9;
+pub const MARKER_DAC: u8 = 0xcc;
+pub const MARKER_SOF15: u8 = 0xcf;
+pub const MARKER_RST0: u8 = 0xd0;
+pub const MARKER_RST7: u8 = 0xd7;
+pub const MARKER_SOI: u8 = 0xd8;
+pub const MARKER_EOI: u8 = 0xd9;
+pub const MARKER_SOS: u8 = 0xda;
+pub const MARKER_DQT: u8 = 0xdb;
+pub const MARKER_DRI: u8 = 0xdd;
+pub const MARKER_APP0: u8 = 0xe0;
+pub const MARKER_APP1: u8 = 0xe1;
+pub const MARKER_TEM: u8 = 0x01;
+
+/* Annex K.3 tables, used when a frame comes without DHT (e.g. Motion JPEG) */
+pub const DEFAULT_DC_LUMA_CODES: [u8; 16] = [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0];
+pub const DEFAULT_DC_CHROMA_CODES: [u8; 16] = [0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0];
+pub const DEFAULT_DC_VALUES: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+pub const DEFAULT_AC_LUMA_CODES: [u8; 16] = [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7d];
+pub const DEFAULT_AC_LUMA_VALUES: [u8; 162] =
+    [0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61,
+     0x07, 0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xa1, 0x08, 0x23, 0x42, 0xb1, 0xc1, 0x15, 0x52,
+     0xd1, 0xf0, 0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0a, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x25,
+     0x26, 0x27, 0x28, 0x29, 0x2a, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45,
+     0x46, 0x47, 0x48, 0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64,
+     0x65, 0x66, 0x67, 0x68, 0x69, 0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x83,
+     0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99,
+     0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6,
+     0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3,
+     0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe1, 0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8,
+     0xe9, 0xea, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa];
+pub const DEFAULT_AC_CHROMA_CODES: [u8; 16] = [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77];
+pub const DEFAULT_AC_CHROMA_VALUES: [u8; 162] =
+    [0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61,
+     0x71, 0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91, 0xa1, 0xb1, 0xc1, 0x09, 0x23, 0x33,
+     0x52, 0xf0, 0x15, 0x62, 0x72, 0xd1, 0x0a, 0x16, 0x24, 0x34, 0xe1, 0x25, 0xf1, 0x17, 0x18,
+     0x19, 0x1a, 0x26, 0x27, 0x28, 0x29, 0x2a, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44,
+     0x45, 0x46, 0x47, 0x48, 0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63,
+     0x64, 0x65, 0x66, 0x67, 0x68, 0x69, 0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a,
+     0x82, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97,
+     0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4,
+     0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca,
+     0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7,
+     0xe8, 0xe9, 0xea, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JpegError {
+    InvalidStream,
+    /* Progressive DCT, SOF2 and SOF6 */
+    Progressive,
+    /* Arithmetic coding, SOF9 to SOF15 or a DAC marker */
+    Arithmetic,
+    /* Lossless, SOF3 and SOF7 */
+    Lossless,
+    /* Hierarchical sequential, SOF5 */
+    Hierarchical,
+    /* Anything else outside of the baseline constraints, e.g. 12-bit samples */
+    Unsupported,
+}
+
+impl From<JpegError> for DecodeError {
+    fn from(e: JpegError) -> DecodeError {
+        match e {
+            JpegError::InvalidStream => DecodeError::InvalidStream,
+            e => DecodeError::Jpeg(e),
+        }
+    }
+}
+
+fn read_u16(data: &[u8], pos: usize) -> Result<u16, JpegError> {
+    if pos + 2 > data.len() {
+        return Err(JpegError::InvalidStream);
+    }
+    Ok(((data[pos] as u16) << 8) | data[pos + 1] as u16)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Component {
+    pub id: u8,
+    pub h_sampling_factor: u8,
+    pub v_sampling_factor: u8,
+    pub quant_table: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct FrameHeader {
+    /* SOF0 or SOF1 */
+    pub marker: u8,
+    pub width: u32,
+    pub height: u32,
+    pub components: Vec<Component>,
+}
+
+impl FrameHeader {
+    pub fn parse(marker: u8, data: &[u8]) -> Result<FrameHeader, JpegError> {
+        if data.len() < 6 {
+            return Err(JpegError::InvalidStream);
+        }
+        if data[0] != 8 {
+            return Err(JpegError::Unsupported);
+        }
+        let height = read_u16(data, 1)? as u32;
+        let width = read_u16(data, 3)? as u32;
+        let num_components = data[5] as usize;
+        /* A zero height would need the DNL marker */
+        if width == 0 || height == 0 {
+            return Err(JpegError::Unsupported);
+        }
+        if num_components == 0 || num_components > 4 || data.len() < 6 + num_components * 3 {
+            return Err(JpegError::InvalidStream);
+        }
+
+        let mut components = Vec::with_capacity(num_components);
+        for i in 0..num_components {
+            let c = &data[6 + i * 3..9 + i * 3];
+            let component = Component {
+                id: c[0],
+                h_sampling_factor: c[1] >> 4,
+                v_sampling_factor: c[1] & 0xf,
+                quant_table: c[2],
+            };
+            if component.h_sampling_factor == 0 || component.h_sampling_factor > 4 ||
+               component.v_sampling_factor == 0 ||
+               component.v_sampling_factor > 4 || component.quant_table > 3 {
+                return Err(JpegError::InvalidStream);
+            }
+            if components.iter().any(|c: &Component| c.id == component.id) {
+                return Err(JpegError::InvalidStream);
+            }
+            components.push(component);
+        }
+
+        Ok(FrameHeader {
+            marker: marker,
+            width: width,
+            height: height,
+            components: components,
+        })
+    }
+
+    pub fn get_max_sampling_factors(&self) -> (u32, u32) {
+        let h = self.components.iter().map(|c| c.h_sampling_factor).max().unwrap_or(1);
+        let v = self.components.iter().map(|c| c.v_sampling_factor).max().unwrap_or(1);
+        (h as u32, v as u32)
+    }
+
+    /* Picks the surface chroma format from the sampling factors */
+    pub fn get_rt_format(&self) -> Result<u32, JpegError> {
+        let c = &self.components;
+        if c.len() == 1 {
+            return Ok(ffi::VA_RT_FORMAT_YUV400);
+        }
+        if c.len() != 3 || c[1].h_sampling_factor != 1 || c[1].v_sampling_factor != 1 ||
+           c[2].h_sampling_factor != 1 || c[2].v_sampling_factor != 1 {
+            return Err(JpegError::Unsupported);
+        }
+        match (c[0].h_sampling_factor, c[0].v_sampling_factor) {
+            (1, 1) => Ok(ffi::VA_RT_FORMAT_YUV444),
+            (2, 1) | (1, 2) => Ok(ffi::VA_RT_FORMAT_YUV422),
+            (2, 2) => Ok(ffi::VA_RT_FORMAT_YUV420),
+            (4, 1) => Ok(ffi::VA_RT_FORMAT_YUV411),
+            _ => Err(JpegError::Unsupported),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HuffmanTable {
+    pub codes: [u8; 16],
+    pub values: Vec<u8>,
+}
+
+impl HuffmanTable {
+    pub fn new(codes: &[u8; 16], values: &[u8]) -> HuffmanTable {
+        HuffmanTable {
+            codes: *codes,
+            values: values.to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ScanComponent {
+    pub selector: u8,
+    pub dc_table: u8,
+    pub ac_table: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct Scan {
+    pub components: Vec<ScanComponent>,
+    pub restart_interval: u16,
+    /* Entropy-coded data, restart markers included, in the parsed buffer */
+    pub offset: usize,
+    pub size: usize,
+}
+
+/* Orientation tag of the EXIF IFD0, 1 being the normal one */
+fn parse_exif_orientation(data: &[u8]) -> Option<u8> {
+    if data.len() < 14 || &data[0..6] != b"Exif\0\0" {
+        return None;
+    }
+    let tiff = &data[6..];
+    let big_endian = match &tiff[0..2] {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+    let read16 = |pos: usize| -> Option<u16> {
+        if pos + 2 > tiff.len() {
+            return None;
+        }
+        Some(if big_endian {
+            (tiff[pos] as u16) << 8 | tiff[pos + 1] as u16
+        } else {
+            (tiff[pos + 1] as u16) << 8 | tiff[pos] as u16
+        })
+    };
+    let read32 = |pos: usize| -> Option<u32> {
+        let (a, b) = (read16(pos)? as u32, read16(pos + 2)? as u32);
+        Some(if big_endian { a << 16 | b } else { b << 16 | a })
+    };
+
+    if read16(2)? != 42 {
+        return None;
+    }
+    let ifd = read32(4)? as usize;
+    let num_entries = read16(ifd)? as usize;
+    for i in 0..num_entries {
+        let entry = ifd + 2 + i * 12;
+        /* A SHORT value sits in the first bytes of the value field */
+        if read16(entry)? == 0x0112 && read16(entry + 2)? == 3 {
+            return match read16(entry + 8)? {
+                value @ 1..=8 => Some(value as u8),
+                _ => None,
+            };
+        }
+    }
+    None
+}
+
+/* Tables and scans collected from one image */
+#[derive(Debug, Clone)]
+pub struct JpegImage {
+    pub frame: FrameHeader,
+    pub quant_tables: [Option<[u8; 64]>; 4],
+    pub dc_tables: [HuffmanTable; 2],
+    pub ac_tables: [HuffmanTable; 2],
+    pub scans: Vec<Scan>,
+    pub jfif: bool,
+    pub exif_orientation: Option<u8>,
+    /* The data ends before EOI, the last scan may miss some MCUs */
+    pub truncated: bool,
+}
+
+/* State of the marker walk, until the frame header is known */
+struct Tables {
+    quant_tables: [Option<[u8; 64]>; 4],
+    dc_tables: [HuffmanTable; 2],
+    ac_tables: [HuffmanTable; 2],
+    restart_interval: u16,
+}
+
+impl Tables {
+    fn new() -> Tables {
+        Tables {
+            quant_tables: [None; 4],
+            dc_tables: [HuffmanTable::new(&DEFAULT_DC_LUMA_CODES, &DEFAULT_DC_VALUES),
+                        HuffmanTable::new(&DEFAULT_DC_CHROMA_CODES, &DEFAULT_DC_VALUES)],
+            ac_tables: [HuffmanTable::new(&DEFAULT_AC_LUMA_CODES, &DEFAULT_AC_LUMA_VALUES),
+                        HuffmanTable::new(&DEFAULT_AC_CHROMA_CODES, &DEFAULT_AC_CHROMA_VALUES)],
+            restart_interval: 0,
+        }
+    }
+
+    fn parse_huffman_tables(&mut self, mut data: &[u8]) -> Result<(), JpegError> {
+        while !data.is_empty() {
+            if data.len() < 17 {
+                return Err(JpegError::InvalidStream);
+            }
+            let class = data[0] >> 4;
+            let id = (data[0] & 0xf) as usize;
+            let mut codes = [0; 16];
+            codes.copy_from_slice(&data[1..17]);
+            let count = codes.iter().map(|&c| c as usize).sum::<usize>();
+            if data.len() < 17 + count {
+                return Err(JpegError::InvalidStream);
+            }
+            /* Baseline only has two tables of each class */
+            if class > 1 || id > 1 {
+                return Err(JpegError::Unsupported);
+            }
+            let max_count = if class == 0 { 12 } else { 162 };
+            if count > max_count {
+                return Err(JpegError::InvalidStream);
+            }
+
+            let table = HuffmanTable::new(&codes, &data[17..17 + count]);
+            if class == 0 {
+                self.dc_tables[id] = table;
+            } else {
+                self.ac_tables[id] = table;
+            }
+            data = &data[17 + count..];
+        }
+
+        Ok(())
+    }
+
+    fn parse_quant_tables(&mut self, mut data: &[u8]) -> Result<(), JpegError> {
+        while !data.is_empty() {
+            let precision = data[0] >> 4;
+            let id = (data[0] & 0xf) as usize;
+            /* 16-bit tables are not baseline */
+            if precision != 0 {
+                return Err(JpegError::Unsupported);
+            }
+            if id > 3 || data.len() < 65 {
+                return Err(JpegError::InvalidStream);
+            }
+            let mut table = [0; 64];
+            table.copy_from_slice(&data[1..65]);
+            self.quant_tables[id] = Some(table);
+            data = &data[65..];
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_scan_header(frame: &FrameHeader, data: &[u8]) -> Result<Vec<ScanComponent>, JpegError> {
+    let num_components = *data.get(0).ok_or(JpegError::InvalidStream)? as usize;
+    if num_components == 0 || num_components > 4 || data.len() < 1 + num_components * 2 + 3 {
+        return Err(JpegError::InvalidStream);
+    }
+
+    let mut components = Vec::with_capacity(num_components);
+    for i in 0..num_components {
+        let c = &data[1 + i * 2..3 + i * 2];
+        let component = ScanComponent {
+            selector: c[0],
+            dc_table: c[1] >> 4,
+            ac_table: c[1] & 0xf,
+        };
+        if !frame.components.iter().any(|c| c.id == component.selector) {
+            return Err(JpegError::InvalidStream);
+        }
+        if component.dc_table > 1 || component.ac_table > 1 {
+            return Err(JpegError::Unsupported);
+        }
+        components.push(component);
+    }
+
+    /* Ss, Se, Ah and Al are fixed for sequential DCT */
+    let spectral = &data[1 + num_components * 2..];
+    if spectral[0] != 0 || spectral[1] != 63 || spectral[2] != 0 {
+        return Err(JpegError::Unsupported);
+    }
+
+    Ok(components)
+}
+
+/* Entropy-coded data runs up to the first marker that is not a restart marker */
+fn find_scan_end(data: &[u8], mut pos: usize) -> usize {
+    while pos + 1 < data.len() {
+        if data[pos] == 0xff {
+            match data[pos + 1] {
+                0x00 | MARKER_RST0..=MARKER_RST7 => pos += 2,
+                _ => return pos,
+            }
+        } else {
+            pos += 1;
+        }
+    }
+    data.len()
+}
+
+impl JpegImage {
+    pub fn parse(data: &[u8]) -> Result<JpegImage, JpegError> {
+        if data.len() < 2 || data[0] != 0xff || data[1] != MARKER_SOI {
+            return Err(JpegError::InvalidStream);
+        }
+
+        let mut tables = Tables::new();
+        let mut frame = None;
+        let mut scans = Vec::new();
+        let mut jfif = false;
+        let mut exif_orientation = None;
+        let mut truncated = true;
+        let mut pos = 2;
+        loop {
+            while pos < data.len() && data[pos] != 0xff {
+                pos += 1;
+            }
+            /* Skip fill bytes */
+            while pos + 1 < data.len() && data[pos + 1] == 0xff {
+                pos += 1;
+            }
+            if pos + 1 >= data.len() {
+                break;
+            }
+
+            let marker = data[pos + 1];
+            pos += 2;
+            match marker {
+                MARKER_EOI => {
+                    truncated = false;
+                    break;
+                }
+                MARKER_SOI | MARKER_TEM | MARKER_RST0..=MARKER_RST7 => continue,
+                _ => {}
+            }
+
+            let length = read_u16(data, pos)? as usize;
+            if length < 2 || pos + length > data.len() {
+                return Err(JpegError::InvalidStream);
+            }
+            let segment = &data[pos + 2..pos + length];
+            pos += length;
+
+            match marker {
+                MARKER_SOF0 | MARKER_SOF1 => {
+                    if frame.is_some() {
+                        return Err(JpegError::InvalidStream);
+                    }
+                    frame = Some(FrameHeader::parse(marker, segment)?);
+                }
+                MARKER_SOF2 | MARKER_SOF6 => return Err(JpegError::Progressive),
+                MARKER_SOF3 | MARKER_SOF7 => return Err(JpegError::Lossless),
+                MARKER_SOF5 => return Err(JpegError::Hierarchical),
+                MARKER_SOF9..=MARKER_SOF15 => return Err(JpegError::Arithmetic),
+                MARKER_DHT => tables.parse_huffman_tables(segment)?,
+                MARKER_DQT => tables.parse_quant_tables(segment)?,
+                MARKER_DRI => tables.restart_interval = read_u16(segment, 0)?,
+                MARKER_APP0 => jfif = jfif || segment.starts_with(b"JFIF\0"),
+                MARKER_APP1 if exif_orientation.is_none() => {
+                    exif_orientation = parse_exif_orientation(segment)
+                }
+                MARKER_SOS => {
+                    let components = match frame {
+                        Some(ref frame) => parse_scan_header(frame, segment)?,
+                        None => return Err(JpegError::InvalidStream),
+                    };
+                    let end = find_scan_end(data, pos);
+                    scans.push(Scan {
+                        components: components,
+                        restart_interval: tables.restart_interval,
+                        offset: pos,
+                        size: end - pos,
+                    });
+                    pos = end;
+                }
+                _ => {}
+            }
+        }
+
+        let frame = frame.ok_or(JpegError::InvalidStream)?;
+        if scans.is_empty() {
+            return Err(JpegError::InvalidStream);
+        }
+        for c in &frame.components {
+            if tables.quant_tables[c.quant_table as usize].is_none() {
+                return Err(JpegError::InvalidStream);
+            }
+        }
+
+        Ok(JpegImage {
+            frame: frame,
+            quant_tables: tables.quant_tables,
+            dc_tables: tables.dc_tables,
+            ac_tables: tables.ac_tables,
+            scans: scans,
+            jfif: jfif,
+            exif_orientation: exif_orientation,
+            truncated: truncated,
+        })
+    }
+
+    pub fn get_num_mcus(&self, scan: &Scan) -> u32 {
+        let frame = &self.frame;
+        let (max_h, max_v) = frame.get_max_sampling_factors();
+
+        /* A non-interleaved scan codes the blocks of a single component */
+        if scan.components.len() == 1 {
+            let (h, v) = frame.components
+                .iter()
+                .find(|c| c.id == scan.components[0].selector)
+                .map_or((max_h, max_v),
+                        |c| (c.h_sampling_factor as u32, c.v_sampling_factor as u32));
+            let width = (frame.width * h + max_h - 1) / max_h;
+            let height = (frame.height * v + max_v - 1) / max_v;
+            return ((width + 7) / 8) * ((height + 7) / 8);
+        }
+
+        let mcu_width = 8 * max_h;
+        let mcu_height = 8 * max_v;
+        ((frame.width + mcu_width - 1) / mcu_width) * ((frame.height + mcu_height - 1) / mcu_height)
+    }
+}
+
+pub fn build_picture_param(image: &JpegImage) -> ffi::VAPictureParameterBufferJPEGBaseline {
+    let frame = &image.frame;
+    let mut pic_param: ffi::VAPictureParameterBufferJPEGBaseline = zeroed();
+    pic_param.picture_width = frame.width as u16;
+    pic_param.picture_height = frame.height as u16;
+    pic_param.num_components = frame.components.len() as u8;
+    for (i, c) in frame.components.iter().enumerate() {
+        pic_param.components[i].component_id = c.id;
+        pic_param.components[i].h_sampling_factor = c.h_sampling_factor;
+        pic_param.components[i].v_sampling_factor = c.v_sampling_factor;
+        pic_param.components[i].quantiser_table_selector = c.quant_table;
+    }
+    pic_param
+}
+
+pub fn build_iq_matrix(image: &JpegImage) -> ffi::VAIQMatrixBufferJPEGBaseline {
+    let mut iq_matrix: ffi::VAIQMatrixBufferJPEGBaseline = zeroed();
+    for (i, table) in image.quant_tables.iter().enumerate() {
+        if let Some(ref table) = *table {
+            iq_matrix.load_quantiser_table[i] = 1;
+            iq_matrix.quantiser_table[i] = *table;
+        }
+    }
+    iq_matrix
+}
+
+pub fn build_huffman_table(image: &JpegImage) -> ffi::VAHuffmanTableBufferJPEGBaseline {
+    let mut huffman_table: ffi::VAHuffmanTableBufferJPEGBaseline = zeroed();
+    huffman_table.load_huffman_table = [1; 2];
+    for i in 0..2 {
+        let table = &mut huffman_table.huffman_table[i];
+        table.num_dc_codes = image.dc_tables[i].codes;
+        table.dc_values[..image.dc_tables[i].values.len()]
+            .copy_from_slice(&image.dc_tables[i].values);
+        table.num_ac_codes = image.ac_tables[i].codes;
+        table.ac_values[..image.ac_tables[i].values.len()]
+            .copy_from_slice(&image.ac_tables[i].values);
+    }
+    huffman_table
+}
+
+/* The scan data goes to a slice data buffer of its own, hence the zero offset */
+pub fn build_slice_param(image: &JpegImage,
+                         scan: &Scan)
+                         -> ffi::VASliceParameterBufferJPEGBaseline {
+    let mut slice_param: ffi::VASliceParameterBufferJPEGBaseline = zeroed();
+    slice_param.slice_data_size = scan.size as u32;
+    slice_param.slice_data_flag = ffi::VA_SLICE_DATA_FLAG_ALL;
+    slice_param.num_components = scan.components.len() as u8;
+    slice_param.restart_interval = scan.restart_interval;
+    slice_param.num_mcus = image.get_num_mcus(scan);
+    for (i, c) in scan.components.iter().enumerate() {
+        slice_param.components[i].component_selector = c.selector;
+        slice_param.components[i].dc_table_selector = c.dc_table;
+        slice_param.components[i].ac_table_selector = c.ac_table;
+    }
+    slice_param
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use decoder::DecodeError;
+    use ffi;
+
+    /* JFIF and EXIF (orientation 6) segments, two quantisation tables, a 33x17 4:2:0
+     * frame, DC and AC tables, a restart interval and a scan with stuffed bytes and
+     * restart markers */
+    const EXIF_420: &'static [u8] = include_bytes!("../tests/data/jpeg/exif_420.jpg");
+
+    /* SOI, a frame header with the given marker and EOI */
+    fn with_frame_marker(marker: u8) -> Vec<u8> {
+        let mut data = vec![0xff, MARKER_SOI, 0xff, marker, 0x00, 0x0b, 8, 0, 16, 0, 16, 1, 1,
+                            0x11, 0];
+        data.extend_from_slice(&[0xff, MARKER_EOI]);
+        data
+    }
+
+    #[test]
+    fn markers() {
+        let image = JpegImage::parse(EXIF_420).unwrap();
+        assert!(image.jfif);
+        assert_eq!(image.exif_orientation, Some(6));
+
+        let frame = &image.frame;
+        assert_eq!(frame.marker, MARKER_SOF0);
+        assert_eq!((frame.width, frame.height), (33, 17));
+        assert_eq!(frame.components.len(), 3);
+        assert_eq!((frame.components[0].h_sampling_factor, frame.components[0].v_sampling_factor),
+                   (2, 2));
+        assert_eq!(frame.components[2].quant_table, 1);
+        assert_eq!(frame.get_max_sampling_factors(), (2, 2));
+        assert_eq!(frame.get_rt_format(), Ok(ffi::VA_RT_FORMAT_YUV420));
+
+        assert_eq!(image.quant_tables[0].unwrap()[63], 64);
+        assert_eq!(image.quant_tables[1].unwrap()[0], 99);
+        assert!(image.quant_tables[2].is_none());
+        assert_eq!(image.ac_tables[1].values, vec![0]);
+        assert_eq!(image.dc_tables[1].codes, DEFAULT_DC_CHROMA_CODES);
+
+        /* The scan runs over stuffed bytes and restart markers up to the COM segment */
+        assert_eq!(image.scans.len(), 1);
+        let scan = &image.scans[0];
+        assert_eq!((scan.offset, scan.size), (309, 12));
+        assert_eq!(scan.restart_interval, 4);
+        assert_eq!(scan.components[1].selector, 2);
+        assert_eq!((scan.components[1].dc_table, scan.components[1].ac_table), (1, 1));
+        assert!(!image.truncated);
+
+        /* Data cut in the middle of the scan */
+        let image = JpegImage::parse(&EXIF_420[..315]).unwrap();
+        assert!(image.truncated);
+        assert_eq!((image.scans[0].offset, image.scans[0].size), (309, 6));
+    }
+
+    #[test]
+    fn exif_orientation() {
+        /* Little endian IFD0 with the orientation as its only entry */
+        let mut data = b"Exif\0\0II\x2a\x00\x08\x00\x00\x00\x01\x00".to_vec();
+        data.extend_from_slice(&[0x12, 0x01, 0x03, 0x00, 0x01, 0x00, 0x00, 0x00, 0x08, 0x00,
+                                 0x00, 0x00]);
+        assert_eq!(parse_exif_orientation(&data), Some(8));
+        /* Out of range values, truncated IFDs and other APP1 payloads */
+        data[24] = 9;
+        assert_eq!(parse_exif_orientation(&data), None);
+        assert_eq!(parse_exif_orientation(&data[..20]), None);
+        assert_eq!(parse_exif_orientation(b"http://ns.adobe.com/xap/1.0/\0"), None);
+    }
+
+    #[test]
+    fn rejected_images() {
+        assert!(JpegImage::parse(&with_frame_marker(MARKER_SOF0)).is_err());
+        assert_eq!(JpegImage::parse(&with_frame_marker(MARKER_SOF2)).unwrap_err(),
+                   JpegError::Progressive);
+        assert_eq!(JpegImage::parse(&with_frame_marker(MARKER_SOF6)).unwrap_err(),
+                   JpegError::Progressive);
+        assert_eq!(JpegImage::parse(&with_frame_marker(MARKER_SOF9)).unwrap_err(),
+                   JpegError::Arithmetic);
+        assert_eq!(JpegImage::parse(&with_frame_marker(0xca)).unwrap_err(),
+                   JpegError::Arithmetic);
+        assert_eq!(JpegImage::parse(&with_frame_marker(MARKER_SOF3)).unwrap_err(),
+                   JpegError::Lossless);
+        assert_eq!(JpegImage::parse(&with_frame_marker(MARKER_SOF5)).unwrap_err(),
+                   JpegError::Hierarchical);
+        assert_eq!(DecodeError::from(JpegError::Progressive),
+                   DecodeError::Jpeg(JpegError::Progressive));
+        assert_eq!(DecodeError::from(JpegError::InvalidStream), DecodeError::InvalidStream);
+
+        /* 12-bit samples */
+        let mut data = with_frame_marker(MARKER_SOF1);
+        data[6] = 12;
+        assert_eq!(JpegImage::parse(&data).unwrap_err(), JpegError::Unsupported);
+
+        /* No SOI, or a frame without scans */
+        assert_eq!(JpegImage::parse(&EXIF_420[2..]).unwrap_err(), JpegError::InvalidStream);
+        let end = EXIF_420.len();
+        let mut data = EXIF_420[..309 - 14].to_vec();
+        data.extend_from_slice(&EXIF_420[end - 2..]);
+        assert_eq!(JpegImage::parse(&data).unwrap_err(), JpegError::InvalidStream);
+
+        /* A scan component missing from the frame, and successive approximation */
+        let mut data = EXIF_420.to_vec();
+        data[300] = 4;
+        assert_eq!(JpegImage::parse(&data).unwrap_err(), JpegError::InvalidStream);
+        let mut data = EXIF_420.to_vec();
+        data[308] = 0x01;
+        assert_eq!(JpegImage::parse(&data).unwrap_err(), JpegError::Unsupported);
+    }
+
+    #[test]
+    fn va_buffers() {
+        let image = JpegImage::parse(EXIF_420).unwrap();
+
+        let pic_param = build_picture_param(&image);
+        assert_eq!((pic_param.picture_width, pic_param.picture_height), (33, 17));
+        assert_eq!(pic_param.num_components, 3);
+        assert_eq!(pic_param.components[0].h_sampling_factor, 2);
+        assert_eq!(pic_param.components[1].component_id, 2);
+        assert_eq!(pic_param.components[2].quantiser_table_selector, 1);
+
+        let iq_matrix = build_iq_matrix(&image);
+        assert_eq!(iq_matrix.load_quantiser_table, [1, 1, 0, 0]);
+        assert_eq!(iq_matrix.quantiser_table[0][1], 2);
+
+        let huffman_table = build_huffman_table(&image);
+        assert_eq!(huffman_table.load_huffman_table, [1, 1]);
+        assert_eq!(huffman_table.huffman_table[0].num_dc_codes, DEFAULT_DC_LUMA_CODES);
+        assert_eq!(huffman_table.huffman_table[0].ac_values[..], DEFAULT_AC_LUMA_VALUES[..]);
+        assert_eq!(huffman_table.huffman_table[1].num_ac_codes[1], 1);
+        assert_eq!(huffman_table.huffman_table[1].ac_values[..2], [0, 0]);
+
+        let scan = &image.scans[0];
+        let slice_param = build_slice_param(&image, scan);
+        assert_eq!(slice_param.slice_data_size, 12);
+        assert_eq!(slice_param.slice_data_offset, 0);
+        assert_eq!(slice_param.num_components, 3);
+        assert_eq!(slice_param.restart_interval, 4);
+        assert_eq!(slice_param.num_mcus, 6);
+        assert_eq!(slice_param.components[2].component_selector, 3);
+
+        /* Non-interleaved scans count the blocks of their component */
+        let mut single = scan.clone();
+        single.components.truncate(1);
+        assert_eq!(image.get_num_mcus(&single), 15);
+        single.components[0].selector = 2;
+        assert_eq!(image.get_num_mcus(&single), 6);
+    }
+}
@@ -9,9 +9,44 @@
 extern crate x11;
 extern crate libc;
 
-pub mod ffi;
+#[cfg(not(va_generated_bindings))]
+#[path = "ffi.rs"]
+mod ffi_bindings;
+#[cfg(va_generated_bindings)]
+#[path = "ffi_generated.rs"]
+mod ffi_bindings;
+
+/* The bindings under the names build.rs gives them in both modes: anonymous unions and
+ * structures named after their parent and field, and enumerators as module constants */
+pub mod ffi {
+    #![allow(non_upper_case_globals)]
+    #![allow(non_camel_case_types)]
+
+    pub use ffi_bindings::*;
+    include!(concat!(env!("OUT_DIR"), "/ffi_names.rs"));
+}
 pub mod ffi_x11;
 pub mod va;
 pub mod renderer;
 pub mod renderer_x11;
-
+pub mod bitreader;
+pub mod bitfields;
+pub mod decoder;
+pub mod future;
+pub mod decoder_mpeg2;
+pub mod decoder_jpeg;
+pub mod decoder_vc1;
+pub mod decoder_mpeg4;
+pub mod h264;
+pub mod hevc;
+pub mod jpeg;
+pub mod mpeg2;
+pub mod ivf;
+pub mod vp8;
+pub mod vp9;
+#[cfg(va_av1_decode)]
+pub mod av1;
+pub mod decoder_h264;
+#[cfg(va_av1_decode)]
+pub mod decoder_av1;
+pub mod thumbnail;
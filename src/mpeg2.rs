@@ -0,0 +1,978 @@
+// Copyright (c) 2017 zzoon <zzoon@igalia.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/* Parser for the MPEG-2 video syntax needed to drive VA decoding: start code units,
+ * sequence, GOP and picture headers with their extensions, and slice headers, and their
+ * conversion into the VA buffers. Quantiser matrices are kept in zig-zag order. */
+
+use bitfields::{MPEG2PictureCodingExtension, zeroed};
+use bitreader::BitReader;
+use decoder::{find_start_codes, ZIGZAG};
+use ffi;
+
+pub const PICTURE_START_CODE: u8 = 0x00;
+pub const SLICE_MIN_START_CODE: u8 = 0x01;
+pub const SLICE_MAX_START_CODE: u8 = 0xaf;
+pub const USER_DATA_START_CODE: u8 = 0xb2;
+pub const SEQUENCE_HEADER_CODE: u8 = 0xb3;
+pub const SEQUENCE_ERROR_CODE: u8 = 0xb4;
+pub const EXTENSION_START_CODE: u8 = 0xb5;
+pub const SEQUENCE_END_CODE: u8 = 0xb7;
+pub const GROUP_START_CODE: u8 = 0xb8;
+
+pub const SEQUENCE_EXTENSION_ID: u32 = 1;
+pub const SEQUENCE_DISPLAY_EXTENSION_ID: u32 = 2;
+pub const QUANT_MATRIX_EXTENSION_ID: u32 = 3;
+pub const PICTURE_CODING_EXTENSION_ID: u32 = 8;
+
+pub const PICTURE_TYPE_I: u8 = 1;
+pub const PICTURE_TYPE_P: u8 = 2;
+pub const PICTURE_TYPE_B: u8 = 3;
+
+pub const PICTURE_STRUCTURE_TOP_FIELD: u8 = 1;
+pub const PICTURE_STRUCTURE_BOTTOM_FIELD: u8 = 2;
+pub const PICTURE_STRUCTURE_FRAME: u8 = 3;
+
+pub const CHROMA_FORMAT_420: u8 = 1;
+pub const CHROMA_FORMAT_422: u8 = 2;
+pub const CHROMA_FORMAT_444: u8 = 3;
+
+/* Default intra matrix of ISO/IEC 13818-2, in raster order */
+pub const DEFAULT_INTRA_MATRIX: [u8; 64] = [8, 16, 19, 22, 26, 27, 29, 34, 16, 16, 22, 24, 27,
+                                            29, 34, 37, 19, 22, 26, 27, 29, 34, 34, 38, 22, 22,
+                                            26, 27, 29, 34, 37, 40, 22, 26, 27, 29, 32, 35, 40,
+                                            48, 26, 27, 29, 32, 35, 40, 48, 58, 26, 27, 29, 34,
+                                            38, 46, 56, 69, 27, 29, 35, 38, 46, 56, 69, 83];
+
+fn default_intra_matrix() -> [u8; 64] {
+    let mut matrix = [0; 64];
+    for i in 0..64 {
+        matrix[i] = DEFAULT_INTRA_MATRIX[ZIGZAG[i]];
+    }
+    matrix
+}
+
+fn read_matrix(br: &mut BitReader) -> Result<[u8; 64], ()> {
+    let mut matrix = [0; 64];
+    for value in matrix.iter_mut() {
+        *value = br.read_bits(8)? as u8;
+    }
+    Ok(matrix)
+}
+
+fn read_optional_matrix(br: &mut BitReader) -> Result<Option<[u8; 64]>, ()> {
+    if br.read_flag()? {
+        Ok(Some(read_matrix(br)?))
+    } else {
+        Ok(None)
+    }
+}
+
+/* A start code unit, from its 00 00 01 prefix up to the next one */
+#[derive(Debug, Clone)]
+pub struct StartCodeUnit<'a> {
+    pub code: u8,
+    /* Position of the prefix in the buffer the unit was split from */
+    pub offset: usize,
+    pub data: &'a [u8],
+}
+
+impl<'a> StartCodeUnit<'a> {
+    pub fn is_slice(&self) -> bool {
+        is_slice_start_code(self.code)
+    }
+
+    /* Payload following the start code value */
+    pub fn payload(&self) -> &'a [u8] {
+        &self.data[4..]
+    }
+}
+
+pub fn is_slice_start_code(code: u8) -> bool {
+    code >= SLICE_MIN_START_CODE && code <= SLICE_MAX_START_CODE
+}
+
+/* Start code units of an elementary stream; they keep their prefix since VA wants the
+ * slice data from the slice start code on */
+pub fn split_start_code_units<'a>(data: &'a [u8]) -> Vec<StartCodeUnit<'a>> {
+    let offsets = find_start_codes(data);
+    let mut units = Vec::with_capacity(offsets.len());
+
+    for (i, &start) in offsets.iter().enumerate() {
+        let end = offsets.get(i + 1).cloned().unwrap_or(data.len());
+        if end - start < 4 {
+            continue;
+        }
+        units.push(StartCodeUnit {
+            code: data[start + 3],
+            offset: start,
+            data: &data[start..end],
+        });
+    }
+
+    units
+}
+
+/* Start code units of a stream fed in chunks, e.g. PES payloads. Only the units followed by
+ * another start code are known to be complete; the rest of the buffer, from the offset
+ * returned along with them, may continue in the next chunk */
+pub fn split_complete_units<'a>(data: &'a [u8]) -> (Vec<StartCodeUnit<'a>>, usize) {
+    /* Without any start code only the bytes of a cut prefix are worth keeping */
+    let rest = match find_start_codes(data).last() {
+        Some(&last) => last,
+        None => data.len().saturating_sub(3),
+    };
+    (split_start_code_units(&data[..rest]), rest)
+}
+
+#[derive(Debug, Clone)]
+pub struct SequenceHeader {
+    pub horizontal_size_value: u32,
+    pub vertical_size_value: u32,
+    pub aspect_ratio_information: u8,
+    pub frame_rate_code: u8,
+    pub bit_rate_value: u32,
+    pub vbv_buffer_size_value: u32,
+    pub constrained_parameters_flag: bool,
+    pub intra_quantiser_matrix: Option<[u8; 64]>,
+    pub non_intra_quantiser_matrix: Option<[u8; 64]>,
+}
+
+impl SequenceHeader {
+    pub fn parse(br: &mut BitReader) -> Result<SequenceHeader, ()> {
+        let horizontal_size_value = br.read_bits(12)?;
+        let vertical_size_value = br.read_bits(12)?;
+        let aspect_ratio_information = br.read_bits(4)? as u8;
+        let frame_rate_code = br.read_bits(4)? as u8;
+        let bit_rate_value = br.read_bits(18)?;
+        /* marker_bit */
+        br.skip_bits(1)?;
+        let vbv_buffer_size_value = br.read_bits(10)?;
+        let constrained_parameters_flag = br.read_flag()?;
+        let intra_quantiser_matrix = read_optional_matrix(br)?;
+        let non_intra_quantiser_matrix = read_optional_matrix(br)?;
+
+        if horizontal_size_value == 0 || vertical_size_value == 0 {
+            return Err(());
+        }
+
+        Ok(SequenceHeader {
+            horizontal_size_value: horizontal_size_value,
+            vertical_size_value: vertical_size_value,
+            aspect_ratio_information: aspect_ratio_information,
+            frame_rate_code: frame_rate_code,
+            bit_rate_value: bit_rate_value,
+            vbv_buffer_size_value: vbv_buffer_size_value,
+            constrained_parameters_flag: constrained_parameters_flag,
+            intra_quantiser_matrix: intra_quantiser_matrix,
+            non_intra_quantiser_matrix: non_intra_quantiser_matrix,
+        })
+    }
+
+    /* Picture size, including the upper bits of the sequence extension if any */
+    pub fn get_size(&self, ext: Option<&SequenceExtension>) -> (u32, u32) {
+        match ext {
+            Some(ext) => {
+                (self.horizontal_size_value | (ext.horizontal_size_extension << 12),
+                 self.vertical_size_value | (ext.vertical_size_extension << 12))
+            }
+            None => (self.horizontal_size_value, self.vertical_size_value),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SequenceExtension {
+    pub profile_and_level_indication: u8,
+    pub progressive_sequence: bool,
+    pub chroma_format: u8,
+    pub horizontal_size_extension: u32,
+    pub vertical_size_extension: u32,
+    pub bit_rate_extension: u32,
+    pub vbv_buffer_size_extension: u32,
+    pub low_delay: bool,
+    pub frame_rate_extension_n: u8,
+    pub frame_rate_extension_d: u8,
+}
+
+impl SequenceExtension {
+    pub fn parse(br: &mut BitReader) -> Result<SequenceExtension, ()> {
+        let profile_and_level_indication = br.read_bits(8)? as u8;
+        let progressive_sequence = br.read_flag()?;
+        let chroma_format = br.read_bits(2)? as u8;
+        let horizontal_size_extension = br.read_bits(2)?;
+        let vertical_size_extension = br.read_bits(2)?;
+        let bit_rate_extension = br.read_bits(12)?;
+        /* marker_bit */
+        br.skip_bits(1)?;
+        let vbv_buffer_size_extension = br.read_bits(8)?;
+        let low_delay = br.read_flag()?;
+        let frame_rate_extension_n = br.read_bits(2)? as u8;
+        let frame_rate_extension_d = br.read_bits(5)? as u8;
+
+        if chroma_format == 0 {
+            return Err(());
+        }
+
+        Ok(SequenceExtension {
+            profile_and_level_indication: profile_and_level_indication,
+            progressive_sequence: progressive_sequence,
+            chroma_format: chroma_format,
+            horizontal_size_extension: horizontal_size_extension,
+            vertical_size_extension: vertical_size_extension,
+            bit_rate_extension: bit_rate_extension,
+            vbv_buffer_size_extension: vbv_buffer_size_extension,
+            low_delay: low_delay,
+            frame_rate_extension_n: frame_rate_extension_n,
+            frame_rate_extension_d: frame_rate_extension_d,
+        })
+    }
+
+    /* profile_and_level_indication split into the 3 bits profile and 4 bits level */
+    pub fn get_profile(&self) -> u8 {
+        (self.profile_and_level_indication >> 4) & 0x7
+    }
+
+    pub fn get_level(&self) -> u8 {
+        self.profile_and_level_indication & 0xf
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ColourDescription {
+    pub colour_primaries: u8,
+    pub transfer_characteristics: u8,
+    pub matrix_coefficients: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct SequenceDisplayExtension {
+    pub video_format: u8,
+    pub colour_description: Option<ColourDescription>,
+    pub display_horizontal_size: u32,
+    pub display_vertical_size: u32,
+}
+
+impl SequenceDisplayExtension {
+    pub fn parse(br: &mut BitReader) -> Result<SequenceDisplayExtension, ()> {
+        let video_format = br.read_bits(3)? as u8;
+        let colour_description = if br.read_flag()? {
+            Some(ColourDescription {
+                colour_primaries: br.read_bits(8)? as u8,
+                transfer_characteristics: br.read_bits(8)? as u8,
+                matrix_coefficients: br.read_bits(8)? as u8,
+            })
+        } else {
+            None
+        };
+        let display_horizontal_size = br.read_bits(14)?;
+        /* marker_bit */
+        br.skip_bits(1)?;
+        let display_vertical_size = br.read_bits(14)?;
+
+        Ok(SequenceDisplayExtension {
+            video_format: video_format,
+            colour_description: colour_description,
+            display_horizontal_size: display_horizontal_size,
+            display_vertical_size: display_vertical_size,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct QuantMatrixExtension {
+    pub intra_quantiser_matrix: Option<[u8; 64]>,
+    pub non_intra_quantiser_matrix: Option<[u8; 64]>,
+    pub chroma_intra_quantiser_matrix: Option<[u8; 64]>,
+    pub chroma_non_intra_quantiser_matrix: Option<[u8; 64]>,
+}
+
+impl QuantMatrixExtension {
+    pub fn parse(br: &mut BitReader) -> Result<QuantMatrixExtension, ()> {
+        Ok(QuantMatrixExtension {
+            intra_quantiser_matrix: read_optional_matrix(br)?,
+            non_intra_quantiser_matrix: read_optional_matrix(br)?,
+            chroma_intra_quantiser_matrix: read_optional_matrix(br)?,
+            chroma_non_intra_quantiser_matrix: read_optional_matrix(br)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GopHeader {
+    pub drop_frame_flag: bool,
+    pub time_code_hours: u8,
+    pub time_code_minutes: u8,
+    pub time_code_seconds: u8,
+    pub time_code_pictures: u8,
+    pub closed_gop: bool,
+    pub broken_link: bool,
+}
+
+impl GopHeader {
+    pub fn parse(br: &mut BitReader) -> Result<GopHeader, ()> {
+        let drop_frame_flag = br.read_flag()?;
+        let time_code_hours = br.read_bits(5)? as u8;
+        let time_code_minutes = br.read_bits(6)? as u8;
+        /* marker_bit */
+        br.skip_bits(1)?;
+        let time_code_seconds = br.read_bits(6)? as u8;
+        let time_code_pictures = br.read_bits(6)? as u8;
+
+        Ok(GopHeader {
+            drop_frame_flag: drop_frame_flag,
+            time_code_hours: time_code_hours,
+            time_code_minutes: time_code_minutes,
+            time_code_seconds: time_code_seconds,
+            time_code_pictures: time_code_pictures,
+            closed_gop: br.read_flag()?,
+            broken_link: br.read_flag()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PictureHeader {
+    pub temporal_reference: u32,
+    pub picture_coding_type: u8,
+    pub vbv_delay: u32,
+    /* MPEG-1 motion vector ranges, superseded by the picture coding extension */
+    pub full_pel_forward_vector: bool,
+    pub forward_f_code: u8,
+    pub full_pel_backward_vector: bool,
+    pub backward_f_code: u8,
+}
+
+impl PictureHeader {
+    pub fn parse(br: &mut BitReader) -> Result<PictureHeader, ()> {
+        let temporal_reference = br.read_bits(10)?;
+        let picture_coding_type = br.read_bits(3)? as u8;
+        let vbv_delay = br.read_bits(16)?;
+        let mut hdr = PictureHeader {
+            temporal_reference: temporal_reference,
+            picture_coding_type: picture_coding_type,
+            vbv_delay: vbv_delay,
+            full_pel_forward_vector: false,
+            forward_f_code: 0,
+            full_pel_backward_vector: false,
+            backward_f_code: 0,
+        };
+
+        if picture_coding_type == PICTURE_TYPE_P || picture_coding_type == PICTURE_TYPE_B {
+            hdr.full_pel_forward_vector = br.read_flag()?;
+            hdr.forward_f_code = br.read_bits(3)? as u8;
+        }
+        if picture_coding_type == PICTURE_TYPE_B {
+            hdr.full_pel_backward_vector = br.read_flag()?;
+            hdr.backward_f_code = br.read_bits(3)? as u8;
+        }
+
+        Ok(hdr)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PictureCodingExtension {
+    pub f_code: [[u8; 2]; 2],
+    pub intra_dc_precision: u8,
+    pub picture_structure: u8,
+    pub top_field_first: bool,
+    pub frame_pred_frame_dct: bool,
+    pub concealment_motion_vectors: bool,
+    pub q_scale_type: bool,
+    pub intra_vlc_format: bool,
+    pub alternate_scan: bool,
+    pub repeat_first_field: bool,
+    pub chroma_420_type: bool,
+    pub progressive_frame: bool,
+    pub composite_display_flag: bool,
+}
+
+impl PictureCodingExtension {
+    pub fn parse(br: &mut BitReader) -> Result<PictureCodingExtension, ()> {
+        let mut f_code = [[0; 2]; 2];
+        for s in 0..2 {
+            for t in 0..2 {
+                f_code[s][t] = br.read_bits(4)? as u8;
+            }
+        }
+        let intra_dc_precision = br.read_bits(2)? as u8;
+        let picture_structure = br.read_bits(2)? as u8;
+        let top_field_first = br.read_flag()?;
+        let frame_pred_frame_dct = br.read_flag()?;
+        let concealment_motion_vectors = br.read_flag()?;
+        let q_scale_type = br.read_flag()?;
+        let intra_vlc_format = br.read_flag()?;
+        let alternate_scan = br.read_flag()?;
+        let repeat_first_field = br.read_flag()?;
+        let chroma_420_type = br.read_flag()?;
+        let progressive_frame = br.read_flag()?;
+        /* v_axis, field_sequence, sub_carrier, burst_amplitude and sub_carrier_phase follow
+         * when composite_display_flag is set, none of them matters for decoding */
+        let composite_display_flag = br.read_flag()?;
+
+        if picture_structure == 0 {
+            return Err(());
+        }
+
+        Ok(PictureCodingExtension {
+            f_code: f_code,
+            intra_dc_precision: intra_dc_precision,
+            picture_structure: picture_structure,
+            top_field_first: top_field_first,
+            frame_pred_frame_dct: frame_pred_frame_dct,
+            concealment_motion_vectors: concealment_motion_vectors,
+            q_scale_type: q_scale_type,
+            intra_vlc_format: intra_vlc_format,
+            alternate_scan: alternate_scan,
+            repeat_first_field: repeat_first_field,
+            chroma_420_type: chroma_420_type,
+            progressive_frame: progressive_frame,
+            composite_display_flag: composite_display_flag,
+        })
+    }
+
+    pub fn is_field(&self) -> bool {
+        self.picture_structure != PICTURE_STRUCTURE_FRAME
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Extension {
+    Sequence(SequenceExtension),
+    SequenceDisplay(SequenceDisplayExtension),
+    /* Boxed, it carries up to four matrices */
+    QuantMatrix(Box<QuantMatrixExtension>),
+    PictureCoding(PictureCodingExtension),
+    /* Extensions of the scalable profiles, ignored */
+    Unknown(u32),
+}
+
+impl Extension {
+    /* Parses the payload of an extension start code unit */
+    pub fn parse(br: &mut BitReader) -> Result<Extension, ()> {
+        let id = br.read_bits(4)?;
+        Ok(match id {
+            SEQUENCE_EXTENSION_ID => Extension::Sequence(SequenceExtension::parse(br)?),
+            SEQUENCE_DISPLAY_EXTENSION_ID => {
+                Extension::SequenceDisplay(SequenceDisplayExtension::parse(br)?)
+            }
+            QUANT_MATRIX_EXTENSION_ID => {
+                Extension::QuantMatrix(Box::new(QuantMatrixExtension::parse(br)?))
+            }
+            PICTURE_CODING_EXTENSION_ID => {
+                Extension::PictureCoding(PictureCodingExtension::parse(br)?)
+            }
+            _ => Extension::Unknown(id),
+        })
+    }
+}
+
+/* Quantiser matrices currently in effect, in zig-zag scan order */
+#[derive(Debug, Clone)]
+pub struct QuantMatrices {
+    pub intra: [u8; 64],
+    pub non_intra: [u8; 64],
+    pub chroma_intra: [u8; 64],
+    pub chroma_non_intra: [u8; 64],
+}
+
+impl QuantMatrices {
+    pub fn new() -> QuantMatrices {
+        QuantMatrices {
+            intra: default_intra_matrix(),
+            non_intra: [16; 64],
+            chroma_intra: default_intra_matrix(),
+            chroma_non_intra: [16; 64],
+        }
+    }
+
+    /* A sequence header resets every matrix, to the default ones when not loaded */
+    pub fn update_from_sequence(&mut self, seq_hdr: &SequenceHeader) {
+        self.intra = seq_hdr.intra_quantiser_matrix.unwrap_or_else(default_intra_matrix);
+        self.non_intra = seq_hdr.non_intra_quantiser_matrix.unwrap_or([16; 64]);
+        self.chroma_intra = self.intra;
+        self.chroma_non_intra = self.non_intra;
+    }
+
+    /* The luma matrices of the extension apply to chroma too, unless it loads those */
+    pub fn update_from_extension(&mut self, ext: &QuantMatrixExtension) {
+        if let Some(matrix) = ext.intra_quantiser_matrix {
+            self.intra = matrix;
+            self.chroma_intra = matrix;
+        }
+        if let Some(matrix) = ext.non_intra_quantiser_matrix {
+            self.non_intra = matrix;
+            self.chroma_non_intra = matrix;
+        }
+        if let Some(matrix) = ext.chroma_intra_quantiser_matrix {
+            self.chroma_intra = matrix;
+        }
+        if let Some(matrix) = ext.chroma_non_intra_quantiser_matrix {
+            self.chroma_non_intra = matrix;
+        }
+    }
+}
+
+impl Default for QuantMatrices {
+    fn default() -> QuantMatrices {
+        QuantMatrices::new()
+    }
+}
+
+/* Table B-1, macroblock_address_increment, as (code length, code, value) */
+const MB_ADDRESS_INCREMENT: [(u32, u32, u32); 33] =
+    [(1, 0b1, 1), (3, 0b011, 2), (3, 0b010, 3), (4, 0b0011, 4), (4, 0b0010, 5),
+     (5, 0b00011, 6), (5, 0b00010, 7), (7, 0b0000111, 8), (7, 0b0000110, 9),
+     (8, 0b00001011, 10), (8, 0b00001010, 11), (8, 0b00001001, 12), (8, 0b00001000, 13),
+     (8, 0b00000111, 14), (8, 0b00000110, 15), (10, 0b0000010111, 16),
+     (10, 0b0000010110, 17), (10, 0b0000010101, 18), (10, 0b0000010100, 19),
+     (10, 0b0000010011, 20), (10, 0b0000010010, 21), (11, 0b00000100011, 22),
+     (11, 0b00000100010, 23), (11, 0b00000100001, 24), (11, 0b00000100000, 25),
+     (11, 0b00000011111, 26), (11, 0b00000011110, 27), (11, 0b00000011101, 28),
+     (11, 0b00000011100, 29), (11, 0b00000011011, 30), (11, 0b00000011010, 31),
+     (11, 0b00000011001, 32), (11, 0b00000011000, 33)];
+
+const MB_ESCAPE: u32 = 0b00000001000;
+
+fn read_mb_address_increment(br: &mut BitReader) -> Result<u32, ()> {
+    let mut increment = 0;
+    while br.peek_bits(11) == Ok(MB_ESCAPE) {
+        br.skip_bits(11)?;
+        increment += 33;
+    }
+    for &(len, code, value) in MB_ADDRESS_INCREMENT.iter() {
+        if br.peek_bits(len) == Ok(code) {
+            br.skip_bits(len as usize)?;
+            return Ok(increment + value);
+        }
+    }
+    Err(())
+}
+
+#[derive(Debug, Clone)]
+pub struct SliceHeader {
+    pub slice_vertical_position: u32,
+    pub quantiser_scale_code: u32,
+    pub intra_slice_flag: bool,
+    pub intra_slice: bool,
+    /* Macroblock column of the first macroblock, from its address increment */
+    pub slice_horizontal_position: u32,
+    /* Bits from the start code prefix to the first macroblock */
+    pub macroblock_offset: u32,
+}
+
+impl SliceHeader {
+    /* Parses the slice header of a slice unit that still carries its start code; the
+     * vertical size selects the slice_vertical_position_extension of tall pictures */
+    pub fn parse(data: &[u8], vertical_size: u32) -> Result<SliceHeader, ()> {
+        let mut br = BitReader::new(data);
+        br.skip_bits(24)?;
+        let slice_start_code = br.read_bits(8)? as u8;
+        if !is_slice_start_code(slice_start_code) {
+            return Err(());
+        }
+        let mut slice_vertical_position = slice_start_code as u32 - 1;
+        if vertical_size > 2800 {
+            slice_vertical_position += br.read_bits(3)? << 7;
+        }
+        let quantiser_scale_code = br.read_bits(5)?;
+        let mut intra_slice_flag = false;
+        let mut intra_slice = false;
+        if br.peek_bits(1)? == 1 {
+            intra_slice_flag = br.read_flag()?;
+            intra_slice = br.read_flag()?;
+            /* reserved_bits */
+            br.skip_bits(7)?;
+            while br.peek_bits(1)? == 1 {
+                /* extra_bit_slice, extra_information_slice */
+                br.skip_bits(1 + 8)?;
+            }
+        }
+        /* extra_bit_slice */
+        br.skip_bits(1)?;
+        let macroblock_offset = br.get_pos() as u32;
+        let slice_horizontal_position = read_mb_address_increment(&mut br)? - 1;
+
+        Ok(SliceHeader {
+            slice_vertical_position: slice_vertical_position,
+            quantiser_scale_code: quantiser_scale_code,
+            intra_slice_flag: intra_slice_flag,
+            intra_slice: intra_slice,
+            slice_horizontal_position: slice_horizontal_position,
+            macroblock_offset: macroblock_offset,
+        })
+    }
+}
+
+/* The references are the surfaces of the previous and next anchor pictures as the
+ * picture coding type needs them, VA_INVALID_SURFACE otherwise */
+pub fn build_picture_param(seq_hdr: &SequenceHeader,
+                           seq_ext: Option<&SequenceExtension>,
+                           pic_hdr: &PictureHeader,
+                           pic_ext: &PictureCodingExtension,
+                           forward: ffi::VASurfaceID,
+                           backward: ffi::VASurfaceID,
+                           is_first_field: bool)
+                           -> Result<ffi::VAPictureParameterBufferMPEG2, ()> {
+    let (width, height) = seq_hdr.get_size(seq_ext);
+    let f_code = &pic_ext.f_code;
+    let picture_coding_extension = MPEG2PictureCodingExtension {
+        intra_dc_precision: pic_ext.intra_dc_precision as u32,
+        picture_structure: pic_ext.picture_structure as u32,
+        top_field_first: pic_ext.top_field_first as u32,
+        frame_pred_frame_dct: pic_ext.frame_pred_frame_dct as u32,
+        concealment_motion_vectors: pic_ext.concealment_motion_vectors as u32,
+        q_scale_type: pic_ext.q_scale_type as u32,
+        intra_vlc_format: pic_ext.intra_vlc_format as u32,
+        alternate_scan: pic_ext.alternate_scan as u32,
+        repeat_first_field: pic_ext.repeat_first_field as u32,
+        progressive_frame: pic_ext.progressive_frame as u32,
+        is_first_field: is_first_field as u32,
+    };
+    let mut param: ffi::VAPictureParameterBufferMPEG2 = zeroed();
+    param.horizontal_size = width as u16;
+    param.vertical_size = height as u16;
+    param.forward_reference_picture = forward;
+    param.backward_reference_picture = backward;
+    param.picture_coding_type = pic_hdr.picture_coding_type as i32;
+    param.f_code = ((f_code[0][0] as i32) << 12) | ((f_code[0][1] as i32) << 8) |
+                   ((f_code[1][0] as i32) << 4) | f_code[1][1] as i32;
+    param.picture_coding_extension = picture_coding_extension.to_va()?;
+    Ok(param)
+}
+
+pub fn build_iq_matrix(quant: &QuantMatrices) -> ffi::VAIQMatrixBufferMPEG2 {
+    let mut iq_matrix: ffi::VAIQMatrixBufferMPEG2 = zeroed();
+    iq_matrix.load_intra_quantiser_matrix = 1;
+    iq_matrix.load_non_intra_quantiser_matrix = 1;
+    iq_matrix.load_chroma_intra_quantiser_matrix = 1;
+    iq_matrix.load_chroma_non_intra_quantiser_matrix = 1;
+    iq_matrix.intra_quantiser_matrix = quant.intra;
+    iq_matrix.non_intra_quantiser_matrix = quant.non_intra;
+    iq_matrix.chroma_intra_quantiser_matrix = quant.chroma_intra;
+    iq_matrix.chroma_non_intra_quantiser_matrix = quant.chroma_non_intra;
+    iq_matrix
+}
+
+/* slice_data_offset and slice_data_size locate the slice unit, start code included, in
+ * the slice data buffer */
+pub fn build_slice_param(hdr: &SliceHeader,
+                         slice_data_offset: u32,
+                         slice_data_size: u32)
+                         -> ffi::VASliceParameterBufferMPEG2 {
+    let mut param: ffi::VASliceParameterBufferMPEG2 = zeroed();
+    param.slice_data_size = slice_data_size;
+    param.slice_data_offset = slice_data_offset;
+    param.slice_data_flag = ffi::VA_SLICE_DATA_FLAG_ALL;
+    param.macroblock_offset = hdr.macroblock_offset;
+    param.slice_horizontal_position = hdr.slice_horizontal_position;
+    param.slice_vertical_position = hdr.slice_vertical_position;
+    param.quantiser_scale_code = hdr.quantiser_scale_code as i32;
+    param.intra_slice_flag = hdr.intra_slice_flag as i32;
+    param
+}
+
+/* Slice parameters of every slice found in the buffer, for submitting the whole buffer
+ * as a single slice data buffer */
+pub fn build_slice_params(data: &[u8],
+                          vertical_size: u32)
+                          -> Result<Vec<ffi::VASliceParameterBufferMPEG2>, ()> {
+    let mut params = Vec::new();
+    for unit in split_start_code_units(data).iter().filter(|unit| unit.is_slice()) {
+        let hdr = SliceHeader::parse(unit.data, vertical_size)?;
+        params.push(build_slice_param(&hdr, unit.offset as u32, unit.data.len() as u32));
+    }
+    Ok(params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitreader::BitReader;
+    use ffi;
+
+    /* Sequence header with an intra matrix and its extensions, GOP, then an I frame with
+     * a quant matrix extension, a P field pair and a B frame of a 576i Main stream */
+    const MAIN_576I: &'static [u8] = include_bytes!("../tests/data/mpeg2/main_576i.m2v");
+
+    fn parse_units(code: u8) -> Vec<StartCodeUnit<'static>> {
+        split_start_code_units(MAIN_576I).into_iter().filter(|unit| unit.code == code).collect()
+    }
+
+    fn parse_extensions() -> Vec<Extension> {
+        parse_units(EXTENSION_START_CODE)
+            .iter()
+            .map(|unit| Extension::parse(&mut BitReader::new(unit.payload())).unwrap())
+            .collect()
+    }
+
+    fn parse_sequence() -> (SequenceHeader, SequenceExtension) {
+        let unit = &parse_units(SEQUENCE_HEADER_CODE)[0];
+        let seq_hdr = SequenceHeader::parse(&mut BitReader::new(unit.payload())).unwrap();
+        match parse_extensions().remove(0) {
+            Extension::Sequence(ext) => (seq_hdr, ext),
+            _ => panic!("no sequence extension"),
+        }
+    }
+
+    fn parse_pictures() -> Vec<(PictureHeader, PictureCodingExtension)> {
+        let headers = parse_units(PICTURE_START_CODE)
+            .iter()
+            .map(|unit| PictureHeader::parse(&mut BitReader::new(unit.payload())).unwrap())
+            .collect::<Vec<_>>();
+        let exts = parse_extensions()
+            .into_iter()
+            .filter_map(|ext| match ext {
+                Extension::PictureCoding(ext) => Some(ext),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        headers.into_iter().zip(exts).collect()
+    }
+
+    #[test]
+    fn start_codes() {
+        let units = split_start_code_units(MAIN_576I);
+        let codes: Vec<u8> = units.iter().map(|unit| unit.code).collect();
+        assert_eq!(codes,
+                   vec![SEQUENCE_HEADER_CODE, EXTENSION_START_CODE, EXTENSION_START_CODE,
+                        GROUP_START_CODE, PICTURE_START_CODE, EXTENSION_START_CODE,
+                        EXTENSION_START_CODE, 1, 2, 3, PICTURE_START_CODE, EXTENSION_START_CODE,
+                        1, PICTURE_START_CODE, EXTENSION_START_CODE, 1, PICTURE_START_CODE,
+                        EXTENSION_START_CODE, 1, 2, SEQUENCE_END_CODE]);
+        assert_eq!(units[7].offset, 256);
+        assert_eq!(units[7].data.len(), 10);
+        assert!(units[7].is_slice() && !units[6].is_slice());
+        assert_eq!(units[20].data, &[0, 0, 1, SEQUENCE_END_CODE]);
+
+        /* Truncated prefixes are dropped */
+        assert!(split_start_code_units(&[0, 0, 1]).is_empty());
+        assert_eq!(split_start_code_units(&[0, 0, 0, 1, 0xb7]).len(), 1);
+    }
+
+    #[test]
+    fn sequence_headers() {
+        let (seq_hdr, seq_ext) = parse_sequence();
+        assert_eq!(seq_hdr.get_size(Some(&seq_ext)), (720, 576));
+        assert_eq!(seq_hdr.aspect_ratio_information, 2);
+        assert_eq!(seq_hdr.frame_rate_code, 3);
+        assert_eq!(seq_hdr.bit_rate_value, 15000);
+        assert_eq!(seq_hdr.vbv_buffer_size_value, 112);
+        assert!(!seq_hdr.constrained_parameters_flag);
+        assert_eq!(seq_hdr.intra_quantiser_matrix.unwrap()[63], 71);
+        assert!(seq_hdr.non_intra_quantiser_matrix.is_none());
+
+        assert_eq!(seq_ext.get_profile(), 4);
+        assert_eq!(seq_ext.get_level(), 8);
+        assert!(!seq_ext.progressive_sequence);
+        assert_eq!(seq_ext.chroma_format, CHROMA_FORMAT_420);
+        assert!(!seq_ext.low_delay);
+
+        let display = match parse_extensions().remove(1) {
+            Extension::SequenceDisplay(ext) => ext,
+            _ => panic!("no sequence display extension"),
+        };
+        assert_eq!(display.video_format, 1);
+        let colour = display.colour_description.unwrap();
+        assert_eq!((colour.colour_primaries,
+                    colour.transfer_characteristics,
+                    colour.matrix_coefficients),
+                   (5, 5, 5));
+        assert_eq!((display.display_horizontal_size, display.display_vertical_size),
+                   (704, 576));
+
+        /* Size extensions and zero sizes */
+        let mut ext = seq_ext.clone();
+        ext.horizontal_size_extension = 1;
+        assert_eq!(seq_hdr.get_size(Some(&ext)), (720 + 4096, 576));
+        assert_eq!(seq_hdr.get_size(None), (720, 576));
+        let data = [0x00, 0x02, 0x40, 0x23, 0xff, 0xff, 0xe0, 0x00];
+        assert!(SequenceHeader::parse(&mut BitReader::new(&data)).is_err());
+    }
+
+    #[test]
+    fn gop_and_pictures() {
+        let unit = &parse_units(GROUP_START_CODE)[0];
+        let gop = GopHeader::parse(&mut BitReader::new(unit.payload())).unwrap();
+        assert_eq!((gop.time_code_hours,
+                    gop.time_code_minutes,
+                    gop.time_code_seconds,
+                    gop.time_code_pictures),
+                   (1, 2, 3, 4));
+        assert!(gop.closed_gop && !gop.broken_link && !gop.drop_frame_flag);
+
+        let pictures = parse_pictures();
+        let types: Vec<(u32, u8, u8)> = pictures.iter()
+            .map(|&(ref hdr, ref ext)| {
+                (hdr.temporal_reference, hdr.picture_coding_type, ext.picture_structure)
+            })
+            .collect();
+        assert_eq!(types,
+                   vec![(2, PICTURE_TYPE_I, PICTURE_STRUCTURE_FRAME),
+                        (5, PICTURE_TYPE_P, PICTURE_STRUCTURE_TOP_FIELD),
+                        (5, PICTURE_TYPE_P, PICTURE_STRUCTURE_BOTTOM_FIELD),
+                        (3, PICTURE_TYPE_B, PICTURE_STRUCTURE_FRAME)]);
+
+        let (ref hdr, ref ext) = pictures[3];
+        assert_eq!(hdr.vbv_delay, 0xffff);
+        assert_eq!((hdr.forward_f_code, hdr.backward_f_code), (7, 7));
+        assert_eq!(ext.f_code, [[1, 2], [1, 2]]);
+        assert_eq!(ext.intra_dc_precision, 2);
+        assert!(ext.top_field_first && ext.frame_pred_frame_dct && ext.q_scale_type);
+        assert!(ext.progressive_frame && !ext.is_field());
+        assert!(pictures[1].1.is_field() && pictures[1].1.alternate_scan);
+
+        /* picture_structure 0 is reserved */
+        let data = [0x8f, 0xff, 0xf0, 0x00, 0x00];
+        assert!(Extension::parse(&mut BitReader::new(&data)).is_err());
+        match Extension::parse(&mut BitReader::new(&[0xa0])).unwrap() {
+            Extension::Unknown(id) => assert_eq!(id, 10),
+            _ => panic!("unexpected extension"),
+        }
+    }
+
+    #[test]
+    fn quant_matrices() {
+        let (seq_hdr, _) = parse_sequence();
+        let mut quant = QuantMatrices::new();
+        assert_eq!(quant.intra[..4], [8, 16, 16, 19]);
+        assert_eq!(quant.non_intra, [16; 64]);
+
+        quant.update_from_sequence(&seq_hdr);
+        assert_eq!(quant.intra[10], 18);
+        assert_eq!(quant.chroma_intra, quant.intra);
+        assert_eq!(quant.non_intra, [16; 64]);
+
+        for ext in parse_extensions() {
+            if let Extension::QuantMatrix(ext) = ext {
+                quant.update_from_extension(&ext);
+            }
+        }
+        assert_eq!(quant.intra[10], 18);
+        assert_eq!(quant.non_intra, [20; 64]);
+        assert_eq!(quant.chroma_intra, [24; 64]);
+        assert_eq!(quant.chroma_non_intra, [20; 64]);
+
+        let iq = build_iq_matrix(&quant);
+        assert_eq!(iq.load_chroma_non_intra_quantiser_matrix, 1);
+        assert_eq!(iq.intra_quantiser_matrix[63], 71);
+        assert_eq!(iq.chroma_intra_quantiser_matrix[0], 24);
+    }
+
+    #[test]
+    fn slices() {
+        let slices: Vec<SliceHeader> = split_start_code_units(MAIN_576I)
+            .iter()
+            .filter(|unit| unit.is_slice())
+            .map(|unit| SliceHeader::parse(unit.data, 576).unwrap())
+            .collect();
+        let positions: Vec<(u32, u32, u32, u32)> = slices.iter()
+            .map(|hdr| {
+                (hdr.slice_vertical_position,
+                 hdr.slice_horizontal_position,
+                 hdr.quantiser_scale_code,
+                 hdr.macroblock_offset)
+            })
+            .collect();
+        assert_eq!(positions,
+                   vec![(0, 0, 8, 38), (1, 33, 10, 56), (2, 1, 12, 38), (0, 0, 9, 38),
+                        (0, 3, 9, 38), (0, 0, 14, 38), (1, 0, 14, 38)]);
+        assert!(slices[1].intra_slice_flag && slices[1].intra_slice);
+        assert!(!slices[0].intra_slice_flag);
+
+        /* The whole buffer as one slice data buffer */
+        let params = build_slice_params(MAIN_576I, 576).unwrap();
+        assert_eq!(params.len(), 7);
+        let layout: Vec<(u32, u32)> = params.iter()
+            .map(|param| (param.slice_data_offset, param.slice_data_size))
+            .collect();
+        assert_eq!(&layout[..3], &[(256, 10), (266, 14), (280, 10)]);
+        assert_eq!(params[1].macroblock_offset, 56);
+        assert_eq!(params[1].slice_horizontal_position, 33);
+        assert_eq!(params[1].intra_slice_flag, 1);
+        assert_eq!(params[0].slice_data_flag, ffi::VA_SLICE_DATA_FLAG_ALL);
+
+        /* slice_vertical_position_extension of pictures taller than 2800 lines */
+        let data = [0x00, 0x00, 0x01, 0x05, 0x44, 0x50];
+        let hdr = SliceHeader::parse(&data, 3000).unwrap();
+        assert_eq!(hdr.slice_vertical_position, 4 + (2 << 7));
+        assert_eq!(hdr.quantiser_scale_code, 4);
+        assert_eq!(hdr.macroblock_offset, 41);
+        assert_eq!(hdr.slice_horizontal_position, 0);
+
+        /* Not a slice, and no macroblock address increment */
+        assert!(SliceHeader::parse(&[0x00, 0x00, 0x01, 0xb3, 0x40, 0x80], 576).is_err());
+        assert!(SliceHeader::parse(&[0x00, 0x00, 0x01, 0x01, 0x40, 0x00], 576).is_err());
+    }
+
+    #[test]
+    fn va_buffers() {
+        let (seq_hdr, seq_ext) = parse_sequence();
+        let pictures = parse_pictures();
+
+        let (ref hdr, ref ext) = pictures[0];
+        let param = build_picture_param(&seq_hdr,
+                                        Some(&seq_ext),
+                                        hdr,
+                                        ext,
+                                        ffi::VA_INVALID_SURFACE,
+                                        ffi::VA_INVALID_SURFACE,
+                                        true).unwrap();
+        assert_eq!((param.horizontal_size, param.vertical_size), (720, 576));
+        assert_eq!(param.picture_coding_type, PICTURE_TYPE_I as i32);
+        assert_eq!(param.f_code, 0xffff);
+        let bits = MPEG2PictureCodingExtension::from_va(&param.picture_coding_extension);
+        assert_eq!(bits.intra_dc_precision, 2);
+        assert_eq!(bits.picture_structure, PICTURE_STRUCTURE_FRAME as u32);
+        assert_eq!(bits.top_field_first, 1);
+        assert_eq!(bits.q_scale_type, 1);
+        assert_eq!(bits.intra_vlc_format, 1);
+        assert_eq!(bits.progressive_frame, 0);
+        assert_eq!(bits.is_first_field, 1);
+
+        let (ref hdr, ref ext) = pictures[2];
+        let param = build_picture_param(&seq_hdr, Some(&seq_ext), hdr, ext, 3, 7, false).unwrap();
+        assert_eq!(param.forward_reference_picture, 3);
+        assert_eq!(param.backward_reference_picture, 7);
+        assert_eq!(param.f_code, 0x12ff);
+        let bits = MPEG2PictureCodingExtension::from_va(&param.picture_coding_extension);
+        assert_eq!(bits.picture_structure, PICTURE_STRUCTURE_BOTTOM_FIELD as u32);
+        assert_eq!(bits.alternate_scan, 1);
+        assert_eq!(bits.is_first_field, 0);
+    }
+
+    #[test]
+    fn test_split_complete_units() {
+        let whole = split_start_code_units(MAIN_576I)
+            .iter()
+            .map(|unit| unit.data.to_vec())
+            .collect::<Vec<_>>();
+
+        /* Chunks cutting through prefixes and units alike, as PES payloads do */
+        for &size in &[1, 2, 5, 184] {
+            let mut pending = Vec::new();
+            let mut units = Vec::new();
+            for chunk in MAIN_576I.chunks(size) {
+                pending.extend_from_slice(chunk);
+                let rest = {
+                    let (complete, rest) = split_complete_units(&pending);
+                    units.extend(complete.iter().map(|unit| unit.data.to_vec()));
+                    rest
+                };
+                pending.drain(..rest);
+            }
+            units.extend(split_start_code_units(&pending).iter().map(|unit| unit.data.to_vec()));
+            assert_eq!(units, whole);
+        }
+
+        let (units, rest) = split_complete_units(&[0x12, 0x34, 0, 0, 1]);
+        assert!(units.is_empty());
+        assert_eq!(rest, 2);
+    }
+}
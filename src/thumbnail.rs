@@ -0,0 +1,172 @@
+// Copyright (c) 2017 zzoon <zzoon@igalia.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::cmp;
+use std::rc::Rc;
+use std::os::raw::{c_int, c_short, c_ushort};
+
+use bitfields::zeroed;
+use ffi;
+use va::*;
+use decoder::*;
+
+/* Video processing pipeline scaling decoded frames into an RGBA surface */
+#[derive(Debug)]
+pub struct VAScaler {
+    config: VAConfig,
+    context: VAContext,
+    surface: Rc<VASurface>,
+    width: u32,
+    height: u32,
+}
+
+impl VAScaler {
+    pub fn new(va_disp: &VADisplay, width: u32, height: u32) -> Result<VAScaler, ()> {
+        let config = VAConfig::new(va_disp, ffi::VAProfileNone, ffi::VAEntrypointVideoProc, 1)?;
+        let surface = match VASurface::new(va_disp, width, height, ffi::VA_RT_FORMAT_RGB32, 1) {
+            Ok(surface) => surface,
+            Err(_) => {
+                config.destroy(va_disp);
+                return Err(());
+            }
+        };
+        let context = match VAContext::new_with_surfaces(va_disp,
+                                                         &config,
+                                                         &[surface.get_id()],
+                                                         width as c_int,
+                                                         height as c_int,
+                                                         ffi::VA_PROGRESSIVE as c_int) {
+            Ok(context) => context,
+            Err(_) => {
+                surface.destroy_surfaces(va_disp);
+                config.destroy(va_disp);
+                return Err(());
+            }
+        };
+
+        Ok(VAScaler {
+            config: config,
+            context: context,
+            surface: Rc::new(surface),
+            width: width,
+            height: height,
+        })
+    }
+
+    /* Scales a decoded frame to the output size, keeping its aspect ratio with black bars
+     * around it, and copies the result to memory as RGBA */
+    pub fn scale(&self, va_disp: &VADisplay, frame: &VADecodedFrame)
+                 -> Result<VAFrameImage, DecodeError> {
+        let mut surface_region: ffi::VARectangle = zeroed();
+        surface_region.width = frame.get_width() as c_ushort;
+        surface_region.height = frame.get_height() as c_ushort;
+        let output_region =
+            fit_region(frame.get_width(), frame.get_height(), self.width, self.height);
+
+        let mut param: ffi::VAProcPipelineParameterBuffer = zeroed();
+        param.surface = frame.get_surface().get_id();
+        param.surface_region = &surface_region;
+        param.output_region = &output_region;
+        param.output_background_color = 0xff000000;
+        param.filter_flags = ffi::VA_FILTER_SCALING_FAST;
+
+        let buffer = VABuffer::from_param(va_disp, &self.context, &param)
+            .map_err(|_| DecodeError::VAFailed)?;
+        let buffers = [buffer];
+        let ret = self.context.begin_picture(va_disp, &self.surface).and_then(|_| {
+            let rendered = self.context.render_picture(va_disp, &buffers);
+            let ended = self.context.end_picture(va_disp);
+            rendered.and(ended)
+        });
+        buffers[0].destroy(va_disp);
+        ret.map_err(|_| DecodeError::VAFailed)?;
+
+        let mut output =
+            VADecodedFrame::new(self.surface.clone(), self.width, self.height, frame.get_pts());
+        output.sync(va_disp)?;
+        output.download(va_disp)
+    }
+
+    pub fn destroy(&self, va_disp: &VADisplay) {
+        self.context.destroy(va_disp);
+        self.surface.destroy_surfaces(va_disp);
+        self.config.destroy(va_disp);
+    }
+}
+
+/* Largest region with the aspect ratio of the source that fits the output, centered */
+fn fit_region(src_width: u32, src_height: u32, width: u32, height: u32) -> ffi::VARectangle {
+    let (src_width, src_height) = (cmp::max(src_width, 1) as u64, cmp::max(src_height, 1) as u64);
+    let (mut w, mut h) = (width as u64, height as u64);
+    if src_width * h > w * src_height {
+        h = cmp::max(src_height * w / src_width, 1);
+    } else {
+        w = cmp::max(src_width * h / src_height, 1);
+    }
+
+    let mut region: ffi::VARectangle = zeroed();
+    region.x = ((width as u64 - w) / 2) as c_short;
+    region.y = ((height as u64 - h) / 2) as c_short;
+    region.width = w as c_ushort;
+    region.height = h as c_ushort;
+    region
+}
+
+/* Decodes the keyframe of the given index, 0 for the first one, of data starting at a seek
+ * point and scales it into an RGBA thumbnail. Only keyframes are submitted to VA and
+ * decoding stops at the one wanted; the decoder is flushed and left in keyframe mode */
+pub fn decode_thumbnail<I, D>(va_disp: &VADisplay,
+                              decoder: &mut VADecoder,
+                              data: I,
+                              keyframe: usize,
+                              width: u32,
+                              height: u32)
+                              -> Result<VAFrameImage, DecodeError>
+    where I: IntoIterator<Item = (D, u64)>,
+          D: AsRef<[u8]>
+{
+    decoder.flush();
+    decoder.set_decode_mode(DecodeMode::KeyframesOnly);
+
+    let mut units = data.into_iter();
+    let mut count = 0;
+    let mut frame = None;
+    while frame.is_none() {
+        let end = match units.next() {
+            Some((unit, pts)) => {
+                decoder.decode(va_disp, unit.as_ref(), pts)?;
+                false
+            }
+            None => {
+                decoder.drain(va_disp)?;
+                true
+            }
+        };
+        while let Some(decoded) = decoder.get_frame() {
+            if count == keyframe {
+                frame = Some(decoded);
+                break;
+            }
+            count += 1;
+        }
+        if end && frame.is_none() {
+            return Err(DecodeError::InvalidStream);
+        }
+    }
+
+    let mut frame = frame.unwrap();
+    let ret = frame.sync(va_disp)
+        .and_then(|_| VAScaler::new(va_disp, width, height).map_err(|_| DecodeError::VAFailed))
+        .and_then(|scaler| {
+            let ret = scaler.scale(va_disp, &frame);
+            scaler.destroy(va_disp);
+            ret
+        });
+    decoder.flush();
+    ret
+}
@@ -11,9 +11,15 @@
 
 use libc;
 use std::os::raw::{c_uint, c_int, c_void, c_short, c_ushort};
+use std::cell::Cell;
+use std::mem;
 use std::ptr;
+use std::slice;
 use std::ffi::CString;
+use std::thread;
+use std::time::{Duration, Instant};
 
+use bitfields::zeroed;
 use ffi;
 use ffi_x11;
 
@@ -26,6 +32,7 @@ pub type VAEntrypoint = ffi::VAEntrypoint;
 pub type VABufferType = ffi::VABufferType;
 
 pub const VA_STATUS_SUCCESS: i32 = ffi::VA_STATUS_SUCCESS as i32;
+pub const VA_STATUS_ERROR_DECODING_ERROR: i32 = ffi::VA_STATUS_ERROR_DECODING_ERROR as i32;
 
 #[derive(Debug)]
 pub struct VADisplay {
@@ -194,6 +201,18 @@ impl VAImage {
         self.image.image_id
     }
 
+    pub fn get_fourcc(&self) -> u32 {
+        self.image.format.fourcc
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.image.width as u32
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.image.height as u32
+    }
+
     pub fn get_num_planes(&self) -> u32 {
         self.image.num_planes
     }
@@ -215,6 +234,80 @@ impl VAImage {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VAMacroblockErrors {
+    pub start_mb: u32,
+    pub end_mb: u32,
+    pub error_type: ffi::VADecodeErrorType,
+}
+
+/* Macroblock ranges the driver could not decode, in raster order */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VADecodeErrorReport {
+    pub ranges: Vec<VAMacroblockErrors>,
+}
+
+impl VADecodeErrorReport {
+    /* Copies the array returned by vaQuerySurfaceError, terminated by a status of -1;
+     * `errors` must be null or point to such an array */
+    unsafe fn from_raw(mut errors: *const ffi::VASurfaceDecodeMBErrors) -> VADecodeErrorReport {
+        let mut report = VADecodeErrorReport::default();
+        if errors.is_null() {
+            return report;
+        }
+        while (*errors).status != -1 {
+            report.ranges.push(VAMacroblockErrors {
+                start_mb: (*errors).start_mb,
+                end_mb: (*errors).end_mb,
+                error_type: (*errors).decode_error_type,
+            });
+            errors = errors.offset(1);
+        }
+        report
+    }
+
+    pub fn get_num_damaged_mbs(&self) -> u32 {
+        self.ranges.iter().map(|r| r.end_mb.saturating_sub(r.start_mb) + 1).sum()
+    }
+
+    pub fn has_missing_slices(&self) -> bool {
+        self.ranges.iter().any(|r| r.error_type == ffi::VADecodeSliceMissing)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VASyncError {
+    /* The surface holds a picture decoded with errors */
+    Decoding(VADecodeErrorReport),
+    Failed(ffi::VAStatus),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SurfaceStatus {
+    Rendering,
+    Displaying,
+    Ready,
+    Skipped,
+}
+
+impl SurfaceStatus {
+    /* The driver reports a mask, pending rendering wins over the other states */
+    fn from_mask(mask: c_uint) -> SurfaceStatus {
+        if mask & ffi::VASurfaceRendering as c_uint != 0 {
+            SurfaceStatus::Rendering
+        } else if mask & ffi::VASurfaceDisplaying as c_uint != 0 {
+            SurfaceStatus::Displaying
+        } else if mask & ffi::VASurfaceSkipped as c_uint != 0 {
+            SurfaceStatus::Skipped
+        } else {
+            SurfaceStatus::Ready
+        }
+    }
+}
+
+/* Interval between two status queries while waiting for surfaces */
+const SURFACE_POLL_INTERVAL_MS: u64 = 1;
+
 #[derive(Debug)]
 pub struct VASurface {
     id: ffi::VASurfaceID,
@@ -222,6 +315,12 @@ pub struct VASurface {
     width: c_uint,
     height: c_uint,
     num_surfaces: c_uint,
+    /* End of the submission of the picture being decoded, until the surface is synced */
+    submitted: Cell<Option<Instant>>,
+    /* Submit to ready time of the last picture */
+    latency: Cell<Option<Duration>>,
+    /* Pictures synced so far and the sum of their latencies */
+    latencies: Cell<(u64, Duration)>,
 }
 
 impl VASurface {
@@ -246,6 +345,9 @@ impl VASurface {
                     width: width,
                     height: height,
                     num_surfaces: num_surfaces,
+                    submitted: Cell::new(None),
+                    latency: Cell::new(None),
+                    latencies: Cell::new((0, Duration::from_secs(0))),
                 })
             }
             _ => return Err(()),
@@ -299,14 +401,102 @@ impl VASurface {
         }
     }
 
-    pub fn sync(&self, va_disp: &VADisplay) {
-        match va_sync_surface(va_disp.disp, self.id) {
-            VA_STATUS_SUCCESS => {
-                println!("sync surface success!");
-            }
+    /* Copies the surface to a new image in the pixel format of its render target, for
+     * drivers that cannot derive images */
+    pub fn get_image(&self, va_disp: &VADisplay) -> Result<VAImage, ()> {
+        let (fourcc, bits_per_pixel) = match get_pixel_format(self.format) {
+            Some(ffi::VA_FOURCC_P010) => (ffi::VA_FOURCC_P010, 24),
+            Some(ffi::VA_FOURCC_RGBA) => (ffi::VA_FOURCC_RGBA, 32),
+            Some(_) => (ffi::VA_FOURCC_NV12, 12),
+            None => return Err(()),
+        };
+        let format = VAImageFormat::new(ffi::VAImageFormat {
+            fourcc: fourcc,
+            byte_order: ffi::VA_LSB_FIRST,
+            bits_per_pixel: bits_per_pixel,
+            ..Default::default()
+        });
+        let image = VAImage::new(va_disp, &format, self.width as c_int, self.height as c_int)?;
+
+        match va_get_image(va_disp.disp, self.id, self.width, self.height, image.get_id()) {
+            VA_STATUS_SUCCESS => Ok(image),
             _ => {
-                println!("sync surface fail!");
+                image.destroy(va_disp);
+                Err(())
+            }
+        }
+    }
+
+    /* Starts timing the picture submitted to the surface, after vaEndPicture */
+    pub fn mark_submitted(&self) {
+        self.submitted.set(Some(Instant::now()));
+        self.latency.set(None);
+    }
+
+    fn mark_ready(&self) {
+        if let Some(submitted) = self.submitted.take() {
+            let latency = submitted.elapsed();
+            let (count, total) = self.latencies.get();
+            self.latency.set(Some(latency));
+            self.latencies.set((count + 1, total + latency));
+        }
+    }
+
+    /* Submit to ready time of the last picture, once the surface is synced */
+    pub fn get_latency(&self) -> Option<Duration> {
+        self.latency.get()
+    }
+
+    /* Number of pictures timed and the sum of their latencies */
+    pub fn get_latencies(&self) -> (u64, Duration) {
+        self.latencies.get()
+    }
+
+    pub fn sync(&self, va_disp: &VADisplay) -> Result<(), VASyncError> {
+        let status = va_sync_surface(va_disp.disp, self.id);
+        if status == VA_STATUS_SUCCESS || status == VA_STATUS_ERROR_DECODING_ERROR {
+            self.mark_ready();
+        }
+        match status {
+            VA_STATUS_SUCCESS => Ok(()),
+            VA_STATUS_ERROR_DECODING_ERROR => {
+                /* Drivers without macroblock level reporting give an empty report */
+                Err(VASyncError::Decoding(self.query_errors(va_disp).unwrap_or_default()))
+            }
+            status => Err(VASyncError::Failed(status)),
+        }
+    }
+
+    pub fn status(&self, va_disp: &VADisplay) -> Result<SurfaceStatus, ()> {
+        let mut mask = 0;
+        match va_query_surface_status(va_disp.disp, self.id, &mut mask) {
+            VA_STATUS_SUCCESS => Ok(SurfaceStatus::from_mask(mask)),
+            _ => Err(()),
+        }
+    }
+
+    /* Syncs only when the rendering is over, so it never blocks; tells whether the
+     * surface is ready */
+    pub fn try_sync(&self, va_disp: &VADisplay) -> Result<bool, VASyncError> {
+        match self.status(va_disp) {
+            Ok(SurfaceStatus::Rendering) => Ok(false),
+            Ok(_) => self.sync(va_disp).map(|_| true),
+            Err(_) => Err(VASyncError::Failed(ffi::VA_STATUS_ERROR_OPERATION_FAILED as i32)),
+        }
+    }
+
+    /* Damaged macroblocks of the last decoding into the surface */
+    pub fn query_errors(&self, va_disp: &VADisplay) -> Result<VADecodeErrorReport, ()> {
+        let mut error_info = ptr::null_mut();
+        match va_query_surface_error(va_disp.disp,
+                                     self.id,
+                                     VA_STATUS_ERROR_DECODING_ERROR,
+                                     &mut error_info) {
+            VA_STATUS_SUCCESS => {
+                let errors = error_info as *const ffi::VASurfaceDecodeMBErrors;
+                Ok(unsafe { VADecodeErrorReport::from_raw(errors) })
             }
+            _ => Err(()),
         }
     }
 
@@ -319,6 +509,30 @@ impl VASurface {
     }
 }
 
+/* Polls the surfaces until all of them are done rendering or the timeout expires, and
+ * gives the indices of those that are done; syncing them will not block */
+pub fn wait_surfaces(va_disp: &VADisplay,
+                     surfaces: &[&VASurface],
+                     timeout: Duration)
+                     -> Result<Vec<usize>, ()> {
+    let deadline = Instant::now() + timeout;
+    let mut done = vec![false; surfaces.len()];
+
+    loop {
+        for (i, surface) in surfaces.iter().enumerate() {
+            if !done[i] {
+                done[i] = surface.status(va_disp)? != SurfaceStatus::Rendering;
+            }
+        }
+        if done.iter().all(|&d| d) || Instant::now() >= deadline {
+            break;
+        }
+        thread::sleep(Duration::from_millis(SURFACE_POLL_INTERVAL_MS));
+    }
+
+    Ok((0..surfaces.len()).filter(|&i| done[i]).collect())
+}
+
 #[derive(Debug)]
 pub struct VAConfig {
     id: ffi::VAConfigID,
@@ -334,10 +548,8 @@ impl VAConfig {
                entrypoint: VAEntrypoint,
                attr_num: c_int)
                -> Result<VAConfig, ()> {
-        let mut attr_list = ffi::VAConfigAttrib {
-            type_: ffi::VAConfigAttribRTFormat,
-            value: 0,
-        };
+        let mut attr_list: ffi::VAConfigAttrib = zeroed();
+        attr_list.type_ = ffi::VAConfigAttribRTFormat;
         match va_get_config_attributes(va_disp.disp,
                                        profile,
                                        entrypoint,
@@ -422,6 +634,57 @@ impl VAContext {
         }
     }
 
+    pub fn new_with_surfaces(va_disp: &VADisplay,
+                             va_config: &VAConfig,
+                             surfaces: &[ffi::VASurfaceID],
+                             width: c_int,
+                             height: c_int,
+                             flag: c_int)
+                             -> Result<VAContext, ()> {
+        let mut id = 0;
+        let mut render_targets = surfaces.to_vec();
+        match va_create_context(va_disp.disp,
+                                va_config.id,
+                                width,
+                                height,
+                                flag,
+                                render_targets.as_mut_ptr(),
+                                render_targets.len() as c_int,
+                                &mut id) {
+            VA_STATUS_SUCCESS => {
+                Ok(VAContext {
+                    id: id,
+                    width: width,
+                    height: height,
+                    flag: flag,
+                })
+            }
+            _ => return Err(()),
+        }
+    }
+
+    pub fn begin_picture(&self, va_disp: &VADisplay, va_surface: &VASurface) -> Result<(), ()> {
+        match va_begin_picture(va_disp.disp, self.id, va_surface.id) {
+            VA_STATUS_SUCCESS => Ok(()),
+            _ => Err(()),
+        }
+    }
+
+    pub fn render_picture(&self, va_disp: &VADisplay, buffers: &[VABuffer]) -> Result<(), ()> {
+        let mut ids: Vec<ffi::VABufferID> = buffers.iter().map(|b| b.id).collect();
+        match va_render_picture(va_disp.disp, self.id, ids.as_mut_ptr(), ids.len() as c_int) {
+            VA_STATUS_SUCCESS => Ok(()),
+            _ => Err(()),
+        }
+    }
+
+    pub fn end_picture(&self, va_disp: &VADisplay) -> Result<(), ()> {
+        match va_end_picture(va_disp.disp, self.id) {
+            VA_STATUS_SUCCESS => Ok(()),
+            _ => Err(()),
+        }
+    }
+
     pub fn destroy(&self, va_disp: &VADisplay) {
         match va_destroy_context(va_disp.disp, self.id) {
             VA_STATUS_SUCCESS => {
@@ -438,6 +701,113 @@ impl VAContext {
     }
 }
 
+/* Parameter structures and the type of the buffers they are submitted in */
+pub trait ParamBuffer {
+    fn get_buffer_type() -> VABufferType;
+}
+
+/* Encoder parameters submitted after a VAEncMiscParameterBuffer header */
+pub trait MiscParamBuffer {
+    fn get_misc_type() -> ffi::VAEncMiscParameterType;
+}
+
+macro_rules! param_buffers {
+    ($($buffer_type:ident => [$($param:ident),+],)+) => {
+        $($(impl ParamBuffer for ffi::$param {
+            fn get_buffer_type() -> VABufferType {
+                ffi::$buffer_type
+            }
+        })+)+
+    }
+}
+
+param_buffers! {
+    VAPictureParameterBufferType => [VAPictureParameterBufferH264,
+                                     VAPictureParameterBufferHEVC,
+                                     VAPictureParameterBufferJPEGBaseline,
+                                     VAPictureParameterBufferMPEG2,
+                                     VAPictureParameterBufferMPEG4,
+                                     VAPictureParameterBufferVC1,
+                                     VAPictureParameterBufferVP8,
+                                     VADecPictureParameterBufferVP9],
+    VASliceParameterBufferType => [VASliceParameterBufferBase,
+                                   VASliceParameterBufferBaseHEVC,
+                                   VASliceParameterBufferH264,
+                                   VASliceParameterBufferHEVC,
+                                   VASliceParameterBufferJPEGBaseline,
+                                   VASliceParameterBufferMPEG2,
+                                   VASliceParameterBufferMPEG4,
+                                   VASliceParameterBufferVC1,
+                                   VASliceParameterBufferVP8,
+                                   VASliceParameterBufferVP9],
+    VAIQMatrixBufferType => [VAIQMatrixBufferH264,
+                             VAIQMatrixBufferHEVC,
+                             VAIQMatrixBufferJPEGBaseline,
+                             VAIQMatrixBufferMPEG2,
+                             VAIQMatrixBufferMPEG4,
+                             VAIQMatrixBufferVP8],
+    VAQMatrixBufferType => [VAQMatrixBufferHEVC, VAQMatrixBufferJPEG, VAQMatrixBufferVP8],
+    VAHuffmanTableBufferType => [VAHuffmanTableBufferJPEGBaseline],
+    VAProbabilityBufferType => [VAProbabilityDataBufferVP8],
+    VAMacroblockParameterBufferType => [VAMacroblockParameterBufferMPEG2],
+    VAEncSequenceParameterBufferType => [VAEncSequenceParameterBufferH263,
+                                         VAEncSequenceParameterBufferH264,
+                                         VAEncSequenceParameterBufferHEVC,
+                                         VAEncSequenceParameterBufferMPEG2,
+                                         VAEncSequenceParameterBufferMPEG4,
+                                         VAEncSequenceParameterBufferVP8,
+                                         VAEncSequenceParameterBufferVP9],
+    VAEncPictureParameterBufferType => [VAEncPictureParameterBufferH263,
+                                        VAEncPictureParameterBufferH264,
+                                        VAEncPictureParameterBufferHEVC,
+                                        VAEncPictureParameterBufferJPEG,
+                                        VAEncPictureParameterBufferMPEG2,
+                                        VAEncPictureParameterBufferMPEG4,
+                                        VAEncPictureParameterBufferVP8,
+                                        VAEncPictureParameterBufferVP9],
+    VAEncSliceParameterBufferType => [VAEncSliceParameterBuffer,
+                                      VAEncSliceParameterBufferH264,
+                                      VAEncSliceParameterBufferHEVC,
+                                      VAEncSliceParameterBufferJPEG,
+                                      VAEncSliceParameterBufferMPEG2],
+    VAEncPackedHeaderParameterBufferType => [VAEncPackedHeaderParameterBuffer],
+    VAEncMacroblockParameterBufferType => [VAEncMacroblockParameterBufferH264],
+    /* The only misc parameter carrying its own header */
+    VAEncMiscParameterBufferType => [VAEncMiscParameterBufferMaxFrameSize],
+    VAProcPipelineParameterBufferType => [VAProcPipelineParameterBuffer],
+    VAProcFilterParameterBufferType => [VAProcFilterParameterBuffer,
+                                        VAProcFilterParameterBufferBase,
+                                        VAProcFilterParameterBufferColorBalance,
+                                        VAProcFilterParameterBufferDeinterlacing],
+}
+
+#[cfg(va_av1_decode)]
+param_buffers! {
+    VAPictureParameterBufferType => [VADecPictureParameterBufferAV1],
+    VASliceParameterBufferType => [VASliceParameterBufferAV1],
+}
+
+macro_rules! misc_param_buffers {
+    ($($misc_type:ident => $param:ident,)+) => {
+        $(impl MiscParamBuffer for ffi::$param {
+            fn get_misc_type() -> ffi::VAEncMiscParameterType {
+                ffi::$misc_type
+            }
+        })+
+    }
+}
+
+misc_param_buffers! {
+    VAEncMiscParameterTypeFrameRate => VAEncMiscParameterFrameRate,
+    VAEncMiscParameterTypeRateControl => VAEncMiscParameterRateControl,
+    VAEncMiscParameterTypeMaxSliceSize => VAEncMiscParameterMaxSliceSize,
+    VAEncMiscParameterTypeAIR => VAEncMiscParameterAIR,
+    VAEncMiscParameterTypeHRD => VAEncMiscParameterHRD,
+    VAEncMiscParameterTypeQualityLevel => VAEncMiscParameterBufferQualityLevel,
+    VAEncMiscParameterTypeSkipFrame => VAEncMiscParameterSkipFrame,
+    VAEncMiscParameterTypeROI => VAEncMiscParameterBufferROI,
+}
+
 #[derive(Debug)]
 pub struct VABuffer {
     id: ffi::VABufferID,
@@ -472,6 +842,49 @@ impl VABuffer {
         }
     }
 
+    /* Buffer of the type and size of the parameters */
+    pub fn from_param<T: ParamBuffer>(va_disp: &VADisplay,
+                                      va_context: &VAContext,
+                                      param: &T)
+                                      -> Result<VABuffer, ()> {
+        VABuffer::from_params(va_disp, va_context, slice::from_ref(param))
+    }
+
+    /* One buffer with an element per parameter structure, e.g. for several slices */
+    pub fn from_params<T: ParamBuffer>(va_disp: &VADisplay,
+                                       va_context: &VAContext,
+                                       params: &[T])
+                                       -> Result<VABuffer, ()> {
+        VABuffer::new(va_disp,
+                      va_context,
+                      T::get_buffer_type(),
+                      mem::size_of::<T>() as c_uint,
+                      params.len() as c_uint,
+                      params.as_ptr() as *mut c_void)
+    }
+
+    /* VAEncMiscParameterBuffer header followed by the parameters */
+    pub fn from_misc_param<T: MiscParamBuffer>(va_disp: &VADisplay,
+                                               va_context: &VAContext,
+                                               param: &T)
+                                               -> Result<VABuffer, ()> {
+        let mut header: ffi::VAEncMiscParameterBuffer = zeroed();
+        header.type_ = T::get_misc_type();
+        let mut data = Vec::with_capacity(mem::size_of_val(&header) + mem::size_of::<T>());
+        unsafe {
+            data.extend_from_slice(slice::from_raw_parts(&header as *const _ as *const u8,
+                                                         mem::size_of_val(&header)));
+            data.extend_from_slice(slice::from_raw_parts(param as *const T as *const u8,
+                                                         mem::size_of::<T>()));
+        }
+        VABuffer::new(va_disp,
+                      va_context,
+                      ffi::VAEncMiscParameterBufferType,
+                      data.len() as c_uint,
+                      1,
+                      data.as_mut_ptr() as *mut c_void)
+    }
+
     pub fn destroy(&self, va_disp: &VADisplay) {
         match va_destroy_buffer(va_disp.disp, self.id) {
             VA_STATUS_SUCCESS => {
@@ -483,11 +896,16 @@ impl VABuffer {
         }
     }
 
-    pub fn map(&self, va_disp: &VADisplay) -> *mut u8 {
+    /* Contents of the buffer as elements of T, null when it cannot be mapped or holds
+     * less than one of them */
+    pub fn map<T>(&self, va_disp: &VADisplay) -> *mut T {
+        if !self.holds::<T>() {
+            return ptr::null_mut();
+        }
         let mut p_buf = ptr::null_mut();
         match va_map_buffer(va_disp.disp, self.id, &mut p_buf) {
             VA_STATUS_SUCCESS => {
-                p_buf as *mut u8
+                p_buf as *mut T
             }
             _ => {
                 ptr::null_mut()
@@ -506,10 +924,34 @@ impl VABuffer {
         }
     }
 
+    fn holds<T>(&self) -> bool {
+        (self.size as usize) * (self.num_elem as usize) >= mem::size_of::<T>()
+    }
+
+    fn check_num_elements(&self, num_elem: c_uint) -> Result<(), ()> {
+        if num_elem <= self.num_elem { Ok(()) } else { Err(()) }
+    }
+
+    /* Elements of the buffer the driver reads, no more than it was created with */
+    pub fn set_num_elements(&mut self, va_disp: &VADisplay, num_elem: c_uint) -> Result<(), ()> {
+        self.check_num_elements(num_elem)?;
+        match va_buffer_set_num_elements(va_disp.disp, self.id, num_elem) {
+            VA_STATUS_SUCCESS => {
+                self.num_elem = num_elem;
+                Ok(())
+            }
+            _ => Err(()),
+        }
+    }
+
     pub fn get_size(&self) -> u32 {
         self.size
     }
 
+    pub fn get_num_elements(&self) -> u32 {
+        self.num_elem
+    }
+
     pub fn get_id(&self) -> ffi::VABufferID {
         self.id
     }
@@ -537,6 +979,17 @@ pub fn va_max_num_entrypoints(disp: ffi::VADisplay) -> c_int {
     unsafe { ffi::vaMaxNumEntrypoints(disp) }
 }
 
+/* Pixel format of the surfaces of a render target format, samples of high bit depth
+ * ones sit in the high bits of 16-bit words */
+pub fn get_pixel_format(format: c_uint) -> Option<c_uint> {
+    match format {
+        ffi::VA_RT_FORMAT_YUV420 => Some(ffi::VA_FOURCC_NV12),
+        ffi::VA_RT_FORMAT_YUV420_10BPP => Some(ffi::VA_FOURCC_P010),
+        ffi::VA_RT_FORMAT_RGB32 => Some(ffi::VA_FOURCC_RGBA),
+        _ => None,
+    }
+}
+
 pub fn va_create_surfaces(disp: ffi::VADisplay,
                           width: c_uint,
                           height: c_uint,
@@ -544,22 +997,22 @@ pub fn va_create_surfaces(disp: ffi::VADisplay,
                           num_surfaces: c_uint,
                           surfaces: *mut ffi::VASurfaceID)
                           -> ffi::VAStatus {
+    /* VAGenericValueType has no zero member, so only the value union starts zeroed */
+    let mut value: ffi::VAGenericValue_value = zeroed();
     unsafe {
+        *value.i.as_mut() = get_pixel_format(format).unwrap_or(0) as i32;
         let mut attr = ffi::VASurfaceAttrib {
             type_: ffi::VASurfaceAttribPixelFormat,
             flags: ffi::VA_SURFACE_ATTRIB_SETTABLE,
             value: ffi::VAGenericValue {
                 type_: ffi::VAGenericValueTypeInteger,
-                value: ffi::_VAGenericValue__bindgen_ty_1 {
-                    i: Default::default(),
-                    f: Default::default(),
-                    p: Default::default(),
-                    fn_: Default::default(),
-                    bindgen_union_field: ffi::VA_FOURCC_NV12 as u64,
-                },
+                value: value,
             },
         };
 
+        /* Other render targets are left to the default format of the driver */
+        let num_attribs = if get_pixel_format(format).is_some() { 1 } else { 0 };
+
         ffi::vaCreateSurfaces(disp,
                               format,
                               width,
@@ -567,7 +1020,7 @@ pub fn va_create_surfaces(disp: ffi::VADisplay,
                               surfaces,
                               num_surfaces,
                               &mut attr,
-                              1)
+                              num_attribs)
     }
 }
 
@@ -582,6 +1035,22 @@ pub fn va_sync_surface(disp: ffi::VADisplay, target: ffi::VASurfaceID) -> ffi::V
     unsafe { ffi::vaSyncSurface(disp, target) }
 }
 
+pub fn va_query_surface_status(disp: ffi::VADisplay,
+                               surface: ffi::VASurfaceID,
+                               status: *mut c_uint)
+                               -> ffi::VAStatus {
+    /* Read back as an integer, the driver may set several bits of the enum */
+    unsafe { ffi::vaQuerySurfaceStatus(disp, surface, status as *mut ffi::VASurfaceStatus) }
+}
+
+pub fn va_query_surface_error(disp: ffi::VADisplay,
+                              surface: ffi::VASurfaceID,
+                              error_status: ffi::VAStatus,
+                              error_info: *mut *mut c_void)
+                              -> ffi::VAStatus {
+    unsafe { ffi::vaQuerySurfaceError(disp, surface, error_status, error_info) }
+}
+
 pub fn va_get_config_attributes(disp: ffi::VADisplay,
                                 profile: ffi::VAProfile,
                                 entrypoint: ffi::VAEntrypoint,
@@ -718,6 +1187,13 @@ pub fn va_destroy_buffer(disp: ffi::VADisplay, id: ffi::VABufferID) -> ffi::VASt
     unsafe { ffi::vaDestroyBuffer(disp, id) }
 }
 
+pub fn va_buffer_set_num_elements(disp: ffi::VADisplay,
+                                  id: ffi::VABufferID,
+                                  num_elements: c_uint)
+                                  -> ffi::VAStatus {
+    unsafe { ffi::vaBufferSetNumElements(disp, id, num_elements) }
+}
+
 pub fn va_map_buffer(disp: ffi::VADisplay,
                      id: ffi::VABufferID,
                      pbuf: *mut *mut c_void)
@@ -755,6 +1231,15 @@ pub fn va_derive_image(disp: ffi::VADisplay,
     unsafe { ffi::vaDeriveImage(disp, surface_id, image) }
 }
 
+pub fn va_get_image(disp: ffi::VADisplay,
+                    surface_id: ffi::VASurfaceID,
+                    width: c_uint,
+                    height: c_uint,
+                    image: ffi::VAImageID)
+                    -> ffi::VAStatus {
+    unsafe { ffi::vaGetImage(disp, surface_id, 0, 0, width, height, image) }
+}
+
 pub fn va_begin_picture(disp: ffi::VADisplay,
                         context_id: ffi::VAContextID,
                         surface_id: ffi::VASurfaceID)
@@ -800,3 +1285,76 @@ pub fn va_put_image(disp: ffi::VADisplay,
                         dst_h)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_param_buffer_types() {
+        assert_eq!(ffi::VAPictureParameterBufferH264::get_buffer_type(),
+                   ffi::VAPictureParameterBufferType);
+        assert_eq!(ffi::VASliceParameterBufferH264::get_buffer_type(),
+                   ffi::VASliceParameterBufferType);
+        assert_eq!(ffi::VAIQMatrixBufferMPEG2::get_buffer_type(), ffi::VAIQMatrixBufferType);
+        assert_eq!(ffi::VAQMatrixBufferJPEG::get_buffer_type(), ffi::VAQMatrixBufferType);
+        assert_eq!(ffi::VAHuffmanTableBufferJPEGBaseline::get_buffer_type(),
+                   ffi::VAHuffmanTableBufferType);
+        assert_eq!(ffi::VAProbabilityDataBufferVP8::get_buffer_type(),
+                   ffi::VAProbabilityBufferType);
+        assert_eq!(ffi::VAEncSequenceParameterBufferHEVC::get_buffer_type(),
+                   ffi::VAEncSequenceParameterBufferType);
+        assert_eq!(ffi::VAEncSliceParameterBuffer::get_buffer_type(),
+                   ffi::VAEncSliceParameterBufferType);
+        assert_eq!(ffi::VAEncMiscParameterBufferMaxFrameSize::get_buffer_type(),
+                   ffi::VAEncMiscParameterBufferType);
+        assert_eq!(ffi::VAProcPipelineParameterBuffer::get_buffer_type(),
+                   ffi::VAProcPipelineParameterBufferType);
+    }
+
+    #[cfg(va_av1_decode)]
+    #[test]
+    fn test_av1_param_buffer_types() {
+        assert_eq!(ffi::VADecPictureParameterBufferAV1::get_buffer_type(),
+                   ffi::VAPictureParameterBufferType);
+        assert_eq!(ffi::VASliceParameterBufferAV1::get_buffer_type(),
+                   ffi::VASliceParameterBufferType);
+    }
+
+    #[test]
+    fn test_misc_param_buffer_types() {
+        assert_eq!(ffi::VAEncMiscParameterFrameRate::get_misc_type(),
+                   ffi::VAEncMiscParameterTypeFrameRate);
+        assert_eq!(ffi::VAEncMiscParameterRateControl::get_misc_type(),
+                   ffi::VAEncMiscParameterTypeRateControl);
+        assert_eq!(ffi::VAEncMiscParameterHRD::get_misc_type(), ffi::VAEncMiscParameterTypeHRD);
+    }
+
+    /* Buffer as from_params() creates it, never passed to VA */
+    fn array_buffer<T>(num_elem: c_uint) -> VABuffer {
+        VABuffer {
+            id: ffi::VA_INVALID_ID,
+            size: mem::size_of::<T>() as c_uint,
+            num_elem: num_elem,
+        }
+    }
+
+    #[test]
+    fn test_num_elements() {
+        let buffer = array_buffer::<ffi::VASliceParameterBufferH264>(4);
+        assert_eq!(buffer.check_num_elements(0), Ok(()));
+        assert_eq!(buffer.check_num_elements(4), Ok(()));
+        assert_eq!(buffer.check_num_elements(5), Err(()));
+    }
+
+    #[test]
+    fn test_map_size() {
+        let buffer = array_buffer::<ffi::VASliceParameterBufferH264>(2);
+        assert!(buffer.holds::<ffi::VASliceParameterBufferH264>());
+        assert!(buffer.holds::<[ffi::VASliceParameterBufferH264; 2]>());
+        assert!(!buffer.holds::<[ffi::VASliceParameterBufferH264; 3]>());
+
+        let buffer = array_buffer::<ffi::VASliceParameterBufferBase>(1);
+        assert!(!buffer.holds::<ffi::VASliceParameterBufferH264>());
+    }
+}
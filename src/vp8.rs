@@ -0,0 +1,1104 @@
+// Copyright (c) 2017 zzoon <zzoon@igalia.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/* Parser for the VP8 frame headers of RFC 6386 needed to drive VA decoding: the
+ * uncompressed data chunk, the bool coded header at the start of the first partition
+ * with the probability context kept across frames, and their conversion into the VA
+ * buffers. */
+
+use std::cmp;
+
+use bitfields::{VP8PicFields, zeroed};
+use ffi;
+
+pub const KEY_FRAME_START_CODE: [u8; 3] = [0x9d, 0x01, 0x2a];
+pub const FRAME_TAG_BYTES: usize = 3;
+pub const KEY_FRAME_HEADER_BYTES: usize = 10;
+
+pub const MAX_SEGMENTS: usize = 4;
+pub const MAX_PARTITIONS: usize = 8;
+
+pub const SEGMENT_FEATURE_MODE_DELTA: u8 = 0;
+pub const SEGMENT_FEATURE_MODE_ABSOLUTE: u8 = 1;
+
+const MAX_QUANT_INDEX: i32 = 127;
+const MAX_LOOP_FILTER_LEVEL: i32 = 63;
+
+pub const DEFAULT_Y_MODE_PROBS: [u8; 4] = [112, 86, 140, 37];
+pub const DEFAULT_UV_MODE_PROBS: [u8; 3] = [162, 101, 204];
+
+/* Section 17.2, row then column: is_short, sign, short tree and long bits */
+pub const DEFAULT_MV_PROBS: [[u8; 19]; 2] = [[162, 128, 225, 146, 172, 147, 214, 39, 156, 128,
+                                              129, 132, 75, 145, 178, 206, 239, 254, 254],
+                                             [164, 128, 204, 170, 119, 235, 140, 230, 228, 128,
+                                              130, 130, 74, 148, 180, 203, 236, 254, 254]];
+pub const MV_UPDATE_PROBS: [[u8; 19]; 2] = [[237, 246, 253, 253, 254, 254, 254, 254, 254, 254,
+                                             254, 254, 254, 254, 250, 250, 252, 254, 254],
+                                            [231, 243, 245, 253, 254, 254, 254, 254, 254, 254,
+                                             254, 254, 254, 254, 251, 251, 254, 254, 254]];
+
+pub type CoeffProbs = [[[[u8; 11]; 3]; 8]; 4];
+
+/* Section 13.5 */
+pub const DEFAULT_COEFF_PROBS: CoeffProbs =
+    [[[[128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128],
+       [128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128],
+       [128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128]],
+      [[253, 136, 254, 255, 228, 219, 128, 128, 128, 128, 128],
+       [189, 129, 242, 255, 227, 213, 255, 219, 128, 128, 128],
+       [106, 126, 227, 252, 214, 209, 255, 255, 128, 128, 128]],
+      [[1, 98, 248, 255, 236, 226, 255, 255, 128, 128, 128],
+       [181, 133, 238, 254, 221, 234, 255, 154, 128, 128, 128],
+       [78, 134, 202, 247, 198, 180, 255, 219, 128, 128, 128]],
+      [[1, 185, 249, 255, 243, 255, 128, 128, 128, 128, 128],
+       [184, 150, 247, 255, 236, 224, 128, 128, 128, 128, 128],
+       [77, 110, 216, 255, 236, 230, 128, 128, 128, 128, 128]],
+      [[1, 101, 251, 255, 241, 255, 128, 128, 128, 128, 128],
+       [170, 139, 241, 252, 236, 209, 255, 255, 128, 128, 128],
+       [37, 116, 196, 243, 228, 255, 255, 255, 128, 128, 128]],
+      [[1, 204, 254, 255, 245, 255, 128, 128, 128, 128, 128],
+       [207, 160, 250, 255, 238, 128, 128, 128, 128, 128, 128],
+       [102, 103, 231, 255, 211, 171, 128, 128, 128, 128, 128]],
+      [[1, 152, 252, 255, 240, 255, 128, 128, 128, 128, 128],
+       [177, 135, 243, 255, 234, 225, 128, 128, 128, 128, 128],
+       [80, 129, 211, 255, 194, 224, 128, 128, 128, 128, 128]],
+      [[1, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+       [246, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+       [255, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128]]],
+     [[[198, 35, 237, 223, 193, 187, 162, 160, 145, 155, 62],
+       [131, 45, 198, 221, 172, 176, 220, 157, 252, 221, 1],
+       [68, 47, 146, 208, 149, 167, 221, 162, 255, 223, 128]],
+      [[1, 149, 241, 255, 221, 224, 255, 255, 128, 128, 128],
+       [184, 141, 234, 253, 222, 220, 255, 199, 128, 128, 128],
+       [81, 99, 181, 242, 176, 190, 249, 202, 255, 255, 128]],
+      [[1, 129, 232, 253, 214, 197, 242, 196, 255, 255, 128],
+       [99, 121, 210, 250, 201, 198, 255, 202, 128, 128, 128],
+       [23, 91, 163, 242, 170, 187, 247, 210, 255, 255, 128]],
+      [[1, 200, 246, 255, 234, 255, 128, 128, 128, 128, 128],
+       [109, 178, 241, 255, 231, 245, 255, 255, 128, 128, 128],
+       [44, 130, 201, 253, 205, 192, 255, 255, 128, 128, 128]],
+      [[1, 132, 239, 251, 219, 209, 255, 165, 128, 128, 128],
+       [94, 136, 225, 251, 218, 190, 255, 255, 128, 128, 128],
+       [22, 100, 174, 245, 186, 161, 255, 199, 128, 128, 128]],
+      [[1, 182, 249, 255, 232, 235, 128, 128, 128, 128, 128],
+       [124, 143, 241, 255, 227, 234, 128, 128, 128, 128, 128],
+       [35, 77, 181, 251, 193, 211, 255, 205, 128, 128, 128]],
+      [[1, 157, 247, 255, 236, 231, 255, 255, 128, 128, 128],
+       [121, 141, 235, 255, 225, 227, 255, 255, 128, 128, 128],
+       [45, 99, 188, 251, 195, 217, 255, 224, 128, 128, 128]],
+      [[1, 1, 251, 255, 213, 255, 128, 128, 128, 128, 128],
+       [203, 1, 248, 255, 255, 128, 128, 128, 128, 128, 128],
+       [137, 1, 177, 255, 224, 255, 128, 128, 128, 128, 128]]],
+     [[[253, 9, 248, 251, 207, 208, 255, 192, 128, 128, 128],
+       [175, 13, 224, 243, 193, 185, 249, 198, 255, 255, 128],
+       [73, 17, 171, 221, 161, 179, 236, 167, 255, 234, 128]],
+      [[1, 95, 247, 253, 212, 183, 255, 255, 128, 128, 128],
+       [239, 90, 244, 250, 211, 209, 255, 255, 128, 128, 128],
+       [155, 77, 195, 248, 188, 195, 255, 255, 128, 128, 128]],
+      [[1, 24, 239, 251, 218, 219, 255, 205, 128, 128, 128],
+       [201, 51, 219, 255, 196, 186, 128, 128, 128, 128, 128],
+       [69, 46, 190, 239, 201, 218, 255, 228, 128, 128, 128]],
+      [[1, 191, 251, 255, 255, 128, 128, 128, 128, 128, 128],
+       [223, 165, 249, 255, 213, 255, 128, 128, 128, 128, 128],
+       [141, 124, 248, 255, 255, 128, 128, 128, 128, 128, 128]],
+      [[1, 16, 248, 255, 255, 128, 128, 128, 128, 128, 128],
+       [190, 36, 230, 255, 236, 255, 128, 128, 128, 128, 128],
+       [149, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128]],
+      [[1, 226, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+       [247, 192, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+       [240, 128, 255, 128, 128, 128, 128, 128, 128, 128, 128]],
+      [[1, 134, 252, 255, 255, 128, 128, 128, 128, 128, 128],
+       [213, 62, 250, 255, 255, 128, 128, 128, 128, 128, 128],
+       [55, 93, 255, 128, 128, 128, 128, 128, 128, 128, 128]],
+      [[128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128],
+       [128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128],
+       [128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128]]],
+     [[[202, 24, 213, 235, 186, 191, 220, 160, 240, 175, 255],
+       [126, 38, 182, 232, 169, 184, 228, 174, 255, 187, 128],
+       [61, 46, 138, 219, 151, 178, 240, 170, 255, 216, 128]],
+      [[1, 112, 230, 250, 199, 191, 247, 159, 255, 255, 128],
+       [166, 109, 228, 252, 211, 215, 255, 174, 128, 128, 128],
+       [39, 77, 162, 232, 172, 180, 245, 178, 255, 255, 128]],
+      [[1, 52, 220, 246, 198, 199, 249, 220, 255, 255, 128],
+       [124, 74, 191, 243, 183, 193, 250, 221, 255, 255, 128],
+       [24, 71, 130, 219, 154, 170, 243, 182, 255, 255, 128]],
+      [[1, 182, 225, 249, 219, 240, 255, 224, 128, 128, 128],
+       [149, 150, 226, 252, 216, 205, 255, 171, 128, 128, 128],
+       [28, 108, 170, 242, 183, 194, 254, 223, 255, 255, 128]],
+      [[1, 81, 230, 252, 204, 203, 255, 192, 128, 128, 128],
+       [123, 102, 209, 247, 188, 196, 255, 233, 128, 128, 128],
+       [20, 95, 153, 243, 164, 173, 255, 203, 128, 128, 128]],
+      [[1, 222, 248, 255, 216, 213, 128, 128, 128, 128, 128],
+       [168, 175, 246, 252, 235, 205, 255, 255, 128, 128, 128],
+       [47, 116, 215, 255, 211, 212, 255, 255, 128, 128, 128]],
+      [[1, 121, 236, 253, 212, 214, 255, 255, 128, 128, 128],
+       [141, 84, 213, 252, 201, 202, 255, 219, 128, 128, 128],
+       [42, 80, 160, 240, 162, 185, 255, 205, 128, 128, 128]],
+      [[1, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+       [244, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+       [238, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128]]]];
+
+/* Section 13.4 */
+pub const COEFF_UPDATE_PROBS: CoeffProbs =
+    [[[[255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+      [[176, 246, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [223, 241, 252, 255, 255, 255, 255, 255, 255, 255, 255],
+       [249, 253, 253, 255, 255, 255, 255, 255, 255, 255, 255]],
+      [[255, 244, 252, 255, 255, 255, 255, 255, 255, 255, 255],
+       [234, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+       [253, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+      [[255, 246, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+       [239, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+       [254, 255, 254, 255, 255, 255, 255, 255, 255, 255, 255]],
+      [[255, 248, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+       [251, 255, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+       [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+      [[255, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+       [251, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+       [254, 255, 254, 255, 255, 255, 255, 255, 255, 255, 255]],
+      [[255, 254, 253, 255, 254, 255, 255, 255, 255, 255, 255],
+       [250, 255, 254, 255, 254, 255, 255, 255, 255, 255, 255],
+       [254, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+      [[255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]]],
+     [[[217, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [225, 252, 241, 253, 255, 255, 254, 255, 255, 255, 255],
+       [234, 250, 241, 250, 253, 255, 253, 254, 255, 255, 255]],
+      [[255, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [223, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+       [238, 253, 254, 254, 255, 255, 255, 255, 255, 255, 255]],
+      [[255, 248, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+       [249, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+      [[255, 253, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [247, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+      [[255, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+       [252, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+      [[255, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+       [253, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+      [[255, 254, 253, 255, 255, 255, 255, 255, 255, 255, 255],
+       [250, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [254, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+      [[255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]]],
+     [[[186, 251, 250, 255, 255, 255, 255, 255, 255, 255, 255],
+       [234, 251, 244, 254, 255, 255, 255, 255, 255, 255, 255],
+       [251, 251, 243, 253, 254, 255, 254, 255, 255, 255, 255]],
+      [[255, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+       [236, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+       [251, 253, 253, 254, 254, 255, 255, 255, 255, 255, 255]],
+      [[255, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+       [254, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+       [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+      [[255, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [254, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [254, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+      [[255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [254, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+      [[255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+      [[255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+      [[255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]]],
+     [[[248, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [250, 254, 252, 254, 255, 255, 255, 255, 255, 255, 255],
+       [248, 254, 249, 253, 255, 255, 255, 255, 255, 255, 255]],
+      [[255, 253, 253, 255, 255, 255, 255, 255, 255, 255, 255],
+       [246, 253, 253, 255, 255, 255, 255, 255, 255, 255, 255],
+       [252, 254, 251, 254, 254, 255, 255, 255, 255, 255, 255]],
+      [[255, 254, 252, 255, 255, 255, 255, 255, 255, 255, 255],
+       [248, 254, 253, 255, 255, 255, 255, 255, 255, 255, 255],
+       [253, 255, 254, 254, 255, 255, 255, 255, 255, 255, 255]],
+      [[255, 251, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+       [245, 251, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+       [253, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255]],
+      [[255, 251, 253, 255, 255, 255, 255, 255, 255, 255, 255],
+       [252, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+       [255, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+      [[255, 252, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [249, 255, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+       [255, 255, 254, 255, 255, 255, 255, 255, 255, 255, 255]],
+      [[255, 255, 253, 255, 255, 255, 255, 255, 255, 255, 255],
+       [250, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+      [[255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [254, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+       [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]]]];
+
+/* Boolean entropy decoder of section 7, reading zeros past the end of its data. The
+ * value keeps two bytes, the upper one being compared against the split. */
+pub struct BoolDecoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    value: u32,
+    range: u32,
+    bit_count: u32,
+}
+
+impl<'a> BoolDecoder<'a> {
+    pub fn new(data: &'a [u8]) -> BoolDecoder<'a> {
+        let mut bd = BoolDecoder {
+            data: data,
+            pos: 0,
+            value: 0,
+            range: 255,
+            bit_count: 0,
+        };
+        bd.value = (bd.next_byte() << 8) | bd.next_byte();
+        bd
+    }
+
+    fn next_byte(&mut self) -> u32 {
+        let byte = self.data.get(self.pos).cloned().unwrap_or(0);
+        self.pos += 1;
+        byte as u32
+    }
+
+    pub fn read_bool(&mut self, prob: u8) -> bool {
+        let split = 1 + (((self.range - 1) * prob as u32) >> 8);
+        let big_split = split << 8;
+        let bit = if self.value >= big_split {
+            self.range -= split;
+            self.value -= big_split;
+            true
+        } else {
+            self.range = split;
+            false
+        };
+
+        while self.range < 128 {
+            self.value <<= 1;
+            self.range <<= 1;
+            self.bit_count += 1;
+            if self.bit_count == 8 {
+                self.bit_count = 0;
+                self.value |= self.next_byte();
+            }
+        }
+
+        bit
+    }
+
+    pub fn read_flag(&mut self) -> bool {
+        self.read_bool(128)
+    }
+
+    pub fn read_literal(&mut self, bits: u32) -> u32 {
+        let mut value = 0;
+        for _ in 0..bits {
+            value = (value << 1) | self.read_flag() as u32;
+        }
+        value
+    }
+
+    /* Magnitude followed by a sign bit */
+    pub fn read_signed(&mut self, bits: u32) -> i32 {
+        let value = self.read_literal(bits) as i32;
+        if self.read_flag() { -value } else { value }
+    }
+
+    /* Optional signed value preceded by its presence flag */
+    fn read_delta(&mut self, bits: u32) -> Option<i32> {
+        if self.read_flag() {
+            Some(self.read_signed(bits))
+        } else {
+            None
+        }
+    }
+
+    /* Position in bits of the first bit of the upper byte of the value */
+    pub fn get_pos(&self) -> usize {
+        8 * self.pos + self.bit_count as usize - 16
+    }
+
+    /* The state expected by VA to resume decoding at get_pos(): count is the number of
+     * bits of the next byte which are already shifted into the value */
+    pub fn get_state(&self) -> ffi::VABoolCoderContextVPX {
+        let mut state: ffi::VABoolCoderContextVPX = zeroed();
+        state.range = self.range as u8;
+        state.value = (self.value >> 8) as u8;
+        state.count = ((8 - self.bit_count) % 8) as u8;
+        state
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Segmentation {
+    pub segmentation_enabled: bool,
+    pub update_mb_segmentation_map: bool,
+    pub update_segment_feature_data: bool,
+    pub segment_feature_mode: u8,
+    pub quantizer_update_value: [i8; MAX_SEGMENTS],
+    pub loop_filter_update_value: [i8; MAX_SEGMENTS],
+    pub segment_probs: [u8; 3],
+}
+
+impl Segmentation {
+    /* Only the update flags are per frame, the rest is kept from the previous frames */
+    fn parse(&mut self, bd: &mut BoolDecoder) {
+        self.update_mb_segmentation_map = false;
+        self.update_segment_feature_data = false;
+        self.segmentation_enabled = bd.read_flag();
+        if !self.segmentation_enabled {
+            return;
+        }
+
+        self.update_mb_segmentation_map = bd.read_flag();
+        self.update_segment_feature_data = bd.read_flag();
+        if self.update_segment_feature_data {
+            self.segment_feature_mode = bd.read_flag() as u8;
+            for i in 0..MAX_SEGMENTS {
+                self.quantizer_update_value[i] = bd.read_delta(7).unwrap_or(0) as i8;
+            }
+            for i in 0..MAX_SEGMENTS {
+                self.loop_filter_update_value[i] = bd.read_delta(6).unwrap_or(0) as i8;
+            }
+        }
+        if self.update_mb_segmentation_map {
+            for i in 0..3 {
+                self.segment_probs[i] = if bd.read_flag() {
+                    bd.read_literal(8) as u8
+                } else {
+                    255
+                };
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LoopFilterDeltas {
+    pub loop_filter_adj_enable: bool,
+    pub mode_ref_lf_delta_update: bool,
+    /* Intra, last, golden and altref frames */
+    pub ref_frame_delta: [i8; 4],
+    /* B_PRED, ZEROMV, NEARESTMV/NEARMV/NEWMV and SPLITMV */
+    pub mb_mode_delta: [i8; 4],
+}
+
+impl LoopFilterDeltas {
+    fn parse(&mut self, bd: &mut BoolDecoder) {
+        self.mode_ref_lf_delta_update = false;
+        self.loop_filter_adj_enable = bd.read_flag();
+        if !self.loop_filter_adj_enable {
+            return;
+        }
+
+        self.mode_ref_lf_delta_update = bd.read_flag();
+        if self.mode_ref_lf_delta_update {
+            for i in 0..4 {
+                if let Some(delta) = bd.read_delta(6) {
+                    self.ref_frame_delta[i] = delta as i8;
+                }
+            }
+            for i in 0..4 {
+                if let Some(delta) = bd.read_delta(6) {
+                    self.mb_mode_delta[i] = delta as i8;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct QuantIndices {
+    pub y_ac_qi: u8,
+    pub y_dc_delta: i8,
+    pub y2_dc_delta: i8,
+    pub y2_ac_delta: i8,
+    pub uv_dc_delta: i8,
+    pub uv_ac_delta: i8,
+}
+
+impl QuantIndices {
+    fn parse(bd: &mut BoolDecoder) -> QuantIndices {
+        let y_ac_qi = bd.read_literal(7) as u8;
+        let mut deltas = [0; 5];
+        for delta in &mut deltas {
+            *delta = bd.read_delta(4).unwrap_or(0) as i8;
+        }
+
+        QuantIndices {
+            y_ac_qi: y_ac_qi,
+            y_dc_delta: deltas[0],
+            y2_dc_delta: deltas[1],
+            y2_ac_delta: deltas[2],
+            uv_dc_delta: deltas[3],
+            uv_ac_delta: deltas[4],
+        }
+    }
+}
+
+/* Probabilities which persist across frames */
+#[derive(Debug, Clone, Copy)]
+pub struct EntropyContext {
+    pub coeff_probs: CoeffProbs,
+    pub y_mode_probs: [u8; 4],
+    pub uv_mode_probs: [u8; 3],
+    pub mv_probs: [[u8; 19]; 2],
+}
+
+impl EntropyContext {
+    pub fn new() -> EntropyContext {
+        EntropyContext {
+            coeff_probs: DEFAULT_COEFF_PROBS,
+            y_mode_probs: DEFAULT_Y_MODE_PROBS,
+            uv_mode_probs: DEFAULT_UV_MODE_PROBS,
+            mv_probs: DEFAULT_MV_PROBS,
+        }
+    }
+
+    fn parse_coeff_prob_updates(&mut self, bd: &mut BoolDecoder) {
+        for i in 0..4 {
+            for j in 0..8 {
+                for k in 0..3 {
+                    for l in 0..11 {
+                        if bd.read_bool(COEFF_UPDATE_PROBS[i][j][k][l]) {
+                            self.coeff_probs[i][j][k][l] = bd.read_literal(8) as u8;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn parse_mv_prob_updates(&mut self, bd: &mut BoolDecoder) {
+        for i in 0..2 {
+            for j in 0..19 {
+                if bd.read_bool(MV_UPDATE_PROBS[i][j]) {
+                    let prob = bd.read_literal(7) as u8;
+                    self.mv_probs[i][j] = if prob > 0 { prob << 1 } else { 1 };
+                }
+            }
+        }
+    }
+}
+
+impl Default for EntropyContext {
+    fn default() -> EntropyContext {
+        EntropyContext::new()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FrameHeader {
+    pub key_frame: bool,
+    pub version: u8,
+    pub show_frame: bool,
+    pub first_part_size: u32,
+    /* Of the last key frame for inter frames */
+    pub width: u16,
+    pub horizontal_scale: u8,
+    pub height: u16,
+    pub vertical_scale: u8,
+    pub color_space: u8,
+    pub clamping_type: u8,
+    pub segmentation: Segmentation,
+    pub filter_type: u8,
+    pub loop_filter_level: u8,
+    pub sharpness_level: u8,
+    pub lf_deltas: LoopFilterDeltas,
+    pub log2_nbr_of_dct_partitions: u8,
+    pub quant_indices: QuantIndices,
+    pub refresh_golden_frame: bool,
+    pub refresh_alternate_frame: bool,
+    pub copy_buffer_to_golden: u8,
+    pub copy_buffer_to_alternate: u8,
+    pub sign_bias_golden: bool,
+    pub sign_bias_alternate: bool,
+    pub refresh_entropy_probs: bool,
+    pub refresh_last: bool,
+    pub mb_no_coeff_skip: bool,
+    pub prob_skip_false: u8,
+    pub prob_intra: u8,
+    pub prob_last: u8,
+    pub prob_gf: u8,
+    /* Probabilities in use for this frame, after the updates of its header */
+    pub entropy: EntropyContext,
+    /* Size in bits of the header in the first partition and the bool decoder state
+     * at its end */
+    pub header_size: u32,
+    pub bool_coder: ffi::VABoolCoderContextVPX,
+    /* Offset of the first partition in the frame and size of all the partitions with
+     * the table of the DCT partition sizes */
+    pub data_offset: usize,
+    pub data_size: usize,
+    /* The first partition followed by the DCT partitions */
+    pub partition_sizes: Vec<u32>,
+}
+
+impl FrameHeader {
+    pub fn get_num_dct_partitions(&self) -> usize {
+        1 << self.log2_nbr_of_dct_partitions
+    }
+}
+
+/* Keeps the segmentation, loop filter deltas and probabilities across the frames */
+#[derive(Debug, Clone, Default)]
+pub struct Parser {
+    have_key_frame: bool,
+    width: u16,
+    horizontal_scale: u8,
+    height: u16,
+    vertical_scale: u8,
+    segmentation: Segmentation,
+    lf_deltas: LoopFilterDeltas,
+    entropy: EntropyContext,
+}
+
+impl Parser {
+    pub fn new() -> Parser {
+        Parser::default()
+    }
+
+    /* Resets the state as a key frame does */
+    fn reset(&mut self) {
+        self.segmentation = Segmentation {
+            segment_probs: [255; 3],
+            ..Segmentation::default()
+        };
+        self.lf_deltas = LoopFilterDeltas::default();
+        self.entropy = EntropyContext::new();
+    }
+
+    pub fn parse_frame(&mut self, data: &[u8]) -> Result<FrameHeader, ()> {
+        if data.len() < FRAME_TAG_BYTES {
+            return Err(());
+        }
+        let tag = data[0] as u32 | (data[1] as u32) << 8 | (data[2] as u32) << 16;
+        let key_frame = tag & 1 == 0;
+        let version = ((tag >> 1) & 7) as u8;
+        let show_frame = (tag >> 4) & 1 != 0;
+        let first_part_size = tag >> 5;
+        if version > 3 {
+            return Err(());
+        }
+
+        let data_offset = if key_frame {
+            if data.len() < KEY_FRAME_HEADER_BYTES || data[3..6] != KEY_FRAME_START_CODE {
+                return Err(());
+            }
+            let width = data[6] as u16 | (data[7] as u16) << 8;
+            let height = data[8] as u16 | (data[9] as u16) << 8;
+            self.width = width & 0x3fff;
+            self.horizontal_scale = (width >> 14) as u8;
+            self.height = height & 0x3fff;
+            self.vertical_scale = (height >> 14) as u8;
+            self.have_key_frame = true;
+            self.reset();
+            KEY_FRAME_HEADER_BYTES
+        } else {
+            if !self.have_key_frame {
+                return Err(());
+            }
+            FRAME_TAG_BYTES
+        };
+        if first_part_size as usize > data.len() - data_offset {
+            return Err(());
+        }
+
+        let first_part = &data[data_offset..data_offset + first_part_size as usize];
+        let mut bd = BoolDecoder::new(first_part);
+        let (color_space, clamping_type) = if key_frame {
+            (bd.read_flag() as u8, bd.read_flag() as u8)
+        } else {
+            (0, 0)
+        };
+        self.segmentation.parse(&mut bd);
+        let filter_type = bd.read_flag() as u8;
+        let loop_filter_level = bd.read_literal(6) as u8;
+        let sharpness_level = bd.read_literal(3) as u8;
+        self.lf_deltas.parse(&mut bd);
+        let log2_nbr_of_dct_partitions = bd.read_literal(2) as u8;
+        let quant_indices = QuantIndices::parse(&mut bd);
+
+        let mut refresh_golden_frame = true;
+        let mut refresh_alternate_frame = true;
+        let mut copy_buffer_to_golden = 0;
+        let mut copy_buffer_to_alternate = 0;
+        let mut sign_bias_golden = false;
+        let mut sign_bias_alternate = false;
+        let refresh_entropy_probs;
+        let mut refresh_last = true;
+        if key_frame {
+            refresh_entropy_probs = bd.read_flag();
+        } else {
+            refresh_golden_frame = bd.read_flag();
+            refresh_alternate_frame = bd.read_flag();
+            if !refresh_golden_frame {
+                copy_buffer_to_golden = bd.read_literal(2) as u8;
+            }
+            if !refresh_alternate_frame {
+                copy_buffer_to_alternate = bd.read_literal(2) as u8;
+            }
+            sign_bias_golden = bd.read_flag();
+            sign_bias_alternate = bd.read_flag();
+            refresh_entropy_probs = bd.read_flag();
+            refresh_last = bd.read_flag();
+        }
+
+        /* Without refresh_entropy_probs, the updates only apply to this frame */
+        let saved_entropy = self.entropy;
+        self.entropy.parse_coeff_prob_updates(&mut bd);
+
+        let mb_no_coeff_skip = bd.read_flag();
+        let prob_skip_false = if mb_no_coeff_skip {
+            bd.read_literal(8) as u8
+        } else {
+            0
+        };
+
+        let mut prob_intra = 0;
+        let mut prob_last = 0;
+        let mut prob_gf = 0;
+        if !key_frame {
+            prob_intra = bd.read_literal(8) as u8;
+            prob_last = bd.read_literal(8) as u8;
+            prob_gf = bd.read_literal(8) as u8;
+            if bd.read_flag() {
+                for prob in &mut self.entropy.y_mode_probs {
+                    *prob = bd.read_literal(8) as u8;
+                }
+            }
+            if bd.read_flag() {
+                for prob in &mut self.entropy.uv_mode_probs {
+                    *prob = bd.read_literal(8) as u8;
+                }
+            }
+            self.entropy.parse_mv_prob_updates(&mut bd);
+        }
+
+        let header_size = bd.get_pos();
+        if header_size > 8 * first_part.len() {
+            return Err(());
+        }
+        let bool_coder = bd.get_state();
+
+        let entropy = self.entropy;
+        if !refresh_entropy_probs {
+            self.entropy = saved_entropy;
+        }
+
+        /* The sizes of all but the last DCT partition are 3 bytes little endian values
+         * following the first partition */
+        let num_partitions = 1usize << log2_nbr_of_dct_partitions;
+        let mut pos = data_offset + first_part.len();
+        let table_size = 3 * (num_partitions - 1);
+        if table_size > data.len() - pos {
+            return Err(());
+        }
+        let mut partition_sizes = vec![first_part_size];
+        let mut remaining = data.len() - pos - table_size;
+        for _ in 0..num_partitions - 1 {
+            let size = data[pos] as usize | (data[pos + 1] as usize) << 8 |
+                       (data[pos + 2] as usize) << 16;
+            if size > remaining {
+                return Err(());
+            }
+            partition_sizes.push(size as u32);
+            remaining -= size;
+            pos += 3;
+        }
+        partition_sizes.push(remaining as u32);
+
+        Ok(FrameHeader {
+            key_frame: key_frame,
+            version: version,
+            show_frame: show_frame,
+            first_part_size: first_part_size,
+            width: self.width,
+            horizontal_scale: self.horizontal_scale,
+            height: self.height,
+            vertical_scale: self.vertical_scale,
+            color_space: color_space,
+            clamping_type: clamping_type,
+            segmentation: self.segmentation.clone(),
+            filter_type: filter_type,
+            loop_filter_level: loop_filter_level,
+            sharpness_level: sharpness_level,
+            lf_deltas: self.lf_deltas.clone(),
+            log2_nbr_of_dct_partitions: log2_nbr_of_dct_partitions,
+            quant_indices: quant_indices,
+            refresh_golden_frame: refresh_golden_frame,
+            refresh_alternate_frame: refresh_alternate_frame,
+            copy_buffer_to_golden: copy_buffer_to_golden,
+            copy_buffer_to_alternate: copy_buffer_to_alternate,
+            sign_bias_golden: sign_bias_golden,
+            sign_bias_alternate: sign_bias_alternate,
+            refresh_entropy_probs: refresh_entropy_probs,
+            refresh_last: refresh_last,
+            mb_no_coeff_skip: mb_no_coeff_skip,
+            prob_skip_false: prob_skip_false,
+            prob_intra: prob_intra,
+            prob_last: prob_last,
+            prob_gf: prob_gf,
+            entropy: entropy,
+            header_size: header_size as u32,
+            bool_coder: bool_coder,
+            data_offset: data_offset,
+            data_size: data.len() - data_offset,
+            partition_sizes: partition_sizes,
+        })
+    }
+}
+
+fn clamp(value: i32, max: i32) -> u8 {
+    cmp::min(cmp::max(value, 0), max) as u8
+}
+
+/* Applies the segment value to the frame one, as an absolute value or as a delta */
+fn get_segment_value(hdr: &FrameHeader, base: u8, value: i8) -> i32 {
+    let seg = &hdr.segmentation;
+    if !seg.segmentation_enabled {
+        base as i32
+    } else if seg.segment_feature_mode == SEGMENT_FEATURE_MODE_ABSOLUTE {
+        value as i32
+    } else {
+        base as i32 + value as i32
+    }
+}
+
+/* The references are managed by the caller, and are ignored for key frames */
+pub fn build_picture_param(hdr: &FrameHeader,
+                           last_ref: ffi::VASurfaceID,
+                           golden_ref: ffi::VASurfaceID,
+                           alt_ref: ffi::VASurfaceID)
+                           -> Result<ffi::VAPictureParameterBufferVP8, ()> {
+    let mut param: ffi::VAPictureParameterBufferVP8 = zeroed();
+    param.frame_width = hdr.width as u32;
+    param.frame_height = hdr.height as u32;
+    if hdr.key_frame {
+        param.last_ref_frame = ffi::VA_INVALID_SURFACE;
+        param.golden_ref_frame = ffi::VA_INVALID_SURFACE;
+        param.alt_ref_frame = ffi::VA_INVALID_SURFACE;
+    } else {
+        param.last_ref_frame = last_ref;
+        param.golden_ref_frame = golden_ref;
+        param.alt_ref_frame = alt_ref;
+    }
+    param.out_of_loop_frame = ffi::VA_INVALID_SURFACE;
+
+    let seg = &hdr.segmentation;
+    let lf = &hdr.lf_deltas;
+    param.pic_fields = VP8PicFields {
+        /* Same meaning as in the frame tag, 0 for key frames */
+        key_frame: !hdr.key_frame as u32,
+        version: hdr.version as u32,
+        segmentation_enabled: seg.segmentation_enabled as u32,
+        update_mb_segmentation_map: seg.update_mb_segmentation_map as u32,
+        update_segment_feature_data: seg.update_segment_feature_data as u32,
+        filter_type: hdr.filter_type as u32,
+        sharpness_level: hdr.sharpness_level as u32,
+        loop_filter_adj_enable: lf.loop_filter_adj_enable as u32,
+        mode_ref_lf_delta_update: lf.mode_ref_lf_delta_update as u32,
+        sign_bias_golden: hdr.sign_bias_golden as u32,
+        sign_bias_alternate: hdr.sign_bias_alternate as u32,
+        mb_no_coeff_skip: hdr.mb_no_coeff_skip as u32,
+        loop_filter_disable: (hdr.loop_filter_level == 0) as u32,
+    }.to_va()?;
+
+    param.mb_segment_tree_probs = if seg.segmentation_enabled {
+        seg.segment_probs
+    } else {
+        [255; 3]
+    };
+    for i in 0..MAX_SEGMENTS {
+        let level = get_segment_value(hdr, hdr.loop_filter_level, seg.loop_filter_update_value[i]);
+        param.loop_filter_level[i] = clamp(level, MAX_LOOP_FILTER_LEVEL);
+        param.loop_filter_deltas_ref_frame[i] = lf.ref_frame_delta[i] as _;
+        param.loop_filter_deltas_mode[i] = lf.mb_mode_delta[i] as _;
+    }
+
+    param.prob_skip_false = hdr.prob_skip_false;
+    param.prob_intra = hdr.prob_intra;
+    param.prob_last = hdr.prob_last;
+    param.prob_gf = hdr.prob_gf;
+    param.y_mode_probs = hdr.entropy.y_mode_probs;
+    param.uv_mode_probs = hdr.entropy.uv_mode_probs;
+    param.mv_probs = hdr.entropy.mv_probs;
+    param.bool_coder_ctx = hdr.bool_coder;
+    Ok(param)
+}
+
+/* The slice data is the frame from the first partition on */
+pub fn build_slice_param(hdr: &FrameHeader) -> ffi::VASliceParameterBufferVP8 {
+    let mut param: ffi::VASliceParameterBufferVP8 = zeroed();
+    param.slice_data_size = hdr.data_size as u32;
+    param.slice_data_offset = 0;
+    param.slice_data_flag = ffi::VA_SLICE_DATA_FLAG_ALL;
+    param.macroblock_offset = hdr.header_size;
+    param.num_of_partitions = hdr.partition_sizes.len() as u8;
+    /* What is left of the first partition from the byte holding the first macroblock */
+    param.partition_size[0] = hdr.first_part_size - ((hdr.header_size + 7) >> 3);
+    for (i, size) in hdr.partition_sizes.iter().enumerate().skip(1) {
+        param.partition_size[i] = *size;
+    }
+    param
+}
+
+pub fn build_probability_data(hdr: &FrameHeader) -> ffi::VAProbabilityDataBufferVP8 {
+    let mut prob_data: ffi::VAProbabilityDataBufferVP8 = zeroed();
+    prob_data.dct_coeff_probs = hdr.entropy.coeff_probs;
+    prob_data
+}
+
+/* Indices of Y AC, Y DC, Y2 DC, Y2 AC, UV DC and UV AC for each segment */
+pub fn build_iq_matrix(hdr: &FrameHeader) -> ffi::VAIQMatrixBufferVP8 {
+    let q = &hdr.quant_indices;
+    let mut iq_matrix: ffi::VAIQMatrixBufferVP8 = zeroed();
+    for i in 0..MAX_SEGMENTS {
+        let base = get_segment_value(hdr, q.y_ac_qi, hdr.segmentation.quantizer_update_value[i]);
+        let deltas = [0, q.y_dc_delta, q.y2_dc_delta, q.y2_ac_delta, q.uv_dc_delta, q.uv_ac_delta];
+        for j in 0..6 {
+            iq_matrix.quantization_index[i][j] =
+                clamp(base + deltas[j] as i32, MAX_QUANT_INDEX) as u16;
+        }
+    }
+    iq_matrix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi;
+    use ivf::parse_ivf;
+
+    /* A QCIF key frame with segmentation, loop filter deltas and four DCT partitions,
+     * an inter frame whose probability updates are not kept and an inter frame with
+     * segmentation disabled, followed by a truncated frame */
+    const QCIF_SEGMENTS: &'static [u8] = include_bytes!("../tests/data/vp8/qcif_segments.ivf");
+
+    fn parse_stream(data: &[u8]) -> Vec<FrameHeader> {
+        let (_, frames) = parse_ivf(data).unwrap();
+        let mut parser = Parser::new();
+        frames.iter().map(|frame| parser.parse_frame(frame.data).unwrap()).collect()
+    }
+
+    #[test]
+    fn ivf_frames() {
+        let (header, frames) = parse_ivf(QCIF_SEGMENTS).unwrap();
+        assert_eq!(&header.fourcc, b"VP80");
+        assert_eq!((header.width, header.height), (176, 144));
+        assert_eq!((header.frame_rate, header.time_scale), (30, 1));
+        assert_eq!(header.num_frames, 3);
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames.iter().map(|frame| frame.pts).collect::<Vec<u64>>(), vec![0, 1, 2]);
+        assert_eq!(frames.iter().map(|frame| frame.data.len()).collect::<Vec<usize>>(),
+                   vec![233, 131, 91]);
+
+        assert!(parse_ivf(&QCIF_SEGMENTS[..31]).is_err());
+        assert!(parse_ivf(&QCIF_SEGMENTS[4..]).is_err());
+    }
+
+    #[test]
+    fn bool_decoder() {
+        /* Two bytes are loaded at first, the next ones one bit at a time */
+        let mut bd = BoolDecoder::new(&[0xa5, 0x3c]);
+        assert_eq!(bd.get_pos(), 0);
+        assert_eq!((bd.get_state().range, bd.get_state().value, bd.get_state().count),
+                   (255, 0xa5, 0));
+        assert_eq!(bd.read_literal(4), 0xa);
+        assert_eq!(bd.get_pos(), 4);
+        assert_eq!((bd.get_state().range, bd.get_state().value, bd.get_state().count),
+                   (254, 87, 4));
+        assert_eq!(bd.read_literal(8), 88);
+        assert_eq!(bd.read_signed(3), -3);
+        /* Zeros are read past the end */
+        assert_eq!(bd.read_literal(8), 14);
+    }
+
+    #[test]
+    fn key_frame() {
+        let frames = parse_stream(QCIF_SEGMENTS);
+        let hdr = &frames[0];
+        assert!(hdr.key_frame && hdr.show_frame);
+        assert_eq!(hdr.version, 0);
+        assert_eq!((hdr.width, hdr.height), (176, 144));
+        assert_eq!((hdr.horizontal_scale, hdr.vertical_scale), (0, 0));
+        assert_eq!((hdr.color_space, hdr.clamping_type), (0, 1));
+
+        let seg = &hdr.segmentation;
+        assert!(seg.segmentation_enabled && seg.update_mb_segmentation_map &&
+                seg.update_segment_feature_data);
+        assert_eq!(seg.segment_feature_mode, SEGMENT_FEATURE_MODE_DELTA);
+        assert_eq!(seg.quantizer_update_value, [0, -5, 10, 3]);
+        assert_eq!(seg.loop_filter_update_value, [2, 0, -4, 0]);
+        assert_eq!(seg.segment_probs, [120, 255, 64]);
+
+        assert_eq!((hdr.filter_type, hdr.loop_filter_level, hdr.sharpness_level), (0, 32, 2));
+        assert!(hdr.lf_deltas.loop_filter_adj_enable && hdr.lf_deltas.mode_ref_lf_delta_update);
+        assert_eq!(hdr.lf_deltas.ref_frame_delta, [2, 0, -2, -2]);
+        assert_eq!(hdr.lf_deltas.mb_mode_delta, [4, -2, 2, 4]);
+
+        let q = &hdr.quant_indices;
+        assert_eq!(q.y_ac_qi, 60);
+        assert_eq!((q.y_dc_delta, q.y2_dc_delta, q.y2_ac_delta, q.uv_dc_delta, q.uv_ac_delta),
+                   (-3, 0, 4, 0, -2));
+        assert!(hdr.refresh_golden_frame && hdr.refresh_alternate_frame && hdr.refresh_last);
+        assert!(hdr.refresh_entropy_probs);
+
+        assert_eq!(hdr.entropy.coeff_probs[0][1][0][0], 200);
+        assert_eq!(hdr.entropy.coeff_probs[1][0][2][10], 7);
+        assert_eq!(hdr.entropy.coeff_probs[3][7][1][0], 99);
+        assert_eq!(hdr.entropy.coeff_probs[0][1][1], DEFAULT_COEFF_PROBS[0][1][1]);
+        assert_eq!(hdr.entropy.mv_probs, DEFAULT_MV_PROBS);
+        assert!(hdr.mb_no_coeff_skip);
+        assert_eq!(hdr.prob_skip_false, 210);
+
+        assert_eq!(hdr.first_part_size, 87);
+        assert_eq!(hdr.get_num_dct_partitions(), 4);
+        assert_eq!(hdr.partition_sizes, vec![87, 40, 35, 0, 52]);
+        assert_eq!((hdr.data_offset, hdr.data_size), (10, 223));
+        assert_eq!(hdr.header_size, 238);
+        let state = hdr.bool_coder;
+        assert_eq!((state.range, state.value, state.count), (234, 227, 2));
+    }
+
+    #[test]
+    fn inter_frames() {
+        let frames = parse_stream(QCIF_SEGMENTS);
+
+        /* The segmentation and the loop filter deltas are kept from the key frame */
+        let hdr = &frames[1];
+        assert!(!hdr.key_frame);
+        assert_eq!((hdr.width, hdr.height), (176, 144));
+        assert!(hdr.segmentation.segmentation_enabled);
+        assert!(!hdr.segmentation.update_mb_segmentation_map &&
+                !hdr.segmentation.update_segment_feature_data);
+        assert_eq!(hdr.segmentation.quantizer_update_value, [0, -5, 10, 3]);
+        assert_eq!(hdr.segmentation.segment_probs, [120, 255, 64]);
+        assert_eq!(hdr.lf_deltas.ref_frame_delta, [2, 5, -2, -2]);
+        assert_eq!(hdr.lf_deltas.mb_mode_delta, [4, -2, 2, 4]);
+        assert!(!hdr.refresh_golden_frame && hdr.refresh_alternate_frame);
+        assert_eq!((hdr.copy_buffer_to_golden, hdr.copy_buffer_to_alternate), (2, 0));
+        assert!(hdr.sign_bias_golden && !hdr.sign_bias_alternate);
+        assert!(!hdr.refresh_entropy_probs && hdr.refresh_last);
+        assert!(!hdr.mb_no_coeff_skip);
+        assert_eq!((hdr.prob_intra, hdr.prob_last, hdr.prob_gf), (30, 180, 90));
+        assert_eq!(hdr.entropy.coeff_probs[0][1][0][0], 50);
+        assert_eq!(hdr.entropy.coeff_probs[2][3][2][0], 17);
+        assert_eq!(hdr.entropy.coeff_probs[1][0][2][10], 7);
+        assert_eq!(hdr.entropy.y_mode_probs, [100, 90, 130, 40]);
+        assert_eq!(hdr.entropy.uv_mode_probs, DEFAULT_UV_MODE_PROBS);
+        assert_eq!(hdr.entropy.mv_probs[0][0], 1);
+        assert_eq!(hdr.entropy.mv_probs[1][18], 140);
+        assert_eq!(hdr.partition_sizes, vec![51, 77]);
+        assert_eq!(hdr.header_size, 177);
+        let state = hdr.bool_coder;
+        assert_eq!((state.range, state.value, state.count), (128, 60, 7));
+
+        /* The updates of the previous frame were dropped */
+        let hdr = &frames[2];
+        assert_eq!(hdr.version, 1);
+        assert!(!hdr.show_frame);
+        assert!(!hdr.segmentation.segmentation_enabled);
+        assert_eq!(hdr.loop_filter_level, 0);
+        assert!(!hdr.lf_deltas.loop_filter_adj_enable);
+        assert_eq!(hdr.entropy.coeff_probs[0][1][0][0], 200);
+        assert_eq!(hdr.entropy.coeff_probs[2][3][2][0], DEFAULT_COEFF_PROBS[2][3][2][0]);
+        assert_eq!(hdr.entropy.y_mode_probs, DEFAULT_Y_MODE_PROBS);
+        assert_eq!(hdr.entropy.uv_mode_probs, [150, 100, 200]);
+        assert_eq!(hdr.entropy.mv_probs[0][0], DEFAULT_MV_PROBS[0][0]);
+        assert_eq!(hdr.entropy.mv_probs[0][2], 254);
+        assert_eq!(hdr.prob_skip_false, 1);
+        assert_eq!(hdr.partition_sizes, vec![34, 20, 31]);
+        assert_eq!(hdr.header_size, 123);
+        let state = hdr.bool_coder;
+        assert_eq!((state.range, state.value, state.count), (138, 108, 5));
+    }
+
+    #[test]
+    fn invalid_frames() {
+        let (_, frames) = parse_ivf(QCIF_SEGMENTS).unwrap();
+        let mut parser = Parser::new();
+
+        /* Inter frames need a previous key frame */
+        assert!(parser.parse_frame(frames[1].data).is_err());
+        assert!(parser.parse_frame(&frames[0].data[..2]).is_err());
+        /* Missing start code, then truncated first partition and partition table */
+        let mut data = frames[0].data.to_vec();
+        data[4] = 0;
+        assert!(parser.parse_frame(&data).is_err());
+        assert!(parser.parse_frame(&frames[0].data[..90]).is_err());
+        assert!(parser.parse_frame(&frames[0].data[..99]).is_err());
+        assert!(parser.parse_frame(frames[0].data).is_ok());
+    }
+
+    #[test]
+    fn va_buffers() {
+        let frames = parse_stream(QCIF_SEGMENTS);
+
+        let param = build_picture_param(&frames[0], 1, 2, 3).unwrap();
+        assert_eq!((param.frame_width, param.frame_height), (176, 144));
+        assert_eq!(param.last_ref_frame, ffi::VA_INVALID_SURFACE);
+        assert_eq!(param.out_of_loop_frame, ffi::VA_INVALID_SURFACE);
+        let fields = VP8PicFields::from_va(&param.pic_fields);
+        assert_eq!(fields.key_frame, 0);
+        assert_eq!(fields.segmentation_enabled, 1);
+        assert_eq!(fields.update_mb_segmentation_map, 1);
+        assert_eq!(fields.sharpness_level, 2);
+        assert_eq!(fields.mb_no_coeff_skip, 1);
+        assert_eq!(fields.loop_filter_disable, 0);
+        assert_eq!(param.mb_segment_tree_probs, [120, 255, 64]);
+        assert_eq!(param.loop_filter_level, [34, 32, 28, 32]);
+        assert_eq!(param.loop_filter_deltas_ref_frame, [2, 0, -2, -2]);
+        assert_eq!(param.loop_filter_deltas_mode, [4, -2, 2, 4]);
+        assert_eq!(param.prob_skip_false, 210);
+        assert_eq!(param.y_mode_probs, DEFAULT_Y_MODE_PROBS);
+        assert_eq!((param.bool_coder_ctx.range, param.bool_coder_ctx.value), (234, 227));
+
+        let slice = build_slice_param(&frames[0]);
+        assert_eq!(slice.slice_data_size, 223);
+        assert_eq!(slice.macroblock_offset, 238);
+        assert_eq!(slice.num_of_partitions, 5);
+        assert_eq!(slice.partition_size[..5], [57, 40, 35, 0, 52]);
+
+        let iq_matrix = build_iq_matrix(&frames[0]);
+        assert_eq!(iq_matrix.quantization_index[0], [60, 57, 60, 64, 60, 58]);
+        assert_eq!(iq_matrix.quantization_index[1], [55, 52, 55, 59, 55, 53]);
+        assert_eq!(iq_matrix.quantization_index[2], [70, 67, 70, 74, 70, 68]);
+
+        let probs = build_probability_data(&frames[0]);
+        assert_eq!(probs.dct_coeff_probs[3][7][1][0], 99);
+
+        let param = build_picture_param(&frames[1], 1, 2, 3).unwrap();
+        assert_eq!((param.last_ref_frame, param.golden_ref_frame, param.alt_ref_frame),
+                   (1, 2, 3));
+        let fields = VP8PicFields::from_va(&param.pic_fields);
+        assert_eq!(fields.key_frame, 1);
+        assert_eq!(fields.update_mb_segmentation_map, 0);
+        assert_eq!(fields.sign_bias_golden, 1);
+        assert_eq!(param.loop_filter_level, [22, 20, 16, 20]);
+        assert_eq!((param.prob_intra, param.prob_last, param.prob_gf), (30, 180, 90));
+        assert_eq!(param.mv_probs[1][18], 140);
+        let iq_matrix = build_iq_matrix(&frames[1]);
+        assert_eq!(iq_matrix.quantization_index[2], [110, 110, 110, 110, 104, 110]);
+
+        /* Segmentation disabled: the frame values are used, clamped */
+        let param = build_picture_param(&frames[2], 1, 2, 3).unwrap();
+        let fields = VP8PicFields::from_va(&param.pic_fields);
+        assert_eq!(fields.version, 1);
+        assert_eq!(fields.loop_filter_disable, 1);
+        assert_eq!(param.mb_segment_tree_probs, [255; 3]);
+        assert_eq!(param.loop_filter_level, [0; 4]);
+        let iq_matrix = build_iq_matrix(&frames[2]);
+        assert_eq!(iq_matrix.quantization_index, [[127; 6]; 4]);
+    }
+}
@@ -0,0 +1,1205 @@
+// Copyright (c) 2017 zzoon <zzoon@igalia.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/* Parser for the VP9 frame headers needed to drive VA decoding: the superframe index,
+ * the uncompressed header with the state kept across frames (reference frame sizes,
+ * loop filter deltas and segmentation), the syntax of the compressed header, and their
+ * conversion into the VA buffers. The probabilities themselves are adapted by the
+ * hardware, which gets the compressed header with the frame data. */
+
+use std::cmp;
+
+use bitfields::{VP9PicFields, VP9SegmentFlags, zeroed};
+use bitreader::BitReader;
+use ffi;
+use vp8::BoolDecoder;
+
+pub const FRAME_MARKER: u32 = 2;
+pub const SYNC_CODE: u32 = 0x498342;
+
+pub const KEY_FRAME: u8 = 0;
+pub const NON_KEY_FRAME: u8 = 1;
+
+pub const NUM_REF_FRAMES: usize = 8;
+pub const REFS_PER_FRAME: usize = 3;
+pub const MAX_SEGMENTS: usize = 8;
+
+pub const INTRA_FRAME: usize = 0;
+pub const LAST_FRAME: usize = 1;
+pub const GOLDEN_FRAME: usize = 2;
+pub const ALTREF_FRAME: usize = 3;
+
+pub const CS_UNKNOWN: u8 = 0;
+pub const CS_BT_601: u8 = 1;
+pub const CS_RGB: u8 = 7;
+
+/* Same values as the VA mcomp_filter_type */
+pub const INTERP_FILTER_EIGHTTAP: u8 = 0;
+pub const INTERP_FILTER_EIGHTTAP_SMOOTH: u8 = 1;
+pub const INTERP_FILTER_EIGHTTAP_SHARP: u8 = 2;
+pub const INTERP_FILTER_BILINEAR: u8 = 3;
+pub const INTERP_FILTER_SWITCHABLE: u8 = 4;
+const LITERAL_TO_INTERP_FILTER: [u8; 4] = [INTERP_FILTER_EIGHTTAP_SMOOTH,
+                                           INTERP_FILTER_EIGHTTAP,
+                                           INTERP_FILTER_EIGHTTAP_SHARP,
+                                           INTERP_FILTER_BILINEAR];
+
+pub const SEG_LVL_ALT_Q: usize = 0;
+pub const SEG_LVL_ALT_L: usize = 1;
+pub const SEG_LVL_REF_FRAME: usize = 2;
+pub const SEG_LVL_SKIP: usize = 3;
+pub const SEG_LVL_MAX: usize = 4;
+const SEGMENTATION_FEATURE_BITS: [u32; SEG_LVL_MAX] = [8, 6, 2, 0];
+const SEGMENTATION_FEATURE_SIGNED: [bool; SEG_LVL_MAX] = [true, true, false, false];
+
+pub const TX_MODE_ONLY_4X4: u8 = 0;
+pub const TX_MODE_ALLOW_32X32: u8 = 3;
+pub const TX_MODE_SELECT: u8 = 4;
+
+pub const SINGLE_REFERENCE: u8 = 0;
+pub const COMPOUND_REFERENCE: u8 = 1;
+pub const REFERENCE_MODE_SELECT: u8 = 2;
+
+const MAX_LOOP_FILTER: i32 = 63;
+const MAX_TILE_WIDTH_B64: u32 = 64;
+const MIN_TILE_WIDTH_B64: u32 = 4;
+/* Probability of the flags announcing a probability update in the compressed header */
+const DIFF_UPDATE_PROB: u8 = 252;
+
+/* Quantizer lookup tables for 8 bits streams */
+pub const DC_QLOOKUP: [i16; 256] = [4, 8, 8, 9, 10, 11, 12, 12, 13, 14, 15, 16, 17, 18, 19, 19,
+                                    20, 21, 22, 23, 24, 25, 26, 26, 27, 28, 29, 30, 31, 32, 32,
+                                    33, 34, 35, 36, 37, 38, 38, 39, 40, 41, 42, 43, 43, 44, 45,
+                                    46, 47, 48, 48, 49, 50, 51, 52, 53, 53, 54, 55, 56, 57, 57,
+                                    58, 59, 60, 61, 62, 62, 63, 64, 65, 66, 66, 67, 68, 69, 70,
+                                    70, 71, 72, 73, 74, 74, 75, 76, 77, 78, 78, 79, 80, 81, 81,
+                                    82, 83, 84, 85, 85, 87, 88, 90, 92, 93, 95, 96, 98, 99, 101,
+                                    102, 104, 105, 107, 108, 110, 111, 113, 114, 116, 117, 118,
+                                    120, 121, 123, 125, 127, 129, 131, 134, 136, 138, 140, 142,
+                                    144, 146, 148, 150, 152, 154, 156, 158, 161, 164, 166, 169,
+                                    172, 174, 177, 180, 182, 185, 187, 190, 192, 195, 199, 202,
+                                    205, 208, 211, 214, 217, 220, 223, 226, 230, 233, 237, 240,
+                                    243, 247, 250, 253, 257, 261, 265, 269, 272, 276, 280, 284,
+                                    288, 292, 296, 300, 304, 309, 313, 317, 322, 326, 330, 335,
+                                    340, 344, 349, 354, 359, 364, 369, 374, 379, 384, 389, 395,
+                                    400, 406, 411, 417, 423, 429, 435, 441, 447, 454, 461, 467,
+                                    475, 482, 489, 497, 505, 513, 522, 530, 539, 549, 559, 569,
+                                    579, 590, 602, 614, 626, 640, 654, 668, 684, 700, 717, 736,
+                                    755, 775, 796, 819, 843, 869, 896, 925, 955, 988, 1022, 1058,
+                                    1098, 1139, 1184, 1232, 1282, 1336];
+pub const AC_QLOOKUP: [i16; 256] = [4, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22,
+                                    23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37,
+                                    38, 39, 40, 41, 42, 43, 44, 45, 46, 47, 48, 49, 50, 51, 52,
+                                    53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63, 64, 65, 66, 67,
+                                    68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79, 80, 81, 82,
+                                    83, 84, 85, 86, 87, 88, 89, 90, 91, 92, 93, 94, 95, 96, 97,
+                                    98, 99, 100, 101, 102, 104, 106, 108, 110, 112, 114, 116,
+                                    118, 120, 122, 124, 126, 128, 130, 132, 134, 136, 138, 140,
+                                    142, 144, 146, 148, 150, 152, 155, 158, 161, 164, 167, 170,
+                                    173, 176, 179, 182, 185, 188, 191, 194, 197, 200, 203, 207,
+                                    211, 215, 219, 223, 227, 231, 235, 239, 243, 247, 251, 255,
+                                    260, 265, 270, 275, 280, 285, 290, 295, 300, 305, 311, 317,
+                                    323, 329, 335, 341, 347, 353, 359, 366, 373, 380, 387, 394,
+                                    401, 408, 416, 424, 432, 440, 448, 456, 465, 474, 483, 492,
+                                    501, 510, 520, 530, 540, 550, 560, 571, 582, 593, 604, 615,
+                                    627, 639, 651, 663, 676, 689, 702, 715, 729, 743, 757, 771,
+                                    786, 801, 816, 832, 848, 864, 881, 898, 915, 933, 951, 969,
+                                    988, 1007, 1026, 1046, 1066, 1087, 1108, 1129, 1151, 1173,
+                                    1196, 1219, 1243, 1267, 1292, 1317, 1343, 1369, 1396, 1423,
+                                    1451, 1479, 1508, 1537, 1567, 1597, 1628, 1660, 1692, 1725,
+                                    1759, 1793, 1828];
+
+fn clamp(value: i32, min: i32, max: i32) -> i32 {
+    cmp::min(cmp::max(value, min), max)
+}
+
+/* Splits a chunk into its frames following the superframe index found at its end, or
+ * returns the whole chunk when there is no index */
+pub fn split_superframe(data: &[u8]) -> Result<Vec<&[u8]>, ()> {
+    let marker = match data.last() {
+        Some(marker) => *marker,
+        None => return Err(()),
+    };
+    if marker & 0xe0 != 0xc0 {
+        return Ok(vec![data]);
+    }
+
+    let bytes_per_size = ((marker >> 3) & 3) as usize + 1;
+    let num_frames = (marker & 7) as usize + 1;
+    let index_size = 2 + bytes_per_size * num_frames;
+    if data.len() < index_size || data[data.len() - index_size] != marker {
+        return Ok(vec![data]);
+    }
+
+    let mut frames = Vec::new();
+    let mut index = data.len() - index_size + 1;
+    let mut pos = 0;
+    for _ in 0..num_frames {
+        let mut size = 0;
+        for i in 0..bytes_per_size {
+            size |= (data[index + i] as usize) << (8 * i);
+        }
+        index += bytes_per_size;
+        if size > data.len() - index_size - pos {
+            return Err(());
+        }
+        frames.push(&data[pos..pos + size]);
+        pos += size;
+    }
+
+    Ok(frames)
+}
+
+/* Size and format of the frame held by a reference slot */
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RefFrameInfo {
+    pub width: u32,
+    pub height: u32,
+    pub subsampling_x: u8,
+    pub subsampling_y: u8,
+    pub bit_depth: u8,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ColorConfig {
+    pub bit_depth: u8,
+    pub color_space: u8,
+    pub color_range: u8,
+    pub subsampling_x: u8,
+    pub subsampling_y: u8,
+}
+
+impl ColorConfig {
+    fn parse(br: &mut BitReader, profile: u8) -> Result<ColorConfig, ()> {
+        let bit_depth = if profile >= 2 {
+            if br.read_flag()? { 12 } else { 10 }
+        } else {
+            8
+        };
+        let color_space = br.read_bits(3)? as u8;
+        let mut config = ColorConfig {
+            bit_depth: bit_depth,
+            color_space: color_space,
+            color_range: 1,
+            subsampling_x: 1,
+            subsampling_y: 1,
+        };
+
+        if color_space != CS_RGB {
+            config.color_range = br.read_bits(1)? as u8;
+            if profile == 1 || profile == 3 {
+                config.subsampling_x = br.read_bits(1)? as u8;
+                config.subsampling_y = br.read_bits(1)? as u8;
+                /* 4:2:0 is only allowed in profiles 0 and 2 */
+                if config.subsampling_x == 1 && config.subsampling_y == 1 || br.read_flag()? {
+                    return Err(());
+                }
+            }
+        } else {
+            /* 4:4:4 only, in profiles 1 and 3 */
+            if profile == 0 || profile == 2 || br.read_flag()? {
+                return Err(());
+            }
+            config.subsampling_x = 0;
+            config.subsampling_y = 0;
+        }
+
+        Ok(config)
+    }
+
+    /* Implied by intra only frames of profile 0 */
+    fn new_profile0() -> ColorConfig {
+        ColorConfig {
+            bit_depth: 8,
+            color_space: CS_BT_601,
+            color_range: 0,
+            subsampling_x: 1,
+            subsampling_y: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LoopFilter {
+    pub level: u8,
+    pub sharpness: u8,
+    pub delta_enabled: bool,
+    pub delta_update: bool,
+    /* Intra, last, golden and altref frames */
+    pub ref_deltas: [i8; 4],
+    /* ZEROMV and the other inter modes */
+    pub mode_deltas: [i8; 2],
+}
+
+impl LoopFilter {
+    fn reset_deltas(&mut self) {
+        self.delta_enabled = true;
+        self.ref_deltas = [1, 0, -1, -1];
+        self.mode_deltas = [0, 0];
+    }
+
+    /* The deltas are kept from the previous frames unless updated */
+    fn parse(&mut self, br: &mut BitReader) -> Result<(), ()> {
+        self.level = br.read_bits(6)? as u8;
+        self.sharpness = br.read_bits(3)? as u8;
+        self.delta_update = false;
+        self.delta_enabled = br.read_flag()?;
+        if self.delta_enabled {
+            self.delta_update = br.read_flag()?;
+            if self.delta_update {
+                for delta in &mut self.ref_deltas {
+                    if br.read_flag()? {
+                        *delta = read_signed(br, 6)? as i8;
+                    }
+                }
+                for delta in &mut self.mode_deltas {
+                    if br.read_flag()? {
+                        *delta = read_signed(br, 6)? as i8;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Quantization {
+    pub base_q_idx: u8,
+    pub delta_q_y_dc: i8,
+    pub delta_q_uv_dc: i8,
+    pub delta_q_uv_ac: i8,
+}
+
+impl Quantization {
+    fn parse(br: &mut BitReader) -> Result<Quantization, ()> {
+        let base_q_idx = br.read_bits(8)? as u8;
+        let mut deltas = [0; 3];
+        for delta in &mut deltas {
+            if br.read_flag()? {
+                *delta = read_signed(br, 4)? as i8;
+            }
+        }
+
+        Ok(Quantization {
+            base_q_idx: base_q_idx,
+            delta_q_y_dc: deltas[0],
+            delta_q_uv_dc: deltas[1],
+            delta_q_uv_ac: deltas[2],
+        })
+    }
+
+    pub fn is_lossless(&self) -> bool {
+        self.base_q_idx == 0 && self.delta_q_y_dc == 0 && self.delta_q_uv_dc == 0 &&
+        self.delta_q_uv_ac == 0
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Segmentation {
+    pub enabled: bool,
+    pub update_map: bool,
+    pub tree_probs: [u8; 7],
+    pub pred_probs: [u8; 3],
+    pub temporal_update: bool,
+    pub update_data: bool,
+    pub abs_or_delta_update: bool,
+    pub feature_enabled: [[bool; SEG_LVL_MAX]; MAX_SEGMENTS],
+    pub feature_data: [[i16; SEG_LVL_MAX]; MAX_SEGMENTS],
+}
+
+impl Segmentation {
+    fn reset_features(&mut self) {
+        self.abs_or_delta_update = false;
+        self.feature_enabled = [[false; SEG_LVL_MAX]; MAX_SEGMENTS];
+        self.feature_data = [[0; SEG_LVL_MAX]; MAX_SEGMENTS];
+    }
+
+    /* The features are kept from the previous frames unless updated, the probabilities
+     * only matter to the frames updating the map */
+    fn parse(&mut self, br: &mut BitReader) -> Result<(), ()> {
+        self.update_map = false;
+        self.temporal_update = false;
+        self.update_data = false;
+        self.tree_probs = [255; 7];
+        self.pred_probs = [255; 3];
+        self.enabled = br.read_flag()?;
+        if !self.enabled {
+            return Ok(());
+        }
+
+        self.update_map = br.read_flag()?;
+        if self.update_map {
+            for prob in &mut self.tree_probs {
+                *prob = read_prob(br)?;
+            }
+            self.temporal_update = br.read_flag()?;
+            if self.temporal_update {
+                for prob in &mut self.pred_probs {
+                    *prob = read_prob(br)?;
+                }
+            }
+        }
+
+        self.update_data = br.read_flag()?;
+        if self.update_data {
+            self.abs_or_delta_update = br.read_flag()?;
+            for i in 0..MAX_SEGMENTS {
+                for j in 0..SEG_LVL_MAX {
+                    let mut value = 0;
+                    self.feature_enabled[i][j] = br.read_flag()?;
+                    if self.feature_enabled[i][j] {
+                        value = br.read_bits(SEGMENTATION_FEATURE_BITS[j])? as i16;
+                        if SEGMENTATION_FEATURE_SIGNED[j] && br.read_flag()? {
+                            value = -value;
+                        }
+                    }
+                    self.feature_data[i][j] = value;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn is_feature_active(&self, segment: usize, feature: usize) -> bool {
+        self.enabled && self.feature_enabled[segment][feature]
+    }
+}
+
+fn read_signed(br: &mut BitReader, bits: u32) -> Result<i32, ()> {
+    let value = br.read_bits(bits)? as i32;
+    Ok(if br.read_flag()? { -value } else { value })
+}
+
+fn read_prob(br: &mut BitReader) -> Result<u8, ()> {
+    if br.read_flag()? {
+        Ok(br.read_bits(8)? as u8)
+    } else {
+        Ok(255)
+    }
+}
+
+/* Syntax of the compressed header. Only the modes are kept, the probability updates are
+ * counted to check the header against its size. */
+#[derive(Debug, Clone, Default)]
+pub struct CompressedHeader {
+    pub tx_mode: u8,
+    pub reference_mode: u8,
+    /* update_probs of the coefficient probabilities, for each transform size */
+    pub coef_probs_updated: [bool; 4],
+    pub num_prob_updates: u32,
+}
+
+impl CompressedHeader {
+    pub fn parse(data: &[u8], hdr: &FrameHeader) -> Result<CompressedHeader, ()> {
+        let mut bd = BoolDecoder::new(data);
+        if data.is_empty() || bd.read_flag() {
+            return Err(());
+        }
+
+        let mut ch = CompressedHeader::default();
+        ch.tx_mode = if hdr.quant.is_lossless() {
+            TX_MODE_ONLY_4X4
+        } else {
+            let mut tx_mode = bd.read_literal(2) as u8;
+            if tx_mode == TX_MODE_ALLOW_32X32 {
+                tx_mode += bd.read_literal(1) as u8;
+            }
+            tx_mode
+        };
+        if ch.tx_mode == TX_MODE_SELECT {
+            /* tx_probs_8x8, tx_probs_16x16 and tx_probs_32x32 */
+            ch.read_prob_updates(&mut bd, 2 * (1 + 2 + 3));
+        }
+
+        let max_tx_size = cmp::min(ch.tx_mode, TX_MODE_ALLOW_32X32) as usize;
+        for tx_size in 0..max_tx_size + 1 {
+            ch.coef_probs_updated[tx_size] = bd.read_flag();
+            if ch.coef_probs_updated[tx_size] {
+                /* 2 planes, intra and inter, band 0 with 3 contexts then 5 bands of 6 */
+                ch.read_prob_updates(&mut bd, 2 * 2 * (3 + 5 * 6) * 3);
+            }
+        }
+        /* skip_prob */
+        ch.read_prob_updates(&mut bd, 3);
+
+        if !hdr.is_intra() {
+            /* inter_mode_probs, interp_filter_probs and is_inter_prob */
+            ch.read_prob_updates(&mut bd, 7 * 3);
+            if hdr.interp_filter == INTERP_FILTER_SWITCHABLE {
+                ch.read_prob_updates(&mut bd, 4 * 2);
+            }
+            ch.read_prob_updates(&mut bd, 4);
+
+            let bias = &hdr.ref_frame_sign_bias;
+            let compound_allowed = bias[GOLDEN_FRAME] != bias[LAST_FRAME] ||
+                                   bias[ALTREF_FRAME] != bias[LAST_FRAME];
+            ch.reference_mode = if compound_allowed && bd.read_flag() {
+                if bd.read_flag() {
+                    REFERENCE_MODE_SELECT
+                } else {
+                    COMPOUND_REFERENCE
+                }
+            } else {
+                SINGLE_REFERENCE
+            };
+            if ch.reference_mode == REFERENCE_MODE_SELECT {
+                ch.read_prob_updates(&mut bd, 5);
+            }
+            if ch.reference_mode != COMPOUND_REFERENCE {
+                ch.read_prob_updates(&mut bd, 5 * 2);
+            }
+            if ch.reference_mode != SINGLE_REFERENCE {
+                ch.read_prob_updates(&mut bd, 5);
+            }
+
+            /* y_mode_probs and partition_probs */
+            ch.read_prob_updates(&mut bd, 4 * 9);
+            ch.read_prob_updates(&mut bd, 16 * 3);
+
+            /* joints, then sign, classes, class0 and bits, then class0_fr and fr, then
+             * class0_hp and hp of both components */
+            let mut num_mv_probs = 3 + 2 * (1 + 10 + 1 + 10) + 2 * (2 * 3 + 3);
+            if hdr.allow_high_precision_mv {
+                num_mv_probs += 2 * 2;
+            }
+            for _ in 0..num_mv_probs {
+                if bd.read_bool(DIFF_UPDATE_PROB) {
+                    bd.read_literal(7);
+                    ch.num_prob_updates += 1;
+                }
+            }
+        }
+
+        if bd.get_pos() > 8 * data.len() {
+            return Err(());
+        }
+
+        Ok(ch)
+    }
+
+    fn read_prob_updates(&mut self, bd: &mut BoolDecoder, count: usize) {
+        for _ in 0..count {
+            if bd.read_bool(DIFF_UPDATE_PROB) {
+                read_term_subexp(bd);
+                self.num_prob_updates += 1;
+            }
+        }
+    }
+}
+
+/* Delta of a probability update, to be remapped around the current probability */
+fn read_term_subexp(bd: &mut BoolDecoder) -> u8 {
+    if !bd.read_flag() {
+        return bd.read_literal(4) as u8;
+    }
+    if !bd.read_flag() {
+        return bd.read_literal(4) as u8 + 16;
+    }
+    if !bd.read_flag() {
+        return bd.read_literal(5) as u8 + 32;
+    }
+    let value = bd.read_literal(7);
+    if value < 65 {
+        return (value + 64) as u8;
+    }
+    ((value << 1) - 1 + bd.read_literal(1)) as u8
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FrameHeader {
+    pub profile: u8,
+    pub show_existing_frame: bool,
+    pub frame_to_show_map_idx: u8,
+    pub frame_type: u8,
+    pub show_frame: bool,
+    pub error_resilient_mode: bool,
+    pub intra_only: bool,
+    pub reset_frame_context: u8,
+    pub color_config: ColorConfig,
+    pub refresh_frame_flags: u8,
+    pub ref_frame_idx: [u8; REFS_PER_FRAME],
+    /* Indexed by reference frame, from INTRA_FRAME to ALTREF_FRAME */
+    pub ref_frame_sign_bias: [bool; 4],
+    pub width: u32,
+    pub height: u32,
+    pub render_width: u32,
+    pub render_height: u32,
+    pub allow_high_precision_mv: bool,
+    pub interp_filter: u8,
+    pub refresh_frame_context: bool,
+    pub frame_parallel_decoding_mode: bool,
+    pub frame_context_idx: u8,
+    pub loop_filter: LoopFilter,
+    pub quant: Quantization,
+    pub segmentation: Segmentation,
+    pub tile_cols_log2: u8,
+    pub tile_rows_log2: u8,
+    /* Sizes in bytes of both headers */
+    pub uncompressed_header_size: usize,
+    pub header_size_in_bytes: u16,
+    pub compressed_header: CompressedHeader,
+    /* Size of the whole frame */
+    pub size: usize,
+}
+
+impl FrameHeader {
+    pub fn is_intra(&self) -> bool {
+        self.frame_type == KEY_FRAME || self.intra_only
+    }
+
+    pub fn get_mi_cols(&self) -> u32 {
+        (self.width + 7) >> 3
+    }
+
+    pub fn get_mi_rows(&self) -> u32 {
+        (self.height + 7) >> 3
+    }
+
+    pub fn get_sb64_cols(&self) -> u32 {
+        (self.get_mi_cols() + 7) >> 3
+    }
+
+    /* Surfaces follow the bit depth; 12-bit and 4:4:4 profiles have no render target */
+    pub fn get_rt_format(&self) -> Result<u32, ()> {
+        match (self.profile, self.color_config.bit_depth) {
+            (0, 8) => Ok(ffi::VA_RT_FORMAT_YUV420),
+            (2, 10) => Ok(ffi::VA_RT_FORMAT_YUV420_10BPP),
+            _ => Err(()),
+        }
+    }
+
+    pub fn get_va_profile(&self) -> Result<ffi::VAProfile, ()> {
+        match self.get_rt_format()? {
+            ffi::VA_RT_FORMAT_YUV420 => Ok(ffi::VAProfileVP9Profile0),
+            _ => Ok(ffi::VAProfileVP9Profile2),
+        }
+    }
+
+    fn get_min_log2_tile_cols(&self) -> u8 {
+        let mut min_log2 = 0;
+        while (MAX_TILE_WIDTH_B64 << min_log2) < self.get_sb64_cols() {
+            min_log2 += 1;
+        }
+        min_log2
+    }
+
+    fn get_max_log2_tile_cols(&self) -> u8 {
+        let mut max_log2 = 1;
+        while (self.get_sb64_cols() >> max_log2) >= MIN_TILE_WIDTH_B64 {
+            max_log2 += 1;
+        }
+        max_log2 - 1
+    }
+
+    /* Quantizer index of a segment */
+    pub fn get_qindex(&self, segment: usize) -> u8 {
+        let base = self.quant.base_q_idx as i32;
+        if !self.segmentation.is_feature_active(segment, SEG_LVL_ALT_Q) {
+            return base as u8;
+        }
+        let data = self.segmentation.feature_data[segment][SEG_LVL_ALT_Q] as i32;
+        if self.segmentation.abs_or_delta_update {
+            clamp(data, 0, 255) as u8
+        } else {
+            clamp(base + data, 0, 255) as u8
+        }
+    }
+
+    /* Loop filter level of a segment for a reference frame and a mode, 0 being ZEROMV */
+    pub fn get_filter_level(&self, segment: usize, ref_frame: usize, mode: usize) -> u8 {
+        let lf = &self.loop_filter;
+        let mut level = lf.level as i32;
+        if self.segmentation.is_feature_active(segment, SEG_LVL_ALT_L) {
+            let data = self.segmentation.feature_data[segment][SEG_LVL_ALT_L] as i32;
+            level = if self.segmentation.abs_or_delta_update {
+                data
+            } else {
+                level + data
+            };
+            level = clamp(level, 0, MAX_LOOP_FILTER);
+        }
+
+        if lf.delta_enabled {
+            let shift = level >> 5;
+            level += (lf.ref_deltas[ref_frame] as i32) << shift;
+            if ref_frame != INTRA_FRAME {
+                level += (lf.mode_deltas[mode] as i32) << shift;
+            }
+            level = clamp(level, 0, MAX_LOOP_FILTER);
+        }
+
+        level as u8
+    }
+}
+
+/* Keeps the reference frame sizes, the color configuration, the loop filter deltas and
+ * the segmentation features across the frames */
+#[derive(Debug, Clone, Default)]
+pub struct Parser {
+    ref_frames: [Option<RefFrameInfo>; NUM_REF_FRAMES],
+    color_config: ColorConfig,
+    loop_filter: LoopFilter,
+    segmentation: Segmentation,
+}
+
+impl Parser {
+    pub fn new() -> Parser {
+        Parser::default()
+    }
+
+    pub fn get_ref_frame(&self, idx: usize) -> Option<RefFrameInfo> {
+        self.ref_frames[idx]
+    }
+
+    /* Parses a single frame, see split_superframe() */
+    pub fn parse_frame(&mut self, data: &[u8]) -> Result<FrameHeader, ()> {
+        let mut br = BitReader::new(data);
+        let mut hdr = FrameHeader::default();
+        hdr.size = data.len();
+
+        if br.read_bits(2)? != FRAME_MARKER {
+            return Err(());
+        }
+        let profile_low_bit = br.read_bits(1)?;
+        hdr.profile = ((br.read_bits(1)? << 1) | profile_low_bit) as u8;
+        if hdr.profile == 3 && br.read_flag()? {
+            return Err(());
+        }
+
+        hdr.show_existing_frame = br.read_flag()?;
+        if hdr.show_existing_frame {
+            hdr.frame_to_show_map_idx = br.read_bits(3)? as u8;
+            if self.ref_frames[hdr.frame_to_show_map_idx as usize].is_none() {
+                return Err(());
+            }
+            hdr.show_frame = true;
+            return Ok(hdr);
+        }
+
+        hdr.frame_type = br.read_bits(1)? as u8;
+        hdr.show_frame = br.read_flag()?;
+        hdr.error_resilient_mode = br.read_flag()?;
+        if hdr.frame_type == KEY_FRAME {
+            if br.read_bits(24)? != SYNC_CODE {
+                return Err(());
+            }
+            self.color_config = ColorConfig::parse(&mut br, hdr.profile)?;
+            self.parse_frame_size(&mut br, &mut hdr)?;
+            hdr.refresh_frame_flags = 0xff;
+        } else {
+            if !hdr.show_frame {
+                hdr.intra_only = br.read_flag()?;
+            }
+            if !hdr.error_resilient_mode {
+                hdr.reset_frame_context = br.read_bits(2)? as u8;
+            }
+
+            if hdr.intra_only {
+                if br.read_bits(24)? != SYNC_CODE {
+                    return Err(());
+                }
+                self.color_config = if hdr.profile > 0 {
+                    ColorConfig::parse(&mut br, hdr.profile)?
+                } else {
+                    ColorConfig::new_profile0()
+                };
+                hdr.refresh_frame_flags = br.read_bits(8)? as u8;
+                self.parse_frame_size(&mut br, &mut hdr)?;
+            } else {
+                hdr.refresh_frame_flags = br.read_bits(8)? as u8;
+                for i in 0..REFS_PER_FRAME {
+                    hdr.ref_frame_idx[i] = br.read_bits(3)? as u8;
+                    hdr.ref_frame_sign_bias[LAST_FRAME + i] = br.read_flag()?;
+                }
+                self.parse_frame_size_with_refs(&mut br, &mut hdr)?;
+                hdr.allow_high_precision_mv = br.read_flag()?;
+                hdr.interp_filter = if br.read_flag()? {
+                    INTERP_FILTER_SWITCHABLE
+                } else {
+                    LITERAL_TO_INTERP_FILTER[br.read_bits(2)? as usize]
+                };
+            }
+        }
+        hdr.color_config = self.color_config.clone();
+
+        if !hdr.error_resilient_mode {
+            hdr.refresh_frame_context = br.read_flag()?;
+            hdr.frame_parallel_decoding_mode = br.read_flag()?;
+        } else {
+            hdr.frame_parallel_decoding_mode = true;
+        }
+        hdr.frame_context_idx = br.read_bits(2)? as u8;
+
+        /* setup_past_independence() */
+        if hdr.is_intra() || hdr.error_resilient_mode {
+            self.segmentation.reset_features();
+            self.loop_filter.reset_deltas();
+        }
+
+        self.loop_filter.parse(&mut br)?;
+        hdr.loop_filter = self.loop_filter.clone();
+        hdr.quant = Quantization::parse(&mut br)?;
+        self.segmentation.parse(&mut br)?;
+        hdr.segmentation = self.segmentation.clone();
+
+        let min_log2 = hdr.get_min_log2_tile_cols();
+        let max_log2 = hdr.get_max_log2_tile_cols();
+        hdr.tile_cols_log2 = min_log2;
+        while hdr.tile_cols_log2 < max_log2 && br.read_flag()? {
+            hdr.tile_cols_log2 += 1;
+        }
+        if br.read_flag()? {
+            hdr.tile_rows_log2 = 1 + br.read_bits(1)? as u8;
+        }
+
+        hdr.header_size_in_bytes = br.read_bits(16)? as u16;
+        br.byte_align();
+        hdr.uncompressed_header_size = br.get_pos() / 8;
+
+        let start = hdr.uncompressed_header_size;
+        let size = hdr.header_size_in_bytes as usize;
+        if size == 0 || size > data.len() - start {
+            return Err(());
+        }
+        hdr.compressed_header = CompressedHeader::parse(&data[start..start + size], &hdr)?;
+
+        let info = RefFrameInfo {
+            width: hdr.width,
+            height: hdr.height,
+            subsampling_x: hdr.color_config.subsampling_x,
+            subsampling_y: hdr.color_config.subsampling_y,
+            bit_depth: hdr.color_config.bit_depth,
+        };
+        for i in 0..NUM_REF_FRAMES {
+            if hdr.refresh_frame_flags & (1 << i) != 0 {
+                self.ref_frames[i] = Some(info);
+            }
+        }
+
+        Ok(hdr)
+    }
+
+    fn parse_frame_size(&self, br: &mut BitReader, hdr: &mut FrameHeader) -> Result<(), ()> {
+        hdr.width = br.read_bits(16)? + 1;
+        hdr.height = br.read_bits(16)? + 1;
+        self.parse_render_size(br, hdr)
+    }
+
+    fn parse_render_size(&self, br: &mut BitReader, hdr: &mut FrameHeader) -> Result<(), ()> {
+        if br.read_flag()? {
+            hdr.render_width = br.read_bits(16)? + 1;
+            hdr.render_height = br.read_bits(16)? + 1;
+        } else {
+            hdr.render_width = hdr.width;
+            hdr.render_height = hdr.height;
+        }
+        Ok(())
+    }
+
+    /* The size is either copied from one of the references or sent explicitly */
+    fn parse_frame_size_with_refs(&self,
+                                  br: &mut BitReader,
+                                  hdr: &mut FrameHeader)
+                                  -> Result<(), ()> {
+        for i in 0..REFS_PER_FRAME {
+            if br.read_flag()? {
+                let info = match self.ref_frames[hdr.ref_frame_idx[i] as usize] {
+                    Some(info) => info,
+                    None => return Err(()),
+                };
+                hdr.width = info.width;
+                hdr.height = info.height;
+                return self.parse_render_size(br, hdr);
+            }
+        }
+        self.parse_frame_size(br, hdr)
+    }
+}
+
+/* The reference frames are the surfaces of the eight slots, managed by the caller */
+pub fn build_picture_param(hdr: &FrameHeader,
+                           reference_frames: &[ffi::VASurfaceID; NUM_REF_FRAMES])
+                           -> Result<ffi::VADecPictureParameterBufferVP9, ()> {
+    let mut param: ffi::VADecPictureParameterBufferVP9 = zeroed();
+    param.frame_width = hdr.width as u16;
+    param.frame_height = hdr.height as u16;
+    param.reference_frames = *reference_frames;
+
+    let seg = &hdr.segmentation;
+    param.pic_fields = VP9PicFields {
+        subsampling_x: hdr.color_config.subsampling_x as u32,
+        subsampling_y: hdr.color_config.subsampling_y as u32,
+        frame_type: hdr.frame_type as u32,
+        show_frame: hdr.show_frame as u32,
+        error_resilient_mode: hdr.error_resilient_mode as u32,
+        intra_only: hdr.intra_only as u32,
+        allow_high_precision_mv: hdr.allow_high_precision_mv as u32,
+        mcomp_filter_type: hdr.interp_filter as u32,
+        frame_parallel_decoding_mode: hdr.frame_parallel_decoding_mode as u32,
+        reset_frame_context: hdr.reset_frame_context as u32,
+        refresh_frame_context: hdr.refresh_frame_context as u32,
+        frame_context_idx: hdr.frame_context_idx as u32,
+        segmentation_enabled: seg.enabled as u32,
+        segmentation_temporal_update: seg.temporal_update as u32,
+        segmentation_update_map: seg.update_map as u32,
+        last_ref_frame: hdr.ref_frame_idx[0] as u32,
+        last_ref_frame_sign_bias: hdr.ref_frame_sign_bias[LAST_FRAME] as u32,
+        golden_ref_frame: hdr.ref_frame_idx[1] as u32,
+        golden_ref_frame_sign_bias: hdr.ref_frame_sign_bias[GOLDEN_FRAME] as u32,
+        alt_ref_frame: hdr.ref_frame_idx[2] as u32,
+        alt_ref_frame_sign_bias: hdr.ref_frame_sign_bias[ALTREF_FRAME] as u32,
+        lossless_flag: hdr.quant.is_lossless() as u32,
+    }.to_va()?;
+
+    param.filter_level = hdr.loop_filter.level;
+    param.sharpness_level = hdr.loop_filter.sharpness;
+    param.log2_tile_rows = hdr.tile_rows_log2;
+    param.log2_tile_columns = hdr.tile_cols_log2;
+    param.frame_header_length_in_bytes = hdr.uncompressed_header_size as u8;
+    param.first_partition_size = hdr.header_size_in_bytes;
+    param.mb_segment_tree_probs = seg.tree_probs;
+    param.segment_pred_probs = seg.pred_probs;
+    param.profile = hdr.profile;
+    param.bit_depth = hdr.color_config.bit_depth;
+    Ok(param)
+}
+
+/* The slice data is the whole frame. The quantizer scales of the segments are only
+ * filled for 8 bits streams. */
+pub fn build_slice_param(hdr: &FrameHeader) -> Result<ffi::VASliceParameterBufferVP9, ()> {
+    let mut param: ffi::VASliceParameterBufferVP9 = zeroed();
+    param.slice_data_size = hdr.size as u32;
+    param.slice_data_offset = 0;
+    param.slice_data_flag = ffi::VA_SLICE_DATA_FLAG_ALL;
+
+    let seg = &hdr.segmentation;
+    let q = &hdr.quant;
+    for i in 0..MAX_SEGMENTS {
+        let seg_param = &mut param.seg_param[i];
+        seg_param.segment_flags = VP9SegmentFlags {
+            segment_reference_enabled: seg.is_feature_active(i, SEG_LVL_REF_FRAME) as u16,
+            segment_reference: seg.feature_data[i][SEG_LVL_REF_FRAME] as u16,
+            segment_reference_skipped: seg.is_feature_active(i, SEG_LVL_SKIP) as u16,
+        }.to_va()?;
+
+        for ref_frame in 0..4 {
+            for mode in 0..2 {
+                seg_param.filter_level[ref_frame][mode] =
+                    hdr.get_filter_level(i, ref_frame, mode);
+            }
+        }
+
+        if hdr.color_config.bit_depth == 8 {
+            let qindex = hdr.get_qindex(i) as i32;
+            let lookup = |table: &[i16; 256], delta: i8| {
+                table[clamp(qindex + delta as i32, 0, 255) as usize]
+            };
+            seg_param.luma_dc_quant_scale = lookup(&DC_QLOOKUP, q.delta_q_y_dc);
+            seg_param.luma_ac_quant_scale = lookup(&AC_QLOOKUP, 0);
+            seg_param.chroma_dc_quant_scale = lookup(&DC_QLOOKUP, q.delta_q_uv_dc);
+            seg_param.chroma_ac_quant_scale = lookup(&AC_QLOOKUP, q.delta_q_uv_ac);
+        }
+    }
+        Ok(param)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi;
+    use ivf::parse_ivf;
+
+    /* A 1080p key frame with segmentation and tiles, a superframe made of a hidden
+     * frame and an error resilient lossless frame, a frame showing an existing one and
+     * an intra only frame */
+    const SUPERFRAME_1080P: &'static [u8] =
+        include_bytes!("../tests/data/vp9/1080p_superframe.ivf");
+    /* A profile 2 key frame of 10 bits with the maximum number of tile columns */
+    const PROFILE2_2160P: &'static [u8] = include_bytes!("../tests/data/vp9/2160p_10bit.ivf");
+
+    fn parse_stream(data: &[u8]) -> (Parser, Vec<FrameHeader>) {
+        let (_, chunks) = parse_ivf(data).unwrap();
+        let mut parser = Parser::new();
+        let mut frames = Vec::new();
+        for chunk in chunks {
+            for frame in split_superframe(chunk.data).unwrap() {
+                frames.push(parser.parse_frame(frame).unwrap());
+            }
+        }
+        (parser, frames)
+    }
+
+    #[test]
+    fn superframes() {
+        let (_, chunks) = parse_ivf(SUPERFRAME_1080P).unwrap();
+        assert_eq!(chunks.len(), 4);
+        let sizes: Vec<usize> = split_superframe(chunks[1].data)
+            .unwrap()
+            .iter()
+            .map(|frame| frame.len())
+            .collect();
+        assert_eq!(sizes, vec![86, 61]);
+        assert_eq!(split_superframe(chunks[0].data).unwrap(), vec![chunks[0].data]);
+        assert_eq!(split_superframe(chunks[2].data).unwrap(), vec![chunks[2].data]);
+
+        /* Frame sizes past the data */
+        let mut data = chunks[1].data.to_vec();
+        let len = data.len();
+        data[len - 2] = 0xff;
+        assert!(split_superframe(&data).is_err());
+        assert!(split_superframe(&[]).is_err());
+    }
+
+    #[test]
+    fn key_frame() {
+        let (_, frames) = parse_stream(SUPERFRAME_1080P);
+        let hdr = &frames[0];
+        assert_eq!(hdr.profile, 0);
+        assert_eq!(hdr.frame_type, KEY_FRAME);
+        assert!(hdr.show_frame && !hdr.error_resilient_mode && hdr.is_intra());
+        assert_eq!(hdr.color_config.bit_depth, 8);
+        assert_eq!(hdr.color_config.color_space, 2);
+        assert_eq!(hdr.get_rt_format(), Ok(ffi::VA_RT_FORMAT_YUV420));
+        assert_eq!((hdr.color_config.subsampling_x, hdr.color_config.subsampling_y), (1, 1));
+        assert_eq!((hdr.width, hdr.height), (1920, 1080));
+        assert_eq!((hdr.render_width, hdr.render_height), (1920, 1080));
+        assert_eq!(hdr.refresh_frame_flags, 0xff);
+        assert!(hdr.refresh_frame_context && !hdr.frame_parallel_decoding_mode);
+
+        let lf = &hdr.loop_filter;
+        assert_eq!((lf.level, lf.sharpness), (36, 0));
+        assert!(lf.delta_enabled && lf.delta_update);
+        assert_eq!(lf.ref_deltas, [2, 0, -2, -1]);
+        assert_eq!(lf.mode_deltas, [0, 1]);
+        assert_eq!(hdr.quant.base_q_idx, 80);
+        assert_eq!((hdr.quant.delta_q_y_dc, hdr.quant.delta_q_uv_dc, hdr.quant.delta_q_uv_ac),
+                   (-2, 0, 3));
+        assert!(!hdr.quant.is_lossless());
+
+        let seg = &hdr.segmentation;
+        assert!(seg.enabled && seg.update_map && !seg.temporal_update && seg.update_data);
+        assert!(!seg.abs_or_delta_update);
+        assert_eq!(seg.tree_probs, [128, 255, 60, 70, 255, 200, 30]);
+        assert_eq!(seg.pred_probs, [255; 3]);
+        assert_eq!(seg.feature_data[1][SEG_LVL_ALT_Q], -20);
+        assert_eq!(seg.feature_data[2][SEG_LVL_ALT_L], 10);
+        assert!(seg.is_feature_active(3, SEG_LVL_REF_FRAME));
+        assert_eq!(seg.feature_data[3][SEG_LVL_REF_FRAME], 1);
+        assert!(seg.is_feature_active(4, SEG_LVL_SKIP));
+        assert!(!seg.is_feature_active(0, SEG_LVL_ALT_Q));
+        assert_eq!(hdr.get_qindex(0), 80);
+        assert_eq!(hdr.get_qindex(1), 60);
+        assert_eq!(hdr.get_qindex(5), 255);
+
+        assert_eq!((hdr.tile_cols_log2, hdr.tile_rows_log2), (1, 1));
+        assert_eq!(hdr.uncompressed_header_size, 34);
+        assert_eq!(hdr.header_size_in_bytes, 16);
+        let ch = &hdr.compressed_header;
+        assert_eq!(ch.tx_mode, TX_MODE_SELECT);
+        assert_eq!(ch.coef_probs_updated, [true, false, true, false]);
+        assert_eq!(ch.num_prob_updates, 6);
+        assert_eq!(ch.reference_mode, SINGLE_REFERENCE);
+    }
+
+    #[test]
+    fn inter_frames() {
+        let (parser, frames) = parse_stream(SUPERFRAME_1080P);
+        assert_eq!(frames.len(), 5);
+
+        /* The segmentation features and the loop filter deltas are kept */
+        let hdr = &frames[1];
+        assert_eq!(hdr.frame_type, NON_KEY_FRAME);
+        assert!(!hdr.show_frame && !hdr.intra_only);
+        assert_eq!(hdr.refresh_frame_flags, 0x04);
+        assert_eq!(hdr.ref_frame_idx, [0, 1, 2]);
+        assert_eq!(hdr.ref_frame_sign_bias, [false, false, false, true]);
+        assert_eq!((hdr.width, hdr.height), (1920, 1080));
+        assert!(hdr.allow_high_precision_mv);
+        assert_eq!(hdr.interp_filter, INTERP_FILTER_EIGHTTAP_SHARP);
+        assert!(!hdr.refresh_frame_context && hdr.frame_parallel_decoding_mode);
+        assert_eq!(hdr.frame_context_idx, 1);
+        assert!(hdr.loop_filter.delta_enabled && !hdr.loop_filter.delta_update);
+        assert_eq!(hdr.loop_filter.ref_deltas, [2, 0, -2, -1]);
+        let seg = &hdr.segmentation;
+        assert!(seg.update_map && seg.temporal_update && !seg.update_data);
+        assert_eq!(seg.pred_probs, [255, 90, 10]);
+        assert_eq!(seg.feature_data[1][SEG_LVL_ALT_Q], -20);
+        assert_eq!(hdr.get_qindex(1), 80);
+        assert_eq!(hdr.uncompressed_header_size, 14);
+        let ch = &hdr.compressed_header;
+        assert_eq!(ch.tx_mode, 2);
+        assert_eq!(ch.reference_mode, REFERENCE_MODE_SELECT);
+        assert_eq!(ch.num_prob_updates, 5);
+
+        /* Error resilient: the features and deltas are reset */
+        let hdr = &frames[2];
+        assert!(hdr.show_frame && hdr.error_resilient_mode);
+        assert_eq!(hdr.reset_frame_context, 0);
+        assert!(!hdr.refresh_frame_context && hdr.frame_parallel_decoding_mode);
+        assert_eq!(hdr.frame_context_idx, 3);
+        assert_eq!((hdr.width, hdr.height), (1920, 1080));
+        assert_eq!((hdr.render_width, hdr.render_height), (1920, 800));
+        assert_eq!(hdr.interp_filter, INTERP_FILTER_SWITCHABLE);
+        assert!(!hdr.segmentation.enabled);
+        assert!(!hdr.segmentation.feature_enabled[1][SEG_LVL_ALT_Q]);
+        assert!(!hdr.loop_filter.delta_enabled);
+        assert_eq!(hdr.loop_filter.ref_deltas, [1, 0, -1, -1]);
+        assert!(hdr.quant.is_lossless());
+        assert_eq!(hdr.compressed_header.tx_mode, TX_MODE_ONLY_4X4);
+        assert_eq!(hdr.compressed_header.reference_mode, SINGLE_REFERENCE);
+        assert_eq!(hdr.compressed_header.num_prob_updates, 2);
+
+        let hdr = &frames[3];
+        assert!(hdr.show_existing_frame && hdr.show_frame);
+        assert_eq!(hdr.frame_to_show_map_idx, 2);
+        assert_eq!(hdr.size, 1);
+
+        let hdr = &frames[4];
+        assert!(hdr.intra_only && hdr.is_intra() && !hdr.show_frame);
+        assert_eq!(hdr.reset_frame_context, 2);
+        assert_eq!(hdr.color_config.color_space, CS_BT_601);
+        assert_eq!(hdr.refresh_frame_flags, 0x80);
+        assert_eq!((hdr.width, hdr.height), (960, 540));
+        assert_eq!(hdr.tile_cols_log2, 0);
+        assert_eq!(hdr.compressed_header.tx_mode, 1);
+        assert_eq!(hdr.compressed_header.num_prob_updates, 0);
+
+        let full = RefFrameInfo {
+            width: 1920,
+            height: 1080,
+            subsampling_x: 1,
+            subsampling_y: 1,
+            bit_depth: 8,
+        };
+        for i in 0..7 {
+            assert_eq!(parser.get_ref_frame(i), Some(full));
+        }
+        assert_eq!(parser.get_ref_frame(7).unwrap().width, 960);
+    }
+
+    #[test]
+    fn profile2() {
+        let (_, frames) = parse_stream(PROFILE2_2160P);
+        let hdr = &frames[0];
+        assert_eq!(hdr.profile, 2);
+        assert_eq!(hdr.color_config.bit_depth, 10);
+        assert_eq!((hdr.color_config.color_space, hdr.color_config.color_range), (5, 1));
+        assert_eq!(hdr.get_rt_format(), Ok(ffi::VA_RT_FORMAT_YUV420_10BPP));
+        assert_eq!(hdr.get_va_profile(), Ok(ffi::VAProfileVP9Profile2));
+        assert_eq!((hdr.width, hdr.height), (3840, 2160));
+        assert_eq!(hdr.tile_cols_log2, 3);
+        assert_eq!(hdr.loop_filter.ref_deltas, [1, 0, -1, -1]);
+        assert_eq!(hdr.quant.delta_q_uv_dc, -4);
+        assert_eq!(hdr.compressed_header.tx_mode, TX_MODE_ALLOW_32X32);
+        assert_eq!(hdr.compressed_header.coef_probs_updated, [false; 4]);
+
+        let param = build_picture_param(hdr, &[0; NUM_REF_FRAMES]).unwrap();
+        assert_eq!((param.profile, param.bit_depth), (2, 10));
+        assert_eq!(param.log2_tile_columns, 3);
+        let slice = build_slice_param(hdr).unwrap();
+        assert_eq!(slice.seg_param[0].luma_ac_quant_scale, 0);
+    }
+
+    #[test]
+    fn invalid_frames() {
+        let (_, chunks) = parse_ivf(SUPERFRAME_1080P).unwrap();
+        let mut parser = Parser::new();
+
+        /* References and shown frames need to be decoded first */
+        let frames = split_superframe(chunks[1].data).unwrap();
+        assert!(parser.parse_frame(frames[0]).is_err());
+        assert!(parser.parse_frame(chunks[2].data).is_err());
+
+        /* RGB in profile 0, bad sync code, truncated headers and frame marker */
+        let mut data = chunks[0].data.to_vec();
+        data[4] |= 0xe0;
+        assert!(parser.parse_frame(&data).is_err());
+        let mut data = chunks[0].data.to_vec();
+        data[2] ^= 1;
+        assert!(parser.parse_frame(&data).is_err());
+        assert!(parser.parse_frame(&chunks[0].data[..20]).is_err());
+        assert!(parser.parse_frame(&chunks[0].data[..40]).is_err());
+        assert!(parser.parse_frame(&[0x0a]).is_err());
+        assert!(parser.parse_frame(chunks[0].data).is_ok());
+    }
+
+    #[test]
+    fn va_buffers() {
+        let (_, frames) = parse_stream(SUPERFRAME_1080P);
+        let refs = [10, 11, 12, 13, 14, 15, 16, 17];
+
+        let param = build_picture_param(&frames[0], &refs).unwrap();
+        assert_eq!((param.frame_width, param.frame_height), (1920, 1080));
+        assert_eq!(param.reference_frames, refs);
+        let fields = VP9PicFields::from_va(&param.pic_fields);
+        assert_eq!(fields.frame_type, 0);
+        assert_eq!(fields.show_frame, 1);
+        assert_eq!(fields.subsampling_x, 1);
+        assert_eq!(fields.segmentation_enabled, 1);
+        assert_eq!(fields.segmentation_update_map, 1);
+        assert_eq!(fields.refresh_frame_context, 1);
+        assert_eq!(fields.lossless_flag, 0);
+        assert_eq!((param.filter_level, param.sharpness_level), (36, 0));
+        assert_eq!((param.log2_tile_columns, param.log2_tile_rows), (1, 1));
+        assert_eq!(param.frame_header_length_in_bytes, 34);
+        assert_eq!(param.first_partition_size, 16);
+        assert_eq!(param.mb_segment_tree_probs, [128, 255, 60, 70, 255, 200, 30]);
+        assert_eq!((param.profile, param.bit_depth), (0, 8));
+
+        let slice = build_slice_param(&frames[0]).unwrap();
+        assert_eq!(slice.slice_data_size, 170);
+        let seg = &slice.seg_param[0];
+        assert_eq!(seg.filter_level, [[40, 40], [36, 38], [32, 34], [34, 36]]);
+        assert_eq!(seg.luma_dc_quant_scale, DC_QLOOKUP[78]);
+        assert_eq!(seg.luma_ac_quant_scale, AC_QLOOKUP[80]);
+        assert_eq!(seg.chroma_dc_quant_scale, DC_QLOOKUP[80]);
+        assert_eq!(seg.chroma_ac_quant_scale, AC_QLOOKUP[83]);
+        assert_eq!(slice.seg_param[1].luma_ac_quant_scale, AC_QLOOKUP[60]);
+        assert_eq!(slice.seg_param[5].luma_ac_quant_scale, AC_QLOOKUP[255]);
+        assert_eq!(slice.seg_param[2].filter_level[0], [50, 50]);
+        assert_eq!(slice.seg_param[2].filter_level[1], [46, 48]);
+        let flags = VP9SegmentFlags::from_va(&slice.seg_param[3].segment_flags);
+        assert_eq!(flags.segment_reference_enabled, 1);
+        assert_eq!(flags.segment_reference, 1);
+        assert_eq!(flags.segment_reference_skipped, 0);
+        let flags = VP9SegmentFlags::from_va(&slice.seg_param[4].segment_flags);
+        assert_eq!(flags.segment_reference_enabled, 0);
+        assert_eq!(flags.segment_reference_skipped, 1);
+
+        let param = build_picture_param(&frames[1], &refs).unwrap();
+        let fields = VP9PicFields::from_va(&param.pic_fields);
+        assert_eq!(fields.frame_type, 1);
+        assert_eq!(fields.show_frame, 0);
+        assert_eq!(fields.mcomp_filter_type, INTERP_FILTER_EIGHTTAP_SHARP as u32);
+        assert_eq!(fields.segmentation_temporal_update, 1);
+        assert_eq!(fields.frame_context_idx, 1);
+        assert_eq!((fields.last_ref_frame, fields.golden_ref_frame), (0, 1));
+        assert_eq!(fields.alt_ref_frame, 2);
+        assert_eq!(fields.alt_ref_frame_sign_bias, 1);
+        assert_eq!(fields.allow_high_precision_mv, 1);
+        assert_eq!(param.mb_segment_tree_probs, [255; 7]);
+        assert_eq!(param.segment_pred_probs, [255, 90, 10]);
+        let slice = build_slice_param(&frames[1]).unwrap();
+        assert_eq!(slice.seg_param[2].filter_level, [[32, 32], [30, 31], [28, 29], [29, 30]]);
+
+        /* Without segmentation and loop filter deltas, all the segments are the same */
+        let param = build_picture_param(&frames[2], &refs).unwrap();
+        let fields = VP9PicFields::from_va(&param.pic_fields);
+        assert_eq!(fields.error_resilient_mode, 1);
+        assert_eq!(fields.lossless_flag, 1);
+        assert_eq!(fields.segmentation_enabled, 0);
+        let slice = build_slice_param(&frames[2]).unwrap();
+        for seg in slice.seg_param.iter() {
+            assert_eq!(seg.filter_level, [[0; 2]; 4]);
+            assert_eq!(seg.luma_dc_quant_scale, DC_QLOOKUP[0]);
+        }
+    }
+}